@@ -321,7 +321,8 @@ impl BuildStep for BootloaderBuild {
 
         for part in &self.config.bin_parts_path {
             if part.to_str().unwrap().contains("kernel") {
-                self.write_part_to_img(&mut kernel_img, part)
+                // the kernel is loaded as an ELF executable, not as a flat binary
+                self.write_part_to_img(&mut kernel_img, &part.with_extension(""))
                     .await
                     .map_err(|_| self.build_fail(master.clone(), None))?;
             } else {
@@ -349,3 +349,60 @@ fn __locate_smbios_entry() -> Option<u32> {
 
     None
 }
+
+/// SMBIOS entry point structure, located by [`smbios_entry_point`].
+#[derive(Clone, Copy, Debug)]
+pub struct SMBIOSEntryPoint {
+    /// Major version of the SMBIOS specification implemented by the firmware.
+    pub major_version: u8,
+
+    /// Minor version of the SMBIOS specification implemented by the firmware.
+    pub minor_version: u8,
+
+    /// Raw content of the entry point structure.
+    pub data: &'static [u8],
+}
+
+/// Locates the 32-bit (`_SM_`) or 64-bit (`_SM3_`) SMBIOS entry point structure.
+///
+/// Unlike [`load_smbios_entry`], does not output anything, and can be used once the _BIOS_
+/// services are not available anymore.
+#[must_use]
+pub fn smbios_entry_point() -> Option<SMBIOSEntryPoint> {
+    let bios_area: &'static [u8] =
+        unsafe { slice::from_raw_parts(ptr::with_exposed_provenance(0xF_0000), 0x1_0000) };
+
+    for offset in (0..bios_area.len()).step_by(16) {
+        let candidate = &bios_area[offset..];
+
+        // (length offset, version offset)
+        let layout = if candidate.starts_with(b"_SM3_") {
+            Some((6, 7))
+        } else if candidate.starts_with(b"_SM_") {
+            Some((5, 6))
+        } else {
+            None
+        };
+
+        if let Some((length_offset, version_offset)) = layout {
+            let Some(data) = candidate
+                .get(length_offset)
+                .and_then(|length| candidate.get(..usize::from(*length)))
+            else {
+                continue;
+            };
+
+            if data.len() > version_offset + 1
+                && data.iter().fold(0u8, |sum, byte| sum.wrapping_add(*byte)) == 0
+            {
+                return Some(SMBIOSEntryPoint {
+                    major_version: data[version_offset],
+                    minor_version: data[version_offset + 1],
+                    data,
+                });
+            }
+        }
+    }
+
+    None
+}
@@ -0,0 +1,381 @@
+//! Boot Loader Specification (_BLS_) entries.
+//!
+//! Distributions following the _BLS_ install one Type #1 entry file per kernel in
+//! [`BLS_ENTRIES_DIR`], as a list of `key value` pairs, one per line:
+//!
+//! ```text
+//! title      Fedora Linux 40 (Workstation Edition)
+//! version    6.8.5-301.fc40.x86_64
+//! machine-id 6a9857a393724b7a981ebb5b8495b9ea
+//! linux      /vmlinuz-6.8.5-301.fc40.x86_64
+//! initrd     /initramfs-6.8.5-301.fc40.x86_64.img
+//! options    root=UUID=4bd3ab14-1bde-4c8e-9f5b-1d4fa1f2eb2c ro rhgb quiet
+//! ```
+//!
+//! Paths are relative to the root of the partition storing the entries. `initrd` and `options`
+//! can be repeated, and unknown keys are ignored. Entries without a `linux` key cannot be booted
+//! and are discarded.
+
+use core::cmp::Ordering;
+
+use alloc::{
+    format,
+    string::{String, ToString},
+    vec::Vec,
+};
+
+use crate::boot::config::{BootEntry, BootModule, BootProtocol, ModuleSource};
+
+/// Directory storing the entry files, relative to the root of the partition.
+pub const BLS_ENTRIES_DIR: &str = "/loader/entries";
+
+/// Suffix of the entry files.
+pub const BLS_ENTRY_SUFFIX: &str = ".conf";
+
+/// Character starting a comment line.
+const COMMENT_CHAR: char = '#';
+
+/// A _BLS_ Type #1 boot entry.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct BlsEntry {
+    /// Name of the entry file, without the [`BLS_ENTRY_SUFFIX`].
+    pub id: String,
+
+    /// Human readable name of the entry.
+    pub title: Option<String>,
+
+    /// Version of the kernel.
+    pub version: Option<String>,
+
+    /// Identifier of the operating system installation the entry belongs to.
+    pub machine_id: Option<String>,
+
+    /// Path to the Linux kernel image.
+    pub linux: String,
+
+    /// Paths to the initial ramdisks, in the order in which they are loaded.
+    pub initrd: Vec<String>,
+
+    /// Kernel command line.
+    pub options: String,
+}
+
+impl BlsEntry {
+    /// Parses the content of the entry file named `<id>.conf`.
+    ///
+    /// Returns `None` if the entry has no `linux` key.
+    #[must_use]
+    pub fn parse(id: &str, text: &str) -> Option<Self> {
+        let mut entry = Self {
+            id: id.to_string(),
+            ..Self::default()
+        };
+        let mut linux = None;
+
+        for line in text.lines() {
+            let line = line.trim();
+
+            if line.is_empty() || line.starts_with(COMMENT_CHAR) {
+                continue;
+            }
+
+            let (key, value) = line
+                .split_once(char::is_whitespace)
+                .map_or((line, ""), |(key, value)| (key, value.trim()));
+
+            match key {
+                "title" => entry.title = Some(value.to_string()),
+                "version" => entry.version = Some(value.to_string()),
+                "machine-id" => entry.machine_id = Some(value.to_string()),
+                "linux" => linux = Some(absolute_path(value)),
+                "initrd" => entry.initrd.push(absolute_path(value)),
+                "options" => {
+                    if !entry.options.is_empty() {
+                        entry.options.push(' ');
+                    }
+
+                    entry.options.push_str(value);
+                }
+                _ => {}
+            }
+        }
+
+        entry.linux = linux?;
+        Some(entry)
+    }
+
+    /// Compares two entries by boot menu order: entries are grouped by `machine-id`, the most
+    /// recent version first (see [`compare_versions`]), and by entry file name otherwise.
+    #[must_use]
+    pub fn menu_order(&self, other: &Self) -> Ordering {
+        self.machine_id
+            .cmp(&other.machine_id)
+            .then_with(|| {
+                compare_versions(
+                    other.version.as_deref().unwrap_or_default(),
+                    self.version.as_deref().unwrap_or_default(),
+                )
+            })
+            .then_with(|| compare_versions(&other.id, &self.id))
+    }
+
+    /// Converts this entry to a [`BootProtocol::Linux`] boot entry.
+    ///
+    /// The first initial ramdisk is used as the entry `initrd`, the next ones are loaded as boot
+    /// modules, which are appended to it.
+    #[must_use]
+    pub fn into_boot_entry(self) -> BootEntry {
+        let title = match (self.title, self.version) {
+            (Some(title), _) => title,
+            (None, Some(version)) => version,
+            (None, None) => self.id,
+        };
+        let mut initrd = self.initrd.into_iter();
+
+        BootEntry {
+            title,
+            protocol: BootProtocol::Linux,
+            kernel: self.linux,
+            initrd: initrd.next(),
+            modules: initrd
+                .map(|path| BootModule {
+                    source: ModuleSource::File(path),
+                    cmdline: String::new(),
+                })
+                .collect(),
+            cmdline: self.options,
+            chainload: None,
+            slot: None,
+        }
+    }
+}
+
+/// Compares two version strings, the way `rpm` and the _BLS_ do.
+///
+/// Versions are split in alphabetic and numeric segments, separated by any other character.
+/// Numeric segments are compared numerically and are newer than alphabetic ones, which are
+/// compared lexicographically. A `~` sorts before anything, even the end of the version
+/// (`1.0~rc1` is older than `1.0`).
+#[must_use]
+pub fn compare_versions(a: &str, b: &str) -> Ordering {
+    let is_separator = |ch: &u8| !ch.is_ascii_alphanumeric() && *ch != b'~';
+    let (mut a, mut b) = (a.as_bytes(), b.as_bytes());
+
+    loop {
+        a = &a[a.iter().take_while(|ch| is_separator(ch)).count()..];
+        b = &b[b.iter().take_while(|ch| is_separator(ch)).count()..];
+
+        match (a.first(), b.first()) {
+            (Some(b'~'), Some(b'~')) => {
+                a = &a[1..];
+                b = &b[1..];
+            }
+            (Some(b'~'), _) => return Ordering::Less,
+            (_, Some(b'~')) => return Ordering::Greater,
+            // the longest version is the newest one
+            (None, _) | (_, None) => return a.len().cmp(&b.len()),
+            (Some(a_first), Some(b_first)) => {
+                let numeric = a_first.is_ascii_digit();
+
+                if numeric != b_first.is_ascii_digit() {
+                    return if numeric {
+                        Ordering::Greater
+                    } else {
+                        Ordering::Less
+                    };
+                }
+
+                let segment_len = |version: &[u8]| {
+                    version
+                        .iter()
+                        .take_while(|ch| {
+                            if numeric {
+                                ch.is_ascii_digit()
+                            } else {
+                                ch.is_ascii_alphabetic()
+                            }
+                        })
+                        .count()
+                };
+                let (a_segment, a_rest) = a.split_at(segment_len(a));
+                let (b_segment, b_rest) = b.split_at(segment_len(b));
+
+                let ordering = if numeric {
+                    let a_segment = trim_leading_zeros(a_segment);
+                    let b_segment = trim_leading_zeros(b_segment);
+
+                    a_segment
+                        .len()
+                        .cmp(&b_segment.len())
+                        .then_with(|| a_segment.cmp(b_segment))
+                } else {
+                    a_segment.cmp(b_segment)
+                };
+
+                if ordering != Ordering::Equal {
+                    return ordering;
+                }
+
+                (a, b) = (a_rest, b_rest);
+            }
+        }
+    }
+}
+
+/// Removes the leading zeros of a numeric version segment.
+fn trim_leading_zeros(segment: &[u8]) -> &[u8] {
+    &segment[segment.iter().take_while(|&&ch| ch == b'0').count()..]
+}
+
+/// Makes a path relative to the root of the partition, as the specification does not require a
+/// leading `/`.
+fn absolute_path(path: &str) -> String {
+    if path.starts_with('/') {
+        path.to_string()
+    } else {
+        format!("/{path}")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use core::cmp::Ordering;
+
+    use alloc::{string::String, vec::Vec};
+
+    use super::{compare_versions, BlsEntry};
+    use crate::boot::config::{BootModule, BootProtocol, ModuleSource};
+
+    #[test]
+    fn parses_entry() {
+        let entry = BlsEntry::parse(
+            "6a9857a3-6.8.5",
+            "# installed by kernel-install\n\
+             title      Fedora Linux 40 (Workstation Edition)\n\
+             version    6.8.5-301.fc40.x86_64\n\
+             machine-id 6a9857a393724b7a981ebb5b8495b9ea\n\
+             linux      /vmlinuz-6.8.5-301.fc40.x86_64\n\
+             \n\
+             initrd     /initramfs-6.8.5-301.fc40.x86_64.img\n\
+             initrd     microcode.img\n\
+             options    root=UUID=4bd3ab14 ro\n\
+             options    rhgb   quiet\n\
+             devicetree /dtb\n",
+        )
+        .unwrap();
+
+        assert_eq!(
+            entry,
+            BlsEntry {
+                id: String::from("6a9857a3-6.8.5"),
+                title: Some(String::from("Fedora Linux 40 (Workstation Edition)")),
+                version: Some(String::from("6.8.5-301.fc40.x86_64")),
+                machine_id: Some(String::from("6a9857a393724b7a981ebb5b8495b9ea")),
+                linux: String::from("/vmlinuz-6.8.5-301.fc40.x86_64"),
+                initrd: Vec::from([
+                    String::from("/initramfs-6.8.5-301.fc40.x86_64.img"),
+                    String::from("/microcode.img"),
+                ]),
+                options: String::from("root=UUID=4bd3ab14 ro rhgb   quiet"),
+            }
+        );
+    }
+
+    #[test]
+    fn discards_entries_without_kernel() {
+        assert_eq!(BlsEntry::parse("a", "title A\ninitrd /initrd.img\n"), None);
+        assert_eq!(BlsEntry::parse("a", ""), None);
+    }
+
+    #[test]
+    fn converts_to_boot_entry() {
+        let entry = BlsEntry::parse(
+            "a",
+            "version 6.1\nlinux vmlinuz\ninitrd /initrd.img\ninitrd /extra.img\noptions ro\n",
+        )
+        .unwrap()
+        .into_boot_entry();
+
+        assert_eq!(entry.title, "6.1");
+        assert_eq!(entry.protocol, BootProtocol::Linux);
+        assert_eq!(entry.kernel, "/vmlinuz");
+        assert_eq!(entry.initrd.as_deref(), Some("/initrd.img"));
+        assert_eq!(
+            entry.modules,
+            [BootModule {
+                source: ModuleSource::File(String::from("/extra.img")),
+                cmdline: String::new(),
+            }]
+        );
+        assert_eq!(entry.cmdline, "ro");
+
+        let entry = BlsEntry::parse("a", "linux /vmlinuz\n")
+            .unwrap()
+            .into_boot_entry();
+        assert_eq!(entry.title, "a");
+        assert_eq!(entry.initrd, None);
+    }
+
+    #[test]
+    fn compares_versions() {
+        let cases = [
+            ("1.0", "1.0", Ordering::Equal),
+            ("1.0", "2.0", Ordering::Less),
+            ("2.0.1", "2.0", Ordering::Greater),
+            ("6.10.2", "6.9.12", Ordering::Greater),
+            ("1.001", "1.1", Ordering::Equal),
+            ("1.0-1", "1.0_1", Ordering::Equal),
+            ("2.0a", "2.0", Ordering::Greater),
+            ("2.0", "2.0b", Ordering::Less),
+            ("1.0a", "1.0b", Ordering::Less),
+            ("1.0", "1.0a", Ordering::Less),
+            ("a", "1", Ordering::Less),
+            ("1.0~rc1", "1.0", Ordering::Less),
+            ("1.0~rc1", "1.0~rc2", Ordering::Less),
+            ("1.0~rc1~git", "1.0~rc1", Ordering::Less),
+            (
+                "6.8.5-301.fc40.x86_64",
+                "6.8.5-300.fc40.x86_64",
+                Ordering::Greater,
+            ),
+        ];
+
+        for (a, b, ordering) in cases {
+            assert_eq!(compare_versions(a, b), ordering, "{a} <=> {b}");
+            assert_eq!(compare_versions(b, a), ordering.reverse(), "{b} <=> {a}");
+        }
+    }
+
+    #[test]
+    fn sorts_entries_by_menu_order() {
+        let entry = |id: &str, machine_id: &str, version: &str| BlsEntry {
+            id: String::from(id),
+            machine_id: Some(String::from(machine_id)),
+            version: (!version.is_empty()).then(|| String::from(version)),
+            ..BlsEntry::default()
+        };
+
+        let mut entries = Vec::from([
+            entry("b-6.1", "b", "6.1"),
+            entry("a-6.1", "a", "6.1"),
+            entry("a-6.10", "a", "6.10"),
+            entry("a-6.9", "a", "6.9"),
+            entry("a-rescue-1", "a", ""),
+            entry("a-rescue-2", "a", ""),
+        ]);
+        entries.sort_by(BlsEntry::menu_order);
+
+        let ids: Vec<&str> = entries.iter().map(|entry| entry.id.as_str()).collect();
+        assert_eq!(
+            ids,
+            [
+                "a-6.10",
+                "a-6.9",
+                "a-6.1",
+                "a-rescue-2",
+                "a-rescue-1",
+                "b-6.1"
+            ]
+        );
+    }
+}
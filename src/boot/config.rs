@@ -0,0 +1,680 @@
+//! Bootloader configuration file related code.
+//!
+//! The configuration file (see [`CONFIG_PATH`]) is a list of `key = value` pairs, one per line.
+//! Empty lines, and lines starting with `#` are ignored.
+//!
+//! Global options must be set before the first boot entry. Each boot entry starts with a `title`
+//! key, and lasts until the next one:
+//!
+//! ```text
+//! timeout = 5
+//! default = 0
+//! kernel_partition = type:BC13C2FF-59E6-4262-A352-B275FD6F7172
+//! verify = warn
+//! kaslr = full
+//!
+//! title = Debian GNU/Linux
+//! protocol = linux
+//! kernel = /boot/vmlinuz-6.1
+//! initrd = /boot/initrd.img-6.1
+//! cmdline = root=/dev/sda2 ro quiet
+//!
+//! title = Hobby kernel
+//! protocol = multiboot2
+//! kernel = /boot/kernel.elf
+//! module = /boot/initfs.tar initfs
+//! module = label:drivers drivers
+//!
+//! title = Windows
+//! protocol = chainload
+//! partition = mbr:07,active
+//! ```
+//!
+//! Global options:
+//!
+//! - `timeout`: number of seconds before booting the default entry.
+//!
+//! - `default`: index (starting at 0) or title of the default entry.
+//!
+//! - `kernel_partition`: partition storing the native kernel image, as `label:<name>`,
+//!   `type:<guid>`, `guid:<guid>` or `mbr:<type>[,active]` (see [`PartitionSelector::parse`]). Can
+//!   be repeated, in which case the selectors are tried in order.
+//!
+//! - `slot_state`: partition whose first sector stores the state of the A/B boot slots, using the
+//!   same syntax as `kernel_partition`.
+//!
+//! - `verify`: what to do when a file of a boot entry has no valid signature, one of `ignore`,
+//!   `warn` or `refuse` (see [`crate::boot::verify`]). Defaults to `ignore`, unless a stricter
+//!   policy was set at build time.
+//!
+//! - `kaslr`: which base addresses of position-independent native kernels are randomised, one of
+//!   `off`, `virtual` or `full` (see [`crate::boot::kaslr`]). Defaults to `full`.
+//!
+//! Boot entry options:
+//!
+//! - `title` (mandatory): name of the entry.
+//!
+//! - `protocol` (mandatory): boot protocol of the kernel, one of `native`, `multiboot`,
+//!   `multiboot2`, `linux` or `chainload`.
+//!
+//! - `kernel` (mandatory, except for `chainload` entries): path to the kernel image.
+//!
+//! - `initrd`: path to the initial ramdisk.
+//!
+//! - `module`: path to a boot module, or selector of a raw partition storing it (with the same
+//!   syntax as `kernel_partition`), optionally followed by its command line. Can be repeated.
+//!   Modules of `linux` entries are appended to the initial ramdisk.
+//!
+//! - `cmdline`: kernel command line.
+//!
+//! - `disk` (`chainload` entries only): index (starting at 0) of the disk whose boot sector is
+//!   loaded. Defaults to the disk storing `partition`, if set.
+//!
+//! - `partition` (`chainload` entries only): partition whose boot sector is loaded, using the same
+//!   syntax as `kernel_partition`. If unset, the _MBR_ of `disk` is loaded instead. One of `disk`
+//!   or `partition` is mandatory.
+//!
+//! - `slot`: boot slot (`a` or `b`) the entry belongs to. When `slot_state` is set, the entry of
+//!   the active slot is booted by default, and the other one is used as a fallback (see
+//!   [`crate::boot::slots`]).
+
+use core::fmt::{self, Display, Formatter};
+
+use alloc::{
+    string::{String, ToString},
+    vec::Vec,
+};
+
+use crate::boot::kaslr::KaslrMode;
+use crate::boot::slots::BootSlot;
+use crate::boot::verify::VerifyPolicy;
+use crate::errors::ConfigError;
+use crate::fs::partitions::PartitionSelector;
+
+/// Path to the configuration file, relative to the root of the boot partition.
+pub const CONFIG_PATH: &str = "/fzboot/fzboot.cfg";
+
+/// Character starting a comment line.
+const COMMENT_CHAR: char = '#';
+
+/// Boot protocol used to load a kernel, and hand out control to it.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum BootProtocol {
+    /// `FrozenBoot` native kernel, as an ELF64 executable.
+    Native,
+
+    /// Multiboot compliant kernel.
+    Multiboot,
+
+    /// Multiboot2 compliant kernel.
+    Multiboot2,
+
+    /// Linux kernel (`bzImage`).
+    Linux,
+
+    /// Boot sector of another disk or partition, started in real mode.
+    Chainload,
+}
+
+impl BootProtocol {
+    /// Parses a boot protocol name.
+    fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "native" => Some(Self::Native),
+            "multiboot" => Some(Self::Multiboot),
+            "multiboot2" => Some(Self::Multiboot2),
+            "linux" => Some(Self::Linux),
+            "chainload" => Some(Self::Chainload),
+            _ => None,
+        }
+    }
+}
+
+/// Location of a boot module.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ModuleSource {
+    /// File stored on the partition storing the configuration file, given by its absolute path.
+    File(String),
+
+    /// Raw partition whose whole content is loaded as the module.
+    Partition(PartitionSelector),
+}
+
+impl ModuleSource {
+    /// Parses the location of a boot module, either an absolute path or a partition selector
+    /// (see [`PartitionSelector::parse`]).
+    fn parse(value: &str) -> Option<Self> {
+        match parse_path(value) {
+            Some(path) => Some(Self::File(path)),
+            None => PartitionSelector::parse(value).map(Self::Partition),
+        }
+    }
+}
+
+/// A boot module, loaded along with the kernel of a boot entry.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct BootModule {
+    /// Location of the module.
+    pub source: ModuleSource,
+
+    /// Command line associated with the module.
+    pub cmdline: String,
+}
+
+/// Location of the boot sector loaded by a [`BootProtocol::Chainload`] entry.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ChainloadTarget {
+    /// Index of the disk, in the order in which disks are enumerated.
+    pub disk: Option<usize>,
+
+    /// Partition whose first sector is loaded, or `None` to load the _MBR_ of the disk.
+    pub partition: Option<PartitionSelector>,
+}
+
+/// A boot entry, describing how to load a kernel.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct BootEntry {
+    /// Name of the entry.
+    pub title: String,
+
+    /// Boot protocol of the kernel.
+    pub protocol: BootProtocol,
+
+    /// Path to the kernel image (empty for [`BootProtocol::Chainload`] entries).
+    pub kernel: String,
+
+    /// Path to the initial ramdisk, if any.
+    pub initrd: Option<String>,
+
+    /// Boot modules loaded along with the kernel.
+    pub modules: Vec<BootModule>,
+
+    /// Kernel command line.
+    pub cmdline: String,
+
+    /// Boot sector to load, for [`BootProtocol::Chainload`] entries.
+    pub chainload: Option<ChainloadTarget>,
+
+    /// Boot slot the entry belongs to, if any.
+    pub slot: Option<BootSlot>,
+}
+
+/// Error raised while parsing the configuration file, along with the line on which it occurred.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ConfigParseError {
+    /// Line number (starting at 1).
+    pub line: usize,
+
+    /// Error type.
+    pub error: ConfigError,
+}
+
+impl Display for ConfigParseError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "line {}: ", self.line)?;
+
+        match self.error {
+            ConfigError::InvalidSyntax => f.write_str("expected `key = value`"),
+            ConfigError::UnknownKey => f.write_str("unknown key"),
+            ConfigError::InvalidValue => f.write_str("invalid value"),
+            ConfigError::DuplicateKey => f.write_str("duplicate key"),
+            ConfigError::MisplacedKey => f.write_str("key not allowed here"),
+            ConfigError::MissingKey(key) => write!(f, "boot entry without `{key}` key"),
+        }
+    }
+}
+
+/// Parsed configuration file.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct BootConfig {
+    /// Number of seconds before booting the default entry, if set.
+    pub timeout: Option<u32>,
+
+    /// Index of the default entry in `entries`.
+    pub default: usize,
+
+    /// Selectors used to locate the native kernel partition, by order of priority.
+    pub kernel_partition: Vec<PartitionSelector>,
+
+    /// Selector of the partition storing the state of the boot slots, if any.
+    pub slot_state: Option<PartitionSelector>,
+
+    /// Signature verification policy, if set.
+    pub verify: Option<VerifyPolicy>,
+
+    /// Address space layout randomisation mode of native kernels, if set.
+    pub kaslr: Option<KaslrMode>,
+
+    /// Boot entries, in the order in which they are defined.
+    pub entries: Vec<BootEntry>,
+}
+
+impl BootConfig {
+    /// Parses the content of a configuration file.
+    ///
+    /// Parsing does not stop at the first error: invalid lines are skipped, and boot entries with
+    /// missing mandatory keys are discarded. Every error is returned along with the configuration.
+    #[must_use]
+    pub fn parse(text: &str) -> (Self, Vec<ConfigParseError>) {
+        let mut config = Self::default();
+        let mut errors = Vec::new();
+        let mut default: Option<(usize, &str)> = None;
+        let mut entry: Option<PendingEntry> = None;
+
+        for (index, line) in text.lines().enumerate() {
+            let line_number = index + 1;
+            let line = line.trim();
+
+            if line.is_empty() || line.starts_with(COMMENT_CHAR) {
+                continue;
+            }
+
+            let Some((key, value)) = line.split_once('=') else {
+                errors.push(ConfigParseError {
+                    line: line_number,
+                    error: ConfigError::InvalidSyntax,
+                });
+                continue;
+            };
+            let (key, value) = (key.trim(), value.trim());
+
+            let result = match (key, &mut entry) {
+                ("title", _) => {
+                    if let Some(pending) = entry.take() {
+                        pending.finish(&mut config.entries, &mut errors);
+                    }
+
+                    entry = Some(PendingEntry::new(line_number, value));
+                    Ok(())
+                }
+                ("timeout", None) => set_once(&mut config.timeout, value.parse().ok()),
+                ("default", None) => set_once(&mut default, Some((line_number, value))),
+                ("kernel_partition", None) => PartitionSelector::parse(value)
+                    .map(|selector| config.kernel_partition.push(selector))
+                    .ok_or(ConfigError::InvalidValue),
+                ("slot_state", None) => {
+                    set_once(&mut config.slot_state, PartitionSelector::parse(value))
+                }
+                ("verify", None) => set_once(&mut config.verify, VerifyPolicy::from_name(value)),
+                ("kaslr", None) => set_once(&mut config.kaslr, KaslrMode::from_name(value)),
+                (
+                    "timeout" | "default" | "kernel_partition" | "slot_state" | "verify" | "kaslr",
+                    Some(_),
+                ) => Err(ConfigError::MisplacedKey),
+                (_, Some(pending)) => pending.set(key, value),
+                (_, None) => Err(if PendingEntry::is_entry_key(key) {
+                    ConfigError::MisplacedKey
+                } else {
+                    ConfigError::UnknownKey
+                }),
+            };
+
+            if let Err(error) = result {
+                errors.push(ConfigParseError {
+                    line: line_number,
+                    error,
+                });
+            }
+        }
+
+        if let Some(pending) = entry {
+            pending.finish(&mut config.entries, &mut errors);
+        }
+
+        if let Some((line, value)) = default {
+            match config.find_entry(value) {
+                Some(index) => config.default = index,
+                None => errors.push(ConfigParseError {
+                    line,
+                    error: ConfigError::InvalidValue,
+                }),
+            }
+        }
+
+        (config, errors)
+    }
+
+    /// Returns the default boot entry, if any.
+    #[must_use]
+    pub fn default_entry(&self) -> Option<&BootEntry> {
+        self.entries.get(self.default)
+    }
+
+    /// Finds a boot entry from its index or title.
+    fn find_entry(&self, name: &str) -> Option<usize> {
+        match name.parse::<usize>() {
+            Ok(index) => (index < self.entries.len()).then_some(index),
+            Err(_) => self.entries.iter().position(|entry| entry.title == name),
+        }
+    }
+}
+
+/// Boot entry currently being parsed.
+struct PendingEntry {
+    line: usize,
+    title: String,
+    protocol: Option<BootProtocol>,
+    kernel: Option<String>,
+    initrd: Option<String>,
+    modules: Vec<BootModule>,
+    cmdline: Option<String>,
+    disk: Option<usize>,
+    partition: Option<PartitionSelector>,
+    slot: Option<BootSlot>,
+}
+
+impl PendingEntry {
+    /// Starts a new boot entry, defined at line `line`.
+    fn new(line: usize, title: &str) -> Self {
+        Self {
+            line,
+            title: title.to_string(),
+            protocol: None,
+            kernel: None,
+            initrd: None,
+            modules: Vec::new(),
+            cmdline: None,
+            disk: None,
+            partition: None,
+            slot: None,
+        }
+    }
+
+    /// Checks if a key can be set in a boot entry.
+    fn is_entry_key(key: &str) -> bool {
+        matches!(
+            key,
+            "protocol" | "kernel" | "initrd" | "module" | "cmdline" | "disk" | "partition" | "slot"
+        )
+    }
+
+    /// Sets one of the boot entry options.
+    fn set(&mut self, key: &str, value: &str) -> Result<(), ConfigError> {
+        match key {
+            "protocol" => set_once(&mut self.protocol, BootProtocol::from_name(value)),
+            "kernel" => set_once(&mut self.kernel, parse_path(value)),
+            "initrd" => set_once(&mut self.initrd, parse_path(value)),
+            "cmdline" => set_once(&mut self.cmdline, Some(value.to_string())),
+            "disk" => set_once(&mut self.disk, value.parse().ok()),
+            "partition" => set_once(&mut self.partition, PartitionSelector::parse(value)),
+            "slot" => set_once(&mut self.slot, BootSlot::from_name(value)),
+            "module" => {
+                let (source, cmdline) =
+                    value.split_once(char::is_whitespace).unwrap_or((value, ""));
+
+                self.modules.push(BootModule {
+                    source: ModuleSource::parse(source).ok_or(ConfigError::InvalidValue)?,
+                    cmdline: cmdline.trim().to_string(),
+                });
+                Ok(())
+            }
+            _ => Err(ConfigError::UnknownKey),
+        }
+    }
+
+    /// Completes the boot entry, and appends it to `entries` if all the mandatory keys are set.
+    fn finish(self, entries: &mut Vec<BootEntry>, errors: &mut Vec<ConfigParseError>) {
+        let missing_key = |key| ConfigParseError {
+            line: self.line,
+            error: ConfigError::MissingKey(key),
+        };
+
+        let Some(protocol) = self.protocol else {
+            errors.push(missing_key("protocol"));
+            return;
+        };
+
+        let (kernel, chainload) = if protocol == BootProtocol::Chainload {
+            if self.disk.is_none() && self.partition.is_none() {
+                errors.push(missing_key("disk"));
+                return;
+            }
+
+            let target = ChainloadTarget {
+                disk: self.disk,
+                partition: self.partition,
+            };
+
+            (String::new(), Some(target))
+        } else {
+            let Some(kernel) = self.kernel else {
+                errors.push(missing_key("kernel"));
+                return;
+            };
+
+            (kernel, None)
+        };
+
+        entries.push(BootEntry {
+            title: self.title,
+            protocol,
+            kernel,
+            initrd: self.initrd,
+            modules: self.modules,
+            cmdline: self.cmdline.unwrap_or_default(),
+            chainload,
+            slot: self.slot,
+        });
+    }
+}
+
+/// Sets an option that can only be set once, from its parsed value.
+fn set_once<T>(option: &mut Option<T>, value: Option<T>) -> Result<(), ConfigError> {
+    if option.is_some() {
+        return Err(ConfigError::DuplicateKey);
+    }
+
+    *option = Some(value.ok_or(ConfigError::InvalidValue)?);
+    Ok(())
+}
+
+/// Checks that a path is absolute (relative to the root of the partition).
+fn parse_path(value: &str) -> Option<String> {
+    value.starts_with('/').then(|| value.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use alloc::{string::String, vec::Vec};
+
+    use super::{BootConfig, BootModule, BootProtocol, ChainloadTarget, ModuleSource};
+    use crate::errors::ConfigError;
+    use crate::fs::partitions::PartitionSelector;
+
+    /// Parses `text`, and returns the errors as `(line, error)` pairs.
+    fn parse(text: &str) -> (BootConfig, Vec<(usize, ConfigError)>) {
+        let (config, errors) = BootConfig::parse(text);
+
+        (
+            config,
+            errors
+                .iter()
+                .map(|error| (error.line, error.error))
+                .collect(),
+        )
+    }
+
+    #[test]
+    fn parses_global_options_and_entries() {
+        let (config, errors) = parse(
+            "# comment\n\
+             timeout = 5\n\
+             default = Hobby kernel\n\
+             kernel_partition = label:kernelfs\n\
+             kernel_partition = mbr:7f,active\n\
+             \n\
+             title = Debian GNU/Linux\n\
+             protocol = linux\n\
+             kernel = /boot/vmlinuz\n\
+             initrd = /boot/initrd.img\n\
+             cmdline = root=/dev/sda2 ro quiet\n\
+             \n\
+             title = Hobby kernel\n\
+             protocol = multiboot2\n\
+             kernel = /boot/kernel.elf\n\
+             module = /boot/initfs.tar  initfs\n\
+             module = label:drivers\n\
+             \n\
+             title = Windows\n\
+             protocol = chainload\n\
+             partition = mbr:07,active\n",
+        );
+
+        assert_eq!(errors, []);
+        assert_eq!(config.timeout, Some(5));
+        assert_eq!(
+            config.kernel_partition,
+            [
+                PartitionSelector::Label(String::from("kernelfs")),
+                PartitionSelector::Mbr {
+                    part_type: Some(0x7F),
+                    active: true,
+                },
+            ]
+        );
+        assert_eq!(config.entries.len(), 3);
+        assert_eq!(config.default, 1);
+
+        let linux = &config.entries[0];
+        assert_eq!(linux.title, "Debian GNU/Linux");
+        assert_eq!(linux.protocol, BootProtocol::Linux);
+        assert_eq!(linux.kernel, "/boot/vmlinuz");
+        assert_eq!(linux.initrd.as_deref(), Some("/boot/initrd.img"));
+        assert_eq!(linux.cmdline, "root=/dev/sda2 ro quiet");
+
+        let multiboot = config.default_entry().unwrap();
+        assert_eq!(multiboot.protocol, BootProtocol::Multiboot2);
+        assert_eq!(multiboot.cmdline, "");
+        assert_eq!(
+            multiboot.modules,
+            [
+                BootModule {
+                    source: ModuleSource::File(String::from("/boot/initfs.tar")),
+                    cmdline: String::from("initfs"),
+                },
+                BootModule {
+                    source: ModuleSource::Partition(PartitionSelector::Label(String::from(
+                        "drivers"
+                    ))),
+                    cmdline: String::new(),
+                },
+            ]
+        );
+
+        let chainload = &config.entries[2];
+        assert_eq!(chainload.kernel, "");
+        assert_eq!(
+            chainload.chainload,
+            Some(ChainloadTarget {
+                disk: None,
+                partition: Some(PartitionSelector::Mbr {
+                    part_type: Some(0x07),
+                    active: true,
+                }),
+            })
+        );
+    }
+
+    #[test]
+    fn reports_errors_with_line_numbers() {
+        let (config, errors) = parse(
+            "timeout = soon\n\
+             no separator\n\
+             colour = blue\n\
+             kernel = /boot/kernel.elf\n\
+             title = A\n\
+             protocol = native\n\
+             protocol = linux\n\
+             kernel = boot/kernel.elf\n\
+             kernel = /boot/kernel.elf\n\
+             timeout = 3\n\
+             video = 1024x768\n",
+        );
+
+        assert_eq!(
+            errors,
+            [
+                (1, ConfigError::InvalidValue),
+                (2, ConfigError::InvalidSyntax),
+                (3, ConfigError::UnknownKey),
+                (4, ConfigError::MisplacedKey),
+                (7, ConfigError::DuplicateKey),
+                (8, ConfigError::InvalidValue),
+                (10, ConfigError::MisplacedKey),
+                (11, ConfigError::UnknownKey),
+            ]
+        );
+        assert_eq!(config.timeout, None);
+        assert_eq!(config.entries.len(), 1);
+        assert_eq!(config.entries[0].protocol, BootProtocol::Native);
+        assert_eq!(config.entries[0].kernel, "/boot/kernel.elf");
+    }
+
+    #[test]
+    fn discards_incomplete_entries() {
+        let (config, errors) = parse(
+            "title = No protocol\n\
+             kernel = /boot/kernel.elf\n\
+             title = No kernel\n\
+             protocol = native\n\
+             title = No boot sector\n\
+             protocol = chainload\n\
+             title = Complete\n\
+             protocol = chainload\n\
+             disk = 1\n",
+        );
+
+        assert_eq!(
+            errors,
+            [
+                (1, ConfigError::MissingKey("protocol")),
+                (3, ConfigError::MissingKey("kernel")),
+                (5, ConfigError::MissingKey("disk")),
+            ]
+        );
+        assert_eq!(config.entries.len(), 1);
+        assert_eq!(config.entries[0].title, "Complete");
+        assert_eq!(
+            config.entries[0].chainload,
+            Some(ChainloadTarget {
+                disk: Some(1),
+                partition: None,
+            })
+        );
+    }
+
+    #[test]
+    fn selects_default_entry() {
+        let text = "title = A\nprotocol = native\nkernel = /a\n\
+                    title = B\nprotocol = native\nkernel = /b\n";
+
+        let (config, errors) = parse(text);
+        assert_eq!(errors, []);
+        assert_eq!(config.default_entry().unwrap().title, "A");
+
+        let (config, errors) = parse(&(String::from("default = 1\n") + text));
+        assert_eq!(errors, []);
+        assert_eq!(config.default_entry().unwrap().title, "B");
+
+        let (config, errors) = parse(&(String::from("default = 2\n") + text));
+        assert_eq!(errors, [(1, ConfigError::InvalidValue)]);
+        assert_eq!(config.default, 0);
+
+        let (_, errors) = parse(&(String::from("default = C\n") + text));
+        assert_eq!(errors, [(1, ConfigError::InvalidValue)]);
+    }
+
+    #[test]
+    fn rejects_unknown_protocols() {
+        let (config, errors) = parse("title = A\nprotocol = beos\nkernel = /a\n");
+
+        assert_eq!(
+            errors,
+            [
+                (2, ConfigError::InvalidValue),
+                (1, ConfigError::MissingKey("protocol")),
+            ]
+        );
+        assert!(config.entries.is_empty());
+    }
+}
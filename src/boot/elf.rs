@@ -0,0 +1,710 @@
+//! ELF (_Executable and Linkable Format_) kernel images related code.
+//!
+//! Both 32-bit (`i386`) and 64-bit (`x86_64`) little-endian executables are supported. The
+//! loadable segments (`PT_LOAD` program headers) describe where each part of the image has to be
+//! copied, both in physical and virtual memory, along with its access rights.
+//!
+//! Position-independent 64-bit images (`ET_DYN`) can be loaded at any virtual address, provided
+//! that their relocations are applied (see [`ElfImage::relocations`]). Only the relative
+//! relocations emitted for static position-independent executables are supported.
+//!
+//! Based on the following specification: <https://refspecs.linuxfoundation.org/elf/gabi4+/contents.html>
+
+use core::mem;
+
+use bytemuck::{Pod, Zeroable};
+
+use crate::errors::BootError;
+
+/// Magic number at the start of every ELF file.
+const ELF_MAGIC: [u8; 4] = [0x7F, b'E', b'L', b'F'];
+
+/// `EI_CLASS`: 32-bit objects.
+const ELF_CLASS_32: u8 = 1;
+
+/// `EI_CLASS`: 64-bit objects.
+const ELF_CLASS_64: u8 = 2;
+
+/// `EI_DATA`: little-endian encoding.
+const ELF_DATA_LSB: u8 = 1;
+
+/// `EI_VERSION` / `e_version`: current ELF version.
+const ELF_VERSION_CURRENT: u8 = 1;
+
+/// `e_type`: executable file.
+const ELF_TYPE_EXEC: u16 = 2;
+
+/// `e_type`: shared object file (position-independent executable).
+const ELF_TYPE_DYN: u16 = 3;
+
+/// `e_machine`: Intel 80386 architecture.
+const ELF_MACHINE_386: u16 = 0x03;
+
+/// `e_machine`: AMD x86-64 architecture.
+const ELF_MACHINE_X86_64: u16 = 0x3E;
+
+/// `p_type`: loadable segment.
+pub const PT_LOAD: u32 = 1;
+
+/// `p_type`: dynamic linking information.
+pub const PT_DYNAMIC: u32 = 2;
+
+/// `d_tag`: end of the dynamic section.
+const DT_NULL: u64 = 0;
+
+/// `d_tag`: address of the relocation table with explicit addends.
+const DT_RELA: u64 = 7;
+
+/// `d_tag`: total size of the `DT_RELA` relocation table, in bytes.
+const DT_RELASZ: u64 = 8;
+
+/// `d_tag`: size of a `DT_RELA` relocation entry, in bytes.
+const DT_RELAENT: u64 = 9;
+
+/// `d_tag`: address of the relocation table with implicit addends.
+const DT_REL: u64 = 17;
+
+/// `d_tag`: address of the compact relative relocation table.
+const DT_RELR: u64 = 36;
+
+/// `r_type`: no relocation.
+const R_X86_64_NONE: u32 = 0;
+
+/// `r_type`: the location is set to the load offset of the image plus the addend.
+pub const R_X86_64_RELATIVE: u32 = 8;
+
+/// `p_flags`: executable segment.
+const PF_X: u32 = 1 << 0;
+
+/// `p_flags`: writable segment.
+const PF_W: u32 = 1 << 1;
+
+/// `p_flags`: readable segment.
+const PF_R: u32 = 1 << 2;
+
+/// Size of the identification bytes (`e_ident`), which are common to every ELF class.
+pub const ELF_IDENT_SIZE: usize = 16;
+
+/// Class (word size) of an ELF image.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ElfClass {
+    /// 32-bit `i386` image.
+    Elf32,
+
+    /// 64-bit `x86_64` image.
+    Elf64,
+}
+
+impl ElfClass {
+    /// Returns the size of the file header for this class.
+    fn header_size(self) -> usize {
+        match self {
+            Self::Elf32 => mem::size_of::<Elf32RawHeader>(),
+            Self::Elf64 => mem::size_of::<Elf64RawHeader>(),
+        }
+    }
+
+    /// Returns the size of a program header for this class.
+    fn program_header_size(self) -> usize {
+        match self {
+            Self::Elf32 => mem::size_of::<Elf32RawProgramHeader>(),
+            Self::Elf64 => mem::size_of::<Elf64RawProgramHeader>(),
+        }
+    }
+}
+
+/// ELF32 file header, as stored in the image.
+#[derive(Clone, Copy, Debug, Pod, Zeroable)]
+#[repr(C)]
+struct Elf32RawHeader {
+    ident: [u8; ELF_IDENT_SIZE],
+    elf_type: u16,
+    machine: u16,
+    version: u32,
+    entry: u32,
+    phoff: u32,
+    shoff: u32,
+    flags: u32,
+    ehsize: u16,
+    phentsize: u16,
+    phnum: u16,
+    shentsize: u16,
+    shnum: u16,
+    shstrndx: u16,
+}
+
+/// ELF64 file header, as stored in the image.
+#[derive(Clone, Copy, Debug, Pod, Zeroable)]
+#[repr(C)]
+struct Elf64RawHeader {
+    ident: [u8; ELF_IDENT_SIZE],
+    elf_type: u16,
+    machine: u16,
+    version: u32,
+    entry: u64,
+    phoff: u64,
+    shoff: u64,
+    flags: u32,
+    ehsize: u16,
+    phentsize: u16,
+    phnum: u16,
+    shentsize: u16,
+    shnum: u16,
+    shstrndx: u16,
+}
+
+/// ELF32 program header, as stored in the image.
+#[derive(Clone, Copy, Debug, Pod, Zeroable)]
+#[repr(C)]
+struct Elf32RawProgramHeader {
+    p_type: u32,
+    offset: u32,
+    vaddr: u32,
+    paddr: u32,
+    filesz: u32,
+    memsz: u32,
+    flags: u32,
+    align: u32,
+}
+
+/// ELF64 program header, as stored in the image.
+#[derive(Clone, Copy, Debug, Pod, Zeroable)]
+#[repr(C)]
+struct Elf64RawProgramHeader {
+    p_type: u32,
+    flags: u32,
+    offset: u64,
+    vaddr: u64,
+    paddr: u64,
+    filesz: u64,
+    memsz: u64,
+    align: u64,
+}
+
+/// ELF64 dynamic section entry, as stored in the image.
+#[derive(Clone, Copy, Debug, Pod, Zeroable)]
+#[repr(C)]
+struct Elf64RawDynamic {
+    tag: u64,
+    val: u64,
+}
+
+/// ELF64 relocation entry with an explicit addend, as stored in the image.
+#[derive(Clone, Copy, Debug, Pod, Zeroable)]
+#[repr(C)]
+struct Elf64RawRela {
+    offset: u64,
+    info: u64,
+    addend: i64,
+}
+
+/// ELF file header.
+///
+/// Located at the very start of the file, it identifies the file format, and locates the program
+/// headers table. ELF32 headers are widened to 64-bit values.
+#[derive(Clone, Copy, Debug)]
+pub struct ElfHeader {
+    class: ElfClass,
+    elf_type: u16,
+    entry: u64,
+    phoff: u64,
+    phnum: u16,
+}
+
+impl ElfHeader {
+    /// Reads and validates the [`ElfHeader`] of an ELF image.
+    ///
+    /// Only the first bytes of the image are required (52 bytes for 32-bit images, 64 bytes for
+    /// 64-bit images).
+    ///
+    /// # Errors
+    ///
+    /// Returns [`BootError::InvalidImage`] if the image does not start with a valid ELF header, and
+    /// [`BootError::UnsupportedProtocol`] if the image is neither a 32-bit `i386` nor a 64-bit
+    /// `x86_64` little-endian executable.
+    pub fn from_image(image: &[u8]) -> Result<Self, BootError> {
+        let ident = image.get(..ELF_IDENT_SIZE).ok_or(BootError::InvalidImage)?;
+
+        if ident[..4] != ELF_MAGIC || ident[6] != ELF_VERSION_CURRENT {
+            return Err(BootError::InvalidImage);
+        }
+
+        let class = match ident[4] {
+            ELF_CLASS_32 => ElfClass::Elf32,
+            ELF_CLASS_64 => ElfClass::Elf64,
+            _ => return Err(BootError::UnsupportedProtocol),
+        };
+
+        let header_bytes = image
+            .get(..class.header_size())
+            .ok_or(BootError::InvalidImage)?;
+
+        let (header, machine, version, phentsize) = match class {
+            ElfClass::Elf32 => {
+                let raw: Elf32RawHeader = bytemuck::pod_read_unaligned(header_bytes);
+                let header = Self {
+                    class,
+                    elf_type: raw.elf_type,
+                    entry: u64::from(raw.entry),
+                    phoff: u64::from(raw.phoff),
+                    phnum: raw.phnum,
+                };
+
+                (header, raw.machine, raw.version, raw.phentsize)
+            }
+            ElfClass::Elf64 => {
+                let raw: Elf64RawHeader = bytemuck::pod_read_unaligned(header_bytes);
+                let header = Self {
+                    class,
+                    elf_type: raw.elf_type,
+                    entry: raw.entry,
+                    phoff: raw.phoff,
+                    phnum: raw.phnum,
+                };
+
+                (header, raw.machine, raw.version, raw.phentsize)
+            }
+        };
+
+        if version != u32::from(ELF_VERSION_CURRENT) {
+            return Err(BootError::InvalidImage);
+        }
+
+        let expected_machine = match class {
+            ElfClass::Elf32 => ELF_MACHINE_386,
+            ElfClass::Elf64 => ELF_MACHINE_X86_64,
+        };
+
+        if ident[5] != ELF_DATA_LSB
+            || machine != expected_machine
+            || !matches!(header.elf_type, ELF_TYPE_EXEC | ELF_TYPE_DYN)
+        {
+            return Err(BootError::UnsupportedProtocol);
+        }
+
+        if usize::from(phentsize) != class.program_header_size() {
+            return Err(BootError::InvalidImage);
+        }
+
+        Ok(header)
+    }
+
+    /// Returns the class (word size) of the image.
+    #[must_use]
+    pub fn class(&self) -> ElfClass {
+        self.class
+    }
+
+    /// Returns the virtual address of the entry point.
+    #[must_use]
+    pub fn entry(&self) -> u64 {
+        self.entry
+    }
+
+    /// Checks if the image is position-independent (`ET_DYN`).
+    #[must_use]
+    pub fn position_independent(&self) -> bool {
+        self.elf_type == ELF_TYPE_DYN
+    }
+
+    /// Returns the number of program headers.
+    #[must_use]
+    pub fn program_headers_count(&self) -> usize {
+        usize::from(self.phnum)
+    }
+
+    /// Returns the offset of the end of the program headers table, which is the minimum number of
+    /// bytes of the image required to locate its segments.
+    #[must_use]
+    pub fn program_headers_end(&self) -> usize {
+        usize::try_from(self.phoff)
+            .unwrap_or(usize::MAX)
+            .saturating_add(self.program_headers_count() * self.class.program_header_size())
+    }
+}
+
+/// ELF program header, describing a segment of the image.
+///
+/// ELF32 program headers are widened to 64-bit values.
+#[derive(Clone, Copy, Debug)]
+pub struct ElfProgramHeader {
+    p_type: u32,
+    flags: u32,
+    offset: u64,
+    vaddr: u64,
+    paddr: u64,
+    filesz: u64,
+    memsz: u64,
+    align: u64,
+}
+
+impl ElfProgramHeader {
+    /// Reads a program header of the given class.
+    fn from_bytes(class: ElfClass, bytes: &[u8]) -> Self {
+        match class {
+            ElfClass::Elf32 => {
+                let raw: Elf32RawProgramHeader = bytemuck::pod_read_unaligned(bytes);
+
+                Self {
+                    p_type: raw.p_type,
+                    flags: raw.flags,
+                    offset: u64::from(raw.offset),
+                    vaddr: u64::from(raw.vaddr),
+                    paddr: u64::from(raw.paddr),
+                    filesz: u64::from(raw.filesz),
+                    memsz: u64::from(raw.memsz),
+                    align: u64::from(raw.align),
+                }
+            }
+            ElfClass::Elf64 => {
+                let raw: Elf64RawProgramHeader = bytemuck::pod_read_unaligned(bytes);
+
+                Self {
+                    p_type: raw.p_type,
+                    flags: raw.flags,
+                    offset: raw.offset,
+                    vaddr: raw.vaddr,
+                    paddr: raw.paddr,
+                    filesz: raw.filesz,
+                    memsz: raw.memsz,
+                    align: raw.align,
+                }
+            }
+        }
+    }
+
+    /// Returns the type of the segment (for instance [`PT_LOAD`]).
+    #[must_use]
+    pub fn segment_type(&self) -> u32 {
+        self.p_type
+    }
+
+    /// Returns the offset of the segment in the image.
+    #[must_use]
+    pub fn offset(&self) -> u64 {
+        self.offset
+    }
+
+    /// Returns the virtual address at which the segment must be mapped.
+    #[must_use]
+    pub fn virt_addr(&self) -> u64 {
+        self.vaddr
+    }
+
+    /// Returns the physical address at which the segment must be loaded.
+    #[must_use]
+    pub fn phys_addr(&self) -> u64 {
+        self.paddr
+    }
+
+    /// Returns the size of the segment in the image, in bytes.
+    #[must_use]
+    pub fn file_size(&self) -> u64 {
+        self.filesz
+    }
+
+    /// Returns the size of the segment in memory, in bytes.
+    ///
+    /// The bytes located after [`Self::file_size`] must be zeroed (usually the `.bss` section).
+    #[must_use]
+    pub fn mem_size(&self) -> u64 {
+        self.memsz
+    }
+
+    /// Returns the required alignment of the segment, in bytes.
+    #[must_use]
+    pub fn alignment(&self) -> u64 {
+        self.align
+    }
+
+    /// Checks if the segment is readable.
+    #[must_use]
+    pub fn readable(&self) -> bool {
+        self.flags & PF_R != 0
+    }
+
+    /// Checks if the segment is writable.
+    #[must_use]
+    pub fn writable(&self) -> bool {
+        self.flags & PF_W != 0
+    }
+
+    /// Checks if the segment is executable.
+    #[must_use]
+    pub fn executable(&self) -> bool {
+        self.flags & PF_X != 0
+    }
+
+    /// Returns the offset of the end of the segment in the image.
+    fn file_end(&self) -> Option<u64> {
+        self.offset.checked_add(self.filesz)
+    }
+}
+
+/// Relocation of a position-independent image (`Elf64_Rela` entry).
+#[derive(Clone, Copy, Debug)]
+pub struct ElfRelocation {
+    offset: u64,
+    r_type: u32,
+    addend: i64,
+}
+
+impl ElfRelocation {
+    /// Reads a relocation entry.
+    fn from_bytes(bytes: &[u8]) -> Self {
+        let raw: Elf64RawRela = bytemuck::pod_read_unaligned(bytes);
+
+        Self {
+            offset: raw.offset,
+            r_type: u32::try_from(raw.info & 0xFFFF_FFFF).expect("invalid relocation type"),
+            addend: raw.addend,
+        }
+    }
+
+    /// Returns the link-time virtual address of the location to relocate.
+    #[must_use]
+    pub fn offset(&self) -> u64 {
+        self.offset
+    }
+
+    /// Returns the type of the relocation (for instance [`R_X86_64_RELATIVE`]).
+    #[must_use]
+    pub fn relocation_type(&self) -> u32 {
+        self.r_type
+    }
+
+    /// Returns the 64-bit value to store at the relocated location, for an image loaded
+    /// `load_offset` bytes away from its link-time virtual addresses.
+    ///
+    /// Returns `None` if the relocation type is not supported.
+    #[must_use]
+    pub fn value(&self, load_offset: u64) -> Option<u64> {
+        match self.r_type {
+            R_X86_64_RELATIVE => Some(load_offset.wrapping_add_signed(self.addend)),
+            _ => None,
+        }
+    }
+}
+
+/// A validated ELF kernel image.
+#[derive(Clone, Copy, Debug)]
+pub struct ElfImage<'a> {
+    image: &'a [u8],
+    header: ElfHeader,
+}
+
+impl<'a> ElfImage<'a> {
+    /// Parses an ELF kernel image, and checks that every loadable segment is consistent.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`BootError::InvalidImage`] if the image is malformed (truncated image, segments
+    /// out of bounds or overlapping the end of the address space), and
+    /// [`BootError::UnsupportedProtocol`] if the image is neither a 32-bit `i386` nor a 64-bit
+    /// `x86_64` executable.
+    pub fn parse(image: &'a [u8]) -> Result<Self, BootError> {
+        let header = ElfHeader::from_image(image)?;
+
+        if header.program_headers_end() > image.len() {
+            return Err(BootError::InvalidImage);
+        }
+
+        let elf_image = Self { image, header };
+        let image_len = u64::try_from(image.len()).unwrap_or(u64::MAX);
+
+        for segment in elf_image.load_segments() {
+            let in_bounds = segment.file_end().is_some_and(|end| end <= image_len);
+            let fits_in_memory = segment
+                .virt_addr()
+                .checked_add(segment.mem_size())
+                .is_some()
+                && segment
+                    .phys_addr()
+                    .checked_add(segment.mem_size())
+                    .is_some();
+
+            if !in_bounds || !fits_in_memory || segment.file_size() > segment.mem_size() {
+                return Err(BootError::InvalidImage);
+            }
+        }
+
+        if elf_image.load_segments().next().is_none() {
+            return Err(BootError::InvalidImage);
+        }
+
+        Ok(elf_image)
+    }
+
+    /// Returns the size of the ELF image, computed from its first bytes.
+    ///
+    /// The returned size covers the headers and every loadable segment, which is enough to load
+    /// the kernel. `image` must contain at least [`ElfHeader::program_headers_end`] bytes.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the header is invalid, or if `image` does not contain the program
+    /// headers.
+    pub fn image_size(image: &[u8]) -> Result<usize, BootError> {
+        let header = ElfHeader::from_image(image)?;
+        let headers_end = header.program_headers_end();
+
+        if headers_end > image.len() {
+            return Err(BootError::InvalidImage);
+        }
+
+        ElfImage { image, header }
+            .program_headers()
+            .filter(|segment| segment.segment_type() == PT_LOAD)
+            .try_fold(headers_end, |size, segment| {
+                let end = usize::try_from(segment.file_end()?).ok()?;
+                Some(size.max(end))
+            })
+            .ok_or(BootError::InvalidImage)
+    }
+
+    /// Returns the ELF header of the image.
+    #[must_use]
+    pub fn header(&self) -> &ElfHeader {
+        &self.header
+    }
+
+    /// Returns the virtual address of the entry point.
+    #[must_use]
+    pub fn entry(&self) -> u64 {
+        self.header.entry()
+    }
+
+    /// Returns an iterator over every program header of the image.
+    pub fn program_headers(&self) -> impl Iterator<Item = ElfProgramHeader> + 'a {
+        let image = self.image;
+        let class = self.header.class();
+        let entry_size = class.program_header_size();
+        let phoff = usize::try_from(self.header.phoff).unwrap_or(usize::MAX);
+
+        (0..self.header.program_headers_count()).filter_map(move |idx| {
+            let start = phoff.checked_add(idx * entry_size)?;
+            let bytes = image.get(start..start.checked_add(entry_size)?)?;
+
+            Some(ElfProgramHeader::from_bytes(class, bytes))
+        })
+    }
+
+    /// Returns an iterator over the loadable segments of the image.
+    pub fn load_segments(&self) -> impl Iterator<Item = ElfProgramHeader> + 'a {
+        self.program_headers()
+            .filter(|segment| segment.segment_type() == PT_LOAD)
+    }
+
+    /// Returns the lowest virtual address of the loadable segments, and the end of the highest one.
+    #[must_use]
+    pub fn virt_bounds(&self) -> (u64, u64) {
+        self.load_segments()
+            .fold((u64::MAX, 0), |(start, end), segment| {
+                (
+                    start.min(segment.virt_addr()),
+                    end.max(segment.virt_addr() + segment.mem_size()),
+                )
+            })
+    }
+
+    /// Returns an iterator over the relocations of a position-independent 64-bit image, found
+    /// through its dynamic section (`PT_DYNAMIC` segment). `R_X86_64_NONE` entries are skipped.
+    ///
+    /// Images without dynamic section have no relocations.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`BootError::InvalidImage`] if the dynamic section or the relocation table is out of
+    /// the image, and [`BootError::UnsupportedProtocol`] if the image uses relocation tables other
+    /// than `DT_RELA`.
+    pub fn relocations(&self) -> Result<impl Iterator<Item = ElfRelocation> + 'a, BootError> {
+        Ok(self
+            .relocation_table()?
+            .chunks_exact(mem::size_of::<Elf64RawRela>())
+            .map(ElfRelocation::from_bytes)
+            .filter(|relocation| relocation.relocation_type() != R_X86_64_NONE))
+    }
+
+    /// Returns the content of a segment stored in the image (`file_size` bytes).
+    ///
+    /// # Panics
+    ///
+    /// Panics if the segment does not belong to this image.
+    #[must_use]
+    pub fn segment_data(&self, segment: &ElfProgramHeader) -> &'a [u8] {
+        let start = usize::try_from(segment.offset()).expect("invalid segment offset");
+        let end = usize::try_from(segment.file_end().expect("invalid segment size"))
+            .expect("invalid segment size");
+
+        &self.image[start..end]
+    }
+
+    /// Locates the `DT_RELA` relocation table, using the dynamic section of the image.
+    ///
+    /// Returns an empty table if the image has no dynamic section, or no relocations.
+    fn relocation_table(&self) -> Result<&'a [u8], BootError> {
+        let image = self.image;
+        let Some(dynamic) = self
+            .program_headers()
+            .find(|segment| segment.segment_type() == PT_DYNAMIC)
+        else {
+            return Ok(&[]);
+        };
+
+        if self.header.class() != ElfClass::Elf64 {
+            return Err(BootError::UnsupportedProtocol);
+        }
+
+        let dynamic_data = dynamic
+            .file_end()
+            .and_then(|end| {
+                image.get(usize::try_from(dynamic.offset()).ok()?..usize::try_from(end).ok()?)
+            })
+            .ok_or(BootError::InvalidImage)?;
+
+        let rela_size = mem::size_of::<Elf64RawRela>();
+        let (mut table_addr, mut table_size, mut entry_size) = (None, 0, rela_size);
+
+        for entry in dynamic_data.chunks_exact(mem::size_of::<Elf64RawDynamic>()) {
+            let entry: Elf64RawDynamic = bytemuck::pod_read_unaligned(entry);
+
+            match entry.tag {
+                DT_NULL => break,
+                DT_RELA => table_addr = Some(entry.val),
+                DT_RELASZ => table_size = entry.val,
+                DT_RELAENT => entry_size = usize::try_from(entry.val).unwrap_or(0),
+                DT_REL | DT_RELR => return Err(BootError::UnsupportedProtocol),
+                _ => {}
+            }
+        }
+
+        let Some(table_addr) = table_addr else {
+            return Ok(&[]);
+        };
+
+        if entry_size != rela_size {
+            return Err(BootError::InvalidImage);
+        }
+
+        let start = self
+            .file_offset(table_addr)
+            .ok_or(BootError::InvalidImage)?;
+        let end = usize::try_from(table_size)
+            .ok()
+            .and_then(|size| start.checked_add(size))
+            .ok_or(BootError::InvalidImage)?;
+
+        image.get(start..end).ok_or(BootError::InvalidImage)
+    }
+
+    /// Converts a virtual address to an offset in the image, if it is stored in the file part of
+    /// a loadable segment.
+    fn file_offset(&self, virt_addr: u64) -> Option<usize> {
+        let segment = self.load_segments().find(|segment| {
+            virt_addr >= segment.virt_addr()
+                && virt_addr - segment.virt_addr() < segment.file_size()
+        })?;
+
+        usize::try_from(segment.offset() + (virt_addr - segment.virt_addr())).ok()
+    }
+}
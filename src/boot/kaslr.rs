@@ -0,0 +1,83 @@
+//! Kernel address space layout randomisation (_KASLR_) of native kernels.
+//!
+//! Native kernels built as position-independent executables (`ET_DYN`, linked with `-pie`) are
+//! loaded at a random virtual address, chosen among the [`KASLR_ALIGN`] aligned addresses of the
+//! window of [`KASLR_VIRT_WINDOW_SIZE`] bytes starting at [`KERNEL_CODE_MAPPING_BASE`]. Their
+//! relocations are applied accordingly (see [`crate::boot::elf::ElfImage::relocations`]). Their
+//! physical load address can be randomised as well, among the aligned addresses of the usable
+//! memory.
+//!
+//! Random numbers come from `RDSEED` or `RDRAND` when available, or from the _TSC_ jitter (see
+//! [`crate::x86::random`]). The chosen addresses are reported to the kernel in the boot
+//! information.
+//!
+//! Executables linked at fixed addresses (`ET_EXEC`) are always loaded at their link addresses.
+
+use crate::kernel_syms::KERNEL_CODE_MAPPING_BASE;
+
+/// Alignment of the randomised virtual and physical base addresses of the kernel (2MB, the size
+/// of a large page).
+pub const KASLR_ALIGN: u64 = 0x20_0000;
+
+/// Size of the virtual memory window in which the kernel is loaded (1GB).
+pub const KASLR_VIRT_WINDOW_SIZE: u64 = 0x4000_0000;
+
+/// Which base addresses of a position-independent kernel are randomised.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum KaslrMode {
+    /// The kernel is loaded at the start of the window, in the first free physical memory range.
+    Off,
+
+    /// Only the virtual base address is randomised.
+    Virtual,
+
+    /// Both the virtual and the physical base addresses are randomised.
+    #[default]
+    Full,
+}
+
+impl KaslrMode {
+    /// Parses a mode name: `off`, `virtual` or `full`.
+    #[must_use]
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "off" => Some(Self::Off),
+            "virtual" => Some(Self::Virtual),
+            "full" => Some(Self::Full),
+            _ => None,
+        }
+    }
+
+    /// Returns the name of the mode.
+    #[must_use]
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Off => "off",
+            Self::Virtual => "virtual",
+            Self::Full => "full",
+        }
+    }
+
+    /// Checks if the virtual base address is randomised.
+    #[must_use]
+    pub fn randomize_virt(self) -> bool {
+        self != Self::Off
+    }
+
+    /// Checks if the physical base address is randomised.
+    #[must_use]
+    pub fn randomize_phys(self) -> bool {
+        self == Self::Full
+    }
+}
+
+/// Picks the virtual base address of a kernel of `size` bytes, using the random number `random`.
+///
+/// Returns `None` if the kernel does not fit in the window.
+#[must_use]
+pub fn random_virt_base(size: u64, random: u64) -> Option<u64> {
+    let size = size.next_multiple_of(KASLR_ALIGN);
+    let slots = KASLR_VIRT_WINDOW_SIZE.checked_sub(size)? / KASLR_ALIGN + 1;
+
+    Some(u64::from(KERNEL_CODE_MAPPING_BASE) + (random % slots) * KASLR_ALIGN)
+}
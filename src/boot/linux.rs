@@ -0,0 +1,452 @@
+//! Linux x86 boot protocol related code.
+//!
+//! A Linux kernel image (`bzImage`) is made of a real-mode setup code, followed by the protected-mode
+//! kernel. The first sectors of the setup code contain the _setup header_ ([`SetupHeader`]), which
+//! describes how the kernel expects to be loaded.
+//!
+//! Before handing out control to the kernel, the bootloader fills the _zero page_ ([`BootParams`]),
+//! which contains a copy of the setup header along with information about the system (memory map,
+//! framebuffer, command line, initial ramdisk, ...).
+//!
+//! Based on the following specification: <https://www.kernel.org/doc/html/latest/arch/x86/boot.html>
+
+use core::mem;
+
+use bytemuck::{bytes_of_mut, Pod, Zeroable};
+
+use crate::{
+    errors::BootError,
+    mem::e820::E820MemoryMap,
+    video::vesa::video_mode::ModeInfoBlock,
+};
+
+/// Offset of the [`SetupHeader`] in a `bzImage` kernel image (and in the [`BootParams`]).
+pub const SETUP_HEADER_OFFSET: usize = 0x1F1;
+
+/// Minimum size of a kernel image to contain a full [`SetupHeader`].
+pub const SETUP_HEADER_END: usize = SETUP_HEADER_OFFSET + mem::size_of::<SetupHeader>();
+
+/// Value of the `boot_flag` field of a valid [`SetupHeader`].
+const LINUX_BOOT_FLAG: u16 = 0xAA55;
+
+/// Value of the `header` field of a valid [`SetupHeader`] (`HdrS`).
+const LINUX_HEADER_MAGIC: u32 = 0x5372_6448;
+
+/// Oldest supported boot protocol version.
+///
+/// Version `2.06` is the first one to provide the `cmdline_size` field, and to allow the
+/// kernel to be loaded above 1MB.
+pub const LINUX_MIN_PROTOCOL_VERSION: u16 = 0x0206;
+
+/// Boot protocol version starting from which the 64-bit entry point is described by `xloadflags`.
+const LINUX_64BIT_PROTOCOL_VERSION: u16 = 0x020C;
+
+/// Default number of setup sectors, if the `setup_sects` field is 0.
+const LINUX_DEFAULT_SETUP_SECTS: u8 = 4;
+
+/// Offset of the 64-bit entry point, from the start of the protected-mode kernel.
+pub const LINUX_64BIT_ENTRY_OFFSET: u64 = 0x200;
+
+/// Default load address of the protected-mode kernel, for non relocatable kernels.
+pub const LINUX_DEFAULT_LOAD_ADDR: u64 = 0x100_000;
+
+/// Maximum number of memory map entries that fit in the [`BootParams`].
+pub const LINUX_E820_MAX_ENTRIES: usize = 128;
+
+/// Bootloader identifier (`type_of_loader`) for bootloaders without an assigned ID.
+const LINUX_LOADER_TYPE_UNDEFINED: u8 = 0xFF;
+
+/// `orig_video_isVGA` value for a VESA linear framebuffer.
+const LINUX_VIDEO_TYPE_VLFB: u8 = 0x23;
+
+/// `loadflags`: the protected-mode kernel is loaded at `0x100000`.
+const LINUX_LOADED_HIGH: u8 = 1 << 0;
+
+/// `loadflags`: the value of `heap_end_ptr` is valid.
+const LINUX_CAN_USE_HEAP: u8 = 1 << 7;
+
+/// `xloadflags`: the kernel has the legacy 64-bit entry point at `0x200`.
+const LINUX_XLF_KERNEL_64: u16 = 1 << 0;
+
+/// `xloadflags`: the kernel, boot parameters, command line and ramdisk may be located above 4GB.
+const LINUX_XLF_CAN_BE_LOADED_ABOVE_4G: u16 = 1 << 1;
+
+/// Linux kernel setup header.
+///
+/// Located at offset [`SETUP_HEADER_OFFSET`] in the kernel image, it describes how the kernel
+/// should be loaded. A copy of it is also part of the [`BootParams`], in which the bootloader
+/// writes some of the fields to communicate with the kernel.
+#[derive(Clone, Copy, Debug, Pod, Zeroable)]
+#[repr(C, packed)]
+pub struct SetupHeader {
+    setup_sects: u8,
+    root_flags: u16,
+    syssize: u32,
+    ram_size: u16,
+    vid_mode: u16,
+    root_dev: u16,
+    boot_flag: u16,
+    jump: u16,
+    header: u32,
+    version: u16,
+    realmode_swtch: u32,
+    start_sys_seg: u16,
+    kernel_version: u16,
+    type_of_loader: u8,
+    loadflags: u8,
+    setup_move_size: u16,
+    code32_start: u32,
+    ramdisk_image: u32,
+    ramdisk_size: u32,
+    bootsect_kludge: u32,
+    heap_end_ptr: u16,
+    ext_loader_ver: u8,
+    ext_loader_type: u8,
+    cmd_line_ptr: u32,
+    initrd_addr_max: u32,
+    kernel_alignment: u32,
+    relocatable_kernel: u8,
+    min_alignment: u8,
+    xloadflags: u16,
+    cmdline_size: u32,
+    hardware_subarch: u32,
+    hardware_subarch_data: u64,
+    payload_offset: u32,
+    payload_length: u32,
+    setup_data: u64,
+    pref_address: u64,
+    init_size: u32,
+    handover_offset: u32,
+    kernel_info_offset: u32,
+}
+
+impl SetupHeader {
+    /// Reads and validates the [`SetupHeader`] of a `bzImage` kernel image.
+    ///
+    /// Only the first [`SETUP_HEADER_END`] bytes of the image are required.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`BootError::InvalidImage`] if the image does not contain a valid setup header, and
+    /// [`BootError::UnsupportedProtocol`] if the kernel uses a boot protocol older than
+    /// [`LINUX_MIN_PROTOCOL_VERSION`].
+    pub fn from_image(image: &[u8]) -> Result<Self, BootError> {
+        let header_bytes = image
+            .get(SETUP_HEADER_OFFSET..SETUP_HEADER_END)
+            .ok_or(BootError::InvalidImage)?;
+        let header: SetupHeader = bytemuck::pod_read_unaligned(header_bytes);
+
+        if header.boot_flag != LINUX_BOOT_FLAG || header.header != LINUX_HEADER_MAGIC {
+            return Err(BootError::InvalidImage);
+        }
+
+        if header.version < LINUX_MIN_PROTOCOL_VERSION {
+            return Err(BootError::UnsupportedProtocol);
+        }
+
+        Ok(header)
+    }
+
+    /// Returns the boot protocol version implemented by the kernel.
+    ///
+    /// The major version is stored in the high byte, and the minor version in the low byte.
+    #[must_use]
+    pub fn protocol_version(&self) -> u16 {
+        self.version
+    }
+
+    /// Returns the size of the real-mode setup code, in bytes (including the boot sector).
+    ///
+    /// The protected-mode kernel starts right after the setup code in the kernel image.
+    #[must_use]
+    pub fn setup_size(&self) -> usize {
+        let setup_sects = if self.setup_sects == 0 {
+            LINUX_DEFAULT_SETUP_SECTS
+        } else {
+            self.setup_sects
+        };
+
+        (usize::from(setup_sects) + 1) * 0x200
+    }
+
+    /// Returns the size of the protected-mode kernel, in bytes.
+    #[must_use]
+    pub fn kernel_size(&self) -> usize {
+        usize::try_from(self.syssize)
+            .unwrap_or(usize::MAX)
+            .saturating_mul(0x10)
+    }
+
+    /// Returns the total size of the kernel image, in bytes.
+    #[must_use]
+    pub fn image_size(&self) -> usize {
+        self.setup_size() + self.kernel_size()
+    }
+
+    /// Returns the amount of linear contiguous memory the kernel needs, starting at its load
+    /// address, before it is able to examine its memory map.
+    ///
+    /// That includes the space required to decompress the kernel in place.
+    #[must_use]
+    pub fn init_size(&self) -> u64 {
+        u64::from(self.init_size).max(u64::try_from(self.kernel_size()).unwrap_or(u64::MAX))
+    }
+
+    /// Checks if the protected-mode kernel can be loaded at any properly aligned address.
+    #[must_use]
+    pub fn relocatable(&self) -> bool {
+        self.relocatable_kernel != 0
+    }
+
+    /// Returns the alignment required by the protected-mode kernel, if it is relocatable.
+    #[must_use]
+    pub fn kernel_alignment(&self) -> u64 {
+        u64::from(self.kernel_alignment).max(0x1000)
+    }
+
+    /// Returns the preferred load address of the protected-mode kernel.
+    ///
+    /// A non relocatable kernel must be loaded at that address.
+    #[must_use]
+    pub fn pref_address(&self) -> u64 {
+        if self.version >= 0x020A && self.pref_address != 0 {
+            self.pref_address
+        } else {
+            LINUX_DEFAULT_LOAD_ADDR
+        }
+    }
+
+    /// Returns the highest address that may be occupied by the initial ramdisk.
+    #[must_use]
+    pub fn initrd_addr_max(&self) -> u64 {
+        if self.xloadflags & LINUX_XLF_CAN_BE_LOADED_ABOVE_4G != 0 {
+            u64::MAX
+        } else {
+            u64::from(self.initrd_addr_max)
+        }
+    }
+
+    /// Returns the maximum size of the command line, without the terminating zero.
+    #[must_use]
+    pub fn cmdline_size(&self) -> usize {
+        usize::try_from(self.cmdline_size).unwrap_or(usize::MAX)
+    }
+
+    /// Checks if the kernel provides a 64-bit entry point, located [`LINUX_64BIT_ENTRY_OFFSET`]
+    /// bytes after the start of the protected-mode kernel.
+    #[must_use]
+    pub fn has_64bit_entry(&self) -> bool {
+        self.version >= LINUX_64BIT_PROTOCOL_VERSION && self.xloadflags & LINUX_XLF_KERNEL_64 != 0
+    }
+}
+
+/// A `bzImage` Linux kernel image.
+///
+/// Wraps the raw bytes of the kernel image, along with its validated [`SetupHeader`].
+#[derive(Clone, Copy, Debug)]
+pub struct LinuxKernelImage<'a> {
+    image: &'a [u8],
+    header: SetupHeader,
+}
+
+impl<'a> LinuxKernelImage<'a> {
+    /// Parses a `bzImage` Linux kernel image.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`BootError::InvalidImage`] if the image does not contain a valid setup header, or
+    /// if it is smaller than what the header describes.
+    /// Returns [`BootError::UnsupportedProtocol`] if the boot protocol used by the kernel is too old.
+    pub fn parse(image: &'a [u8]) -> Result<Self, BootError> {
+        let header = SetupHeader::from_image(image)?;
+
+        if image.len() < header.setup_size() {
+            return Err(BootError::InvalidImage);
+        }
+
+        Ok(Self { image, header })
+    }
+
+    /// Returns the [`SetupHeader`] of this kernel image.
+    #[must_use]
+    pub fn header(&self) -> &SetupHeader {
+        &self.header
+    }
+
+    /// Returns the protected-mode part of the kernel, which must be loaded at the kernel load
+    /// address.
+    #[must_use]
+    pub fn protected_mode_kernel(&self) -> &'a [u8] {
+        &self.image[self.header.setup_size()..]
+    }
+
+    /// Returns the raw bytes of the setup header, as they should be copied to the [`BootParams`].
+    ///
+    /// Its length is given by the offset of the jump instruction at `0x200`.
+    fn raw_setup_header(&self) -> &'a [u8] {
+        let header_end = (0x202 + usize::from(self.image[0x201])).min(SETUP_HEADER_END);
+
+        &self.image[SETUP_HEADER_OFFSET..header_end]
+    }
+}
+
+/// Linux _zero page_, or boot parameters structure.
+///
+/// Filled by the bootloader, it contains a copy of the kernel [`SetupHeader`], as well as
+/// information about the system, and is passed to the kernel at its entry point.
+#[derive(Clone, Copy, Debug, Pod, Zeroable)]
+#[repr(C, packed)]
+pub struct BootParams {
+    screen_info: ScreenInfo,
+    reserved_1: [u8; 0x30],
+    acpi_rsdp_addr: u64,
+    reserved_2: [u8; 0x48],
+    ext_ramdisk_image: u32,
+    ext_ramdisk_size: u32,
+    ext_cmd_line_ptr: u32,
+    reserved_3: [u8; 0x11C],
+    e820_entries: u8,
+    reserved_4: [u8; 0x8],
+    hdr: SetupHeader,
+    reserved_5: [u8; 0x64],
+    e820_table: [LinuxE820Entry; LINUX_E820_MAX_ENTRIES],
+    reserved_6: [u8; 0x330],
+}
+
+const _: () = assert!(mem::size_of::<BootParams>() == 0x1000);
+
+impl BootParams {
+    /// Creates a new zeroed `BootParams` structure, containing the setup header of the given
+    /// kernel image.
+    #[must_use]
+    pub fn new(image: &LinuxKernelImage) -> Self {
+        let mut params = Self::zeroed();
+        let raw_header = image.raw_setup_header();
+
+        bytes_of_mut(&mut params.hdr)[..raw_header.len()].copy_from_slice(raw_header);
+
+        params.hdr.type_of_loader = LINUX_LOADER_TYPE_UNDEFINED;
+        params.hdr.loadflags &= !LINUX_CAN_USE_HEAP;
+        params.hdr.loadflags |= LINUX_LOADED_HIGH;
+
+        params
+    }
+
+    /// Sets the physical address at which the protected-mode kernel was loaded.
+    pub fn set_kernel_addr(&mut self, addr: u32) {
+        self.hdr.code32_start = addr;
+    }
+
+    /// Sets the physical address of the kernel command line.
+    ///
+    /// The command line must be a C-style zero terminated string.
+    pub fn set_cmdline(&mut self, addr: u64) {
+        [self.hdr.cmd_line_ptr, self.ext_cmd_line_ptr] = bytemuck::cast(addr);
+    }
+
+    /// Sets the physical address and size of the initial ramdisk.
+    pub fn set_ramdisk(&mut self, addr: u64, size: u64) {
+        [self.hdr.ramdisk_image, self.ext_ramdisk_image] = bytemuck::cast(addr);
+        [self.hdr.ramdisk_size, self.ext_ramdisk_size] = bytemuck::cast(size);
+    }
+
+    /// Sets the physical address of the ACPI `RSDP` structure.
+    pub fn set_acpi_rsdp_addr(&mut self, addr: u64) {
+        self.acpi_rsdp_addr = addr;
+    }
+
+    /// Fills the memory map of the boot parameters using the _BIOS_-provided memory map.
+    ///
+    /// Only the first [`LINUX_E820_MAX_ENTRIES`] entries are used.
+    pub fn set_memory_map(&mut self, memory_map: E820MemoryMap) {
+        let mut entries_count = 0;
+
+        for (entry, ard) in self.e820_table.iter_mut().zip(memory_map) {
+            *entry = LinuxE820Entry {
+                addr: ard.phys_base(),
+                size: ard.length(),
+                entry_type: u32::from(ard.addr_type),
+            };
+            entries_count += 1;
+        }
+
+        self.e820_entries = entries_count;
+    }
+
+    /// Fills the video information of the boot parameters using the current VESA mode.
+    pub fn set_framebuffer(&mut self, mode_info: &ModeInfoBlock) {
+        let linelength = mode_info.bytes_per_scanline;
+        let fb_size = u32::from(linelength) * u32::from(mode_info.height);
+
+        self.screen_info = ScreenInfo {
+            orig_video_is_vga: LINUX_VIDEO_TYPE_VLFB,
+            lfb_width: mode_info.width,
+            lfb_height: mode_info.height,
+            lfb_depth: u16::from(mode_info.bits_per_pixel),
+            lfb_base: mode_info.framebuffer,
+            lfb_size: fb_size.div_ceil(0x10000),
+            lfb_linelength: linelength,
+            red_size: mode_info.red_mask_s,
+            red_pos: mode_info.red_field_pos,
+            green_size: mode_info.green_mask_s,
+            green_pos: mode_info.green_field_pos,
+            blue_size: mode_info.blue_mask_s,
+            blue_pos: mode_info.blue_field_pos,
+            rsvd_size: mode_info.rsvd_mask_size,
+            rsvd_pos: mode_info.rsvd_field_pos,
+            vesa_attributes: mode_info.mode_attributes,
+            ..ScreenInfo::zeroed()
+        };
+    }
+}
+
+/// Memory map entry, as stored in the [`BootParams`].
+#[derive(Clone, Copy, Debug, Pod, Zeroable)]
+#[repr(C, packed)]
+pub struct LinuxE820Entry {
+    addr: u64,
+    size: u64,
+    entry_type: u32,
+}
+
+/// Video mode information, as stored in the [`BootParams`].
+#[derive(Clone, Copy, Debug, Pod, Zeroable)]
+#[repr(C, packed)]
+pub struct ScreenInfo {
+    orig_x: u8,
+    orig_y: u8,
+    ext_mem_k: u16,
+    orig_video_page: u16,
+    orig_video_mode: u8,
+    orig_video_cols: u8,
+    flags: u8,
+    unused_1: u8,
+    orig_video_ega_bx: u16,
+    unused_2: u16,
+    orig_video_lines: u8,
+    orig_video_is_vga: u8,
+    orig_video_points: u16,
+    lfb_width: u16,
+    lfb_height: u16,
+    lfb_depth: u16,
+    lfb_base: u32,
+    lfb_size: u32,
+    cl_magic: u16,
+    cl_offset: u16,
+    lfb_linelength: u16,
+    red_size: u8,
+    red_pos: u8,
+    green_size: u8,
+    green_pos: u8,
+    blue_size: u8,
+    blue_pos: u8,
+    rsvd_size: u8,
+    rsvd_pos: u8,
+    vesapm_seg: u16,
+    vesapm_off: u16,
+    pages: u16,
+    vesa_attributes: u16,
+    capabilities: u32,
+    ext_lfb_base: u32,
+    reserved: [u8; 2],
+}
@@ -1 +1,8 @@
+pub mod bls;
+pub mod config;
+pub mod elf;
+pub mod kaslr;
+pub mod linux;
 pub mod multiboot;
+pub mod slots;
+pub mod verify;
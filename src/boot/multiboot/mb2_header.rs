@@ -0,0 +1,301 @@
+//! Multiboot2 header related code.
+//!
+//! A Multiboot2 compliant kernel image embeds a header in its first 32KB, aligned on 8 bytes. The
+//! header is made of a fixed part, followed by a list of tags through which the kernel requests
+//! specific features from the bootloader (load and entry addresses, framebuffer, ...).
+//!
+//! Based on the following specification: <https://www.gnu.org/software/grub/manual/multiboot2/multiboot.html>
+
+use core::mem;
+
+use alloc::vec::Vec;
+use bytemuck::{Pod, Zeroable};
+
+use crate::errors::BootError;
+
+use super::{mb2_information::is_supported_tag, MultibootLoadAddress};
+
+/// Value of the `magic` field of a valid [`Multiboot2Header`].
+pub const MULTIBOOT2_HEADER_MAGIC: u32 = 0xE852_50D6;
+
+/// Value stored in `EAX` when handing out control to a Multiboot2 kernel.
+pub const MULTIBOOT2_BOOTLOADER_MAGIC: u32 = 0x36D7_6289;
+
+/// The Multiboot2 header must be entirely located in the first 32KB of the image.
+pub const MULTIBOOT2_SEARCH_LIMIT: usize = 0x8000;
+
+/// Alignment of the Multiboot2 header, and of every header tag.
+const MULTIBOOT2_HEADER_ALIGN: usize = 8;
+
+/// `architecture`: 32-bit protected mode of the `i386` architecture.
+const MULTIBOOT2_ARCH_I386: u32 = 0;
+
+/// Header tag flags: the bootloader may ignore this tag if it does not support it.
+const MULTIBOOT2_TAG_OPTIONAL: u16 = 1 << 0;
+
+/// Header tag: end of the tags list.
+const MULTIBOOT2_HEADER_TAG_END: u16 = 0;
+
+/// Header tag: information requested by the kernel.
+const MULTIBOOT2_HEADER_TAG_INFORMATION_REQUEST: u16 = 1;
+
+/// Header tag: load addresses of a kernel image that is not an ELF executable.
+const MULTIBOOT2_HEADER_TAG_ADDRESS: u16 = 2;
+
+/// Header tag: entry point address.
+const MULTIBOOT2_HEADER_TAG_ENTRY_ADDRESS: u16 = 3;
+
+/// Header tag: console requirements.
+const MULTIBOOT2_HEADER_TAG_CONSOLE_FLAGS: u16 = 4;
+
+/// Header tag: preferred framebuffer mode.
+const MULTIBOOT2_HEADER_TAG_FRAMEBUFFER: u16 = 5;
+
+/// Header tag: boot modules must be page aligned.
+const MULTIBOOT2_HEADER_TAG_MODULE_ALIGN: u16 = 6;
+
+/// Header tag: the kernel may be loaded at another address than its link address.
+const MULTIBOOT2_HEADER_TAG_RELOCATABLE: u16 = 10;
+
+/// Fixed part of the Multiboot2 header.
+#[derive(Clone, Copy, Debug, Pod, Zeroable)]
+#[repr(C)]
+struct Multiboot2RawHeader {
+    magic: u32,
+    architecture: u32,
+    header_length: u32,
+    checksum: u32,
+}
+
+/// Header shared by every Multiboot2 header tag.
+#[derive(Clone, Copy, Debug, Pod, Zeroable)]
+#[repr(C)]
+struct Multiboot2HeaderTag {
+    tag_type: u16,
+    flags: u16,
+    size: u32,
+}
+
+/// Content of the address header tag.
+#[derive(Clone, Copy, Debug, Pod, Zeroable)]
+#[repr(C)]
+struct Multiboot2AddressTag {
+    header: u32,
+    load: u32,
+    load_end: u32,
+    bss_end: u32,
+}
+
+/// Framebuffer mode requested by a Multiboot2 kernel.
+///
+/// A value of 0 means that the kernel has no preference.
+#[derive(Clone, Copy, Debug, Pod, Zeroable)]
+#[repr(C)]
+pub struct Multiboot2FramebufferRequest {
+    width: u32,
+    height: u32,
+    depth: u32,
+}
+
+impl Multiboot2FramebufferRequest {
+    /// Returns the preferred number of columns (pixels).
+    #[must_use]
+    pub fn width(&self) -> u32 {
+        self.width
+    }
+
+    /// Returns the preferred number of lines (pixels).
+    #[must_use]
+    pub fn height(&self) -> u32 {
+        self.height
+    }
+
+    /// Returns the preferred number of bits per pixel.
+    #[must_use]
+    pub fn depth(&self) -> u32 {
+        self.depth
+    }
+
+    /// Checks if a framebuffer mode satisfies the kernel preferences.
+    #[must_use]
+    pub fn matches(&self, width: u32, height: u32, depth: u32) -> bool {
+        (self.width == 0 || self.width == width)
+            && (self.height == 0 || self.height == height)
+            && (self.depth == 0 || self.depth == depth)
+    }
+}
+
+/// A validated Multiboot2 header.
+#[derive(Clone, Debug)]
+pub struct Multiboot2Header {
+    offset: usize,
+    load_address: Option<MultibootLoadAddress>,
+    entry_addr: Option<u32>,
+    framebuffer: Option<Multiboot2FramebufferRequest>,
+    module_align: bool,
+    requested_tags: Vec<u32>,
+}
+
+impl Multiboot2Header {
+    /// Searches for a Multiboot2 header in the first [`MULTIBOOT2_SEARCH_LIMIT`] bytes of a kernel
+    /// image, and parses its tags.
+    ///
+    /// Returns `Ok(None)` if the image does not contain any Multiboot2 header.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`BootError::InvalidImage`] if the header tags are malformed, and
+    /// [`BootError::UnsupportedProtocol`] if the kernel targets another architecture than `i386`,
+    /// or requires a feature that is not supported.
+    pub fn locate(image: &[u8]) -> Result<Option<Self>, BootError> {
+        let search_area = &image[..image.len().min(MULTIBOOT2_SEARCH_LIMIT)];
+        let header_size = mem::size_of::<Multiboot2RawHeader>();
+
+        for offset in (0..search_area.len()).step_by(MULTIBOOT2_HEADER_ALIGN) {
+            let Some(header_bytes) = search_area.get(offset..offset + header_size) else {
+                break;
+            };
+            let header: Multiboot2RawHeader = bytemuck::pod_read_unaligned(header_bytes);
+
+            let checksum = header
+                .magic
+                .wrapping_add(header.architecture)
+                .wrapping_add(header.header_length)
+                .wrapping_add(header.checksum);
+
+            if header.magic != MULTIBOOT2_HEADER_MAGIC || checksum != 0 {
+                continue;
+            }
+
+            if header.architecture != MULTIBOOT2_ARCH_I386 {
+                return Err(BootError::UnsupportedProtocol);
+            }
+
+            let header_end = usize::try_from(header.header_length)
+                .ok()
+                .and_then(|length| offset.checked_add(length))
+                .filter(|end| *end <= search_area.len())
+                .ok_or(BootError::InvalidImage)?;
+
+            return Self::parse_tags(offset, &search_area[offset + header_size..header_end])
+                .map(Some);
+        }
+
+        Ok(None)
+    }
+
+    /// Parses the header tags, located right after the fixed part of the header.
+    fn parse_tags(offset: usize, mut tags: &[u8]) -> Result<Self, BootError> {
+        let mut header = Self {
+            offset,
+            load_address: None,
+            entry_addr: None,
+            framebuffer: None,
+            module_align: false,
+            requested_tags: Vec::new(),
+        };
+
+        loop {
+            let tag: Multiboot2HeaderTag = bytemuck::pod_read_unaligned(
+                tags.get(..mem::size_of::<Multiboot2HeaderTag>())
+                    .ok_or(BootError::InvalidImage)?,
+            );
+            let tag_size = usize::try_from(tag.size).map_err(|_| BootError::InvalidImage)?;
+            let content = tags
+                .get(mem::size_of::<Multiboot2HeaderTag>()..tag_size)
+                .ok_or(BootError::InvalidImage)?;
+            let optional = tag.flags & MULTIBOOT2_TAG_OPTIONAL != 0;
+
+            match tag.tag_type {
+                MULTIBOOT2_HEADER_TAG_END => break,
+                MULTIBOOT2_HEADER_TAG_INFORMATION_REQUEST => {
+                    for request in content.chunks_exact(mem::size_of::<u32>()) {
+                        let tag_type: u32 = bytemuck::pod_read_unaligned(request);
+
+                        if !optional && !is_supported_tag(tag_type) {
+                            return Err(BootError::UnsupportedProtocol);
+                        }
+                        header.requested_tags.push(tag_type);
+                    }
+                }
+                MULTIBOOT2_HEADER_TAG_ADDRESS => {
+                    let address: Multiboot2AddressTag = read_tag_content(content)?;
+
+                    header.load_address = Some(MultibootLoadAddress::new(
+                        offset,
+                        address.header,
+                        address.load,
+                        address.load_end,
+                        address.bss_end,
+                    )?);
+                }
+                MULTIBOOT2_HEADER_TAG_ENTRY_ADDRESS => {
+                    header.entry_addr = Some(read_tag_content(content)?);
+                }
+                MULTIBOOT2_HEADER_TAG_FRAMEBUFFER => {
+                    header.framebuffer = Some(read_tag_content(content)?);
+                }
+                MULTIBOOT2_HEADER_TAG_MODULE_ALIGN => header.module_align = true,
+
+                // The only available console is the framebuffer, and the kernel is always loaded
+                // at its link address.
+                MULTIBOOT2_HEADER_TAG_CONSOLE_FLAGS | MULTIBOOT2_HEADER_TAG_RELOCATABLE => {}
+                _ => {
+                    if !optional {
+                        return Err(BootError::UnsupportedProtocol);
+                    }
+                }
+            }
+
+            let next_tag = tag_size.next_multiple_of(MULTIBOOT2_HEADER_ALIGN);
+            tags = tags.get(next_tag..).ok_or(BootError::InvalidImage)?;
+        }
+
+        Ok(header)
+    }
+
+    /// Returns the offset of the header in the image.
+    #[must_use]
+    pub fn offset(&self) -> usize {
+        self.offset
+    }
+
+    /// Returns the load addresses requested by the kernel, if the image must not be loaded as an
+    /// ELF executable.
+    #[must_use]
+    pub fn load_address(&self) -> Option<&MultibootLoadAddress> {
+        self.load_address.as_ref()
+    }
+
+    /// Returns the entry point address requested by the kernel, which overrides the ELF entry point.
+    #[must_use]
+    pub fn entry_addr(&self) -> Option<u32> {
+        self.entry_addr
+    }
+
+    /// Returns the framebuffer mode preferred by the kernel, if any.
+    #[must_use]
+    pub fn framebuffer(&self) -> Option<&Multiboot2FramebufferRequest> {
+        self.framebuffer.as_ref()
+    }
+
+    /// Checks if the boot modules must be aligned on page (4KB) boundaries.
+    #[must_use]
+    pub fn module_align(&self) -> bool {
+        self.module_align
+    }
+
+    /// Returns the information tag types explicitly requested by the kernel.
+    #[must_use]
+    pub fn requested_tags(&self) -> &[u32] {
+        &self.requested_tags
+    }
+}
+
+/// Reads the content of a header tag, which must be large enough to contain a `T`.
+fn read_tag_content<T: Pod>(content: &[u8]) -> Result<T, BootError> {
+    content
+        .get(..mem::size_of::<T>())
+        .map(bytemuck::pod_read_unaligned)
+        .ok_or(BootError::InvalidImage)
+}
@@ -0,0 +1,268 @@
+//! Multiboot2 boot information related code.
+//!
+//! Unlike the legacy Multiboot information structure (see [`super::mb_information`]), the
+//! Multiboot2 boot information is a list of tags, each one carrying a specific piece of information
+//! about the system. The list is preceded by its total size, and terminated by an end tag.
+//!
+//! Based on the following specification: <https://www.gnu.org/software/grub/manual/multiboot2/multiboot.html>
+
+use core::mem;
+
+use alloc::{boxed::Box, vec, vec::Vec};
+use bytemuck::{bytes_of, Pod, Zeroable};
+
+use crate::{
+    io::acpi::RSDPDescriptor, mem::e820::E820MemoryMap, video::vesa::video_mode::ModeInfoBlock,
+};
+
+use super::memory_type;
+
+/// Alignment of the boot information structure, and of every tag.
+const MULTIBOOT2_TAG_ALIGN: usize = 8;
+
+/// Tag: end of the tags list.
+const MULTIBOOT2_TAG_END: u32 = 0;
+
+/// Tag: kernel command line.
+const MULTIBOOT2_TAG_CMDLINE: u32 = 1;
+
+/// Tag: name of the bootloader.
+const MULTIBOOT2_TAG_BOOTLOADER_NAME: u32 = 2;
+
+/// Tag: boot module loaded along with the kernel.
+const MULTIBOOT2_TAG_MODULE: u32 = 3;
+
+/// Tag: amount of lower and upper memory.
+const MULTIBOOT2_TAG_BASIC_MEMINFO: u32 = 4;
+
+/// Tag: _BIOS_-provided memory map.
+const MULTIBOOT2_TAG_MMAP: u32 = 6;
+
+/// Tag: framebuffer information.
+const MULTIBOOT2_TAG_FRAMEBUFFER: u32 = 8;
+
+/// Tag: copy of the SMBIOS entry point structure.
+const MULTIBOOT2_TAG_SMBIOS: u32 = 13;
+
+/// Tag: copy of the ACPI 1.0 `RSDP` structure.
+const MULTIBOOT2_TAG_ACPI_OLD: u32 = 14;
+
+/// Tag: copy of the ACPI 2.0 (or later) `RSDP` structure.
+const MULTIBOOT2_TAG_ACPI_NEW: u32 = 15;
+
+/// Information tags that can be provided to the kernel.
+const MULTIBOOT2_SUPPORTED_TAGS: [u32; 10] = [
+    MULTIBOOT2_TAG_END,
+    MULTIBOOT2_TAG_CMDLINE,
+    MULTIBOOT2_TAG_BOOTLOADER_NAME,
+    MULTIBOOT2_TAG_MODULE,
+    MULTIBOOT2_TAG_BASIC_MEMINFO,
+    MULTIBOOT2_TAG_MMAP,
+    MULTIBOOT2_TAG_FRAMEBUFFER,
+    MULTIBOOT2_TAG_SMBIOS,
+    MULTIBOOT2_TAG_ACPI_OLD,
+    MULTIBOOT2_TAG_ACPI_NEW,
+];
+
+/// Framebuffer tag: direct RGB color framebuffer.
+const MULTIBOOT2_FRAMEBUFFER_TYPE_RGB: u8 = 1;
+
+/// Checks if an information tag type can be provided to the kernel.
+#[must_use]
+pub fn is_supported_tag(tag_type: u32) -> bool {
+    MULTIBOOT2_SUPPORTED_TAGS.contains(&tag_type)
+}
+
+/// Header shared by every Multiboot2 information tag.
+#[derive(Clone, Copy, Debug, Pod, Zeroable)]
+#[repr(C)]
+struct Multiboot2TagHeader {
+    tag_type: u32,
+    size: u32,
+}
+
+/// Memory map entry, as stored in the memory map tag.
+#[derive(Clone, Copy, Debug, Pod, Zeroable)]
+#[repr(C)]
+struct Multiboot2MemoryMapEntry {
+    base_addr: u64,
+    length: u64,
+    entry_type: u32,
+    reserved: u32,
+}
+
+/// Content of the framebuffer information tag, for direct RGB color framebuffers.
+#[derive(Clone, Copy, Debug, Pod, Zeroable)]
+#[repr(C, packed)]
+struct Multiboot2FramebufferTag {
+    addr: u64,
+    pitch: u32,
+    width: u32,
+    height: u32,
+    bpp: u8,
+    framebuffer_type: u8,
+    reserved: u16,
+    red_field_pos: u8,
+    red_mask_size: u8,
+    green_field_pos: u8,
+    green_mask_size: u8,
+    blue_field_pos: u8,
+    blue_mask_size: u8,
+}
+
+/// Builds the Multiboot2 boot information structure, one tag after the other.
+#[derive(Clone, Debug)]
+pub struct Multiboot2InformationBuilder {
+    buffer: Vec<u8>,
+}
+
+impl Default for Multiboot2InformationBuilder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Multiboot2InformationBuilder {
+    /// Creates an empty boot information structure.
+    #[must_use]
+    pub fn new() -> Self {
+        // `total_size` and `reserved` fields, written when building the structure
+        Self { buffer: vec![0; 8] }
+    }
+
+    /// Appends a tag made of the given content parts.
+    fn add_tag(&mut self, tag_type: u32, content: &[&[u8]]) {
+        let size = mem::size_of::<Multiboot2TagHeader>()
+            + content.iter().map(|part| part.len()).sum::<usize>();
+
+        self.buffer
+            .extend_from_slice(bytes_of(&Multiboot2TagHeader {
+                tag_type,
+                size: u32::try_from(size).expect("invalid multiboot2 tag size"),
+            }));
+        for part in content {
+            self.buffer.extend_from_slice(part);
+        }
+
+        self.buffer
+            .resize(self.buffer.len().next_multiple_of(MULTIBOOT2_TAG_ALIGN), 0);
+    }
+
+    /// Adds the kernel command line.
+    pub fn add_cmdline(&mut self, cmdline: &str) {
+        self.add_tag(MULTIBOOT2_TAG_CMDLINE, &[cmdline.as_bytes(), &[0]]);
+    }
+
+    /// Adds the name of the bootloader.
+    pub fn add_bootloader_name(&mut self, name: &str) {
+        self.add_tag(MULTIBOOT2_TAG_BOOTLOADER_NAME, &[name.as_bytes(), &[0]]);
+    }
+
+    /// Adds a boot module, located in the physical memory range `[start, end)`.
+    pub fn add_module(&mut self, start: u32, end: u32, cmdline: &str) {
+        self.add_tag(
+            MULTIBOOT2_TAG_MODULE,
+            &[bytes_of(&start), bytes_of(&end), cmdline.as_bytes(), &[0]],
+        );
+    }
+
+    /// Adds the amount of lower and upper memory, in kilobytes.
+    pub fn add_basic_meminfo(&mut self, mem_lower: u32, mem_upper: u32) {
+        self.add_tag(
+            MULTIBOOT2_TAG_BASIC_MEMINFO,
+            &[bytes_of(&mem_lower), bytes_of(&mem_upper)],
+        );
+    }
+
+    /// Adds the _BIOS_-provided memory map.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the memory map is too large to fit in a tag.
+    pub fn add_memory_map(&mut self, memory_map: E820MemoryMap) {
+        let entries: Vec<Multiboot2MemoryMapEntry> = memory_map
+            .map(|entry| Multiboot2MemoryMapEntry {
+                base_addr: entry.phys_base(),
+                length: entry.length(),
+                entry_type: memory_type(entry.addr_type),
+                reserved: 0,
+            })
+            .collect();
+
+        let entry_size =
+            u32::try_from(mem::size_of::<Multiboot2MemoryMapEntry>()).expect("invalid entry size");
+        let entry_version: u32 = 0;
+
+        self.add_tag(
+            MULTIBOOT2_TAG_MMAP,
+            &[
+                bytes_of(&entry_size),
+                bytes_of(&entry_version),
+                bytemuck::cast_slice(&entries),
+            ],
+        );
+    }
+
+    /// Adds the framebuffer information, using the current VESA mode.
+    pub fn add_framebuffer(&mut self, mode_info: &ModeInfoBlock) {
+        let framebuffer = Multiboot2FramebufferTag {
+            addr: u64::from(mode_info.framebuffer),
+            pitch: u32::from(mode_info.bytes_per_scanline),
+            width: u32::from(mode_info.width),
+            height: u32::from(mode_info.height),
+            bpp: mode_info.bits_per_pixel,
+            framebuffer_type: MULTIBOOT2_FRAMEBUFFER_TYPE_RGB,
+            reserved: 0,
+            red_field_pos: mode_info.red_field_pos,
+            red_mask_size: mode_info.red_mask_s,
+            green_field_pos: mode_info.green_field_pos,
+            green_mask_size: mode_info.green_mask_s,
+            blue_field_pos: mode_info.blue_field_pos,
+            blue_mask_size: mode_info.blue_mask_s,
+        };
+
+        self.add_tag(MULTIBOOT2_TAG_FRAMEBUFFER, &[bytes_of(&framebuffer)]);
+    }
+
+    /// Adds a copy of the SMBIOS entry point structure.
+    pub fn add_smbios(&mut self, major_version: u8, minor_version: u8, entry_point: &[u8]) {
+        self.add_tag(
+            MULTIBOOT2_TAG_SMBIOS,
+            &[&[major_version, minor_version], &[0; 6], entry_point],
+        );
+    }
+
+    /// Adds a copy of the ACPI `RSDP` structure.
+    ///
+    /// The tag type depends on the ACPI revision.
+    pub fn add_acpi_rsdp(&mut self, rsdp: &RSDPDescriptor) {
+        let tag_type = match rsdp {
+            RSDPDescriptor::V1(_) => MULTIBOOT2_TAG_ACPI_OLD,
+            RSDPDescriptor::V2(_) => MULTIBOOT2_TAG_ACPI_NEW,
+        };
+
+        self.add_tag(tag_type, &[rsdp.as_bytes()]);
+    }
+
+    /// Terminates the tags list, and returns the boot information structure.
+    ///
+    /// The structure is stored in a buffer of 64-bit words, which ensures the required 8 bytes
+    /// alignment.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the structure is larger than 4GB.
+    #[must_use]
+    pub fn build(mut self) -> Box<[u64]> {
+        self.add_tag(MULTIBOOT2_TAG_END, &[]);
+
+        let total_size =
+            u32::try_from(self.buffer.len()).expect("invalid multiboot2 information size");
+        self.buffer[..4].copy_from_slice(bytes_of(&total_size));
+
+        let mut information = vec![0u64; self.buffer.len() / mem::size_of::<u64>()];
+        bytemuck::cast_slice_mut(&mut information).copy_from_slice(&self.buffer);
+
+        information.into_boxed_slice()
+    }
+}
@@ -0,0 +1,247 @@
+//! Multiboot header related code.
+//!
+//! A Multiboot compliant kernel image embeds a header in its first 8KB, aligned on 4 bytes. The
+//! header flags indicate which features the kernel requires from the bootloader, and the optional
+//! fields provide the load addresses of kernel images that are not ELF executables (the _a.out
+//! kludge_), as well as the preferred video mode.
+//!
+//! Based on the following specification: <https://www.gnu.org/software/grub/manual/multiboot/multiboot.html>
+
+use core::mem;
+
+use bytemuck::{Pod, Zeroable};
+
+use crate::errors::BootError;
+
+use super::MultibootLoadAddress;
+
+/// Value of the `magic` field of a valid [`MultibootHeader`].
+pub const MULTIBOOT_HEADER_MAGIC: u32 = 0x1BAD_B002;
+
+/// Value stored in `EAX` when handing out control to a Multiboot kernel.
+pub const MULTIBOOT_BOOTLOADER_MAGIC: u32 = 0x2BAD_B002;
+
+/// The Multiboot header must be entirely located in the first 8KB of the image.
+pub const MULTIBOOT_SEARCH_LIMIT: usize = 0x2000;
+
+/// Alignment of the Multiboot header.
+const MULTIBOOT_HEADER_ALIGN: usize = 4;
+
+/// Header flags: boot modules must be aligned on page (4KB) boundaries.
+const MULTIBOOT_PAGE_ALIGN: u32 = 1 << 0;
+
+/// Header flags: the memory information fields must be provided.
+const MULTIBOOT_MEMORY_INFO: u32 = 1 << 1;
+
+/// Header flags: the video mode table must be provided.
+const MULTIBOOT_VIDEO_MODE: u32 = 1 << 2;
+
+/// Header flags: the address fields of the header are valid (_a.out kludge_).
+const MULTIBOOT_AOUT_KLUDGE: u32 = 1 << 16;
+
+/// Header flags: the lower 16 bits are requirements, which must all be supported by the bootloader.
+const MULTIBOOT_REQUIRED_FLAGS_MASK: u32 = 0xFFFF;
+
+/// Header flags: requirements supported by the bootloader.
+const MULTIBOOT_SUPPORTED_FLAGS: u32 =
+    MULTIBOOT_PAGE_ALIGN | MULTIBOOT_MEMORY_INFO | MULTIBOOT_VIDEO_MODE;
+
+/// Video mode type: linear graphics mode.
+const MULTIBOOT_VIDEO_LINEAR: u32 = 0;
+
+/// Fixed part of the Multiboot header, always present.
+#[derive(Clone, Copy, Debug, Pod, Zeroable)]
+#[repr(C)]
+struct MultibootRawHeader {
+    magic: u32,
+    flags: u32,
+    checksum: u32,
+}
+
+/// Address fields of the Multiboot header, valid if [`MULTIBOOT_AOUT_KLUDGE`] is set.
+#[derive(Clone, Copy, Debug, Pod, Zeroable)]
+#[repr(C)]
+struct MultibootAddressFields {
+    header: u32,
+    load: u32,
+    load_end: u32,
+    bss_end: u32,
+    entry: u32,
+}
+
+/// Video mode preferred by a Multiboot kernel.
+///
+/// A value of 0 means that the kernel has no preference.
+#[derive(Clone, Copy, Debug, Pod, Zeroable)]
+#[repr(C)]
+pub struct MultibootVideoModeRequest {
+    mode_type: u32,
+    width: u32,
+    height: u32,
+    depth: u32,
+}
+
+impl MultibootVideoModeRequest {
+    /// Returns the preferred number of columns (pixels, or characters in text mode).
+    #[must_use]
+    pub fn width(&self) -> u32 {
+        self.width
+    }
+
+    /// Returns the preferred number of lines (pixels, or characters in text mode).
+    #[must_use]
+    pub fn height(&self) -> u32 {
+        self.height
+    }
+
+    /// Returns the preferred number of bits per pixel.
+    #[must_use]
+    pub fn depth(&self) -> u32 {
+        self.depth
+    }
+
+    /// Checks if the kernel prefers a linear graphics mode over the EGA text mode.
+    #[must_use]
+    pub fn linear_graphics(&self) -> bool {
+        self.mode_type == MULTIBOOT_VIDEO_LINEAR
+    }
+
+    /// Checks if a linear graphics mode satisfies the kernel preferences.
+    #[must_use]
+    pub fn matches(&self, width: u32, height: u32, depth: u32) -> bool {
+        self.linear_graphics()
+            && (self.width == 0 || self.width == width)
+            && (self.height == 0 || self.height == height)
+            && (self.depth == 0 || self.depth == depth)
+    }
+}
+
+/// A validated Multiboot header.
+#[derive(Clone, Copy, Debug)]
+pub struct MultibootHeader {
+    offset: usize,
+    flags: u32,
+    load_address: Option<MultibootLoadAddress>,
+    entry_addr: Option<u32>,
+    video_mode: Option<MultibootVideoModeRequest>,
+}
+
+impl MultibootHeader {
+    /// Searches for a Multiboot header in the first [`MULTIBOOT_SEARCH_LIMIT`] bytes of a kernel
+    /// image.
+    ///
+    /// Returns `Ok(None)` if the image does not contain any Multiboot header.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`BootError::InvalidImage`] if the header is truncated or its addresses are
+    /// inconsistent, and [`BootError::UnsupportedProtocol`] if the kernel requires a feature that
+    /// is not supported.
+    pub fn locate(image: &[u8]) -> Result<Option<Self>, BootError> {
+        let search_area = &image[..image.len().min(MULTIBOOT_SEARCH_LIMIT)];
+        let header_size = mem::size_of::<MultibootRawHeader>();
+
+        for offset in (0..search_area.len()).step_by(MULTIBOOT_HEADER_ALIGN) {
+            let Some(header_bytes) = search_area.get(offset..offset + header_size) else {
+                break;
+            };
+            let header: MultibootRawHeader = bytemuck::pod_read_unaligned(header_bytes);
+
+            let checksum = header
+                .magic
+                .wrapping_add(header.flags)
+                .wrapping_add(header.checksum);
+
+            if header.magic != MULTIBOOT_HEADER_MAGIC || checksum != 0 {
+                continue;
+            }
+
+            if header.flags & MULTIBOOT_REQUIRED_FLAGS_MASK & !MULTIBOOT_SUPPORTED_FLAGS != 0 {
+                return Err(BootError::UnsupportedProtocol);
+            }
+
+            return Self::parse_fields(offset, header.flags, &search_area[offset + header_size..])
+                .map(Some);
+        }
+
+        Ok(None)
+    }
+
+    /// Parses the optional fields of the header, located right after its fixed part.
+    fn parse_fields(offset: usize, flags: u32, fields: &[u8]) -> Result<Self, BootError> {
+        let mut header = Self {
+            offset,
+            flags,
+            load_address: None,
+            entry_addr: None,
+            video_mode: None,
+        };
+
+        let address_size = mem::size_of::<MultibootAddressFields>();
+
+        if flags & MULTIBOOT_AOUT_KLUDGE != 0 {
+            let address: MultibootAddressFields = bytemuck::pod_read_unaligned(
+                fields.get(..address_size).ok_or(BootError::InvalidImage)?,
+            );
+
+            header.load_address = Some(MultibootLoadAddress::new(
+                offset,
+                address.header,
+                address.load,
+                address.load_end,
+                address.bss_end,
+            )?);
+            header.entry_addr = Some(address.entry);
+        }
+
+        // the video mode fields are always located after the address fields, even if those are
+        // not valid
+        if flags & MULTIBOOT_VIDEO_MODE != 0 {
+            header.video_mode = Some(bytemuck::pod_read_unaligned(
+                fields
+                    .get(address_size..address_size + mem::size_of::<MultibootVideoModeRequest>())
+                    .ok_or(BootError::InvalidImage)?,
+            ));
+        }
+
+        Ok(header)
+    }
+
+    /// Returns the offset of the header in the image.
+    #[must_use]
+    pub fn offset(&self) -> usize {
+        self.offset
+    }
+
+    /// Checks if the boot modules must be aligned on page (4KB) boundaries.
+    #[must_use]
+    pub fn page_align(&self) -> bool {
+        self.flags & MULTIBOOT_PAGE_ALIGN != 0
+    }
+
+    /// Checks if the kernel requires the memory information fields.
+    #[must_use]
+    pub fn memory_info(&self) -> bool {
+        self.flags & MULTIBOOT_MEMORY_INFO != 0
+    }
+
+    /// Returns the load addresses provided by the header, if the image must not be loaded as an
+    /// ELF executable.
+    #[must_use]
+    pub fn load_address(&self) -> Option<&MultibootLoadAddress> {
+        self.load_address.as_ref()
+    }
+
+    /// Returns the entry point address provided by the header, if the image must not be loaded
+    /// as an ELF executable.
+    #[must_use]
+    pub fn entry_addr(&self) -> Option<u32> {
+        self.entry_addr
+    }
+
+    /// Returns the video mode preferred by the kernel, if it requires the video mode table.
+    #[must_use]
+    pub fn video_mode(&self) -> Option<&MultibootVideoModeRequest> {
+        self.video_mode.as_ref()
+    }
+}
@@ -4,17 +4,19 @@
 //! be placed anywhere in memory, and the operating system should be careful not to overwrite it before
 //! readint it.
 
-use alloc::string::String;
+use alloc::{string::String, vec::Vec};
 use bytemuck::{Pod, Zeroable};
 
 use crate::{
     mem::{
-        e820::{E820_MAP_ADDR, E820_MAP_LENGTH},
+        e820::{E820MemoryMap, E820_MAP_ADDR, E820_MAP_LENGTH},
         MemoryAddress, PhyAddr, PhyAddr32,
     },
     video::vesa::video_mode::ModeInfoBlock,
 };
 
+use super::memory_type;
+
 /// Multiboot information structure.
 ///
 /// Used by the bootloader to communicate basic information to the operating system, before handing out
@@ -95,6 +97,46 @@ impl MultibootInformation {
         self.mmap_addr
     }
 
+    /// Sets the amount of lower and upper memory, in kilobytes.
+    pub fn set_memory_info(&mut self, mem_lower: u32, mem_upper: u32) {
+        self.flags |= MultibootInformationFlags::MEM_FIELD_VALID;
+        self.mem_lower = mem_lower;
+        self.mem_upper = mem_upper;
+    }
+
+    /// Sets the _BIOS_ drive number and top-level partition from which the kernel was loaded.
+    pub fn set_boot_device(&mut self, drive: u8, partition: u8) {
+        self.flags |= MultibootInformationFlags::BOOT_DEVICE_VALID;
+        self.boot_device = MultibootBootDevice {
+            drive,
+            top_level_part: partition,
+            sub_part: 0xFF,
+            sub_sub_part: 0xFF,
+        };
+    }
+
+    /// Sets the address of the kernel command line, a C-style zero terminated string.
+    pub fn set_cmdline(&mut self, str_address: PhyAddr32) {
+        self.flags |= MultibootInformationFlags::CMDLINE_VALID;
+        self.cmdline = str_address;
+    }
+
+    /// Sets the address and number of the [`MultibootModuleEntry`] structures describing the boot
+    /// modules.
+    pub fn set_modules(&mut self, mods_address: PhyAddr32, mods_count: u32) {
+        self.flags |= MultibootInformationFlags::MODS_VALID;
+        self.mods_addr = mods_address;
+        self.mods_count = mods_count;
+    }
+
+    /// Sets the address and length (in bytes) of a memory map made of [`MultibootMemoryMapEntry`]
+    /// structures.
+    pub fn set_memory_map(&mut self, mmap_address: PhyAddr32, mmap_length: u32) {
+        self.flags |= MultibootInformationFlags::MMAP_VALID;
+        self.mmap_addr = mmap_address;
+        self.mmap_length = mmap_length;
+    }
+
     pub fn set_bootloader_name(&mut self, str_address: PhyAddr32) {
         self.flags |= MultibootInformationFlags::BOOTLOADER_NAME_VALID;
         self.boot_loader_name = str_address;
@@ -141,9 +183,7 @@ impl MultibootInformation {
 
         self.framebuffer = FramebufferMultibootInformation {
             addr: u64::from(mode_info_block.framebuffer).into(),
-            pitch: u32::from(
-                mode_info_block.bytes_per_scanline / u16::from(mode_info_block.bits_per_pixel >> 3),
-            ),
+            pitch: u32::from(mode_info_block.bytes_per_scanline),
             width: u32::from(mode_info_block.width),
             height: u32::from(mode_info_block.height),
             bpp: mode_info_block.bits_per_pixel,
@@ -340,6 +380,63 @@ pub struct FramebufferMultibootInformation {
     pub(crate) blue_mask_size: u8,
 }
 
+/// Describes a boot module loaded along with the kernel image.
+#[derive(Clone, Copy, Debug, Pod, Zeroable)]
+#[repr(C)]
+pub struct MultibootModuleEntry {
+    /// Physical start address of the module.
+    pub mod_start: PhyAddr32,
+
+    /// Physical end address of the module (excluded).
+    pub mod_end: PhyAddr32,
+
+    /// Physical address of the module command line, a C-style zero terminated string.
+    pub string: PhyAddr32,
+    reserved: u32,
+}
+
+impl MultibootModuleEntry {
+    /// Creates a module entry, for a module located in the physical memory range `[start, end)`.
+    #[must_use]
+    pub fn new(mod_start: PhyAddr32, mod_end: PhyAddr32, string: PhyAddr32) -> Self {
+        Self {
+            mod_start,
+            mod_end,
+            string,
+            reserved: 0,
+        }
+    }
+}
+
+/// Memory map entry, as expected by a Multiboot kernel.
+///
+/// Unlike the E820 memory map, every entry is preceded by its size (excluding the `size` field
+/// itself).
+#[derive(Clone, Copy, Debug, Pod, Zeroable)]
+#[repr(C, packed)]
+pub struct MultibootMemoryMapEntry {
+    size: u32,
+    base_addr: u64,
+    length: u64,
+    entry_type: u32,
+}
+
+/// Size of a [`MultibootMemoryMapEntry`], excluding its `size` field.
+const MULTIBOOT_MMAP_ENTRY_SIZE: u32 = 20;
+
+/// Converts the _BIOS_-provided memory map to the format expected by a Multiboot kernel.
+#[must_use]
+pub fn multiboot_memory_map(memory_map: E820MemoryMap) -> Vec<MultibootMemoryMapEntry> {
+    memory_map
+        .map(|entry| MultibootMemoryMapEntry {
+            size: MULTIBOOT_MMAP_ENTRY_SIZE,
+            base_addr: entry.phys_base(),
+            length: entry.length(),
+            entry_type: memory_type(entry.addr_type),
+        })
+        .collect()
+}
+
 /// Contains information about the disk device from which the OS image was loaded.
 ///
 /// Part of the Multiboot information header.
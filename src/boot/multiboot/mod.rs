@@ -1 +1,168 @@
+//! Multiboot boot protocols related code.
+//!
+//! Both the legacy Multiboot specification and Multiboot2 describe how a kernel image, stored
+//! either as an ELF executable or as a raw binary with explicit load addresses, is loaded and
+//! receives information about the system from the bootloader.
+
+use crate::{
+    errors::BootError,
+    mem::e820::{E820MemType, E820MemoryMap},
+};
+
+pub mod mb2_header;
+pub mod mb2_information;
+pub mod mb_header;
 pub mod mb_information;
+
+/// Start of the upper memory, as defined by the Multiboot specifications.
+const UPPER_MEMORY_START: u64 = 0x100_000;
+
+/// Maximum amount of lower memory, in kilobytes.
+const MAX_LOWER_MEMORY_KB: u64 = 640;
+
+/// Memory map entry type: available RAM.
+const MULTIBOOT_MEMORY_AVAILABLE: u32 = 1;
+
+/// Memory map entry type: reserved memory.
+const MULTIBOOT_MEMORY_RESERVED: u32 = 2;
+
+/// Memory map entry type: memory holding ACPI information, usable once the tables are parsed.
+const MULTIBOOT_MEMORY_ACPI_RECLAIMABLE: u32 = 3;
+
+/// Memory map entry type: memory that must be preserved on hibernation.
+const MULTIBOOT_MEMORY_NVS: u32 = 4;
+
+/// Memory map entry type: defective RAM.
+const MULTIBOOT_MEMORY_BADRAM: u32 = 5;
+
+/// Load addresses of a kernel image that is not stored as an ELF executable.
+///
+/// Those addresses are provided by the Multiboot header (the _a.out kludge_), and describe which
+/// part of the image file is copied to memory, and where.
+#[derive(Clone, Copy, Debug)]
+pub struct MultibootLoadAddress {
+    file_offset: usize,
+    load_addr: u32,
+    load_end_addr: Option<u32>,
+    bss_end_addr: Option<u32>,
+}
+
+impl MultibootLoadAddress {
+    /// Computes the load addresses from the fields of a Multiboot header located at `header_offset`
+    /// in the image file.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`BootError::InvalidImage`] if the addresses are inconsistent with each other, or
+    /// with the location of the header in the image.
+    pub fn new(
+        header_offset: usize,
+        header_addr: u32,
+        load_addr: u32,
+        load_end_addr: u32,
+        bss_end_addr: u32,
+    ) -> Result<Self, BootError> {
+        let header_delta = usize::try_from(
+            header_addr
+                .checked_sub(load_addr)
+                .ok_or(BootError::InvalidImage)?,
+        )
+        .map_err(|_| BootError::InvalidImage)?;
+        let file_offset = header_offset
+            .checked_sub(header_delta)
+            .ok_or(BootError::InvalidImage)?;
+
+        let load_end_addr = (load_end_addr != 0).then_some(load_end_addr);
+        let bss_end_addr = (bss_end_addr != 0).then_some(bss_end_addr);
+
+        if load_end_addr.is_some_and(|end| end <= load_addr)
+            || bss_end_addr.is_some_and(|end| end < load_end_addr.unwrap_or(load_addr))
+        {
+            return Err(BootError::InvalidImage);
+        }
+
+        Ok(Self {
+            file_offset,
+            load_addr,
+            load_end_addr,
+            bss_end_addr,
+        })
+    }
+
+    /// Returns the offset in the image file of the first byte to load.
+    #[must_use]
+    pub fn file_offset(&self) -> usize {
+        self.file_offset
+    }
+
+    /// Returns the physical address to which the image is loaded.
+    #[must_use]
+    pub fn load_addr(&self) -> u32 {
+        self.load_addr
+    }
+
+    /// Returns the number of bytes to copy from the image file.
+    ///
+    /// Returns `None` if the image is loaded up to the end of the file.
+    #[must_use]
+    pub fn load_size(&self) -> Option<usize> {
+        self.load_end_addr
+            .and_then(|end| usize::try_from(end - self.load_addr).ok())
+    }
+
+    /// Returns the size of the image file required to load the kernel, if known.
+    #[must_use]
+    pub fn image_size(&self) -> Option<usize> {
+        self.load_size()
+            .and_then(|size| self.file_offset.checked_add(size))
+    }
+
+    /// Returns the physical end address of the `.bss` segment, which has to be zeroed after the
+    /// loaded data.
+    #[must_use]
+    pub fn bss_end_addr(&self) -> Option<u32> {
+        self.bss_end_addr
+    }
+}
+
+/// Computes the amount of lower and upper memory, in kilobytes, from the _BIOS_-provided memory
+/// map.
+///
+/// The lower memory starts at address 0, and the upper memory at 1MB. Both values only count the
+/// usable memory available before the first memory hole.
+#[must_use]
+pub fn basic_memory_info(memory_map: E820MemoryMap) -> (u32, u32) {
+    let mut mem_lower = 0;
+    let mut mem_upper = 0;
+
+    for entry in memory_map {
+        if !matches!(entry.addr_type, E820MemType::RAM) {
+            continue;
+        }
+
+        let entry_end = entry.phys_base() + entry.length();
+
+        if entry.phys_base() == 0 {
+            mem_lower = (entry.length() / 1024).min(MAX_LOWER_MEMORY_KB);
+        } else if entry.phys_base() <= UPPER_MEMORY_START && entry_end > UPPER_MEMORY_START {
+            mem_upper = (entry_end - UPPER_MEMORY_START) / 1024;
+        }
+    }
+
+    (
+        u32::try_from(mem_lower).unwrap_or(u32::MAX),
+        u32::try_from(mem_upper).unwrap_or(u32::MAX),
+    )
+}
+
+/// Converts an E820 memory type to the memory map entry type defined by both Multiboot
+/// specifications.
+fn memory_type(addr_type: E820MemType) -> u32 {
+    match addr_type {
+        E820MemType::RAM => MULTIBOOT_MEMORY_AVAILABLE,
+        E820MemType::ACPI => MULTIBOOT_MEMORY_ACPI_RECLAIMABLE,
+        E820MemType::NVS => MULTIBOOT_MEMORY_NVS,
+        E820MemType::UNUSABLE => MULTIBOOT_MEMORY_BADRAM,
+        _ => MULTIBOOT_MEMORY_RESERVED,
+    }
+}
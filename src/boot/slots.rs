@@ -0,0 +1,412 @@
+//! A/B boot slots, with boot attempt counters.
+//!
+//! Boot entries can be assigned to one of two slots (see [`BootSlot`]), typically holding the
+//! current and the previous version of the system. The state of the slots is stored in the first
+//! sector of a dedicated partition (the `slot_state` option of the configuration file), which is
+//! read and written by the bootloader on every boot.
+//!
+//! The bootloader boots the _active_ slot. Unless it has been marked as successful, each boot
+//! attempt decrements its counter. Once the counter reaches zero, the bootloader falls back to the
+//! other slot, which becomes the active one.
+//!
+//! # Updating a slot
+//!
+//! Once the new system is installed in the inactive slot, the updater sets its counter (usually
+//! to [`DEFAULT_BOOT_TRIES`]), clears its _successful_ flag, and makes it the active slot.
+//!
+//! # Marking a boot as successful
+//!
+//! The slot that has been booted is appended to the kernel command line, as
+//! `fzboot.slot=a` or `fzboot.slot=b` (see [`SLOT_CMDLINE_KEY`]). Once the booted system considers
+//! itself healthy, it must set the _successful_ flag of that slot, so that its counter is not
+//! decremented anymore:
+//!
+//! ```ignore
+//! let mut state = read_slot_state(&partition)?;
+//! state.mark_successful(BootSlot::B);
+//! write_slot_state(&partition, &state)?;
+//! ```
+//!
+//! Systems which are not linked against this crate can update the sector directly. Its layout,
+//! with every field stored as little-endian, is:
+//!
+//! | Offset | Size | Description                                               |
+//! |--------|------|-----------------------------------------------------------|
+//! | 0x00   | 8    | Magic number, `FZBSLOTS`                                  |
+//! | 0x08   | 1    | Version of the layout ([`SLOT_STATE_VERSION`])            |
+//! | 0x09   | 1    | Active slot (0 for `A`, 1 for `B`)                        |
+//! | 0x0A   | 1    | Remaining boot attempts of slot `A`                       |
+//! | 0x0B   | 1    | Flags of slot `A` (bit 0: successful)                     |
+//! | 0x0C   | 1    | Remaining boot attempts of slot `B`                       |
+//! | 0x0D   | 1    | Flags of slot `B` (bit 0: successful)                     |
+//! | 0x0E   | 2    | Reserved, must be zero                                    |
+//! | 0x10   | 4    | CRC32 (as used by _GPT_) of the first 16 bytes            |
+
+use alloc::vec::Vec;
+
+use crate::{
+    drivers::generics::dev_disk::{get_sata_drive, DiskDevice},
+    errors::{CanFail, IOError},
+    fs::partitions::{gpt::crc32_calc, Partition},
+};
+
+/// Magic number stored at the beginning of the state sector.
+pub const SLOT_STATE_MAGIC: [u8; 8] = *b"FZBSLOTS";
+
+/// Version of the state sector layout.
+pub const SLOT_STATE_VERSION: u8 = 1;
+
+/// Number of boot attempts given to a newly installed slot.
+pub const DEFAULT_BOOT_TRIES: u8 = 3;
+
+/// Kernel command line key used to report the booted slot.
+pub const SLOT_CMDLINE_KEY: &str = "fzboot.slot";
+
+/// Size of the state sector, in bytes.
+const SLOT_STATE_SECTOR_SIZE: usize = 0x200;
+
+/// Offset of the state checksum, which covers every preceding byte.
+const SLOT_STATE_CHECKSUM_OFFSET: usize = 0x10;
+
+/// Flag set once the system of a slot has booted successfully.
+const SLOT_FLAG_SUCCESSFUL: u8 = 0x1;
+
+/// One of the two boot slots.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum BootSlot {
+    /// First slot.
+    A,
+
+    /// Second slot.
+    B,
+}
+
+impl BootSlot {
+    /// Parses a slot name, `a` or `b`.
+    #[must_use]
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "a" | "A" => Some(Self::A),
+            "b" | "B" => Some(Self::B),
+            _ => None,
+        }
+    }
+
+    /// Returns the name of the slot, as reported on the kernel command line.
+    #[must_use]
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::A => "a",
+            Self::B => "b",
+        }
+    }
+
+    /// Returns the other slot.
+    #[must_use]
+    pub fn other(self) -> Self {
+        match self {
+            Self::A => Self::B,
+            Self::B => Self::A,
+        }
+    }
+
+    /// Returns the index of the slot in the state sector.
+    fn index(self) -> usize {
+        match self {
+            Self::A => 0,
+            Self::B => 1,
+        }
+    }
+}
+
+/// State of a single boot slot.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct SlotInfo {
+    /// Number of boot attempts left before falling back to the other slot.
+    pub tries: u8,
+
+    /// Whether the system of this slot has already booted successfully.
+    pub successful: bool,
+}
+
+impl SlotInfo {
+    /// Checks if this slot can still be booted.
+    #[must_use]
+    pub fn is_bootable(&self) -> bool {
+        self.successful || self.tries > 0
+    }
+}
+
+/// State of the two boot slots, as stored in the state sector.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct SlotState {
+    /// Slot booted by default.
+    pub active: BootSlot,
+
+    slots: [SlotInfo; 2],
+}
+
+impl Default for SlotState {
+    /// State of a freshly installed system: slot `A` is active and known to boot, slot `B` is
+    /// empty.
+    fn default() -> Self {
+        Self {
+            active: BootSlot::A,
+            slots: [
+                SlotInfo {
+                    tries: DEFAULT_BOOT_TRIES,
+                    successful: true,
+                },
+                SlotInfo::default(),
+            ],
+        }
+    }
+}
+
+impl SlotState {
+    /// Parses the content of a state sector.
+    ///
+    /// Returns `None` if the magic number, the version or the checksum is invalid.
+    #[must_use]
+    pub fn from_bytes(sector: &[u8]) -> Option<Self> {
+        let header = sector.get(..SLOT_STATE_CHECKSUM_OFFSET + 4)?;
+        let checksum = u32::from_le_bytes(header[SLOT_STATE_CHECKSUM_OFFSET..].try_into().ok()?);
+
+        if header[..8] != SLOT_STATE_MAGIC
+            || header[8] != SLOT_STATE_VERSION
+            || crc32_calc(&header[..SLOT_STATE_CHECKSUM_OFFSET]) != checksum
+        {
+            return None;
+        }
+
+        let slot = |offset: usize| SlotInfo {
+            tries: header[offset],
+            successful: header[offset + 1] & SLOT_FLAG_SUCCESSFUL != 0,
+        };
+
+        Some(Self {
+            active: match header[9] {
+                0 => BootSlot::A,
+                1 => BootSlot::B,
+                _ => return None,
+            },
+            slots: [slot(0xA), slot(0xC)],
+        })
+    }
+
+    /// Serializes this state to a state sector.
+    #[must_use]
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut sector = alloc::vec![0u8; SLOT_STATE_SECTOR_SIZE];
+
+        sector[..8].copy_from_slice(&SLOT_STATE_MAGIC);
+        sector[8] = SLOT_STATE_VERSION;
+        sector[9] = match self.active {
+            BootSlot::A => 0,
+            BootSlot::B => 1,
+        };
+
+        for (slot, offset) in self.slots.iter().zip([0xA, 0xC]) {
+            sector[offset] = slot.tries;
+            sector[offset + 1] = if slot.successful {
+                SLOT_FLAG_SUCCESSFUL
+            } else {
+                0
+            };
+        }
+
+        let checksum = crc32_calc(&sector[..SLOT_STATE_CHECKSUM_OFFSET]);
+        sector[SLOT_STATE_CHECKSUM_OFFSET..SLOT_STATE_CHECKSUM_OFFSET + 4]
+            .copy_from_slice(&checksum.to_le_bytes());
+
+        sector
+    }
+
+    /// Returns the state of a slot.
+    #[must_use]
+    pub fn slot(&self, slot: BootSlot) -> &SlotInfo {
+        &self.slots[slot.index()]
+    }
+
+    /// Returns a mutable reference to the state of a slot.
+    pub fn slot_mut(&mut self, slot: BootSlot) -> &mut SlotInfo {
+        &mut self.slots[slot.index()]
+    }
+
+    /// Selects the slot to boot: the active one if it can still be booted, the other one
+    /// otherwise, which then becomes the active slot.
+    ///
+    /// Returns `None` if neither of the slots can be booted.
+    pub fn select(&mut self) -> Option<BootSlot> {
+        if !self.slot(self.active).is_bootable() {
+            if !self.slot(self.active.other()).is_bootable() {
+                return None;
+            }
+
+            self.active = self.active.other();
+        }
+
+        Some(self.active)
+    }
+
+    /// Records a boot attempt of a slot, decrementing its counter unless it has already booted
+    /// successfully.
+    pub fn record_attempt(&mut self, slot: BootSlot) {
+        let info = self.slot_mut(slot);
+
+        if !info.successful {
+            info.tries = info.tries.saturating_sub(1);
+        }
+    }
+
+    /// Marks the boot of a slot as successful. Should be called by the booted system.
+    pub fn mark_successful(&mut self, slot: BootSlot) {
+        self.slot_mut(slot).successful = true;
+    }
+}
+
+/// Reads the slot state stored on the first sector of a partition.
+///
+/// If the sector does not contain a valid state (for instance, on a freshly created partition),
+/// [`SlotState::default`] is returned.
+///
+/// # Errors
+///
+/// Returns [`IOError::InvalidDevice`] if the disk storing the partition is not available, and
+/// [`IOError::Unknown`] in case of disk I/O error.
+pub fn read_slot_state(partition: &Partition) -> Result<SlotState, IOError> {
+    let drive = get_sata_drive(partition.drive_id()).ok_or(IOError::InvalidDevice)?;
+    let sector = drive
+        .read(partition.start_lba(), 1)
+        .complete()
+        .data
+        .ok_or(IOError::Unknown)?;
+
+    Ok(SlotState::from_bytes(&sector).unwrap_or_default())
+}
+
+/// Writes a slot state to the first sector of a partition.
+///
+/// # Errors
+///
+/// Returns [`IOError::InvalidDevice`] if the disk storing the partition is not available, and
+/// [`IOError::Unknown`] in case of disk I/O error.
+pub fn write_slot_state(partition: &Partition, state: &SlotState) -> CanFail<IOError> {
+    let drive = get_sata_drive(partition.drive_id()).ok_or(IOError::InvalidDevice)?;
+    let result = drive
+        .write(partition.start_lba(), 1, state.to_bytes())
+        .complete();
+
+    if result.is_success() {
+        Ok(())
+    } else {
+        Err(IOError::Unknown)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{BootSlot, SlotInfo, SlotState, DEFAULT_BOOT_TRIES, SLOT_STATE_SECTOR_SIZE};
+
+    /// State right after an update installed in slot `B`.
+    fn updated_state() -> SlotState {
+        let mut state = SlotState::default();
+
+        *state.slot_mut(BootSlot::B) = SlotInfo {
+            tries: DEFAULT_BOOT_TRIES,
+            successful: false,
+        };
+        state.active = BootSlot::B;
+        state
+    }
+
+    /// Simulates a boot: selects a slot, and records the attempt.
+    fn boot(state: &mut SlotState) -> Option<BootSlot> {
+        let slot = state.select()?;
+
+        state.record_attempt(slot);
+        Some(slot)
+    }
+
+    #[test]
+    fn boots_successful_slot_forever() {
+        let mut state = SlotState::default();
+
+        for _ in 0..10 {
+            assert_eq!(boot(&mut state), Some(BootSlot::A));
+        }
+
+        assert_eq!(state.slot(BootSlot::A).tries, DEFAULT_BOOT_TRIES);
+    }
+
+    #[test]
+    fn falls_back_once_tries_are_exhausted() {
+        let mut state = updated_state();
+
+        for tries in (0..DEFAULT_BOOT_TRIES).rev() {
+            assert_eq!(boot(&mut state), Some(BootSlot::B));
+            assert_eq!(state.slot(BootSlot::B).tries, tries);
+        }
+
+        assert_eq!(boot(&mut state), Some(BootSlot::A));
+        assert_eq!(state.active, BootSlot::A);
+        assert_eq!(boot(&mut state), Some(BootSlot::A));
+    }
+
+    #[test]
+    fn stops_counting_once_successful() {
+        let mut state = updated_state();
+
+        assert_eq!(boot(&mut state), Some(BootSlot::B));
+        state.mark_successful(BootSlot::B);
+
+        for _ in 0..10 {
+            assert_eq!(boot(&mut state), Some(BootSlot::B));
+        }
+
+        assert_eq!(state.slot(BootSlot::B).tries, DEFAULT_BOOT_TRIES - 1);
+    }
+
+    #[test]
+    fn refuses_to_boot_without_bootable_slot() {
+        let mut state = updated_state();
+        *state.slot_mut(BootSlot::A) = SlotInfo::default();
+        state.slot_mut(BootSlot::B).tries = 1;
+
+        assert_eq!(boot(&mut state), Some(BootSlot::B));
+        assert_eq!(state.select(), None);
+        assert_eq!(state.active, BootSlot::B);
+    }
+
+    #[test]
+    fn serializes_state_sector() {
+        let mut state = updated_state();
+        state.record_attempt(BootSlot::B);
+
+        let sector = state.to_bytes();
+
+        assert_eq!(sector.len(), SLOT_STATE_SECTOR_SIZE);
+        assert_eq!(sector[..0x10], *b"FZBSLOTS\x01\x01\x03\x01\x02\x00\x00\x00");
+        assert!(sector[0x14..].iter().all(|&byte| byte == 0));
+        assert_eq!(SlotState::from_bytes(&sector), Some(state));
+    }
+
+    #[test]
+    fn rejects_invalid_state_sectors() {
+        let sector = updated_state().to_bytes();
+
+        for (offset, value) in [(0, b'X'), (8, 2), (9, 2), (0xA, 0), (0x10, 0)] {
+            let mut corrupted = sector.clone();
+            corrupted[offset] = value;
+
+            assert_eq!(
+                SlotState::from_bytes(&corrupted),
+                None,
+                "offset {offset:#x}"
+            );
+        }
+
+        assert_eq!(SlotState::from_bytes(&sector[..0x13]), None);
+        assert_eq!(SlotState::from_bytes(&[0; SLOT_STATE_SECTOR_SIZE]), None);
+    }
+}
@@ -0,0 +1,112 @@
+//! Boot images signature verification.
+//!
+//! Every file loaded by a boot entry (kernel, initial ramdisk and modules) can be checked against
+//! a detached signature, stored next to it with the [`SIGNATURE_SUFFIX`] extension. The signature
+//! is the raw, 64 bytes long, `Ed25519` signature of the `SHA-256` digest of the file.
+//!
+//! The public key is embedded in the bootloader at build time, from the `FZBOOT_PUBLIC_KEY`
+//! environment variable (as 64 hexadecimal digits). What happens when a file cannot be verified
+//! is set by the [`VerifyPolicy`], either from the `verify` option of the configuration file, or
+//! from the `FZBOOT_VERIFY_POLICY` environment variable at build time. As the configuration file
+//! is not signed, the latter is a lower bound: the configuration file can only make it stricter.
+//!
+//! A key pair can be generated, and a kernel signed, with `openssl`:
+//!
+//! ```text
+//! openssl genpkey -algorithm ed25519 -out fzboot.pem
+//! openssl pkey -in fzboot.pem -pubout -outform DER | tail -c 32 | xxd -p -c 32
+//! openssl dgst -sha256 -binary vmlinuz > vmlinuz.sha256
+//! openssl pkeyutl -sign -rawin -inkey fzboot.pem -in vmlinuz.sha256 -out vmlinuz.sig
+//! ```
+//!
+//! Images stored on raw partitions have no detached signature, and are always reported as
+//! unsigned.
+
+use crate::{
+    crypto::{
+        ed25519::{self, PUBLIC_KEY_SIZE, SIGNATURE_SIZE},
+        sha256::sha256,
+    },
+    errors::{CanFail, SignatureError},
+};
+
+/// Suffix appended to the path of a file to locate its detached signature.
+pub const SIGNATURE_SUFFIX: &str = ".sig";
+
+/// What to do when a boot image is unsigned, or its signature is invalid.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord)]
+pub enum VerifyPolicy {
+    /// Signatures are not checked.
+    #[default]
+    Ignore,
+
+    /// Verification failures are reported, but the image is booted anyway.
+    Warn,
+
+    /// Images which cannot be verified are not booted.
+    Refuse,
+}
+
+impl VerifyPolicy {
+    /// Parses a policy name: `ignore`, `warn` or `refuse`.
+    #[must_use]
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "ignore" => Some(Self::Ignore),
+            "warn" => Some(Self::Warn),
+            "refuse" => Some(Self::Refuse),
+            _ => None,
+        }
+    }
+
+    /// Returns the name of the policy.
+    #[must_use]
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Ignore => "ignore",
+            Self::Warn => "warn",
+            Self::Refuse => "refuse",
+        }
+    }
+}
+
+/// Parses a public key, as 64 hexadecimal digits.
+#[must_use]
+pub fn parse_public_key(hex: &str) -> Option<[u8; PUBLIC_KEY_SIZE]> {
+    let hex = hex.trim().as_bytes();
+
+    if hex.len() != 2 * PUBLIC_KEY_SIZE {
+        return None;
+    }
+
+    let mut key = [0; PUBLIC_KEY_SIZE];
+
+    for (byte, digits) in key.iter_mut().zip(hex.chunks_exact(2)) {
+        let digits = core::str::from_utf8(digits).ok()?;
+        *byte = u8::from_str_radix(digits, 16).ok()?;
+    }
+
+    Some(key)
+}
+
+/// Verifies the detached signature of a boot image.
+///
+/// # Errors
+///
+/// Returns [`SignatureError::MalformedSignature`] if `signature` does not have the size of an
+/// `Ed25519` signature, and [`SignatureError::InvalidSignature`] if it does not match the image.
+pub fn verify_image(
+    public_key: &[u8; PUBLIC_KEY_SIZE],
+    image: &[u8],
+    signature: &[u8],
+) -> CanFail<SignatureError> {
+    let signature: &[u8; SIGNATURE_SIZE] = signature
+        .try_into()
+        .map_err(|_| SignatureError::MalformedSignature)?;
+
+    if ed25519::verify(public_key, &sha256(image), signature) {
+        Ok(())
+    } else {
+        Err(SignatureError::InvalidSignature)
+    }
+}
@@ -0,0 +1,461 @@
+//! `Ed25519` signature verification (_RFC 8032_).
+//!
+//! Field elements of `GF(2^255 - 19)` are stored as 16 limbs of 16 bits, and points of the curve
+//! as extended coordinates `(X, Y, Z, T)`, following the `TweetNaCl` implementation.
+
+use super::sha512::Sha512;
+
+/// Size of a public key, in bytes.
+pub const PUBLIC_KEY_SIZE: usize = 32;
+
+/// Size of a signature, in bytes.
+pub const SIGNATURE_SIZE: usize = 64;
+
+/// Element of `GF(2^255 - 19)`.
+type Fe = [i64; 16];
+
+/// Point of the curve, in extended coordinates.
+type Point = [Fe; 4];
+
+const FE_ZERO: Fe = [0; 16];
+
+const FE_ONE: Fe = [1, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0];
+
+/// Curve constant `d`.
+const D: Fe = [
+    0x78a3, 0x1359, 0x4dca, 0x75eb, 0xd8ab, 0x4141, 0x0a4d, 0x0070, 0xe898, 0x7779, 0x4079, 0x8cc7,
+    0xfe73, 0x2b6f, 0x6cee, 0x5203,
+];
+
+/// `2 * d`.
+const D2: Fe = [
+    0xf159, 0x26b2, 0x9b94, 0xebd6, 0xb156, 0x8283, 0x149a, 0x00e0, 0xd130, 0xeef3, 0x80f2, 0x198e,
+    0xfce7, 0x56df, 0xd9dc, 0x2406,
+];
+
+/// `X` coordinate of the base point.
+const BASE_X: Fe = [
+    0xd51a, 0x8f25, 0x2d60, 0xc956, 0xa7b2, 0x9525, 0xc760, 0x692c, 0xdc5c, 0xfdd6, 0xe231, 0xc0a4,
+    0x53fe, 0xcd6e, 0x36d3, 0x2169,
+];
+
+/// `Y` coordinate of the base point.
+const BASE_Y: Fe = [
+    0x6658, 0x6666, 0x6666, 0x6666, 0x6666, 0x6666, 0x6666, 0x6666, 0x6666, 0x6666, 0x6666, 0x6666,
+    0x6666, 0x6666, 0x6666, 0x6666,
+];
+
+/// Square root of `-1`.
+const SQRT_M1: Fe = [
+    0xa0b0, 0x4a0e, 0x1b27, 0xc4ee, 0xe478, 0xad2f, 0x1806, 0x2f43, 0xd7a7, 0x3dfb, 0x0099, 0x2b4d,
+    0xdf0b, 0x4fc1, 0x2480, 0x2b83,
+];
+
+/// Order of the base point, `2^252 + 27742317777372353535851937790883648493`, as little-endian
+/// bytes.
+const ORDER: [i64; 32] = [
+    0xed, 0xd3, 0xf5, 0x5c, 0x1a, 0x63, 0x12, 0x58, 0xd6, 0x9c, 0xf7, 0xa2, 0xde, 0xf9, 0xde, 0x14,
+    0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0x10,
+];
+
+/// Verifies the `Ed25519` signature of `message`.
+///
+/// Returns `false` if the public key is not a valid point of the curve, if the signature is not
+/// canonical, or if it does not match the message.
+#[must_use]
+#[allow(clippy::missing_panics_doc, clippy::many_single_char_names)]
+pub fn verify(
+    public_key: &[u8; PUBLIC_KEY_SIZE],
+    message: &[u8],
+    signature: &[u8; SIGNATURE_SIZE],
+) -> bool {
+    let (r, s) = signature.split_at(32);
+    let s: &[u8; 32] = s.try_into().expect("invalid signature size");
+
+    if !is_canonical_scalar(s) {
+        return false;
+    }
+
+    let Some(neg_a) = unpack_neg(public_key) else {
+        return false;
+    };
+
+    let mut hasher = Sha512::new();
+    hasher.update(r);
+    hasher.update(public_key);
+    hasher.update(message);
+    let h = reduce(&hasher.finalize());
+
+    // checks that [s]B - [h]A = R
+    let mut p = scalar_mult(neg_a, &h);
+    let q = scalar_mult(base_point(), s);
+    add(&mut p, &q);
+
+    pack_point(&p) == r
+}
+
+/// Checks that a scalar is lower than [`ORDER`].
+fn is_canonical_scalar(s: &[u8; 32]) -> bool {
+    for (&byte, &order) in s.iter().zip(ORDER.iter()).rev() {
+        match i64::from(byte).cmp(&order) {
+            core::cmp::Ordering::Less => return true,
+            core::cmp::Ordering::Greater => return false,
+            core::cmp::Ordering::Equal => {}
+        }
+    }
+
+    false
+}
+
+/// Returns the base point of the curve.
+fn base_point() -> Point {
+    [BASE_X, BASE_Y, FE_ONE, mul(&BASE_X, &BASE_Y)]
+}
+
+/// Propagates the carries of a field element, so that every limb fits in 16 bits.
+fn carry(o: &mut Fe) {
+    for i in 0..16 {
+        o[i] += 1 << 16;
+        let c = o[i] >> 16;
+
+        if i < 15 {
+            o[i + 1] += c - 1;
+        } else {
+            o[0] += 38 * (c - 1);
+        }
+
+        o[i] -= c << 16;
+    }
+}
+
+/// Swaps `p` and `q` if `b` is 1.
+fn select(p: &mut Fe, q: &mut Fe, b: i64) {
+    let mask = !(b - 1);
+
+    for (p, q) in p.iter_mut().zip(q.iter_mut()) {
+        let t = mask & (*p ^ *q);
+        *p ^= t;
+        *q ^= t;
+    }
+}
+
+/// Serializes a field element, fully reduced, as little-endian bytes.
+#[allow(
+    clippy::as_conversions,
+    clippy::cast_possible_truncation,
+    clippy::cast_sign_loss
+)]
+fn pack(n: &Fe) -> [u8; 32] {
+    let mut t = *n;
+    let mut m = FE_ZERO;
+
+    carry(&mut t);
+    carry(&mut t);
+    carry(&mut t);
+
+    for _ in 0..2 {
+        m[0] = t[0] - 0xffed;
+
+        for i in 1..15 {
+            m[i] = t[i] - 0xffff - ((m[i - 1] >> 16) & 1);
+            m[i - 1] &= 0xffff;
+        }
+
+        m[15] = t[15] - 0x7fff - ((m[14] >> 16) & 1);
+        let b = (m[15] >> 16) & 1;
+        m[14] &= 0xffff;
+        select(&mut t, &mut m, 1 - b);
+    }
+
+    let mut o = [0; 32];
+
+    for (bytes, limb) in o.chunks_exact_mut(2).zip(t) {
+        bytes[0] = limb as u8;
+        bytes[1] = (limb >> 8) as u8;
+    }
+
+    o
+}
+
+/// Deserializes a field element, ignoring the most significant bit.
+fn unpack(n: &[u8; 32]) -> Fe {
+    let mut o = FE_ZERO;
+
+    for (limb, bytes) in o.iter_mut().zip(n.chunks_exact(2)) {
+        *limb = i64::from(bytes[0]) + (i64::from(bytes[1]) << 8);
+    }
+
+    o[15] &= 0x7fff;
+    o
+}
+
+/// Returns the parity of a field element.
+fn parity(a: &Fe) -> u8 {
+    pack(a)[0] & 1
+}
+
+fn add_fe(a: &Fe, b: &Fe) -> Fe {
+    core::array::from_fn(|i| a[i] + b[i])
+}
+
+fn sub_fe(a: &Fe, b: &Fe) -> Fe {
+    core::array::from_fn(|i| a[i] - b[i])
+}
+
+fn mul(a: &Fe, b: &Fe) -> Fe {
+    let mut t = [0i64; 31];
+
+    for i in 0..16 {
+        for j in 0..16 {
+            t[i + j] += a[i] * b[j];
+        }
+    }
+
+    for i in 0..15 {
+        t[i] += 38 * t[i + 16];
+    }
+
+    let mut o: Fe = t[..16].try_into().expect("invalid field element size");
+    carry(&mut o);
+    carry(&mut o);
+    o
+}
+
+fn square(a: &Fe) -> Fe {
+    mul(a, a)
+}
+
+/// Returns `a^(p - 2)`, the inverse of `a`.
+fn invert(a: &Fe) -> Fe {
+    let mut c = *a;
+
+    for i in (0..=253).rev() {
+        c = square(&c);
+
+        if i != 2 && i != 4 {
+            c = mul(&c, a);
+        }
+    }
+
+    c
+}
+
+/// Returns `a^((p - 5) / 8)`, used to compute square roots.
+fn pow2523(a: &Fe) -> Fe {
+    let mut c = *a;
+
+    for i in (0..=250).rev() {
+        c = square(&c);
+
+        if i != 1 {
+            c = mul(&c, a);
+        }
+    }
+
+    c
+}
+
+/// Adds `q` to `p`.
+#[allow(clippy::many_single_char_names)]
+fn add(p: &mut Point, q: &Point) {
+    let a = mul(&sub_fe(&p[1], &p[0]), &sub_fe(&q[1], &q[0]));
+    let b = mul(&add_fe(&p[0], &p[1]), &add_fe(&q[0], &q[1]));
+    let c = mul(&mul(&p[3], &q[3]), &D2);
+    let d = mul(&p[2], &q[2]);
+    let d = add_fe(&d, &d);
+
+    let e = sub_fe(&b, &a);
+    let f = sub_fe(&d, &c);
+    let g = add_fe(&d, &c);
+    let h = add_fe(&b, &a);
+
+    *p = [mul(&e, &f), mul(&h, &g), mul(&g, &f), mul(&e, &h)];
+}
+
+/// Swaps `p` and `q` if `b` is 1.
+fn swap_points(p: &mut Point, q: &mut Point, b: i64) {
+    for (p, q) in p.iter_mut().zip(q.iter_mut()) {
+        select(p, q, b);
+    }
+}
+
+/// Serializes a point, as its `y` coordinate along with the sign of its `x` coordinate.
+fn pack_point(p: &Point) -> [u8; 32] {
+    let z_inv = invert(&p[2]);
+    let x = mul(&p[0], &z_inv);
+    let y = mul(&p[1], &z_inv);
+
+    let mut r = pack(&y);
+    r[31] ^= parity(&x) << 7;
+    r
+}
+
+/// Returns `[s]q`, `s` being a little-endian scalar.
+fn scalar_mult(mut q: Point, s: &[u8; 32]) -> Point {
+    let mut p = [FE_ZERO, FE_ONE, FE_ONE, FE_ZERO];
+
+    for i in (0..256).rev() {
+        let b = i64::from((s[i / 8] >> (i & 7)) & 1);
+
+        swap_points(&mut p, &mut q, b);
+        add(&mut q, &p);
+        let p_copy = p;
+        add(&mut p, &p_copy);
+        swap_points(&mut p, &mut q, b);
+    }
+
+    p
+}
+
+/// Deserializes a point, and returns its opposite.
+///
+/// Returns `None` if the bytes do not encode a point of the curve.
+fn unpack_neg(bytes: &[u8; 32]) -> Option<Point> {
+    let y = unpack(bytes);
+    let num = square(&y);
+    let den = mul(&num, &D);
+    let num = sub_fe(&num, &FE_ONE);
+    let den = add_fe(&FE_ONE, &den);
+
+    let den2 = square(&den);
+    let den4 = square(&den2);
+    let den6 = mul(&den4, &den2);
+    let mut t = mul(&mul(&den6, &num), &den);
+
+    t = pow2523(&t);
+    t = mul(&mul(&mul(&t, &num), &den), &den);
+    let mut x = mul(&t, &den);
+
+    if pack(&mul(&square(&x), &den)) != pack(&num) {
+        x = mul(&x, &SQRT_M1);
+    }
+
+    if pack(&mul(&square(&x), &den)) != pack(&num) {
+        return None;
+    }
+
+    if parity(&x) == bytes[31] >> 7 {
+        x = sub_fe(&FE_ZERO, &x);
+    }
+
+    let t = mul(&x, &y);
+    Some([x, y, FE_ONE, t])
+}
+
+/// Reduces a 512-bit little-endian integer modulo [`ORDER`].
+#[allow(
+    clippy::as_conversions,
+    clippy::cast_possible_truncation,
+    clippy::cast_sign_loss
+)]
+fn reduce(bytes: &[u8; 64]) -> [u8; 32] {
+    let mut x: [i64; 64] = core::array::from_fn(|i| i64::from(bytes[i]));
+
+    for i in (32..64).rev() {
+        let mut carry = 0;
+
+        for j in (i - 32)..(i - 12) {
+            x[j] += carry - 16 * x[i] * ORDER[j - (i - 32)];
+            carry = (x[j] + 128) >> 8;
+            x[j] -= carry << 8;
+        }
+
+        x[i - 12] += carry;
+        x[i] = 0;
+    }
+
+    let mut carry = 0;
+
+    for j in 0..32 {
+        x[j] += carry - (x[31] >> 4) * ORDER[j];
+        carry = x[j] >> 8;
+        x[j] &= 0xff;
+    }
+
+    for j in 0..32 {
+        x[j] -= carry * ORDER[j];
+    }
+
+    let mut r = [0; 32];
+
+    for i in 0..32 {
+        x[i + 1] += x[i] >> 8;
+        r[i] = (x[i] & 0xff) as u8;
+    }
+
+    r
+}
+
+#[cfg(test)]
+mod tests {
+    use super::verify;
+    use crate::crypto::from_hex;
+
+    /// Test vectors of _RFC 8032_ (section 7.1), as `(public key, message, signature)`.
+    const RFC8032_VECTORS: [(&str, &[u8], &str); 3] = [
+        (
+            "d75a980182b10ab7d54bfed3c964073a0ee172f3daa62325af021a68f707511a",
+            b"",
+            "e5564300c360ac729086e2cc806e828a84877f1eb8e5d974d873e06522490155\
+             5fb8821590a33bacc61e39701cf9b46bd25bf5f0595bbe24655141438e7a100b",
+        ),
+        (
+            "3d4017c3e843895a92b70aa74d1b7ebc9c982ccf2ec4968cc0cd55f12af4660c",
+            b"\x72",
+            "92a009a9f0d4cab8720e820b5f642540a2b27b5416503f8fb3762223ebdb69da\
+             085ac1e43e15996e458f3613d0f11d8c387b2eaeb4302aeeb00d291612bb0c00",
+        ),
+        (
+            "fc51cd8e6218a1a38da47ed00230f0580816ed13ba3303ac5deb911548908025",
+            b"\xaf\x82",
+            "6291d657deec24024827e69c3abe01a30ce548a284743a445e3680d7db5ac3ac\
+             18ff9b538d16f290ae67f760984dc6594a7c15e9716ed28dc027beceea1ec40a",
+        ),
+    ];
+
+    #[test]
+    fn accepts_rfc8032_signatures() {
+        for (public_key, message, signature) in RFC8032_VECTORS {
+            assert!(verify(&from_hex(public_key), message, &from_hex(signature)));
+        }
+    }
+
+    #[test]
+    fn rejects_tampered_signatures() {
+        for (public_key, message, signature) in RFC8032_VECTORS {
+            let public_key = from_hex(public_key);
+            let signature = from_hex(signature);
+
+            let mut tampered_message = [0u8; 3];
+            tampered_message[..message.len()].copy_from_slice(message);
+            assert!(!verify(
+                &public_key,
+                &tampered_message[..=message.len()],
+                &signature
+            ));
+
+            for byte in [0, 31, 32, 63] {
+                let mut tampered_signature = signature;
+                tampered_signature[byte] ^= 0x01;
+                assert!(!verify(&public_key, message, &tampered_signature));
+            }
+
+            let mut tampered_key = public_key;
+            tampered_key[0] ^= 0x01;
+            assert!(!verify(&tampered_key, message, &signature));
+        }
+    }
+
+    #[test]
+    fn rejects_non_canonical_scalars() {
+        let (public_key, message, _) = RFC8032_VECTORS[0];
+
+        // signature of the first test vector, with the order of the base point added to `s`
+        let signature = from_hex(
+            "e5564300c360ac729086e2cc806e828a84877f1eb8e5d974d873e06522490155\
+             4c8c7872aa064e049dbb3013fbf29380d25bf5f0595bbe24655141438e7a101b",
+        );
+
+        assert!(!verify(&from_hex(public_key), message, &signature));
+    }
+}
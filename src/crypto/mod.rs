@@ -0,0 +1,22 @@
+//! Cryptographic primitives used to verify the integrity of boot images.
+//!
+//! Only what is required to check signatures is implemented: constant-time behaviour is not a
+//! goal, as no secret is ever handled by the bootloader.
+
+pub mod ed25519;
+pub mod sha256;
+pub mod sha512;
+
+/// Decodes a hexadecimal test vector.
+#[cfg(test)]
+pub(crate) fn from_hex<const N: usize>(text: &str) -> [u8; N] {
+    let mut bytes = [0; N];
+
+    assert_eq!(text.len(), 2 * N, "invalid test vector length");
+
+    for (byte, digits) in bytes.iter_mut().zip(text.as_bytes().chunks_exact(2)) {
+        *byte = u8::from_str_radix(core::str::from_utf8(digits).unwrap(), 16).unwrap();
+    }
+
+    bytes
+}
@@ -0,0 +1,290 @@
+//! `SHA-256` hash function (_FIPS 180-4_).
+
+/// Size of a digest, in bytes.
+pub const SHA256_DIGEST_SIZE: usize = 32;
+
+/// Size of the blocks processed by the compression function, in bytes.
+const BLOCK_SIZE: usize = 64;
+
+/// Initial hash value.
+const H0: [u32; 8] = [
+    0x6a09_e667,
+    0xbb67_ae85,
+    0x3c6e_f372,
+    0xa54f_f53a,
+    0x510e_527f,
+    0x9b05_688c,
+    0x1f83_d9ab,
+    0x5be0_cd19,
+];
+
+/// Round constants.
+const K: [u32; 64] = [
+    0x428a_2f98,
+    0x7137_4491,
+    0xb5c0_fbcf,
+    0xe9b5_dba5,
+    0x3956_c25b,
+    0x59f1_11f1,
+    0x923f_82a4,
+    0xab1c_5ed5,
+    0xd807_aa98,
+    0x1283_5b01,
+    0x2431_85be,
+    0x550c_7dc3,
+    0x72be_5d74,
+    0x80de_b1fe,
+    0x9bdc_06a7,
+    0xc19b_f174,
+    0xe49b_69c1,
+    0xefbe_4786,
+    0x0fc1_9dc6,
+    0x240c_a1cc,
+    0x2de9_2c6f,
+    0x4a74_84aa,
+    0x5cb0_a9dc,
+    0x76f9_88da,
+    0x983e_5152,
+    0xa831_c66d,
+    0xb003_27c8,
+    0xbf59_7fc7,
+    0xc6e0_0bf3,
+    0xd5a7_9147,
+    0x06ca_6351,
+    0x1429_2967,
+    0x27b7_0a85,
+    0x2e1b_2138,
+    0x4d2c_6dfc,
+    0x5338_0d13,
+    0x650a_7354,
+    0x766a_0abb,
+    0x81c2_c92e,
+    0x9272_2c85,
+    0xa2bf_e8a1,
+    0xa81a_664b,
+    0xc24b_8b70,
+    0xc76c_51a3,
+    0xd192_e819,
+    0xd699_0624,
+    0xf40e_3585,
+    0x106a_a070,
+    0x19a4_c116,
+    0x1e37_6c08,
+    0x2748_774c,
+    0x34b0_bcb5,
+    0x391c_0cb3,
+    0x4ed8_aa4a,
+    0x5b9c_ca4f,
+    0x682e_6ff3,
+    0x748f_82ee,
+    0x78a5_636f,
+    0x84c8_7814,
+    0x8cc7_0208,
+    0x90be_fffa,
+    0xa450_6ceb,
+    0xbef9_a3f7,
+    0xc671_78f2,
+];
+
+/// Incremental `SHA-256` hasher.
+///
+/// # Examples
+///
+/// ```
+/// use fzboot::crypto::sha256::{sha256, Sha256};
+///
+/// let mut hasher = Sha256::new();
+/// hasher.update(b"abc");
+///
+/// assert_eq!(hasher.finalize(), sha256(b"abc"));
+/// ```
+#[derive(Clone, Copy, Debug)]
+pub struct Sha256 {
+    state: [u32; 8],
+    block: [u8; BLOCK_SIZE],
+    block_len: usize,
+    length: u64,
+}
+
+impl Default for Sha256 {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Sha256 {
+    /// Creates a new hasher.
+    #[must_use]
+    pub fn new() -> Self {
+        Self {
+            state: H0,
+            block: [0; BLOCK_SIZE],
+            block_len: 0,
+            length: 0,
+        }
+    }
+
+    /// Hashes `data`, which may be split across several calls.
+    #[allow(clippy::missing_panics_doc)]
+    pub fn update(&mut self, mut data: &[u8]) {
+        self.length = self.length.wrapping_add(
+            u64::try_from(data.len())
+                .expect("invalid data length")
+                .wrapping_mul(8),
+        );
+
+        while !data.is_empty() {
+            let len = (BLOCK_SIZE - self.block_len).min(data.len());
+
+            self.block[self.block_len..self.block_len + len].copy_from_slice(&data[..len]);
+            self.block_len += len;
+            data = &data[len..];
+
+            if self.block_len == BLOCK_SIZE {
+                compress(&mut self.state, &self.block);
+                self.block_len = 0;
+            }
+        }
+    }
+
+    /// Pads the message, and returns its digest.
+    #[must_use]
+    pub fn finalize(mut self) -> [u8; SHA256_DIGEST_SIZE] {
+        let length = self.length;
+
+        self.block[self.block_len] = 0x80;
+        self.block[self.block_len + 1..].fill(0);
+
+        if self.block_len >= BLOCK_SIZE - 8 {
+            compress(&mut self.state, &self.block);
+            self.block.fill(0);
+        }
+
+        self.block[BLOCK_SIZE - 8..].copy_from_slice(&length.to_be_bytes());
+        compress(&mut self.state, &self.block);
+
+        let mut digest = [0; SHA256_DIGEST_SIZE];
+
+        for (chunk, word) in digest.chunks_exact_mut(4).zip(self.state) {
+            chunk.copy_from_slice(&word.to_be_bytes());
+        }
+
+        digest
+    }
+}
+
+/// Returns the `SHA-256` digest of `data`.
+#[must_use]
+pub fn sha256(data: &[u8]) -> [u8; SHA256_DIGEST_SIZE] {
+    let mut hasher = Sha256::new();
+    hasher.update(data);
+    hasher.finalize()
+}
+
+/// Processes a single block.
+#[allow(clippy::many_single_char_names)]
+fn compress(state: &mut [u32; 8], block: &[u8; BLOCK_SIZE]) {
+    let mut w = [0u32; 64];
+
+    for (word, chunk) in w.iter_mut().zip(block.chunks_exact(4)) {
+        *word = u32::from_be_bytes(chunk.try_into().expect("invalid block size"));
+    }
+
+    for i in 16..64 {
+        let s0 = w[i - 15].rotate_right(7) ^ w[i - 15].rotate_right(18) ^ (w[i - 15] >> 3);
+        let s1 = w[i - 2].rotate_right(17) ^ w[i - 2].rotate_right(19) ^ (w[i - 2] >> 10);
+
+        w[i] = w[i - 16]
+            .wrapping_add(s0)
+            .wrapping_add(w[i - 7])
+            .wrapping_add(s1);
+    }
+
+    let [mut a, mut b, mut c, mut d, mut e, mut f, mut g, mut h] = *state;
+
+    for (k, w) in K.iter().zip(w) {
+        let s1 = e.rotate_right(6) ^ e.rotate_right(11) ^ e.rotate_right(25);
+        let ch = (e & f) ^ (!e & g);
+        let t1 = h
+            .wrapping_add(s1)
+            .wrapping_add(ch)
+            .wrapping_add(*k)
+            .wrapping_add(w);
+        let s0 = a.rotate_right(2) ^ a.rotate_right(13) ^ a.rotate_right(22);
+        let maj = (a & b) ^ (a & c) ^ (b & c);
+        let t2 = s0.wrapping_add(maj);
+
+        h = g;
+        g = f;
+        f = e;
+        e = d.wrapping_add(t1);
+        d = c;
+        c = b;
+        b = a;
+        a = t1.wrapping_add(t2);
+    }
+
+    for (word, value) in state.iter_mut().zip([a, b, c, d, e, f, g, h]) {
+        *word = word.wrapping_add(value);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{sha256, Sha256};
+    use crate::crypto::from_hex;
+
+    #[test]
+    fn matches_fips_test_vectors() {
+        let vectors: [(&[u8], &str); 3] = [
+            (
+                b"",
+                "e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855",
+            ),
+            (
+                b"abc",
+                "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad",
+            ),
+            (
+                b"abcdbcdecdefdefgefghfghighijhijkijkljklmklmnlmnomnopnopq",
+                "248d6a61d20638b8e5c026930c3e6039a33ce45964ff2167f6ecedd419db06c1",
+            ),
+        ];
+
+        for (data, digest) in vectors {
+            assert_eq!(sha256(data), from_hex(digest));
+        }
+    }
+
+    #[test]
+    fn hashes_data_split_across_updates() {
+        let mut hasher = Sha256::new();
+
+        for _ in 0..1000 {
+            hasher.update(&[b'a'; 1000]);
+        }
+
+        assert_eq!(
+            hasher.finalize(),
+            from_hex("cdc76e5c9914fb9281a1c7e284d73e67f1809a48a497200e046d39ccc7112cd0")
+        );
+
+        let mut data = [0u8; 768];
+
+        for (index, byte) in data.iter_mut().enumerate() {
+            *byte = u8::try_from(index % 256).unwrap();
+        }
+
+        for split in [0, 1, 55, 56, 63, 64, 65, 300] {
+            let mut hasher = Sha256::new();
+            hasher.update(&data[..split]);
+            hasher.update(&data[split..]);
+
+            assert_eq!(
+                hasher.finalize(),
+                from_hex("f3a25aa93aa2fbba28d79260535bbd6a5eb0fc1c24a8b0f04e12b484c1dfe363"),
+                "split at {split}"
+            );
+        }
+    }
+}
@@ -565,6 +565,10 @@ impl<T: Debug + PrimInt> Vob<T> {
         R: RangeBounds<usize>,
     {
         let valid_range = self.process_range(range);
+        if valid_range.is_empty() {
+            return;
+        }
+
         let first_blk_id = block_offset::<T>(valid_range.start);
         let first_blk = self.vec[first_blk_id];
         let first_blk_off = valid_range.start % bits_per_block::<T>();
@@ -574,9 +578,8 @@ impl<T: Debug + PrimInt> Vob<T> {
         if first_blk_bits_to_set == bits_per_block::<T>() {
             self.vec[first_blk_id] = T::zero();
         } else {
-            self.vec[first_blk_id] = T::zero()
-                | (first_blk & ((T::one() << first_blk_off) - T::one()))
-                | (first_blk & !((T::one() << first_blk_bits_to_set) - T::one()));
+            self.vec[first_blk_id] =
+                first_blk & !(((T::one() << first_blk_bits_to_set) - T::one()) << first_blk_off);
         }
 
         let blk_to_set = (valid_range.len() - first_blk_bits_to_set) / bits_per_block::<T>();
@@ -601,6 +604,10 @@ impl<T: Debug + PrimInt> Vob<T> {
         R: RangeBounds<usize>,
     {
         let valid_range = self.process_range(range);
+        if valid_range.is_empty() {
+            return;
+        }
+
         let first_blk_id = block_offset::<T>(valid_range.start);
         let first_blk = self.vec[first_blk_id];
         let first_blk_off = valid_range.start % bits_per_block::<T>();
@@ -610,11 +617,8 @@ impl<T: Debug + PrimInt> Vob<T> {
         if first_blk_bits_to_set == bits_per_block::<T>() {
             self.vec[first_blk_id] = T::max_value();
         } else {
-            self.vec[first_blk_id] = (T::max_value()
-                & !((T::one() << first_blk_off) - T::one())
-                & ((T::one() << first_blk_bits_to_set) - T::one()))
-                | (first_blk & ((T::one() << first_blk_off) - T::one()))
-                | (first_blk & !((T::one() << first_blk_bits_to_set) - T::one()));
+            self.vec[first_blk_id] =
+                first_blk | (((T::one() << first_blk_bits_to_set) - T::one()) << first_blk_off);
         }
 
         let blk_to_set = (valid_range.len() - first_blk_bits_to_set) / bits_per_block::<T>();
@@ -623,7 +627,7 @@ impl<T: Debug + PrimInt> Vob<T> {
         }
 
         let remaining_bits =
-            valid_range.len() - first_blk_bits_to_set - 8 * blk_to_set * bits_per_block::<T>();
+            valid_range.len() - first_blk_bits_to_set - blk_to_set * bits_per_block::<T>();
 
         if remaining_bits == 0 {
             return;
@@ -1556,3 +1560,90 @@ impl<T: Debug + PrimInt> core::ops::BitXor<&Vob<T>> for Vob<T> {
         self
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const EDGES: [usize; 9] = [0, 1, 31, 32, 33, 63, 64, 65, 96];
+
+    /// Builds the expected result of a range operation bit by bit.
+    fn reference<T: Debug + PrimInt>(init: bool, len: usize, start: usize, end: usize) -> Vob<T> {
+        let mut v = Vob::<T>::from_elem_with_storage_type(init, len);
+        for i in start..end {
+            v.set(i, !init);
+        }
+        v
+    }
+
+    fn check_ranges<T: Debug + PrimInt>() {
+        let len = 130;
+        for &start in &EDGES {
+            for &end in EDGES.iter().filter(|&&end| end >= start) {
+                let mut set = Vob::<T>::from_elem_with_storage_type(false, len);
+                set.set_bit_range(start..end);
+                assert_eq!(set, reference(false, len, start, end), "set {start}..{end}");
+
+                let mut clear = Vob::<T>::from_elem_with_storage_type(true, len);
+                clear.clear_bit_range(start..end);
+                assert_eq!(
+                    clear,
+                    reference(true, len, start, end),
+                    "clear {start}..{end}"
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn bit_ranges_u32() {
+        check_ranges::<u32>();
+    }
+
+    #[test]
+    fn bit_ranges_u64() {
+        check_ranges::<u64>();
+    }
+
+    #[test]
+    fn empty_bit_range_is_noop() {
+        let mut v = Vob::<u32>::from_elem_with_storage_type(false, 64);
+        v.set_bit_range(32..32);
+        assert_eq!(v.iter_set_bits(..).next(), None);
+
+        let mut v = Vob::<u32>::from_elem_with_storage_type(true, 64);
+        v.clear_bit_range(32..32);
+        assert_eq!(v.iter_unset_bits(..).next(), None);
+    }
+
+    #[test]
+    fn bit_range_ending_on_block_boundary() {
+        let mut v = Vob::<u32>::from_elem_with_storage_type(false, 96);
+        v.set_bit_range(16..64);
+        assert!(v.iter_storage().eq([0xFFFF_0000, u32::MAX, 0]));
+
+        v.clear_bit_range(32..64);
+        assert!(v.iter_storage().eq([0xFFFF_0000, 0, 0]));
+    }
+
+    #[test]
+    fn iter_bits_across_blocks() {
+        let mut v = Vob::<u32>::from_elem_with_storage_type(false, 100);
+        v.set_bit_range(30..34);
+        v.set(63, true);
+        v.set(64, true);
+        v.set(99, true);
+
+        let set: Vec<usize> = v.iter_set_bits(..).collect();
+        assert_eq!(set, [30, 31, 32, 33, 63, 64, 99]);
+
+        let set: Vec<usize> = v.iter_set_bits(32..64).collect();
+        assert_eq!(set, [32, 33, 63]);
+
+        let unset: Vec<usize> = v.iter_unset_bits(28..36).collect();
+        assert_eq!(unset, [28, 29, 34, 35]);
+
+        assert_eq!(v.iter_set_bits(100..).next(), None);
+        assert_eq!(v.iter_set_bits(40..40).next(), None);
+    }
+}
@@ -7,6 +7,8 @@
 //! implementation of those method may depend on the physical controller to which the disk is linked.
 
 use crate::drivers::ahci::ahci_devices;
+#[cfg(test)]
+use crate::drivers::generics::mem_disk::memory_devices;
 use crate::drivers::ide::ata_pio::{ata_devices, AtaDevice, AtaIoRequest};
 use crate::drivers::ide::AtaDeviceIdentifier;
 use crate::fs::partitions::Partition;
@@ -32,6 +34,10 @@ pub struct SataDevice {
 pub enum SataDeviceType {
     IDE,
     AHCI,
+
+    /// Disk stored in memory, used by tests (see [`super::mem_disk::MemoryDisk`]).
+    #[cfg(test)]
+    Memory,
 }
 
 /// Returns a [`SataDevice`] structure encapsulating a physical disk device,
@@ -46,6 +52,11 @@ pub fn get_sata_drive(id: AtaDeviceIdentifier) -> Option<SataDevice> {
             identifier: id.clone(),
             inner: ahci_devices().read().get(&id)?.clone(),
         }),
+        #[cfg(test)]
+        SataDeviceType::Memory => Some(SataDevice {
+            identifier: id.clone(),
+            inner: memory_devices().read().get(&id)?.clone(),
+        }),
     }
 }
 
@@ -0,0 +1,145 @@
+//! Disk devices backed by memory, used to test filesystems against disk images.
+//!
+//! A [`MemoryDisk`] holds a _MBR_ partition table with a single partition, which contains the provided image. It is
+//! registered next to the physical devices, so that filesystems access it through the same [`DiskDevice`] interface.
+
+use core::cell::UnsafeCell;
+use core::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+
+use alloc::collections::BTreeMap;
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+use conquer_once::spin::OnceCell;
+use spin::{Mutex, RwLock};
+
+use crate::drivers::generics::dev_disk::{DiskDevice, SataDeviceType};
+use crate::drivers::ide::ata_command::AtaCommand;
+use crate::drivers::ide::ata_pio::{AtaIoRequest, AtaIoResult, AtaResult};
+use crate::drivers::ide::AtaDeviceIdentifier;
+use crate::fs::partitions::mbr::{load_drive_mbr, PartitionType};
+use crate::fs::partitions::Partition;
+use crate::video::vesa::init_text_buffer_in_memory;
+
+/// Size of a sector of a [`MemoryDisk`], in bytes.
+const SECTOR_SIZE: usize = 512;
+
+/// Offset of the partition table in the first sector of a [`MemoryDisk`].
+const MBR_PART_OFFSET: usize = 0x1BE;
+
+static LAST_MEMORY_DEVICE: AtomicUsize = AtomicUsize::new(0);
+
+pub(crate) fn memory_devices() -> &'static RwLock<BTreeMap<AtaDeviceIdentifier, Arc<MemoryDisk>>> {
+    static MEMORY_DEVICES: OnceCell<RwLock<BTreeMap<AtaDeviceIdentifier, Arc<MemoryDisk>>>> =
+        OnceCell::uninit();
+
+    MEMORY_DEVICES
+        .try_get_or_init(|| RwLock::new(BTreeMap::new()))
+        .unwrap()
+}
+
+/// Disk device whose sectors are stored in memory.
+pub(crate) struct MemoryDisk {
+    id: AtaDeviceIdentifier,
+    sectors: Mutex<Vec<u8>>,
+    partitions: UnsafeCell<Vec<Partition>>,
+}
+
+unsafe impl Send for MemoryDisk {}
+unsafe impl Sync for MemoryDisk {}
+
+impl MemoryDisk {
+    /// Registers a new disk, holding a single partition of type `part_type` which contains `image`.
+    ///
+    /// The filesystem stored in `image` is mounted, and the partition is returned.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the size of `image` is not a multiple of the sector size, or if its filesystem cannot be mounted.
+    pub(crate) fn with_partition(part_type: PartitionType, image: &[u8]) -> (Arc<Self>, Partition) {
+        assert_eq!(image.len() % SECTOR_SIZE, 0, "invalid image size");
+
+        // filesystems report mounts on the screen
+        init_text_buffer_in_memory();
+
+        let sectors_count = u32::try_from(image.len() / SECTOR_SIZE).expect("invalid image size");
+        let mut sectors = alloc::vec![0u8; SECTOR_SIZE];
+
+        // single partition entry, starting right after the partition table
+        let entry = &mut sectors[MBR_PART_OFFSET..MBR_PART_OFFSET + 16];
+        entry[4] = u8::from(part_type);
+        entry[8..12].copy_from_slice(&1u32.to_le_bytes());
+        entry[12..16].copy_from_slice(&sectors_count.to_le_bytes());
+        sectors[SECTOR_SIZE - 2..].copy_from_slice(&[0x55, 0xAA]);
+        sectors.extend_from_slice(image);
+
+        let device_id = LAST_MEMORY_DEVICE.fetch_add(1, Ordering::Relaxed);
+        let disk = Arc::new(Self {
+            id: AtaDeviceIdentifier::new(SataDeviceType::Memory, 0, device_id),
+            sectors: Mutex::new(sectors),
+            partitions: UnsafeCell::new(alloc::vec![]),
+        });
+
+        unsafe {
+            *disk.partitions.get() = load_drive_mbr(disk.as_ref(), 0).get_partitions();
+        }
+        memory_devices().write().insert(disk.id, disk.clone());
+
+        let mut partition = disk.partitions()[0].clone();
+        partition.load_fs().expect("failed to mount the image");
+
+        (disk, partition)
+    }
+
+    /// Returns a copy of the content of the partition stored on this disk.
+    pub(crate) fn image(&self) -> Vec<u8> {
+        self.sectors.lock()[SECTOR_SIZE..].to_vec()
+    }
+}
+
+impl DiskDevice for MemoryDisk {
+    fn read(&self, start_lba: u64, sectors_count: u16) -> AtaIoRequest {
+        let io_req = AtaIoRequest::new(AtomicBool::new(true));
+        let start = usize::try_from(start_lba).expect("invalid LBA") * SECTOR_SIZE;
+        let end = start + usize::from(sectors_count) * SECTOR_SIZE;
+
+        let data = self.sectors.lock().get(start..end).map(<[u8]>::to_vec);
+        *io_req.inner.result.lock() = Some(AtaIoResult {
+            result: AtaResult::Success,
+            command: AtaCommand::AtaReadSectors,
+            data,
+        });
+
+        io_req
+    }
+
+    fn write(&self, start_lba: u64, sectors_count: u16, data: Vec<u8>) -> AtaIoRequest {
+        let io_req = AtaIoRequest::new(AtomicBool::new(true));
+        let start = usize::try_from(start_lba).expect("invalid LBA") * SECTOR_SIZE;
+        let end = start + usize::from(sectors_count) * SECTOR_SIZE;
+
+        self.sectors.lock()[start..end].copy_from_slice(&data[..end - start]);
+        *io_req.inner.result.lock() = Some(AtaIoResult {
+            result: AtaResult::Success,
+            command: AtaCommand::AtaWriteSectors,
+            data: None,
+        });
+
+        io_req
+    }
+
+    fn partitions(&self) -> &Vec<Partition> {
+        unsafe { &(*self.partitions.get()) }
+    }
+
+    fn identifier(&self) -> AtaDeviceIdentifier {
+        self.id
+    }
+
+    fn max_sector(&self) -> usize {
+        self.sectors.lock().len() / SECTOR_SIZE - 1
+    }
+
+    fn logical_sector_size(&self) -> u64 {
+        u64::try_from(SECTOR_SIZE).expect("invalid sector size")
+    }
+}
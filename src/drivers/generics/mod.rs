@@ -1 +1,3 @@
 pub mod dev_disk;
+#[cfg(test)]
+pub(crate) mod mem_disk;
@@ -54,6 +54,14 @@ pub struct AtaIoResult {
     pub data: Option<Vec<u8>>,
 }
 
+impl AtaIoResult {
+    /// Checks if the command completed without error.
+    #[must_use]
+    pub fn is_success(&self) -> bool {
+        matches!(self.result, AtaResult::Success)
+    }
+}
+
 pub struct AtaIoRequest {
     pub(in crate::drivers) inner: Arc<AtaIoRequestInner>,
 }
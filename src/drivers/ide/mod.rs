@@ -92,6 +92,8 @@ impl Display for AtaDeviceIdentifier {
         let disk_type_str = match self.disk_type {
             SataDeviceType::IDE => "IDE",
             SataDeviceType::AHCI => "AHCI",
+            #[cfg(test)]
+            SataDeviceType::Memory => "memory",
         };
         f.write_fmt(format_args!(
             "ATA device   device_type = {}    controller_id = {}    device_id = {}",
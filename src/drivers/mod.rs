@@ -5,6 +5,8 @@ pub mod ahci;
 pub mod ide;
 #[cfg(feature = "alloc")]
 pub mod pci;
+#[cfg(feature = "alloc")]
+pub mod tpm;
 
 #[cfg(feature = "alloc")]
 pub mod generics;
@@ -0,0 +1,124 @@
+//! Encoding of the _TPM 2.0_ commands used by the bootloader.
+//!
+//! Every field is stored as big-endian. Commands start with a header made of a tag (whether the
+//! command has an authorization area), the size of the whole command and the command code.
+//! Responses start with a tag, their size and a response code, 0 on success.
+
+use alloc::vec::Vec;
+
+use crate::errors::TpmError;
+
+/// Size of the command and response headers.
+pub const HEADER_SIZE: usize = 10;
+
+/// Command without authorization area.
+const TPM_ST_NO_SESSIONS: u16 = 0x8001;
+
+/// Command with an authorization area.
+const TPM_ST_SESSIONS: u16 = 0x8002;
+
+/// `TPM2_Startup` command code.
+const TPM_CC_STARTUP: u32 = 0x144;
+
+/// `TPM2_PCR_Extend` command code.
+const TPM_CC_PCR_EXTEND: u32 = 0x182;
+
+/// Password authorization session, with an empty password.
+const TPM_RS_PW: u32 = 0x4000_0009;
+
+/// Identifier of the `SHA-256` hash algorithm.
+pub const TPM_ALG_SHA256: u16 = 0x000B;
+
+/// `TPM2_Startup` type resetting the _PCRs_.
+pub const TPM_SU_CLEAR: u16 = 0x0000;
+
+/// Response code returned when the _TPM_ has not been started.
+pub const TPM_RC_INITIALIZE: u32 = 0x100;
+
+/// Encodes a `TPM2_Startup` command.
+#[must_use]
+pub fn startup(startup_type: u16) -> Vec<u8> {
+    let mut command = header(TPM_ST_NO_SESSIONS, TPM_CC_STARTUP);
+    command.extend_from_slice(&startup_type.to_be_bytes());
+
+    finish(command)
+}
+
+/// Encodes a `TPM2_PCR_Extend` command, extending the `SHA-256` bank of `pcr` with `digest`.
+///
+/// The _PCRs_ extended by the bootloader do not require any authorization, so an empty password
+/// session is used.
+#[must_use]
+pub fn pcr_extend(pcr: u32, digest: &[u8; 32]) -> Vec<u8> {
+    let mut command = header(TPM_ST_SESSIONS, TPM_CC_PCR_EXTEND);
+    command.extend_from_slice(&pcr.to_be_bytes());
+
+    // authorization area: session handle, empty nonce, no attributes, empty password
+    command.extend_from_slice(&9u32.to_be_bytes());
+    command.extend_from_slice(&TPM_RS_PW.to_be_bytes());
+    command.extend_from_slice(&0u16.to_be_bytes());
+    command.push(0);
+    command.extend_from_slice(&0u16.to_be_bytes());
+
+    // list of digests
+    command.extend_from_slice(&1u32.to_be_bytes());
+    command.extend_from_slice(&TPM_ALG_SHA256.to_be_bytes());
+    command.extend_from_slice(digest);
+
+    finish(command)
+}
+
+/// Returns the size of a response, or command, from its header.
+///
+/// # Errors
+///
+/// Returns [`TpmError::InvalidResponse`] if the header is truncated, or if the size is smaller
+/// than the header itself.
+pub fn response_size(header: &[u8]) -> Result<usize, TpmError> {
+    let size = header
+        .get(2..6)
+        .and_then(|size| Some(u32::from_be_bytes(size.try_into().ok()?)))
+        .and_then(|size| usize::try_from(size).ok())
+        .ok_or(TpmError::InvalidResponse)?;
+
+    if size < HEADER_SIZE {
+        return Err(TpmError::InvalidResponse);
+    }
+
+    Ok(size)
+}
+
+/// Returns the response code of a response.
+///
+/// # Errors
+///
+/// Returns [`TpmError::InvalidResponse`] if the response is truncated.
+pub fn response_code(response: &[u8]) -> Result<u32, TpmError> {
+    if response_size(response)? != response.len() {
+        return Err(TpmError::InvalidResponse);
+    }
+
+    response
+        .get(6..HEADER_SIZE)
+        .and_then(|code| Some(u32::from_be_bytes(code.try_into().ok()?)))
+        .ok_or(TpmError::InvalidResponse)
+}
+
+/// Starts a command with its tag and command code, the size being set by [`finish`].
+fn header(tag: u16, command_code: u32) -> Vec<u8> {
+    let mut command = Vec::with_capacity(HEADER_SIZE);
+
+    command.extend_from_slice(&tag.to_be_bytes());
+    command.extend_from_slice(&0u32.to_be_bytes());
+    command.extend_from_slice(&command_code.to_be_bytes());
+
+    command
+}
+
+/// Sets the size of a command in its header.
+fn finish(mut command: Vec<u8>) -> Vec<u8> {
+    let size = u32::try_from(command.len()).expect("invalid TPM command size");
+    command[2..6].copy_from_slice(&size.to_be_bytes());
+
+    command
+}
@@ -0,0 +1,180 @@
+//! Command Response Buffer (_CRB_) interface, as defined by the _PC Client Platform TPM Profile_.
+//!
+//! Commands are written to a memory buffer whose address is given by the _TPM_ registers, and
+//! responses read from another one (usually the same).
+
+use core::ptr;
+
+use alloc::vec::Vec;
+
+use crate::{errors::TpmError, wait_for_or};
+
+use super::{command, read_reg, write_reg};
+
+/// Locality state register.
+const TPM_LOC_STATE: usize = 0x00;
+
+/// Locality control register.
+const TPM_LOC_CTRL: usize = 0x08;
+
+/// Locality status register.
+const TPM_LOC_STS: usize = 0x0C;
+
+/// Control area request register.
+const TPM_CRB_CTRL_REQ: usize = 0x40;
+
+/// Control area status register.
+const TPM_CRB_CTRL_STS: usize = 0x44;
+
+/// Command start register.
+const TPM_CRB_CTRL_START: usize = 0x4C;
+
+/// Command buffer size register.
+const TPM_CRB_CTRL_CMD_SIZE: usize = 0x58;
+
+/// Command buffer address register (low 32 bits).
+const TPM_CRB_CTRL_CMD_LADDR: usize = 0x5C;
+
+/// Command buffer address register (high 32 bits).
+const TPM_CRB_CTRL_CMD_HADDR: usize = 0x60;
+
+/// Response buffer size register.
+const TPM_CRB_CTRL_RSP_SIZE: usize = 0x64;
+
+/// Response buffer address register.
+const TPM_CRB_CTRL_RSP_ADDR: usize = 0x68;
+
+/// The other bits of [`TPM_LOC_STATE`] are valid.
+const LOC_STATE_VALID: u32 = 0x80;
+
+/// Requests the use of the locality.
+const LOC_CTRL_REQUEST_ACCESS: u32 = 0x1;
+
+/// Releases the locality.
+const LOC_CTRL_RELINQUISH: u32 = 0x2;
+
+/// The locality has been granted.
+const LOC_STS_GRANTED: u32 = 0x1;
+
+/// Requests the _TPM_ to get ready for a command.
+const CTRL_REQ_CMD_READY: u32 = 0x1;
+
+/// Requests the _TPM_ to go idle.
+const CTRL_REQ_GO_IDLE: u32 = 0x2;
+
+/// The _TPM_ is in a fatal error state.
+const CTRL_STS_ERROR: u32 = 0x1;
+
+/// Command Response Buffer interface registers.
+#[derive(Clone, Copy, Debug)]
+pub struct CrbInterface {
+    base: usize,
+}
+
+impl CrbInterface {
+    /// Checks that a _TPM_ answers at `base`.
+    pub(super) fn new(base: usize) -> Option<Self> {
+        let state = unsafe { read_reg::<u32>(base, TPM_LOC_STATE) };
+
+        if state == u32::MAX || state & LOC_STATE_VALID == 0 {
+            return None;
+        }
+
+        Some(Self { base })
+    }
+
+    /// Sends a command, and returns the response.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`TpmError::Timeout`] if the _TPM_ does not execute the command in time, and
+    /// [`TpmError::InvalidResponse`] if the response is malformed, or does not fit in the
+    /// response buffer.
+    pub fn submit(&mut self, command: &[u8]) -> Result<Vec<u8>, TpmError> {
+        unsafe { write_reg(self.base, TPM_LOC_CTRL, LOC_CTRL_REQUEST_ACCESS) };
+
+        wait_for_or!(
+            self.reg(TPM_LOC_STS) & LOC_STS_GRANTED != 0,
+            750,
+            return Err(TpmError::Timeout)
+        );
+
+        let response = self.transmit(command);
+
+        unsafe {
+            write_reg(self.base, TPM_CRB_CTRL_REQ, CTRL_REQ_GO_IDLE);
+            write_reg(self.base, TPM_LOC_CTRL, LOC_CTRL_RELINQUISH);
+        }
+
+        response
+    }
+
+    /// Writes a command to the command buffer, starts its execution, and reads the response.
+    fn transmit(&mut self, command: &[u8]) -> Result<Vec<u8>, TpmError> {
+        unsafe { write_reg(self.base, TPM_CRB_CTRL_REQ, CTRL_REQ_CMD_READY) };
+
+        wait_for_or!(
+            self.reg(TPM_CRB_CTRL_REQ) & CTRL_REQ_CMD_READY == 0,
+            750,
+            return Err(TpmError::Timeout)
+        );
+
+        if self.reg(TPM_CRB_CTRL_STS) & CTRL_STS_ERROR != 0 {
+            return Err(TpmError::InvalidResponse);
+        }
+
+        let command_addr = u64::from(self.reg(TPM_CRB_CTRL_CMD_LADDR))
+            | (u64::from(self.reg(TPM_CRB_CTRL_CMD_HADDR)) << 32);
+        let command_size = self.buffer_size(TPM_CRB_CTRL_CMD_SIZE)?;
+        let response_addr = unsafe { read_reg::<u64>(self.base, TPM_CRB_CTRL_RSP_ADDR) };
+        let response_size = self.buffer_size(TPM_CRB_CTRL_RSP_SIZE)?;
+
+        if command.len() > command_size {
+            return Err(TpmError::InvalidResponse);
+        }
+
+        let command_buffer = buffer_ptr(command_addr)?;
+        let response_buffer = buffer_ptr(response_addr)?;
+
+        unsafe {
+            for (i, &byte) in command.iter().enumerate() {
+                ptr::write_volatile(command_buffer.add(i), byte);
+            }
+
+            write_reg(self.base, TPM_CRB_CTRL_START, 1u32);
+        }
+
+        wait_for_or!(
+            self.reg(TPM_CRB_CTRL_START) == 0,
+            2000,
+            return Err(TpmError::Timeout)
+        );
+
+        let read = |offset: usize| unsafe { ptr::read_volatile(response_buffer.add(offset)) };
+        let header: Vec<u8> = (0..command::HEADER_SIZE).map(read).collect();
+        let size = command::response_size(&header)?;
+
+        if size > response_size {
+            return Err(TpmError::InvalidResponse);
+        }
+
+        Ok((0..size).map(read).collect())
+    }
+
+    /// Reads the size of the command or response buffer.
+    fn buffer_size(self, reg: usize) -> Result<usize, TpmError> {
+        usize::try_from(self.reg(reg)).map_err(|_| TpmError::InvalidResponse)
+    }
+
+    fn reg(self, reg: usize) -> u32 {
+        unsafe { read_reg(self.base, reg) }
+    }
+}
+
+/// Returns a pointer to a command or response buffer.
+#[allow(clippy::as_conversions)]
+fn buffer_ptr(addr: u64) -> Result<*mut u8, TpmError> {
+    usize::try_from(addr)
+        .map(|addr| addr as *mut u8)
+        .map_err(|_| TpmError::InvalidResponse)
+}
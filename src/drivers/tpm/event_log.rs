@@ -0,0 +1,95 @@
+//! _TCG_ event log, in the crypto agile format of the _PC Client Platform Firmware Profile_.
+//!
+//! The log starts with a `TCG_PCClientPCREvent` structure of type [`EV_NO_ACTION`], whose data is
+//! the `Spec ID Event03` header listing the hash algorithms used by the log (`SHA-256` only).
+//! Each measurement is then recorded as a `TCG_PCR_EVENT2` structure:
+//!
+//! | Size     | Description                                         |
+//! |----------|-----------------------------------------------------|
+//! | 4        | Index of the extended _PCR_                         |
+//! | 4        | Event type                                          |
+//! | 4        | Number of digests (always 1)                        |
+//! | 2        | Hash algorithm of the digest (`TPM_ALG_SHA256`)     |
+//! | 32       | Digest of the measured data                         |
+//! | 4        | Size of the event data                              |
+//! | variable | Event data, describing what has been measured       |
+//!
+//! Every field is stored as little-endian.
+
+use alloc::vec::Vec;
+
+use super::command::TPM_ALG_SHA256;
+
+/// Event which is not extended into any _PCR_, used for the log header.
+pub const EV_NO_ACTION: u32 = 0x3;
+
+/// Event used by the bootloader to measure the images it loads, and the kernel command line.
+pub const EV_IPL: u32 = 0xD;
+
+/// Signature of the log header.
+const SPEC_ID_SIGNATURE: &[u8; 16] = b"Spec ID Event03\0";
+
+/// Size of a `SHA-256` digest.
+const SHA256_DIGEST_SIZE: u16 = 32;
+
+/// In-memory event log.
+#[derive(Clone, Debug, Default)]
+pub struct EventLog {
+    data: Vec<u8>,
+}
+
+impl EventLog {
+    /// Creates an empty log. The header is written along with the first event.
+    #[must_use]
+    pub const fn new() -> Self {
+        Self { data: Vec::new() }
+    }
+
+    /// Records an event, whose data has been measured into `pcr` with the `SHA-256` `digest`.
+    pub fn append(&mut self, pcr: u32, event_type: u32, digest: &[u8; 32], event: &[u8]) {
+        if self.data.is_empty() {
+            self.append_header();
+        }
+
+        self.data.extend_from_slice(&pcr.to_le_bytes());
+        self.data.extend_from_slice(&event_type.to_le_bytes());
+        self.data.extend_from_slice(&1u32.to_le_bytes());
+        self.data.extend_from_slice(&TPM_ALG_SHA256.to_le_bytes());
+        self.data.extend_from_slice(digest);
+        self.push_event_data(event);
+    }
+
+    /// Returns the content of the log, empty if no event has been recorded.
+    #[must_use]
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.data
+    }
+
+    /// Writes the `Spec ID Event03` header.
+    fn append_header(&mut self) {
+        let mut spec_id = Vec::new();
+
+        spec_id.extend_from_slice(SPEC_ID_SIGNATURE);
+        // platform class (client), specification version 2.0 errata 0, 64-bit `UINTN`
+        spec_id.extend_from_slice(&0u32.to_le_bytes());
+        spec_id.extend_from_slice(&[0, 2, 0, 2]);
+        spec_id.extend_from_slice(&1u32.to_le_bytes());
+        spec_id.extend_from_slice(&TPM_ALG_SHA256.to_le_bytes());
+        spec_id.extend_from_slice(&SHA256_DIGEST_SIZE.to_le_bytes());
+        // no vendor information
+        spec_id.push(0);
+
+        self.data.extend_from_slice(&0u32.to_le_bytes());
+        self.data.extend_from_slice(&EV_NO_ACTION.to_le_bytes());
+        self.data.extend_from_slice(&[0; 20]);
+        self.push_event_data(&spec_id);
+    }
+
+    /// Writes the size of the event data, followed by the data itself.
+    fn push_event_data(&mut self, event: &[u8]) {
+        let size = u32::try_from(event.len()).expect("invalid event size");
+
+        self.data.extend_from_slice(&size.to_le_bytes());
+        self.data.extend_from_slice(event);
+    }
+}
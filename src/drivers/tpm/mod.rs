@@ -0,0 +1,199 @@
+//! _TPM 2.0_ driver, used to measure the images loaded by the bootloader.
+//!
+//! The _TPM_ is accessed through the locality 0 registers located at [`TPM_BASE_ADDR`], using
+//! either the _FIFO_ interface (see [`tis`]) or the Command Response Buffer interface (see
+//! [`crb`]), depending on the one reported by the _TPM_.
+//!
+//! Each measurement extends the `SHA-256` bank of a _PCR_ (see [`command`]), and is recorded in
+//! the event log (see [`event_log`]), which is handed out to the kernel so that it can replay the
+//! measurements.
+
+pub mod command;
+pub mod crb;
+pub mod event_log;
+pub mod tis;
+
+use core::ptr;
+
+use alloc::vec::Vec;
+use conquer_once::spin::OnceCell;
+use spin::Mutex;
+
+use crate::{
+    crypto::sha256::sha256,
+    errors::{CanFail, TpmError},
+    info,
+};
+
+use self::{
+    command::{TPM_RC_INITIALIZE, TPM_SU_CLEAR},
+    crb::CrbInterface,
+    event_log::EventLog,
+    tis::TisInterface,
+};
+
+/// Physical address of the locality 0 registers.
+pub const TPM_BASE_ADDR: usize = 0xFED4_0000;
+
+/// Offset of the interface identifier register, shared by both interfaces.
+const INTERFACE_ID: usize = 0x30;
+
+/// Interface type reported by _FIFO_ interfaces.
+const INTERFACE_TYPE_FIFO: u32 = 0x0;
+
+/// Interface type reported by Command Response Buffer interfaces.
+const INTERFACE_TYPE_CRB: u32 = 0x1;
+
+/// Interface type reported by _TIS 1.3_ compatible interfaces.
+const INTERFACE_TYPE_TIS: u32 = 0xF;
+
+/// Shared [`Tpm`], available after its initialization using [`tpm_init`].
+pub static TPM: OnceCell<Mutex<Tpm>> = OnceCell::uninit();
+
+/// Event log of every measurement made by [`measure`].
+pub static EVENT_LOG: Mutex<EventLog> = Mutex::new(EventLog::new());
+
+/// Initializes the shared [`Tpm`], if one is available.
+pub fn tpm_init() {
+    if let Some(tpm) = Tpm::probe() {
+        info!("tpm", "found TPM ({} interface)", tpm.interface_name());
+        TPM.init_once(|| Mutex::new(tpm));
+    } else {
+        info!("tpm", "no TPM available");
+    }
+}
+
+/// Measures `data` into `pcr`, and records the measurement in the [`EVENT_LOG`], along with its
+/// type and description.
+///
+/// # Errors
+///
+/// Returns [`TpmError::NotPresent`] if no _TPM_ has been initialized, or any error raised while
+/// extending the _PCR_.
+pub fn measure(pcr: u32, event_type: u32, data: &[u8], event: &[u8]) -> CanFail<TpmError> {
+    let tpm = TPM.get().ok_or(TpmError::NotPresent)?;
+    let digest = sha256(data);
+
+    tpm.lock().pcr_extend(pcr, &digest)?;
+    EVENT_LOG.lock().append(pcr, event_type, &digest, event);
+
+    Ok(())
+}
+
+/// Register interface used to communicate with the _TPM_.
+#[derive(Clone, Copy, Debug)]
+pub enum TpmInterface {
+    /// _FIFO_ interface.
+    Tis(TisInterface),
+
+    /// Command Response Buffer interface.
+    Crb(CrbInterface),
+}
+
+/// A _TPM 2.0_ device.
+#[derive(Clone, Copy, Debug)]
+pub struct Tpm {
+    interface: TpmInterface,
+}
+
+impl Tpm {
+    /// Looks for a _TPM_ at [`TPM_BASE_ADDR`].
+    ///
+    /// Returns `None` if no _TPM_ answers, or if it uses an unknown interface.
+    #[must_use]
+    pub fn probe() -> Option<Self> {
+        let interface_id = unsafe { read_reg::<u32>(TPM_BASE_ADDR, INTERFACE_ID) };
+
+        let interface = match interface_id & 0xF {
+            INTERFACE_TYPE_FIFO | INTERFACE_TYPE_TIS => {
+                TpmInterface::Tis(TisInterface::new(TPM_BASE_ADDR)?)
+            }
+            INTERFACE_TYPE_CRB => TpmInterface::Crb(CrbInterface::new(TPM_BASE_ADDR)?),
+            _ => return None,
+        };
+
+        Some(Self { interface })
+    }
+
+    /// Returns the name of the interface used to communicate with the _TPM_.
+    #[must_use]
+    pub fn interface_name(&self) -> &'static str {
+        match self.interface {
+            TpmInterface::Tis(_) => "FIFO",
+            TpmInterface::Crb(_) => "CRB",
+        }
+    }
+
+    /// Sends a command to the _TPM_, and returns its response.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`TpmError::Timeout`] if the _TPM_ does not execute the command in time, and
+    /// [`TpmError::InvalidResponse`] if the response is malformed.
+    pub fn submit(&mut self, command: &[u8]) -> Result<Vec<u8>, TpmError> {
+        match &mut self.interface {
+            TpmInterface::Tis(tis) => tis.submit(command),
+            TpmInterface::Crb(crb) => crb.submit(command),
+        }
+    }
+
+    /// Sends a command to the _TPM_, and checks its response code.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`TpmError::ResponseCode`] if the _TPM_ fails to execute the command, or any error
+    /// raised by [`Tpm::submit`].
+    pub fn execute(&mut self, command: &[u8]) -> Result<Vec<u8>, TpmError> {
+        let response = self.submit(command)?;
+
+        match command::response_code(&response)? {
+            0 => Ok(response),
+            code => Err(TpmError::ResponseCode(code)),
+        }
+    }
+
+    /// Extends the `SHA-256` bank of a _PCR_ with `digest`.
+    ///
+    /// If the _TPM_ has not been started by the firmware, it is started first.
+    ///
+    /// # Errors
+    ///
+    /// Returns any error raised by [`Tpm::execute`].
+    pub fn pcr_extend(&mut self, pcr: u32, digest: &[u8; 32]) -> CanFail<TpmError> {
+        let extend = command::pcr_extend(pcr, digest);
+
+        match self.execute(&extend) {
+            Err(TpmError::ResponseCode(TPM_RC_INITIALIZE)) => {
+                info!("tpm", "TPM not started by the firmware, starting it");
+
+                self.execute(&command::startup(TPM_SU_CLEAR))?;
+                self.execute(&extend)?;
+            }
+            result => {
+                result?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Reads a register of the _TPM_.
+///
+/// # Safety
+///
+/// `base + offset` must be the address of a _TPM_ register of type `T`.
+#[allow(clippy::as_conversions)]
+unsafe fn read_reg<T: Copy>(base: usize, offset: usize) -> T {
+    ptr::read_volatile((base + offset) as *const T)
+}
+
+/// Writes a register of the _TPM_.
+///
+/// # Safety
+///
+/// `base + offset` must be the address of a _TPM_ register of type `T`.
+#[allow(clippy::as_conversions)]
+unsafe fn write_reg<T: Copy>(base: usize, offset: usize, value: T) {
+    ptr::write_volatile((base + offset) as *mut T, value);
+}
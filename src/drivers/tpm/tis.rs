@@ -0,0 +1,185 @@
+//! _FIFO_ interface (_TPM Interface Specification_, as defined by the _PC Client Platform TPM
+//! Profile_).
+//!
+//! Commands are written to, and responses read from, a single data register, by chunks whose
+//! size is given by the `burstCount` field of the status register.
+
+use alloc::vec::Vec;
+
+use crate::{errors::TpmError, wait_for_or};
+
+use super::{command, read_reg, write_reg};
+
+/// Locality access register.
+const TPM_ACCESS: usize = 0x00;
+
+/// Status register.
+const TPM_STS: usize = 0x18;
+
+/// Data register.
+const TPM_DATA_FIFO: usize = 0x24;
+
+/// Vendor and device identifier register.
+const TPM_DID_VID: usize = 0xF00;
+
+/// The other bits of [`TPM_ACCESS`] are valid.
+const ACCESS_VALID: u8 = 0x80;
+
+/// The locality is active (reading), or released (writing).
+const ACCESS_ACTIVE_LOCALITY: u8 = 0x20;
+
+/// Requests the use of the locality.
+const ACCESS_REQUEST_USE: u8 = 0x02;
+
+/// The `Expect` and `dataAvail` bits of [`TPM_STS`] are valid.
+const STS_VALID: u32 = 0x80;
+
+/// The _TPM_ is ready to receive a command (reading), or aborts the current one (writing).
+const STS_COMMAND_READY: u32 = 0x40;
+
+/// Starts the execution of the command.
+const STS_GO: u32 = 0x20;
+
+/// Response bytes are available in the data register.
+const STS_DATA_AVAIL: u32 = 0x10;
+
+/// The _TPM_ expects more command bytes.
+const STS_EXPECT: u32 = 0x08;
+
+/// _FIFO_ interface registers.
+#[derive(Clone, Copy, Debug)]
+pub struct TisInterface {
+    base: usize,
+}
+
+impl TisInterface {
+    /// Checks that a _TPM_ answers at `base`.
+    pub(super) fn new(base: usize) -> Option<Self> {
+        let did_vid = unsafe { read_reg::<u32>(base, TPM_DID_VID) };
+        let access = unsafe { read_reg::<u8>(base, TPM_ACCESS) };
+
+        if did_vid == 0 || did_vid == u32::MAX || access & ACCESS_VALID == 0 {
+            return None;
+        }
+
+        Some(Self { base })
+    }
+
+    /// Sends a command, and returns the response.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`TpmError::Timeout`] if the _TPM_ does not execute the command in time, and
+    /// [`TpmError::InvalidResponse`] if the response is malformed.
+    pub fn submit(&mut self, command: &[u8]) -> Result<Vec<u8>, TpmError> {
+        self.request_locality()?;
+
+        let response = self.transmit(command);
+
+        unsafe {
+            write_reg(self.base, TPM_STS, STS_COMMAND_READY);
+            write_reg(self.base, TPM_ACCESS, ACCESS_ACTIVE_LOCALITY);
+        }
+
+        response
+    }
+
+    /// Requests the use of locality 0.
+    fn request_locality(&mut self) -> Result<(), TpmError> {
+        unsafe { write_reg(self.base, TPM_ACCESS, ACCESS_REQUEST_USE) };
+
+        let granted = ACCESS_VALID | ACCESS_ACTIVE_LOCALITY;
+
+        wait_for_or!(
+            self.access() & granted == granted,
+            750,
+            return Err(TpmError::Timeout)
+        );
+
+        Ok(())
+    }
+
+    /// Writes a command to the data register, starts its execution, and reads the response.
+    fn transmit(&mut self, command: &[u8]) -> Result<Vec<u8>, TpmError> {
+        unsafe { write_reg(self.base, TPM_STS, STS_COMMAND_READY) };
+
+        wait_for_or!(
+            self.status() & STS_COMMAND_READY != 0,
+            750,
+            return Err(TpmError::Timeout)
+        );
+
+        let mut written = 0;
+
+        while written < command.len() {
+            let burst = self.burst_count()?;
+
+            for &byte in command[written..].iter().take(burst) {
+                unsafe { write_reg(self.base, TPM_DATA_FIFO, byte) };
+            }
+
+            written = (written + burst).min(command.len());
+        }
+
+        wait_for_or!(
+            self.status() & STS_VALID != 0,
+            750,
+            return Err(TpmError::Timeout)
+        );
+
+        if self.status() & STS_EXPECT != 0 {
+            return Err(TpmError::InvalidResponse);
+        }
+
+        unsafe { write_reg(self.base, TPM_STS, STS_GO) };
+
+        let available = STS_VALID | STS_DATA_AVAIL;
+
+        wait_for_or!(
+            self.status() & available == available,
+            2000,
+            return Err(TpmError::Timeout)
+        );
+
+        let mut response = self.read_data(command::HEADER_SIZE)?;
+        let size = command::response_size(&response)?;
+        response.extend(self.read_data(size - command::HEADER_SIZE)?);
+
+        Ok(response)
+    }
+
+    /// Reads `len` bytes from the data register.
+    fn read_data(&mut self, len: usize) -> Result<Vec<u8>, TpmError> {
+        let mut data = Vec::with_capacity(len);
+
+        while data.len() < len {
+            let burst = self.burst_count()?.min(len - data.len());
+
+            for _ in 0..burst {
+                data.push(unsafe { read_reg::<u8>(self.base, TPM_DATA_FIFO) });
+            }
+        }
+
+        Ok(data)
+    }
+
+    /// Waits until the _TPM_ can transfer bytes, and returns how many of them can be transferred
+    /// without waiting again.
+    fn burst_count(self) -> Result<usize, TpmError> {
+        wait_for_or!(
+            (self.status() >> 8) & 0xFFFF != 0,
+            750,
+            return Err(TpmError::Timeout)
+        );
+
+        usize::try_from((self.status() >> 8) & 0xFFFF).map_err(|_| TpmError::InvalidResponse)
+    }
+
+    fn access(self) -> u8 {
+        unsafe { read_reg(self.base, TPM_ACCESS) }
+    }
+
+    fn status(self) -> u32 {
+        unsafe { read_reg(self.base, TPM_STS) }
+    }
+}
@@ -0,0 +1,228 @@
+//! exFAT boot sector, and boot region checksum.
+//!
+//! The main boot region of an exFAT volume spans its first 12 sectors: the boot sector, which
+//! describes the layout of the volume, 8 extended boot sectors, the OEM parameters, a reserved
+//! sector, and a last sector filled with the checksum of the 11 previous ones.
+
+use core::mem::size_of;
+
+use bytemuck::{Pod, Zeroable};
+
+/// Jump instruction stored at the start of the boot sector.
+const JUMP_BOOT: [u8; 3] = [0xEB, 0x76, 0x90];
+
+/// Name of the filesystem, stored after the jump instruction.
+const FILE_SYSTEM_NAME: [u8; 8] = *b"EXFAT   ";
+
+/// Offset of the boot sector signature.
+const BOOT_SIGNATURE_OFFSET: usize = 510;
+
+/// Signature stored at the end of the boot sector.
+const BOOT_SIGNATURE: [u8; 2] = [0x55, 0xAA];
+
+/// Number of sectors of the main boot region, checksum sector included.
+pub(crate) const BOOT_REGION_SECTORS: u32 = 12;
+
+/// Index of the checksum sector in the boot region.
+const CHECKSUM_SECTOR: usize = 11;
+
+/// Offsets of the boot sector bytes which are not covered by the checksum (`VolumeFlags` and
+/// `PercentInUse`), as they change while the volume is in use.
+const CHECKSUM_SKIPPED_BYTES: [usize; 3] = [106, 107, 112];
+
+/// Smallest valid sector size, as a power of two.
+const MIN_BYTES_PER_SECTOR_SHIFT: u8 = 9;
+
+/// Largest valid sector size, as a power of two.
+const MAX_BYTES_PER_SECTOR_SHIFT: u8 = 12;
+
+/// Largest valid cluster size, as a power of two.
+const MAX_CLUSTER_SIZE_SHIFT: u8 = 25;
+
+/// Largest valid number of clusters.
+const MAX_CLUSTERS_COUNT: u32 = 0xFFFF_FFF5;
+
+/// Smallest offset of the first FAT, in sectors.
+const MIN_FAT_OFFSET: u32 = 24;
+
+/// Set in the volume flags if the second FAT and allocation bitmap are the active ones.
+const VOLUME_FLAG_ACTIVE_FAT: u16 = 1 << 0;
+
+/// Boot sector of an exFAT volume, as stored on disk.
+#[derive(Clone, Copy, Debug, Pod, Zeroable)]
+#[repr(C, packed)]
+struct RawBootSector {
+    jump_boot: [u8; 3],
+    file_system_name: [u8; 8],
+    must_be_zero: [u8; 53],
+    partition_offset: u64,
+    volume_length: u64,
+    fat_offset: u32,
+    fat_length: u32,
+    cluster_heap_offset: u32,
+    cluster_count: u32,
+    first_cluster_of_root_directory: u32,
+    volume_serial_number: u32,
+    file_system_revision: u16,
+    volume_flags: u16,
+    bytes_per_sector_shift: u8,
+    sectors_per_cluster_shift: u8,
+    number_of_fats: u8,
+    drive_select: u8,
+    percent_in_use: u8,
+    reserved: [u8; 7],
+}
+
+/// Validated layout of an exFAT volume, read from its boot sector.
+///
+/// Every position is expressed in sectors (of [`BootSector::bytes_per_sector`] bytes), relative to
+/// the start of the volume.
+#[derive(Clone, Copy, Debug)]
+pub(crate) struct BootSector {
+    /// Size of a sector, in bytes.
+    pub(crate) bytes_per_sector: u32,
+
+    /// Size of a cluster, in sectors.
+    pub(crate) sectors_per_cluster: u32,
+
+    /// Total number of sectors of the volume.
+    pub(crate) volume_length: u64,
+
+    /// First sector of the first FAT.
+    pub(crate) fat_offset: u32,
+
+    /// Size of a single FAT, in sectors.
+    pub(crate) fat_length: u32,
+
+    /// First sector of the cluster heap, which starts with cluster 2.
+    pub(crate) cluster_heap_offset: u32,
+
+    /// Number of clusters of the cluster heap.
+    pub(crate) clusters_count: u32,
+
+    /// First cluster of the root directory.
+    pub(crate) root_cluster: u32,
+
+    /// Volume serial number.
+    pub(crate) volume_serial: u32,
+
+    /// Index of the active FAT and allocation bitmap (0 or 1).
+    pub(crate) active_fat: u32,
+}
+
+impl BootSector {
+    /// Parses and validates the boot sector of an exFAT volume.
+    ///
+    /// Returns `None` if the sector does not describe a consistent exFAT volume, which is the case
+    /// for volumes formatted with any other filesystem.
+    pub(crate) fn parse(sector: &[u8]) -> Option<Self> {
+        if sector.get(BOOT_SIGNATURE_OFFSET..BOOT_SIGNATURE_OFFSET + 2)? != BOOT_SIGNATURE {
+            return None;
+        }
+
+        let raw: RawBootSector =
+            bytemuck::pod_read_unaligned(sector.get(..size_of::<RawBootSector>())?);
+
+        // the zeroed area overlaps the BPB of FAT volumes, so that they cannot be mistaken for exFAT ones
+        if raw.jump_boot != JUMP_BOOT
+            || raw.file_system_name != FILE_SYSTEM_NAME
+            || raw.must_be_zero.iter().any(|&byte| byte != 0)
+            || raw.file_system_revision >> 8 != 1
+        {
+            return None;
+        }
+
+        let valid_geometry = (MIN_BYTES_PER_SECTOR_SHIFT..=MAX_BYTES_PER_SECTOR_SHIFT)
+            .contains(&raw.bytes_per_sector_shift)
+            && raw.bytes_per_sector_shift + raw.sectors_per_cluster_shift <= MAX_CLUSTER_SIZE_SHIFT
+            && matches!(raw.number_of_fats, 1 | 2);
+
+        if !valid_geometry {
+            return None;
+        }
+
+        let bytes_per_sector = 1u32 << raw.bytes_per_sector_shift;
+        let sectors_per_cluster = 1u32 << raw.sectors_per_cluster_shift;
+
+        // each FAT must be large enough to describe every cluster, and the regions must not overlap
+        let min_fat_length = (u64::from(raw.cluster_count) + 2) * 4;
+        let heap_start =
+            u64::from(raw.fat_offset) + u64::from(raw.fat_length) * u64::from(raw.number_of_fats);
+        let heap_end = u64::from(raw.cluster_heap_offset)
+            + u64::from(raw.cluster_count) * u64::from(sectors_per_cluster);
+
+        let consistent = raw.fat_offset >= MIN_FAT_OFFSET
+            && u64::from(raw.fat_length) * u64::from(bytes_per_sector) >= min_fat_length
+            && u64::from(raw.cluster_heap_offset) >= heap_start
+            && heap_end <= raw.volume_length
+            && raw.cluster_count <= MAX_CLUSTERS_COUNT
+            && raw.first_cluster_of_root_directory >= 2
+            && raw.first_cluster_of_root_directory - 2 < raw.cluster_count;
+
+        if !consistent {
+            return None;
+        }
+
+        Some(Self {
+            bytes_per_sector,
+            sectors_per_cluster,
+            volume_length: raw.volume_length,
+            fat_offset: raw.fat_offset,
+            fat_length: raw.fat_length,
+            cluster_heap_offset: raw.cluster_heap_offset,
+            clusters_count: raw.cluster_count,
+            root_cluster: raw.first_cluster_of_root_directory,
+            volume_serial: raw.volume_serial_number,
+            active_fat: u32::from(
+                raw.number_of_fats == 2 && raw.volume_flags & VOLUME_FLAG_ACTIVE_FAT != 0,
+            ),
+        })
+    }
+
+    /// Returns the size of a cluster, in bytes.
+    pub(crate) fn cluster_size(&self) -> u32 {
+        self.sectors_per_cluster * self.bytes_per_sector
+    }
+
+    /// Returns the first sector of the active FAT.
+    pub(crate) fn fat_start(&self) -> u32 {
+        self.fat_offset + self.active_fat * self.fat_length
+    }
+
+    /// Checks if `cluster` is a valid data cluster number.
+    pub(crate) fn is_valid_cluster(&self, cluster: u32) -> bool {
+        cluster >= 2 && cluster - 2 < self.clusters_count
+    }
+
+    /// Returns the first sector of a data cluster.
+    pub(crate) fn cluster_start(&self, cluster: u32) -> u64 {
+        u64::from(self.cluster_heap_offset)
+            + u64::from(cluster - 2) * u64::from(self.sectors_per_cluster)
+    }
+}
+
+/// Checks the checksum of the main boot region, whose 12 sectors are stored in `region`.
+///
+/// The checksum sector holds the checksum of the 11 previous sectors, repeated to fill the sector.
+pub(crate) fn verify_boot_checksum(region: &[u8], bytes_per_sector: usize) -> bool {
+    let Some(checked) = region.get(..CHECKSUM_SECTOR * bytes_per_sector) else {
+        return false;
+    };
+    let Some(checksum_sector) =
+        region.get(CHECKSUM_SECTOR * bytes_per_sector..(CHECKSUM_SECTOR + 1) * bytes_per_sector)
+    else {
+        return false;
+    };
+
+    let checksum = checked
+        .iter()
+        .enumerate()
+        .filter(|(offset, _)| !CHECKSUM_SKIPPED_BYTES.contains(offset))
+        .fold(0u32, |checksum, (_, &byte)| {
+            checksum.rotate_right(1).wrapping_add(u32::from(byte))
+        });
+
+    checksum_sector
+        .chunks_exact(4)
+        .all(|stored| stored == checksum.to_le_bytes())
+}
@@ -0,0 +1,534 @@
+//! `exFAT` directory-related structures
+//!
+//! A directory is a table of 32-byte entries, grouped in _entry sets_. Each file is described by a set made of a
+//! _file_ entry, holding its attributes, followed by a _stream extension_ entry, holding its size and location, and
+//! by one or more _file name_ entries, storing its name in UTF-16. The integrity of every set is protected by a
+//! checksum.
+//!
+//! The root directory also holds the entries locating the allocation bitmap and the up-case table of the volume,
+//! and its label. Unlike `FAT` directories, `exFAT` directories have no `.` and `..` entries.
+
+use alloc::boxed::Box;
+use alloc::{string::String, vec::Vec};
+
+use bytemuck::{Pod, Zeroable};
+
+use crate::errors::IOError;
+use crate::fs::exfat::file::ExFatFile;
+use crate::fs::exfat::{DataStream, LockedExFatFs};
+use crate::fs::{DirEntry, Directory, FsDirectory, IOResult};
+
+/// Size of a directory entry, in bytes.
+const DIR_ENTRY_SIZE: usize = 32;
+
+/// Type of the entry marking the end of the directory.
+const ENTRY_TYPE_END: u8 = 0x00;
+
+/// Set in the type of the entries which are in use.
+const ENTRY_TYPE_IN_USE: u8 = 0x80;
+
+/// Set in the type of benign entries, which can be ignored if they are not recognized.
+const ENTRY_TYPE_BENIGN: u8 = 0x20;
+
+/// Type of the allocation bitmap entry.
+const ENTRY_TYPE_ALLOCATION_BITMAP: u8 = 0x81;
+
+/// Type of the up-case table entry.
+const ENTRY_TYPE_UPCASE_TABLE: u8 = 0x82;
+
+/// Type of the volume label entry.
+const ENTRY_TYPE_VOLUME_LABEL: u8 = 0x83;
+
+/// Type of the file entry, first of a file entry set.
+const ENTRY_TYPE_FILE: u8 = 0x85;
+
+/// Type of the stream extension entry, following a file entry.
+const ENTRY_TYPE_STREAM_EXTENSION: u8 = 0xC0;
+
+/// Type of the file name entries, following a stream extension entry.
+const ENTRY_TYPE_FILE_NAME: u8 = 0xC1;
+
+/// The file is a directory.
+pub(crate) const ATTR_DIRECTORY: u16 = 0x10;
+
+/// Set in the flags of a stream extension entry if clusters are allocated to the stream.
+const FLAG_ALLOCATION_POSSIBLE: u8 = 1 << 0;
+
+/// Set in the flags of a stream extension entry if its clusters are contiguous, and not described by the FAT.
+const FLAG_NO_FAT_CHAIN: u8 = 1 << 1;
+
+/// Set in the flags of the allocation bitmap entry describing the second bitmap.
+const FLAG_SECOND_BITMAP: u8 = 1 << 0;
+
+/// Number of UTF-16 code units stored in a file name entry.
+const FILE_NAME_ENTRY_CHARS: usize = 15;
+
+/// Number of file name entries that an entry set can hold at most (255 characters).
+const MAX_FILE_NAME_ENTRIES: usize = 17;
+
+/// File entry, as stored on disk.
+#[derive(Clone, Copy, Debug, Pod, Zeroable)]
+#[repr(C, packed)]
+struct RawFileEntry {
+    entry_type: u8,
+    secondary_count: u8,
+    set_checksum: u16,
+    file_attributes: u16,
+    reserved1: u16,
+    create_timestamp: u32,
+    last_modified_timestamp: u32,
+    last_accessed_timestamp: u32,
+    create_10ms_increment: u8,
+    last_modified_10ms_increment: u8,
+    create_utc_offset: u8,
+    last_modified_utc_offset: u8,
+    last_accessed_utc_offset: u8,
+    reserved2: [u8; 7],
+}
+
+/// Stream extension entry, as stored on disk.
+#[derive(Clone, Copy, Debug, Pod, Zeroable)]
+#[repr(C, packed)]
+struct RawStreamExtensionEntry {
+    entry_type: u8,
+    flags: u8,
+    reserved1: u8,
+    name_length: u8,
+    name_hash: u16,
+    reserved2: u16,
+    valid_data_length: u64,
+    reserved3: u32,
+    first_cluster: u32,
+    data_length: u64,
+}
+
+/// File name entry, as stored on disk.
+#[derive(Clone, Copy, Debug, Pod, Zeroable)]
+#[repr(C, packed)]
+struct RawFileNameEntry {
+    entry_type: u8,
+    flags: u8,
+    file_name: [u16; FILE_NAME_ENTRY_CHARS],
+}
+
+/// Allocation bitmap entry, as stored on disk.
+#[derive(Clone, Copy, Debug, Pod, Zeroable)]
+#[repr(C, packed)]
+pub(crate) struct RawAllocationBitmapEntry {
+    entry_type: u8,
+    bitmap_flags: u8,
+    reserved: [u8; 18],
+    pub(crate) first_cluster: u32,
+    pub(crate) data_length: u64,
+}
+
+/// Up-case table entry, as stored on disk.
+#[derive(Clone, Copy, Debug, Pod, Zeroable)]
+#[repr(C, packed)]
+pub(crate) struct RawUpcaseTableEntry {
+    entry_type: u8,
+    reserved1: [u8; 3],
+    pub(crate) table_checksum: u32,
+    reserved2: [u8; 12],
+    pub(crate) first_cluster: u32,
+    pub(crate) data_length: u64,
+}
+
+/// Volume label entry, as stored on disk.
+#[derive(Clone, Copy, Debug, Pod, Zeroable)]
+#[repr(C, packed)]
+struct RawVolumeLabelEntry {
+    entry_type: u8,
+    character_count: u8,
+    volume_label: [u16; 11],
+    reserved: [u8; 8],
+}
+
+/// Entries of the root directory which describe the volume itself.
+#[derive(Clone, Debug, Default)]
+pub(crate) struct VolumeEntries {
+    /// Entry of the active allocation bitmap.
+    pub(crate) allocation_bitmap: Option<RawAllocationBitmapEntry>,
+
+    /// Entry of the up-case table.
+    pub(crate) upcase_table: Option<RawUpcaseTableEntry>,
+
+    /// Volume label, if set.
+    pub(crate) volume_label: Option<String>,
+}
+
+impl VolumeEntries {
+    /// Looks up the entries describing the volume in the content of its root directory.
+    ///
+    /// `active_fat` selects the allocation bitmap to use, if the volume has two of them.
+    pub(crate) fn scan(root_dir: &[u8], active_fat: u32) -> Self {
+        let mut entries = Self::default();
+
+        for raw_entry in root_dir.chunks_exact(DIR_ENTRY_SIZE) {
+            match raw_entry[0] {
+                ENTRY_TYPE_END => break,
+                ENTRY_TYPE_ALLOCATION_BITMAP => {
+                    let bitmap: RawAllocationBitmapEntry = bytemuck::pod_read_unaligned(raw_entry);
+
+                    if u32::from(bitmap.bitmap_flags & FLAG_SECOND_BITMAP) == active_fat {
+                        entries.allocation_bitmap = Some(bitmap);
+                    }
+                }
+                ENTRY_TYPE_UPCASE_TABLE => {
+                    entries.upcase_table = Some(bytemuck::pod_read_unaligned(raw_entry));
+                }
+                ENTRY_TYPE_VOLUME_LABEL => {
+                    let label: RawVolumeLabelEntry = bytemuck::pod_read_unaligned(raw_entry);
+                    let chars = label.volume_label;
+                    let len = usize::min(usize::from(label.character_count), chars.len());
+
+                    entries.volume_label = Some(decode_name(&chars[..len]));
+                }
+                _ => (),
+            }
+        }
+
+        entries
+    }
+}
+
+/// Decodes a UTF-16 name, replacing invalid characters.
+fn decode_name(units: &[u16]) -> String {
+    char::decode_utf16(units.iter().copied())
+        .map(|decoded| decoded.unwrap_or(char::REPLACEMENT_CHARACTER))
+        .collect()
+}
+
+/// Computes the checksum of an entry set, stored in its file entry.
+fn entry_set_checksum(entry_set: &[u8]) -> u16 {
+    entry_set
+        .iter()
+        .enumerate()
+        // the checksum itself is not covered
+        .filter(|(offset, _)| !matches!(offset, 2 | 3))
+        .fold(0u16, |checksum, (_, &byte)| {
+            checksum.rotate_right(1).wrapping_add(u16::from(byte))
+        })
+}
+
+/// Representation of a directory entry in the `exFAT` filesystem.
+#[derive(Clone)]
+pub(crate) struct ExFatDirectoryEntry {
+    fs: LockedExFatFs,
+
+    /// Name of this entry.
+    pub(crate) name: String,
+
+    /// Name of this entry, in UTF-16, as stored on disk.
+    pub(crate) name_units: Vec<u16>,
+
+    /// Hash of the name of this entry, computed from its uppercase version.
+    pub(crate) name_hash: u16,
+
+    /// Attributes of this entry.
+    pub(crate) attributes: u16,
+
+    /// Location and size of the content of this entry.
+    pub(crate) stream: DataStream,
+
+    /// Number of bytes of the content which have actually been written. Bytes past this length read as zeros.
+    pub(crate) valid_data_length: u64,
+
+    /// Directories containing this entry, starting from the root directory.
+    ancestors: Vec<DataStream>,
+}
+
+impl core::fmt::Debug for ExFatDirectoryEntry {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.write_fmt(format_args!(
+            "exfat directory entry | name = {}    attributes = {:#x}    cluster = {}    size = {}",
+            self.name, self.attributes, self.stream.first_cluster, self.stream.length
+        ))
+    }
+}
+
+impl ExFatDirectoryEntry {
+    /// Returns `true` if this entry is a directory.
+    pub(crate) fn is_dir(&self) -> bool {
+        self.attributes & ATTR_DIRECTORY != 0
+    }
+
+    /// Loads the directory described by this entry.
+    ///
+    /// The entry must have the [`ATTR_DIRECTORY`] attribute.
+    #[must_use]
+    pub(crate) fn as_directory(&self) -> Option<GenericExFatDirectory> {
+        if !self.is_dir() {
+            return None;
+        }
+
+        let dir = ExFatDirectory::from_stream(self.fs.clone(), self.stream, self.ancestors.clone())
+            .ok()?;
+
+        Some(GenericExFatDirectory { dir })
+    }
+
+    /// Loads the regular file described by this entry.
+    ///
+    /// The entry must not have the [`ATTR_DIRECTORY`] attribute.
+    #[must_use]
+    pub(crate) fn as_file(&self) -> Option<ExFatFile> {
+        if self.is_dir() {
+            return None;
+        }
+
+        ExFatFile::from_entry(self.fs.clone(), self).ok()
+    }
+}
+
+impl TryInto<DirEntry> for ExFatDirectoryEntry {
+    type Error = IOError;
+
+    fn try_into(self) -> Result<DirEntry, Self::Error> {
+        if self.is_dir() {
+            Ok(DirEntry::Directory(Box::new(
+                self.as_directory().ok_or(IOError::Unknown)?,
+            )))
+        } else {
+            Ok(DirEntry::File(Box::new(
+                self.as_file().ok_or(IOError::Unknown)?,
+            )))
+        }
+    }
+}
+
+/// Representation of a directory in the `exFAT` filesystem.
+///
+/// The whole directory table is read when the directory is loaded.
+#[derive(Clone)]
+pub(crate) struct ExFatDirectory {
+    fs: LockedExFatFs,
+
+    /// Location and size of the directory table.
+    pub(crate) stream: DataStream,
+
+    ancestors: Vec<DataStream>,
+    data: Vec<u8>,
+    internal_cursor: usize,
+}
+
+impl core::fmt::Debug for ExFatDirectory {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.write_fmt(format_args!(
+            "exfat directory | cluster = {}    root = {}    size = {}",
+            self.stream.first_cluster,
+            self.ancestors.is_empty(),
+            self.data.len()
+        ))
+    }
+}
+
+impl ExFatDirectory {
+    /// Loads the root directory of an `exFAT` filesystem.
+    ///
+    /// # Errors
+    ///
+    /// May return any variant of [`IOError`] in case of a failure while attempting to read from disk.
+    pub(crate) fn root(locked_fs: LockedExFatFs) -> IOResult<Self> {
+        let fs = locked_fs.read();
+        let stream = fs.root_stream()?;
+        drop(fs);
+
+        Self::from_stream(locked_fs, stream, Vec::new())
+    }
+
+    /// Loads a directory from the location of its table.
+    ///
+    /// `ancestors` are the directories containing this one, starting from the root directory.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`IOError::Unknown`] if the directory table is corrupted. May return any other variant of [`IOError`]
+    /// in case of a failure while attempting to read from disk.
+    pub(crate) fn from_stream(
+        locked_fs: LockedExFatFs,
+        stream: DataStream,
+        ancestors: Vec<DataStream>,
+    ) -> IOResult<Self> {
+        let fs = locked_fs.read();
+        let clusters = fs.stream_clusters(stream)?;
+        let mut data = fs.read_chain(&clusters)?;
+        drop(fs);
+
+        data.truncate(usize::try_from(stream.length).map_err(|_| IOError::Unknown)?);
+
+        Ok(Self {
+            fs: locked_fs,
+            stream,
+            ancestors,
+            data,
+            internal_cursor: 0,
+        })
+    }
+
+    /// Search this directory for a given name, compared case-insensitively (using the up-case table of the volume)
+    /// to the names of its entries.
+    ///
+    /// Returns the corresponding entry if available.
+    pub(crate) fn search(&mut self, name: &str) -> Option<ExFatDirectoryEntry> {
+        let locked_fs = self.fs.clone();
+        let fs = locked_fs.read();
+        let name_units: Vec<u16> = name.encode_utf16().collect();
+        let name_hash = fs.upcase.name_hash(&name_units);
+
+        self.internal_cursor = 0;
+        self.find(|entry| {
+            entry.name_hash == name_hash && fs.upcase.names_match(&entry.name_units, &name_units)
+        })
+    }
+
+    /// Parses the entry set starting at `offset` in the directory table, whose file entry is `file_entry`.
+    ///
+    /// Returns `None` if the entry set is corrupted.
+    fn parse_entry_set(
+        &self,
+        offset: usize,
+        file_entry: &RawFileEntry,
+    ) -> Option<ExFatDirectoryEntry> {
+        let secondary_count = usize::from(file_entry.secondary_count);
+        let entry_set = self
+            .data
+            .get(offset..offset + (secondary_count + 1) * DIR_ENTRY_SIZE)?;
+
+        if secondary_count < 2 || entry_set_checksum(entry_set) != file_entry.set_checksum {
+            return None;
+        }
+
+        let mut secondaries = entry_set[DIR_ENTRY_SIZE..].chunks_exact(DIR_ENTRY_SIZE);
+        let stream_entry: RawStreamExtensionEntry =
+            bytemuck::pod_read_unaligned(secondaries.next()?);
+
+        if stream_entry.entry_type != ENTRY_TYPE_STREAM_EXTENSION || stream_entry.name_length == 0 {
+            return None;
+        }
+
+        let name_len = usize::from(stream_entry.name_length);
+        let name_entries = name_len.div_ceil(FILE_NAME_ENTRY_CHARS);
+        let mut name_units = Vec::with_capacity(name_entries * FILE_NAME_ENTRY_CHARS);
+
+        if name_entries > usize::min(secondary_count - 1, MAX_FILE_NAME_ENTRIES) {
+            return None;
+        }
+
+        for raw_entry in secondaries.by_ref().take(name_entries) {
+            let name_entry: RawFileNameEntry = bytemuck::pod_read_unaligned(raw_entry);
+
+            if name_entry.entry_type != ENTRY_TYPE_FILE_NAME {
+                return None;
+            }
+
+            let chars = name_entry.file_name;
+            name_units.extend_from_slice(&chars);
+        }
+
+        // the remaining secondary entries can only be skipped if they are benign
+        if secondaries.any(|raw_entry| {
+            raw_entry[0] & ENTRY_TYPE_IN_USE != 0 && raw_entry[0] & ENTRY_TYPE_BENIGN == 0
+        }) {
+            return None;
+        }
+
+        name_units.truncate(name_len);
+
+        let fs = self.fs.read();
+
+        if fs.upcase.name_hash(&name_units) != stream_entry.name_hash {
+            return None;
+        }
+
+        drop(fs);
+
+        let allocated = stream_entry.flags & FLAG_ALLOCATION_POSSIBLE != 0;
+        let mut ancestors = self.ancestors.clone();
+        ancestors.push(self.stream);
+
+        Some(ExFatDirectoryEntry {
+            fs: self.fs.clone(),
+            name: decode_name(&name_units),
+            name_units,
+            name_hash: stream_entry.name_hash,
+            attributes: file_entry.file_attributes,
+            stream: DataStream {
+                first_cluster: if allocated {
+                    stream_entry.first_cluster
+                } else {
+                    0
+                },
+                length: stream_entry.data_length,
+                no_fat_chain: allocated && stream_entry.flags & FLAG_NO_FAT_CHAIN != 0,
+            },
+            valid_data_length: stream_entry.valid_data_length,
+            ancestors,
+        })
+    }
+}
+
+impl Iterator for ExFatDirectory {
+    type Item = ExFatDirectoryEntry;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        while let Some(raw_entry) = self
+            .data
+            .get(self.internal_cursor..self.internal_cursor + DIR_ENTRY_SIZE)
+        {
+            let offset = self.internal_cursor;
+            self.internal_cursor += DIR_ENTRY_SIZE;
+
+            match raw_entry[0] {
+                // end of the directory
+                ENTRY_TYPE_END => break,
+                ENTRY_TYPE_FILE => (),
+                // unused entries, entries describing the volume, and secondary entries of unknown sets
+                _ => continue,
+            }
+
+            let file_entry: RawFileEntry = bytemuck::pod_read_unaligned(raw_entry);
+
+            // corrupted entry sets are skipped, along with the entries they are supposed to contain
+            if let Some(entry) = self.parse_entry_set(offset, &file_entry) {
+                self.internal_cursor += usize::from(file_entry.secondary_count) * DIR_ENTRY_SIZE;
+                return Some(entry);
+            }
+        }
+
+        self.internal_cursor = 0;
+        None
+    }
+}
+
+#[derive(Debug)]
+pub(crate) struct GenericExFatDirectory {
+    pub(super) dir: ExFatDirectory,
+}
+
+impl Iterator for GenericExFatDirectory {
+    type Item = DirEntry;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.dir.next()?.try_into().ok()
+    }
+}
+
+impl FsDirectory for GenericExFatDirectory {
+    fn parent(&mut self) -> Option<Directory> {
+        let mut ancestors = self.dir.ancestors.clone();
+        let parent = ancestors.pop()?;
+
+        Some(Box::new(GenericExFatDirectory {
+            dir: ExFatDirectory::from_stream(self.dir.fs.clone(), parent, ancestors).ok()?,
+        }))
+    }
+
+    fn is_root_dir(&self) -> IOResult<bool> {
+        Ok(self.dir.ancestors.is_empty())
+    }
+
+    fn size(&self) -> IOResult<usize> {
+        Ok(self.dir.data.len())
+    }
+}
@@ -0,0 +1,123 @@
+//! `exFAT` file-related structures
+//!
+//! Provides methods for loading and reading bytes from files, as defined by the `exFAT` filesystem.
+//! Serves as as interface between the `exFAT` definition of a file and the abstract implementation in `FrozenBoot`
+
+use alloc::vec::Vec;
+
+use crate::errors::IOError;
+use crate::fs::exfat::dir::ExFatDirectoryEntry;
+use crate::fs::exfat::LockedExFatFs;
+use crate::fs::{FsFile, IOResult, Seek};
+
+/// Representation of a file in the `exFAT` filesystem.
+pub(crate) struct ExFatFile {
+    fs: LockedExFatFs,
+    clusters: Vec<u32>,
+    size: usize,
+    valid_size: usize,
+    cursor: usize,
+}
+
+impl core::fmt::Debug for ExFatFile {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.write_fmt(format_args!(
+            "exfat file | first_cluster = {}    clusters_count = {}    size = {}    valid_size = {}",
+            self.clusters.first().copied().unwrap_or_default(),
+            self.clusters.len(),
+            self.size,
+            self.valid_size
+        ))
+    }
+}
+
+impl ExFatFile {
+    /// Loads an `ExFatFile` from its directory entry.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`IOError::Unknown`] if the clusters of the file are corrupted (broken chain, or clusters marked as free
+    /// in the allocation bitmap), or too few for its size. May return any other variant of [`IOError`] in case of a
+    /// failure while attempting to read from disk.
+    pub(crate) fn from_entry(
+        locked_fs: LockedExFatFs,
+        entry: &ExFatDirectoryEntry,
+    ) -> IOResult<Self> {
+        let fs = locked_fs.read();
+        let clusters = fs.stream_clusters(entry.stream)?;
+        drop(fs);
+
+        let size = usize::try_from(entry.stream.length).map_err(|_| IOError::Unknown)?;
+        let valid_size = usize::try_from(entry.valid_data_length).map_err(|_| IOError::Unknown)?;
+
+        if valid_size > size {
+            return Err(IOError::Unknown);
+        }
+
+        Ok(Self {
+            fs: locked_fs,
+            clusters,
+            size,
+            valid_size,
+            cursor: 0,
+        })
+    }
+}
+
+impl FsFile for ExFatFile {
+    fn read(&mut self, buf: &mut [u8]) -> IOResult<usize> {
+        let bytes_count = usize::min(buf.len(), self.size - self.cursor);
+
+        if bytes_count == 0 {
+            return Ok(0);
+        }
+
+        // bytes past the valid data length have never been written, and read as zeros
+        let valid_count = usize::min(bytes_count, self.valid_size.saturating_sub(self.cursor));
+
+        if valid_count > 0 {
+            let fs = self.fs.read();
+            let cluster_size = fs.cluster_size();
+
+            let first_cluster = self.cursor / cluster_size;
+            let last_cluster = (self.cursor + valid_count - 1) / cluster_size;
+            let data = fs.read_chain(&self.clusters[first_cluster..=last_cluster])?;
+
+            let offset = self.cursor % cluster_size;
+            buf[..valid_count].copy_from_slice(&data[offset..offset + valid_count]);
+        }
+
+        buf[valid_count..bytes_count].fill(0);
+        self.seek(Seek::Forward(bytes_count));
+
+        Ok(bytes_count)
+    }
+
+    fn seek(&mut self, pos: Seek) -> usize {
+        match pos {
+            Seek::Backward(count) => {
+                self.cursor = self.cursor.saturating_sub(count);
+            }
+            Seek::Current => (),
+            Seek::Forward(count) => {
+                self.cursor = usize::min(self.cursor.saturating_add(count), self.size);
+            }
+        }
+
+        self.cursor
+    }
+
+    fn size(&self) -> IOResult<usize> {
+        Ok(self.size)
+    }
+
+    fn truncate(&mut self, _size: usize) -> IOResult<usize> {
+        // `exFAT` filesystems are mounted read-only
+        Err(IOError::InvalidCommand)
+    }
+
+    fn extend(&mut self, _size: usize) -> IOResult<usize> {
+        // `exFAT` filesystems are mounted read-only
+        Err(IOError::InvalidCommand)
+    }
+}
@@ -0,0 +1,558 @@
+//! `exFAT` (Extended File Allocation Table) filesystems `FrozenBoot`'s implementation.
+//!
+//! `exFAT` is the successor of `FAT32`, designed for large removable media (it is the default filesystem of
+//! _SDXC_ cards). It keeps a File Allocation Table, but tracks free clusters with an allocation bitmap, and files
+//! whose clusters are contiguous are not described by the FAT at all (`NoFatChain` streams).
+//!
+//! File names are stored in UTF-16, and looked up case-insensitively using the up-case table of the volume. The
+//! integrity of the boot region, of the up-case table and of every directory entry set is checked with their
+//! checksums.
+//!
+//! This implementation can only mount such filesystems read-only.
+
+use alloc::boxed::Box;
+use alloc::sync::{Arc, Weak};
+use alloc::{string::String, vec::Vec};
+use core::cell::RefCell;
+
+use spin::RwLock;
+
+use crate::drivers::generics::dev_disk::{get_sata_drive, DiskDevice};
+use crate::drivers::ide::AtaDeviceIdentifier;
+use crate::errors::{IOError, MountError};
+use crate::fs::exfat::boot::{verify_boot_checksum, BootSector, BOOT_REGION_SECTORS};
+use crate::fs::exfat::dir::{ExFatDirectory, GenericExFatDirectory, VolumeEntries};
+use crate::fs::exfat::file::ExFatFile;
+use crate::fs::exfat::upcase::UpcaseTable;
+use crate::fs::fat::table::{FatCache, FatEntry};
+use crate::fs::{Directory, Fs, IOResult};
+use crate::info;
+
+pub(crate) mod boot;
+pub(crate) mod dir;
+pub(crate) mod file;
+pub(crate) mod upcase;
+
+/// Maximum number of disk sectors read with a single request.
+const MAX_SECTORS_PER_REQUEST: u64 = 0x80;
+
+/// Size of a FAT entry, in bytes.
+const FAT_ENTRY_SIZE: u64 = 4;
+
+/// FAT entry of a cluster containing a bad sector.
+const FAT_ENTRY_BAD: u32 = 0xFFFF_FFF7;
+
+/// FAT entry of the last cluster of a chain.
+const FAT_ENTRY_END_OF_CHAIN: u32 = 0xFFFF_FFFF;
+
+/// Strong pointer to a locked [`ExFatFs`] structure.
+///
+/// The [`ExFatFs`] structure will remain allocated for as long as the filesystem is mounted.
+pub(super) type LockedExFatFs = Arc<RwLock<ExFatFs>>;
+
+/// Location and size of the content of a file or directory.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) struct DataStream {
+    /// First cluster of the stream, or 0 if no cluster is allocated to it.
+    pub(crate) first_cluster: u32,
+
+    /// Size of the stream, in bytes.
+    pub(crate) length: u64,
+
+    /// `true` if the clusters of the stream are contiguous, in which case the FAT does not describe them.
+    pub(crate) no_fat_chain: bool,
+}
+
+/// Internal representation of an `exFAT` filesystem.
+///
+/// Holds the layout of the volume, read from its boot sector, its up-case table, and caches of the allocation table
+/// and of the allocation bitmap.
+///
+/// This structure can only be accessed through a smart [`Arc`] pointer, the underlying allocation is guaranteed to
+/// remain valid while the filesystem is mounted.
+#[derive(Debug)]
+pub(crate) struct ExFatFs {
+    drive_id: AtaDeviceIdentifier,
+    partition_id: usize,
+    start_lba: u64,
+
+    /// Layout of the volume.
+    pub(crate) boot: BootSector,
+
+    /// Up-case table of the volume, used to compare file names.
+    pub(crate) upcase: UpcaseTable,
+
+    /// Clusters of the active allocation bitmap.
+    bitmap_clusters: Vec<u32>,
+
+    fat_cache: RefCell<FatCache>,
+
+    /// Cache of the allocation bitmap sectors, indexed by their position in the bitmap.
+    bitmap_cache: RefCell<FatCache>,
+
+    fs_ptr: Weak<RwLock<Self>>,
+}
+
+impl ExFatFs {
+    /// Reads `count` sectors of the volume, starting from `sector`.
+    ///
+    /// Sectors are the ones of the filesystem (see [`BootSector::bytes_per_sector`]), which may be larger than the
+    /// logical sectors of the disk.
+    ///
+    /// # Errors
+    ///
+    /// May return any variant of [`IOError`] in case of a failure while attempting to read from disk.
+    pub(crate) fn read_sectors(&self, sector: u64, count: u32) -> IOResult<Vec<u8>> {
+        let drive = get_sata_drive(self.drive_id).ok_or(IOError::InvalidDevice)?;
+        let lba_per_sector = u64::from(self.boot.bytes_per_sector) / drive.logical_sector_size();
+
+        let mut lba = self.start_lba + sector * lba_per_sector;
+        let end_lba = lba + u64::from(count) * lba_per_sector;
+        let mut data = Vec::new();
+
+        while lba < end_lba {
+            let lba_count = (end_lba - lba).min(MAX_SECTORS_PER_REQUEST);
+            let read_req = drive
+                .read(
+                    lba,
+                    u16::try_from(lba_count).expect("invalid sectors count"),
+                )
+                .complete();
+
+            data.extend_from_slice(&read_req.data.ok_or(IOError::Unknown)?);
+            lba += lba_count;
+        }
+
+        Ok(data)
+    }
+
+    /// Returns the entry of the active allocation table associated to `cluster`.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`IOError::InvalidCommand`] if `cluster` is not a valid data cluster. May return any other variant of
+    /// [`IOError`] in case of a failure while attempting to read from disk.
+    pub(crate) fn fat_entry(&self, cluster: u32) -> IOResult<FatEntry> {
+        if !self.boot.is_valid_cluster(cluster) {
+            return Err(IOError::InvalidCommand);
+        }
+
+        // entries are aligned, and never straddle two sectors
+        let offset = u64::from(cluster) * FAT_ENTRY_SIZE;
+        let sector = u32::try_from(offset / u64::from(self.boot.bytes_per_sector))
+            .expect("invalid FAT sector");
+        let sector_offset = usize::try_from(offset % u64::from(self.boot.bytes_per_sector))
+            .expect("invalid sector offset");
+
+        let mut fat_cache = self.fat_cache.borrow_mut();
+
+        if fat_cache.get(sector).is_none() {
+            fat_cache.insert(
+                sector,
+                self.read_sectors(u64::from(self.boot.fat_start() + sector), 1)?,
+            );
+        }
+
+        let raw = fat_cache
+            .get(sector)
+            .and_then(|data| data.get(sector_offset..sector_offset + 4))
+            .map(|bytes| u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+            .ok_or(IOError::Unknown)?;
+
+        Ok(match raw {
+            0 => FatEntry::Free,
+            FAT_ENTRY_BAD => FatEntry::Bad,
+            FAT_ENTRY_END_OF_CHAIN => FatEntry::EndOfChain,
+            next if self.boot.is_valid_cluster(next) => FatEntry::Next(next),
+            _ => FatEntry::Reserved,
+        })
+    }
+
+    /// Returns the clusters of the chain starting at `first_cluster`, in order.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`IOError::Unknown`] if the chain is corrupted (free or bad clusters, or loops). May return any other
+    /// variant of [`IOError`] in case of a failure while attempting to read from disk.
+    pub(crate) fn cluster_chain(&self, first_cluster: u32) -> IOResult<Vec<u32>> {
+        let mut clusters = Vec::new();
+        let mut cluster = first_cluster;
+
+        loop {
+            // a chain cannot be longer than the volume, otherwise it contains a loop
+            if clusters.len()
+                > usize::try_from(self.boot.clusters_count).expect("invalid clusters count")
+            {
+                return Err(IOError::Unknown);
+            }
+
+            clusters.push(cluster);
+
+            match self.fat_entry(cluster)? {
+                FatEntry::Next(next) => cluster = next,
+                FatEntry::EndOfChain => return Ok(clusters),
+                FatEntry::Free | FatEntry::Bad | FatEntry::Reserved => {
+                    return Err(IOError::Unknown)
+                }
+            }
+        }
+    }
+
+    /// Returns the clusters holding the content of `stream`, in order.
+    ///
+    /// Clusters of `NoFatChain` streams are contiguous, and are not looked up in the FAT. Every cluster must be marked
+    /// as allocated in the allocation bitmap.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`IOError::Unknown`] if the clusters of the stream are corrupted, or too few for its size. May return
+    /// any other variant of [`IOError`] in case of a failure while attempting to read from disk.
+    pub(crate) fn stream_clusters(&self, stream: DataStream) -> IOResult<Vec<u32>> {
+        let count = usize::try_from(stream.length.div_ceil(u64::from(self.boot.cluster_size())))
+            .map_err(|_| IOError::Unknown)?;
+
+        if stream.first_cluster == 0 {
+            return if count == 0 {
+                Ok(Vec::new())
+            } else {
+                Err(IOError::Unknown)
+            };
+        }
+
+        let clusters: Vec<u32> = if stream.no_fat_chain {
+            let last_cluster = u32::try_from(count)
+                .ok()
+                .and_then(|count| stream.first_cluster.checked_add(count))
+                .ok_or(IOError::Unknown)?;
+
+            (stream.first_cluster..last_cluster).collect()
+        } else {
+            let mut clusters = self.cluster_chain(stream.first_cluster)?;
+
+            if clusters.len() < count {
+                return Err(IOError::Unknown);
+            }
+
+            clusters.truncate(count);
+            clusters
+        };
+
+        for &cluster in &clusters {
+            if !self.is_cluster_allocated(cluster)? {
+                return Err(IOError::Unknown);
+            }
+        }
+
+        Ok(clusters)
+    }
+
+    /// Checks the allocation bitmap to know if `cluster` is in use.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`IOError::InvalidCommand`] if `cluster` is not a valid data cluster. May return any other variant of
+    /// [`IOError`] in case of a failure while attempting to read from disk.
+    pub(crate) fn is_cluster_allocated(&self, cluster: u32) -> IOResult<bool> {
+        if !self.boot.is_valid_cluster(cluster) {
+            return Err(IOError::InvalidCommand);
+        }
+
+        let bit = cluster - 2;
+        let byte_offset = bit / 8;
+        let sector = byte_offset / self.boot.bytes_per_sector;
+        let sector_offset = usize::try_from(byte_offset % self.boot.bytes_per_sector)
+            .expect("invalid sector offset");
+
+        let mut bitmap_cache = self.bitmap_cache.borrow_mut();
+
+        if bitmap_cache.get(sector).is_none() {
+            let bitmap_cluster = self
+                .bitmap_clusters
+                .get(
+                    usize::try_from(sector / self.boot.sectors_per_cluster)
+                        .expect("invalid cluster index"),
+                )
+                .ok_or(IOError::Unknown)?;
+            let data = self.read_sectors(
+                self.boot.cluster_start(*bitmap_cluster)
+                    + u64::from(sector % self.boot.sectors_per_cluster),
+                1,
+            )?;
+
+            bitmap_cache.insert(sector, data);
+        }
+
+        let byte = bitmap_cache
+            .get(sector)
+            .and_then(|data| data.get(sector_offset).copied())
+            .ok_or(IOError::Unknown)?;
+
+        Ok(byte & (1 << (bit % 8)) != 0)
+    }
+
+    /// Returns the size of a cluster, in bytes.
+    pub(crate) fn cluster_size(&self) -> usize {
+        usize::try_from(self.boot.cluster_size()).expect("invalid cluster size")
+    }
+
+    /// Reads `count` contiguous clusters, starting from `cluster`.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`IOError::InvalidCommand`] if the clusters are not valid data clusters. May return any other variant of
+    /// [`IOError`] in case of a failure while attempting to read from disk.
+    pub(crate) fn read_clusters(&self, cluster: u32, count: u32) -> IOResult<Vec<u8>> {
+        if count == 0 {
+            return Ok(Vec::new());
+        }
+
+        if !self.boot.is_valid_cluster(cluster) || !self.boot.is_valid_cluster(cluster + count - 1)
+        {
+            return Err(IOError::InvalidCommand);
+        }
+
+        self.read_sectors(
+            self.boot.cluster_start(cluster),
+            count * self.boot.sectors_per_cluster,
+        )
+    }
+
+    /// Reads every cluster of `clusters`, in order.
+    ///
+    /// Contiguous clusters are read with a single disk request.
+    ///
+    /// # Errors
+    ///
+    /// May return any variant of [`IOError`] in case of a failure while attempting to read from disk.
+    pub(crate) fn read_chain(&self, clusters: &[u32]) -> IOResult<Vec<u8>> {
+        let mut data = Vec::new();
+        let mut remaining = clusters;
+
+        while let Some(&first) = remaining.first() {
+            let run = remaining
+                .iter()
+                .zip(first..)
+                .take_while(|(&cluster, expected)| cluster == *expected)
+                .count();
+
+            data.extend_from_slice(
+                &self.read_clusters(first, u32::try_from(run).expect("invalid clusters count"))?,
+            );
+            remaining = &remaining[run..];
+        }
+
+        Ok(data)
+    }
+
+    /// Returns the location and size of the root directory table, which is always described by the FAT.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`IOError::Unknown`] if the cluster chain of the root directory is corrupted. May return any other
+    /// variant of [`IOError`] in case of a failure while attempting to read from disk.
+    pub(crate) fn root_stream(&self) -> IOResult<DataStream> {
+        let clusters = self.cluster_chain(self.boot.root_cluster)?;
+
+        Ok(DataStream {
+            first_cluster: self.boot.root_cluster,
+            length: u64::try_from(clusters.len()).expect("invalid clusters count")
+                * u64::from(self.boot.cluster_size()),
+            no_fat_chain: false,
+        })
+    }
+
+    /// Returns the root directory of this filesystem.
+    ///
+    /// # Errors
+    ///
+    /// In case of any I/O error, a generic error will be returned. An error may mean that the filesystem
+    /// is corrupted.
+    pub(crate) fn root_dir(&self) -> IOResult<Directory> {
+        Ok(Box::new(GenericExFatDirectory {
+            dir: self.root_exfat_dir()?,
+        }))
+    }
+
+    /// Opens the regular file located at `path`, relative to the root directory of this filesystem.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`IOError::NotFound`] if one of the path components does not exist, or is not of the expected type.
+    /// May return any other variant of [`IOError`] in case of a failure while attempting to read from disk.
+    pub(crate) fn open_file(&self, path: &str) -> IOResult<ExFatFile> {
+        let path = path.trim_end_matches('/');
+        let (parent, name) = path.rsplit_once('/').unwrap_or(("", path));
+
+        self.find_dir(parent)?
+            .search(name)
+            .and_then(|entry| entry.as_file())
+            .ok_or(IOError::NotFound)
+    }
+
+    /// Lists the directory located at `path`, relative to the root directory of this filesystem.
+    ///
+    /// Returns the names of its entries, in the order in which they are stored.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`IOError::NotFound`] if one of the path components does not exist, or is not a directory.
+    /// May return any other variant of [`IOError`] in case of a failure while attempting to read from disk.
+    pub(crate) fn read_dir(&self, path: &str) -> IOResult<Vec<String>> {
+        Ok(self.find_dir(path)?.map(|entry| entry.name).collect())
+    }
+
+    /// Loads the directory located at `path`, relative to the root directory of this filesystem.
+    fn find_dir(&self, path: &str) -> IOResult<ExFatDirectory> {
+        let mut dir = self.root_exfat_dir()?;
+
+        for component in path.split('/').filter(|component| !component.is_empty()) {
+            dir = dir
+                .search(component)
+                .and_then(|entry| entry.as_directory())
+                .ok_or(IOError::NotFound)?
+                .dir;
+        }
+
+        Ok(dir)
+    }
+
+    fn root_exfat_dir(&self) -> IOResult<ExFatDirectory> {
+        ExFatDirectory::root(self.fs_ptr.upgrade().ok_or(IOError::Unknown)?)
+    }
+
+    /// Locates the allocation bitmap and loads the up-case table, from their entries in the root directory.
+    ///
+    /// Returns the volume label, if set.
+    fn load_volume_entries(&mut self) -> Result<Option<String>, MountError> {
+        let root_clusters = self
+            .cluster_chain(self.boot.root_cluster)
+            .map_err(|_| MountError::BadSuperblock)?;
+        let root_dir = self
+            .read_chain(&root_clusters)
+            .map_err(|_| MountError::IOError)?;
+
+        let entries = VolumeEntries::scan(&root_dir, self.boot.active_fat);
+        let bitmap = entries.allocation_bitmap.ok_or(MountError::BadSuperblock)?;
+        let upcase_table = entries.upcase_table.ok_or(MountError::BadSuperblock)?;
+
+        // the bitmap has one bit per cluster
+        if bitmap.data_length < u64::from(self.boot.clusters_count).div_ceil(8) {
+            return Err(MountError::BadSuperblock);
+        }
+
+        let cluster_size = u64::from(self.boot.cluster_size());
+        let bitmap_clusters = self
+            .cluster_chain(bitmap.first_cluster)
+            .map_err(|_| MountError::BadSuperblock)?;
+
+        if u64::try_from(bitmap_clusters.len()).expect("invalid clusters count") * cluster_size
+            < bitmap.data_length
+        {
+            return Err(MountError::BadSuperblock);
+        }
+
+        let upcase_length =
+            usize::try_from(upcase_table.data_length).map_err(|_| MountError::BadSuperblock)?;
+        let upcase_clusters = self
+            .cluster_chain(upcase_table.first_cluster)
+            .map_err(|_| MountError::BadSuperblock)?;
+        let mut upcase_data = self
+            .read_chain(&upcase_clusters)
+            .map_err(|_| MountError::IOError)?;
+
+        if upcase_data.len() < upcase_length {
+            return Err(MountError::BadSuperblock);
+        }
+
+        upcase_data.truncate(upcase_length);
+
+        self.upcase = UpcaseTable::parse(&upcase_data, upcase_table.table_checksum)?;
+        self.bitmap_clusters = bitmap_clusters;
+
+        Ok(entries.volume_label)
+    }
+}
+
+impl Fs for ExFatFs {
+    fn mount(
+        drive_id: AtaDeviceIdentifier,
+        partition_id: usize,
+        partition_data: u64,
+    ) -> Result<LockedExFatFs, MountError> {
+        let drive = get_sata_drive(drive_id).ok_or(MountError::IOError)?;
+        let boot_sector = drive
+            .read(partition_data, 1)
+            .complete()
+            .data
+            .ok_or(MountError::IOError)?;
+
+        let boot = BootSector::parse(&boot_sector).ok_or(MountError::BadSuperblock)?;
+
+        if u64::from(boot.bytes_per_sector) % drive.logical_sector_size() != 0 {
+            return Err(MountError::BadSuperblock);
+        }
+
+        let lba_per_sector = u64::from(boot.bytes_per_sector) / drive.logical_sector_size();
+        let boot_region = drive
+            .read(
+                partition_data,
+                u16::try_from(u64::from(BOOT_REGION_SECTORS) * lba_per_sector)
+                    .expect("invalid sectors count"),
+            )
+            .complete()
+            .data
+            .ok_or(MountError::IOError)?;
+
+        if !verify_boot_checksum(
+            &boot_region,
+            usize::try_from(boot.bytes_per_sector).expect("invalid sector size"),
+        ) {
+            return Err(MountError::InvalidChecksum);
+        }
+
+        let fs = Arc::new_cyclic(|ptr| {
+            RwLock::new(ExFatFs {
+                drive_id,
+                partition_id,
+                start_lba: partition_data,
+                boot,
+                upcase: UpcaseTable::default(),
+                bitmap_clusters: Vec::new(),
+                fat_cache: RefCell::new(FatCache::default()),
+                bitmap_cache: RefCell::new(FatCache::default()),
+                fs_ptr: ptr.clone(),
+            })
+        });
+
+        let volume_label = fs.write().load_volume_entries()?;
+
+        info!(
+            "exfat-fs",
+            "mounted exFAT filesystem on drive {drive_id} partition {partition_id}"
+        );
+
+        info!(
+            "exfat-fs",
+            "label = {}    volume_serial = {:#x}    clusters_count = {}    cluster_size = {}",
+            volume_label.unwrap_or_default(),
+            boot.volume_serial,
+            boot.clusters_count,
+            boot.cluster_size()
+        );
+
+        Ok(fs)
+    }
+
+    fn identify(drive_id: AtaDeviceIdentifier, partition_data: u64) -> IOResult<bool> {
+        let drive = get_sata_drive(drive_id).ok_or(IOError::InvalidDevice)?;
+        let boot_sector = drive
+            .read(partition_data, 1)
+            .complete()
+            .data
+            .ok_or(IOError::Unknown)?;
+
+        Ok(BootSector::parse(&boot_sector).is_some())
+    }
+}
+
+unsafe impl Sync for ExFatFs {}
@@ -0,0 +1,181 @@
+//! exFAT up-case table.
+//!
+//! File names are compared case-insensitively, by converting them to uppercase with the up-case
+//! table of the volume, which maps every UTF-16 code unit to its uppercase version. The table may
+//! be stored compressed: identity mappings are replaced by a `0xFFFF` marker followed by the number
+//! of characters they cover.
+
+use alloc::vec::Vec;
+
+use crate::errors::MountError;
+
+/// Marker of a range of identity mappings, in the compressed table.
+const IDENTITY_RANGE_MARKER: u16 = 0xFFFF;
+
+/// Number of entries of a fully expanded table.
+const MAX_TABLE_ENTRIES: usize = 0x1_0000;
+
+/// Expanded up-case table of an exFAT volume.
+///
+/// An empty table maps every character to itself.
+#[derive(Clone, Debug, Default)]
+pub(crate) struct UpcaseTable {
+    mappings: Vec<u16>,
+}
+
+impl UpcaseTable {
+    /// Loads an up-case table from its content on disk, and checks its checksum.
+    ///
+    /// As in Linux, a unit equal to the index of the entry it describes is an identity mapping
+    /// before being a compression marker, so that uncompressed tables (whose last unit is `0xFFFF`)
+    /// are accepted. Units past the last entry of the table are ignored.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`MountError::InvalidChecksum`] if the checksum does not match, and
+    /// [`MountError::BadSuperblock`] if the table is malformed.
+    pub(crate) fn parse(data: &[u8], checksum: u32) -> Result<Self, MountError> {
+        let computed = data.iter().fold(0u32, |checksum, &byte| {
+            checksum.rotate_right(1).wrapping_add(u32::from(byte))
+        });
+
+        if computed != checksum {
+            return Err(MountError::InvalidChecksum);
+        }
+
+        if data.len() % 2 != 0 {
+            return Err(MountError::BadSuperblock);
+        }
+
+        let mut mappings = Vec::new();
+        let mut units = data
+            .chunks_exact(2)
+            .map(|unit| u16::from_le_bytes([unit[0], unit[1]]));
+
+        while mappings.len() < MAX_TABLE_ENTRIES {
+            let Some(unit) = units.next() else {
+                break;
+            };
+            let index = mappings.len();
+
+            // the last entry of an uncompressed table maps `0xFFFF` to itself, which is not a marker
+            if usize::from(unit) != index && unit == IDENTITY_RANGE_MARKER {
+                let count = units.next().ok_or(MountError::BadSuperblock)?;
+                let end = usize::min(index + usize::from(count), MAX_TABLE_ENTRIES);
+
+                mappings.extend(
+                    (index..end).map(|idx| u16::try_from(idx).expect("invalid table index")),
+                );
+            } else {
+                mappings.push(unit);
+            }
+        }
+
+        Ok(Self { mappings })
+    }
+
+    /// Converts a UTF-16 code unit to uppercase.
+    ///
+    /// Code units which are not covered by the table are left unchanged.
+    pub(crate) fn upcase(&self, unit: u16) -> u16 {
+        self.mappings
+            .get(usize::from(unit))
+            .copied()
+            .unwrap_or(unit)
+    }
+
+    /// Compares two UTF-16 file names, ignoring case.
+    pub(crate) fn names_match(&self, left: &[u16], right: &[u16]) -> bool {
+        left.len() == right.len()
+            && left
+                .iter()
+                .zip(right)
+                .all(|(&left, &right)| self.upcase(left) == self.upcase(right))
+    }
+
+    /// Computes the hash of a UTF-16 file name, which is stored in its directory entry set to speed
+    /// up lookups.
+    pub(crate) fn name_hash(&self, name: &[u16]) -> u16 {
+        name.iter()
+            .flat_map(|&unit| self.upcase(unit).to_le_bytes())
+            .fold(0u16, |hash, byte| {
+                hash.rotate_right(1).wrapping_add(u16::from(byte))
+            })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use alloc::vec::Vec;
+
+    use super::UpcaseTable;
+    use crate::errors::MountError;
+
+    /// Serializes a table, and returns it along with its checksum.
+    fn table_data(units: &[u16]) -> (Vec<u8>, u32) {
+        let data: Vec<u8> = units.iter().flat_map(|unit| unit.to_le_bytes()).collect();
+        let checksum = data.iter().fold(0u32, |checksum, &byte| {
+            checksum.rotate_right(1).wrapping_add(u32::from(byte))
+        });
+
+        (data, checksum)
+    }
+
+    fn check_ascii_table(table: &UpcaseTable) {
+        assert_eq!(table.upcase(u16::from(b'a')), u16::from(b'A'));
+        assert_eq!(table.upcase(u16::from(b'z')), u16::from(b'Z'));
+        assert_eq!(table.upcase(u16::from(b'{')), u16::from(b'{'));
+        assert_eq!(table.upcase(0x00E9), 0x00E9);
+        assert_eq!(table.upcase(0xFFFF), 0xFFFF);
+        assert_eq!(table.mappings.len(), 0x1_0000);
+    }
+
+    #[test]
+    fn parses_compressed_tables() {
+        let mut units = alloc::vec![0xFFFF, u16::from(b'a')];
+        units.extend(u16::from(b'A')..=u16::from(b'Z'));
+        units.extend([0xFFFF, 0xFFFF - u16::from(b'z')]);
+
+        let (data, checksum) = table_data(&units);
+        let table = UpcaseTable::parse(&data, checksum).unwrap();
+
+        check_ascii_table(&table);
+        assert!(table.names_match(
+            &[0x0062, 0x006F, 0x006F, 0x0074],
+            &[0x0042, 0x004F, 0x004F, 0x0054]
+        ));
+    }
+
+    #[test]
+    fn parses_uncompressed_tables() {
+        let units: Vec<u16> = (0..=0xFFFF)
+            .map(|unit| {
+                if (u16::from(b'a')..=u16::from(b'z')).contains(&unit) {
+                    unit - 0x20
+                } else {
+                    unit
+                }
+            })
+            .collect();
+
+        let (data, checksum) = table_data(&units);
+        let table = UpcaseTable::parse(&data, checksum).unwrap();
+
+        check_ascii_table(&table);
+    }
+
+    #[test]
+    fn rejects_invalid_tables() {
+        let (data, checksum) = table_data(&[0x0000, 0xFFFF]);
+        assert!(matches!(
+            UpcaseTable::parse(&data, checksum),
+            Err(MountError::BadSuperblock)
+        ));
+
+        let (data, checksum) = table_data(&[0xFFFF, 0x0010]);
+        assert!(matches!(
+            UpcaseTable::parse(&data, checksum.wrapping_add(1)),
+            Err(MountError::InvalidChecksum)
+        ));
+    }
+}
@@ -14,6 +14,27 @@ use core::ops::Range;
 
 use vob::Vob;
 
+/// Loads a raw bitmap extracted from the filesystem into a [`Vob`].
+///
+/// On disk, the state of entry `n` is stored in bit `n % 8` of byte `n / 8`.
+fn vob_from_bytes(bitmap: &[u8]) -> Vob {
+    bitmap
+        .iter()
+        .flat_map(|&byte| (0..8).map(move |bit| byte & (1 << bit) != 0))
+        .collect()
+}
+
+/// Converts a [`Vob`] back to a raw bitmap, as stored on disk.
+fn vob_to_bytes(vob: &Vob) -> Vec<u8> {
+    let mut bitmap = alloc::vec![0u8; vob.len().div_ceil(8)];
+
+    for entry in vob.iter_set_bits(..) {
+        bitmap[entry / 8] |= 1 << (entry % 8);
+    }
+
+    bitmap
+}
+
 /// Checksum of the [`BlockBitmap`] structure.
 #[derive(Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord, Hash, Pod, Zeroable)]
 #[repr(transparent)]
@@ -37,6 +58,18 @@ impl core::ops::Add<BlockBitmapChksumHi> for BlockBitmapChksumLo {
 #[repr(transparent)]
 pub(super) struct BlockBitmapChksumHi(u16);
 
+impl From<BlockBitmapChksum> for BlockBitmapChksumLo {
+    fn from(value: BlockBitmapChksum) -> Self {
+        BlockBitmapChksumLo(u16::try_from(value.0 & 0xFFFF).expect("invalid conversion"))
+    }
+}
+
+impl From<BlockBitmapChksum> for BlockBitmapChksumHi {
+    fn from(value: BlockBitmapChksum) -> Self {
+        BlockBitmapChksumHi(u16::try_from(value.0 >> 16).expect("invalid conversion"))
+    }
+}
+
 /// The `BlockBitmap` is used by `ext4` to store whether the different blocks of a block group are in use or not.
 ///
 /// Each bit in the bitmap represents the state of the corresponding block (in-use or free) for this block
@@ -52,30 +85,23 @@ impl BlockBitmap {
     /// ```
     /// crc32_calc(fs_uuid + block_bitmap)
     /// ```
+    ///
+    /// Only the low 16-bits of the checksum are stored in 32-bytes group descriptors: `has_chksum_hi` must be `false`
+    /// in that case.
     pub(super) fn validate_chksum(
         &self,
         fs_uuid: Ext4FsUuid,
         on_disk_chksum: BlockBitmapChksum,
+        has_chksum_hi: bool,
     ) -> bool {
-        let mut chksum_bytes = alloc::vec![0u8; 0];
-        chksum_bytes.extend_from_slice(bytes_of(&fs_uuid));
-
-        self.0.get_storage().iter().for_each(|w| {
-            let mut bitmap_bytes = w.to_le_bytes();
-            bitmap_bytes.iter_mut().for_each(|b| *b = b.reverse_bits());
-
-            chksum_bytes.extend_from_slice(&bitmap_bytes);
-        });
+        let mut comp_chksum = self.compute_chksum(fs_uuid);
 
-        // we have to correct the length to remove additional bytes added by `Vob` using 32-bits aligned
-        // storage instead of bytes aligned.
-        let real_chksum_bytes_len = core::mem::size_of::<Ext4FsUuid>() + self.0.len() / 8;
-        chksum_bytes.truncate(real_chksum_bytes_len);
-
-        let comp_chksum: BlockBitmapChksum = cast(crc32c_calc(&chksum_bytes));
+        if !has_chksum_hi {
+            comp_chksum.0 &= 0xFFFF;
+        }
 
         if comp_chksum != on_disk_chksum {
-            error!("ext4", "invalid inode bitmap checksum",);
+            error!("ext4", "invalid block bitmap checksum",);
 
             return false;
         }
@@ -83,9 +109,23 @@ impl BlockBitmap {
         true
     }
 
+    /// Computes the checksum of this `BlockBitmap`.
+    pub(super) fn compute_chksum(&self, fs_uuid: Ext4FsUuid) -> BlockBitmapChksum {
+        let mut chksum_bytes = alloc::vec![0u8; 0];
+        chksum_bytes.extend_from_slice(bytes_of(&fs_uuid));
+        chksum_bytes.extend_from_slice(&self.to_bytes());
+
+        cast(crc32c_calc(&chksum_bytes))
+    }
+
     /// Converts a raw inode bitmap extracted from the filesystem to its in-memory representation, based on a [`Vob`].
     pub(crate) fn from_bytes(bitmap: &[u8]) -> Self {
-        BlockBitmap(Vob::from_bytes(bitmap))
+        BlockBitmap(vob_from_bytes(bitmap))
+    }
+
+    /// Converts this `BlockBitmap` back to its on-disk representation.
+    pub(crate) fn to_bytes(&self) -> Vec<u8> {
+        vob_to_bytes(&self.0)
     }
 
     /// Checks if a given block, identified by its [`Ext4RealBlkId`] is marked in-use in this `BlockBitmap`.
@@ -176,6 +216,18 @@ impl core::ops::Add<InodeBitmapChksumHi> for InodeBitmapChksumLo {
     }
 }
 
+impl From<InodeBitmapChksum> for InodeBitmapChksumLo {
+    fn from(value: InodeBitmapChksum) -> Self {
+        InodeBitmapChksumLo(u16::try_from(value.0 & 0xFFFF).expect("invalid conversion"))
+    }
+}
+
+impl From<InodeBitmapChksum> for InodeBitmapChksumHi {
+    fn from(value: InodeBitmapChksum) -> Self {
+        InodeBitmapChksumHi(u16::try_from(value.0 >> 16).expect("invalid conversion"))
+    }
+}
+
 /// The `InodeBitmap` is used by `ext4` to store whether the different [`Inode`] of a block group are in use or not.
 ///
 /// Each bit in the bitmap represents the state of the corresponding `Inode` entry (in-use or free) for this block
@@ -191,27 +243,20 @@ impl InodeBitmap {
     /// ```
     /// crc32_calc(fs_uuid + inode_bitmap)
     /// ```
+    ///
+    /// Only the low 16-bits of the checksum are stored in 32-bytes group descriptors: `has_chksum_hi` must be `false`
+    /// in that case.
     pub(super) fn validate_chksum(
         &self,
         fs_uuid: Ext4FsUuid,
         on_disk_chksum: InodeBitmapChksum,
+        has_chksum_hi: bool,
     ) -> bool {
-        let mut chksum_bytes = alloc::vec![0u8; 0];
-        chksum_bytes.extend_from_slice(bytes_of(&fs_uuid));
-
-        self.0.get_storage().iter().for_each(|w| {
-            let mut bitmap_bytes = w.to_le_bytes();
-            bitmap_bytes.iter_mut().for_each(|b| *b = b.reverse_bits());
-
-            chksum_bytes.extend_from_slice(&bitmap_bytes);
-        });
+        let mut comp_chksum = self.compute_chksum(fs_uuid);
 
-        // we have to correct the length to remove additional bytes added by `Vob` using 32-bits aligned
-        // storage instead of bytes aligned.
-        let real_chksum_bytes_len = core::mem::size_of::<Ext4FsUuid>() + self.0.len() / 8;
-        chksum_bytes.truncate(real_chksum_bytes_len);
-
-        let comp_chksum: InodeBitmapChksum = cast(crc32c_calc(&chksum_bytes));
+        if !has_chksum_hi {
+            comp_chksum.0 &= 0xFFFF;
+        }
 
         if comp_chksum != on_disk_chksum {
             error!("ext4", "invalid inode bitmap checksum",);
@@ -222,9 +267,23 @@ impl InodeBitmap {
         true
     }
 
+    /// Computes the checksum of this `InodeBitmap`.
+    pub(super) fn compute_chksum(&self, fs_uuid: Ext4FsUuid) -> InodeBitmapChksum {
+        let mut chksum_bytes = alloc::vec![0u8; 0];
+        chksum_bytes.extend_from_slice(bytes_of(&fs_uuid));
+        chksum_bytes.extend_from_slice(&self.to_bytes());
+
+        cast(crc32c_calc(&chksum_bytes))
+    }
+
     /// Converts a raw inode bitmap extracted from the filesystem to its in-memory representation, based on a [`Vob`].
     pub(crate) fn from_bytes(bitmap: &[u8]) -> Self {
-        InodeBitmap(Vob::from_bytes(bitmap))
+        InodeBitmap(vob_from_bytes(bitmap))
+    }
+
+    /// Converts this `InodeBitmap` back to its on-disk representation.
+    pub(crate) fn to_bytes(&self) -> Vec<u8> {
+        vob_to_bytes(&self.0)
     }
 
     /// Checks if a given [`Inode`], identified by its [`InodeNumber`] is marked in-use in this `InodeBitmap`.
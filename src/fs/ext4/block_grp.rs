@@ -3,17 +3,17 @@
 //! Block groups are a logical grouping of contiguous blocks on disk. Their size is equal to the number of bits in
 //! one block (the [`BlockBitmap`] must fit in a single logical block).
 
-use crate::errors::IOError;
+use crate::errors::{CanFail, IOError};
 use crate::fs::ext4::bitmap::{
     BlockBitmap, BlockBitmapChksumHi, BlockBitmapChksumLo, InodeBitmap, InodeBitmapChksumHi,
     InodeBitmapChksumLo,
 };
 use crate::fs::ext4::extent::{Ext4RealBlkId, Ext4RealBlkId32};
-use crate::fs::ext4::inode::{InodeCount, InodeCount16};
+use crate::fs::ext4::inode::{InodeCount, InodeCount16, InodeNumber};
 use crate::fs::ext4::sb::{
-    Ext4BlkCount, Ext4BlkCount16, Ext4FsUuid, Ext4Superblock, IncompatibleFeatureSet,
+    Ext4BlkCount, Ext4BlkCount16, Ext4Superblock, ReadOnlyCompatibleFeatureSet,
 };
-use crate::fs::ext4::{crc32c_calc, LockedExt4Fs, WeakLockedExt4Fs};
+use crate::fs::ext4::{crc16_calc, crc32c_calc, Ext4Fs, LockedExt4Fs, WeakLockedExt4Fs};
 use crate::fs::IOResult;
 use crate::time::{current_timestamp, UnixTimestamp};
 use crate::{error, ext4_flag_field, ext4_uint_field_range};
@@ -100,6 +100,15 @@ impl GroupDescriptorFlags {
     pub(crate) const EXT4_BG_INODE_ZEROED: Self = Self(0x0004);
 }
 
+/// Offset of the checksum field in an [`Ext4GroupDescriptor`].
+const CHKSUM_OFFSET: usize = 0x1E;
+
+/// The high 16-bits of the [`BlockBitmap`] checksum are only stored in group descriptors at least that large.
+const BLOCK_BITMAP_CHKSUM_HI_END: u16 = 0x3C;
+
+/// The high 16-bits of the [`InodeBitmap`] checksum are only stored in group descriptors at least that large.
+const INODE_BITMAP_CHKSUM_HI_END: u16 = 0x3A;
+
 /// Checksum of the associated `GroupDescriptor` structure.
 #[derive(Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord, Hash, Pod, Zeroable)]
 #[repr(transparent)]
@@ -135,34 +144,36 @@ impl DerefMut for GroupDescriptor {
 }
 
 impl GroupDescriptor {
-    pub(crate) fn get_or_load_inode_bitmap(&mut self) -> &mut InodeBitmap {
-        if self.inode_bitmap.is_some() {
-            return self.inode_bitmap.as_mut().unwrap();
+    pub(crate) fn get_or_load_inode_bitmap(&mut self) -> IOResult<&mut InodeBitmap> {
+        if self.inode_bitmap.is_none() {
+            self.load_inode_bitmap()?;
         }
 
-        self.load_inode_bitmap();
-        self.inode_bitmap.as_mut().unwrap()
+        self.inode_bitmap.as_mut().ok_or(IOError::Unknown)
     }
 
-    pub(crate) fn get_or_load_blk_bitmap(&mut self) -> &mut BlockBitmap {
-        if self.block_bitmap.is_some() {
-            return self.block_bitmap.as_mut().unwrap();
+    pub(crate) fn get_or_load_blk_bitmap(&mut self) -> IOResult<&mut BlockBitmap> {
+        if self.block_bitmap.is_none() {
+            self.load_blk_bitmap()?;
         }
 
-        self.load_blk_bitmap();
-        self.block_bitmap.as_mut().unwrap()
+        self.block_bitmap.as_mut().ok_or(IOError::Unknown)
     }
+
     /// Compares the checksum of the `GroupDescriptor` to its on-disk value.
     ///
-    /// The checksum of an [`Ext4GroupDescriptor`] can be computed (after having set the checksum field to 0) using:
+    /// With the `metadata_csum` feature, the checksum of an [`Ext4GroupDescriptor`] can be computed (after having
+    /// set the checksum field to 0) using:
     ///
     /// ```
-    /// crc32c_calc(fs_uuid + group_number + group_descriptor)
+    /// crc32c_calc(fs_uuid + group_number + group_descriptor) & 0xFFFF
     /// ```
+    ///
+    /// With the older `gdt_csum` feature, `crc16` is used instead, and the checksum field is skipped.
     pub(crate) fn validate_chksum(&self) -> bool {
         let fs = self.fs.read();
         let sb = fs.superblock.read();
-        let comp_chksum = self.compute_chksum(sb.uuid);
+        let comp_chksum = self.compute_chksum(&sb);
 
         if comp_chksum != self.checksum {
             error!(
@@ -190,34 +201,13 @@ impl GroupDescriptor {
             return Err(IOError::InvalidCommand);
         }
 
-        let descriptor_size = if superblock
-            .feature_incompat
-            .includes(IncompatibleFeatureSet::EXT4_FEATURE_INCOMPAT_64BIT)
-        {
-            64
-        } else {
-            32
-        };
-
-        let initial_blk_offset = if superblock.blk_size()
-            == u64::try_from(mem::size_of::<Ext4Superblock>()).expect("invalid superblock size")
-        {
-            2
-        } else {
-            1
-        };
-
-        let descriptor_per_block = superblock.blk_size() / descriptor_size;
-        let desc_blk_id = initial_blk_offset + (id * descriptor_size) / superblock.blk_size();
-        let desc_idx_in_blk = id % descriptor_per_block;
+        let (desc_blk_id, desc_offset) = descriptor_pos(id, &superblock);
+        let descriptor_size = usize::from(superblock.desc_size());
 
         let mut desc_blk = fs.allocate_blk();
-        fs.read_blk_from_device(Ext4RealBlkId::from(desc_blk_id), &mut desc_blk)?;
+        fs.read_blk_from_device(desc_blk_id, &mut desc_blk)?;
 
-        let raw_bg_descriptor = &desc_blk[usize::try_from(desc_idx_in_blk * descriptor_size)
-            .expect("invalid group descriptor")
-            ..usize::try_from((desc_idx_in_blk + 1) * descriptor_size)
-                .expect("invalid group descriptor")];
+        let raw_bg_descriptor = &desc_blk[desc_offset..desc_offset + descriptor_size];
 
         let mut filled_descriptor = alloc::vec![0u8; mem::size_of::<Ext4GroupDescriptor>()];
         filled_descriptor[..raw_bg_descriptor.len()].copy_from_slice(raw_bg_descriptor);
@@ -238,62 +228,450 @@ impl GroupDescriptor {
     /// Loads the [`BlockBitmap`] associated to this block group.
     ///
     /// It verifies its checksum, and initializes it if need be during the process.
-    pub(crate) fn load_blk_bitmap(&mut self) {
-        let fs = self.fs.read();
+    pub(crate) fn load_blk_bitmap(&mut self) -> CanFail<IOError> {
+        let locked_fs = self.fs.clone();
+        let fs = locked_fs.read();
         let sb = fs.superblock.read();
+
+        if self.has_flag(GroupDescriptorFlags::EXT4_BG_BLOCK_UNINIT) {
+            let bitmap = self.init_blk_bitmap(&fs, &sb)?;
+            self.block_bitmap = Some(bitmap);
+
+            return Ok(());
+        }
+
         let mut blk_bitmap_buf = fs.allocate_blk();
 
-        fs.read_blk_from_device(self.block_bitmap_blk_addr(), &mut blk_bitmap_buf)
-            .unwrap();
+        fs.read_blk_from_device(self.block_bitmap_blk_addr(), &mut blk_bitmap_buf)?;
         let bitmap = BlockBitmap::from_bytes(
             &blk_bitmap_buf
                 [..usize::try_from(sb.blocks_per_group / 8).expect("invalid block bitmap size")],
         );
-        let chksum = self.block_bitmap_csum_lo + self.block_bitmap_csum_hi;
-        bitmap.validate_chksum(sb.uuid, cast(chksum));
+
+        if sb.has_metadata_chksum() {
+            let chksum = self.block_bitmap_csum_lo + self.block_bitmap_csum_hi;
+            bitmap.validate_chksum(
+                sb.uuid,
+                chksum,
+                sb.desc_size() >= BLOCK_BITMAP_CHKSUM_HI_END,
+            );
+        }
 
         self.block_bitmap = Some(bitmap);
+
+        Ok(())
     }
 
     /// Returns the [`InodeBitmap`] associated to this block group.
     ///
     /// It verifies its checksum, and initializes it if need be during the process.
-    pub(crate) fn load_inode_bitmap(&mut self) {
+    pub(crate) fn load_inode_bitmap(&mut self) -> CanFail<IOError> {
         let fs = self.fs.read();
         let sb = fs.superblock.read();
+        let bitmap_size =
+            usize::try_from(sb.inodes_per_group / 8).expect("invalid inode bitmap size");
+
+        // no inode of the group is in use
+        if self.has_flag(GroupDescriptorFlags::EXT4_BG_INODE_UNINIT) {
+            self.inode_bitmap = Some(InodeBitmap::from_bytes(&alloc::vec![0; bitmap_size]));
+
+            return Ok(());
+        }
+
         let mut inode_bitmap_buf = fs.allocate_blk();
 
-        fs.read_blk_from_device(self.inode_bitmap_blk_addr(), &mut inode_bitmap_buf)
-            .unwrap();
-        let bitmap = InodeBitmap::from_bytes(
-            &inode_bitmap_buf
-                [..usize::try_from(sb.inodes_per_group / 8).expect("invalid inode bitmap size")],
-        );
-        let chksum = self.inode_bitmap_csum_lo + self.inode_bitmap_csum_hi;
-        bitmap.validate_chksum(sb.uuid, cast(chksum));
+        fs.read_blk_from_device(self.inode_bitmap_blk_addr(), &mut inode_bitmap_buf)?;
+        let bitmap = InodeBitmap::from_bytes(&inode_bitmap_buf[..bitmap_size]);
+
+        if sb.has_metadata_chksum() {
+            let chksum = self.inode_bitmap_csum_lo + self.inode_bitmap_csum_hi;
+            bitmap.validate_chksum(
+                sb.uuid,
+                chksum,
+                sb.desc_size() >= INODE_BITMAP_CHKSUM_HI_END,
+            );
+        }
 
         self.inode_bitmap = Some(bitmap);
+
+        Ok(())
     }
 
-    fn compute_chksum(&self, fs_uuid: Ext4FsUuid) -> GroupDescriptorChksum {
-        let mut chksum_bytes: Vec<u8> = alloc::vec![];
+    /// Builds the [`BlockBitmap`] of a block group whose bitmap is not initialized on disk
+    /// (`EXT4_BG_BLOCK_UNINIT`).
+    ///
+    /// Only filesystem metadata is allocated in such a group: the backups of the superblock and of the group
+    /// descriptors table, and the bitmaps and inode tables stored in this group (with the `flex_bg` feature, the
+    /// ones of other block groups may be stored there as well).
+    fn init_blk_bitmap(&self, fs: &Ext4Fs, sb: &Ext4Superblock) -> IOResult<BlockBitmap> {
+        let blk_size = sb.blk_size();
+        let blks_per_group = cast::<Ext4BlkCount, u64>(sb.blocks_per_group.into());
+        let first_blk = cast::<Ext4RealBlkId, u64>(sb.bg_first_blk(self.group_number));
+        let group_blks = u64::min(
+            blks_per_group,
+            cast::<Ext4BlkCount, u64>(sb.blk_count()) - first_blk,
+        );
 
-        chksum_bytes.extend_from_slice(bytes_of(&fs_uuid));
-        chksum_bytes.extend_from_slice(bytes_of(&self.group_number));
+        let mut bitmap = BlockBitmap::from_bytes(&alloc::vec![
+            0;
+            usize::try_from(blks_per_group / 8)
+                .expect("invalid block bitmap size")
+        ]);
+        let mut mark_used = |start: u64, count: u64| {
+            let end = u64::min(start.saturating_add(count), group_blks);
+
+            if start < end {
+                bitmap.mark_blk_range_used(Ext4RealBlkId::from(start)..Ext4RealBlkId::from(end));
+            }
+        };
+
+        // blocks past the end of the filesystem are always marked in-use
+        mark_used(group_blks, blks_per_group - group_blks);
+
+        if sb.bg_has_sb_backup(self.group_number) {
+            let bg_count = u64::from(cast::<BlockGroupNumber, u32>(sb.bg_count()));
+            let gdt_blks = (bg_count * u64::from(sb.desc_size())).div_ceil(blk_size);
+            let reserved_gdt_blks = cast::<Ext4BlkCount, u64>(sb.reserved_gdt_blocks.into());
+
+            mark_used(0, 1 + gdt_blks + reserved_gdt_blks);
+        }
+
+        let inode_table_blks = (u64::from(cast::<InodeCount, u32>(sb.inodes_per_group))
+            * u64::from(sb.inode_entry_size()))
+        .div_ceil(blk_size);
+
+        for bg in 0..cast::<BlockGroupNumber, u32>(sb.bg_count()) {
+            let bg = cast::<u32, BlockGroupNumber>(bg);
+
+            // the descriptor of this group is already locked
+            let descriptor = if bg == self.group_number {
+                self.descriptor
+            } else {
+                fs.get_group_descriptor(bg)
+                    .ok_or(IOError::Unknown)?
+                    .read()
+                    .descriptor
+            };
+
+            for (start, count) in [
+                (descriptor.block_bitmap_blk_addr(), 1),
+                (descriptor.inode_bitmap_blk_addr(), 1),
+                (descriptor.inode_table_blk_addr(), inode_table_blks),
+            ] {
+                let start = cast::<Ext4RealBlkId, u64>(start);
+
+                if start >= first_blk && start < first_blk + blks_per_group {
+                    mark_used(start - first_blk, count);
+                }
+            }
+        }
+
+        Ok(bitmap)
+    }
+
+    /// Looks for a run of free blocks in this block group, starting from the block of index `from` in the group.
+    ///
+    /// Returns the index of the first block of the run in the group, and its length (at most `max_len` blocks), or
+    /// `None` if there is no free block left past `from`.
+    pub(crate) fn find_free_blks(
+        &mut self,
+        from: u64,
+        max_len: u64,
+    ) -> IOResult<Option<(u64, u64)>> {
+        let blks_per_group = {
+            let fs = self.fs.read();
+            let sb = fs.superblock.read();
+
+            cast::<Ext4BlkCount, u64>(sb.blocks_per_group.into())
+        };
+
+        let free_blks = self.get_or_load_blk_bitmap()?.available_blks_in_range(
+            Ext4RealBlkId::from(from)..Ext4RealBlkId::from(blks_per_group),
+        );
+        let Some(&first) = free_blks.first() else {
+            return Ok(None);
+        };
+
+        let run_len = (0..)
+            .zip(&free_blks)
+            .take_while(|&(idx, &blk)| blk == first + idx)
+            .take(usize::try_from(max_len).unwrap_or(usize::MAX))
+            .count();
+
+        Ok(Some((
+            cast::<Ext4RealBlkId, u64>(first),
+            u64::try_from(run_len).expect("invalid block count"),
+        )))
+    }
+
+    /// Marks `count` blocks of this block group as in-use, starting from the block of index `first` in the group.
+    ///
+    /// The block bitmap and the group descriptor are written back to disk.
+    pub(crate) fn mark_blks_used(&mut self, first: u64, count: u64) -> CanFail<IOError> {
+        self.get_or_load_blk_bitmap()?
+            .mark_blk_range_used(Ext4RealBlkId::from(first)..Ext4RealBlkId::from(first + count));
+
+        let free_blk_count = cast::<Ext4BlkCount, u64>(self.free_blk_count()) - count;
+        self.descriptor.set_free_blk_count(free_blk_count);
+        self.write_blk_bitmap()
+    }
+
+    /// Marks `count` blocks of this block group as free, starting from the block of index `first` in the group.
+    ///
+    /// The block bitmap and the group descriptor are written back to disk.
+    pub(crate) fn mark_blks_free(&mut self, first: u64, count: u64) -> CanFail<IOError> {
+        self.get_or_load_blk_bitmap()?
+            .free_blk_range(Ext4RealBlkId::from(first)..Ext4RealBlkId::from(first + count));
+
+        let free_blk_count = cast::<Ext4BlkCount, u64>(self.free_blk_count()) + count;
+        self.descriptor.set_free_blk_count(free_blk_count);
+        self.write_blk_bitmap()
+    }
+
+    /// Looks for a free entry of the inode table of this block group, starting from the entry of index `from` in the
+    /// group.
+    ///
+    /// Returns the index of the entry in the group, or `None` if there is no free entry left past `from`.
+    pub(crate) fn find_free_inode(&mut self, from: u32) -> IOResult<Option<u32>> {
+        let inodes_per_group = {
+            let fs = self.fs.read();
+            let sb = fs.superblock.read();
+
+            cast::<InodeCount, u32>(sb.inodes_per_group)
+        };
+
+        let free_inodes = self.get_or_load_inode_bitmap()?.available_inodes_in_range(
+            InodeNumber::from(usize::try_from(from).expect("invalid inode index"))
+                ..InodeNumber::from(
+                    usize::try_from(inodes_per_group).expect("invalid inode index"),
+                ),
+        );
+
+        Ok(free_inodes.first().map(|&inode_idx| u32::from(inode_idx)))
+    }
+
+    /// Marks the entry of index `idx` of the inode table of this block group as in-use.
+    ///
+    /// The inode bitmap and the group descriptor are written back to disk. The block bitmap of the group is
+    /// initialized if need be, as the kernel expects block groups with in-use inodes to have an initialized block
+    /// bitmap.
+    pub(crate) fn mark_inode_used(&mut self, idx: u32, is_dir: bool) -> CanFail<IOError> {
+        self.get_or_load_inode_bitmap()?
+            .set_inode_in_use(InodeNumber::from(
+                usize::try_from(idx).expect("invalid inode index"),
+            ));
+
+        let inodes_per_group = {
+            let fs = self.fs.read();
+            let sb = fs.superblock.read();
+
+            cast::<InodeCount, u32>(sb.inodes_per_group)
+        };
+
+        // entries past the last one in use are not initialized, and are skipped by `e2fsck`
+        let unused_inodes_count = cast::<InodeCount, u32>(self.unused_inodes_count());
+        if idx >= inodes_per_group.saturating_sub(unused_inodes_count) {
+            self.descriptor
+                .set_unused_inodes_count(inodes_per_group - idx - 1);
+        }
+
+        let free_inode_count = cast::<InodeCount, u32>(self.free_inode_count()) - 1;
+        self.descriptor.set_free_inode_count(free_inode_count);
 
+        if is_dir {
+            let directory_count = self.directory_count() + 1;
+            self.descriptor.set_directory_count(directory_count);
+        }
+
+        if self.has_flag(GroupDescriptorFlags::EXT4_BG_BLOCK_UNINIT) {
+            self.get_or_load_blk_bitmap()?;
+            self.write_blk_bitmap()?;
+        }
+
+        self.write_inode_bitmap()
+    }
+
+    /// Marks the entry of index `idx` of the inode table of this block group as free.
+    ///
+    /// The inode bitmap and the group descriptor are written back to disk.
+    pub(crate) fn mark_inode_free(&mut self, idx: u32, is_dir: bool) -> CanFail<IOError> {
+        self.get_or_load_inode_bitmap()?
+            .free_inode(InodeNumber::from(
+                usize::try_from(idx).expect("invalid inode index"),
+            ));
+
+        let free_inode_count = cast::<InodeCount, u32>(self.free_inode_count()) + 1;
+        self.descriptor.set_free_inode_count(free_inode_count);
+
+        if is_dir {
+            let directory_count = self.directory_count().saturating_sub(1);
+            self.descriptor.set_directory_count(directory_count);
+        }
+
+        self.write_inode_bitmap()
+    }
+
+    /// Writes the [`InodeBitmap`] of this block group back to disk, followed by the group descriptor (with the
+    /// updated checksum of the bitmap).
+    ///
+    /// Once written, the bitmap is initialized on disk: the `EXT4_BG_INODE_UNINIT` flag is cleared.
+    fn write_inode_bitmap(&mut self) -> CanFail<IOError> {
+        let locked_fs = self.fs.clone();
+        let fs = locked_fs.read();
+        let sb = fs.superblock.read();
+        let fs_uuid = sb.uuid;
+        let has_metadata_chksum = sb.has_metadata_chksum();
+        let has_chksum_hi = sb.desc_size() >= INODE_BITMAP_CHKSUM_HI_END;
+        drop(sb);
+
+        let bitmap = self.inode_bitmap.as_ref().ok_or(IOError::Unknown)?;
+        let raw_bitmap = bitmap.to_bytes();
+
+        if has_metadata_chksum {
+            let chksum = bitmap.compute_chksum(fs_uuid);
+
+            self.descriptor.inode_bitmap_csum_lo = chksum.into();
+            if has_chksum_hi {
+                self.descriptor.inode_bitmap_csum_hi = chksum.into();
+            }
+        }
+
+        // the end of the block is padding when groups hold less inodes than the bitmap, it is kept as is
+        let bitmap_blk_id = self.inode_bitmap_blk_addr();
+        let mut bitmap_blk = fs.allocate_blk();
+
+        if self.has_flag(GroupDescriptorFlags::EXT4_BG_INODE_UNINIT) {
+            bitmap_blk.fill(0xFF);
+            self.descriptor.flags = self.flags ^ GroupDescriptorFlags::EXT4_BG_INODE_UNINIT;
+        } else {
+            fs.read_blk_from_device(bitmap_blk_id, &mut bitmap_blk)?;
+        }
+
+        bitmap_blk[..raw_bitmap.len()].copy_from_slice(&raw_bitmap);
+        fs.write_blk_to_device(bitmap_blk_id, &bitmap_blk)?;
+        drop(fs);
+
+        self.write_descriptor()
+    }
+
+    /// Writes the [`BlockBitmap`] of this block group back to disk, followed by the group descriptor (with the
+    /// updated checksum of the bitmap).
+    ///
+    /// Once written, the bitmap is initialized on disk: the `EXT4_BG_BLOCK_UNINIT` flag is cleared.
+    fn write_blk_bitmap(&mut self) -> CanFail<IOError> {
+        let locked_fs = self.fs.clone();
+        let fs = locked_fs.read();
+        let sb = fs.superblock.read();
+        let fs_uuid = sb.uuid;
+        let has_metadata_chksum = sb.has_metadata_chksum();
+        let has_chksum_hi = sb.desc_size() >= BLOCK_BITMAP_CHKSUM_HI_END;
+        drop(sb);
+
+        let bitmap = self.block_bitmap.as_ref().ok_or(IOError::Unknown)?;
+        let raw_bitmap = bitmap.to_bytes();
+
+        if has_metadata_chksum {
+            let chksum = bitmap.compute_chksum(fs_uuid);
+
+            self.descriptor.block_bitmap_csum_lo = chksum.into();
+            if has_chksum_hi {
+                self.descriptor.block_bitmap_csum_hi = chksum.into();
+            }
+        }
+
+        // the end of the block is padding when groups are smaller than the block bitmap, it is kept as is
+        let bitmap_blk_id = self.block_bitmap_blk_addr();
+        let mut bitmap_blk = fs.allocate_blk();
+
+        if self.has_flag(GroupDescriptorFlags::EXT4_BG_BLOCK_UNINIT) {
+            bitmap_blk.fill(0xFF);
+            self.descriptor.flags = self.flags ^ GroupDescriptorFlags::EXT4_BG_BLOCK_UNINIT;
+        } else {
+            fs.read_blk_from_device(bitmap_blk_id, &mut bitmap_blk)?;
+        }
+
+        bitmap_blk[..raw_bitmap.len()].copy_from_slice(&raw_bitmap);
+        fs.write_blk_to_device(bitmap_blk_id, &bitmap_blk)?;
+        drop(fs);
+
+        self.write_descriptor()
+    }
+
+    /// Writes this `GroupDescriptor` back to disk, after having updated its checksum.
+    pub(crate) fn write_descriptor(&mut self) -> CanFail<IOError> {
+        let locked_fs = self.fs.clone();
+        let fs = locked_fs.read();
+        let sb = fs.superblock.read();
+        let chksum = self.compute_chksum(&sb);
+        let (desc_blk_id, desc_offset) = descriptor_pos(self.group_number, &sb);
+        let descriptor_size = usize::from(sb.desc_size());
+        drop(sb);
+
+        self.descriptor.set_chksum(chksum);
+
+        let mut desc_blk = fs.allocate_blk();
+        fs.read_blk_from_device(desc_blk_id, &mut desc_blk)?;
+
+        desc_blk[desc_offset..desc_offset + descriptor_size]
+            .copy_from_slice(&bytes_of(&self.descriptor)[..descriptor_size]);
+
+        fs.write_blk_to_device(desc_blk_id, &desc_blk)
+    }
+
+    /// Checks if one or more flags are set for this block group.
+    pub(crate) fn has_flag(&self, flag: GroupDescriptorFlags) -> bool {
+        self.flags & flag != GroupDescriptorFlags(0)
+    }
+
+    fn compute_chksum(&self, sb: &Ext4Superblock) -> GroupDescriptorChksum {
         let mut desc_no_chksum: Ext4GroupDescriptor = self.descriptor;
         desc_no_chksum.set_chksum(GroupDescriptorChksum::ERASE_CHKSUM);
 
-        chksum_bytes.extend_from_slice(bytes_of(&desc_no_chksum));
+        let raw_desc = &bytes_of(&desc_no_chksum)[..usize::from(sb.desc_size())];
+
+        let fs_uuid = sb.uuid;
+
+        let mut chksum_bytes: Vec<u8> = alloc::vec![];
+        chksum_bytes.extend_from_slice(bytes_of(&fs_uuid));
+        chksum_bytes.extend_from_slice(bytes_of(&self.group_number));
+
+        if sb.has_metadata_chksum() {
+            chksum_bytes.extend_from_slice(raw_desc);
 
-        let comp_chksum: u16 = (crc32c_calc(&chksum_bytes) & 0xFFFF)
-            .try_into()
-            .expect("invalid group descriptor chksum");
+            let comp_chksum: u16 = (crc32c_calc(&chksum_bytes) & 0xFFFF)
+                .try_into()
+                .expect("invalid group descriptor chksum");
 
-        cast(comp_chksum)
+            cast(comp_chksum)
+        } else if sb
+            .feature_ro_compat
+            .includes(ReadOnlyCompatibleFeatureSet::EXT4_FEATURE_R0_COMPAT_GDT_CSUM)
+        {
+            chksum_bytes.extend_from_slice(&raw_desc[..CHKSUM_OFFSET]);
+            chksum_bytes.extend_from_slice(
+                &raw_desc[CHKSUM_OFFSET + mem::size_of::<GroupDescriptorChksum>()..],
+            );
+
+            cast(crc16_calc(&chksum_bytes))
+        } else {
+            GroupDescriptorChksum::ERASE_CHKSUM
+        }
     }
 }
 
+/// Returns the position of the descriptor of a block group on disk.
+///
+/// The position is a tuple `(descriptor_blk, descriptor_byte_offset_in_blk)`. The group descriptors table starts in
+/// the block following the one containing the superblock.
+fn descriptor_pos(id: BlockGroupNumber, sb: &Ext4Superblock) -> (Ext4RealBlkId, usize) {
+    let desc_byte_offset = id * u64::from(sb.desc_size());
+
+    (
+        sb.first_data_blk() + 1 + desc_byte_offset / sb.blk_size(),
+        usize::try_from(desc_byte_offset % sb.blk_size()).expect("invalid group descriptor"),
+    )
+}
+
 /// Each block group on the file system has a `GroupDescriptor` associated with it.
 ///
 /// A `block group` is a logical grouping of contiguous block.
@@ -400,6 +778,14 @@ impl Ext4GroupDescriptor {
             .into()
     }
 
+    /// Sets the count of free blocks in this block group.
+    pub(crate) fn set_free_blk_count(&mut self, count: u64) {
+        self.free_blocks_count_lo =
+            cast(u16::try_from(count & 0xFFFF).expect("invalid conversion"));
+        self.free_blocks_count_hi =
+            cast(u16::try_from((count >> 16) & 0xFFFF).expect("invalid conversion"));
+    }
+
     /// Returns the count of free [`Inode`] in this block group.
     pub(crate) fn free_inode_count(&self) -> InodeCount {
         self.free_inodes_count_lo
@@ -415,6 +801,29 @@ impl Ext4GroupDescriptor {
     pub(crate) fn unused_inodes_count(&self) -> InodeCount {
         self.itable_unused_lo.add_high_bits(self.itable_unused_hi)
     }
+
+    /// Sets the count of free [`Inode`] in this block group.
+    pub(crate) fn set_free_inode_count(&mut self, count: u32) {
+        (self.free_inodes_count_lo, self.free_inodes_count_hi) = split_count(count);
+    }
+
+    /// Sets the count of [`Ext4Directory`] that belongs to this block group.
+    pub(crate) fn set_directory_count(&mut self, count: u32) {
+        (self.used_dirs_count_lo, self.used_dirs_count_hi) = split_count(count);
+    }
+
+    /// Sets the number of unused [`Inode`] entries in the inode table for this block group.
+    pub(crate) fn set_unused_inodes_count(&mut self, count: u32) {
+        (self.itable_unused_lo, self.itable_unused_hi) = split_count(count);
+    }
+}
+
+/// Splits a 32-bit count into the low and high 16-bit fields of an [`Ext4GroupDescriptor`].
+fn split_count<T: Pod>(count: u32) -> (T, T) {
+    (
+        cast(u16::try_from(count & 0xFFFF).expect("invalid conversion")),
+        cast(u16::try_from(count >> 16).expect("invalid conversion")),
+    )
 }
 
 pub(super) type LockedGroupDescriptor = Arc<RwLock<GroupDescriptor>>;
@@ -0,0 +1,196 @@
+//! ext2/ext3 logical block map implementation
+//!
+//! Inodes which do not use an extent tree (every inode of `ext2` and `ext3` filesystems, and old inodes of upgraded
+//! `ext4` filesystems) map their file blocks to physical blocks with the 15 block pointers stored in `i_block`:
+//!
+//! - 0 -> 11 : direct pointers to file blocks 0 - 11
+//!
+//! - 12: pointer to an indirect block, filled with pointers to file blocks
+//!
+//! - 13: pointer to a double-indirect block, filled with pointers to indirect blocks
+//!
+//! - 14: pointer to a triple-indirect block, filled with pointers to double-indirect blocks
+//!
+//! A null pointer denotes a hole in a sparse file: the blocks it would have covered are not allocated, and read as
+//! zeros.
+
+use alloc::vec::Vec;
+use bytemuck::cast;
+
+use crate::errors::IOError;
+use crate::fs::ext4::extent::{Ext4InodeRelBlkId, Ext4RealBlkId};
+use crate::fs::ext4::inode::{InodeSize, LockedInodeStrongRef};
+use crate::fs::ext4::{Ext4Fs, LockedExt4Fs};
+use crate::fs::IOResult;
+
+/// Number of direct block pointers in the block map of an inode.
+const DIRECT_BLOCKS: usize = 12;
+
+/// Size of a block pointer, in bytes.
+const BLOCK_POINTER_SIZE: usize = 4;
+
+/// Run of file blocks stored contiguously on disk.
+#[derive(Clone, Copy, Debug)]
+struct BlockRun {
+    /// First file block covered by the run.
+    block: u64,
+
+    /// Physical block storing the first file block of the run.
+    start: u64,
+
+    /// Number of blocks covered by the run.
+    len: u64,
+}
+
+/// Internal ext2/ext3 block map representation.
+///
+/// The block map is entirely loaded to memory, and stored as a sorted list of runs of contiguous blocks, so that
+/// indirect blocks do not have to be read again for each access.
+#[derive(Clone, Default)]
+pub(crate) struct BlockMap {
+    runs: Vec<BlockRun>,
+}
+
+impl core::fmt::Debug for BlockMap {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        for run in &self.runs {
+            f.write_fmt(format_args!(
+                "({}-{}) -> {} \n",
+                run.block,
+                run.block + run.len,
+                run.start
+            ))?;
+        }
+
+        Ok(())
+    }
+}
+
+impl BlockMap {
+    /// Loads the entire block map associated with an [`Ext4Inode`] to memory.
+    ///
+    /// Returns `None` if the inode uses an extent tree or stores its content inline instead, or if the block map is
+    /// corrupted (pointers to blocks outside of the filesystem).
+    ///
+    /// [`Ext4Inode`]: crate::fs::ext4::inode::Ext4Inode
+    pub(crate) fn load_block_map(
+        locked_fs: &LockedExt4Fs,
+        locked_inode: &LockedInodeStrongRef,
+    ) -> Option<Self> {
+        let fs = locked_fs.read();
+        let inode = locked_inode.read();
+
+        if inode.uses_extent_tree() || inode.has_inline_data() {
+            return None;
+        }
+
+        let blk_size = fs.superblock.read().blk_size();
+
+        // blocks past the end of the file are never mapped, even if the pointers are not null
+        let blk_count = cast::<InodeSize, u64>(inode.size()).div_ceil(blk_size);
+        let pointers = inode.i_block.as_blk_pointers();
+        drop(inode);
+
+        let pointers_per_blk =
+            blk_size / u64::try_from(BLOCK_POINTER_SIZE).expect("invalid pointer size");
+        let mut block_map = Self::default();
+        let mut first_blk = 0;
+
+        for (idx, &pointer) in pointers.iter().enumerate() {
+            let depth = u32::try_from(idx.saturating_sub(DIRECT_BLOCKS - 1))
+                .expect("invalid block map depth");
+
+            block_map
+                .traverse_indirect_layer(&fs, pointer, depth, first_blk, blk_count)
+                .ok()?;
+
+            first_blk = first_blk.saturating_add(pointers_per_blk.saturating_pow(depth));
+        }
+
+        Some(block_map)
+    }
+
+    /// Returns the physical block address corresponding to a logical block of the [`Ext4Inode`].
+    ///
+    /// Returns `None` if that block is not allocated (because it is located in a hole of a sparse file, or past the
+    /// end of the file).
+    ///
+    /// [`Ext4Inode`]: crate::fs::ext4::inode::Ext4Inode
+    pub(crate) fn get_exact_blk_mapping(&self, blk_id: Ext4InodeRelBlkId) -> Option<Ext4RealBlkId> {
+        let blk_id = cast::<Ext4InodeRelBlkId, u64>(blk_id);
+        let run_idx = self
+            .runs
+            .partition_point(|run| run.block + run.len <= blk_id);
+        let run = self.runs.get(run_idx)?;
+
+        if run.block > blk_id {
+            return None;
+        }
+
+        Some(Ext4RealBlkId::from(run.start + (blk_id - run.block)))
+    }
+
+    /// Block map traversal routine.
+    ///
+    /// Maps the file blocks covered by `pointer`, which points to an indirect block of the given `depth` (or to a
+    /// data block if `depth == 0`), and whose first file block is `first_blk`.
+    fn traverse_indirect_layer(
+        &mut self,
+        fs: &Ext4Fs,
+        pointer: u32,
+        depth: u32,
+        first_blk: u64,
+        blk_count: u64,
+    ) -> IOResult<()> {
+        if pointer == 0 || first_blk >= blk_count {
+            return Ok(());
+        }
+
+        if Ext4RealBlkId::from(u64::from(pointer)) >= fs.superblock.read().blk_count() {
+            return Err(IOError::Unknown);
+        }
+
+        if depth == 0 {
+            self.push_blk(first_blk, u64::from(pointer));
+            return Ok(());
+        }
+
+        let mut indirect_blk = fs.allocate_blk();
+        fs.read_blk_from_device(Ext4RealBlkId::from(u64::from(pointer)), &mut indirect_blk)?;
+
+        let pointers_per_blk =
+            u64::try_from(indirect_blk.len() / BLOCK_POINTER_SIZE).expect("invalid block size");
+        let blks_per_pointer = pointers_per_blk.saturating_pow(depth - 1);
+
+        for (idx, raw_pointer) in (0..).zip(indirect_blk.chunks_exact(BLOCK_POINTER_SIZE)) {
+            let child_pointer =
+                u32::from_le_bytes(raw_pointer.try_into().expect("invalid block pointer"));
+
+            self.traverse_indirect_layer(
+                fs,
+                child_pointer,
+                depth - 1,
+                first_blk.saturating_add(idx * blks_per_pointer),
+                blk_count,
+            )?;
+        }
+
+        Ok(())
+    }
+
+    /// Maps a file block to a physical block, extending the last run if both are contiguous.
+    fn push_blk(&mut self, blk: u64, real_blk: u64) {
+        if let Some(last_run) = self.runs.last_mut() {
+            if last_run.block + last_run.len == blk && last_run.start + last_run.len == real_blk {
+                last_run.len += 1;
+                return;
+            }
+        }
+
+        self.runs.push(BlockRun {
+            block: blk,
+            start: real_blk,
+            len: 1,
+        });
+    }
+}
@@ -3,23 +3,33 @@
 //! Provides methods for loading and parsing directories, as defined by the `ext4` filesystem.
 //! Serves as as interface between the `ext4` definition of a directory and the abstract implementation in `FrozenBoot`
 
-use core::slice;
-
 use alloc::boxed::Box;
+use alloc::sync::Arc;
 use alloc::{format, string::String, vec::Vec};
-use bytemuck::{cast, from_bytes, try_cast, Pod, Zeroable};
+use bytemuck::{bytes_of, cast, pod_read_unaligned, Pod, Zeroable};
+use core::mem;
 
 use crate::fs::ext4::file::Ext4File;
-use crate::fs::ext4::inode::{InodeFlags, InodeType, LockedInode, LockedInodeStrongRef};
-use crate::fs::ext4::LockedExt4Fs;
-use crate::fs::{DirEntry, Directory, FsDirectory};
+use crate::fs::ext4::inode::{
+    InodeFlags, InodeHardLinkCount, InodeType, LockedInode, LockedInodeStrongRef,
+};
+use crate::fs::ext4::{crc32c_calc, Ext4Fs, LockedExt4Fs};
+use crate::fs::{DirEntry, Directory, File, FsDirectory};
+use crate::time::current_timestamp;
 use crate::{
     errors::{CanFail, IOError},
     ext4_fs_read_bytes,
     fs::{
         ext4::{
-            extent::{Ext4InodeRelBlkId, Ext4InodeRelBlkIdRange},
-            inode::{InodeFileMode, InodeNumber, InodeSize},
+            blockmap::BlockMap,
+            extent::Ext4InodeRelBlkId,
+            htree::{DxEntries, DxHash, DxRoot},
+            inline::load_inline_dir,
+            inode::{InodeFileMode, InodeGeneration, InodeNumber, InodeSize},
+            sb::{
+                CompatibleFeatureSet, Ext4FsUuid, Ext4SuperblockFlags, IncompatibleFeatureSet,
+                ReadOnlyCompatibleFeatureSet,
+            },
             ExtentTree,
         },
         IOResult,
@@ -33,6 +43,9 @@ pub(crate) struct Ext4DirectoryEntry {
     rec_len: u16,
     name_len: u8,
 
+    /// Offset of this entry from the beginning of the directory, in bytes
+    offset: usize,
+
     /// File type associated to this entry (regular, directory, socket, ...)
     pub(crate) file_type: Option<Ext4DirectoryFileType>,
 
@@ -46,23 +59,38 @@ impl Ext4DirectoryEntry {
     /// Maximum ext4 directory entry size in bytes
     pub(crate) const MAX_ENTRY_SIZE: usize = 263;
 
+    /// Returns the file type of this entry.
+    ///
+    /// The file type is only stored in directory entries if the `filetype` feature is enabled. Otherwise, it is read
+    /// from the inode of the entry.
+    pub(crate) fn entry_type(&self) -> Option<Ext4DirectoryFileType> {
+        if let Some(file_type) = self.file_type {
+            return Some(file_type);
+        }
+
+        let fs = self.fs.read();
+        let inode = fs.get_inode(self.inode_number)?.upgrade()?;
+        let inode_type = inode.read().inode_type();
+
+        Some(inode_type.into())
+    }
+
     /// Consumes this `Ext4DirectoryEntry` into a [`Ext4Directory`].
     ///
     /// The file type associated with the entry must be [`Ext4DirectoryFileType::DIRECTORY`].
     #[must_use]
     pub(crate) fn as_directory(&self) -> Option<GenericExt4Directory> {
-        if let Some(file_type) = self.file_type {
-            if file_type == Ext4DirectoryFileType::DIRECTORY {
-                let fs = self.fs.read();
-                let inode = fs.get_inode(self.inode_number)?;
-                drop(fs);
-                return Some(GenericExt4Directory {
-                    dir: Ext4Directory::from_inode(self.fs.clone(), &inode).ok()?,
-                });
-            }
+        if self.entry_type()? != Ext4DirectoryFileType::DIRECTORY {
+            return None;
         }
 
-        None
+        let fs = self.fs.read();
+        let inode = fs.get_inode(self.inode_number)?;
+        drop(fs);
+
+        Some(GenericExt4Directory {
+            dir: Ext4Directory::from_inode(self.fs.clone(), &inode).ok()?,
+        })
     }
 
     /// Consumes this `Ext4DirectoryEntry` into a [`Ext4File`].
@@ -70,16 +98,15 @@ impl Ext4DirectoryEntry {
     /// The file type associated with the entry must be [`Ext4DirectoryFileType::REGULAR`].
     #[must_use]
     pub(crate) fn as_file(&self) -> Option<Ext4File> {
-        if let Some(file_type) = self.file_type {
-            if file_type == Ext4DirectoryFileType::REGULAR {
-                let fs = self.fs.read();
-                let inode = fs.get_inode(self.inode_number)?;
-                drop(fs);
-                return Ext4File::from_inode(self.fs.clone(), &inode).ok();
-            }
+        if self.entry_type()? != Ext4DirectoryFileType::REGULAR {
+            return None;
         }
 
-        None
+        let fs = self.fs.read();
+        let inode = fs.get_inode(self.inode_number)?;
+        drop(fs);
+
+        Ext4File::from_inode(self.fs.clone(), &inode).ok()
     }
 }
 
@@ -106,41 +133,36 @@ impl Ext4DirectoryFileType {
     pub(crate) const SYMLINK: Self = Self(0x7);
 }
 
+impl From<InodeType> for Ext4DirectoryFileType {
+    fn from(value: InodeType) -> Self {
+        match value {
+            InodeType::Regular => Self::REGULAR,
+            InodeType::Directory => Self::DIRECTORY,
+            InodeType::FIFO => Self::FIFO,
+            InodeType::CharacterDevice => Self::CHAR_DEVICE,
+            InodeType::BlockDevice => Self::BLOCK_DEVICE,
+            InodeType::SymbolicLink => Self::SYMLINK,
+            InodeType::Socket => Self::SOCKET,
+        }
+    }
+}
+
 impl TryInto<DirEntry> for Ext4DirectoryEntry {
     type Error = IOError;
 
     fn try_into(self) -> Result<DirEntry, Self::Error> {
-        if let Some(file_type) = self.file_type {
-            if file_type == Ext4DirectoryFileType::REGULAR {
-                Ok(DirEntry::File(Box::new(self.as_file().unwrap())))
-            } else if file_type == Ext4DirectoryFileType::DIRECTORY {
-                Ok(DirEntry::Directory(Box::new(self.as_directory().unwrap())))
-            } else {
-                return Err(IOError::Unknown);
-            }
-        } else {
-            // The directory entries do not contain a `file_type` field, so we must load the inode to check the type
-            let fs = self.fs.read();
-            let inode_ref = fs
-                .get_inode(self.inode_number)
-                .ok_or(IOError::Unknown)?
-                .upgrade()
-                .ok_or(IOError::Unknown)?;
-            let inode = inode_ref.read();
+        let file_type = self.entry_type().ok_or(IOError::Unknown)?;
 
-            match inode.inode_type() {
-                InodeType::Regular => {
-                    drop(inode);
-                    drop(fs);
-                    Ok(DirEntry::File(Box::new(self.as_file().unwrap())))
-                }
-                InodeType::Directory => {
-                    drop(inode);
-                    drop(fs);
-                    Ok(DirEntry::Directory(Box::new(self.as_directory().unwrap())))
-                }
-                _ => Err(IOError::Unknown),
-            }
+        if file_type == Ext4DirectoryFileType::REGULAR {
+            Ok(DirEntry::File(Box::new(
+                self.as_file().ok_or(IOError::Unknown)?,
+            )))
+        } else if file_type == Ext4DirectoryFileType::DIRECTORY {
+            Ok(DirEntry::Directory(Box::new(
+                self.as_directory().ok_or(IOError::Unknown)?,
+            )))
+        } else {
+            Err(IOError::Unknown)
         }
     }
 }
@@ -181,6 +203,8 @@ pub(crate) struct Ext4Directory {
     fs: LockedExt4Fs,
     internal_cursor: usize,
     extent_tree: Option<ExtentTree>,
+    block_map: Option<BlockMap>,
+    inline_data: Option<Vec<u8>>,
 }
 
 impl core::fmt::Debug for Ext4Directory {
@@ -198,6 +222,14 @@ impl core::fmt::Debug for Ext4Directory {
             f.write_str(&format!("Extents: \n{extent_tree:?}"))?;
         }
 
+        if let Some(block_map) = &self.block_map {
+            f.write_str(&format!("Block map: \n{block_map:?}"))?;
+        }
+
+        if let Some(inline_data) = &self.inline_data {
+            f.write_str(&format!("Inline data: {} bytes", inline_data.len()))?;
+        }
+
         Ok(())
     }
 }
@@ -206,52 +238,67 @@ impl Iterator for Ext4Directory {
     type Item = Ext4DirectoryEntry;
 
     fn next(&mut self) -> Option<Self::Item> {
-        let inode = self.inode.read();
+        let dir_size = self.dir_size();
+
+        let has_file_type = self
+            .fs
+            .read()
+            .superblock
+            .read()
+            .feature_incompat
+            .includes(IncompatibleFeatureSet::EXT4_FEATURE_INCOMPAT_FILETYPE);
         let mut raw_entry = [0u8; Ext4DirectoryEntry::MAX_ENTRY_SIZE];
-        let count_to_read = u64::min(
-            cast(inode.size() - self.internal_cursor.try_into().ok()?),
-            u64::try_from(Ext4DirectoryEntry::MAX_ENTRY_SIZE)
-                .expect("invalid directory entry size"),
-        )
-        .try_into()
-        .ok()?;
-
-        if count_to_read <= 8 {
-            self.internal_cursor = 0;
-            return None;
-        }
 
-        unsafe {
-            self.ext4_read_bytes(self.internal_cursor, count_to_read, &mut raw_entry)
-                .ok()?;
-        }
+        loop {
+            let count_to_read = usize::min(
+                dir_size.saturating_sub(self.internal_cursor),
+                Ext4DirectoryEntry::MAX_ENTRY_SIZE,
+            );
 
-        let inode_number: InodeNumber = *from_bytes(&raw_entry[..4]);
-        let rec_len = u16::from_le_bytes(raw_entry[4..6].try_into().ok()?);
-        let name_len = raw_entry[6];
-        let file_type: Option<Ext4DirectoryFileType> = Some(*from_bytes(&[raw_entry[7]]));
-        let raw_name: Vec<u8> = raw_entry[8..8 + usize::from(name_len)].to_vec();
+            if count_to_read <= 8 {
+                self.internal_cursor = 0;
+                return None;
+            }
 
-        if inode_number == InodeNumber::UNUSED_DIR_ENTRY {
-            self.internal_cursor = 0;
-            return None;
-        }
+            unsafe {
+                self.ext4_read_bytes(self.internal_cursor, count_to_read, &mut raw_entry)
+                    .ok()?;
+            }
 
-        let name = Ext4Filename(raw_name);
+            let inode_number: InodeNumber = pod_read_unaligned(&raw_entry[..4]);
+            let rec_len = u16::from_le_bytes(raw_entry[4..6].try_into().ok()?);
+            let name_len = raw_entry[6];
+            // without the `filetype` feature, this byte holds the high bits of the name length instead
+            let file_type: Option<Ext4DirectoryFileType> =
+                has_file_type.then(|| cast(raw_entry[7]));
 
-        self.internal_cursor = usize::min(
-            self.internal_cursor + usize::from(rec_len),
-            usize::try_from(cast::<InodeSize, u64>(inode.size())).expect("invalid inode size"),
-        );
+            // a record too short to hold an entry header can only be found in a corrupted directory
+            if usize::from(rec_len) < 8 {
+                self.internal_cursor = 0;
+                return None;
+            }
 
-        Some(Ext4DirectoryEntry {
-            fs: self.fs.clone(),
-            rec_len,
-            name_len,
-            file_type,
-            name,
-            inode_number,
-        })
+            let offset = self.internal_cursor;
+            self.internal_cursor = usize::min(offset + usize::from(rec_len), dir_size);
+
+            // unused entries (deleted entries, or checksum tails at the end of directory blocks) do not end the
+            // directory
+            if inode_number == InodeNumber::UNUSED_DIR_ENTRY {
+                continue;
+            }
+
+            let name = Ext4Filename(raw_entry[8..8 + usize::from(name_len)].to_vec());
+
+            return Some(Ext4DirectoryEntry {
+                fs: self.fs.clone(),
+                rec_len,
+                name_len,
+                offset,
+                file_type,
+                name,
+                inode_number,
+            });
+        }
     }
 }
 
@@ -264,7 +311,12 @@ impl Iterator for GenericExt4Directory {
     type Item = DirEntry;
 
     fn next(&mut self) -> Option<Self::Item> {
-        self.dir.next()?.try_into().ok()
+        // entries which are neither files nor directories (symbolic links, devices, ...) are skipped
+        loop {
+            if let Ok(entry) = self.dir.next()?.try_into() {
+                return Some(entry);
+            }
+        }
     }
 }
 
@@ -282,15 +334,236 @@ impl FsDirectory for GenericExt4Directory {
         let inode = self.dir.inode.read();
         Ok(usize::try_from(cast::<InodeSize, u64>(inode.size())).expect("invalid file size"))
     }
+
+    fn create(&mut self, name: &str) -> IOResult<File> {
+        Ok(Box::new(self.dir.create_file(name)?))
+    }
+
+    fn mkdir(&mut self, name: &str) -> IOResult<Directory> {
+        Ok(Box::new(GenericExt4Directory {
+            dir: self.dir.create_dir(name)?,
+        }))
+    }
+
+    fn unlink(&mut self, name: &str) -> CanFail<IOError> {
+        self.dir.unlink(name)
+    }
+
+    fn rename(&mut self, old_name: &str, new_name: &str) -> CanFail<IOError> {
+        self.dir.rename(old_name, new_name)
+    }
 }
 
 impl Ext4Directory {
     /// Search this directory for a given [`Ext4Filename`].
     ///
     /// Returns the corresponding entry if available.
+    ///
+    /// Indexed directories are searched through their hashed index. The directory is scanned linearly if it is not
+    /// indexed, or if its index cannot be used (corrupted blocks, invalid checksum, unsupported hash algorithm).
     #[allow(clippy::needless_pass_by_value)]
     pub(crate) fn search(&mut self, name: Ext4Filename) -> Option<Ext4DirectoryEntry> {
-        self.find(|entry| entry.name == name)
+        let cursor = self.internal_cursor;
+        let indexed_entry = self.htree_search(&name);
+
+        // the whole directory is scanned, whatever the current position of its cursor
+        self.internal_cursor = 0;
+        let entry = match indexed_entry {
+            Ok(entry) => entry,
+            Err(_) => self.find(|entry| entry.name == name),
+        };
+        self.internal_cursor = cursor;
+
+        entry
+    }
+
+    /// Search this directory for a given [`Ext4Filename`], through its hashed index (`htree`).
+    ///
+    /// Only reads the index blocks on the path from the root of the index to the leaf covering the hash of `name`, and
+    /// that leaf (or the following ones, if the hash collides with the names stored in those).
+    ///
+    /// # Errors
+    ///
+    /// Returns [`IOError::InvalidCommand`] if this directory is not indexed, and [`IOError::Unknown`] if its index is
+    /// corrupted, uses an unsupported hash algorithm, or has an invalid checksum. May return any other variant of
+    /// [`IOError`] in case of a failure while attempting to read from disk.
+    fn htree_search(&mut self, name: &Ext4Filename) -> IOResult<Option<Ext4DirectoryEntry>> {
+        let (mut path, hash) = self.htree_path(&name.0)?;
+        let levels = path.len();
+        let blk_size = usize::try_from(self.fs.read().superblock.read().blk_size())
+            .expect("invalid block size");
+        let chksum_seed = self.dx_chksum_seed();
+        let mut blk = alloc::vec![0u8; blk_size];
+
+        loop {
+            let (entries, idx) = path.last().ok_or(IOError::Unknown)?;
+
+            if let Some(entry) = self.search_leaf(entries.block(*idx), blk_size, name) {
+                return Ok(Some(entry));
+            }
+
+            // entries with the same hash may span several leaves: the next leaf must also be searched if its hash
+            // (without the collision bit) is the hash of `name`
+            let Some(level) = path
+                .iter()
+                .rposition(|(entries, idx)| idx + 1 < entries.len())
+            else {
+                return Ok(None);
+            };
+
+            path.truncate(level + 1);
+            let (entries, idx) = &mut path[level];
+            *idx += 1;
+
+            if !entries.continues_hash(*idx, hash) {
+                return Ok(None);
+            }
+
+            while path.len() < levels {
+                let (entries, idx) = path.last().ok_or(IOError::Unknown)?;
+                let child = entries.block(*idx);
+                path.push((self.read_dx_node(child, &mut blk, chksum_seed)?, 0));
+            }
+        }
+    }
+
+    /// Walks the hashed index (`htree`) of this directory, from its root down to the leaf covering the hash of `name`.
+    ///
+    /// Returns the path from the root of the index to that leaf (the entries of each index block, and the entry
+    /// followed in each of them), and the hash of `name`.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`IOError::InvalidCommand`] if this directory is not indexed, and [`IOError::Unknown`] if its index is
+    /// corrupted, uses an unsupported hash algorithm, or has an invalid checksum. May return any other variant of
+    /// [`IOError`] in case of a failure while attempting to read from disk.
+    fn htree_path(&mut self, name: &[u8]) -> IOResult<(Vec<(DxEntries, usize)>, DxHash)> {
+        let fs = self.fs.read();
+        let sb = fs.superblock.read();
+        let inode = self.inode.read();
+
+        if !inode.has_flag(InodeFlags::EXT4_INDEX_FL)
+            || !sb
+                .feature_compat
+                .includes(CompatibleFeatureSet::EXT4_FEATURE_COMPAT_DIR_INDEX)
+        {
+            return Err(IOError::InvalidCommand);
+        }
+
+        let blk_size = usize::try_from(sb.blk_size()).expect("invalid ext4fs block size");
+        let unsigned_hash = { sb.flags }.is_flag_set(Ext4SuperblockFlags::UNSIGNED_DIR_HASH);
+        let hash_seed = { sb.hash_seed };
+
+        // the index has at most 2 levels under its root with the `largedir` feature, 1 otherwise
+        let max_levels = if sb
+            .feature_incompat
+            .includes(IncompatibleFeatureSet::EXT4_FEATURE_INCOMPAT_LARGEDIR)
+        {
+            2
+        } else {
+            1
+        };
+
+        drop(inode);
+        drop(sb);
+        drop(fs);
+
+        let chksum_seed = self.dx_chksum_seed();
+        let mut blk = alloc::vec![0u8; blk_size];
+        unsafe { self.ext4_read_bytes(0, blk_size, &mut blk)? };
+
+        let root = DxRoot::parse(&blk).ok_or(IOError::Unknown)?;
+
+        if root.indirect_levels > max_levels {
+            return Err(IOError::Unknown);
+        }
+
+        if let Some((fs_uuid, inode_id, inode_gen)) = chksum_seed {
+            if !root
+                .entries
+                .validate_chksum(&blk, fs_uuid, inode_id, inode_gen)
+            {
+                return Err(IOError::Unknown);
+            }
+        }
+
+        let hash_version = if unsigned_hash {
+            root.hash_version.as_unsigned()
+        } else {
+            root.hash_version
+        };
+        let hash = DxHash::from_name(name, hash_version, hash_seed).ok_or(IOError::Unknown)?;
+
+        // path from the root of the index to the current leaf: index block, and entry followed in that block
+        let root_idx = root.entries.lookup(hash);
+        let mut path = alloc::vec![(root.entries, root_idx)];
+
+        while path.len() <= usize::from(root.indirect_levels) {
+            let (entries, idx) = path.last().ok_or(IOError::Unknown)?;
+            let node = self.read_dx_node(entries.block(*idx), &mut blk, chksum_seed)?;
+            let node_idx = node.lookup(hash);
+            path.push((node, node_idx));
+        }
+
+        Ok((path, hash))
+    }
+
+    /// Returns the filesystem UUID, inode number and inode generation of this directory, which seed the checksums of
+    /// its index blocks on filesystems using the `metadata_csum` feature.
+    fn dx_chksum_seed(&self) -> Option<(Ext4FsUuid, InodeNumber, InodeGeneration)> {
+        let fs = self.fs.read();
+        let sb = fs.superblock.read();
+        let inode = self.inode.read();
+
+        sb.feature_ro_compat
+            .includes(ReadOnlyCompatibleFeatureSet::EXT4_FEATURE_R0_COMPAT_METADATA_CSUM)
+            .then(|| ({ sb.uuid }, inode.number, inode.generation()))
+    }
+
+    /// Reads an inner node of the hashed index of this directory, from a directory block.
+    ///
+    /// `chksum_seed` holds the filesystem UUID, inode number and inode generation of the directory when its index
+    /// blocks are checksummed.
+    fn read_dx_node(
+        &self,
+        blk_id: u32,
+        blk: &mut [u8],
+        chksum_seed: Option<(Ext4FsUuid, InodeNumber, InodeGeneration)>,
+    ) -> IOResult<DxEntries> {
+        let blk_size = blk.len();
+        let offset = usize::try_from(blk_id).expect("invalid block id") * blk_size;
+        unsafe { self.ext4_read_bytes(offset, blk_size, blk)? };
+
+        let entries = DxEntries::parse_node(blk).ok_or(IOError::Unknown)?;
+
+        if let Some((fs_uuid, inode_id, inode_gen)) = chksum_seed {
+            if !entries.validate_chksum(blk, fs_uuid, inode_id, inode_gen) {
+                return Err(IOError::Unknown);
+            }
+        }
+
+        Ok(entries)
+    }
+
+    /// Searches a leaf block of the hashed index of this directory for a given [`Ext4Filename`].
+    fn search_leaf(
+        &mut self,
+        blk_id: u32,
+        blk_size: usize,
+        name: &Ext4Filename,
+    ) -> Option<Ext4DirectoryEntry> {
+        let blk_start = usize::try_from(blk_id).expect("invalid block id") * blk_size;
+        self.internal_cursor = blk_start;
+
+        while (blk_start..blk_start + blk_size).contains(&self.internal_cursor) {
+            let entry = self.next()?;
+
+            if entry.name == *name {
+                return Some(entry);
+            }
+        }
+
+        None
     }
 
     /// Loads a `Ext4Directory` from disk, from its [`InodeNumber`].
@@ -325,6 +598,10 @@ impl Ext4Directory {
             return Err(IOError::Unknown);
         }
 
+        let inline_data = load_inline_dir(&inode);
+        drop(inode);
+
+        let block_map = BlockMap::load_block_map(&locked_fs, &inode_ptr);
         let extent_tree = ExtentTree::load_extent_tree(locked_fs, inode_ptr.clone());
 
         Ok(Self {
@@ -332,8 +609,935 @@ impl Ext4Directory {
             fs: inode_fs_ptr,
             internal_cursor: 0,
             extent_tree,
+            block_map,
+            inline_data,
         })
     }
 
+    /// Returns the size of the entries of this directory, in bytes.
+    ///
+    /// Entries of inline directories are preceded by the `.` and `..` entries, which are not counted in the size of
+    /// the inode.
+    fn dir_size(&self) -> usize {
+        if let Some(inline_data) = &self.inline_data {
+            return inline_data.len();
+        }
+
+        let inode = self.inode.read();
+        usize::try_from(cast::<InodeSize, u64>(inode.size())).expect("invalid inode size")
+    }
+
     ext4_fs_read_bytes!();
 }
+
+impl Ext4Directory {
+    /// Opens the regular file named `name` in this directory, and creates it (empty) if it does not exist.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`IOError::InvalidCommand`] if `name` is not a valid file name, or is not a regular file, and if this
+    /// directory cannot be written to. May return any other variant of [`IOError`] in case of a failure while
+    /// attempting to read from or write to disk.
+    pub(crate) fn create_file(&mut self, name: &str) -> IOResult<Ext4File> {
+        check_name(name)?;
+
+        if let Some(entry) = self.search(Ext4Filename(name.as_bytes().to_vec())) {
+            return entry.as_file().ok_or(IOError::InvalidCommand);
+        }
+
+        self.check_writable()?;
+
+        let parent_id = self.inode.read().number;
+        let locked_inode = self
+            .fs
+            .read()
+            .create_inode(parent_id, InodeFileMode::DEFAULT_FILE_MODE)?;
+        let inode_id = locked_inode.read().number;
+
+        if let Err(err) = self.add_entry(name.as_bytes(), inode_id, Ext4DirectoryFileType::REGULAR)
+        {
+            self.fs.read().free_inode(inode_id, false)?;
+            return Err(err);
+        }
+
+        self.touch()?;
+
+        Ext4File::from_inode(self.fs.clone(), &Arc::downgrade(&locked_inode))
+    }
+
+    /// Creates an empty directory named `name` in this directory.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`IOError::InvalidCommand`] if `name` is not a valid file name or already exists, if this directory
+    /// has too many subdirectories, and if it cannot be written to. May return any other variant of [`IOError`] in
+    /// case of a failure while attempting to read from or write to disk.
+    pub(crate) fn create_dir(&mut self, name: &str) -> IOResult<Self> {
+        check_name(name)?;
+
+        if self
+            .search(Ext4Filename(name.as_bytes().to_vec()))
+            .is_some()
+        {
+            return Err(IOError::InvalidCommand);
+        }
+
+        self.check_writable()?;
+        let links = self.links_with_subdir()?;
+
+        let parent_id = self.inode.read().number;
+        let locked_inode = self
+            .fs
+            .read()
+            .create_inode(parent_id, InodeFileMode::DEFAULT_DIR_MODE)?;
+        let inode_id = locked_inode.read().number;
+
+        let init = self.init_dir(&locked_inode, parent_id).and_then(|()| {
+            self.add_entry(name.as_bytes(), inode_id, Ext4DirectoryFileType::DIRECTORY)
+        });
+
+        if let Err(err) = init {
+            // the new directory is released along with its first block
+            self.fs.read().release_link(&locked_inode)?;
+            return Err(err);
+        }
+
+        self.inode.write().set_links(links);
+        self.touch()?;
+
+        Self::from_inode(self.fs.clone(), &Arc::downgrade(&locked_inode))
+    }
+
+    /// Removes the entry named `name` from this directory.
+    ///
+    /// The inode of the entry is freed along with its blocks once its last link is removed. Directories must be
+    /// empty.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`IOError::NotFound`] if there is no entry named `name`, and [`IOError::InvalidCommand`] if it is a
+    /// non-empty directory, if its blocks cannot be released, or if this directory cannot be written to. May return
+    /// any other variant of [`IOError`] in case of a failure while attempting to read from or write to disk.
+    pub(crate) fn unlink(&mut self, name: &str) -> CanFail<IOError> {
+        check_name(name)?;
+        self.check_writable()?;
+
+        let entry = self
+            .search(Ext4Filename(name.as_bytes().to_vec()))
+            .ok_or(IOError::NotFound)?;
+        let locked_inode = self.check_removable(&entry)?;
+        let is_dir = matches!(locked_inode.read().inode_type(), InodeType::Directory);
+
+        self.remove_entry(&entry)?;
+
+        if is_dir {
+            let links = self.links_without_subdir();
+            self.inode.write().set_links(links);
+        }
+
+        self.touch()?;
+
+        self.fs.read().release_link(&locked_inode)
+    }
+
+    /// Renames the entry named `old_name` in this directory to `new_name`.
+    ///
+    /// The entry named `new_name` is replaced if it exists (see [`Ext4Directory::move_entry`]).
+    ///
+    /// # Errors
+    ///
+    /// See [`Ext4Directory::move_entry`].
+    pub(crate) fn rename(&mut self, old_name: &str, new_name: &str) -> CanFail<IOError> {
+        let mut target = self.clone();
+
+        self.move_entry(old_name, &mut target, new_name)
+    }
+
+    /// Moves the entry named `old_name` in this directory to the `target` directory, under the name `new_name`.
+    ///
+    /// The entry named `new_name` in `target` is replaced if it exists: it must be of the same type as the moved
+    /// entry, and directories must be empty. Its inode is freed if this was its last link.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`IOError::NotFound`] if there is no entry named `old_name`, and [`IOError::InvalidCommand`] if a name
+    /// is invalid, if the entry cannot be replaced, if a directory is moved to one of its subdirectories, or if one
+    /// of the directories cannot be written to. May return any other variant of [`IOError`] in case of a failure
+    /// while attempting to read from or write to disk.
+    pub(crate) fn move_entry(
+        &mut self,
+        old_name: &str,
+        target: &mut Ext4Directory,
+        new_name: &str,
+    ) -> CanFail<IOError> {
+        check_name(old_name)?;
+        check_name(new_name)?;
+        self.check_writable()?;
+        target.check_writable()?;
+
+        let entry = self
+            .search(Ext4Filename(old_name.as_bytes().to_vec()))
+            .ok_or(IOError::NotFound)?;
+        let file_type = entry.entry_type().ok_or(IOError::Unknown)?;
+        let is_dir = file_type == Ext4DirectoryFileType::DIRECTORY;
+
+        let dir_id = self.inode.read().number;
+        let target_id = target.inode.read().number;
+        let same_dir = dir_id == target_id;
+
+        if same_dir && old_name == new_name {
+            return Ok(());
+        }
+
+        // the `..` entry of a directory moved to another directory must be updated
+        let mut moved_dir = if is_dir && !same_dir {
+            target.check_outside_of(entry.inode_number)?;
+
+            let moved_dir = Self::from_inode_id(self.fs.clone(), entry.inode_number)?;
+            moved_dir.check_writable()?;
+
+            Some(moved_dir)
+        } else {
+            None
+        };
+
+        let replaced = target.search(Ext4Filename(new_name.as_bytes().to_vec()));
+        let mut replaced_inode = None;
+
+        if let Some(replaced) = &replaced {
+            // both names are links to the same inode
+            if replaced.inode_number == entry.inode_number {
+                return Ok(());
+            }
+
+            if (replaced.entry_type().ok_or(IOError::Unknown)? == Ext4DirectoryFileType::DIRECTORY)
+                != is_dir
+            {
+                return Err(IOError::InvalidCommand);
+            }
+
+            replaced_inode = Some(target.check_removable(replaced)?);
+        }
+
+        // a directory gains a link for each of its subdirectories, through their `..` entry
+        let mut dir_links = is_dir.then(|| self.links_without_subdir());
+        let mut target_links = None;
+
+        if is_dir && replaced_inode.is_none() {
+            if same_dir {
+                dir_links = None;
+            } else {
+                target_links = Some(target.links_with_subdir()?);
+            }
+        }
+
+        if let Some(replaced) = &replaced {
+            target.set_entry(replaced, entry.inode_number, file_type)?;
+        } else {
+            target.add_entry(new_name.as_bytes(), entry.inode_number, file_type)?;
+        }
+
+        // both structures share the same inode, but blocks may have been mapped to `target` in the meantime
+        if same_dir {
+            self.extent_tree.clone_from(&target.extent_tree);
+        }
+
+        self.remove_entry(&entry)?;
+
+        if let Some(moved_dir) = &mut moved_dir {
+            let parent_entry = moved_dir
+                .search(Ext4Filename(b"..".to_vec()))
+                .ok_or(IOError::Unknown)?;
+
+            moved_dir.set_entry(&parent_entry, target_id, Ext4DirectoryFileType::DIRECTORY)?;
+            moved_dir.touch()?;
+        }
+
+        if let Some(links) = target_links {
+            target.inode.write().set_links(links);
+        }
+
+        if let Some(links) = dir_links {
+            self.inode.write().set_links(links);
+        }
+
+        target.touch()?;
+        if !same_dir {
+            self.touch()?;
+        }
+
+        if let Some(replaced_inode) = replaced_inode {
+            self.fs.read().release_link(&replaced_inode)?;
+        }
+
+        Ok(())
+    }
+
+    /// Checks whether this directory can be written to.
+    ///
+    /// The blocks of the directory must be mapped by an extent tree, as the filesystem itself must support writes.
+    fn check_writable(&self) -> CanFail<IOError> {
+        self.fs.read().check_writable()?;
+
+        if self.extent_tree.is_none() || self.inline_data.is_some() {
+            return Err(IOError::InvalidCommand);
+        }
+
+        Ok(())
+    }
+
+    /// Checks whether an entry of this directory can be removed, or replaced by another one.
+    ///
+    /// Returns a strong reference to the inode of the entry, to release it once the entry is removed.
+    fn check_removable(&self, entry: &Ext4DirectoryEntry) -> IOResult<LockedInodeStrongRef> {
+        let fs = self.fs.read();
+        let locked_inode = fs
+            .get_inode_strong(entry.inode_number)
+            .ok_or(IOError::Unknown)?;
+        drop(fs);
+
+        let inode = locked_inode.read();
+        let is_dir = matches!(inode.inode_type(), InodeType::Directory);
+
+        // blocks are only released along with the last link of the inode
+        if is_dir || u16::from(inode.links()) <= 1 {
+            Ext4Fs::check_releasable(&inode)?;
+        }
+
+        drop(inode);
+
+        if is_dir && !Self::from_inode(self.fs.clone(), &Arc::downgrade(&locked_inode))?.is_empty()
+        {
+            return Err(IOError::InvalidCommand);
+        }
+
+        Ok(locked_inode)
+    }
+
+    /// Checks that this directory is not `inode_id`, or one of its subdirectories.
+    ///
+    /// The parent directories of this directory are walked up through their `..` entries, up to the root directory.
+    fn check_outside_of(&self, inode_id: InodeNumber) -> CanFail<IOError> {
+        let mut dir_id = self.inode.read().number;
+
+        while dir_id != InodeNumber::ROOT_DIR {
+            if dir_id == inode_id {
+                return Err(IOError::InvalidCommand);
+            }
+
+            dir_id = Self::from_inode_id(self.fs.clone(), dir_id)?
+                .search(Ext4Filename(b"..".to_vec()))
+                .ok_or(IOError::Unknown)?
+                .inode_number;
+        }
+
+        Ok(())
+    }
+
+    /// Checks if this directory is empty, apart from its `.` and `..` entries.
+    fn is_empty(&self) -> bool {
+        let mut dir = self.clone();
+        dir.internal_cursor = 0;
+
+        dir.all(|entry| entry.name.0 == b"." || entry.name.0 == b"..")
+    }
+
+    /// Returns the link count of this directory, once a subdirectory has been added to it.
+    ///
+    /// The link count of a directory stops being tracked once it reaches its maximum value, if the `DIR_NLINK`
+    /// feature is enabled.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`IOError::InvalidCommand`] if this directory has too many subdirectories.
+    fn links_with_subdir(&self) -> IOResult<InodeHardLinkCount> {
+        let has_dir_nlink = self
+            .fs
+            .read()
+            .superblock
+            .read()
+            .feature_ro_compat
+            .includes(ReadOnlyCompatibleFeatureSet::EXT4_FEATURE_DIR_NLINK);
+        let links = self.inode.read().links();
+
+        if links == InodeHardLinkCount::UNKNOWN_DIR_LINKS && has_dir_nlink {
+            return Ok(links);
+        }
+
+        let links = u16::from(links) + 1;
+
+        if links < u16::from(InodeHardLinkCount::MAX_LINKS) {
+            Ok(InodeHardLinkCount::from(links))
+        } else if has_dir_nlink {
+            Ok(InodeHardLinkCount::UNKNOWN_DIR_LINKS)
+        } else {
+            Err(IOError::InvalidCommand)
+        }
+    }
+
+    /// Returns the link count of this directory, once a subdirectory has been removed from it.
+    fn links_without_subdir(&self) -> InodeHardLinkCount {
+        let links = self.inode.read().links();
+
+        // a directory always keeps its link from its parent, and its `.` entry
+        if u16::from(links) > 2 {
+            InodeHardLinkCount::from(u16::from(links) - 1)
+        } else {
+            links
+        }
+    }
+
+    /// Updates the modification and change times of this directory, and writes its inode back to disk.
+    fn touch(&self) -> CanFail<IOError> {
+        let mut inode = self.inode.write();
+        inode.touch(current_timestamp());
+
+        self.fs.read().write_inode(&mut inode)
+    }
+
+    /// Initializes a new directory, allocating its first block to store its `.` and `..` entries.
+    fn init_dir(
+        &self,
+        locked_inode: &LockedInodeStrongRef,
+        parent_id: InodeNumber,
+    ) -> CanFail<IOError> {
+        let layout = self.blk_layout();
+        let fs = self.fs.read();
+        let (inode_id, inode_gen) = {
+            let inode = locked_inode.read();
+            (inode.number, inode.generation())
+        };
+
+        let goal = {
+            let sb = fs.superblock.read();
+            sb.bg_first_blk(sb.get_inode_blk_group(inode_id))
+        };
+        let (real_blk, _) = fs.allocate_blks(goal, 1)?;
+
+        let mut blk = alloc::vec![0u8; layout.blk_size];
+        let dot_len = dir_entry_size(1);
+        layout.write_entry(
+            &mut blk,
+            0,
+            inode_id,
+            dot_len,
+            b".",
+            Ext4DirectoryFileType::DIRECTORY,
+        );
+        layout.write_entry(
+            &mut blk,
+            dot_len,
+            parent_id,
+            layout.limit() - dot_len,
+            b"..",
+            Ext4DirectoryFileType::DIRECTORY,
+        );
+        layout.seal(&mut blk, inode_id, inode_gen, false);
+
+        if let Err(err) = fs.write_blk_to_device(real_blk, &blk) {
+            fs.free_blks(real_blk, 1)?;
+            return Err(err);
+        }
+
+        let mut extent_tree = ExtentTree::load_extent_tree(self.fs.clone(), locked_inode.clone())
+            .ok_or(IOError::Unknown)?;
+        extent_tree.map_blks(real_blk, cast(0u64), 1);
+
+        let mut inode = locked_inode.write();
+        if let Err(err) = extent_tree.write_tree(&fs, &mut inode) {
+            fs.free_blks(real_blk, 1)?;
+            return Err(err);
+        }
+
+        inode.set_size(cast(
+            u64::try_from(layout.blk_size).expect("invalid block size"),
+        ));
+        inode.update_blk_count(1, 0);
+
+        fs.write_inode(&mut inode)
+    }
+
+    /// Adds an entry to this directory.
+    ///
+    /// In indexed directories, the entry is stored in the leaf block covering the hash of its name. If that block is
+    /// full, the index is dropped and the directory is handled as a linear one. Entries are stored in the first block
+    /// with enough free space, and a new block is appended to the directory if there is none.
+    fn add_entry(
+        &mut self,
+        name: &[u8],
+        inode_id: InodeNumber,
+        file_type: Ext4DirectoryFileType,
+    ) -> CanFail<IOError> {
+        let layout = self.blk_layout();
+
+        if self.inode.read().has_flag(InodeFlags::EXT4_INDEX_FL) {
+            if let Ok((path, _)) = self.htree_path(name) {
+                let (entries, idx) = path.last().ok_or(IOError::Unknown)?;
+
+                if self.insert_entry(
+                    u64::from(entries.block(*idx)),
+                    name,
+                    inode_id,
+                    file_type,
+                    &layout,
+                )? {
+                    return Ok(());
+                }
+            }
+
+            self.drop_index(&layout)?;
+        }
+
+        let blk_count = u64::try_from(self.dir_size() / layout.blk_size).expect("invalid size");
+
+        for blk_idx in 0..blk_count {
+            if self.insert_entry(blk_idx, name, inode_id, file_type, &layout)? {
+                return Ok(());
+            }
+        }
+
+        self.append_entry_blk(name, inode_id, file_type, &layout)
+    }
+
+    /// Inserts an entry in the block of index `blk_idx` of this directory, if it has enough free space.
+    ///
+    /// The entry takes the place of an unused entry, or of the free space at the end of an entry (whose record length
+    /// is shortened). Returns `false` if the block is full.
+    fn insert_entry(
+        &self,
+        blk_idx: u64,
+        name: &[u8],
+        inode_id: InodeNumber,
+        file_type: Ext4DirectoryFileType,
+        layout: &DirBlkLayout,
+    ) -> IOResult<bool> {
+        let mut blk = alloc::vec![0u8; layout.blk_size];
+        self.read_dir_blk(blk_idx, &mut blk)?;
+
+        let required_len = dir_entry_size(name.len());
+
+        for entry in blk_entries(&blk, self.blk_limit(blk_idx, layout))? {
+            let used_len = if entry.inode == InodeNumber::UNUSED_DIR_ENTRY {
+                0
+            } else {
+                dir_entry_size(entry.name_len)
+            };
+
+            if entry.rec_len - used_len < required_len {
+                continue;
+            }
+
+            if used_len != 0 {
+                set_rec_len(&mut blk, entry.offset, used_len);
+            }
+
+            layout.write_entry(
+                &mut blk,
+                entry.offset + used_len,
+                inode_id,
+                entry.rec_len - used_len,
+                name,
+                file_type,
+            );
+            self.write_dir_blk(blk_idx, &mut blk, layout)?;
+
+            return Ok(true);
+        }
+
+        Ok(false)
+    }
+
+    /// Appends a new block to this directory, holding a single entry.
+    fn append_entry_blk(
+        &mut self,
+        name: &[u8],
+        inode_id: InodeNumber,
+        file_type: Ext4DirectoryFileType,
+        layout: &DirBlkLayout,
+    ) -> CanFail<IOError> {
+        let dir_size = self.dir_size();
+        let blk_idx: Ext4InodeRelBlkId =
+            cast(u64::try_from(dir_size / layout.blk_size).expect("invalid size"));
+
+        let fs = self.fs.read();
+        let extent_tree = self.extent_tree.as_mut().ok_or(IOError::InvalidCommand)?;
+        let mut inode = self.inode.write();
+
+        let goal = extent_tree.last_blk().map_or_else(
+            || {
+                let sb = fs.superblock.read();
+                sb.bg_first_blk(sb.get_inode_blk_group(inode.number))
+            },
+            |blk| blk + 1,
+        );
+        let (real_blk, _) = fs.allocate_blks(goal, 1)?;
+
+        let mut blk = alloc::vec![0u8; layout.blk_size];
+        layout.write_entry(&mut blk, 0, inode_id, layout.limit(), name, file_type);
+        layout.seal(&mut blk, inode.number, inode.generation(), false);
+
+        if let Err(err) = fs.write_blk_to_device(real_blk, &blk) {
+            fs.free_blks(real_blk, 1)?;
+            return Err(err);
+        }
+
+        extent_tree.map_blks(real_blk, blk_idx, 1);
+
+        if let Err(err) = extent_tree.write_tree(&fs, &mut inode) {
+            extent_tree.remove_blks_from(blk_idx);
+            fs.free_blks(real_blk, 1)?;
+            return Err(err);
+        }
+
+        inode.set_size(cast(
+            u64::try_from(dir_size + layout.blk_size).expect("invalid size"),
+        ));
+        inode.update_blk_count(1, 0);
+
+        fs.write_inode(&mut inode)
+    }
+
+    /// Removes an entry from this directory.
+    ///
+    /// The record of the entry is merged into the previous entry of its block, or marked as unused if it is the first
+    /// entry of its block.
+    fn remove_entry(&self, entry: &Ext4DirectoryEntry) -> CanFail<IOError> {
+        let layout = self.blk_layout();
+        let blk_idx = u64::try_from(entry.offset / layout.blk_size).expect("invalid offset");
+        let offset_in_blk = entry.offset % layout.blk_size;
+
+        let mut blk = alloc::vec![0u8; layout.blk_size];
+        self.read_dir_blk(blk_idx, &mut blk)?;
+
+        let entries = blk_entries(&blk, self.blk_limit(blk_idx, &layout))?;
+        let idx = entries
+            .iter()
+            .position(|blk_entry| blk_entry.offset == offset_in_blk)
+            .ok_or(IOError::Unknown)?;
+
+        if let Some(prev) = idx.checked_sub(1).map(|prev| &entries[prev]) {
+            set_rec_len(&mut blk, prev.offset, prev.rec_len + entries[idx].rec_len);
+        } else {
+            blk[offset_in_blk..offset_in_blk + mem::size_of::<InodeNumber>()]
+                .copy_from_slice(bytes_of(&InodeNumber::UNUSED_DIR_ENTRY));
+        }
+
+        self.write_dir_blk(blk_idx, &mut blk, &layout)
+    }
+
+    /// Points an existing entry of this directory to another inode.
+    fn set_entry(
+        &self,
+        entry: &Ext4DirectoryEntry,
+        inode_id: InodeNumber,
+        file_type: Ext4DirectoryFileType,
+    ) -> CanFail<IOError> {
+        let layout = self.blk_layout();
+        let blk_idx = u64::try_from(entry.offset / layout.blk_size).expect("invalid offset");
+        let offset_in_blk = entry.offset % layout.blk_size;
+
+        let mut blk = alloc::vec![0u8; layout.blk_size];
+        self.read_dir_blk(blk_idx, &mut blk)?;
+
+        blk[offset_in_blk..offset_in_blk + mem::size_of::<InodeNumber>()]
+            .copy_from_slice(bytes_of(&inode_id));
+        if layout.has_file_type {
+            blk[offset_in_blk + DIR_ENTRY_FILE_TYPE_OFFSET] = cast(file_type);
+        }
+
+        self.write_dir_blk(blk_idx, &mut blk, &layout)
+    }
+
+    /// Drops the hashed index of this directory, which is then handled as a linear directory.
+    ///
+    /// The index blocks are turned into regular directory blocks: the root only keeps the `.` and `..` entries, and
+    /// inner nodes become empty blocks.
+    fn drop_index(&mut self, layout: &DirBlkLayout) -> CanFail<IOError> {
+        let mut root_blk = alloc::vec![0u8; layout.blk_size];
+        self.read_dir_blk(0, &mut root_blk)?;
+
+        let root = DxRoot::parse(&root_blk).ok_or(IOError::Unknown)?;
+        let mut nodes: Vec<u64> = alloc::vec![];
+
+        if root.indirect_levels > 0 {
+            let mut node_blk = alloc::vec![0u8; layout.blk_size];
+
+            for idx in 0..root.entries.len() {
+                let node = u64::from(root.entries.block(idx));
+                nodes.push(node);
+
+                if root.indirect_levels > 1 {
+                    self.read_dir_blk(node, &mut node_blk)?;
+                    let entries = DxEntries::parse_node(&node_blk).ok_or(IOError::Unknown)?;
+
+                    nodes.extend((0..entries.len()).map(|idx| u64::from(entries.block(idx))));
+                }
+            }
+        }
+
+        let mut inode = self.inode.write();
+        inode.i_flags = inode.i_flags & !InodeFlags::EXT4_INDEX_FL;
+        drop(inode);
+
+        // the `..` entry of the root covers the whole block, hiding the index
+        let dot_len = dir_entry_size(1);
+        set_rec_len(&mut root_blk, dot_len, layout.limit() - dot_len);
+        root_blk[dot_len + dir_entry_size(2)..].fill(0);
+        self.write_dir_blk(0, &mut root_blk, layout)?;
+
+        for node in nodes {
+            let mut node_blk = alloc::vec![0u8; layout.blk_size];
+            layout.write_entry(
+                &mut node_blk,
+                0,
+                InodeNumber::UNUSED_DIR_ENTRY,
+                layout.limit(),
+                &[],
+                Ext4DirectoryFileType::UNKNOWN,
+            );
+            self.write_dir_blk(node, &mut node_blk, layout)?;
+        }
+
+        self.touch()
+    }
+
+    /// Returns the layout of the blocks of this directory.
+    fn blk_layout(&self) -> DirBlkLayout {
+        let fs = self.fs.read();
+        let sb = fs.superblock.read();
+
+        DirBlkLayout {
+            blk_size: usize::try_from(sb.blk_size()).expect("invalid block size"),
+            has_file_type: sb
+                .feature_incompat
+                .includes(IncompatibleFeatureSet::EXT4_FEATURE_INCOMPAT_FILETYPE),
+            chksum_uuid: sb.has_metadata_chksum().then_some(sb.uuid),
+        }
+    }
+
+    /// Returns the end of the entries of the block of index `blk_idx` of this directory.
+    ///
+    /// The `..` entry of the root of the index of an indexed directory covers its whole first block.
+    fn blk_limit(&self, blk_idx: u64, layout: &DirBlkLayout) -> usize {
+        if blk_idx == 0 && self.inode.read().has_flag(InodeFlags::EXT4_INDEX_FL) {
+            layout.blk_size
+        } else {
+            layout.limit()
+        }
+    }
+
+    /// Reads the block of index `blk_idx` of this directory.
+    fn read_dir_blk(&self, blk_idx: u64, blk: &mut [u8]) -> CanFail<IOError> {
+        let real_blk = self
+            .extent_tree
+            .as_ref()
+            .and_then(|extent_tree| extent_tree.get_exact_blk_mapping(cast(blk_idx)))
+            .ok_or(IOError::Unknown)?;
+
+        self.fs.read().read_blk_from_device(real_blk, blk)
+    }
+
+    /// Writes the block of index `blk_idx` of this directory back to disk, after having updated its checksum.
+    fn write_dir_blk(
+        &self,
+        blk_idx: u64,
+        blk: &mut [u8],
+        layout: &DirBlkLayout,
+    ) -> CanFail<IOError> {
+        let real_blk = self
+            .extent_tree
+            .as_ref()
+            .and_then(|extent_tree| extent_tree.get_exact_blk_mapping(cast(blk_idx)))
+            .ok_or(IOError::Unknown)?;
+
+        let inode = self.inode.read();
+        let is_dx_root = blk_idx == 0 && inode.has_flag(InodeFlags::EXT4_INDEX_FL);
+        layout.seal(blk, inode.number, inode.generation(), is_dx_root);
+        drop(inode);
+
+        self.fs.read().write_blk_to_device(real_blk, blk)
+    }
+}
+
+/// Size of the header of a directory entry (inode number, record length, name length, and file type), in bytes.
+const DIR_ENTRY_HEADER_SIZE: usize = 8;
+
+/// Offset of the file type in the header of a directory entry.
+const DIR_ENTRY_FILE_TYPE_OFFSET: usize = 7;
+
+/// Directory entries are aligned on 4 bytes.
+const DIR_ENTRY_ALIGN: usize = 4;
+
+/// Maximum length of a file name, in bytes.
+const MAX_NAME_LEN: usize = 255;
+
+/// Size of the fake directory entry holding the checksum of a directory block, at the end of the block.
+const DIR_BLK_TAIL_SIZE: usize = 12;
+
+/// File type of the fake directory entry holding the checksum of a directory block.
+const DIR_BLK_TAIL_FILE_TYPE: u8 = 0xDE;
+
+/// Layout of the blocks of a directory, required to modify them.
+struct DirBlkLayout {
+    blk_size: usize,
+
+    /// Whether directory entries store the file type of their inode (`filetype` feature)
+    has_file_type: bool,
+
+    /// UUID of the filesystem, if directory blocks are checksummed (`metadata_csum` feature)
+    chksum_uuid: Option<Ext4FsUuid>,
+}
+
+impl DirBlkLayout {
+    /// Returns the end of the entries of a directory block, which is followed by the checksum tail of the block if
+    /// directory blocks are checksummed.
+    fn limit(&self) -> usize {
+        if self.chksum_uuid.is_some() {
+            self.blk_size - DIR_BLK_TAIL_SIZE
+        } else {
+            self.blk_size
+        }
+    }
+
+    /// Writes a directory entry at `offset` in a directory block.
+    fn write_entry(
+        &self,
+        blk: &mut [u8],
+        offset: usize,
+        inode_id: InodeNumber,
+        rec_len: usize,
+        name: &[u8],
+        file_type: Ext4DirectoryFileType,
+    ) {
+        let file_type = if self.has_file_type {
+            cast(file_type)
+        } else {
+            0
+        };
+
+        blk[offset..offset + mem::size_of::<InodeNumber>()].copy_from_slice(bytes_of(&inode_id));
+        set_rec_len(blk, offset, rec_len);
+        blk[offset + 6] = u8::try_from(name.len()).expect("invalid name length");
+        blk[offset + DIR_ENTRY_FILE_TYPE_OFFSET] = file_type;
+        blk[offset + DIR_ENTRY_HEADER_SIZE..offset + DIR_ENTRY_HEADER_SIZE + name.len()]
+            .copy_from_slice(name);
+    }
+
+    /// Updates the checksum of a directory block of the directory `inode_id`, if directory blocks are checksummed.
+    ///
+    /// The checksum of a directory block is stored in a fake entry at the end of the block:
+    ///
+    /// ```
+    /// crc32c_calc(fs_uuid + inode_id + inode_gen + dir_entries)
+    /// ```
+    ///
+    /// The root of the index of an indexed directory (`is_dx_root`) is checksummed as an index block instead.
+    fn seal(
+        &self,
+        blk: &mut [u8],
+        inode_id: InodeNumber,
+        inode_gen: InodeGeneration,
+        is_dx_root: bool,
+    ) {
+        let Some(fs_uuid) = self.chksum_uuid else {
+            return;
+        };
+
+        if is_dx_root {
+            if let Some(root) = DxRoot::parse(blk) {
+                root.entries
+                    .update_chksum(blk, fs_uuid, inode_id, inode_gen);
+            }
+
+            return;
+        }
+
+        let tail_offset = self.limit();
+        blk[tail_offset..].fill(0);
+        set_rec_len(blk, tail_offset, DIR_BLK_TAIL_SIZE);
+        blk[tail_offset + DIR_ENTRY_FILE_TYPE_OFFSET] = DIR_BLK_TAIL_FILE_TYPE;
+
+        let mut chksum_bytes: Vec<u8> = alloc::vec![];
+        chksum_bytes.extend_from_slice(bytes_of(&fs_uuid));
+        chksum_bytes.extend_from_slice(bytes_of(&inode_id));
+        chksum_bytes.extend_from_slice(bytes_of(&inode_gen));
+        chksum_bytes.extend_from_slice(&blk[..tail_offset]);
+
+        blk[self.blk_size - mem::size_of::<u32>()..]
+            .copy_from_slice(&crc32c_calc(&chksum_bytes).to_le_bytes());
+    }
+}
+
+/// Header of an entry, as found in a directory block.
+struct BlkEntry {
+    /// Offset of the entry in the block
+    offset: usize,
+    inode: InodeNumber,
+    rec_len: usize,
+    name_len: usize,
+}
+
+/// Lists the entries of a directory block, up to `limit`.
+///
+/// # Errors
+///
+/// Returns [`IOError::Unknown`] if the block is corrupted.
+fn blk_entries(blk: &[u8], limit: usize) -> IOResult<Vec<BlkEntry>> {
+    let mut entries = alloc::vec![];
+    let mut offset = 0;
+
+    while offset < limit {
+        let header = blk
+            .get(offset..offset + DIR_ENTRY_HEADER_SIZE)
+            .ok_or(IOError::Unknown)?;
+        let rec_len = usize::from(u16::from_le_bytes([header[4], header[5]]));
+
+        if rec_len < DIR_ENTRY_HEADER_SIZE || offset + rec_len > limit {
+            return Err(IOError::Unknown);
+        }
+
+        entries.push(BlkEntry {
+            offset,
+            inode: pod_read_unaligned(&header[..mem::size_of::<InodeNumber>()]),
+            rec_len,
+            name_len: usize::from(header[6]),
+        });
+
+        offset += rec_len;
+    }
+
+    Ok(entries)
+}
+
+/// Sets the record length of the directory entry located at `offset` in a directory block.
+fn set_rec_len(blk: &mut [u8], offset: usize, rec_len: usize) {
+    blk[offset + 4..offset + 6].copy_from_slice(
+        &u16::try_from(rec_len)
+            .expect("invalid record length")
+            .to_le_bytes(),
+    );
+}
+
+/// Returns the size of a directory entry holding a name of `name_len` bytes.
+fn dir_entry_size(name_len: usize) -> usize {
+    (DIR_ENTRY_HEADER_SIZE + name_len).next_multiple_of(DIR_ENTRY_ALIGN)
+}
+
+/// Checks that `name` is a valid name for a directory entry.
+///
+/// # Errors
+///
+/// Returns [`IOError::InvalidCommand`] if `name` is empty or too long, contains a `/` or a null character, or is one
+/// of the `.` and `..` special entries.
+fn check_name(name: &str) -> CanFail<IOError> {
+    if name.is_empty()
+        || name.len() > MAX_NAME_LEN
+        || name.contains(['/', '\0'])
+        || name == "."
+        || name == ".."
+    {
+        return Err(IOError::InvalidCommand);
+    }
+
+    Ok(())
+}
@@ -5,10 +5,10 @@
 use core::{cmp::Ordering, mem};
 
 use alloc::vec::Vec;
-use bytemuck::{bytes_of, cast, from_bytes, Pod, Zeroable};
+use bytemuck::{bytes_of, cast, from_bytes, pod_read_unaligned, Pod, Zeroable};
 use core::ops::Deref;
 
-use crate::fs::ext4::inode::{Inode, InodeNumber, LockedInode, LockedInodeStrongRef};
+use crate::fs::ext4::inode::{Inode, InodeBlk, InodeNumber, LockedInode, LockedInodeStrongRef};
 use crate::fs::ext4::sb::{Ext4BlkCount, Ext4FsUuid, IncompatibleFeatureSet};
 use crate::fs::ext4::LockedExt4Fs;
 use crate::{
@@ -16,6 +16,7 @@ use crate::{
     errors::{CanFail, IOError},
     ext4_uint_field_range,
     fs::ext4::{crc32c_calc, inode::InodeGeneration, Ext4Fs, Ext4Inode},
+    fs::IOResult,
 };
 
 /// Internal ext4 extent tree representation.
@@ -23,6 +24,9 @@ use crate::{
 pub(crate) struct ExtentTree {
     pub(crate) extents: Vec<Extent>,
     locked_inode: LockedInodeStrongRef,
+
+    /// Blocks holding the nodes of the tree (all its nodes but the root, which is stored in the inode)
+    tree_blks: Vec<Ext4RealBlkId>,
 }
 
 impl core::fmt::Debug for ExtentTree {
@@ -78,13 +82,7 @@ impl ExtentBlock {
         let on_disk_chksum: ExtentBlockChksum =
             *from_bytes(&self.0[self.0.len() - 4..self.0.len()]);
 
-        let mut chksum_bytes: Vec<u8> = alloc::vec![];
-        chksum_bytes.extend_from_slice(bytes_of(&fs_uuid));
-        chksum_bytes.extend_from_slice(bytes_of(&inode_id));
-        chksum_bytes.extend_from_slice(bytes_of(&inode_gen));
-        chksum_bytes.extend_from_slice(&self.0[..self.0.len() - 4]);
-
-        let comp_chksum: ExtentBlockChksum = cast(crc32c_calc(&chksum_bytes));
+        let comp_chksum = self.compute_chksum(fs_uuid, inode_id, inode_gen);
 
         if comp_chksum != on_disk_chksum {
             error!(
@@ -99,6 +97,44 @@ impl ExtentBlock {
         true
     }
 
+    /// Updates the checksum stored in the tail of this `ExtentBlock`, based on its current content.
+    pub(crate) fn update_chksum(
+        &mut self,
+        fs_uuid: Ext4FsUuid,
+        inode_id: InodeNumber,
+        inode_gen: InodeGeneration,
+    ) {
+        let chksum = self.compute_chksum(fs_uuid, inode_id, inode_gen);
+        let tail_start = self.0.len() - 4;
+
+        self.0[tail_start..].copy_from_slice(bytes_of(&chksum));
+    }
+
+    fn compute_chksum(
+        &self,
+        fs_uuid: Ext4FsUuid,
+        inode_id: InodeNumber,
+        inode_gen: InodeGeneration,
+    ) -> ExtentBlockChksum {
+        let mut chksum_bytes: Vec<u8> = alloc::vec![];
+        chksum_bytes.extend_from_slice(bytes_of(&fs_uuid));
+        chksum_bytes.extend_from_slice(bytes_of(&inode_id));
+        chksum_bytes.extend_from_slice(bytes_of(&inode_gen));
+        chksum_bytes.extend_from_slice(&self.0[..self.0.len() - 4]);
+
+        cast(crc32c_calc(&chksum_bytes))
+    }
+
+    /// Builds an `ExtentBlock` of `size` bytes from an [`ExtentHeader`], followed by its raw entries (either
+    /// [`Extent`] or [`ExtentIdx`] structures).
+    fn build(size: usize, header: ExtentHeader, raw_entries: &[u8]) -> Self {
+        let mut data = bytes_of(&header).to_vec();
+        data.extend_from_slice(raw_entries);
+        data.resize(size, 0);
+
+        Self(data)
+    }
+
     /// Returns the [`ExtentHeader`] for this `ExtentBlock`
     ///
     /// Every block, whether it contains leaf nodes or index nodes, begins with an `ExtentHeader`.
@@ -152,10 +188,13 @@ impl<'en> ExtentBlkRawEntry<'en> {
 pub(crate) struct ExtentBlockChksum(u32);
 
 /// Extent-layer traversal routine.
+///
+/// The blocks holding the nodes of the tree are pushed to `tree_blks`.
 fn traverse_extent_layer(
     fs: &Ext4Fs,
     ext_data: &ExtentBlock,
     extents: &mut Vec<Extent>,
+    tree_blks: &mut Vec<Ext4RealBlkId>,
     inode: &Inode,
 ) -> Option<()> {
     let sb = fs.superblock.read();
@@ -178,10 +217,13 @@ fn traverse_extent_layer(
         let mut data = fs.allocate_blk();
 
         fs.read_blk_from_device(extent_idx.leaf(), &mut data).ok()?;
+        tree_blks.push(extent_idx.leaf());
 
         let extent_blk = ExtentBlock(data);
-        extent_blk.validate_chksum(sb.uuid, inode.number, inode.generation());
-        traverse_extent_layer(fs, &extent_blk, extents, inode);
+        if sb.has_metadata_chksum() {
+            extent_blk.validate_chksum(sb.uuid, inode.number, inode.generation());
+        }
+        traverse_extent_layer(fs, &extent_blk, extents, tree_blks, inode);
     }
 
     Some(())
@@ -204,16 +246,24 @@ impl ExtentTree {
             return None;
         };
         let mut extents: Vec<Extent> = alloc::vec![];
+        let mut tree_blks: Vec<Ext4RealBlkId> = alloc::vec![];
         let extent_blk = inode.i_block.as_extent_block();
         drop(sb);
 
-        traverse_extent_layer(fs.deref(), &extent_blk, &mut extents, inode.deref());
+        traverse_extent_layer(
+            fs.deref(),
+            &extent_blk,
+            &mut extents,
+            &mut tree_blks,
+            inode.deref(),
+        );
         extents.sort_unstable();
         drop(inode);
 
         Some(Self {
             extents,
             locked_inode,
+            tree_blks,
         })
     }
 
@@ -237,8 +287,258 @@ impl ExtentTree {
 
         Some(extent.start_blk() + offset_in_extent)
     }
+
+    /// Returns the last physical block mapped by this extent tree, if any.
+    pub(crate) fn last_blk(&self) -> Option<Ext4RealBlkId> {
+        let extent = self.extents.last()?;
+
+        Some(extent.start_blk() + (u64::from(extent.len.length()) - 1))
+    }
+
+    /// Unmaps every logical block starting from `first_blk`.
+    ///
+    /// Returns the runs of physical blocks that were unmapped (first block, and length), which can then be freed once
+    /// the extent tree has been written back to disk.
+    pub(crate) fn remove_blks_from(
+        &mut self,
+        first_blk: Ext4InodeRelBlkId,
+    ) -> Vec<(Ext4RealBlkId, u64)> {
+        let mut unmapped = alloc::vec![];
+
+        self.extents.retain_mut(|extent| {
+            let length = u64::from(extent.len.length());
+
+            if extent.block >= first_blk {
+                unmapped.push((extent.start_blk(), length));
+                return false;
+            }
+
+            if extent.contains(first_blk) {
+                let kept = cast::<Ext4InodeRelBlkId, u64>(first_blk - extent.block);
+
+                if kept < length {
+                    unmapped.push((extent.start_blk() + kept, length - kept));
+                    extent.len = Ext4ExtentLength::new(
+                        u16::try_from(kept).expect("invalid extent length"),
+                        extent.len.is_initialized(),
+                    );
+                }
+            }
+
+            true
+        });
+
+        unmapped
+    }
+
+    /// Maps `len` logical blocks, starting from `first_blk`, to the contiguous physical blocks starting from `blk`.
+    ///
+    /// The logical blocks must not be mapped yet: they may follow the last extent of the tree, or fill a hole of a
+    /// sparse file. They are merged into the preceding extent when they follow it both logically and physically.
+    pub(crate) fn map_blks(&mut self, blk: Ext4RealBlkId, first_blk: Ext4InodeRelBlkId, len: u64) {
+        let mut idx = self
+            .extents
+            .partition_point(|extent| extent.block < first_blk);
+        let mut mapped = 0;
+
+        if let Some(prev) = idx
+            .checked_sub(1)
+            .and_then(|prev| self.extents.get_mut(prev))
+        {
+            let prev_len = u64::from(prev.len.length());
+
+            if prev.len.is_initialized()
+                && prev.block + prev.len == first_blk
+                && prev.start_blk() + prev_len == blk
+            {
+                let merged = u64::min(len, MAX_INITIALIZED_EXTENT_LEN - prev_len);
+
+                prev.len = Ext4ExtentLength::new(
+                    u16::try_from(prev_len + merged).expect("invalid extent length"),
+                    true,
+                );
+                mapped = merged;
+            }
+        }
+
+        while mapped < len {
+            let extent_len = u64::min(len - mapped, MAX_INITIALIZED_EXTENT_LEN);
+
+            self.extents.insert(
+                idx,
+                Extent::new(
+                    first_blk + mapped,
+                    Ext4ExtentLength::new(
+                        u16::try_from(extent_len).expect("invalid extent length"),
+                        true,
+                    ),
+                    blk + mapped,
+                ),
+            );
+
+            idx += 1;
+            mapped += extent_len;
+        }
+    }
+
+    /// Marks the extent mapping the logical block `blk_id` as initialized, if it is not already.
+    ///
+    /// Returns the run of physical blocks of the extent (first block, and length) if it was uninitialized: as the
+    /// content of uninitialized extents reads as zeros, the caller must fill these blocks with zeros.
+    pub(crate) fn initialize_extent(
+        &mut self,
+        blk_id: Ext4InodeRelBlkId,
+    ) -> Option<(Ext4RealBlkId, u64)> {
+        let extent = self
+            .extents
+            .iter_mut()
+            .find(|extent| extent.contains(blk_id))?;
+
+        if extent.len.is_initialized() {
+            return None;
+        }
+
+        let length = extent.len.length();
+        extent.len = Ext4ExtentLength::new(length, true);
+
+        Some((extent.start_blk(), u64::from(length)))
+    }
+
+    /// Returns the root of an empty extent tree, to be stored in the `i_block` field of a new inode.
+    pub(crate) fn empty_root() -> IOResult<InodeBlk> {
+        let header = ExtentHeader::new(0, ROOT_CAPACITY, 0)?;
+        let root = ExtentBlock::build(mem::size_of::<InodeBlk>(), header, &[]);
+
+        Ok(pod_read_unaligned(&root.0))
+    }
+
+    /// Writes this extent tree back to disk, and its root to the `i_block` field of `inode`.
+    ///
+    /// The whole tree is rebuilt from its extents, its depth growing or shrinking as needed: blocks that were holding
+    /// its nodes are reused when possible, and new blocks are allocated (or released) depending on the number of
+    /// nodes required. The block count of the inode is updated accordingly, but the inode itself is not written.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`IOError::NoSpace`] if the blocks required to store the tree could not be allocated. May return any
+    /// other variant of [`IOError`] in case of a failure while attempting to read from or write to disk.
+    pub(crate) fn write_tree(&mut self, fs: &Ext4Fs, inode: &mut Inode) -> CanFail<IOError> {
+        let sb = fs.superblock.read();
+        let blk_size = usize::try_from(sb.blk_size()).expect("invalid block size");
+        let fs_uuid = sb.uuid;
+        let has_metadata_chksum = sb.has_metadata_chksum();
+        drop(sb);
+
+        // the last 4 bytes of each block are reserved for the checksum of the block
+        let blk_capacity = (blk_size - mem::size_of::<ExtentHeader>()) / mem::size_of::<Extent>();
+
+        // number of nodes stored in blocks, for each level of the tree (from the leaves up)
+        let mut levels: Vec<usize> = alloc::vec![];
+        let mut entries = self.extents.len();
+
+        while entries > ROOT_CAPACITY {
+            entries = entries.div_ceil(blk_capacity);
+            levels.push(entries);
+        }
+
+        let required_blks: usize = levels.iter().sum();
+        let old_blks_count = self.tree_blks.len();
+        let mut tree_blks: Vec<Ext4RealBlkId> =
+            self.tree_blks.iter().copied().take(required_blks).collect();
+
+        while tree_blks.len() < required_blks {
+            let goal = tree_blks
+                .last()
+                .map_or_else(|| self.extents[0].start_blk(), |&blk| blk + 1);
+            let missing = u64::try_from(required_blks - tree_blks.len()).expect("invalid count");
+
+            match fs.allocate_blks(goal, missing) {
+                Ok((blk, count)) => tree_blks.extend((0..count).map(|idx| blk + idx)),
+                Err(err) => {
+                    // the blocks allocated during this call are released, the tree is left unchanged
+                    for &blk in &tree_blks[usize::min(old_blks_count, tree_blks.len())..] {
+                        fs.free_blks(blk, 1)?;
+                    }
+
+                    return Err(err);
+                }
+            }
+        }
+
+        let mut raw_entries: Vec<u8> = self
+            .extents
+            .iter()
+            .flat_map(|extent| bytes_of(extent).to_vec())
+            .collect();
+        let mut first_blks: Vec<Ext4InodeRelBlkId> = self
+            .extents
+            .iter()
+            .map(|extent| cast(u64::from(extent.block.0)))
+            .collect();
+        let mut nodes_blks = tree_blks.iter();
+
+        for (depth, _) in levels.iter().enumerate() {
+            let mut upper_raw_entries: Vec<u8> = alloc::vec![];
+            let mut upper_first_blks: Vec<Ext4InodeRelBlkId> = alloc::vec![];
+
+            for (node_entries, node_first_blks) in raw_entries
+                .chunks(blk_capacity * mem::size_of::<Extent>())
+                .zip(first_blks.chunks(blk_capacity))
+            {
+                let node_blk = *nodes_blks.next().ok_or(IOError::Unknown)?;
+                let header = ExtentHeader::new(
+                    node_first_blks.len(),
+                    blk_capacity,
+                    u16::try_from(depth).expect("invalid extent tree depth"),
+                )?;
+
+                let mut extent_blk = ExtentBlock::build(blk_size, header, node_entries);
+                if has_metadata_chksum {
+                    extent_blk.update_chksum(fs_uuid, inode.number, inode.generation());
+                }
+                fs.write_blk_to_device(node_blk, &extent_blk.0)?;
+
+                upper_raw_entries
+                    .extend_from_slice(bytes_of(&ExtentIdx::new(node_first_blks[0], node_blk)));
+                upper_first_blks.push(node_first_blks[0]);
+            }
+
+            raw_entries = upper_raw_entries;
+            first_blks = upper_first_blks;
+        }
+
+        let root_header = ExtentHeader::new(
+            first_blks.len(),
+            ROOT_CAPACITY,
+            u16::try_from(levels.len()).expect("invalid extent tree depth"),
+        )?;
+        let root = ExtentBlock::build(mem::size_of::<InodeBlk>(), root_header, &raw_entries);
+        inode.i_block = pod_read_unaligned(&root.0);
+
+        // blocks that are not used by the tree anymore are released
+        for &blk in self.tree_blks.iter().skip(required_blks) {
+            fs.free_blks(blk, 1)?;
+        }
+
+        let allocated = u64::try_from(required_blks.saturating_sub(old_blks_count))
+            .expect("invalid block count");
+        let freed = u64::try_from(old_blks_count.saturating_sub(required_blks))
+            .expect("invalid block count");
+        inode.update_blk_count(allocated, freed);
+
+        self.tree_blks = tree_blks;
+
+        Ok(())
+    }
 }
 
+/// Maximum number of blocks covered by an initialized [`Extent`].
+const MAX_INITIALIZED_EXTENT_LEN: u64 = 32768;
+
+/// Maximum number of entries of the root of an extent tree, stored in the `i_block` field of the inode.
+const ROOT_CAPACITY: usize =
+    (mem::size_of::<InodeBlk>() - mem::size_of::<ExtentHeader>()) / mem::size_of::<Extent>();
+
 /// A 16-bit physical block address (valid for direct reads from the disk).
 #[derive(Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord, Hash, Pod, Zeroable)]
 #[repr(transparent)]
@@ -452,6 +752,27 @@ pub(crate) struct ExtentHeader {
 }
 
 impl ExtentHeader {
+    /// Creates a new `ExtentHeader`, for a node of the tree holding `entries` entries out of `max` at depth `depth`.
+    fn new(entries: usize, max: usize, depth: u16) -> IOResult<Self> {
+        let mut header = Self {
+            magic: Ext4ExtentHeaderMagic::VALID_EXT4_MAGIC,
+            entries: Ext4ExtentHeaderEntriesCount(
+                u16::try_from(entries).expect("invalid extent entries count"),
+            ),
+            max: Ext4ExtentHeaderEntriesMax(
+                u16::try_from(max).expect("invalid extent entries count"),
+            ),
+            depth: Ext4ExtentHeaderDepth::LEAF_DEPTH,
+            generation: Ext4ExtentHeaderGeneration::default(),
+        };
+
+        let mut header_depth = header.depth;
+        header_depth.set_depth(depth)?;
+        header.depth = header_depth;
+
+        Ok(header)
+    }
+
     /// Checks if this header corresponds to leaf nodes.
     pub(crate) fn is_leaf(&self) -> bool {
         let depth = self.depth;
@@ -477,9 +798,18 @@ impl ExtentHeader {
 /// extent.
 #[derive(Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord, Hash, Pod, Zeroable)]
 #[repr(transparent)]
-pub(super) struct Ext4ExtentLength(u16);
+pub(crate) struct Ext4ExtentLength(u16);
 
 impl Ext4ExtentLength {
+    /// Creates a new `Ext4ExtentLength`, covering `length` blocks.
+    pub(crate) fn new(length: u16, initialized: bool) -> Self {
+        if initialized {
+            Self(length)
+        } else {
+            Self(length + 32768)
+        }
+    }
+
     /// Checks if this extent is initialized
     pub(crate) fn is_initialized(self) -> bool {
         self.0 <= 32768
@@ -560,12 +890,38 @@ pub(crate) struct Extent {
 }
 
 impl Extent {
+    /// Creates a new `Extent`, mapping the logical blocks starting from `block` to the physical blocks starting from
+    /// `start_blk`.
+    pub(crate) fn new(
+        block: Ext4InodeRelBlkId,
+        len: Ext4ExtentLength,
+        start_blk: Ext4RealBlkId,
+    ) -> Self {
+        Self {
+            block: Ext4ExtentInitialBlock(
+                u32::try_from(block.0).expect("invalid logical block number"),
+            ),
+            len,
+            start_hi: Ext4ExtentPtrHi(
+                u16::try_from(start_blk.0 >> 32).expect("invalid block number"),
+            ),
+            start_lo: Ext4ExtentPtrLo(
+                u32::try_from(start_blk.0 & 0xFFFF_FFFF).expect("invalid block number"),
+            ),
+        }
+    }
+
     pub(crate) fn start_blk(&self) -> Ext4RealBlkId {
         self.start_lo + self.start_hi
     }
 
+    /// Returns the first logical block following this extent.
+    pub(crate) fn end_blk(&self) -> Ext4InodeRelBlkId {
+        Ext4InodeRelBlkId(u64::from(self.block.0) + u64::from(self.len.length()))
+    }
+
     pub(crate) fn contains(&self, blk_id: Ext4InodeRelBlkId) -> bool {
-        self.block <= blk_id && self.block + self.len >= blk_id
+        self.block <= blk_id && self.end_blk() > blk_id
     }
 }
 
@@ -606,6 +962,23 @@ struct ExtentIdx {
 }
 
 impl ExtentIdx {
+    /// Creates a new `ExtentIdx`, pointing to the node of the tree stored in `leaf`, that covers the logical blocks
+    /// starting from `block`.
+    fn new(block: Ext4InodeRelBlkId, leaf: Ext4RealBlkId) -> Self {
+        Self {
+            block: Ext4ExtentInitialBlock(
+                u32::try_from(block.0).expect("invalid logical block number"),
+            ),
+            leaf_lo: Ext4ExtentLeafPtrLo(
+                u32::try_from(leaf.0 & 0xFFFF_FFFF).expect("invalid block number"),
+            ),
+            leaf_hi: Ext4ExtentLeafPtrHi(
+                u16::try_from(leaf.0 >> 32).expect("invalid block number"),
+            ),
+            unused: 0,
+        }
+    }
+
     fn leaf(&self) -> Ext4RealBlkId {
         self.leaf_lo + self.leaf_hi
     }
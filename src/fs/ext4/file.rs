@@ -4,15 +4,17 @@
 //! Serves as as interface between the `ext4` definition of a file and the abstract implementation in `FrozenBoot`
 
 use crate::errors::{CanFail, IOError};
-use crate::fs::ext4::extent::{Ext4InodeRelBlkId, Ext4InodeRelBlkIdRange, ExtentTree};
+use crate::fs::ext4::blockmap::BlockMap;
+use crate::fs::ext4::extent::{Ext4InodeRelBlkId, Ext4RealBlkId, ExtentTree};
+use crate::fs::ext4::inline::load_inline_data;
 use crate::fs::ext4::inode::{
-    InodeFileMode, InodeFlags, InodeNumber, InodeSize, LockedInode, LockedInodeStrongRef,
+    InodeFileMode, InodeFlags, InodeNumber, InodeSize, InodeType, LockedInode, LockedInodeStrongRef,
 };
-use crate::fs::ext4::LockedExt4Fs;
+use crate::fs::ext4::{Ext4Fs, LockedExt4Fs};
 use crate::fs::{FsFile, IOResult, Seek};
-use alloc::format;
-use bytemuck::{cast, try_cast};
-use core::slice;
+use crate::time::current_timestamp;
+use alloc::{format, vec::Vec};
+use bytemuck::cast;
 
 /// Representation of a file in the `ext4` filesystem.
 pub(crate) struct Ext4File {
@@ -20,6 +22,8 @@ pub(crate) struct Ext4File {
     inode: LockedInodeStrongRef,
     cursor: usize,
     extent_tree: Option<ExtentTree>,
+    block_map: Option<BlockMap>,
+    inline_data: Option<Vec<u8>>,
 }
 
 impl core::fmt::Debug for Ext4File {
@@ -37,6 +41,14 @@ impl core::fmt::Debug for Ext4File {
             f.write_str(&format!("Extents: \n{extent_tree:?}"))?;
         }
 
+        if let Some(block_map) = &self.block_map {
+            f.write_str(&format!("Block map: \n{block_map:?}"))?;
+        }
+
+        if let Some(inline_data) = &self.inline_data {
+            f.write_str(&format!("Inline data: {} bytes", inline_data.len()))?;
+        }
+
         Ok(())
     }
 }
@@ -53,84 +65,49 @@ macro_rules! ext4_fs_read_bytes {
             count: usize,
             buf: &mut [u8],
         ) -> CanFail<IOError> {
+            // inline content is loaded along with the inode, and never read from disk
+            if let Some(inline_data) = &self.inline_data {
+                let available = inline_data.get(offset..).unwrap_or_default();
+                let inline_count = usize::min(count, available.len());
+
+                buf[..inline_count].copy_from_slice(&available[..inline_count]);
+                buf[inline_count..count].fill(0);
+
+                return Ok(());
+            }
+
             let fs = self.fs.read();
-            let sb = fs.superblock.read();
-
-            let blk_offset_from_file_start: Ext4InodeRelBlkId =
-                try_cast(u64::try_from(offset).expect("invalid byte offset") / sb.blk_size())
-                    .map_err(|_| IOError::Unknown)?;
-            let blk_offset_from_first_blk =
-                offset % usize::try_from(sb.blk_size()).expect("invalid ext4fs block size");
-            let last_blk: Ext4InodeRelBlkId = try_cast(
-                u64::try_from(offset + count).expect("invalid byte offset") / sb.blk_size(),
-            )
-            .map_err(|_| IOError::Unknown)?;
-            let last_blk_count =
-                count % usize::try_from(sb.blk_size()).expect("invalid ext4fs block size");
+            let blk_size = usize::try_from(fs.superblock.read().blk_size())
+                .expect("invalid ext4fs block size");
+            let mut blk_buf = fs.allocate_blk();
+            let mut bytes_read = 0;
 
-            if let Some(ext_tree) = &self.extent_tree {
-                let mut useful_extents = ext_tree.extents.iter().filter(|ext| {
-                    (cast(ext.block)..ext.block + ext.len).contains(&(blk_offset_from_file_start))
-                });
-                let mut curr_extent = useful_extents.next().unwrap();
-
-                for i in Ext4InodeRelBlkIdRange(
-                    blk_offset_from_file_start,
-                    Ext4InodeRelBlkId::min(cast(0_u64), last_blk - 1),
-                ) {
-                    if (curr_extent.block + curr_extent.len) < i {
-                        curr_extent = useful_extents.next().unwrap();
-                    }
-                    fs.read_blk_from_device(
-                        try_cast(curr_extent.start_blk() + i).map_err(|_| IOError::Unknown)?,
-                        slice::from_raw_parts_mut(
-                            buf.as_mut_ptr().add(
-                                (try_cast::<Ext4InodeRelBlkId, u64>(i)
-                                    .map_err(|_| IOError::Unknown)?
-                                    * sb.blk_size())
-                                .try_into()
-                                .expect("invalid inode number"),
-                            ),
-                            sb.blk_size().try_into().expect("invalid fs block size"),
-                        ),
-                    )?;
-                }
+            while bytes_read < count {
+                let blk_id: Ext4InodeRelBlkId = cast(
+                    u64::try_from((offset + bytes_read) / blk_size).expect("invalid byte offset"),
+                );
+                let offset_in_blk = (offset + bytes_read) % blk_size;
+                let bytes_count = usize::min(blk_size - offset_in_blk, count - bytes_read);
 
-                if (curr_extent.block + curr_extent.len) < last_blk {
-                    curr_extent = useful_extents.next().unwrap();
-                }
+                let real_blk = if let Some(ext_tree) = &self.extent_tree {
+                    ext_tree.get_exact_blk_mapping(blk_id)
+                } else if let Some(block_map) = &self.block_map {
+                    block_map.get_exact_blk_mapping(blk_id)
+                } else {
+                    return Err(IOError::Unknown);
+                };
 
-                let mut temp_buf = fs.allocate_blk();
-                fs.read_blk_from_device(
-                    try_cast(curr_extent.start_blk() + last_blk).map_err(|_| IOError::Unknown)?,
-                    &mut temp_buf,
-                )?;
-
-                if last_blk == cast::<u64, Ext4InodeRelBlkId>(0) {
-                    slice::from_raw_parts_mut(
-                        buf.as_mut_ptr().add(
-                            (try_cast::<Ext4InodeRelBlkId, u64>(last_blk)
-                                .map_err(|_| IOError::Unknown)?
-                                * sb.blk_size())
-                            .try_into()
-                            .expect("invalid inode number"),
-                        ),
-                        last_blk_count - blk_offset_from_first_blk,
-                    )
-                    .copy_from_slice(&temp_buf[blk_offset_from_first_blk..last_blk_count]);
+                let dest = &mut buf[bytes_read..bytes_read + bytes_count];
+
+                if let Some(real_blk) = real_blk {
+                    fs.read_blk_from_device(real_blk, &mut blk_buf)?;
+                    dest.copy_from_slice(&blk_buf[offset_in_blk..offset_in_blk + bytes_count]);
                 } else {
-                    slice::from_raw_parts_mut(
-                        buf.as_mut_ptr().add(
-                            (try_cast::<Ext4InodeRelBlkId, u64>(last_blk)
-                                .map_err(|_| IOError::Unknown)?
-                                * sb.blk_size())
-                            .try_into()
-                            .expect("invalid inode number"),
-                        ),
-                        last_blk_count,
-                    )
-                    .copy_from_slice(&temp_buf[..last_blk_count]);
+                    // unmapped blocks are holes in a sparse file, which read as zeros
+                    dest.fill(0);
                 }
+
+                bytes_read += bytes_count;
             }
 
             Ok(())
@@ -155,6 +132,9 @@ impl Ext4File {
 
     /// Loads a `Ext4File` from disk, from its [`InodeNumber`] and the corresponding [`Ext4Inode`] structure.
     ///
+    /// The inode must be a regular file, or a symbolic link (in which case the content of the file is the target of
+    /// the link).
+    ///
     /// # Errors
     ///
     /// May return any variant of [`IOError`] in case of a failure while attempting to read from disk, or because of
@@ -167,12 +147,17 @@ impl Ext4File {
         let inode_ptr = locked_inode.upgrade().ok_or(IOError::Unknown)?;
         let inode = inode_ptr.read();
 
-        if !inode.mode_contains(InodeFileMode::S_IFREG) {
+        if !matches!(
+            inode.inode_type(),
+            InodeType::Regular | InodeType::SymbolicLink
+        ) {
             return Err(IOError::Unknown);
         }
 
+        let inline_data = load_inline_data(&inode);
         drop(inode);
 
+        let block_map = BlockMap::load_block_map(&locked_fs, &inode_ptr);
         let extent_tree = ExtentTree::load_extent_tree(locked_fs, inode_ptr.clone());
 
         Ok(Self {
@@ -180,9 +165,133 @@ impl Ext4File {
             inode: inode_ptr,
             cursor: 0,
             extent_tree,
+            block_map,
+            inline_data,
         })
     }
 
+    /// Returns the physical block address corresponding to a logical block of this file.
+    ///
+    /// Returns `None` if that block is not allocated, or if the content of the file is stored inline.
+    pub(crate) fn get_blk_mapping(&self, blk_id: Ext4InodeRelBlkId) -> Option<Ext4RealBlkId> {
+        if let Some(extent_tree) = &self.extent_tree {
+            extent_tree.get_exact_blk_mapping(blk_id)
+        } else {
+            self.block_map.as_ref()?.get_exact_blk_mapping(blk_id)
+        }
+    }
+
+    /// Writes `buf` to the blocks of this file, starting from the position of the cursor.
+    ///
+    /// Unmapped blocks (past the end of the file, or holes of a sparse file) are allocated in runs of contiguous
+    /// blocks, which are pushed to `new_runs` once mapped to the file. Returns the number of bytes written, which is
+    /// lower than the length of `buf` if the filesystem is full.
+    fn write_blks(
+        &mut self,
+        fs: &Ext4Fs,
+        buf: &[u8],
+        new_runs: &mut Vec<(Ext4RealBlkId, u64)>,
+    ) -> IOResult<usize> {
+        let extent_tree = self.extent_tree.as_mut().ok_or(IOError::InvalidCommand)?;
+        let blk_size =
+            usize::try_from(fs.superblock.read().blk_size()).expect("invalid block size");
+        let last_blk =
+            u64::try_from((self.cursor + buf.len() - 1) / blk_size).expect("invalid byte offset");
+        let inode_number = self.inode.read().number;
+
+        let mut blk_buf = fs.allocate_blk();
+        let mut written = 0;
+
+        while written < buf.len() {
+            let offset = self.cursor + written;
+            let blk_idx = u64::try_from(offset / blk_size).expect("invalid byte offset");
+            let blk_id: Ext4InodeRelBlkId = cast(blk_idx);
+
+            if let Some(real_blk) = extent_tree.get_exact_blk_mapping(blk_id) {
+                // uninitialized extents read as zeros, which must be written before they are marked as initialized
+                if let Some((first, count)) = extent_tree.initialize_extent(blk_id) {
+                    blk_buf.fill(0);
+
+                    for idx in 0..count {
+                        fs.write_blk_to_device(first + idx, &blk_buf)?;
+                    }
+                }
+
+                let offset_in_blk = offset % blk_size;
+                let count = usize::min(blk_size - offset_in_blk, buf.len() - written);
+
+                if count < blk_size {
+                    fs.read_blk_from_device(real_blk, &mut blk_buf)?;
+                }
+
+                blk_buf[offset_in_blk..offset_in_blk + count]
+                    .copy_from_slice(&buf[written..written + count]);
+                fs.write_blk_to_device(real_blk, &blk_buf)?;
+
+                written += count;
+                continue;
+            }
+
+            let run_len = u64::try_from(
+                (blk_idx..=last_blk)
+                    .take_while(|&blk| extent_tree.get_exact_blk_mapping(cast(blk)).is_none())
+                    .count(),
+            )
+            .expect("invalid block count");
+            let goal = blk_idx
+                .checked_sub(1)
+                .and_then(|prev| extent_tree.get_exact_blk_mapping(cast(prev)))
+                .or_else(|| extent_tree.last_blk())
+                .map_or_else(
+                    || {
+                        let sb = fs.superblock.read();
+                        sb.bg_first_blk(sb.get_inode_blk_group(inode_number))
+                    },
+                    |blk| blk + 1,
+                );
+
+            let (first, len) = match fs.allocate_blks(goal, run_len) {
+                Ok(run) => run,
+                // bytes written so far are kept when the filesystem is full
+                Err(IOError::NoSpace) if written > 0 => break,
+                Err(err) => return Err(err),
+            };
+
+            for idx in 0..len {
+                let offset = self.cursor + written;
+                let offset_in_blk = offset % blk_size;
+                let count = usize::min(blk_size - offset_in_blk, buf.len() - written);
+
+                blk_buf.fill(0);
+                blk_buf[offset_in_blk..offset_in_blk + count]
+                    .copy_from_slice(&buf[written..written + count]);
+
+                if let Err(err) = fs.write_blk_to_device(first + idx, &blk_buf) {
+                    fs.free_blks(first, len)?;
+                    return Err(err);
+                }
+
+                written += count;
+            }
+
+            extent_tree.map_blks(first, blk_id, len);
+            new_runs.push((first, len));
+        }
+
+        Ok(written)
+    }
+
+    /// Releases the blocks allocated for a failed write, and reloads the extent tree of this file from its inode.
+    fn abort_write(&mut self, fs: &Ext4Fs, new_runs: &[(Ext4RealBlkId, u64)]) -> CanFail<IOError> {
+        for &(blk, count) in new_runs {
+            fs.free_blks(blk, count)?;
+        }
+
+        self.extent_tree = ExtentTree::load_extent_tree(self.fs.clone(), self.inode.clone());
+
+        Ok(())
+    }
+
     ext4_fs_read_bytes!();
 }
 
@@ -196,6 +305,53 @@ impl FsFile for Ext4File {
         Ok(bytes_count)
     }
 
+    fn write(&mut self, buf: &[u8]) -> IOResult<usize> {
+        if buf.is_empty() {
+            return Ok(0);
+        }
+
+        let locked_fs = self.fs.clone();
+        let fs = locked_fs.read();
+        fs.check_writable()?;
+
+        // only files mapped with an extent tree can be written to
+        if self.extent_tree.is_none() {
+            return Err(IOError::InvalidCommand);
+        }
+
+        let mut new_runs = alloc::vec![];
+        let written = match self.write_blks(&fs, buf, &mut new_runs) {
+            Ok(written) => written,
+            Err(err) => {
+                self.abort_write(&fs, &new_runs)?;
+                return Err(err);
+            }
+        };
+
+        let extent_tree = self.extent_tree.as_mut().ok_or(IOError::Unknown)?;
+        let mut inode = self.inode.write();
+
+        if let Err(err) = extent_tree.write_tree(&fs, &mut inode) {
+            drop(inode);
+            self.abort_write(&fs, &new_runs)?;
+            return Err(err);
+        }
+
+        let end = u64::try_from(self.cursor + written).expect("invalid file size");
+        if end > cast::<InodeSize, u64>(inode.size()) {
+            inode.set_size(cast(end));
+        }
+
+        inode.update_blk_count(new_runs.iter().map(|&(_, count)| count).sum(), 0);
+        inode.touch(current_timestamp());
+        fs.write_inode(&mut inode)?;
+        drop(inode);
+
+        self.seek(Seek::Forward(written));
+
+        Ok(written)
+    }
+
     fn seek(&mut self, pos: Seek) -> usize {
         match pos {
             Seek::Backward(count) => {
@@ -216,10 +372,120 @@ impl FsFile for Ext4File {
     }
 
     fn truncate(&mut self, size: usize) -> IOResult<usize> {
-        todo!()
+        if size > self.size()? {
+            return Err(IOError::InvalidCommand);
+        }
+
+        let fs = self.fs.read();
+        fs.check_writable()?;
+
+        // only files mapped with an extent tree can be resized
+        let extent_tree = self.extent_tree.as_mut().ok_or(IOError::InvalidCommand)?;
+        let blk_size =
+            usize::try_from(fs.superblock.read().blk_size()).expect("invalid block size");
+        let kept_blks = u64::try_from(size.div_ceil(blk_size)).expect("invalid block count");
+
+        let unmapped = extent_tree.remove_blks_from(cast(kept_blks));
+        let freed: u64 = unmapped.iter().map(|&(_, count)| count).sum();
+
+        let mut inode = self.inode.write();
+        extent_tree.write_tree(&fs, &mut inode)?;
+        inode.set_size(cast(u64::try_from(size).expect("invalid file size")));
+        inode.update_blk_count(0, freed);
+        fs.write_inode(&mut inode)?;
+        drop(inode);
+
+        // blocks are released once the inode does not reference them anymore
+        for (blk, count) in unmapped {
+            fs.free_blks(blk, count)?;
+        }
+
+        Ok(size)
     }
 
     fn extend(&mut self, size: usize) -> IOResult<usize> {
-        todo!()
+        let current_size = self.size()?;
+        if size < current_size {
+            return Err(IOError::InvalidCommand);
+        }
+
+        let fs = self.fs.read();
+        fs.check_writable()?;
+
+        // only files mapped with an extent tree can be resized
+        let extent_tree = self.extent_tree.as_mut().ok_or(IOError::InvalidCommand)?;
+        let blk_size =
+            usize::try_from(fs.superblock.read().blk_size()).expect("invalid block size");
+        let mut blk_buf = fs.allocate_blk();
+
+        // the end of the last block may hold stale bytes, which must read as zeros once the file is extended
+        let offset_in_blk = current_size % blk_size;
+        let last_blk: Ext4InodeRelBlkId =
+            cast(u64::try_from(current_size / blk_size).expect("invalid byte offset"));
+
+        if size > current_size && offset_in_blk != 0 {
+            if let Some(real_blk) = extent_tree.get_exact_blk_mapping(last_blk) {
+                fs.read_blk_from_device(real_blk, &mut blk_buf)?;
+                blk_buf[offset_in_blk..].fill(0);
+                fs.write_blk_to_device(real_blk, &blk_buf)?;
+                blk_buf.fill(0);
+            }
+        }
+
+        let required_blks = u64::try_from(size.div_ceil(blk_size)).expect("invalid block count");
+        // blocks may already be mapped past the end of the file
+        let first_new_blk = u64::max(
+            u64::try_from(current_size.div_ceil(blk_size)).expect("invalid block count"),
+            extent_tree
+                .extents
+                .last()
+                .map_or(0, |extent| cast(extent.end_blk())),
+        );
+
+        let inode_number = self.inode.read().number;
+        let mut allocated = 0;
+
+        while first_new_blk + allocated < required_blks {
+            let goal = extent_tree.last_blk().map_or_else(
+                || {
+                    let sb = fs.superblock.read();
+                    sb.bg_first_blk(sb.get_inode_blk_group(inode_number))
+                },
+                |blk| blk + 1,
+            );
+
+            let allocation = fs
+                .allocate_blks(goal, required_blks - first_new_blk - allocated)
+                .and_then(|(blk, count)| {
+                    for idx in 0..count {
+                        fs.write_blk_to_device(blk + idx, &blk_buf)?;
+                    }
+
+                    Ok((blk, count))
+                });
+
+            match allocation {
+                Ok((blk, count)) => {
+                    extent_tree.map_blks(blk, cast(first_new_blk + allocated), count);
+                    allocated += count;
+                }
+                Err(err) => {
+                    // blocks allocated for this extension are released, the file is left unchanged
+                    for (blk, count) in extent_tree.remove_blks_from(cast(first_new_blk)) {
+                        fs.free_blks(blk, count)?;
+                    }
+
+                    return Err(err);
+                }
+            }
+        }
+
+        let mut inode = self.inode.write();
+        extent_tree.write_tree(&fs, &mut inode)?;
+        inode.set_size(cast(u64::try_from(size).expect("invalid file size")));
+        inode.update_blk_count(allocated, 0);
+        fs.write_inode(&mut inode)?;
+
+        Ok(size)
     }
 }
@@ -0,0 +1,628 @@
+//! ext4 hashed directory index (`htree`) implementation
+//!
+//! Directories with the `EXT4_INDEX_FL` flag are indexed by a constant-depth tree, keyed by a hash of the file names.
+//! The first block of the directory holds the root of the tree (`dx_root`), disguised as the `.` and `..` entries so
+//! that it is skipped when the directory is scanned linearly, and the inner nodes (`dx_node`) are stored in blocks
+//! disguised as a single unused entry. The leaves are regular directory blocks, holding the entries whose name hash
+//! falls in the range covered by their index entry.
+//!
+//! Looking up a name only requires reading one block per level of the tree, and the leaf block covering its hash.
+
+use core::mem::size_of;
+
+use alloc::vec::Vec;
+use bytemuck::{bytes_of, cast, pod_read_unaligned, Pod, Zeroable};
+
+use crate::error;
+use crate::fs::ext4::crc32c_calc;
+use crate::fs::ext4::inode::{InodeGeneration, InodeNumber};
+use crate::fs::ext4::sb::{Ext4FsUuid, Ext4HashAlgorithm};
+
+/// Seed used by the hash algorithms when the superblock does not define one.
+const DEFAULT_HASH_SEED: [u32; 4] = [0x6745_2301, 0xEFCD_AB89, 0x98BA_DCFE, 0x1032_5476];
+
+/// Largest hash value used by 32-bit directory hashes, reserved to mark the end of a directory.
+const HTREE_EOF_32BIT: u32 = 0x7FFF_FFFF;
+
+/// Key schedule constant of the TEA algorithm.
+const TEA_DELTA: u32 = 0x9E37_79B9;
+
+/// Offset of the [`DxRootInfo`] structure in the first block of the directory, right after the fake `.` and `..`
+/// entries.
+const DX_ROOT_INFO_OFFSET: usize = 24;
+
+/// Offset of the index entries in an inner node, right after the fake directory entry header.
+const DX_NODE_ENTRIES_OFFSET: usize = 8;
+
+/// Hash of a file name, used to locate its entry in an indexed directory.
+///
+/// The lowest bit of the hash is always cleared, as it is used in index entries to flag hash collisions spanning
+/// several leaf blocks.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord, Hash, Pod, Zeroable)]
+#[repr(transparent)]
+pub(crate) struct DxHash(u32);
+
+impl DxHash {
+    /// Computes the hash of a file name with the given algorithm and seed (`s_hash_seed` field of the superblock).
+    ///
+    /// Returns `None` if the hash algorithm is not supported.
+    pub(crate) fn from_name(
+        name: &[u8],
+        algorithm: Ext4HashAlgorithm,
+        seed: [u32; 4],
+    ) -> Option<Self> {
+        let mut buf = if seed.iter().any(|&word| word != 0) {
+            seed
+        } else {
+            DEFAULT_HASH_SEED
+        };
+
+        let hash = match algorithm {
+            Ext4HashAlgorithm::LEGACY => legacy_hash(name, false),
+            Ext4HashAlgorithm::LEGACY_UNSIGNED => legacy_hash(name, true),
+            Ext4HashAlgorithm::HALF_MD4 | Ext4HashAlgorithm::HALD_MD4_UNSIGNED => {
+                let unsigned = algorithm == Ext4HashAlgorithm::HALD_MD4_UNSIGNED;
+
+                for offset in (0..name.len()).step_by(32) {
+                    half_md4_transform(&mut buf, str_to_hash_buf(&name[offset..], unsigned));
+                }
+
+                buf[1]
+            }
+            Ext4HashAlgorithm::TEA | Ext4HashAlgorithm::TEA_UNSIGNED => {
+                let unsigned = algorithm == Ext4HashAlgorithm::TEA_UNSIGNED;
+
+                for offset in (0..name.len()).step_by(16) {
+                    tea_transform(&mut buf, str_to_hash_buf(&name[offset..], unsigned));
+                }
+
+                buf[0]
+            }
+            _ => return None,
+        };
+
+        let hash = hash & !1;
+
+        if hash == HTREE_EOF_32BIT << 1 {
+            Some(Self((HTREE_EOF_32BIT - 1) << 1))
+        } else {
+            Some(Self(hash))
+        }
+    }
+}
+
+impl Ext4HashAlgorithm {
+    /// Returns the unsigned variant of this hash algorithm.
+    ///
+    /// Signed and unsigned variants only differ in the way they handle bytes above `0x7F`: they must be used on
+    /// filesystems which set the `UNSIGNED_DIR_HASH` flag in their superblock.
+    pub(crate) fn as_unsigned(self) -> Self {
+        match self {
+            Self::LEGACY => Self::LEGACY_UNSIGNED,
+            Self::HALF_MD4 => Self::HALD_MD4_UNSIGNED,
+            Self::TEA => Self::TEA_UNSIGNED,
+            _ => self,
+        }
+    }
+}
+
+/// Extends a byte of a file name to 32-bits, as an `unsigned char` or as a `signed char`.
+fn extend_name_byte(byte: u8, unsigned: bool) -> u32 {
+    if unsigned || byte < 0x80 {
+        u32::from(byte)
+    } else {
+        u32::from(byte) | 0xFFFF_FF00
+    }
+}
+
+/// Original `ext3` directory hash.
+fn legacy_hash(name: &[u8], unsigned: bool) -> u32 {
+    let mut hash0: u32 = 0x12A3_FE2D;
+    let mut hash1: u32 = 0x37AB_E8F9;
+
+    for &byte in name {
+        let mut hash =
+            hash1.wrapping_add(hash0 ^ extend_name_byte(byte, unsigned).wrapping_mul(7_152_373));
+
+        if hash & 0x8000_0000 != 0 {
+            hash = hash.wrapping_sub(0x7FFF_FFFF);
+        }
+
+        hash1 = hash0;
+        hash0 = hash;
+    }
+
+    hash0 << 1
+}
+
+/// Packs (at most) the first `4 * N` bytes of a file name into `N` words, padded with a value derived from the
+/// remaining length of the name.
+fn str_to_hash_buf<const N: usize>(msg: &[u8], unsigned: bool) -> [u32; N] {
+    let len = u32::try_from(msg.len()).expect("invalid file name length");
+    let mut pad = len | (len << 8);
+    pad |= pad << 16;
+
+    let mut buf = [pad; N];
+    let mut word = 0;
+    let mut val = pad;
+
+    for (idx, &byte) in msg.iter().take(4 * N).enumerate() {
+        val = extend_name_byte(byte, unsigned).wrapping_add(val << 8);
+
+        if idx % 4 == 3 {
+            buf[word] = val;
+            word += 1;
+            val = pad;
+        }
+    }
+
+    if word < N {
+        buf[word] = val;
+    }
+
+    buf
+}
+
+/// Round of the half-MD4 transform.
+struct HalfMd4Round {
+    /// Boolean function of the round.
+    function: fn(u32, u32, u32) -> u32,
+
+    /// Constant added to each input word.
+    constant: u32,
+
+    /// Order in which the input words are processed.
+    order: [usize; 8],
+
+    /// Rotations applied by each step of the round (repeated twice).
+    shifts: [u32; 4],
+}
+
+/// The three rounds of the half-MD4 transform: selection, majority, and parity.
+const HALF_MD4_ROUNDS: [HalfMd4Round; 3] = [
+    HalfMd4Round {
+        function: |x, y, z| z ^ (x & (y ^ z)),
+        constant: 0,
+        order: [0, 1, 2, 3, 4, 5, 6, 7],
+        shifts: [3, 7, 11, 19],
+    },
+    HalfMd4Round {
+        function: |x, y, z| (x & y).wrapping_add((x ^ y) & z),
+        constant: 0x5A82_7999,
+        order: [1, 3, 5, 7, 0, 2, 4, 6],
+        shifts: [3, 5, 9, 13],
+    },
+    HalfMd4Round {
+        function: |x, y, z| x ^ y ^ z,
+        constant: 0x6ED9_EBA1,
+        order: [3, 7, 2, 6, 1, 5, 0, 4],
+        shifts: [3, 9, 11, 15],
+    },
+];
+
+/// Cut-down version of the MD4 transform, mixing 8 words of input into `buf`.
+fn half_md4_transform(buf: &mut [u32; 4], input: [u32; 8]) {
+    let mut state = *buf;
+
+    for round in &HALF_MD4_ROUNDS {
+        for (step, &word) in round.order.iter().enumerate() {
+            // each step updates the registers `a`, `d`, `c`, `b` in turn
+            let target = (4 - step % 4) % 4;
+            let mixed = (round.function)(
+                state[(target + 1) % 4],
+                state[(target + 2) % 4],
+                state[(target + 3) % 4],
+            );
+
+            state[target] = state[target]
+                .wrapping_add(mixed)
+                .wrapping_add(input[word].wrapping_add(round.constant))
+                .rotate_left(round.shifts[step % 4]);
+        }
+    }
+
+    for (word, mixed) in buf.iter_mut().zip(state) {
+        *word = word.wrapping_add(mixed);
+    }
+}
+
+/// Tiny Encryption Algorithm transform, mixing 4 words of input into `buf`.
+fn tea_transform(buf: &mut [u32; 4], input: [u32; 4]) {
+    let [a, b, c, d] = input;
+    let mut b0 = buf[0];
+    let mut b1 = buf[1];
+    let mut sum: u32 = 0;
+
+    for _ in 0..16 {
+        sum = sum.wrapping_add(TEA_DELTA);
+        b0 = b0.wrapping_add(
+            (b1 << 4).wrapping_add(a) ^ b1.wrapping_add(sum) ^ (b1 >> 5).wrapping_add(b),
+        );
+        b1 = b1.wrapping_add(
+            (b0 << 4).wrapping_add(c) ^ b0.wrapping_add(sum) ^ (b0 >> 5).wrapping_add(d),
+        );
+    }
+
+    buf[0] = buf[0].wrapping_add(b0);
+    buf[1] = buf[1].wrapping_add(b1);
+}
+
+/// Header of the index, stored in the first block of the directory after the fake `.` and `..` entries.
+#[derive(Clone, Copy, Debug, Pod, Zeroable)]
+#[repr(C)]
+struct DxRootInfo {
+    reserved_zero: u32,
+
+    /// Hash algorithm used to index the directory
+    hash_version: Ext4HashAlgorithm,
+
+    /// Length of this structure (8)
+    info_length: u8,
+
+    /// Depth of the tree, excluding the root and the leaves
+    indirect_levels: u8,
+
+    unused_flags: u8,
+}
+
+/// Number of index entries of an index block, stored in place of the hash of its first entry.
+#[derive(Clone, Copy, Debug, Pod, Zeroable)]
+#[repr(C)]
+struct DxCountLimit {
+    /// Maximum number of index entries that fit in the block
+    limit: u16,
+
+    /// Number of index entries in the block
+    count: u16,
+}
+
+/// Entry of an index block.
+///
+/// Maps the names whose hash is greater than or equal to `hash` (and lower than the hash of the next entry) to a block
+/// of the directory.
+#[derive(Clone, Copy, Debug, Pod, Zeroable)]
+#[repr(C)]
+struct DxEntry {
+    hash: DxHash,
+
+    /// Block of the directory, relative to its start
+    block: u32,
+}
+
+/// Checksum of an index block, stored after its last possible entry.
+#[derive(Clone, Copy, Debug, Pod, Zeroable)]
+#[repr(C)]
+struct DxTail {
+    reserved: u32,
+    checksum: u32,
+}
+
+/// Root of the index of a directory.
+#[derive(Clone, Debug)]
+pub(crate) struct DxRoot {
+    /// Hash algorithm used to index the directory.
+    pub(crate) hash_version: Ext4HashAlgorithm,
+
+    /// Depth of the tree, excluding the root and the leaves.
+    pub(crate) indirect_levels: u8,
+
+    /// Index entries of the root.
+    pub(crate) entries: DxEntries,
+}
+
+impl DxRoot {
+    /// Parses the root of the index, from the first block of the directory.
+    ///
+    /// Returns `None` if the block does not hold a valid index root.
+    pub(crate) fn parse(blk: &[u8]) -> Option<Self> {
+        let info: DxRootInfo = pod_read_unaligned(
+            blk.get(DX_ROOT_INFO_OFFSET..DX_ROOT_INFO_OFFSET + size_of::<DxRootInfo>())?,
+        );
+
+        if info.reserved_zero != 0 || usize::from(info.info_length) != size_of::<DxRootInfo>() {
+            return None;
+        }
+
+        Some(Self {
+            hash_version: info.hash_version,
+            indirect_levels: info.indirect_levels,
+            entries: DxEntries::parse(blk, DX_ROOT_INFO_OFFSET + usize::from(info.info_length))?,
+        })
+    }
+}
+
+/// Index entries of an index block (the root of the index, or an inner node).
+#[derive(Clone, Debug)]
+pub(crate) struct DxEntries {
+    entries: Vec<DxEntry>,
+
+    /// Offset of the first entry (overlapped by the [`DxCountLimit`] structure) in the block.
+    count_offset: usize,
+
+    /// Maximum number of entries of the block.
+    limit: usize,
+}
+
+impl DxEntries {
+    /// Parses the index entries of an inner node of the index.
+    ///
+    /// Returns `None` if the block does not hold a valid index node.
+    pub(crate) fn parse_node(blk: &[u8]) -> Option<Self> {
+        Self::parse(blk, DX_NODE_ENTRIES_OFFSET)
+    }
+
+    fn parse(blk: &[u8], count_offset: usize) -> Option<Self> {
+        let count_limit: DxCountLimit =
+            pod_read_unaligned(blk.get(count_offset..count_offset + size_of::<DxCountLimit>())?);
+        let count = usize::from(count_limit.count);
+        let limit = usize::from(count_limit.limit);
+
+        if count == 0 || count > limit || count_offset + limit * size_of::<DxEntry>() > blk.len() {
+            return None;
+        }
+
+        let entries = blk[count_offset..count_offset + count * size_of::<DxEntry>()]
+            .chunks_exact(size_of::<DxEntry>())
+            .map(pod_read_unaligned)
+            .collect();
+
+        Some(Self {
+            entries,
+            count_offset,
+            limit,
+        })
+    }
+
+    /// Returns the index of the entry covering `hash`.
+    ///
+    /// The first entry covers every hash lower than the hash of the second one.
+    pub(crate) fn lookup(&self, hash: DxHash) -> usize {
+        self.entries[1..].partition_point(|entry| entry.hash <= hash)
+    }
+
+    /// Returns the directory block pointed to by an entry.
+    pub(crate) fn block(&self, idx: usize) -> u32 {
+        self.entries[idx].block
+    }
+
+    /// Checks if the block pointed to by an entry continues the names with the given hash from the previous block.
+    ///
+    /// When several names share the same hash and do not fit in a single leaf, the hash of the entries pointing to the
+    /// following leaves has its lowest bit set.
+    pub(crate) fn continues_hash(&self, idx: usize, hash: DxHash) -> bool {
+        idx > 0 && DxHash(self.entries[idx].hash.0 & !1) == hash
+    }
+
+    /// Returns the number of entries.
+    pub(crate) fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    /// Compares the checksum of the index block loaded in memory (`blk`) to its on-disk value.
+    ///
+    /// Index blocks are only checksummed on filesystems using the `metadata_csum` feature.
+    ///
+    /// The checksum of an index block is:
+    ///
+    /// ```
+    /// crc32c_calc(fs_uuid + inode_id + inode_gen + index_entries + dx_tail)
+    /// ```
+    pub(crate) fn validate_chksum(
+        &self,
+        blk: &[u8],
+        fs_uuid: Ext4FsUuid,
+        inode_id: InodeNumber,
+        inode_gen: InodeGeneration,
+    ) -> bool {
+        let tail_offset = self.tail_offset();
+        let Some(raw_tail) = blk.get(tail_offset..tail_offset + size_of::<DxTail>()) else {
+            return false;
+        };
+        let tail: DxTail = pod_read_unaligned(raw_tail);
+
+        let comp_chksum = self.compute_chksum(blk, tail, fs_uuid, inode_id, inode_gen);
+
+        if comp_chksum != tail.checksum {
+            error!(
+                "ext4",
+                "invalid directory index checksum (inode {:#x})",
+                cast::<InodeNumber, u32>(inode_id)
+            );
+
+            return false;
+        }
+
+        true
+    }
+
+    /// Updates the checksum of the index block loaded in memory (`blk`), after its header has been modified.
+    ///
+    /// Does nothing if the block has no room for a checksum.
+    pub(crate) fn update_chksum(
+        &self,
+        blk: &mut [u8],
+        fs_uuid: Ext4FsUuid,
+        inode_id: InodeNumber,
+        inode_gen: InodeGeneration,
+    ) {
+        let tail_offset = self.tail_offset();
+        let Some(raw_tail) = blk.get(tail_offset..tail_offset + size_of::<DxTail>()) else {
+            return;
+        };
+        let mut tail: DxTail = pod_read_unaligned(raw_tail);
+
+        tail.checksum = self.compute_chksum(blk, tail, fs_uuid, inode_id, inode_gen);
+        blk[tail_offset..tail_offset + size_of::<DxTail>()].copy_from_slice(bytes_of(&tail));
+    }
+
+    /// Computes the checksum of the index block loaded in memory (`blk`).
+    fn compute_chksum(
+        &self,
+        blk: &[u8],
+        tail: DxTail,
+        fs_uuid: Ext4FsUuid,
+        inode_id: InodeNumber,
+        inode_gen: InodeGeneration,
+    ) -> u32 {
+        let mut chksum_bytes: Vec<u8> = alloc::vec![];
+        chksum_bytes.extend_from_slice(bytes_of(&fs_uuid));
+        chksum_bytes.extend_from_slice(bytes_of(&inode_id));
+        chksum_bytes.extend_from_slice(bytes_of(&inode_gen));
+        chksum_bytes.extend_from_slice(
+            &blk[..self.count_offset + self.entries.len() * size_of::<DxEntry>()],
+        );
+        chksum_bytes.extend_from_slice(bytes_of(&DxTail {
+            reserved: tail.reserved,
+            checksum: 0,
+        }));
+
+        crc32c_calc(&chksum_bytes)
+    }
+
+    /// Returns the offset of the [`DxTail`] structure in the block, right after the last possible entry.
+    fn tail_offset(&self) -> usize {
+        self.count_offset + self.limit * size_of::<DxEntry>()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::DxHash;
+    use crate::fs::ext4::sb::Ext4HashAlgorithm;
+
+    /// Names hashed by the test vectors, the third one holds bytes above `0x7F`.
+    const NAMES: [&[u8]; 5] = [
+        b"",
+        b"a",
+        "café".as_bytes(),
+        b"Hello.txt",
+        b"this-is-a-file-name-longer-than-thirty-two-bytes.conf",
+    ];
+
+    /// Hash algorithms, in the order of the hashes of the test vectors.
+    const ALGORITHMS: [Ext4HashAlgorithm; 6] = [
+        Ext4HashAlgorithm::LEGACY,
+        Ext4HashAlgorithm::HALF_MD4,
+        Ext4HashAlgorithm::TEA,
+        Ext4HashAlgorithm::LEGACY_UNSIGNED,
+        Ext4HashAlgorithm::HALD_MD4_UNSIGNED,
+        Ext4HashAlgorithm::TEA_UNSIGNED,
+    ];
+
+    /// Hashes of [`NAMES`] with the default seed.
+    const DEFAULT_SEED_HASHES: [[u32; 6]; 5] = [
+        [
+            0x2547_FC5A,
+            0xEFCD_AB88,
+            0x6745_2300,
+            0x2547_FC5A,
+            0xEFCD_AB88,
+            0x6745_2300,
+        ],
+        [
+            0xE74B_53E2,
+            0xD5FA_7D7A,
+            0x6D0E_A4C0,
+            0xE74B_53E2,
+            0xD5FA_7D7A,
+            0x6D0E_A4C0,
+        ],
+        [
+            0x96CA_5A2C,
+            0xFB9C_5E5C,
+            0x1058_42EA,
+            0x6DDE_4230,
+            0x9D72_AED6,
+            0x6621_F032,
+        ],
+        [
+            0xAA6B_82EE,
+            0x54AB_9E82,
+            0x89B6_07BC,
+            0xAA6B_82EE,
+            0x54AB_9E82,
+            0x89B6_07BC,
+        ],
+        [
+            0x56DC_C7A8,
+            0x5189_41DC,
+            0x2FA3_02A2,
+            0x56DC_C7A8,
+            0x5189_41DC,
+            0x2FA3_02A2,
+        ],
+    ];
+
+    /// Hashes of [`NAMES`] with the seed `12345678-9abc-def0-1234-56789abcdef0`.
+    const FILESYSTEM_SEED_HASHES: [[u32; 6]; 5] = [
+        [
+            0x2547_FC5A,
+            0xF0DE_BC9A,
+            0x7856_3412,
+            0x2547_FC5A,
+            0xF0DE_BC9A,
+            0x7856_3412,
+        ],
+        [
+            0xE74B_53E2,
+            0xBD1A_2D4E,
+            0x42E8_F46E,
+            0xE74B_53E2,
+            0xBD1A_2D4E,
+            0x42E8_F46E,
+        ],
+        [
+            0x96CA_5A2C,
+            0x6B27_2632,
+            0x390E_3560,
+            0x6DDE_4230,
+            0xED36_F0B4,
+            0xA89C_7908,
+        ],
+        [
+            0xAA6B_82EE,
+            0x0447_EE0C,
+            0xF0F0_698E,
+            0xAA6B_82EE,
+            0x0447_EE0C,
+            0xF0F0_698E,
+        ],
+        [
+            0x56DC_C7A8,
+            0xC8FD_7BE8,
+            0x9950_769C,
+            0x56DC_C7A8,
+            0xC8FD_7BE8,
+            0x9950_769C,
+        ],
+    ];
+
+    /// Checks the hashes of [`NAMES`] with each algorithm of [`ALGORITHMS`].
+    ///
+    /// The expected hashes were computed by `e2fsprogs`, with `debugfs -R 'dx_hash -h <version> -s <seed> "<name>"'`.
+    fn check_hashes(seed: [u32; 4], expected: [[u32; 6]; 5]) {
+        for (name, hashes) in NAMES.iter().zip(expected) {
+            for (algorithm, hash) in ALGORITHMS.into_iter().zip(hashes) {
+                assert_eq!(
+                    DxHash::from_name(name, algorithm, seed),
+                    Some(DxHash(hash)),
+                    "hash of {name:?} with {algorithm:?}"
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn hashes_with_default_seed() {
+        check_hashes([0; 4], DEFAULT_SEED_HASHES);
+    }
+
+    #[test]
+    fn hashes_with_filesystem_seed() {
+        // words of the seed of [`FILESYSTEM_SEED_HASHES`], as stored in the superblock
+        check_hashes(
+            [0x7856_3412, 0xF0DE_BC9A, 0x7856_3412, 0xF0DE_BC9A],
+            FILESYSTEM_SEED_HASHES,
+        );
+    }
+}
@@ -0,0 +1,92 @@
+//! ext4 inline data implementation
+//!
+//! With the `inline_data` feature, the content of very small files and directories is stored in their inode: the
+//! first 60 bytes in place of the block map or extent tree (`i_block`), and the remaining bytes in the `system.data`
+//! extended attribute.
+//!
+//! Symbolic links whose target is shorter than 60 bytes (_fast_ symbolic links) are also stored in `i_block`, on
+//! every revision of the filesystem.
+//!
+//! Inline directories do not hold the `.` and `..` entries: the first 4 bytes of `i_block` store the inode number
+//! of the parent directory instead, and are followed by regular directory entries.
+
+use core::mem::size_of;
+
+use alloc::vec::Vec;
+use bytemuck::{bytes_of, cast, pod_read_unaligned};
+
+use crate::fs::ext4::dir::Ext4DirectoryFileType;
+use crate::fs::ext4::inode::{Inode, InodeFlags, InodeNumber, InodeSize};
+use crate::fs::ext4::xattr::{find_inode_xattr, XattrNameIndex};
+
+/// Name of the extended attribute (with the [`XattrNameIndex::SYSTEM`] prefix) holding the inline data stored past
+/// `i_block`.
+const INLINE_DATA_XATTR_NAME: &[u8] = b"data";
+
+/// Size of the `.` and `..` directory entries, added in front of the entries of inline directories.
+const DOT_ENTRY_SIZE: u16 = 12;
+
+/// Loads the inline content of a file (or the target of a fast symbolic link).
+///
+/// Returns `None` if the content of the file is stored in data blocks.
+pub(crate) fn load_inline_data(inode: &Inode) -> Option<Vec<u8>> {
+    if !inode.has_inline_data() {
+        return None;
+    }
+
+    let size = usize::try_from(cast::<InodeSize, u64>(inode.size())).ok()?;
+    let mut data = raw_inline_data(inode);
+    data.resize(size, 0);
+
+    Some(data)
+}
+
+/// Loads the entries of an inline directory, in the format of a regular directory block.
+///
+/// The `.` and `..` entries, which are not stored in inline directories, are added in front of the other entries.
+///
+/// Returns `None` if the entries of the directory are stored in data blocks.
+pub(crate) fn load_inline_dir(inode: &Inode) -> Option<Vec<u8>> {
+    if !inode.has_flag(InodeFlags::EXT4_INLINE_DATA_FL) {
+        return None;
+    }
+
+    let raw_dir = raw_inline_data(inode);
+    let parent: InodeNumber = pod_read_unaligned(raw_dir.get(..size_of::<InodeNumber>())?);
+
+    let mut dir = Vec::with_capacity(raw_dir.len() + 2 * usize::from(DOT_ENTRY_SIZE));
+    push_dot_entry(&mut dir, inode.number, b".");
+    push_dot_entry(&mut dir, parent, b"..");
+    dir.extend_from_slice(&raw_dir[size_of::<InodeNumber>()..]);
+
+    Some(dir)
+}
+
+/// Returns the inline bytes of an inode: `i_block`, followed by the value of the `system.data` extended attribute.
+fn raw_inline_data(inode: &Inode) -> Vec<u8> {
+    let mut data = bytes_of(&inode.i_block).to_vec();
+
+    if inode.has_flag(InodeFlags::EXT4_INLINE_DATA_FL) {
+        if let Some(xattr) = find_inode_xattr(
+            &inode.inline_xattrs,
+            XattrNameIndex::SYSTEM,
+            INLINE_DATA_XATTR_NAME,
+        ) {
+            data.extend_from_slice(xattr);
+        }
+    }
+
+    data
+}
+
+/// Appends a `.` or `..` directory entry to a directory block.
+fn push_dot_entry(dir: &mut Vec<u8>, inode_id: InodeNumber, name: &[u8]) {
+    let start = dir.len();
+
+    dir.extend_from_slice(bytes_of(&inode_id));
+    dir.extend_from_slice(&DOT_ENTRY_SIZE.to_le_bytes());
+    dir.push(u8::try_from(name.len()).expect("invalid file name length"));
+    dir.push(cast(Ext4DirectoryFileType::DIRECTORY));
+    dir.extend_from_slice(name);
+    dir.resize(start + usize::from(DOT_ENTRY_SIZE), 0);
+}
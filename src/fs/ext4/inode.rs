@@ -7,7 +7,7 @@ use core::fmt::{Display, Formatter};
 
 use alloc::sync::{Arc, Weak};
 use alloc::{format, string::String, vec::Vec};
-use bytemuck::{bytes_of, cast, from_bytes, Pod, Zeroable};
+use bytemuck::{bytes_of, cast, pod_read_unaligned, Pod, Zeroable};
 use core::mem;
 use core::ops::{Deref, DerefMut};
 use core::sync::atomic::{AtomicBool, AtomicU32, Ordering};
@@ -15,7 +15,9 @@ use hashbrown::HashMap;
 use spin::RwLock;
 
 use crate::errors::IOError;
-use crate::fs::ext4::sb::{Ext4FsUuid, LockedSuperblock};
+use crate::fs::ext4::sb::{
+    Ext4FsUuid, LockedSuperblock, ReadOnlyCompatibleFeatureSet, ORIGINAL_INODE_SIZE,
+};
 use crate::fs::ext4::WeakLockedExt4Fs;
 use crate::fs::IOResult;
 use crate::{
@@ -102,6 +104,9 @@ impl InodeNumber {
 
     /// Inode 10 is used for ext4 metadata replication in some non-upstream patches
     pub const REPLICA: Self = Self(0xA);
+
+    /// Inode 11 is the first non-reserved inode on filesystems using the original revision
+    pub const ORIGINAL_FIRST_INODE: Self = Self(0xB);
 }
 
 ext4_uint_field_derive_display!(InodeNumber);
@@ -208,6 +213,12 @@ impl InodeFileMode {
 
     /// Socket
     pub(crate) const S_IFSOCK: Self = Self(0xC000);
+
+    /// Mode of the regular files created on the filesystem (`rw-r--r--`).
+    pub(crate) const DEFAULT_FILE_MODE: Self = Self(0x8000 | 0o644);
+
+    /// Mode of the directories created on the filesystem (`rwxr-xr-x`).
+    pub(crate) const DEFAULT_DIR_MODE: Self = Self(0x4000 | 0o755);
 }
 
 macro_rules! symb_perm {
@@ -268,7 +279,7 @@ impl Display for InodeType {
 
 impl From<InodeFileMode> for InodeType {
     fn from(value: InodeFileMode) -> Self {
-        let file_type = InodeFileMode(value.0 & ((1 << 12) - 1));
+        let file_type = InodeFileMode(value.0 & !((1 << 12) - 1));
 
         match file_type {
             InodeFileMode::S_IFSOCK => Self::Socket,
@@ -522,19 +533,19 @@ impl InodeFlags {
     pub(crate) const EXT4_EOFBLOCKS_FL: Self = Self(0x40_0000);
 
     /// Inode is a snapshot.
-    pub(crate) const EXT4_SNAPFILE_FL: Self = Self(0x80_0000);
+    pub(crate) const EXT4_SNAPFILE_FL: Self = Self(0x100_0000);
 
     /// Snapshot is being deleted.
-    pub(crate) const EXT4_SNAPFILE_DELETED_FL: Self = Self(0x100_0000);
+    pub(crate) const EXT4_SNAPFILE_DELETED_FL: Self = Self(0x400_0000);
 
     /// Snapshot shrink has completed.
-    pub(crate) const EXT4_SNAPFILE_SHRUNK_FL: Self = Self(0x200_0000);
+    pub(crate) const EXT4_SNAPFILE_SHRUNK_FL: Self = Self(0x800_0000);
 
     /// Inode has inline data.
-    pub(crate) const EXT4_INLINE_DATA_FL: Self = Self(0x400_0000);
+    pub(crate) const EXT4_INLINE_DATA_FL: Self = Self(0x1000_0000);
 
     /// Create children with the same project ID.
-    pub(crate) const EXT4_PROJINHERIT_FL: Self = Self(0x800_0000);
+    pub(crate) const EXT4_PROJINHERIT_FL: Self = Self(0x2000_0000);
 
     /// Reserved for `ext4` library.
     pub(crate) const EXT4_RESERVED_FL: Self = Self(0x8000_0000);
@@ -556,6 +567,14 @@ impl core::ops::BitAnd for InodeFlags {
     }
 }
 
+impl core::ops::Not for InodeFlags {
+    type Output = InodeFlags;
+
+    fn not(self) -> Self::Output {
+        InodeFlags(!self.0)
+    }
+}
+
 /// Inode version.
 #[derive(Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord, Hash, Pod, Zeroable)]
 #[repr(transparent)]
@@ -742,6 +761,26 @@ impl Display for InodeHardLinkCount {
     }
 }
 
+impl InodeHardLinkCount {
+    /// Usual maximum hard link count.
+    pub(crate) const MAX_LINKS: Self = Self(65000);
+
+    /// Link count of directories with too many subdirectories to be counted, with the `DIR_NLINK` feature.
+    pub(crate) const UNKNOWN_DIR_LINKS: Self = Self(1);
+}
+
+impl From<InodeHardLinkCount> for u16 {
+    fn from(value: InodeHardLinkCount) -> Self {
+        value.0
+    }
+}
+
+impl From<u16> for InodeHardLinkCount {
+    fn from(value: u16) -> Self {
+        Self(value)
+    }
+}
+
 /// Used for file block indexing information.
 ///
 /// May hold different structures depending on the situation.
@@ -775,6 +814,11 @@ impl InodeBlk {
     pub(crate) fn as_extent_block(&self) -> ExtentBlock {
         ExtentBlock(self.0.to_vec())
     }
+
+    /// Returns the 15 block pointers of the block map stored in this `InodeBlk`.
+    pub(crate) fn as_blk_pointers(&self) -> [u32; 15] {
+        cast(self.0)
+    }
 }
 
 unsafe impl Pod for InodeBlk {}
@@ -881,6 +925,9 @@ pub(crate) struct Inode {
 
     /// `ext4` inode structure
     pub(crate) ext4_struct: Ext4Inode,
+
+    /// Extended attributes stored in the inode body, after the `ext4` inode structure
+    pub(crate) inline_xattrs: Vec<u8>,
 }
 
 impl Inode {
@@ -892,14 +939,57 @@ impl Inode {
         sb: LockedSuperblock,
         ext4_inode: Ext4Inode,
         inode_id: InodeNumber,
+        inline_xattrs: Vec<u8>,
     ) -> Self {
         Self {
             sb,
             number: inode_id,
             cache: AtomicBool::default(),
             ext4_struct: ext4_inode,
+            inline_xattrs,
         }
     }
+
+    /// Builds a new `Inode`, to be stored in a free entry of the inode table.
+    ///
+    /// The `Inode` is empty, owned by the superuser, and has all its timestamps set to `time`. Its blocks are mapped
+    /// by `i_block`. All the extra fields of the [`Ext4Inode`] structure are used if the inode table entries are
+    /// large enough.
+    pub(super) fn create(
+        sb: LockedSuperblock,
+        inode_id: InodeNumber,
+        mode: InodeFileMode,
+        links: InodeHardLinkCount,
+        flags: InodeFlags,
+        i_block: InodeBlk,
+        time: UnixTimestamp,
+    ) -> Self {
+        let entry_size = sb.read().inode_entry_size();
+        let (seconds, extra_bits) = split_timestamp(time);
+
+        let mut ext4_inode = Ext4Inode::zeroed();
+        ext4_inode.i_mode = mode;
+        ext4_inode.i_links_count = links;
+        ext4_inode.i_flags = flags;
+        ext4_inode.i_block = i_block;
+        ext4_inode.i_atime = cast(seconds);
+        ext4_inode.i_crtime = cast(seconds);
+        ext4_inode.touch(time);
+
+        if entry_size > ORIGINAL_INODE_SIZE {
+            let extra_size = u16::try_from(mem::size_of::<Ext4Inode>())
+                .expect("invalid inode size")
+                .min(entry_size)
+                - ORIGINAL_INODE_SIZE;
+
+            ext4_inode.i_extra_isize = cast(extra_size);
+            ext4_inode.i_atime_extra = cast(extra_bits);
+            ext4_inode.i_crtime_extra = cast(extra_bits);
+        }
+
+        Self::from_ext4_inode(sb, ext4_inode, inode_id, Vec::new())
+    }
+
     /// Compares the checksum of the `Inode` to its on-disk value.
     ///
     /// The checksum of an `Inode` can be computed (after having set the checksum field to 0) using:
@@ -909,9 +999,8 @@ impl Inode {
     /// ```
     pub(crate) fn validate_chksum(&self) -> bool {
         let on_disk_chksum = self.i_checksum_lo + self.i_checksum_hi;
-        let sb = self.sb.read();
-        let comp_chksum = self.compute_chksum(sb.uuid, self.number);
-        drop(sb);
+        let fs_uuid = self.sb.read().uuid;
+        let comp_chksum = self.compute_chksum(fs_uuid);
 
         let matching_chksum = if self.i_extra_isize == InodeExtraSize::NO_EXTRA_SIZE {
             let comp_chksum_lo: InodeChksumLo = comp_chksum.into();
@@ -940,9 +1029,75 @@ impl Inode {
     /// Useful before writing back the `Inode` to disk after having updated several of its field.
     pub(crate) fn update_chksum(&mut self) {
         let fs_uuid = self.sb.read().uuid;
-        let new_chksum = self.compute_chksum(fs_uuid, self.number);
+        let new_chksum = self.compute_chksum(fs_uuid);
         self.set_chksum(new_chksum);
     }
+
+    /// Updates the block count of this `Inode`, after `allocated` blocks were allocated to it and `freed` blocks were
+    /// released.
+    ///
+    /// The block count is expressed in 512-byte sectors, unless the inode is flagged as a huge file, in which case it
+    /// is expressed in filesystem blocks.
+    pub(crate) fn update_blk_count(&mut self, allocated: u64, freed: u64) {
+        let sb = self.sb.read();
+        let unit = if sb
+            .feature_ro_compat
+            .includes(ReadOnlyCompatibleFeatureSet::EXT4_FEATURE_R0_COMPAT_HUGE_FILE)
+            && self.has_flag(InodeFlags::EXT4_HUGE_FILE_FL)
+        {
+            1
+        } else {
+            sb.blk_size() / 512
+        };
+        drop(sb);
+
+        let blk_count =
+            cast::<InodeBlkCount, u64>(self.blk_count()) + allocated * unit - freed * unit;
+        self.set_blk_count(cast(blk_count));
+    }
+
+    /// Converts this `Inode` back to its on-disk representation (an entry of the inode table).
+    pub(crate) fn to_raw_bytes(&self) -> Vec<u8> {
+        self.raw_entry(&self.ext4_struct)
+    }
+
+    /// Builds an entry of the inode table from an [`Ext4Inode`] structure, followed by the extended attributes
+    /// stored in the body of this `Inode`.
+    fn raw_entry(&self, ext4_inode: &Ext4Inode) -> Vec<u8> {
+        let entry_size = usize::from(self.sb.read().inode_entry_size());
+        let mut raw_inode = bytes_of(ext4_inode).to_vec();
+
+        if entry_size > usize::from(ORIGINAL_INODE_SIZE) {
+            raw_inode.resize(
+                usize::from(ext4_inode.i_extra_isize + ORIGINAL_INODE_SIZE),
+                0,
+            );
+            raw_inode.extend_from_slice(&self.inline_xattrs);
+        }
+
+        raw_inode.resize(entry_size, 0);
+        raw_inode
+    }
+
+    /// Computes the checksum of this `Inode`.
+    ///
+    /// The checksum covers the entire entry of the inode table, including the extended attributes stored in the
+    /// inode body.
+    fn compute_chksum(&self, fs_uuid: Ext4FsUuid) -> InodeChksum {
+        let mut chksum_bytes: Vec<u8> = alloc::vec![];
+        chksum_bytes.extend_from_slice(bytes_of(&fs_uuid));
+        chksum_bytes.extend_from_slice(bytes_of(&self.number));
+
+        let inode_gen = self.i_generation;
+        chksum_bytes.extend_from_slice(bytes_of(&inode_gen));
+
+        let mut inode_no_chksum = self.ext4_struct;
+        inode_no_chksum.set_chksum(InodeChksum::ERASE_CHKSUM);
+
+        chksum_bytes.extend_from_slice(&self.raw_entry(&inode_no_chksum));
+
+        cast(crc32c_calc(&chksum_bytes))
+    }
 }
 
 impl Deref for Inode {
@@ -1179,6 +1334,21 @@ impl Ext4Inode {
         self.has_flag(InodeFlags::EXT4_EXTENTS_FL)
     }
 
+    /// Checks if this `Inode` is a symbolic link short enough for its target to be stored in place of its block map
+    /// (_fast_ symbolic link).
+    pub(crate) fn is_fast_symlink(&self) -> bool {
+        matches!(self.inode_type(), InodeType::SymbolicLink)
+            && !self.has_flag(InodeFlags::EXT4_INLINE_DATA_FL)
+            && cast::<InodeSize, u64>(self.size())
+                < u64::try_from(mem::size_of::<InodeBlk>()).expect("invalid i_block size")
+    }
+
+    /// Checks if the content of this `Inode` is stored in the inode itself rather than in data blocks (inline data,
+    /// or fast symbolic link).
+    pub(crate) fn has_inline_data(&self) -> bool {
+        self.has_flag(InodeFlags::EXT4_INLINE_DATA_FL) || self.is_fast_symlink()
+    }
+
     /// Returns the hard link count.
     ///
     /// The maximum hard link count is usually 65 000, but may be increased if the `DIR_NLINK`
@@ -1187,25 +1357,40 @@ impl Ext4Inode {
         self.i_links_count
     }
 
-    fn compute_chksum(&self, fs_uuid: Ext4FsUuid, inode_id: InodeNumber) -> InodeChksum {
-        let mut chksum_bytes: Vec<u8> = alloc::vec![];
-        chksum_bytes.extend_from_slice(bytes_of(&fs_uuid));
-        chksum_bytes.extend_from_slice(bytes_of(&inode_id));
-
-        let inode_gen = self.i_generation;
-        chksum_bytes.extend_from_slice(bytes_of(&inode_gen));
+    /// Sets the hard link count.
+    pub(crate) fn set_links(&mut self, links: InodeHardLinkCount) {
+        self.i_links_count = links;
+    }
 
-        let mut inode_no_chksum = *self;
-        inode_no_chksum.set_chksum(InodeChksum::ERASE_CHKSUM);
+    /// Sets the last modification and change times of this `Inode`.
+    ///
+    /// The extra bits of the timestamps are only stored if the `Inode` structure is large enough.
+    pub(crate) fn touch(&mut self, time: UnixTimestamp) {
+        let (seconds, extra_bits) = split_timestamp(time);
 
-        chksum_bytes.extend_from_slice(
-            &bytes_of(&inode_no_chksum)[..usize::from(self.i_extra_isize + 0x80)],
-        );
+        self.i_mtime = cast(seconds);
+        self.i_mtime_extra = cast(extra_bits);
+        self.i_ctime = cast(seconds);
+        self.i_ctime_extra = cast(extra_bits);
+    }
 
-        cast(crc32c_calc(&chksum_bytes))
+    /// Sets the deletion time of this `Inode`.
+    pub(crate) fn set_deletion_time(&mut self, time: UnixTimestamp) {
+        self.i_dtime = cast(split_timestamp(time).0);
     }
 }
 
+/// Splits a [`UnixTimestamp`] into the seconds count stored in the base timestamp fields of an [`Ext4Inode`], and the
+/// bits stored in the matching extra field.
+fn split_timestamp(time: UnixTimestamp) -> (u32, u32) {
+    let raw_time = cast::<UnixTimestamp, u64>(time);
+
+    (
+        u32::try_from(raw_time & 0xFFFF_FFFF).expect("invalid conversion"),
+        u32::try_from(raw_time >> 32).expect("invalid conversion"),
+    )
+}
+
 #[allow(clippy::format_in_format_args)]
 impl Display for Ext4Inode {
     fn fmt(&self, f: &mut Formatter<'_>) -> core::fmt::Result {
@@ -1371,6 +1556,26 @@ impl InodeCache {
         Some(inode)
     }
 
+    /// Inserts an [`Inode`] into the `InodeCache`, replacing any entry with the same [`InodeNumber`].
+    ///
+    /// Used when an inode is created, as the cache may still hold an entry for an inode that was freed previously.
+    pub(super) fn insert_inode(&mut self, inode: Inode) -> LockedInodeStrongRef {
+        let inode_id = inode.number;
+        let locked_inode = Arc::new(RwLock::new(inode));
+
+        let inode_cache_entry = InodeCacheEntry {
+            inode: locked_inode.clone(),
+            usage_count: AtomicU32::default(),
+            entry_state: InodeCacheEntryState::Valid,
+        };
+
+        if let Some(old_entry) = self.hashtable.insert(inode_id, inode_cache_entry) {
+            old_entry.inode.write().cache.store(false, Ordering::SeqCst);
+        }
+
+        locked_inode
+    }
+
     fn load_inode_from_raw(&self, inode_id: InodeNumber) -> IOResult<LockedInodeStrongRef> {
         let locked_fs = self.fs.upgrade().ok_or(IOError::Unknown)?;
         let fs = locked_fs.read();
@@ -1391,16 +1596,38 @@ impl InodeCache {
 
         let raw_inode = &raw_inode_entry_blk[usize::try_from(inode_entry_bytes_offset_in_blk)
             .expect("invalid byte size")
-            ..usize::try_from(inode_entry_bytes_offset_in_blk + u64::from(sb.inode_size))
+            ..usize::try_from(inode_entry_bytes_offset_in_blk + u64::from(sb.inode_entry_size()))
                 .expect("invalid byte size")];
 
+        // on-disk inodes may be smaller (original `ext2` inodes) or larger (extra space for extended attributes) than
+        // the `Ext4Inode` structure
+        let raw_ext4_inode = &raw_inode[..usize::min(raw_inode.len(), mem::size_of::<Ext4Inode>())];
+
         let mut filled_inode = alloc::vec![0u8; mem::size_of::<Ext4Inode>()];
-        filled_inode[..raw_inode.len()].copy_from_slice(raw_inode);
+        filled_inode[..raw_ext4_inode.len()].copy_from_slice(raw_ext4_inode);
+
+        let ext4_inode: Ext4Inode = pod_read_unaligned(&filled_inode);
+
+        // in-inode extended attributes start right after the extra fields of the inode
+        let inline_xattrs = if sb.inode_entry_size() > ORIGINAL_INODE_SIZE {
+            raw_inode
+                .get(usize::from(ext4_inode.i_extra_isize + ORIGINAL_INODE_SIZE)..)
+                .unwrap_or_default()
+                .to_vec()
+        } else {
+            Vec::new()
+        };
 
-        let ext4_inode: Ext4Inode = *from_bytes(&filled_inode);
-        let inode = Inode::from_ext4_inode(fs.superblock.clone(), ext4_inode, inode_id);
+        let inode =
+            Inode::from_ext4_inode(fs.superblock.clone(), ext4_inode, inode_id, inline_xattrs);
 
-        inode.validate_chksum();
+        // inodes are only checksummed on filesystems using the `metadata_csum` feature (not on `ext2` / `ext3`)
+        if sb
+            .feature_ro_compat
+            .includes(ReadOnlyCompatibleFeatureSet::EXT4_FEATURE_R0_COMPAT_METADATA_CSUM)
+        {
+            inode.validate_chksum();
+        }
 
         Ok(Arc::new(RwLock::new(inode)))
     }
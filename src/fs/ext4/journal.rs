@@ -0,0 +1,811 @@
+//! ext4 `jbd2` journal replay
+//!
+//! `ext4` and `ext3` filesystems first log their metadata updates to a journal, stored in a dedicated inode, and only
+//! then write them to their final location. After a crash, transactions committed to the journal may not have reached
+//! their final location yet: the filesystem is then flagged as needing recovery (`EXT4_FEATURE_INCOMPAT_RECOVER`), and
+//! the kernel replays the journal the next time it mounts the filesystem.
+//!
+//! `FrozenBoot` never writes to such a filesystem (see [`Ext4Superblock::is_writable`]). Committed transactions are
+//! instead replayed into an in-memory [`JournalOverlay`], which takes precedence over the disk when reading blocks, so
+//! that reads see the filesystem as it will be once recovered.
+//!
+//! The journal is a circular log of blocks, whose structures are stored in big-endian byte order:
+//!
+//! - descriptor blocks list the final location of the blocks following them in the log
+//!
+//! - commit blocks mark the end of a transaction, which is only replayed if it was committed
+//!
+//! - revoke blocks list blocks which must not be replayed from earlier transactions (they were freed, and may have
+//!   been reused to store file data since, which is not journaled)
+//!
+//! External journal devices and fast commits are not supported.
+//!
+//! [`Ext4Superblock::is_writable`]: crate::fs::ext4::sb::Ext4Superblock::is_writable
+
+use alloc::vec::Vec;
+use core::mem::{self, size_of};
+
+use bytemuck::{cast, pod_read_unaligned, Pod, Zeroable};
+use hashbrown::HashMap;
+
+use crate::errors::{CanFail, IOError};
+use crate::fs::ext4::extent::Ext4RealBlkId;
+use crate::fs::ext4::file::Ext4File;
+use crate::fs::ext4::inode::InodeNumber;
+use crate::fs::ext4::{crc32c_calc, Ext4Fs, LockedExt4Fs};
+use crate::fs::IOResult;
+use crate::{error, ext4_flag_field, info};
+
+/// Magic number found at the start of every journal metadata block.
+const JBD2_MAGIC: u32 = 0xC03B_3998;
+
+/// Number of fast commit blocks reserved at the end of the journal, when the journal superblock does not specify it.
+const DEFAULT_FAST_COMMIT_BLKS: u32 = 256;
+
+/// Size of the UUID following a block tag which does not have the [`JournalTagFlags::SAME_UUID`] flag, in bytes.
+const TAG_UUID_SIZE: usize = 16;
+
+/// Size of the checksum tail of descriptor and revoke blocks, in bytes.
+const BLK_TAIL_SIZE: usize = 4;
+
+/// Offset of the checksum in a commit block.
+const COMMIT_CHKSUM_OFFSET: usize = 16;
+
+/// Offset of the number of bytes used in a revoke block (header included).
+const REVOKE_COUNT_OFFSET: usize = 12;
+
+/// Size of the header of a revoke block, in bytes.
+const REVOKE_HEADER_SIZE: usize = 16;
+
+/// Type of a journal block, stored in its [`JournalHeader`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+struct JournalBlkType(u32);
+
+impl JournalBlkType {
+    /// Descriptor block, listing the final location of the blocks following it in the log.
+    const DESCRIPTOR: Self = Self(1);
+
+    /// Commit block, marking the end of a transaction.
+    const COMMIT: Self = Self(2);
+
+    /// Journal superblock, version 1.
+    const SUPERBLOCK_V1: Self = Self(3);
+
+    /// Journal superblock, version 2.
+    const SUPERBLOCK_V2: Self = Self(4);
+
+    /// Revoke block, listing blocks which must not be replayed from earlier transactions.
+    const REVOKE: Self = Self(5);
+}
+
+ext4_flag_field!(
+    JournalIncompatibleFeatureSet,
+    u32,
+    "Incompatible journal feature set flags. The journal cannot be replayed if one of them is not supported."
+);
+
+impl JournalIncompatibleFeatureSet {
+    /// Empty feature set
+    const EMPTY_SET: Self = Self(0);
+
+    /// The journal contains revoke blocks.
+    const JBD2_FEATURE_INCOMPAT_REVOKE: Self = Self(0x1);
+
+    /// Block numbers are stored on 64 bits.
+    const JBD2_FEATURE_INCOMPAT_64BIT: Self = Self(0x2);
+
+    /// Commit blocks may be written before the blocks of their transaction.
+    const JBD2_FEATURE_INCOMPAT_ASYNC_COMMIT: Self = Self(0x4);
+
+    /// Journal blocks are checksummed with `crc32c`, and block tags store the low 16 bits of the checksum of the
+    /// logged blocks.
+    const JBD2_FEATURE_INCOMPAT_CSUM_V2: Self = Self(0x8);
+
+    /// Journal blocks are checksummed with `crc32c`, and block tags store the full checksum of the logged blocks.
+    const JBD2_FEATURE_INCOMPAT_CSUM_V3: Self = Self(0x10);
+
+    /// Fast commit blocks are reserved at the end of the journal.
+    const JBD2_FEATURE_INCOMPAT_FAST_COMMIT: Self = Self(0x20);
+
+    /// Incompatible features supported when replaying the journal.
+    ///
+    /// Fast commits are not replayed, but the regular transactions of journals using them are.
+    const SUPPORTED_SET: Self = Self(
+        Self::JBD2_FEATURE_INCOMPAT_REVOKE.0
+            | Self::JBD2_FEATURE_INCOMPAT_64BIT.0
+            | Self::JBD2_FEATURE_INCOMPAT_ASYNC_COMMIT.0
+            | Self::JBD2_FEATURE_INCOMPAT_CSUM_V2.0
+            | Self::JBD2_FEATURE_INCOMPAT_CSUM_V3.0
+            | Self::JBD2_FEATURE_INCOMPAT_FAST_COMMIT.0,
+    );
+
+    /// Checks if this `JournalIncompatibleFeatureSet` is a subset of (included in) the
+    /// `JournalIncompatibleFeatureSet` passed as argument.
+    fn is_subset_of(self, features: Self) -> bool {
+        (self | features) ^ features == Self::EMPTY_SET
+    }
+
+    /// Checks if this `JournalIncompatibleFeatureSet` includes the `JournalIncompatibleFeatureSet` passed as argument.
+    fn includes(self, features: Self) -> bool {
+        features.is_subset_of(self)
+    }
+}
+
+ext4_flag_field!(
+    JournalTagFlags,
+    u32,
+    "Flags of a block tag, in a descriptor block."
+);
+
+impl JournalTagFlags {
+    /// The first 4 bytes of the logged block were equal to [`JBD2_MAGIC`], and were zeroed in the log.
+    const ESCAPE: Self = Self(0x1);
+
+    /// The tag is not followed by a UUID (it is the same as the one of the previous tag).
+    const SAME_UUID: Self = Self(0x2);
+
+    /// Last tag of the descriptor block.
+    const LAST_TAG: Self = Self(0x8);
+
+    /// Checks if this `JournalTagFlags` includes the `JournalTagFlags` passed as argument.
+    fn includes(self, flags: Self) -> bool {
+        self & flags == flags
+    }
+}
+
+/// Header found at the start of every journal metadata block.
+#[derive(Clone, Copy, Debug, Pod, Zeroable)]
+#[repr(C)]
+struct JournalHeader {
+    /// Magic number ([`JBD2_MAGIC`])
+    magic: u32,
+
+    /// Type of the block
+    blk_type: u32,
+
+    /// Sequence number of the transaction this block belongs to
+    sequence: u32,
+}
+
+impl JournalHeader {
+    /// Reads the header found at the start of a journal block.
+    ///
+    /// Returns `None` if the block is not a journal metadata block.
+    fn from_blk(blk: &[u8]) -> Option<Self> {
+        let header: Self = pod_read_unaligned(&blk[..size_of::<Self>()]);
+
+        (u32::from_be(header.magic) == JBD2_MAGIC).then_some(header)
+    }
+
+    fn blk_type(&self) -> JournalBlkType {
+        JournalBlkType(u32::from_be(self.blk_type))
+    }
+
+    fn sequence(&self) -> u32 {
+        u32::from_be(self.sequence)
+    }
+}
+
+/// Journal superblock, stored in the first block of the journal.
+#[derive(Clone, Copy, Debug, Pod, Zeroable)]
+#[repr(C)]
+struct JournalSuperblock {
+    /// Common header, whose type is either [`JournalBlkType::SUPERBLOCK_V1`] or [`JournalBlkType::SUPERBLOCK_V2`]
+    header: JournalHeader,
+
+    /// Size of a journal block, in bytes
+    blk_size: u32,
+
+    /// Total number of blocks in the journal
+    max_len: u32,
+
+    /// First block of the log
+    first: u32,
+
+    /// Sequence number of the first transaction of the log
+    sequence: u32,
+
+    /// First block of the log which remains to be replayed, or 0 if the journal is clean
+    start: u32,
+
+    /// Error value, set by `jbd2_journal_abort`
+    errno: u32,
+
+    /// Compatible features (version 2 only)
+    feature_compat: u32,
+
+    /// Incompatible features (version 2 only)
+    feature_incompat: u32,
+
+    /// Read-only compatible features (version 2 only)
+    feature_ro_compat: u32,
+
+    /// UUID of the journal
+    uuid: [u8; 16],
+
+    /// Number of filesystems sharing the journal
+    nr_users: u32,
+
+    /// Location of a dynamic superblock copy (unused)
+    dyn_super: u32,
+
+    /// Maximum number of journal blocks per transaction (unused)
+    max_transaction: u32,
+
+    /// Maximum number of data blocks per transaction (unused)
+    max_trans_data: u32,
+
+    /// Checksum algorithm used for the journal
+    chksum_type: u8,
+
+    padding2: [u8; 3],
+
+    /// Number of fast commit blocks reserved at the end of the journal
+    num_fc_blks: u32,
+
+    /// Block of the head of the log, if the journal is clean
+    head: u32,
+
+    padding: [u32; 40],
+
+    /// `crc32c` checksum of the journal superblock, with this field set to 0
+    chksum: u32,
+
+    /// UUIDs of the filesystems sharing the journal
+    users: [u8; 768],
+}
+
+impl JournalSuperblock {
+    /// Returns the incompatible features of the journal.
+    fn features(&self) -> JournalIncompatibleFeatureSet {
+        // version 1 superblocks do not define any feature
+        if self.header.blk_type() == JournalBlkType::SUPERBLOCK_V1 {
+            JournalIncompatibleFeatureSet::EMPTY_SET
+        } else {
+            JournalIncompatibleFeatureSet(u32::from_be(self.feature_incompat))
+        }
+    }
+
+    /// Checks whether the checksum of the journal superblock is valid.
+    ///
+    /// ```
+    /// crc32c_calc(journal_superblock)
+    /// ```
+    fn validate_chksum(&self) -> bool {
+        let mut sb = *self;
+        sb.chksum = 0;
+
+        crc32c_calc(bytemuck::bytes_of(&sb)) == u32::from_be(self.chksum)
+    }
+}
+
+/// Block copied to the journal by a transaction.
+#[derive(Clone, Copy, Debug)]
+struct LoggedBlk {
+    /// Final location of the block.
+    target: Ext4RealBlkId,
+
+    /// Location of the copy of the block in the journal.
+    log_blk: u32,
+
+    /// Flags of the tag describing the block.
+    flags: JournalTagFlags,
+
+    /// Checksum of the copy of the block, if the journal is checksummed.
+    chksum: u32,
+}
+
+/// Transaction found in the journal.
+#[derive(Debug)]
+struct Transaction {
+    /// Sequence number of the transaction.
+    sequence: u32,
+
+    /// Blocks logged by the transaction.
+    blks: Vec<LoggedBlk>,
+
+    /// Blocks revoked by the transaction.
+    revoked: Vec<Ext4RealBlkId>,
+}
+
+impl Transaction {
+    fn new(sequence: u32) -> Self {
+        Self {
+            sequence,
+            blks: Vec::new(),
+            revoked: Vec::new(),
+        }
+    }
+}
+
+/// `jbd2` journal stored in an inode of the filesystem.
+struct Journal {
+    /// File storing the journal.
+    file: Ext4File,
+
+    /// Superblock of the journal.
+    sb: JournalSuperblock,
+
+    /// Incompatible features of the journal.
+    features: JournalIncompatibleFeatureSet,
+
+    /// First block of the log.
+    first: u32,
+
+    /// Block following the last block of the log (fast commit blocks are not part of the log).
+    last: u32,
+}
+
+impl Journal {
+    /// Loads the journal stored in the inode `inode_id`, and validates its superblock.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`IOError::InvalidCommand`] if the journal uses unsupported features, and [`IOError::Unknown`] if its
+    /// superblock is corrupted. May return any other variant of [`IOError`] in case of a failure while attempting to
+    /// read from disk.
+    fn load(locked_fs: &LockedExt4Fs, fs: &Ext4Fs, inode_id: InodeNumber) -> IOResult<Self> {
+        let file = Ext4File::from_inode_id(locked_fs.clone(), inode_id)?;
+        let mut blk = fs.allocate_blk();
+
+        fs.read_blk_from_device(
+            file.get_blk_mapping(cast(0u64)).ok_or(IOError::Unknown)?,
+            &mut blk,
+        )?;
+
+        let sb: JournalSuperblock = pod_read_unaligned(&blk[..size_of::<JournalSuperblock>()]);
+
+        if JournalHeader::from_blk(&blk).is_none()
+            || !matches!(
+                sb.header.blk_type(),
+                JournalBlkType::SUPERBLOCK_V1 | JournalBlkType::SUPERBLOCK_V2
+            )
+        {
+            error!("ext4", "invalid journal superblock");
+            return Err(IOError::Unknown);
+        }
+
+        let features = sb.features();
+
+        if !features.is_subset_of(JournalIncompatibleFeatureSet::SUPPORTED_SET) {
+            error!(
+                "ext4",
+                "unsupported journal features ({:#x})",
+                cast::<JournalIncompatibleFeatureSet, u32>(features)
+            );
+            return Err(IOError::InvalidCommand);
+        }
+
+        let mut journal = Self {
+            file,
+            sb,
+            features,
+            first: u32::from_be(sb.first),
+            last: u32::from_be(sb.max_len),
+        };
+
+        if journal.has_chksum() && !sb.validate_chksum() {
+            error!("ext4", "invalid journal superblock checksum");
+            return Err(IOError::Unknown);
+        }
+
+        if features.includes(JournalIncompatibleFeatureSet::JBD2_FEATURE_INCOMPAT_FAST_COMMIT) {
+            let fast_commit_blks = match u32::from_be(sb.num_fc_blks) {
+                0 => DEFAULT_FAST_COMMIT_BLKS,
+                count => count,
+            };
+
+            journal.last = journal.last.saturating_sub(fast_commit_blks);
+        }
+
+        let start = u32::from_be(sb.start);
+
+        if u64::from(u32::from_be(sb.blk_size)) != fs.superblock.read().blk_size()
+            || journal.first == 0
+            || journal.first >= journal.last
+            || (start != 0 && (start < journal.first || start >= journal.last))
+        {
+            error!("ext4", "invalid journal geometry");
+            return Err(IOError::Unknown);
+        }
+
+        Ok(journal)
+    }
+
+    /// Scans the log, starting from its first block which remains to be replayed.
+    ///
+    /// Returns the transactions found in the log, in order. The scan stops at the first block which does not belong to
+    /// the next expected transaction, or whose checksum is invalid: only committed transactions are returned.
+    fn scan(&self, fs: &Ext4Fs) -> IOResult<Vec<Transaction>> {
+        let mut transactions = Vec::new();
+        let mut log_blk = u32::from_be(self.sb.start);
+
+        // the journal is clean
+        if log_blk == 0 {
+            return Ok(transactions);
+        }
+
+        let mut pending = Transaction::new(u32::from_be(self.sb.sequence));
+        let mut blk = fs.allocate_blk();
+
+        // every block of the log is visited at most once, even if the log is corrupted
+        let mut remaining_blks = self.last - self.first;
+
+        'scan: while remaining_blks > 0 {
+            self.read_log_blk(fs, log_blk, &mut blk)?;
+            log_blk = self.next_log_blk(log_blk);
+            remaining_blks -= 1;
+
+            let Some(header) = JournalHeader::from_blk(&blk) else {
+                break;
+            };
+
+            if header.sequence() != pending.sequence {
+                break;
+            }
+
+            match header.blk_type() {
+                JournalBlkType::DESCRIPTOR => {
+                    if !self.validate_tail_chksum(&blk) {
+                        error!("ext4", "invalid journal descriptor block checksum");
+                        break;
+                    }
+
+                    for (target, flags, chksum) in self.blk_tags(&blk) {
+                        if remaining_blks == 0 {
+                            break 'scan;
+                        }
+
+                        pending.blks.push(LoggedBlk {
+                            target,
+                            log_blk,
+                            flags,
+                            chksum,
+                        });
+
+                        log_blk = self.next_log_blk(log_blk);
+                        remaining_blks -= 1;
+                    }
+                }
+                JournalBlkType::COMMIT => {
+                    if !self.validate_commit_chksum(&blk) {
+                        error!("ext4", "invalid journal commit block checksum");
+                        break;
+                    }
+
+                    let next_sequence = pending.sequence.wrapping_add(1);
+                    transactions.push(mem::replace(&mut pending, Transaction::new(next_sequence)));
+                }
+                JournalBlkType::REVOKE => {
+                    if !self.validate_tail_chksum(&blk) {
+                        error!("ext4", "invalid journal revoke block checksum");
+                        break;
+                    }
+
+                    let Some(revoked) = self.revoked_blks(&blk) else {
+                        break;
+                    };
+
+                    pending.revoked.extend(revoked);
+                }
+                _ => break,
+            }
+        }
+
+        Ok(transactions)
+    }
+
+    /// Parses the block tags of a descriptor block.
+    ///
+    /// Returns the final location, the flags and the checksum of each block described by the tags.
+    fn blk_tags(&self, blk: &[u8]) -> Vec<(Ext4RealBlkId, JournalTagFlags, u32)> {
+        let has_chksum_v3 = self
+            .features
+            .includes(JournalIncompatibleFeatureSet::JBD2_FEATURE_INCOMPAT_CSUM_V3);
+        let is_64bit = self
+            .features
+            .includes(JournalIncompatibleFeatureSet::JBD2_FEATURE_INCOMPAT_64BIT);
+
+        let tag_size = if has_chksum_v3 {
+            16
+        } else {
+            let chksum_size = if self
+                .features
+                .includes(JournalIncompatibleFeatureSet::JBD2_FEATURE_INCOMPAT_CSUM_V2)
+            {
+                2
+            } else {
+                0
+            };
+
+            8 + chksum_size + if is_64bit { 4 } else { 0 }
+        };
+
+        let end = blk.len() - if self.has_chksum() { BLK_TAIL_SIZE } else { 0 };
+        let mut offset = size_of::<JournalHeader>();
+        let mut tags = Vec::new();
+
+        while offset + tag_size <= end {
+            let blk_lo = read_be32(blk, offset);
+            let blk_hi = if is_64bit {
+                read_be32(blk, offset + 8)
+            } else {
+                0
+            };
+
+            let (flags, chksum) = if has_chksum_v3 {
+                (read_be32(blk, offset + 4), read_be32(blk, offset + 12))
+            } else {
+                (
+                    u32::from(read_be16(blk, offset + 6)),
+                    u32::from(read_be16(blk, offset + 4)),
+                )
+            };
+
+            let flags = JournalTagFlags(flags);
+            tags.push((
+                Ext4RealBlkId::from((u64::from(blk_hi) << 32) | u64::from(blk_lo)),
+                flags,
+                chksum,
+            ));
+
+            offset += tag_size;
+
+            if !flags.includes(JournalTagFlags::SAME_UUID) {
+                offset += TAG_UUID_SIZE;
+            }
+
+            if flags.includes(JournalTagFlags::LAST_TAG) {
+                break;
+            }
+        }
+
+        tags
+    }
+
+    /// Parses the records of a revoke block.
+    ///
+    /// Returns `None` if the block is corrupted.
+    fn revoked_blks(&self, blk: &[u8]) -> Option<Vec<Ext4RealBlkId>> {
+        let record_size = if self
+            .features
+            .includes(JournalIncompatibleFeatureSet::JBD2_FEATURE_INCOMPAT_64BIT)
+        {
+            8
+        } else {
+            4
+        };
+
+        let end = usize::try_from(read_be32(blk, REVOKE_COUNT_OFFSET)).ok()?;
+
+        if end > blk.len() - if self.has_chksum() { BLK_TAIL_SIZE } else { 0 } {
+            error!("ext4", "invalid journal revoke block");
+            return None;
+        }
+
+        Some(
+            blk.get(REVOKE_HEADER_SIZE..end)?
+                .chunks_exact(record_size)
+                .map(|record| {
+                    Ext4RealBlkId::from(if record_size == 8 {
+                        (u64::from(read_be32(record, 0)) << 32) | u64::from(read_be32(record, 4))
+                    } else {
+                        u64::from(read_be32(record, 0))
+                    })
+                })
+                .collect(),
+        )
+    }
+
+    /// Checks whether the metadata blocks of this journal are checksummed (`CSUM_V2` or `CSUM_V3` features).
+    fn has_chksum(&self) -> bool {
+        self.features
+            .includes(JournalIncompatibleFeatureSet::JBD2_FEATURE_INCOMPAT_CSUM_V2)
+            || self
+                .features
+                .includes(JournalIncompatibleFeatureSet::JBD2_FEATURE_INCOMPAT_CSUM_V3)
+    }
+
+    /// Checks whether the checksum stored at `chksum_offset` in a journal block is valid.
+    ///
+    /// ```
+    /// crc32c_calc(journal_uuid + blk)
+    /// ```
+    fn validate_blk_chksum(&self, blk: &[u8], chksum_offset: usize) -> bool {
+        if !self.has_chksum() {
+            return true;
+        }
+
+        let mut chksum_bytes = self.sb.uuid.to_vec();
+        chksum_bytes.extend_from_slice(blk);
+
+        let chksum_field = self.sb.uuid.len() + chksum_offset;
+        chksum_bytes[chksum_field..chksum_field + 4].fill(0);
+
+        crc32c_calc(&chksum_bytes) == read_be32(blk, chksum_offset)
+    }
+
+    /// Checks whether the checksum stored in the tail of a descriptor or revoke block is valid.
+    fn validate_tail_chksum(&self, blk: &[u8]) -> bool {
+        self.validate_blk_chksum(blk, blk.len() - BLK_TAIL_SIZE)
+    }
+
+    /// Checks whether the checksum of a commit block is valid.
+    fn validate_commit_chksum(&self, blk: &[u8]) -> bool {
+        self.validate_blk_chksum(blk, COMMIT_CHKSUM_OFFSET)
+    }
+
+    /// Checks whether the checksum of a block copied to the journal matches the one stored in its tag.
+    ///
+    /// ```
+    /// crc32c_calc(journal_uuid + sequence + blk)
+    /// ```
+    ///
+    /// With the `CSUM_V2` feature, tags only store the low 16 bits of the checksum.
+    fn validate_logged_blk_chksum(
+        &self,
+        logged_blk: &LoggedBlk,
+        sequence: u32,
+        blk: &[u8],
+    ) -> bool {
+        if !self.has_chksum() {
+            return true;
+        }
+
+        let mut chksum_bytes = self.sb.uuid.to_vec();
+        chksum_bytes.extend_from_slice(&sequence.to_be_bytes());
+        chksum_bytes.extend_from_slice(blk);
+
+        let comp_chksum = crc32c_calc(&chksum_bytes);
+
+        if self
+            .features
+            .includes(JournalIncompatibleFeatureSet::JBD2_FEATURE_INCOMPAT_CSUM_V3)
+        {
+            comp_chksum == logged_blk.chksum
+        } else {
+            comp_chksum & 0xFFFF == logged_blk.chksum
+        }
+    }
+
+    /// Reads a block of the journal.
+    fn read_log_blk(&self, fs: &Ext4Fs, log_blk: u32, buf: &mut [u8]) -> CanFail<IOError> {
+        let real_blk = self
+            .file
+            .get_blk_mapping(cast(u64::from(log_blk)))
+            .ok_or(IOError::Unknown)?;
+
+        fs.read_blk_from_device(real_blk, buf)
+    }
+
+    /// Returns the block following `log_blk` in the log, which wraps around at its end.
+    fn next_log_blk(&self, log_blk: u32) -> u32 {
+        if log_blk + 1 >= self.last {
+            self.first
+        } else {
+            log_blk + 1
+        }
+    }
+}
+
+/// Blocks replayed from the journal, indexed by their final location on disk.
+///
+/// Those blocks are more recent than their copy on disk, and take precedence over it when reading blocks.
+#[derive(Default)]
+pub(crate) struct JournalOverlay {
+    blks: HashMap<Ext4RealBlkId, Vec<u8>>,
+}
+
+impl core::fmt::Debug for JournalOverlay {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.write_fmt(format_args!("{} replayed blocks", self.blks.len()))
+    }
+}
+
+impl JournalOverlay {
+    /// Replays the transactions committed to the journal of a filesystem into a new `JournalOverlay`.
+    ///
+    /// Nothing is ever written to disk. Blocks revoked by a later transaction are not replayed, and blocks whose copy
+    /// in the journal is corrupted are skipped.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`IOError::InvalidCommand`] if the journal uses unsupported features, and [`IOError::Unknown`] if it is
+    /// corrupted. May return any other variant of [`IOError`] in case of a failure while attempting to read from disk.
+    pub(crate) fn replay(locked_fs: &LockedExt4Fs) -> IOResult<Self> {
+        let fs = locked_fs.read();
+        let sb = fs.superblock.read();
+        let journal_inode = sb.journal_inum;
+        drop(sb);
+
+        let mut overlay = Self::default();
+
+        if cast::<InodeNumber, u32>(journal_inode) == 0 {
+            error!("ext4", "external journals cannot be replayed");
+            return Ok(overlay);
+        }
+
+        let journal = Journal::load(locked_fs, &fs, journal_inode)?;
+        let transactions = journal.scan(&fs)?;
+
+        // most recent transaction revoking each block
+        let mut revoked: HashMap<Ext4RealBlkId, u32> = HashMap::default();
+
+        for transaction in &transactions {
+            for &blk in &transaction.revoked {
+                revoked.insert(blk, transaction.sequence);
+            }
+        }
+
+        for transaction in &transactions {
+            for logged_blk in &transaction.blks {
+                // revoke records only apply to the transaction revoking the block and to the ones before it
+                if revoked
+                    .get(&logged_blk.target)
+                    .is_some_and(|&sequence| !tid_gt(transaction.sequence, sequence))
+                {
+                    continue;
+                }
+
+                let mut blk = fs.allocate_blk();
+                journal.read_log_blk(&fs, logged_blk.log_blk, &mut blk)?;
+
+                if !journal.validate_logged_blk_chksum(logged_blk, transaction.sequence, &blk) {
+                    error!(
+                        "ext4",
+                        "invalid journal block checksum (block {:#x})",
+                        cast::<Ext4RealBlkId, u64>(logged_blk.target)
+                    );
+                    continue;
+                }
+
+                if logged_blk.flags.includes(JournalTagFlags::ESCAPE) {
+                    blk[..4].copy_from_slice(&JBD2_MAGIC.to_be_bytes());
+                }
+
+                overlay.blks.insert(logged_blk.target, blk);
+            }
+        }
+
+        info!(
+            "ext4-fs",
+            "replayed {} journal transactions ({} blocks)",
+            transactions.len(),
+            overlay.blks.len()
+        );
+
+        Ok(overlay)
+    }
+
+    /// Returns the replayed content of a block, if it was part of a committed transaction.
+    pub(crate) fn get(&self, blk_id: Ext4RealBlkId) -> Option<&[u8]> {
+        self.blks.get(&blk_id).map(Vec::as_slice)
+    }
+}
+
+/// Checks whether the transaction `sequence` is more recent than the transaction `other`.
+///
+/// Transaction sequence numbers wrap around.
+fn tid_gt(sequence: u32, other: u32) -> bool {
+    let diff = sequence.wrapping_sub(other);
+
+    diff != 0 && diff < 0x8000_0000
+}
+
+/// Reads a big-endian 32-bit integer at `offset` in `buf`.
+fn read_be32(buf: &[u8], offset: usize) -> u32 {
+    u32::from_be_bytes(
+        buf[offset..offset + 4]
+            .try_into()
+            .expect("invalid slice length"),
+    )
+}
+
+/// Reads a big-endian 16-bit integer at `offset` in `buf`.
+fn read_be16(buf: &[u8], offset: usize) -> u16 {
+    u16::from_be_bytes(
+        buf[offset..offset + 2]
+            .try_into()
+            .expect("invalid slice length"),
+    )
+}
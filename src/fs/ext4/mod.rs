@@ -8,14 +8,23 @@
 //! - **Extents**
 //! - **Large filesystem support**
 //!
-//! This implementation only covers the basic features of the `ext4` filesystem for now, and can only mount such
-//! filesystems read-only.
+//! This implementation only covers the basic features of the `ext4` filesystem for now. Files and directories can be
+//! created, written to, renamed and removed, as long as the filesystem does not use any feature unsupported when
+//! writing (see [`Ext4Superblock::is_writable`]). Only inodes mapped with an extent tree can be modified: new inodes
+//! always use one.
+//!
+//! Transactions committed to the journal which were not written to their final location yet (after a crash) are
+//! replayed in memory when mounting the filesystem, without writing to the disk (see [`journal`]).
+//!
+//! `ext2` and `ext3` filesystems are mounted through the same implementation, as their on-disk structures are a subset
+//! of `ext4`'s: their inodes map their blocks with indirect block maps instead of extent trees.
 
 #![allow(clippy::copy_iterator)]
 
 use alloc::boxed::Box;
 use alloc::sync::{Arc, Weak};
 use alloc::{string::String, vec::Vec};
+use bytemuck::cast;
 use core::cell::RefCell;
 use core::mem::{self, transmute};
 use dir::GenericExt4Directory;
@@ -30,14 +39,26 @@ use crate::errors::MountError;
 use crate::fs::ext4::block_grp::{BlockGroupNumber, GroupDescriptorCache, LockedGroupDescriptor};
 use crate::fs::ext4::extent::Ext4RealBlkId;
 use crate::fs::ext4::inode::{
-    InodeCache, InodeCacheRemovalPolicy, InodeNumber, LockedInode, LockedInodeStrongRef,
+    Inode, InodeCache, InodeCacheRemovalPolicy, InodeCount, InodeFileMode, InodeFlags,
+    InodeHardLinkCount, InodeNumber, InodeType, InodeXattr, LockedInode, LockedInodeStrongRef,
+};
+use crate::fs::ext4::journal::JournalOverlay;
+use crate::fs::ext4::sb::{
+    Ext4BlkCount, Ext4ChksumAlgorithm, Ext4Superblock, IncompatibleFeatureSet, LockedSuperblock,
+    Superblock,
 };
-use crate::fs::ext4::sb::{Ext4ChksumAlgorithm, Ext4Superblock, LockedSuperblock, Superblock};
-use crate::fs::{Directory, Fs};
+use crate::fs::ext4::xattr::release_xattr_blk;
+use crate::fs::{Directory, Fs, FsFile};
+use crate::time::current_timestamp;
 use crate::{
     errors::{CanFail, IOError},
     fs::{
-        ext4::{dir::Ext4Directory, extent::ExtentTree, inode::Ext4Inode},
+        ext4::{
+            dir::{Ext4Directory, Ext4DirectoryFileType, Ext4Filename},
+            extent::ExtentTree,
+            file::Ext4File,
+            inode::Ext4Inode,
+        },
         IOResult,
     },
     info,
@@ -45,11 +66,18 @@ use crate::{
 
 pub(super) mod bitmap;
 pub(crate) mod block_grp;
+pub(crate) mod blockmap;
 pub(crate) mod dir;
 pub(crate) mod extent;
 pub(crate) mod file;
+pub(crate) mod htree;
+pub(crate) mod inline;
 pub(crate) mod inode;
+pub(crate) mod journal;
 pub(crate) mod sb;
+#[cfg(test)]
+pub(crate) mod tests;
+pub(crate) mod xattr;
 
 /// Strong pointer to a locked [`Ext4Fs`] structure.
 ///
@@ -67,6 +95,14 @@ pub(super) type LockedExt4Fs = Arc<RwLock<Ext4Fs>>;
 /// filesystem cannot be unmounted, contrary to a [`LockedExt4Fs`] reference.
 pub(super) type WeakLockedExt4Fs = Weak<RwLock<Ext4Fs>>;
 
+/// Maximum number of symbolic links followed while resolving a single path, as in Linux.
+///
+/// Paths requiring more links to be followed most likely contain a loop.
+const MAX_SYMLINKS: usize = 40;
+
+/// Offset of the superblock from the beginning of the partition, in bytes.
+const SUPERBLOCK_OFFSET: u64 = 1024;
+
 /// Internal representation of a `ext4` filesystem.
 ///
 /// Holds the main data structures required for the operation of the filesystem:
@@ -90,6 +126,9 @@ pub(crate) struct Ext4Fs {
 
     inode_cache: RefCell<InodeCache>,
 
+    /// Blocks replayed from the journal, if the filesystem needs recovery.
+    journal_overlay: JournalOverlay,
+
     fs_ptr: Weak<RwLock<Self>>,
 }
 
@@ -127,7 +166,7 @@ impl Ext4Fs {
         let sb = self.superblock.read();
         let inode_bg = sb.get_inode_blk_group(inode_id);
 
-        if inode_id > sb.inodes_count || inode_bg > sb.bg_count() {
+        if inode_id > { sb.inodes_count } || inode_bg > sb.bg_count() {
             return None;
         }
 
@@ -135,7 +174,16 @@ impl Ext4Fs {
 
         let mut descriptor = locked_descriptor.write();
 
-        if !descriptor.get_or_load_inode_bitmap().inode_in_use(inode_id) {
+        // inode numbers start at 1
+        let inode_idx = (inode_id - 1) % sb.inodes_per_group;
+
+        if !descriptor
+            .get_or_load_inode_bitmap()
+            .ok()?
+            .inode_in_use(InodeNumber::from(
+                usize::try_from(inode_idx).expect("invalid inode number"),
+            ))
+        {
             return None;
         }
 
@@ -168,6 +216,98 @@ impl Ext4Fs {
         }))
     }
 
+    /// Opens the regular file located at `path`, relative to the root directory of this filesystem.
+    ///
+    /// Symbolic links are followed, including when they are the last component of `path`.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`IOError::NotFound`] if one of the path components does not exist, or is not of the expected type,
+    /// and [`IOError::TooManySymlinks`] if too many symbolic links had to be followed. May return any other variant
+    /// of [`IOError`] in case of a failure while attempting to read from disk.
+    pub(crate) fn open_file(&self, path: &str) -> IOResult<Ext4File> {
+        let (inode_id, file_type) = self.resolve_path(path)?;
+
+        if file_type != Ext4DirectoryFileType::REGULAR {
+            return Err(IOError::NotFound);
+        }
+
+        Ext4File::from_inode_id(self.fs_ptr.upgrade().ok_or(IOError::Unknown)?, inode_id)
+    }
+
+    /// Lists the directory located at `path`, relative to the root directory of this filesystem.
+    ///
+    /// Returns the names of its entries, except `.` and `..`, in the order in which they are stored.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`IOError::NotFound`] if one of the path components does not exist, or is not a directory, and
+    /// [`IOError::TooManySymlinks`] if too many symbolic links had to be followed. May return any other variant of
+    /// [`IOError`] in case of a failure while attempting to read from disk.
+    pub(crate) fn read_dir(&self, path: &str) -> IOResult<Vec<String>> {
+        let (inode_id, file_type) = self.resolve_path(path)?;
+
+        if file_type != Ext4DirectoryFileType::DIRECTORY {
+            return Err(IOError::NotFound);
+        }
+
+        let dir =
+            Ext4Directory::from_inode_id(self.fs_ptr.upgrade().ok_or(IOError::Unknown)?, inode_id)?;
+
+        Ok(dir
+            .map(|entry| String::from(entry.name))
+            .filter(|name| name != "." && name != "..")
+            .collect())
+    }
+
+    /// Resolves `path`, relative to the root directory of this filesystem, to an inode.
+    ///
+    /// Symbolic links met during the walk are followed: absolute targets restart from the root directory, and
+    /// relative targets from the directory holding the link.
+    ///
+    /// Returns the number of the inode, and its type.
+    fn resolve_path(&self, path: &str) -> IOResult<(InodeNumber, Ext4DirectoryFileType)> {
+        let locked_fs = self.fs_ptr.upgrade().ok_or(IOError::Unknown)?;
+
+        // components left to resolve, in reverse order
+        let mut components: Vec<Vec<u8>> = path_components(path.as_bytes()).rev().collect();
+        let mut dir_id = InodeNumber::ROOT_DIR;
+        let mut symlinks_count = 0;
+
+        while let Some(component) = components.pop() {
+            let entry = Ext4Directory::from_inode_id(locked_fs.clone(), dir_id)?
+                .search(Ext4Filename(component))
+                .ok_or(IOError::NotFound)?;
+            let file_type = entry.entry_type().ok_or(IOError::Unknown)?;
+
+            if file_type == Ext4DirectoryFileType::SYMLINK {
+                symlinks_count += 1;
+
+                if symlinks_count > MAX_SYMLINKS {
+                    return Err(IOError::TooManySymlinks);
+                }
+
+                let mut target = Vec::new();
+                Ext4File::from_inode_id(locked_fs.clone(), entry.inode_number)?
+                    .read_file(&mut target)?;
+
+                if target.first() == Some(&b'/') {
+                    dir_id = InodeNumber::ROOT_DIR;
+                }
+
+                components.extend(path_components(&target).rev());
+            } else if file_type == Ext4DirectoryFileType::DIRECTORY {
+                dir_id = entry.inode_number;
+            } else if components.is_empty() {
+                return Ok((entry.inode_number, file_type));
+            } else {
+                return Err(IOError::NotFound);
+            }
+        }
+
+        Ok((dir_id, Ext4DirectoryFileType::DIRECTORY))
+    }
+
     /// Allocates a growable buffer (a [`Vec`]), initialized with a capacity corresponding to the block size
     /// of the filesystem.
     pub(crate) fn allocate_blk(&self) -> Vec<u8> {
@@ -184,6 +324,12 @@ impl Ext4Fs {
             return Err(IOError::InvalidCommand);
         }
 
+        // blocks replayed from the journal are more recent than their copy on disk
+        if let Some(replayed_blk) = self.journal_overlay.get(blk_id) {
+            buffer.copy_from_slice(replayed_blk);
+            return Ok(());
+        }
+
         let mut drive = get_sata_drive(self.drive_id).ok_or(IOError::InvalidDevice)?;
         let partition_data = drive
             .partitions()
@@ -207,6 +353,518 @@ impl Ext4Fs {
     }
 }
 
+impl Ext4Fs {
+    /// Checks whether this filesystem can be written to (see [`Ext4Superblock::is_writable`]).
+    ///
+    /// # Errors
+    ///
+    /// Returns [`IOError::InvalidCommand`] if some of the features enabled on the filesystem are not supported when
+    /// writing.
+    pub(super) fn check_writable(&self) -> CanFail<IOError> {
+        if !self.superblock.read().is_writable() {
+            return Err(IOError::InvalidCommand);
+        }
+
+        Ok(())
+    }
+
+    /// Opens the regular file located at `path`, relative to the root directory of this filesystem, and creates it
+    /// (empty) if it does not exist. Its parent directory must already exist.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`IOError::NotFound`] if the parent directory does not exist, and [`IOError::InvalidCommand`] if
+    /// `path` is not a regular file, if its name is invalid, or if the filesystem cannot be written to. May return
+    /// any other variant of [`IOError`] in case of a failure while attempting to read from or write to disk.
+    pub(crate) fn create_file(&self, path: &str) -> IOResult<Ext4File> {
+        let (parent, name) = split_path(path);
+
+        self.open_dir(parent)?.create_file(name)
+    }
+
+    /// Creates an empty directory at `path`, relative to the root directory of this filesystem. Its parent directory
+    /// must already exist.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`IOError::NotFound`] if the parent directory does not exist, and [`IOError::InvalidCommand`] if
+    /// `path` already exists, if its name is invalid, or if the filesystem cannot be written to. May return any other
+    /// variant of [`IOError`] in case of a failure while attempting to read from or write to disk.
+    pub(crate) fn create_dir(&self, path: &str) -> IOResult<Ext4Directory> {
+        let (parent, name) = split_path(path);
+
+        self.open_dir(parent)?.create_dir(name)
+    }
+
+    /// Removes the entry located at `path`, relative to the root directory of this filesystem.
+    ///
+    /// The inode of the entry is freed along with its blocks once its last link is removed. Directories must be
+    /// empty.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`IOError::NotFound`] if `path` does not exist, and [`IOError::InvalidCommand`] if it is a non-empty
+    /// directory, or if the filesystem cannot be written to. May return any other variant of [`IOError`] in case of a
+    /// failure while attempting to read from or write to disk.
+    pub(crate) fn unlink(&self, path: &str) -> CanFail<IOError> {
+        let (parent, name) = split_path(path);
+
+        self.open_dir(parent)?.unlink(name)
+    }
+
+    /// Moves the entry located at `old_path` to `new_path`, both relative to the root directory of this filesystem.
+    ///
+    /// The entry located at `new_path` is replaced if it exists, and is of the same type (directories must be empty).
+    ///
+    /// # Errors
+    ///
+    /// Returns [`IOError::NotFound`] if `old_path` or the parent directory of `new_path` does not exist, and
+    /// [`IOError::InvalidCommand`] if the entry cannot be moved (a directory moved to one of its subdirectories, an
+    /// entry replaced by one of a different type, ...). May return any other variant of [`IOError`] in case of a
+    /// failure while attempting to read from or write to disk.
+    pub(crate) fn rename(&self, old_path: &str, new_path: &str) -> CanFail<IOError> {
+        let (old_parent, old_name) = split_path(old_path);
+        let (new_parent, new_name) = split_path(new_path);
+
+        let mut old_dir = self.open_dir(old_parent)?;
+        let mut new_dir = self.open_dir(new_parent)?;
+
+        old_dir.move_entry(old_name, &mut new_dir, new_name)
+    }
+
+    /// Opens the directory located at `path`, relative to the root directory of this filesystem.
+    fn open_dir(&self, path: &str) -> IOResult<Ext4Directory> {
+        let (inode_id, file_type) = self.resolve_path(path)?;
+
+        if file_type != Ext4DirectoryFileType::DIRECTORY {
+            return Err(IOError::NotFound);
+        }
+
+        Ext4Directory::from_inode_id(self.fs_ptr.upgrade().ok_or(IOError::Unknown)?, inode_id)
+    }
+
+    /// Allocates a new inode, and writes it to the inode table.
+    ///
+    /// The inode is empty, and its blocks are mapped by an extent tree: the filesystem must support extents. New
+    /// directories start with 2 links (their entry in `parent`, and their own `.` entry).
+    ///
+    /// # Errors
+    ///
+    /// Returns [`IOError::NoSpace`] if there is no free inode left, and [`IOError::InvalidCommand`] if the filesystem
+    /// does not support extents. May return any other variant of [`IOError`] in case of a failure while attempting to
+    /// read from or write to disk.
+    pub(super) fn create_inode(
+        &self,
+        parent: InodeNumber,
+        mode: InodeFileMode,
+    ) -> IOResult<LockedInodeStrongRef> {
+        if !self
+            .superblock
+            .read()
+            .feature_incompat
+            .includes(IncompatibleFeatureSet::EXT4_FEATURE_INCOMPAT_EXTENTS)
+        {
+            return Err(IOError::InvalidCommand);
+        }
+
+        let is_dir = matches!(InodeType::from(mode), InodeType::Directory);
+        let inode_id = self.allocate_inode(parent, is_dir)?;
+        let links = if is_dir { 2 } else { 1 };
+
+        let mut inode = Inode::create(
+            self.superblock.clone(),
+            inode_id,
+            mode,
+            InodeHardLinkCount::from(links),
+            InodeFlags::EXT4_EXTENTS_FL,
+            ExtentTree::empty_root()?,
+            current_timestamp(),
+        );
+
+        if let Err(err) = self.write_inode(&mut inode) {
+            self.free_inode(inode_id, is_dir)?;
+            return Err(err);
+        }
+
+        Ok(self.inode_cache.borrow_mut().insert_inode(inode))
+    }
+
+    /// Checks whether the blocks of an inode can be released, which requires them to be mapped by an extent tree.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`IOError::InvalidCommand`] if the inode maps blocks through an indirect block map.
+    pub(super) fn check_releasable(inode: &Inode) -> CanFail<IOError> {
+        if inode.uses_extent_tree()
+            || inode.has_inline_data()
+            || inode.i_block.as_blk_pointers().iter().all(|&blk| blk == 0)
+        {
+            return Ok(());
+        }
+
+        Err(IOError::InvalidCommand)
+    }
+
+    /// Releases a link to an inode, once one of its entries has been removed from a directory.
+    ///
+    /// Directories lose all their links at once, as the `.` entry and the `..` entries of subdirectories go away with
+    /// them. The inode is freed along with its blocks once it has no link left (see [`Ext4Fs::check_releasable`]).
+    ///
+    /// # Errors
+    ///
+    /// May return any variant of [`IOError`] in case of a failure while attempting to read from or write to disk.
+    pub(super) fn release_link(&self, locked_inode: &LockedInodeStrongRef) -> CanFail<IOError> {
+        let mut inode = locked_inode.write();
+        let is_dir = matches!(inode.inode_type(), InodeType::Directory);
+        let links = if is_dir {
+            0
+        } else {
+            u16::from(inode.links()).saturating_sub(1)
+        };
+
+        inode.set_links(InodeHardLinkCount::from(links));
+
+        if links > 0 {
+            inode.touch(current_timestamp());
+            return self.write_inode(&mut inode);
+        }
+
+        drop(inode);
+
+        let locked_fs = self.fs_ptr.upgrade().ok_or(IOError::Unknown)?;
+        let mut unmapped = alloc::vec![];
+        let mut inode = if let Some(mut extent_tree) =
+            ExtentTree::load_extent_tree(locked_fs, locked_inode.clone())
+        {
+            unmapped = extent_tree.remove_blks_from(cast(0u64));
+
+            let mut inode = locked_inode.write();
+            extent_tree.write_tree(self, &mut inode)?;
+            inode
+        } else {
+            locked_inode.write()
+        };
+
+        let xattr_blk =
+            cast::<InodeXattr, u64>({ inode.i_file_acl_lo } + { inode.i_file_acl_high });
+        let inode_id = inode.number;
+
+        inode.set_size(cast(0u64));
+        inode.set_blk_count(cast(0u64));
+        // a deletion time lower than the inode count reads as an orphan list link, so an unset clock is not trusted
+        let dtime = current_timestamp().max(self.superblock.read().last_write_time());
+        inode.set_deletion_time(dtime);
+        self.write_inode(&mut inode)?;
+        drop(inode);
+
+        // blocks are released once the inode does not reference them anymore
+        for (blk, count) in unmapped {
+            self.free_blks(blk, count)?;
+        }
+
+        if xattr_blk != 0 {
+            release_xattr_blk(self, Ext4RealBlkId::from(xattr_blk))?;
+        }
+
+        self.free_inode(inode_id, is_dir)?;
+        self.inode_cache.borrow_mut().remove_entry(inode_id);
+
+        Ok(())
+    }
+
+    /// Allocates a free inode, as close as possible to its `parent` directory.
+    ///
+    /// Block groups are searched in order, starting from the one containing `parent`. Reserved inodes are never
+    /// allocated.
+    fn allocate_inode(&self, parent: InodeNumber, is_dir: bool) -> IOResult<InodeNumber> {
+        let sb = self.superblock.read();
+        let bg_count = cast::<BlockGroupNumber, u32>(sb.bg_count());
+        let inodes_per_group = cast::<InodeCount, u32>(sb.inodes_per_group);
+        let first_inode = u32::from(sb.first_inode());
+        let parent_bg = sb.get_inode_blk_group(parent);
+        drop(sb);
+
+        for bg_offset in 0..bg_count {
+            let bg = (cast::<BlockGroupNumber, u32>(parent_bg) + bg_offset) % bg_count;
+            // inode numbers start at 1
+            let from = (first_inode - 1).saturating_sub(bg * inodes_per_group);
+
+            let locked_descriptor = self
+                .get_group_descriptor(cast(bg))
+                .ok_or(IOError::Unknown)?;
+            let mut descriptor = locked_descriptor.write();
+
+            if cast::<InodeCount, u32>(descriptor.free_inode_count()) == 0 {
+                continue;
+            }
+
+            if let Some(idx) = descriptor.find_free_inode(from)? {
+                descriptor.mark_inode_used(idx, is_dir)?;
+                drop(descriptor);
+
+                self.update_free_inode_count(|free_inodes_count| free_inodes_count - 1)?;
+
+                return Ok(cast(bg * inodes_per_group + idx + 1));
+            }
+        }
+
+        Err(IOError::NoSpace)
+    }
+
+    /// Frees an inode, which must not be referenced by any directory entry anymore.
+    ///
+    /// # Errors
+    ///
+    /// May return any variant of [`IOError`] in case of a failure while attempting to read from or write to disk.
+    pub(super) fn free_inode(&self, inode_id: InodeNumber, is_dir: bool) -> CanFail<IOError> {
+        let sb = self.superblock.read();
+        let inode_bg = sb.get_inode_blk_group(inode_id);
+        // inode numbers start at 1
+        let inode_idx = (inode_id - 1) % sb.inodes_per_group;
+        drop(sb);
+
+        self.get_group_descriptor(inode_bg)
+            .ok_or(IOError::Unknown)?
+            .write()
+            .mark_inode_free(inode_idx, is_dir)?;
+
+        self.update_free_inode_count(|free_inodes_count| free_inodes_count + 1)
+    }
+
+    /// Updates the count of free inodes stored in the superblock, and writes the superblock back to disk.
+    fn update_free_inode_count(&self, update: impl FnOnce(u32) -> u32) -> CanFail<IOError> {
+        let mut sb = self.superblock.write();
+        let free_inodes_count = update(cast::<InodeCount, u32>(sb.free_inodes_count));
+
+        sb.free_inodes_count = cast(free_inodes_count);
+        drop(sb);
+
+        self.write_superblock()
+    }
+
+    /// Allocates a run of at most `max_len` contiguous blocks, as close as possible after the `goal` block.
+    ///
+    /// Block groups are searched in order, starting from the one containing `goal`. Returns the first block of the
+    /// run, and its length.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`IOError::NoSpace`] if there is no free block left on the filesystem. May return any other variant of
+    /// [`IOError`] in case of a failure while attempting to read from or write to disk.
+    pub(super) fn allocate_blks(
+        &self,
+        goal: Ext4RealBlkId,
+        max_len: u64,
+    ) -> IOResult<(Ext4RealBlkId, u64)> {
+        let sb = self.superblock.read();
+        let bg_count = cast::<BlockGroupNumber, u32>(sb.bg_count());
+        let goal = if goal >= sb.first_data_blk() && goal < sb.blk_count() {
+            goal
+        } else {
+            sb.first_data_blk()
+        };
+        let (goal_bg, goal_idx) = sb.get_blk_group(goal);
+        drop(sb);
+
+        // the group of the goal is searched twice: from the goal first, and then from its beginning
+        for bg_offset in 0..=bg_count {
+            let bg = cast::<u32, BlockGroupNumber>(
+                (cast::<BlockGroupNumber, u32>(goal_bg) + bg_offset) % bg_count,
+            );
+            let from = if bg_offset == 0 { goal_idx } else { 0 };
+
+            let locked_descriptor = self.get_group_descriptor(bg).ok_or(IOError::Unknown)?;
+            let mut descriptor = locked_descriptor.write();
+
+            if cast::<Ext4BlkCount, u64>(descriptor.free_blk_count()) == 0 {
+                continue;
+            }
+
+            if let Some((first, count)) = descriptor.find_free_blks(from, max_len)? {
+                descriptor.mark_blks_used(first, count)?;
+                drop(descriptor);
+
+                self.update_free_blk_count(|free_blk_count| free_blk_count - count)?;
+
+                return Ok((self.superblock.read().bg_first_blk(bg) + first, count));
+            }
+        }
+
+        Err(IOError::NoSpace)
+    }
+
+    /// Frees `count` contiguous blocks, starting from `first`.
+    ///
+    /// # Errors
+    ///
+    /// May return any variant of [`IOError`] in case of a failure while attempting to read from or write to disk.
+    pub(super) fn free_blks(&self, first: Ext4RealBlkId, count: u64) -> CanFail<IOError> {
+        let sb = self.superblock.read();
+        let blks_per_group = cast::<Ext4BlkCount, u64>(sb.blocks_per_group.into());
+        let (mut bg, mut idx) = sb.get_blk_group(first);
+        drop(sb);
+
+        let mut freed = 0;
+
+        // the run of blocks may span several block groups
+        while freed < count {
+            let group_count = u64::min(count - freed, blks_per_group - idx);
+
+            self.get_group_descriptor(bg)
+                .ok_or(IOError::Unknown)?
+                .write()
+                .mark_blks_free(idx, group_count)?;
+
+            freed += group_count;
+            bg = bg + 1;
+            idx = 0;
+        }
+
+        self.update_free_blk_count(|free_blk_count| free_blk_count + count)
+    }
+
+    /// Updates the count of free blocks stored in the superblock, and writes the superblock back to disk.
+    fn update_free_blk_count(&self, update: impl FnOnce(u64) -> u64) -> CanFail<IOError> {
+        let mut sb = self.superblock.write();
+        let free_blk_count = update(cast::<Ext4BlkCount, u64>(sb.free_blk_count()));
+
+        sb.set_free_blk_count(cast::<u64, Ext4BlkCount>(free_blk_count));
+        drop(sb);
+
+        self.write_superblock()
+    }
+
+    /// Writes the superblock back to disk, after having updated its checksum.
+    fn write_superblock(&self) -> CanFail<IOError> {
+        let mut sb = self.superblock.write();
+
+        if sb.has_metadata_chksum() {
+            sb.update_chksum();
+        }
+
+        let raw_sb = sb.as_bytes().to_vec();
+        let blk_size = sb.blk_size();
+        drop(sb);
+
+        // the superblock is always located 1024 bytes after the beginning of the partition, whatever the block size
+        let sb_blk_id = Ext4RealBlkId::from(SUPERBLOCK_OFFSET / blk_size);
+        let sb_offset = usize::try_from(SUPERBLOCK_OFFSET % blk_size).expect("invalid block size");
+
+        let mut sb_blk = self.allocate_blk();
+        self.read_blk_from_device(sb_blk_id, &mut sb_blk)?;
+        sb_blk[sb_offset..sb_offset + raw_sb.len()].copy_from_slice(&raw_sb);
+
+        self.write_blk_to_device(sb_blk_id, &sb_blk)
+    }
+
+    /// Writes an [`Inode`] back to the inode table, after having updated its checksum.
+    ///
+    /// # Errors
+    ///
+    /// May return any variant of [`IOError`] in case of a failure while attempting to read from or write to disk.
+    pub(super) fn write_inode(&self, inode: &mut Inode) -> CanFail<IOError> {
+        let sb = self.superblock.read();
+        let (inode_bg, inode_entry_blk_offset, inode_entry_bytes_offset_in_blk) =
+            sb.get_inode_entry_pos(inode.number);
+        let has_metadata_chksum = sb.has_metadata_chksum();
+        drop(sb);
+
+        if has_metadata_chksum {
+            inode.update_chksum();
+        }
+
+        let raw_inode = inode.to_raw_bytes();
+        let inode_entry_blk = self
+            .get_group_descriptor(inode_bg)
+            .ok_or(IOError::Unknown)?
+            .read()
+            .inode_table_blk_addr()
+            + inode_entry_blk_offset;
+        let entry_offset =
+            usize::try_from(inode_entry_bytes_offset_in_blk).expect("invalid byte size");
+
+        let mut raw_inode_entry_blk = self.allocate_blk();
+        self.read_blk_from_device(inode_entry_blk, &mut raw_inode_entry_blk)?;
+        raw_inode_entry_blk[entry_offset..entry_offset + raw_inode.len()]
+            .copy_from_slice(&raw_inode);
+
+        self.write_blk_to_device(inode_entry_blk, &raw_inode_entry_blk)
+    }
+
+    /// Installs the blocks replayed from the journal, which then take precedence over the disk when reading blocks.
+    ///
+    /// Group descriptors and inodes read while replaying the journal are dropped from the caches, and the superblock is
+    /// reloaded if it was replayed. The filesystem remains flagged as needing recovery, and therefore read-only: the
+    /// journal has not been replayed on disk.
+    fn set_journal_overlay(&mut self, journal_overlay: JournalOverlay) -> Result<(), MountError> {
+        self.descriptors_cache.get_mut().descriptor_table.clear();
+        self.inode_cache.get_mut().hashtable.clear();
+
+        let mut sb = self.superblock.write();
+        let blk_size = sb.blk_size();
+        let sb_blk_id = Ext4RealBlkId::from(SUPERBLOCK_OFFSET / blk_size);
+        let sb_offset = usize::try_from(SUPERBLOCK_OFFSET % blk_size).expect("invalid block size");
+
+        if let Some(sb_blk) = journal_overlay.get(sb_blk_id) {
+            let raw_sb = &sb_blk[sb_offset..sb_offset + mem::size_of::<Ext4Superblock>()];
+            let mut replayed_sb = Superblock {
+                ext4_superblock: unsafe {
+                    core::ptr::read_unaligned(raw_sb.as_ptr().cast::<Ext4Superblock>())
+                },
+            };
+
+            if !replayed_sb.magic.is_valid()
+                || (replayed_sb.checksum_type == Ext4ChksumAlgorithm::CHKSUM_CRC32_C
+                    && !replayed_sb.validate_chksum())
+            {
+                return Err(MountError::BadSuperblock);
+            }
+
+            let mut feature_incompat = replayed_sb.feature_incompat;
+            feature_incompat.extend_from_set(IncompatibleFeatureSet::EXT4_FEATURE_INCOMPAT_RECOVER);
+            replayed_sb.feature_incompat = feature_incompat;
+
+            *sb = replayed_sb;
+        }
+
+        drop(sb);
+        self.journal_overlay = journal_overlay;
+
+        Ok(())
+    }
+
+    fn write_blk_to_device(&self, blk_id: Ext4RealBlkId, buffer: &[u8]) -> CanFail<IOError> {
+        let sb = self.superblock.read();
+        if blk_id >= sb.blk_count() {
+            return Err(IOError::InvalidCommand);
+        }
+
+        let drive = get_sata_drive(self.drive_id).ok_or(IOError::InvalidDevice)?;
+        let partition_data = drive
+            .partitions()
+            .get(self.partition_id)
+            .ok_or(IOError::Unknown)?
+            .start_lba();
+
+        let sectors_count = sb.blk_size() / drive.logical_sector_size();
+        let start_lba = partition_data + (blk_id * sb.blk_size()) / drive.logical_sector_size();
+
+        let write_req = drive
+            .write(
+                start_lba,
+                u16::try_from(sectors_count).expect("invalid sectors count"),
+                buffer.to_vec(),
+            )
+            .complete();
+
+        if !write_req.is_success() {
+            return Err(IOError::Unknown);
+        }
+
+        Ok(())
+    }
+}
+
 impl Fs for Ext4Fs {
     fn mount(
         drive_id: AtaDeviceIdentifier,
@@ -250,7 +908,7 @@ impl Fs for Ext4Fs {
             "ext4-fs",
             "label = {}    inodes_count = {}    blk_count = {}    mmp = {}    opts = {}",
             String::from(sb.volume_name),
-            sb.inodes_count,
+            { sb.inodes_count },
             sb.blk_count(),
             sb.mmp_enabled(),
             String::from(sb.mount_opts)
@@ -271,9 +929,15 @@ impl Fs for Ext4Fs {
                     descriptor_table: HashMap::default(),
                     fs: ptr.clone(),
                 }),
+                journal_overlay: JournalOverlay::default(),
             })
         });
 
+        if fs.read().superblock.read().needs_recovery() {
+            let journal_overlay = JournalOverlay::replay(&fs).map_err(|_| MountError::IOError)?;
+            fs.write().set_journal_overlay(journal_overlay)?;
+        }
+
         Ok(fs)
     }
 
@@ -582,6 +1246,39 @@ const CRC32C_LO_TABLE: [u32; 256] = [
     0xAD7D_5351,
 ];
 
+/// Splits `path` into the path of its parent directory, and the name of its last component.
+fn split_path(path: &str) -> (&str, &str) {
+    path.trim_end_matches('/')
+        .rsplit_once('/')
+        .unwrap_or(("", path.trim_end_matches('/')))
+}
+
+/// Splits a path into its non-empty components.
+fn path_components(path: &[u8]) -> impl DoubleEndedIterator<Item = Vec<u8>> + '_ {
+    path.split(|&byte| byte == b'/')
+        .filter(|component| !component.is_empty())
+        .map(<[u8]>::to_vec)
+}
+
+/// Computes the `crc16` (`CRC-16/ARC` polynomial) checksum of a buffer, as used by the `gdt_csum` feature.
+fn crc16_calc(buf: &[u8]) -> u16 {
+    let mut crc: u16 = 0xFFFF;
+
+    for &b in buf {
+        crc ^= u16::from(b);
+
+        for _ in 0..8 {
+            crc = if crc & 1 == 0 {
+                crc >> 1
+            } else {
+                (crc >> 1) ^ 0xA001
+            };
+        }
+    }
+
+    crc
+}
+
 fn crc32c_calc(buf: &[u8]) -> u32 {
     let mut crc = 0xFFFF_FFFF;
 
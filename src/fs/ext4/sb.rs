@@ -12,7 +12,7 @@ use crate::fs::ext4::block_grp::BlockGroupNumber;
 use crate::fs::ext4::crc32c_calc;
 use crate::fs::ext4::extent::{Ext4RealBlkId, Ext4RealBlkId32};
 use crate::fs::ext4::inode::{InodeBlk, InodeCount, InodeNumber, InodeSizeHi, InodeSizeLo};
-use crate::time::UnixTimestamp32;
+use crate::time::{UnixTimestamp, UnixTimestamp32};
 use alloc::string::String;
 use alloc::sync::Arc;
 use bytemuck::{bytes_of, cast, Pod, Zeroable};
@@ -229,6 +229,19 @@ impl ReadOnlyCompatibleFeatureSet {
 
     pub(crate) const EXT4_FEATURE_R0_COMPAT_ORPHAN_PRESENT: Self = Self(0x10000);
 
+    /// Read-only compatible features supported when writing to the filesystem.
+    ///
+    /// Quotas in particular are not supported, as they would not be updated when allocating or freeing blocks.
+    pub(crate) const WRITE_SUPPORTED_SET: Self = Self(
+        Self::EXT4_FEATURE_R0_COMPAT_SPARSE_SUPER.0
+            | Self::EXT4_FEATURE_R0_COMPAT_LARGE_FILE.0
+            | Self::EXT4_FEATURE_R0_COMPAT_HUGE_FILE.0
+            | Self::EXT4_FEATURE_R0_COMPAT_GDT_CSUM.0
+            | Self::EXT4_FEATURE_DIR_NLINK.0
+            | Self::EXT4_FEATURE_R0_COMPAT_EXTRA_ISIZE.0
+            | Self::EXT4_FEATURE_R0_COMPAT_METADATA_CSUM.0,
+    );
+
     /// Checks if this `CompatibleFeatureSet` is a subset of (included in) the `CompatibleFeatureSet`
     /// passed as argument.
     pub(crate) fn is_subset_of(self, features: ReadOnlyCompatibleFeatureSet) -> bool {
@@ -316,6 +329,19 @@ impl IncompatibleFeatureSet {
     /// casefold (+F) flag enabled.
     pub(crate) const EXT4_FEATURE_INCOMPAT_CASEFOLD: Self = Self(0x20000);
 
+    /// Incompatible features supported when writing to the filesystem.
+    ///
+    /// Filesystems whose journal needs to be recovered (`EXT4_FEATURE_INCOMPAT_RECOVER`) are not writable, as
+    /// replaying the journal later on could overwrite our changes.
+    pub(crate) const WRITE_SUPPORTED_SET: Self = Self(
+        Self::EXT4_FEATURE_INCOMPAT_FILETYPE.0
+            | Self::EXT4_FEATURE_INCOMPAT_EXTENTS.0
+            | Self::EXT4_FEATURE_INCOMPAT_64BIT.0
+            | Self::EXT4_FEATURE_INCOMPAT_FLEX_BG.0
+            | Self::EXT4_FEATURE_INCOMPAT_LARGEDIR.0
+            | Self::EXT4_FEATURE_INCOMPAT_INLINE_DATA.0,
+    );
+
     /// Checks if this `CompatibleFeatureSet` is a subset of (included in) the `CompatibleFeatureSet`
     /// passed as argument.
     pub(crate) fn is_subset_of(self, features: IncompatibleFeatureSet) -> bool {
@@ -577,6 +603,9 @@ impl Ext4CreatorOS {
     pub(crate) const LITES: Self = Self(4);
 }
 
+/// Size of an inode on filesystems using the [`Ext4SuperblockRevision::ORIGINAL`] revision.
+pub(crate) const ORIGINAL_INODE_SIZE: u16 = 128;
+
 /// Superblock's major revision level.
 #[derive(Clone, Copy, Debug, Eq, PartialEq, PartialOrd, Ord, Hash, Pod, Zeroable)]
 #[repr(transparent)]
@@ -710,7 +739,7 @@ impl DerefMut for Superblock {
 /// feature is enabled, in which case it is only kept in groups whose group number is either 0 or a
 /// power of 3, 5, 7.
 #[derive(Clone, Copy, Debug, Eq, PartialEq, PartialOrd, Ord, Hash)]
-#[repr(align(0x8))]
+#[repr(C, packed)]
 pub(crate) struct Ext4Superblock {
     /// Inodes count
     pub(crate) inodes_count: InodeCount,
@@ -1045,7 +1074,7 @@ impl Ext4Superblock {
         inode_id: InodeNumber,
     ) -> (BlockGroupNumber, Ext4BlkCount, u64) {
         let inode_bg_idx = (inode_id - 1) % self.inodes_per_group;
-        let inode_byte_idx = u64::from(inode_bg_idx) * u64::from(self.inode_size);
+        let inode_byte_idx = u64::from(inode_bg_idx) * u64::from(self.inode_entry_size());
 
         let inode_blk_offset: u64 = inode_byte_idx / self.blk_size();
 
@@ -1066,13 +1095,14 @@ impl Ext4Superblock {
     /// ```
     pub(crate) fn validate_chksum(&self) -> bool {
         let comp_chksum = self.compute_chksum();
+        let checksum = self.checksum;
 
-        if comp_chksum != self.checksum {
+        if comp_chksum != checksum {
             error!(
                     "ext4-fs",
                     "found ext4 filesystem with invalid superblock checksum (got {:#010x} expected {:#010x})",
                     comp_chksum.0,
-                self.checksum.0
+                checksum.0
                 );
 
             return false;
@@ -1090,14 +1120,131 @@ impl Ext4Superblock {
     }
 
     /// Returns the number of Block Groups for this filesystem.
+    ///
+    /// Block groups start after the first data block, and the last one may be shorter than the others.
     pub(crate) fn bg_count(&self) -> BlockGroupNumber {
+        let data_blk_count = cast::<Ext4BlkCount, u64>(self.blk_count())
+            - cast::<Ext4RealBlkId, u64>(self.first_data_blk());
+
         cast::<u32, BlockGroupNumber>(
-            (1 + self.blk_count() / self.blocks_per_group.into())
+            data_blk_count
+                .div_ceil(u64::from(self.blocks_per_group.0))
                 .try_into()
                 .expect("invalid block group count"),
         )
     }
 
+    /// Returns the first block of the first block group (the block containing the `Superblock` if blocks are 1024
+    /// bytes long, 0 otherwise).
+    pub(crate) fn first_data_blk(&self) -> Ext4RealBlkId {
+        Ext4RealBlkId::from(u64::from(cast::<Ext4RealBlkId32, u32>(
+            self.first_datablock,
+        )))
+    }
+
+    /// Returns the first block of a block group.
+    pub(crate) fn bg_first_blk(&self, bg: BlockGroupNumber) -> Ext4RealBlkId {
+        self.first_data_blk() + bg * u64::from(self.blocks_per_group.0)
+    }
+
+    /// Returns the block group to which a block belongs, and the index of that block in the group.
+    pub(crate) fn get_blk_group(&self, blk: Ext4RealBlkId) -> (BlockGroupNumber, u64) {
+        let data_blk =
+            cast::<Ext4RealBlkId, u64>(blk) - cast::<Ext4RealBlkId, u64>(self.first_data_blk());
+        let blocks_per_group = u64::from(self.blocks_per_group.0);
+
+        (
+            cast::<u32, BlockGroupNumber>(
+                u32::try_from(data_blk / blocks_per_group).expect("invalid block group"),
+            ),
+            data_blk % blocks_per_group,
+        )
+    }
+
+    /// Checks whether a block group holds a backup of the `Superblock` and of the group descriptors table.
+    ///
+    /// With the `sparse_super` feature, backups are only kept in group 1 and in groups whose number is a power of 3,
+    /// 5 or 7 (the primary copy being in group 0).
+    pub(crate) fn bg_has_sb_backup(&self, bg: BlockGroupNumber) -> bool {
+        let bg = cast::<BlockGroupNumber, u32>(bg);
+
+        if bg <= 1
+            || !self
+                .feature_ro_compat
+                .includes(ReadOnlyCompatibleFeatureSet::EXT4_FEATURE_R0_COMPAT_SPARSE_SUPER)
+        {
+            return true;
+        }
+
+        [3, 5, 7].into_iter().any(|base| {
+            let mut power = base;
+
+            while power < bg {
+                power = power.saturating_mul(base);
+            }
+
+            power == bg
+        })
+    }
+
+    /// Returns the size of a group descriptor, in bytes.
+    pub(crate) fn desc_size(&self) -> u16 {
+        if self
+            .feature_incompat
+            .includes(IncompatibleFeatureSet::EXT4_FEATURE_INCOMPAT_64BIT)
+        {
+            self.desc_size
+        } else {
+            32
+        }
+    }
+
+    /// Checks whether the metadata of this filesystem is checksummed with `crc32c` (`metadata_csum` feature).
+    pub(crate) fn has_metadata_chksum(&self) -> bool {
+        self.feature_ro_compat
+            .includes(ReadOnlyCompatibleFeatureSet::EXT4_FEATURE_R0_COMPAT_METADATA_CSUM)
+    }
+
+    /// Checks whether the journal of this filesystem holds committed transactions which were not written to their final
+    /// location yet (`EXT4_FEATURE_INCOMPAT_RECOVER`).
+    pub(crate) fn needs_recovery(&self) -> bool {
+        self.feature_compat
+            .includes(CompatibleFeatureSet::EXT4_FEATURE_COMPAT_HAS_JOURNAL)
+            && self
+                .feature_incompat
+                .includes(IncompatibleFeatureSet::EXT4_FEATURE_INCOMPAT_RECOVER)
+    }
+
+    /// Checks whether this filesystem can be written to.
+    ///
+    /// Some features require additional bookkeeping when allocating or freeing blocks (quotas, clusters, ...): writes
+    /// are only allowed if every feature enabled on the filesystem is part of the supported feature sets
+    /// ([`IncompatibleFeatureSet::WRITE_SUPPORTED_SET`] and [`ReadOnlyCompatibleFeatureSet::WRITE_SUPPORTED_SET`]).
+    pub(crate) fn is_writable(&self) -> bool {
+        let feature_compat = self.feature_compat;
+        let feature_incompat = self.feature_incompat;
+        let feature_ro_compat = self.feature_ro_compat;
+
+        feature_incompat.is_subset_of(IncompatibleFeatureSet::WRITE_SUPPORTED_SET)
+            && feature_ro_compat.is_subset_of(ReadOnlyCompatibleFeatureSet::WRITE_SUPPORTED_SET)
+            && !feature_compat.includes(CompatibleFeatureSet::EXT4_FEATURE_COMPAT_SPARSE_SUPER2)
+    }
+
+    /// Sets the number of free blocks.
+    pub(crate) fn set_free_blk_count(&mut self, count: Ext4BlkCount) {
+        self.free_blocks_count = Ext4BlkCount32(
+            u32::try_from(count.0 & 0xFFFF_FFFF).expect("invalid free blocks count"),
+        );
+
+        if self
+            .feature_incompat
+            .includes(IncompatibleFeatureSet::EXT4_FEATURE_INCOMPAT_64BIT)
+        {
+            self.free_blocks_count_hi =
+                Ext4BlkCount32(u32::try_from(count.0 >> 32).expect("invalid free blocks count"));
+        }
+    }
+
     /// Returns the number of free blocks.
     pub(crate) fn free_blk_count(&self) -> Ext4BlkCount {
         if self
@@ -1128,19 +1275,55 @@ impl Ext4Superblock {
         1024 << self.log_block_size
     }
 
+    /// Returns the size of an entry of the inode table, in bytes.
+    ///
+    /// Filesystems using the original revision (such as old `ext2` filesystems) do not fill the `inode_size` field,
+    /// and always use 128 bytes inodes.
+    pub(crate) fn inode_entry_size(&self) -> u16 {
+        if { self.rev_level } == Ext4SuperblockRevision::ORIGINAL {
+            ORIGINAL_INODE_SIZE
+        } else {
+            self.inode_size
+        }
+    }
+
+    /// Returns the first non-reserved inode.
+    ///
+    /// Filesystems using the original revision do not fill the `first_ino` field, and always reserve the first 10
+    /// inodes.
+    pub(crate) fn first_inode(&self) -> InodeNumber {
+        if { self.rev_level } == Ext4SuperblockRevision::ORIGINAL {
+            InodeNumber::ORIGINAL_FIRST_INODE
+        } else {
+            self.first_ino
+        }
+    }
+
+    /// Returns the last time this filesystem was written to, as recorded in the superblock.
+    pub(crate) fn last_write_time(&self) -> UnixTimestamp {
+        UnixTimestamp::from(
+            u64::from(cast::<UnixTimestamp32, u32>(self.wtime)) | (u64::from(self.wtime_hi) << 32),
+        )
+    }
+
     /// Checks whether this `ext4` filesystem uses the _Multi Mount Protection_ (`MMP`) feature.
     pub(crate) fn mmp_enabled(&self) -> bool {
         self.feature_incompat
             .includes(IncompatibleFeatureSet::EXT4_FEATURE_INCOMPAT_MMP)
     }
 
-    fn compute_chksum(&self) -> Ext4SuperblockChksum {
-        let sb_bytes = unsafe {
+    /// Returns the raw bytes of this `Ext4Superblock`, as stored on disk.
+    pub(crate) fn as_bytes(&self) -> &[u8] {
+        unsafe {
             core::slice::from_raw_parts(
                 transmute::<*const Ext4Superblock, *const u8>(self),
                 size_of::<Self>(),
             )
-        };
+        }
+    }
+
+    fn compute_chksum(&self) -> Ext4SuperblockChksum {
+        let sb_bytes = self.as_bytes();
         // we remove the checksum bytes from the calculation
         let sb_chk_bytes = &sb_bytes[..sb_bytes.len() - 4];
 
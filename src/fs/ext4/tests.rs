@@ -0,0 +1,247 @@
+//! Tests of the `ext4` filesystem, run against images created by `mke2fs`, checked with `e2fsck` and inspected with
+//! `debugfs`.
+//!
+//! These tools come from `e2fsprogs`, which must be installed on the host running the tests.
+
+extern crate std;
+
+use alloc::string::String;
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+use std::path::PathBuf;
+use std::process::Command;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use crate::drivers::generics::mem_disk::MemoryDisk;
+use crate::fs::partitions::mbr::PartitionType;
+use crate::fs::partitions::Partition;
+use crate::fs::FsFile;
+
+static LAST_IMAGE: AtomicUsize = AtomicUsize::new(0);
+
+/// Returns the path of a new image file, in the temporary directory of the host.
+fn image_path() -> PathBuf {
+    std::env::temp_dir().join(std::format!(
+        "fzboot-ext4-{}-{}.img",
+        std::process::id(),
+        LAST_IMAGE.fetch_add(1, Ordering::Relaxed)
+    ))
+}
+
+/// Creates an `ext4` image of `size` KiB, with 1 KiB blocks, passing `options` to `mke2fs`.
+pub(crate) fn mkfs(size: usize, options: &[&str]) -> Vec<u8> {
+    let path = image_path();
+
+    std::fs::File::create(&path)
+        .and_then(|file| file.set_len(u64::try_from(size * 1024).expect("invalid image size")))
+        .expect("failed to create the image");
+
+    let status = Command::new("mke2fs")
+        .args(["-q", "-F", "-t", "ext4", "-b", "1024"])
+        .args(options)
+        .arg(&path)
+        .status()
+        .expect("failed to run mke2fs (e2fsprogs must be installed)");
+    assert!(status.success(), "mke2fs failed");
+
+    let image = std::fs::read(&path).expect("failed to read the image");
+    std::fs::remove_file(&path).expect("failed to remove the image");
+
+    image
+}
+
+/// Checks the consistency of an `ext4` image with `e2fsck`, without repairing it.
+///
+/// Returns the output of `e2fsck` if it found any error.
+pub(crate) fn fsck(image: &[u8]) -> Result<(), String> {
+    let path = image_path();
+    std::fs::write(&path, image).expect("failed to write the image");
+
+    let output = Command::new("e2fsck")
+        .args(["-f", "-n"])
+        .arg(&path)
+        .output()
+        .expect("failed to run e2fsck (e2fsprogs must be installed)");
+    std::fs::remove_file(&path).expect("failed to remove the image");
+
+    if output.status.success() {
+        Ok(())
+    } else {
+        Err(String::from_utf8_lossy(&output.stdout).into())
+    }
+}
+
+/// Mounts an `ext4` image on a new [`MemoryDisk`].
+pub(crate) fn mount(image: &[u8]) -> (Arc<MemoryDisk>, Partition) {
+    MemoryDisk::with_partition(PartitionType::LinuxNative, image)
+}
+
+/// Creates an `ext4` image of `size` KiB, holding a directory `/dir` with `count` empty files.
+///
+/// The directory is indexed by `e2fsck`, so that entries added to it are inserted in its hash tree.
+fn mkfs_indexed_dir(size: usize, count: usize) -> Vec<u8> {
+    let source = image_path();
+    std::fs::create_dir_all(source.join("dir")).expect("failed to create the source directory");
+    for idx in 0..count {
+        std::fs::write(source.join(std::format!("dir/entry-{idx:04}")), [])
+            .expect("failed to create a source file");
+    }
+
+    let mut image = mkfs(size, &["-d", source.to_str().expect("invalid source path")]);
+    std::fs::remove_dir_all(&source).expect("failed to remove the source directory");
+
+    let path = image_path();
+    std::fs::write(&path, &image).expect("failed to write the image");
+    let status = Command::new("e2fsck")
+        .args(["-f", "-y", "-D"])
+        .arg(&path)
+        .output()
+        .expect("failed to run e2fsck (e2fsprogs must be installed)")
+        .status;
+    // 1 means that the filesystem was modified, which is the point
+    assert!(matches!(status.code(), Some(0 | 1)), "e2fsck failed");
+
+    image = std::fs::read(&path).expect("failed to read the image");
+    std::fs::remove_file(&path).expect("failed to remove the image");
+
+    image
+}
+
+/// Runs the `debugfs` request `request` on an `ext4` image, and returns its output.
+fn debugfs(image: &[u8], request: &str) -> String {
+    let path = image_path();
+    std::fs::write(&path, image).expect("failed to write the image");
+
+    let output = Command::new("debugfs")
+        .args(["-R", request])
+        .arg(&path)
+        .output()
+        .expect("failed to run debugfs (e2fsprogs must be installed)");
+    std::fs::remove_file(&path).expect("failed to remove the image");
+
+    String::from_utf8_lossy(&output.stdout).into()
+}
+
+/// Returns the number of leaf blocks of the extent tree of the file at `path`.
+fn extent_leaves(image: &[u8], path: &str) -> usize {
+    // each line starts with the level of the node and the depth of the tree, as in ` 1/ 2`
+    debugfs(image, &std::format!("ex {path}"))
+        .lines()
+        .filter_map(|line| {
+            let (level, depth) = line.split_once('/')?;
+            let depth = depth.split_whitespace().next()?;
+            Some((
+                level.trim().parse::<usize>().ok()?,
+                depth.parse::<usize>().ok()?,
+            ))
+        })
+        .filter(|&(level, depth)| level + 1 == depth)
+        .count()
+}
+
+/// Returns `size` bytes of data which do not repeat on block boundaries.
+fn pattern(size: usize) -> Vec<u8> {
+    (0..size)
+        .map(|idx| u8::try_from(idx % 251).expect("invalid pattern byte"))
+        .collect()
+}
+
+#[test]
+fn creates_and_reads_back_files() {
+    // 4 block groups, the large file spans several of them
+    let (disk, partition) = mount(&mkfs(32 * 1024, &[]));
+    let data = pattern(12 * 1024 * 1024);
+
+    partition.write_file("/hello.txt", b"hello").unwrap();
+    partition.mkdir("/boot").unwrap();
+    partition.write_file("/boot/data.bin", &data).unwrap();
+
+    assert_eq!(partition.read_file("/hello.txt").unwrap(), b"hello");
+    assert_eq!(partition.read_file("/boot/data.bin").unwrap(), data);
+    assert_eq!(partition.read_dir("/boot").unwrap(), ["data.bin"]);
+
+    fsck(&disk.image()).unwrap();
+}
+
+#[test]
+fn grows_and_truncates_extent_trees() {
+    let (disk, partition) = mount(&mkfs(8 * 1024, &[]));
+    let chunk = pattern(1024);
+
+    // interleaved blocks cannot be merged into extents: each file needs one extent per block,
+    // which does not fit in the inode nor in a single leaf block
+    for _ in 0..400 {
+        partition.append_file("/a.bin", &chunk).unwrap();
+        partition.append_file("/b.bin", &chunk).unwrap();
+    }
+
+    let image = disk.image();
+    fsck(&image).unwrap();
+    assert!(
+        extent_leaves(&image, "/a.bin") > 1,
+        "extent tree did not grow"
+    );
+    assert_eq!(partition.read_file("/a.bin").unwrap(), chunk.repeat(400));
+
+    // shrinking removes whole leaves, and part of the last one kept
+    partition
+        .create("/a.bin")
+        .unwrap()
+        .truncate(100 * 1024 + 10)
+        .unwrap();
+    fsck(&disk.image()).unwrap();
+    assert_eq!(partition.read_file("/a.bin").unwrap(), {
+        let mut data = chunk.repeat(100);
+        data.extend_from_slice(&chunk[..10]);
+        data
+    });
+
+    partition.create("/b.bin").unwrap().truncate(0).unwrap();
+    partition.append_file("/b.bin", &chunk).unwrap();
+    fsck(&disk.image()).unwrap();
+    assert_eq!(partition.read_file("/b.bin").unwrap(), chunk);
+}
+
+#[test]
+fn inserts_entries_in_indexed_directories() {
+    let (disk, partition) = mount(&mkfs_indexed_dir(8 * 1024, 200));
+
+    for idx in 0..20 {
+        partition
+            .write_file(&std::format!("/dir/new-{idx:04}"), b"new")
+            .unwrap();
+    }
+
+    let image = disk.image();
+    fsck(&image).unwrap();
+    assert!(
+        debugfs(&image, "htree /dir").contains("Root node dump"),
+        "index was dropped"
+    );
+
+    for idx in 0..20 {
+        assert_eq!(
+            partition
+                .read_file(&std::format!("/dir/new-{idx:04}"))
+                .unwrap(),
+            b"new"
+        );
+    }
+    assert_eq!(partition.read_dir("/dir").unwrap().len(), 200 + 20);
+
+    // filling the leaf blocks drops the index, the directory is then scanned linearly
+    for idx in 20..600 {
+        partition
+            .write_file(&std::format!("/dir/new-{idx:04}"), b"new")
+            .unwrap();
+    }
+
+    let image = disk.image();
+    fsck(&image).unwrap();
+    assert!(
+        !debugfs(&image, "htree /dir").contains("Root node dump"),
+        "index was kept"
+    );
+    assert_eq!(partition.read_file("/dir/new-0599").unwrap(), b"new");
+    assert_eq!(partition.read_dir("/dir").unwrap().len(), 200 + 600);
+}
@@ -0,0 +1,183 @@
+//! ext4 extended attributes implementation
+//!
+//! Extended attributes are `name -> value` pairs attached to an inode. They are stored in the space left after the
+//! [`Ext4Inode`] structure of large inodes, or in a dedicated block.
+//!
+//! Only the extended attributes stored in the inode body are supported, which is where the kernel stores the
+//! `system.data` attribute of inodes with inline data. Extended attribute blocks are only released when the inode
+//! owning them is freed.
+//!
+//! [`Ext4Inode`]: crate::fs::ext4::inode::Ext4Inode
+
+use alloc::vec::Vec;
+use core::mem::size_of;
+
+use bytemuck::{bytes_of, cast, pod_read_unaligned, Pod, Zeroable};
+
+use crate::errors::{CanFail, IOError};
+use crate::fs::ext4::extent::Ext4RealBlkId;
+use crate::fs::ext4::{crc32c_calc, Ext4Fs};
+
+/// Magic number found at the start of the extended attributes area of an inode, or of an extended attributes block.
+const XATTR_MAGIC: u32 = 0xEA02_0000;
+
+/// Offset of the reference count in the header of an extended attributes block.
+const XATTR_BLK_REFCOUNT_OFFSET: usize = 4;
+
+/// Offset of the checksum in the header of an extended attributes block.
+const XATTR_BLK_CHKSUM_OFFSET: usize = 16;
+
+/// Extended attribute entries are aligned on 4 bytes.
+const XATTR_ENTRY_ALIGN: usize = 4;
+
+/// Prefix of an extended attribute name, stored as an index to save space.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord, Hash, Pod, Zeroable)]
+#[repr(transparent)]
+pub(crate) struct XattrNameIndex(u8);
+
+impl XattrNameIndex {
+    /// `user.` prefix
+    pub(crate) const USER: Self = Self(1);
+
+    /// `system.posix_acl_access` attribute
+    pub(crate) const POSIX_ACL_ACCESS: Self = Self(2);
+
+    /// `system.posix_acl_default` attribute
+    pub(crate) const POSIX_ACL_DEFAULT: Self = Self(3);
+
+    /// `trusted.` prefix
+    pub(crate) const TRUSTED: Self = Self(4);
+
+    /// `security.` prefix
+    pub(crate) const SECURITY: Self = Self(6);
+
+    /// `system.` prefix
+    pub(crate) const SYSTEM: Self = Self(7);
+
+    /// `system.richacl` attribute
+    pub(crate) const RICHACL: Self = Self(8);
+}
+
+/// Header of an extended attribute, followed by its name.
+#[derive(Clone, Copy, Debug, Pod, Zeroable)]
+#[repr(C)]
+struct Ext4XattrEntry {
+    /// Length of the name
+    name_len: u8,
+
+    /// Prefix of the name
+    name_index: XattrNameIndex,
+
+    /// Offset of the value, relative to the first entry
+    value_offs: u16,
+
+    /// Inode storing the value, or 0 if the value is stored with the entries
+    value_inum: u32,
+
+    /// Length of the value
+    value_size: u32,
+
+    /// Hash of the name and value
+    hash: u32,
+}
+
+/// Looks up an extended attribute in the extended attributes area of an inode body.
+///
+/// Returns the value of the attribute, or `None` if it does not exist (or is stored in a separate inode).
+pub(crate) fn find_inode_xattr<'a>(
+    xattrs: &'a [u8],
+    name_index: XattrNameIndex,
+    name: &[u8],
+) -> Option<&'a [u8]> {
+    let magic = u32::from_le_bytes(xattrs.get(..size_of::<u32>())?.try_into().ok()?);
+
+    if magic != XATTR_MAGIC {
+        return None;
+    }
+
+    // value offsets are relative to the first entry
+    let entries = &xattrs[size_of::<u32>()..];
+    let mut offset = 0;
+
+    loop {
+        let raw_entry = entries.get(offset..offset + size_of::<Ext4XattrEntry>())?;
+
+        // the list of entries ends with 4 null bytes
+        if raw_entry[..size_of::<u32>()].iter().all(|&byte| byte == 0) {
+            return None;
+        }
+
+        let entry: Ext4XattrEntry = pod_read_unaligned(raw_entry);
+        let name_start = offset + size_of::<Ext4XattrEntry>();
+        let entry_name = entries.get(name_start..name_start + usize::from(entry.name_len))?;
+
+        if entry.name_index == name_index && entry_name == name && entry.value_inum == 0 {
+            let value_start = usize::from(entry.value_offs);
+            let value_size = usize::try_from(entry.value_size).ok()?;
+
+            return entries.get(value_start..value_start + value_size);
+        }
+
+        offset = (name_start + usize::from(entry.name_len)).next_multiple_of(XATTR_ENTRY_ALIGN);
+    }
+}
+
+/// Releases a reference to an extended attributes block, when the inode referencing it is freed.
+///
+/// Extended attributes blocks may be shared by several inodes with identical attributes: the block is only freed
+/// along with its last reference. Otherwise, its reference count is decremented, and its checksum updated.
+///
+/// The checksum of an extended attributes block is:
+///
+/// ```
+/// crc32c_calc(fs_uuid + blk_id + xattr_block)
+/// ```
+///
+/// # Errors
+///
+/// Returns [`IOError::Unknown`] if `blk` does not hold extended attributes. May return any other variant of
+/// [`IOError`] in case of a failure while attempting to read from or write to disk.
+pub(crate) fn release_xattr_blk(fs: &Ext4Fs, blk: Ext4RealBlkId) -> CanFail<IOError> {
+    let mut raw_blk = fs.allocate_blk();
+    fs.read_blk_from_device(blk, &mut raw_blk)?;
+
+    let read_u32 = |raw_blk: &[u8], offset: usize| {
+        u32::from_le_bytes(
+            raw_blk[offset..offset + size_of::<u32>()]
+                .try_into()
+                .expect("invalid slice length"),
+        )
+    };
+
+    if read_u32(&raw_blk, 0) != XATTR_MAGIC {
+        return Err(IOError::Unknown);
+    }
+
+    let refcount = read_u32(&raw_blk, XATTR_BLK_REFCOUNT_OFFSET);
+
+    if refcount <= 1 {
+        return fs.free_blks(blk, 1);
+    }
+
+    raw_blk[XATTR_BLK_REFCOUNT_OFFSET..XATTR_BLK_REFCOUNT_OFFSET + size_of::<u32>()]
+        .copy_from_slice(&(refcount - 1).to_le_bytes());
+
+    let sb = fs.superblock.read();
+    let fs_uuid = sb.uuid;
+    let has_metadata_chksum = sb.has_metadata_chksum();
+    drop(sb);
+
+    if has_metadata_chksum {
+        raw_blk[XATTR_BLK_CHKSUM_OFFSET..XATTR_BLK_CHKSUM_OFFSET + size_of::<u32>()].fill(0);
+
+        let mut chksum_bytes: Vec<u8> = alloc::vec![];
+        chksum_bytes.extend_from_slice(bytes_of(&fs_uuid));
+        chksum_bytes.extend_from_slice(&cast::<Ext4RealBlkId, u64>(blk).to_le_bytes());
+        chksum_bytes.extend_from_slice(&raw_blk);
+
+        raw_blk[XATTR_BLK_CHKSUM_OFFSET..XATTR_BLK_CHKSUM_OFFSET + size_of::<u32>()]
+            .copy_from_slice(&crc32c_calc(&chksum_bytes).to_le_bytes());
+    }
+
+    fs.write_blk_to_device(blk, &raw_blk)
+}
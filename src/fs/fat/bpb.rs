@@ -0,0 +1,309 @@
+//! FAT boot sector, and _BIOS Parameter Block_ (BPB).
+//!
+//! The first sector of a FAT volume describes its layout: sector and cluster sizes, location and
+//! size of the FATs, of the root directory and of the data region. The FAT type (`FAT12`, `FAT16`
+//! or `FAT32`) is only determined by the number of clusters of the data region.
+
+use core::mem::size_of;
+
+use bytemuck::{Pod, Zeroable};
+
+/// Offset of the boot sector signature.
+const BOOT_SIGNATURE_OFFSET: usize = 510;
+
+/// Signature stored at the end of the boot sector.
+const BOOT_SIGNATURE: [u8; 2] = [0x55, 0xAA];
+
+/// Offset of the FAT32 specific part of the BPB.
+const FAT32_BPB_OFFSET: usize = 36;
+
+/// Offset of the extended boot record of `FAT12` and `FAT16` volumes.
+const FAT16_EBR_OFFSET: usize = 36;
+
+/// Offset of the extended boot record of `FAT32` volumes.
+const FAT32_EBR_OFFSET: usize = 64;
+
+/// Signature of the extended boot record, if the volume label and identifier are valid.
+const EXTENDED_BOOT_SIGNATURE: u8 = 0x29;
+
+/// Maximum number of clusters of a `FAT12` volume.
+const FAT12_MAX_CLUSTERS: u32 = 4084;
+
+/// Maximum number of clusters of a `FAT16` volume.
+const FAT16_MAX_CLUSTERS: u32 = 65524;
+
+/// Size of a directory entry, in bytes.
+pub(crate) const DIR_ENTRY_SIZE: u32 = 32;
+
+/// Set in the FAT32 extended flags if only one FAT is active (FAT mirroring is disabled).
+const FAT32_NO_MIRRORING: u16 = 1 << 7;
+
+/// Type of a FAT filesystem, named after the size of the FAT entries.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum FatType {
+    /// 12-bit FAT entries.
+    Fat12,
+
+    /// 16-bit FAT entries.
+    Fat16,
+
+    /// 28-bit FAT entries (stored on 32 bits).
+    Fat32,
+}
+
+impl FatType {
+    /// Returns the name of the FAT type.
+    pub(crate) fn name(self) -> &'static str {
+        match self {
+            Self::Fat12 => "FAT12",
+            Self::Fat16 => "FAT16",
+            Self::Fat32 => "FAT32",
+        }
+    }
+}
+
+/// Part of the BPB common to every FAT type, as stored in the boot sector.
+#[derive(Clone, Copy, Debug, Pod, Zeroable)]
+#[repr(C, packed)]
+struct RawBpb {
+    jump: [u8; 3],
+    oem_name: [u8; 8],
+    bytes_per_sector: u16,
+    sectors_per_cluster: u8,
+    reserved_sectors: u16,
+    fats_count: u8,
+    root_entries_count: u16,
+    total_sectors_16: u16,
+    media: u8,
+    fat_size_16: u16,
+    sectors_per_track: u16,
+    heads_count: u16,
+    hidden_sectors: u32,
+    total_sectors_32: u32,
+}
+
+/// FAT32 specific part of the BPB, as stored in the boot sector.
+#[derive(Clone, Copy, Debug, Pod, Zeroable)]
+#[repr(C, packed)]
+struct RawFat32Bpb {
+    fat_size_32: u32,
+    ext_flags: u16,
+    fs_version: u16,
+    root_cluster: u32,
+    fs_info: u16,
+    backup_boot_sector: u16,
+    reserved: [u8; 12],
+}
+
+/// Extended boot record, stored after the BPB.
+#[derive(Clone, Copy, Debug, Pod, Zeroable)]
+#[repr(C, packed)]
+struct RawExtendedBootRecord {
+    drive_number: u8,
+    reserved: u8,
+    boot_signature: u8,
+    volume_id: u32,
+    volume_label: [u8; 11],
+    fs_type: [u8; 8],
+}
+
+/// Validated layout of a FAT volume, read from its boot sector.
+///
+/// Every position is expressed in sectors (of [`BiosParameterBlock::bytes_per_sector`] bytes),
+/// relative to the start of the volume.
+#[derive(Clone, Copy, Debug)]
+pub(crate) struct BiosParameterBlock {
+    /// Size of a sector, in bytes.
+    pub(crate) bytes_per_sector: u32,
+
+    /// Size of a cluster, in sectors.
+    pub(crate) sectors_per_cluster: u32,
+
+    /// Number of sectors before the first FAT.
+    pub(crate) reserved_sectors: u32,
+
+    /// Number of copies of the FAT.
+    pub(crate) fats_count: u32,
+
+    /// Size of a single FAT, in sectors.
+    pub(crate) fat_size: u32,
+
+    /// Number of entries of the root directory (`FAT12` and `FAT16` only).
+    pub(crate) root_entries_count: u32,
+
+    /// Total number of sectors of the volume.
+    pub(crate) total_sectors: u32,
+
+    /// First cluster of the root directory (`FAT32` only).
+    pub(crate) root_cluster: u32,
+
+    /// Sector of the `FSInfo` structure (`FAT32` only).
+    pub(crate) fs_info_sector: u32,
+
+    /// Index of the only FAT in use, if FAT mirroring is disabled (`FAT32` only).
+    pub(crate) active_fat: Option<u32>,
+
+    /// Volume serial number, if set.
+    pub(crate) volume_id: Option<u32>,
+
+    /// Volume label, padded with spaces, if set.
+    pub(crate) volume_label: Option<[u8; 11]>,
+
+    /// FAT type, deduced from the number of clusters.
+    pub(crate) fat_type: FatType,
+}
+
+impl BiosParameterBlock {
+    /// Parses and validates the boot sector of a FAT volume.
+    ///
+    /// Returns `None` if the sector does not contain a consistent BPB, which is the case for
+    /// volumes formatted with any other filesystem.
+    pub(crate) fn parse(sector: &[u8]) -> Option<Self> {
+        if sector.get(BOOT_SIGNATURE_OFFSET..BOOT_SIGNATURE_OFFSET + 2)? != BOOT_SIGNATURE {
+            return None;
+        }
+
+        let raw: RawBpb = bytemuck::pod_read_unaligned(sector.get(..size_of::<RawBpb>())?);
+        let bytes_per_sector = u32::from(raw.bytes_per_sector);
+        let sectors_per_cluster = u32::from(raw.sectors_per_cluster);
+
+        let valid_geometry = matches!(bytes_per_sector, 512 | 1024 | 2048 | 4096)
+            && sectors_per_cluster.is_power_of_two()
+            && sectors_per_cluster * bytes_per_sector <= 0x10_0000
+            && raw.reserved_sectors != 0
+            && raw.fats_count != 0
+            && (raw.media == 0xF0 || raw.media >= 0xF8);
+
+        if !valid_geometry {
+            return None;
+        }
+
+        let fat32: RawFat32Bpb = bytemuck::pod_read_unaligned(
+            sector.get(FAT32_BPB_OFFSET..FAT32_BPB_OFFSET + size_of::<RawFat32Bpb>())?,
+        );
+
+        let fat_size = if raw.fat_size_16 == 0 {
+            fat32.fat_size_32
+        } else {
+            u32::from(raw.fat_size_16)
+        };
+        let total_sectors = if raw.total_sectors_16 == 0 {
+            raw.total_sectors_32
+        } else {
+            u32::from(raw.total_sectors_16)
+        };
+
+        if fat_size == 0 || total_sectors == 0 {
+            return None;
+        }
+
+        let root_entries_count = u32::from(raw.root_entries_count);
+        let root_dir_sectors = (root_entries_count * DIR_ENTRY_SIZE).div_ceil(bytes_per_sector);
+        let data_start = u32::from(raw.reserved_sectors)
+            .checked_add(u32::from(raw.fats_count).checked_mul(fat_size)?)?
+            .checked_add(root_dir_sectors)?;
+        let clusters_count = total_sectors.checked_sub(data_start)? / sectors_per_cluster;
+
+        let fat_type = if clusters_count <= FAT12_MAX_CLUSTERS {
+            FatType::Fat12
+        } else if clusters_count <= FAT16_MAX_CLUSTERS {
+            FatType::Fat16
+        } else {
+            FatType::Fat32
+        };
+
+        // FAT32 volumes have no fixed root directory, and FAT12/16 ones no FAT32 BPB
+        let consistent = match fat_type {
+            FatType::Fat32 => {
+                root_entries_count == 0 && raw.fat_size_16 == 0 && fat32.root_cluster >= 2
+            }
+            FatType::Fat12 | FatType::Fat16 => root_entries_count != 0,
+        };
+
+        // the FAT must be large enough to describe every cluster
+        let fat_entries = u64::from(fat_size) * u64::from(bytes_per_sector) * 2
+            / match fat_type {
+                FatType::Fat12 => 3,
+                FatType::Fat16 => 4,
+                FatType::Fat32 => 8,
+            };
+
+        if !consistent || fat_entries < u64::from(clusters_count) + 2 {
+            return None;
+        }
+
+        let ebr_offset = match fat_type {
+            FatType::Fat32 => FAT32_EBR_OFFSET,
+            FatType::Fat12 | FatType::Fat16 => FAT16_EBR_OFFSET,
+        };
+        let ebr: RawExtendedBootRecord = bytemuck::pod_read_unaligned(
+            sector.get(ebr_offset..ebr_offset + size_of::<RawExtendedBootRecord>())?,
+        );
+        let ebr_valid = ebr.boot_signature == EXTENDED_BOOT_SIGNATURE;
+
+        Some(Self {
+            bytes_per_sector,
+            sectors_per_cluster,
+            reserved_sectors: u32::from(raw.reserved_sectors),
+            fats_count: u32::from(raw.fats_count),
+            fat_size,
+            root_entries_count,
+            total_sectors,
+            root_cluster: if fat_type == FatType::Fat32 {
+                fat32.root_cluster
+            } else {
+                0
+            },
+            fs_info_sector: if fat_type == FatType::Fat32 {
+                u32::from(fat32.fs_info)
+            } else {
+                0
+            },
+            active_fat: (fat_type == FatType::Fat32 && fat32.ext_flags & FAT32_NO_MIRRORING != 0)
+                .then_some(u32::from(fat32.ext_flags & 0xF)),
+            volume_id: ebr_valid.then_some(ebr.volume_id),
+            volume_label: ebr_valid.then_some(ebr.volume_label),
+            fat_type,
+        })
+    }
+
+    /// Returns the size of a cluster, in bytes.
+    pub(crate) fn cluster_size(&self) -> u32 {
+        self.sectors_per_cluster * self.bytes_per_sector
+    }
+
+    /// Returns the first sector of the FAT with the given index.
+    pub(crate) fn fat_start(&self, fat_index: u32) -> u32 {
+        self.reserved_sectors + fat_index * self.fat_size
+    }
+
+    /// Returns the first sector of the fixed root directory (`FAT12` and `FAT16` only).
+    pub(crate) fn root_dir_start(&self) -> u32 {
+        self.fat_start(self.fats_count)
+    }
+
+    /// Returns the number of sectors of the fixed root directory (0 for `FAT32` volumes).
+    pub(crate) fn root_dir_sectors(&self) -> u32 {
+        (self.root_entries_count * DIR_ENTRY_SIZE).div_ceil(self.bytes_per_sector)
+    }
+
+    /// Returns the first sector of the data region, which starts with cluster 2.
+    pub(crate) fn data_start(&self) -> u32 {
+        self.root_dir_start() + self.root_dir_sectors()
+    }
+
+    /// Returns the number of clusters of the data region.
+    pub(crate) fn clusters_count(&self) -> u32 {
+        (self.total_sectors - self.data_start()) / self.sectors_per_cluster
+    }
+
+    /// Checks if `cluster` is a valid data cluster number.
+    pub(crate) fn is_valid_cluster(&self, cluster: u32) -> bool {
+        cluster >= 2 && cluster - 2 < self.clusters_count()
+    }
+
+    /// Returns the first sector of a data cluster.
+    pub(crate) fn cluster_start(&self, cluster: u32) -> u32 {
+        self.data_start() + (cluster - 2) * self.sectors_per_cluster
+    }
+}
@@ -0,0 +1,800 @@
+//! `FAT` directory-related structures
+//!
+//! A directory is a table of 32-byte entries. Each file is described by a _short_ (8.3) entry, holding its
+//! attributes, first cluster and size, optionally preceded by a set of _long file name_ (`VFAT`) entries, which
+//! store its full name in UCS-2.
+//!
+//! Files whose name is not a valid 8.3 name are created with a set of long file name entries, and a short name
+//! derived from their long name (`BASIS~N.EXT`), like most implementations do.
+
+use alloc::boxed::Box;
+use alloc::{format, string::String, vec::Vec};
+use core::iter;
+use core::mem::size_of;
+
+use bytemuck::{Pod, Zeroable};
+
+use crate::errors::IOError;
+use crate::fs::fat::bpb::BiosParameterBlock;
+use crate::fs::fat::file::FatFile;
+use crate::fs::fat::LockedFatFs;
+use crate::fs::{DirEntry, Directory, FsDirectory, IOResult};
+use crate::time;
+
+/// The entry cannot be modified.
+pub(crate) const ATTR_READ_ONLY: u8 = 0x01;
+
+/// The entry is hidden from normal directory listings.
+pub(crate) const ATTR_HIDDEN: u8 = 0x02;
+
+/// The entry is an operating system file.
+pub(crate) const ATTR_SYSTEM: u8 = 0x04;
+
+/// The entry holds the volume label, and not a file.
+pub(crate) const ATTR_VOLUME_ID: u8 = 0x08;
+
+/// The entry is a directory.
+pub(crate) const ATTR_DIRECTORY: u8 = 0x10;
+
+/// The file was modified since the last backup.
+pub(crate) const ATTR_ARCHIVE: u8 = 0x20;
+
+/// Combination of attributes marking a long file name entry.
+pub(crate) const ATTR_LONG_NAME: u8 = ATTR_READ_ONLY | ATTR_HIDDEN | ATTR_SYSTEM | ATTR_VOLUME_ID;
+
+/// First name byte of deleted entries.
+const DELETED_ENTRY: u8 = 0xE5;
+
+/// First name byte of entries whose name actually starts with `0xE5`.
+const KANJI_ESCAPE: u8 = 0x05;
+
+/// Set in the order of the last long file name entry of a set (which is stored first).
+const LAST_LONG_ENTRY: u8 = 0x40;
+
+/// Number of UCS-2 characters stored in a long file name entry.
+const LONG_ENTRY_CHARS: usize = 13;
+
+/// Set in the reserved byte of short entries if their base name is lowercase (Windows NT extension).
+const NT_LOWERCASE_BASE: u8 = 0x08;
+
+/// Set in the reserved byte of short entries if their extension is lowercase (Windows NT extension).
+const NT_LOWERCASE_EXT: u8 = 0x10;
+
+/// Maximum length of a long file name, in UCS-2 characters.
+const MAX_LONG_NAME_LEN: usize = 255;
+
+/// Characters which cannot be used in file names.
+const INVALID_NAME_CHARS: &str = "\"*/:<>?\\|";
+
+/// Characters allowed in short names, besides uppercase letters and digits.
+const SHORT_NAME_SPECIAL_CHARS: &str = "$%'-_@~`!(){}^#&";
+
+/// Location of a directory entry on disk.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) struct EntryLocation {
+    /// Sector containing the entry.
+    pub(crate) sector: u32,
+
+    /// Offset of the entry in the sector, in bytes.
+    pub(crate) offset: usize,
+}
+
+/// Short directory entry, as stored on disk.
+#[derive(Clone, Copy, Debug, Pod, Zeroable)]
+#[repr(C)]
+pub(crate) struct RawDirEntry {
+    pub(crate) name: [u8; 11],
+    pub(crate) attributes: u8,
+    pub(crate) nt_reserved: u8,
+    pub(crate) creation_time_tenth: u8,
+    pub(crate) creation_time: u16,
+    pub(crate) creation_date: u16,
+    pub(crate) access_date: u16,
+    pub(crate) first_cluster_high: u16,
+    pub(crate) write_time: u16,
+    pub(crate) write_date: u16,
+    pub(crate) first_cluster_low: u16,
+    pub(crate) file_size: u32,
+}
+
+impl RawDirEntry {
+    /// Returns the first cluster of the file described by this entry.
+    pub(crate) fn first_cluster(&self) -> u32 {
+        (u32::from(self.first_cluster_high) << 16) | u32::from(self.first_cluster_low)
+    }
+
+    /// Returns the short (8.3) name of this entry, formatted as `NAME.EXT`.
+    pub(crate) fn short_name(&self) -> String {
+        let mut raw_name = self.name;
+
+        if raw_name[0] == KANJI_ESCAPE {
+            raw_name[0] = DELETED_ENTRY;
+        }
+
+        let format = |bytes: &[u8], lowercase: bool| -> String {
+            let part = bytes
+                .iter()
+                .rposition(|&byte| byte != b' ')
+                .map_or(&bytes[..0], |last| &bytes[..=last]);
+
+            part.iter()
+                .map(|&byte| {
+                    if lowercase {
+                        char::from(byte.to_ascii_lowercase())
+                    } else {
+                        char::from(byte)
+                    }
+                })
+                .collect()
+        };
+
+        let mut name = format(&raw_name[..8], self.nt_reserved & NT_LOWERCASE_BASE != 0);
+        let ext = format(&raw_name[8..], self.nt_reserved & NT_LOWERCASE_EXT != 0);
+
+        if !ext.is_empty() {
+            name.push('.');
+            name.push_str(&ext);
+        }
+
+        name
+    }
+}
+
+/// Long file name (`VFAT`) directory entry, as stored on disk.
+#[derive(Clone, Copy, Debug, Pod, Zeroable)]
+#[repr(C)]
+pub(crate) struct RawLongDirEntry {
+    pub(crate) order: u8,
+    pub(crate) name1: [u8; 10],
+    pub(crate) attributes: u8,
+    pub(crate) entry_type: u8,
+    pub(crate) checksum: u8,
+    pub(crate) name2: [u8; 12],
+    pub(crate) first_cluster: [u8; 2],
+    pub(crate) name3: [u8; 4],
+}
+
+impl RawLongDirEntry {
+    /// Returns the UCS-2 characters stored in this entry.
+    pub(crate) fn chars(&self) -> [u16; LONG_ENTRY_CHARS] {
+        let mut chars = [0u16; LONG_ENTRY_CHARS];
+
+        for (ucs2, bytes) in chars.iter_mut().zip(
+            self.name1
+                .chunks_exact(2)
+                .chain(self.name2.chunks_exact(2))
+                .chain(self.name3.chunks_exact(2)),
+        ) {
+            *ucs2 = u16::from_le_bytes([bytes[0], bytes[1]]);
+        }
+
+        chars
+    }
+}
+
+/// Computes the checksum of a short name, stored in the long file name entries that precede it.
+pub(crate) fn short_name_checksum(name: &[u8; 11]) -> u8 {
+    name.iter()
+        .fold(0u8, |sum, &byte| sum.rotate_right(1).wrapping_add(byte))
+}
+
+/// Returns the current date and time, encoded as in directory entries.
+pub(crate) fn fat_timestamp() -> (u16, u16) {
+    let now = time::date();
+
+    let date = (now.year.saturating_sub(1980) << 9)
+        | (u16::from(now.month) << 5)
+        | u16::from(now.month_day);
+    let time =
+        (u16::from(now.hours) << 11) | (u16::from(now.minutes) << 5) | u16::from(now.seconds / 2);
+
+    (date, time)
+}
+
+/// Checks if `ch` can be stored in a short name.
+fn is_short_name_char(ch: char) -> bool {
+    ch.is_ascii_uppercase() || ch.is_ascii_digit() || SHORT_NAME_SPECIAL_CHARS.contains(ch)
+}
+
+/// Checks if `name` can be used as the name of a new file.
+fn is_valid_name(name: &str) -> bool {
+    !name.is_empty()
+        && name != "."
+        && name != ".."
+        && !name.ends_with([' ', '.'])
+        && name.encode_utf16().count() <= MAX_LONG_NAME_LEN
+        && !name
+            .chars()
+            .any(|ch| ch < ' ' || INVALID_NAME_CHARS.contains(ch))
+}
+
+/// Builds the short name of `name`, if it is a valid 8.3 name, along with the flags marking its lowercase parts.
+///
+/// Returns `None` if `name` needs a long file name, which is also the case for mixed case names.
+fn exact_short_name(name: &str) -> Option<([u8; 11], u8)> {
+    let (base, ext) = name.split_once('.').unwrap_or((name, ""));
+
+    if base.is_empty() || base.len() > 8 || ext.len() > 3 || ext.contains('.') {
+        return None;
+    }
+
+    let mut raw_name = [b' '; 11];
+    let mut flags = 0;
+
+    for (part, start, flag) in [(base, 0, NT_LOWERCASE_BASE), (ext, 8, NT_LOWERCASE_EXT)] {
+        let upper = part.to_ascii_uppercase();
+
+        if !upper.chars().all(is_short_name_char) {
+            return None;
+        }
+
+        if part != upper {
+            if part != part.to_ascii_lowercase() {
+                return None;
+            }
+
+            flags |= flag;
+        }
+
+        raw_name[start..start + upper.len()].copy_from_slice(upper.as_bytes());
+    }
+
+    Some((raw_name, flags))
+}
+
+/// Builds the short name of a file with a long file name, as `BASIS~N.EXT`, `N` being `number`.
+fn numbered_short_name(name: &str, number: u32) -> [u8; 11] {
+    let convert = |part: &str| -> Vec<u8> {
+        part.chars()
+            .filter(|&ch| ch != ' ' && ch != '.')
+            .map(|ch| {
+                let upper = ch.to_ascii_uppercase();
+
+                if is_short_name_char(upper) {
+                    u8::try_from(upper).expect("invalid short name character")
+                } else {
+                    b'_'
+                }
+            })
+            .collect()
+    };
+
+    let name = name.trim_start_matches('.');
+    let (base, ext) = name.rsplit_once('.').unwrap_or((name, ""));
+    let tail = format!("~{number}");
+
+    let mut base = convert(base);
+    base.truncate(8 - tail.len());
+    base.extend_from_slice(tail.as_bytes());
+
+    let mut ext = convert(ext);
+    ext.truncate(3);
+
+    let mut raw_name = [b' '; 11];
+    raw_name[..base.len()].copy_from_slice(&base);
+    raw_name[8..8 + ext.len()].copy_from_slice(&ext);
+
+    raw_name
+}
+
+/// Builds the set of long file name entries storing `name`, in the order in which they are stored.
+fn long_name_entries(name: &str, checksum: u8) -> Vec<RawLongDirEntry> {
+    let mut chars: Vec<u16> = name.encode_utf16().collect();
+    let count = chars.len().div_ceil(LONG_ENTRY_CHARS);
+
+    // the name is terminated by a null character, and padded with `0xFFFF`, unless it fills the last entry
+    if chars.len() % LONG_ENTRY_CHARS != 0 {
+        chars.push(0);
+        chars.resize(count * LONG_ENTRY_CHARS, 0xFFFF);
+    }
+
+    (0..count)
+        .rev()
+        .map(|idx| {
+            let mut bytes = chars[idx * LONG_ENTRY_CHARS..(idx + 1) * LONG_ENTRY_CHARS]
+                .iter()
+                .flat_map(|ucs2| ucs2.to_le_bytes());
+            let mut fill = |part: &mut [u8]| {
+                for byte in part {
+                    *byte = bytes.next().unwrap_or(0);
+                }
+            };
+
+            let order = u8::try_from(idx + 1).expect("invalid long name entry order");
+            let mut entry = RawLongDirEntry {
+                order: if idx + 1 == count {
+                    order | LAST_LONG_ENTRY
+                } else {
+                    order
+                },
+                attributes: ATTR_LONG_NAME,
+                checksum,
+                ..Zeroable::zeroed()
+            };
+
+            fill(&mut entry.name1);
+            fill(&mut entry.name2);
+            fill(&mut entry.name3);
+
+            entry
+        })
+        .collect()
+}
+
+/// Long file name being assembled from a set of entries.
+#[derive(Clone, Debug)]
+struct PendingLongName {
+    checksum: u8,
+    next_order: u8,
+    chars: Vec<u16>,
+}
+
+impl PendingLongName {
+    /// Returns the long name, if the whole set was read and matches the short entry.
+    fn complete(self, short_entry: &RawDirEntry) -> Option<String> {
+        if self.next_order != 0 || self.checksum != short_name_checksum(&short_entry.name) {
+            return None;
+        }
+
+        let len = self
+            .chars
+            .iter()
+            .position(|&ucs2| ucs2 == 0 || ucs2 == 0xFFFF)
+            .unwrap_or(self.chars.len());
+
+        Some(
+            char::decode_utf16(self.chars[..len].iter().copied())
+                .map(|decoded| decoded.unwrap_or(char::REPLACEMENT_CHARACTER))
+                .collect(),
+        )
+    }
+}
+
+/// Compares two file names, ignoring case.
+fn names_match(left: &str, right: &str) -> bool {
+    left.chars()
+        .flat_map(char::to_lowercase)
+        .eq(right.chars().flat_map(char::to_lowercase))
+}
+
+/// Representation of a directory entry in the `FAT` filesystem.
+#[derive(Clone)]
+pub(crate) struct FatDirectoryEntry {
+    fs: LockedFatFs,
+
+    /// Long file name of this entry, or its short name if it has none.
+    pub(crate) name: String,
+
+    /// Short (8.3) name of this entry.
+    pub(crate) short_name: String,
+
+    /// Attributes of this entry (`ATTR_*`).
+    pub(crate) attributes: u8,
+
+    /// First cluster of the file, or 0 if it is empty.
+    pub(crate) first_cluster: u32,
+
+    /// Size of the file, in bytes (always 0 for directories).
+    pub(crate) size: u32,
+
+    /// Location of the short entry on disk.
+    pub(crate) location: EntryLocation,
+}
+
+impl core::fmt::Debug for FatDirectoryEntry {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.write_fmt(format_args!(
+            "fat directory entry | name = {}    short_name = {}    attributes = {:#x}    cluster = {}    size = {}",
+            self.name, self.short_name, self.attributes, self.first_cluster, self.size
+        ))
+    }
+}
+
+impl FatDirectoryEntry {
+    /// Returns `true` if this entry is a directory.
+    pub(crate) fn is_dir(&self) -> bool {
+        self.attributes & ATTR_DIRECTORY != 0
+    }
+
+    /// Loads the directory described by this entry.
+    ///
+    /// The entry must have the [`ATTR_DIRECTORY`] attribute.
+    #[must_use]
+    pub(crate) fn as_directory(&self) -> Option<GenericFatDirectory> {
+        if !self.is_dir() {
+            return None;
+        }
+
+        // `..` entries of the subdirectories of the root directory reference cluster 0
+        let dir = if self.first_cluster == 0 {
+            FatDirectory::root(self.fs.clone())
+        } else {
+            FatDirectory::from_cluster(self.fs.clone(), self.first_cluster)
+        };
+
+        Some(GenericFatDirectory { dir: dir.ok()? })
+    }
+
+    /// Loads the regular file described by this entry.
+    ///
+    /// The entry must not have the [`ATTR_DIRECTORY`] attribute.
+    #[must_use]
+    pub(crate) fn as_file(&self) -> Option<FatFile> {
+        if self.is_dir() {
+            return None;
+        }
+
+        FatFile::from_entry(self.fs.clone(), self).ok()
+    }
+}
+
+impl TryInto<DirEntry> for FatDirectoryEntry {
+    type Error = IOError;
+
+    fn try_into(self) -> Result<DirEntry, Self::Error> {
+        if self.is_dir() {
+            Ok(DirEntry::Directory(Box::new(
+                self.as_directory().ok_or(IOError::Unknown)?,
+            )))
+        } else {
+            Ok(DirEntry::File(Box::new(
+                self.as_file().ok_or(IOError::Unknown)?,
+            )))
+        }
+    }
+}
+
+/// Representation of a directory in the `FAT` filesystem.
+///
+/// The whole directory table is read when the directory is loaded.
+#[derive(Clone)]
+pub(crate) struct FatDirectory {
+    fs: LockedFatFs,
+
+    /// First cluster of the directory, or 0 for the fixed root directory of `FAT12` and `FAT16` volumes.
+    pub(crate) first_cluster: u32,
+
+    bpb: BiosParameterBlock,
+    clusters: Vec<u32>,
+    is_root: bool,
+    data: Vec<u8>,
+    internal_cursor: usize,
+}
+
+impl core::fmt::Debug for FatDirectory {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.write_fmt(format_args!(
+            "fat directory | cluster = {}    root = {}    size = {}",
+            self.first_cluster,
+            self.is_root,
+            self.data.len()
+        ))
+    }
+}
+
+impl FatDirectory {
+    /// Loads the root directory of a `FAT` filesystem.
+    ///
+    /// # Errors
+    ///
+    /// May return any variant of [`IOError`] in case of a failure while attempting to read from disk.
+    pub(crate) fn root(locked_fs: LockedFatFs) -> IOResult<Self> {
+        let fs = locked_fs.read();
+        let bpb = fs.bpb;
+
+        let (clusters, data) = if bpb.root_cluster == 0 {
+            (Vec::new(), fs.read_root_dir_region()?)
+        } else {
+            let clusters = fs.cluster_chain(bpb.root_cluster)?;
+            let data = fs.read_chain(&clusters)?;
+
+            (clusters, data)
+        };
+
+        drop(fs);
+
+        Ok(Self {
+            fs: locked_fs,
+            first_cluster: bpb.root_cluster,
+            bpb,
+            clusters,
+            is_root: true,
+            data,
+            internal_cursor: 0,
+        })
+    }
+
+    /// Loads a directory from its first cluster.
+    ///
+    /// # Errors
+    ///
+    /// May return any variant of [`IOError`] in case of a failure while attempting to read from disk.
+    pub(crate) fn from_cluster(locked_fs: LockedFatFs, first_cluster: u32) -> IOResult<Self> {
+        let fs = locked_fs.read();
+
+        if first_cluster == fs.bpb.root_cluster {
+            drop(fs);
+            return Self::root(locked_fs);
+        }
+
+        let bpb = fs.bpb;
+        let clusters = fs.cluster_chain(first_cluster)?;
+        let data = fs.read_chain(&clusters)?;
+        drop(fs);
+
+        Ok(Self {
+            fs: locked_fs,
+            first_cluster,
+            bpb,
+            clusters,
+            is_root: false,
+            data,
+            internal_cursor: 0,
+        })
+    }
+
+    /// Search this directory for a given name, compared case-insensitively to both the long and the short names of
+    /// its entries.
+    ///
+    /// Returns the corresponding entry if available.
+    pub(crate) fn search(&mut self, name: &str) -> Option<FatDirectoryEntry> {
+        self.internal_cursor = 0;
+        self.find(|entry| names_match(&entry.name, name) || names_match(&entry.short_name, name))
+    }
+
+    /// Creates an empty regular file named `name` in this directory, and returns its entry.
+    ///
+    /// A set of long file name entries is only created if `name` is not a valid 8.3 name. The directory is extended
+    /// with a new cluster if it has no room left for the new entries.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`IOError::InvalidCommand`] if `name` is not a valid file name, or if an entry with the same name
+    /// already exists, and [`IOError::NoSpace`] if the directory cannot be extended. May return any other variant of
+    /// [`IOError`] in case of a failure while attempting to read from or write to disk.
+    pub(crate) fn create_entry(&mut self, name: &str) -> IOResult<FatDirectoryEntry> {
+        if !is_valid_name(name) || self.search(name).is_some() {
+            return Err(IOError::InvalidCommand);
+        }
+
+        let (short_name, nt_flags, long_entries) =
+            if let Some((short_name, flags)) = exact_short_name(name) {
+                (short_name, flags, Vec::new())
+            } else {
+                let existing: Vec<String> = self.by_ref().map(|entry| entry.short_name).collect();
+                let short_name = (1..=999_999)
+                    .map(|number| numbered_short_name(name, number))
+                    .find(|candidate| {
+                        let candidate = RawDirEntry {
+                            name: *candidate,
+                            ..Zeroable::zeroed()
+                        }
+                        .short_name();
+
+                        !existing
+                            .iter()
+                            .any(|short_name| short_name.eq_ignore_ascii_case(&candidate))
+                    })
+                    .ok_or(IOError::NoSpace)?;
+
+                (
+                    short_name,
+                    0,
+                    long_name_entries(name, short_name_checksum(&short_name)),
+                )
+            };
+
+        let (date, time) = fat_timestamp();
+        let entry = RawDirEntry {
+            name: short_name,
+            attributes: ATTR_ARCHIVE,
+            nt_reserved: nt_flags,
+            creation_time: time,
+            creation_date: date,
+            access_date: date,
+            write_time: time,
+            write_date: date,
+            ..Zeroable::zeroed()
+        };
+
+        let first_slot = self.find_free_slots(long_entries.len() + 1)?;
+        let slots = long_entries
+            .iter()
+            .map(bytemuck::bytes_of)
+            .chain(iter::once(bytemuck::bytes_of(&entry)));
+        let mut location = None;
+
+        // long file name entries are written first, so that a partially created entry set is ignored
+        for (idx, slot) in slots.enumerate() {
+            let offset = (first_slot + idx) * size_of::<RawDirEntry>();
+            let slot: &[u8; 32] = slot.try_into().expect("invalid directory entry size");
+
+            self.data[offset..offset + size_of::<RawDirEntry>()].copy_from_slice(slot);
+            self.fs
+                .read()
+                .write_dir_slot(self.entry_location(offset), slot)?;
+            location = Some(self.entry_location(offset));
+        }
+
+        Ok(FatDirectoryEntry {
+            fs: self.fs.clone(),
+            name: String::from(name),
+            short_name: entry.short_name(),
+            attributes: entry.attributes,
+            first_cluster: 0,
+            size: 0,
+            location: location.ok_or(IOError::Unknown)?,
+        })
+    }
+
+    /// Returns the index of the first of `count` consecutive free slots of this directory, extending it if needed.
+    ///
+    /// Every slot following the end of directory marker is free.
+    fn find_free_slots(&mut self, count: usize) -> IOResult<usize> {
+        loop {
+            let mut run = 0;
+            let mut end_reached = false;
+
+            for (idx, slot) in self.data.chunks_exact(size_of::<RawDirEntry>()).enumerate() {
+                end_reached |= slot[0] == 0;
+
+                if end_reached || slot[0] == DELETED_ENTRY {
+                    run += 1;
+
+                    if run == count {
+                        return Ok(idx + 1 - count);
+                    }
+                } else {
+                    run = 0;
+                }
+            }
+
+            // the fixed root directory of `FAT12` and `FAT16` volumes cannot be extended
+            if self.clusters.is_empty() {
+                return Err(IOError::NoSpace);
+            }
+
+            let clusters = self
+                .fs
+                .read()
+                .allocate_clusters(1, self.clusters.last().copied())?;
+
+            self.clusters.extend_from_slice(&clusters);
+            self.data.resize(
+                self.data.len()
+                    + usize::try_from(self.bpb.cluster_size()).expect("invalid cluster size"),
+                0,
+            );
+        }
+    }
+
+    /// Returns the location on disk of the entry stored at `offset` in this directory.
+    fn entry_location(&self, offset: usize) -> EntryLocation {
+        let bytes_per_sector =
+            usize::try_from(self.bpb.bytes_per_sector).expect("invalid sector size");
+
+        let (first_sector, offset) = if self.clusters.is_empty() {
+            (self.bpb.root_dir_start(), offset)
+        } else {
+            let cluster_size =
+                usize::try_from(self.bpb.cluster_size()).expect("invalid cluster size");
+
+            (
+                self.bpb.cluster_start(self.clusters[offset / cluster_size]),
+                offset % cluster_size,
+            )
+        };
+
+        EntryLocation {
+            sector: first_sector
+                + u32::try_from(offset / bytes_per_sector).expect("invalid directory offset"),
+            offset: offset % bytes_per_sector,
+        }
+    }
+}
+
+impl Iterator for FatDirectory {
+    type Item = FatDirectoryEntry;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let mut long_name: Option<PendingLongName> = None;
+
+        while let Some(raw_entry) = self
+            .data
+            .get(self.internal_cursor..self.internal_cursor + size_of::<RawDirEntry>())
+        {
+            let offset = self.internal_cursor;
+            self.internal_cursor += size_of::<RawDirEntry>();
+
+            let entry: RawDirEntry = bytemuck::pod_read_unaligned(raw_entry);
+
+            match entry.name[0] {
+                // end of the directory
+                0 => break,
+                DELETED_ENTRY => {
+                    long_name = None;
+                    continue;
+                }
+                _ => (),
+            }
+
+            if entry.attributes & ATTR_LONG_NAME == ATTR_LONG_NAME {
+                let long_entry: RawLongDirEntry = bytemuck::pod_read_unaligned(raw_entry);
+                let order = long_entry.order & !LAST_LONG_ENTRY;
+
+                long_name = if long_entry.order & LAST_LONG_ENTRY != 0 {
+                    // first entry of a set, storing the end of the name
+                    (order != 0).then(|| PendingLongName {
+                        checksum: long_entry.checksum,
+                        next_order: order,
+                        chars: Vec::new(),
+                    })
+                } else {
+                    long_name.filter(|pending| {
+                        pending.next_order == order && pending.checksum == long_entry.checksum
+                    })
+                };
+
+                if let Some(pending) = &mut long_name {
+                    let mut chars = long_entry.chars().to_vec();
+                    chars.append(&mut pending.chars);
+
+                    pending.chars = chars;
+                    pending.next_order -= 1;
+                }
+
+                continue;
+            }
+
+            if entry.attributes & ATTR_VOLUME_ID != 0 {
+                long_name = None;
+                continue;
+            }
+
+            let short_name = entry.short_name();
+
+            return Some(FatDirectoryEntry {
+                fs: self.fs.clone(),
+                name: long_name
+                    .take()
+                    .and_then(|pending| pending.complete(&entry))
+                    .unwrap_or_else(|| short_name.clone()),
+                short_name,
+                attributes: entry.attributes,
+                first_cluster: entry.first_cluster(),
+                size: entry.file_size,
+                location: self.entry_location(offset),
+            });
+        }
+
+        self.internal_cursor = 0;
+        None
+    }
+}
+
+#[derive(Debug)]
+pub(crate) struct GenericFatDirectory {
+    pub(super) dir: FatDirectory,
+}
+
+impl Iterator for GenericFatDirectory {
+    type Item = DirEntry;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.dir.next()?.try_into().ok()
+    }
+}
+
+impl FsDirectory for GenericFatDirectory {
+    fn parent(&mut self) -> Option<Directory> {
+        Some(Box::new(self.dir.search("..")?.as_directory()?))
+    }
+
+    fn is_root_dir(&self) -> IOResult<bool> {
+        Ok(self.dir.is_root)
+    }
+
+    fn size(&self) -> IOResult<usize> {
+        Ok(self.dir.data.len())
+    }
+}
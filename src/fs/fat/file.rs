@@ -0,0 +1,261 @@
+//! `FAT` file-related structures
+//!
+//! Provides methods for loading, reading and writing bytes from files, as defined by the `FAT` filesystem.
+//! Serves as as interface between the `FAT` definition of a file and the abstract implementation in `FrozenBoot`
+
+use alloc::vec::Vec;
+
+use crate::errors::{CanFail, IOError};
+use crate::fs::fat::dir::{fat_timestamp, EntryLocation, FatDirectoryEntry, RawDirEntry};
+use crate::fs::fat::table::FatEntry;
+use crate::fs::fat::LockedFatFs;
+use crate::fs::{FsFile, IOResult, Seek};
+
+/// Representation of a file in the `FAT` filesystem.
+pub(crate) struct FatFile {
+    fs: LockedFatFs,
+    clusters: Vec<u32>,
+    size: usize,
+    cursor: usize,
+    location: EntryLocation,
+}
+
+impl core::fmt::Debug for FatFile {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.write_fmt(format_args!(
+            "fat file | first_cluster = {}    clusters_count = {}    size = {}",
+            self.clusters.first().copied().unwrap_or_default(),
+            self.clusters.len(),
+            self.size
+        ))
+    }
+}
+
+impl FatFile {
+    /// Loads a `FatFile` from its directory entry.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`IOError::Unknown`] if the cluster chain of the file is corrupted, or too short for its size. May
+    /// return any other variant of [`IOError`] in case of a failure while attempting to read from disk.
+    pub(crate) fn from_entry(locked_fs: LockedFatFs, entry: &FatDirectoryEntry) -> IOResult<Self> {
+        let fs = locked_fs.read();
+        let clusters = fs.cluster_chain(entry.first_cluster)?;
+        let size = usize::try_from(entry.size).expect("invalid file size");
+
+        if clusters.len() < size.div_ceil(fs.cluster_size()) {
+            return Err(IOError::Unknown);
+        }
+
+        drop(fs);
+
+        Ok(Self {
+            fs: locked_fs,
+            clusters,
+            size,
+            cursor: 0,
+            location: entry.location,
+        })
+    }
+
+    /// Writes `data` to the file, starting from `offset`, and extends the file if needed.
+    fn write_at(&mut self, offset: usize, data: &[u8]) -> CanFail<IOError> {
+        if data.is_empty() {
+            return Ok(());
+        }
+
+        let end = offset + data.len();
+
+        if u32::try_from(end).is_err() {
+            return Err(IOError::InvalidCommand);
+        }
+
+        self.reserve_clusters(end)?;
+
+        let fs = self.fs.read();
+        let cluster_size = fs.cluster_size();
+        let bytes_per_sector =
+            usize::try_from(fs.bpb.bytes_per_sector).expect("invalid sector size");
+        let mut position = offset;
+
+        while position < end {
+            let cluster = self.clusters[position / cluster_size];
+            let start_in_cluster = position % cluster_size;
+            let end_in_cluster = usize::min(cluster_size, start_in_cluster + end - position);
+            let chunk =
+                &data[position - offset..position - offset + end_in_cluster - start_in_cluster];
+
+            // only the sectors containing the written bytes are updated
+            let first_sector = start_in_cluster / bytes_per_sector;
+            let last_sector = (end_in_cluster - 1) / bytes_per_sector;
+            let sector = fs.bpb.cluster_start(cluster)
+                + u32::try_from(first_sector).expect("invalid sector index");
+            let sectors_count =
+                u32::try_from(last_sector - first_sector + 1).expect("invalid sectors count");
+
+            let mut sectors = if start_in_cluster % bytes_per_sector == 0
+                && end_in_cluster % bytes_per_sector == 0
+            {
+                alloc::vec![0u8; chunk.len()]
+            } else {
+                fs.read_sectors(sector, sectors_count)?
+            };
+
+            let start_in_sectors = start_in_cluster - first_sector * bytes_per_sector;
+            sectors[start_in_sectors..start_in_sectors + chunk.len()].copy_from_slice(chunk);
+            fs.write_sectors(sector, &sectors)?;
+
+            position += chunk.len();
+        }
+
+        drop(fs);
+
+        self.size = usize::max(self.size, end);
+        self.update_entry()
+    }
+
+    /// Allocates clusters, so that the file can hold at least `size` bytes.
+    fn reserve_clusters(&mut self, size: usize) -> CanFail<IOError> {
+        let fs = self.fs.read();
+        let required = size.div_ceil(fs.cluster_size());
+
+        if required > self.clusters.len() {
+            let clusters = fs.allocate_clusters(
+                u32::try_from(required - self.clusters.len()).expect("invalid clusters count"),
+                self.clusters.last().copied(),
+            )?;
+
+            self.clusters.extend_from_slice(&clusters);
+        }
+
+        Ok(())
+    }
+
+    /// Stores the size and first cluster of the file in its directory entry, and updates its modification time.
+    fn update_entry(&self) -> CanFail<IOError> {
+        let fs = self.fs.read();
+        let mut entry: RawDirEntry =
+            bytemuck::pod_read_unaligned(&fs.read_dir_slot(self.location)?);
+        let first_cluster = self.clusters.first().copied().unwrap_or_default();
+        let (date, time) = fat_timestamp();
+
+        entry.file_size = u32::try_from(self.size).expect("invalid file size");
+        entry.first_cluster_high =
+            u16::try_from(first_cluster >> 16).expect("invalid cluster number");
+        entry.first_cluster_low =
+            u16::try_from(first_cluster & 0xFFFF).expect("invalid cluster number");
+        entry.write_date = date;
+        entry.write_time = time;
+        entry.access_date = date;
+
+        fs.write_dir_slot(
+            self.location,
+            bytemuck::bytes_of(&entry)
+                .try_into()
+                .expect("invalid directory entry size"),
+        )
+    }
+}
+
+impl FsFile for FatFile {
+    fn read(&mut self, buf: &mut [u8]) -> IOResult<usize> {
+        let bytes_count = usize::min(buf.len(), self.size - self.cursor);
+
+        if bytes_count == 0 {
+            return Ok(0);
+        }
+
+        let fs = self.fs.read();
+        let cluster_size = fs.cluster_size();
+
+        let first_cluster = self.cursor / cluster_size;
+        let last_cluster = (self.cursor + bytes_count - 1) / cluster_size;
+        let data = fs.read_chain(&self.clusters[first_cluster..=last_cluster])?;
+
+        let offset = self.cursor % cluster_size;
+        buf[..bytes_count].copy_from_slice(&data[offset..offset + bytes_count]);
+
+        drop(fs);
+        self.seek(Seek::Forward(bytes_count));
+
+        Ok(bytes_count)
+    }
+
+    fn write(&mut self, buf: &[u8]) -> IOResult<usize> {
+        // the cursor may lie past the end of the file after truncating it
+        if self.cursor > self.size {
+            self.extend(self.cursor)?;
+        }
+
+        self.write_at(self.cursor, buf)?;
+        self.seek(Seek::Forward(buf.len()));
+
+        Ok(buf.len())
+    }
+
+    fn seek(&mut self, pos: Seek) -> usize {
+        match pos {
+            Seek::Backward(count) => {
+                self.cursor = self.cursor.saturating_sub(count);
+            }
+            Seek::Current => (),
+            Seek::Forward(count) => {
+                self.cursor = usize::min(self.cursor.saturating_add(count), self.size);
+            }
+        }
+
+        self.cursor
+    }
+
+    fn size(&self) -> IOResult<usize> {
+        Ok(self.size)
+    }
+
+    fn truncate(&mut self, size: usize) -> IOResult<usize> {
+        if size > self.size {
+            return Err(IOError::InvalidCommand);
+        }
+
+        let fs = self.fs.read();
+        let kept = size.div_ceil(fs.cluster_size());
+
+        if kept < self.clusters.len() {
+            let released = self.clusters.split_off(kept);
+
+            // the chain is cut before its clusters are released, so that it never references free clusters
+            if let Some(&last) = self.clusters.last() {
+                fs.set_fat_entry(last, FatEntry::EndOfChain)?;
+            } else {
+                self.size = 0;
+                self.update_entry()?;
+            }
+
+            fs.free_clusters(&released)?;
+        }
+
+        drop(fs);
+
+        self.size = size;
+        self.update_entry()?;
+
+        Ok(self.size)
+    }
+
+    fn extend(&mut self, size: usize) -> IOResult<usize> {
+        if size < self.size {
+            return Err(IOError::InvalidCommand);
+        }
+
+        // new clusters are allocated filled with zeros, only the end of the last cluster has to be cleared
+        let allocated = self.clusters.len() * self.fs.read().cluster_size();
+        let cleared = usize::min(size, allocated).saturating_sub(self.size);
+
+        self.write_at(self.size, &alloc::vec![0u8; cleared])?;
+        self.reserve_clusters(size)?;
+
+        self.size = size;
+        self.update_entry()?;
+
+        Ok(self.size)
+    }
+}
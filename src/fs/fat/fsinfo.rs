@@ -0,0 +1,72 @@
+//! `FAT32` `FSInfo` sector.
+//!
+//! The `FSInfo` sector caches the number of free clusters of the volume, and a hint of where to look
+//! for free clusters, so that they do not have to be computed by scanning the whole FAT. Both
+//! values are advisory: `0xFFFFFFFF` means that they are unknown.
+
+/// Signature stored at the start of the sector.
+const LEAD_SIGNATURE: u32 = 0x4161_5252;
+
+/// Signature stored before the free clusters count.
+const STRUCT_SIGNATURE: u32 = 0x6141_7272;
+
+/// Signature stored at the end of the sector.
+const TRAIL_SIGNATURE: u32 = 0xAA55_0000;
+
+/// Offset of the signature stored before the free clusters count.
+const STRUCT_SIGNATURE_OFFSET: usize = 484;
+
+/// Offset of the free clusters count.
+const FREE_COUNT_OFFSET: usize = 488;
+
+/// Offset of the next free cluster hint.
+const NEXT_FREE_OFFSET: usize = 492;
+
+/// Offset of the trailing signature.
+const TRAIL_SIGNATURE_OFFSET: usize = 508;
+
+/// Value of the fields which are not known.
+pub(crate) const UNKNOWN: u32 = 0xFFFF_FFFF;
+
+/// Content of the `FSInfo` sector.
+#[derive(Clone, Copy, Debug)]
+pub(crate) struct FsInfo {
+    /// Number of free clusters, or [`UNKNOWN`].
+    pub(crate) free_count: u32,
+
+    /// Cluster from which to start looking for free clusters, or [`UNKNOWN`].
+    pub(crate) next_free: u32,
+}
+
+impl FsInfo {
+    /// Parses the `FSInfo` sector.
+    ///
+    /// Returns `None` if one of its signatures is invalid.
+    pub(crate) fn parse(sector: &[u8]) -> Option<Self> {
+        let read_u32 = |offset: usize| -> Option<u32> {
+            Some(u32::from_le_bytes(
+                sector.get(offset..offset + 4)?.try_into().ok()?,
+            ))
+        };
+
+        if read_u32(0)? != LEAD_SIGNATURE
+            || read_u32(STRUCT_SIGNATURE_OFFSET)? != STRUCT_SIGNATURE
+            || read_u32(TRAIL_SIGNATURE_OFFSET)? != TRAIL_SIGNATURE
+        {
+            return None;
+        }
+
+        Some(Self {
+            free_count: read_u32(FREE_COUNT_OFFSET)?,
+            next_free: read_u32(NEXT_FREE_OFFSET)?,
+        })
+    }
+
+    /// Stores the free clusters count and the next free cluster hint in an `FSInfo` sector.
+    pub(crate) fn store(self, sector: &mut [u8]) {
+        sector[FREE_COUNT_OFFSET..FREE_COUNT_OFFSET + 4]
+            .copy_from_slice(&self.free_count.to_le_bytes());
+        sector[NEXT_FREE_OFFSET..NEXT_FREE_OFFSET + 4]
+            .copy_from_slice(&self.next_free.to_le_bytes());
+    }
+}
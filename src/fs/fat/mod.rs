@@ -0,0 +1,671 @@
+//! `FAT` (File Allocation Table) filesystems `FrozenBoot`'s implementation.
+//!
+//! Covers the three historical variants of the filesystem, `FAT12`, `FAT16` and `FAT32`, which
+//! only differ by the size of the entries of their allocation table, and by the location of their
+//! root directory. `FAT` is still widely used on removable media, and for _EFI_ system partitions.
+//!
+//! Long file names (`VFAT`) are supported, and names are looked up case-insensitively, like most
+//! implementations do.
+//!
+//! Files can be created, written, truncated and extended, which is enough to store boot state and
+//! logs on an _EFI_ system partition. Clusters are allocated from the FAT, whose copies are all
+//! kept identical (unless FAT mirroring is disabled), and the free clusters count of the `FAT32`
+//! `FSInfo` sector is kept up to date. Directories cannot be created, nor files removed.
+
+use alloc::boxed::Box;
+use alloc::sync::{Arc, Weak};
+use alloc::{string::String, vec::Vec};
+use core::cell::RefCell;
+
+use spin::RwLock;
+
+use crate::drivers::generics::dev_disk::{get_sata_drive, DiskDevice};
+use crate::drivers::ide::AtaDeviceIdentifier;
+use crate::errors::{CanFail, IOError, MountError};
+use crate::fs::fat::bpb::{BiosParameterBlock, FatType};
+use crate::fs::fat::dir::{EntryLocation, FatDirectory, GenericFatDirectory};
+use crate::fs::fat::file::FatFile;
+use crate::fs::fat::fsinfo::FsInfo;
+use crate::fs::fat::table::{
+    fat_entry_offset, fat_entry_value, store_fat_entry_value, FatCache, FatEntry,
+};
+use crate::fs::{Directory, Fs, IOResult};
+use crate::info;
+
+pub(crate) mod bpb;
+pub(crate) mod dir;
+pub(crate) mod file;
+pub(crate) mod fsinfo;
+pub(crate) mod table;
+
+#[cfg(test)]
+mod tests;
+
+/// Maximum number of disk sectors read or written with a single request.
+const MAX_SECTORS_PER_REQUEST: u64 = 0x80;
+
+/// Strong pointer to a locked [`FatFs`] structure.
+///
+/// The [`FatFs`] structure will remain allocated for as long as the filesystem is mounted.
+pub(super) type LockedFatFs = Arc<RwLock<FatFs>>;
+
+/// Internal representation of a `FAT` filesystem.
+///
+/// Holds the layout of the volume, read from its boot sector, a cache of the allocation table and, for `FAT32`
+/// volumes, the content of the `FSInfo` sector.
+///
+/// This structure can only be accessed through a smart [`Arc`] pointer, the underlying allocation is guaranteed to
+/// remain valid while the filesystem is mounted.
+#[derive(Debug)]
+pub(crate) struct FatFs {
+    drive_id: AtaDeviceIdentifier,
+    partition_id: usize,
+    start_lba: u64,
+
+    /// Layout of the volume.
+    pub(crate) bpb: BiosParameterBlock,
+
+    fat_cache: RefCell<FatCache>,
+
+    fs_info: RefCell<Option<FsInfo>>,
+
+    fs_ptr: Weak<RwLock<Self>>,
+}
+
+impl FatFs {
+    /// Reads `count` sectors of the volume, starting from `sector`.
+    ///
+    /// Sectors are the ones of the filesystem (see [`BiosParameterBlock::bytes_per_sector`]), which may be larger than
+    /// the logical sectors of the disk.
+    ///
+    /// # Errors
+    ///
+    /// May return any variant of [`IOError`] in case of a failure while attempting to read from disk.
+    pub(crate) fn read_sectors(&self, sector: u32, count: u32) -> IOResult<Vec<u8>> {
+        let drive = get_sata_drive(self.drive_id).ok_or(IOError::InvalidDevice)?;
+        let lba_per_sector = u64::from(self.bpb.bytes_per_sector) / drive.logical_sector_size();
+
+        let mut lba = self.start_lba + u64::from(sector) * lba_per_sector;
+        let end_lba = lba + u64::from(count) * lba_per_sector;
+        let mut data = Vec::new();
+
+        while lba < end_lba {
+            let lba_count = (end_lba - lba).min(MAX_SECTORS_PER_REQUEST);
+            let read_req = drive
+                .read(
+                    lba,
+                    u16::try_from(lba_count).expect("invalid sectors count"),
+                )
+                .complete();
+
+            data.extend_from_slice(&read_req.data.ok_or(IOError::Unknown)?);
+            lba += lba_count;
+        }
+
+        Ok(data)
+    }
+
+    /// Writes `data` to the volume, starting from `sector`.
+    ///
+    /// `data` must contain a whole number of sectors.
+    ///
+    /// # Errors
+    ///
+    /// May return any variant of [`IOError`] in case of a failure while attempting to write to disk.
+    pub(crate) fn write_sectors(&self, sector: u32, data: &[u8]) -> CanFail<IOError> {
+        let drive = get_sata_drive(self.drive_id).ok_or(IOError::InvalidDevice)?;
+        let lba_size = usize::try_from(drive.logical_sector_size()).expect("invalid sector size");
+        let lba_per_sector = u64::from(self.bpb.bytes_per_sector) / drive.logical_sector_size();
+
+        let mut lba = self.start_lba + u64::from(sector) * lba_per_sector;
+
+        for chunk in data.chunks(
+            lba_size * usize::try_from(MAX_SECTORS_PER_REQUEST).expect("invalid sectors count"),
+        ) {
+            let lba_count = chunk.len() / lba_size;
+            let result = drive
+                .write(
+                    lba,
+                    u16::try_from(lba_count).expect("invalid sectors count"),
+                    chunk.to_vec(),
+                )
+                .complete();
+
+            if !result.is_success() {
+                return Err(IOError::Unknown);
+            }
+
+            lba += u64::try_from(lba_count).expect("invalid sectors count");
+        }
+
+        Ok(())
+    }
+
+    /// Returns the entry of the allocation table associated to `cluster`.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`IOError::InvalidCommand`] if `cluster` is not a valid data cluster. May return any other variant of
+    /// [`IOError`] in case of a failure while attempting to read from disk.
+    pub(crate) fn fat_entry(&self, cluster: u32) -> IOResult<FatEntry> {
+        if !self.bpb.is_valid_cluster(cluster) {
+            return Err(IOError::InvalidCommand);
+        }
+
+        let fat_type = self.bpb.fat_type;
+        let bytes = self.read_fat_bytes(fat_entry_offset(fat_type, cluster))?;
+
+        Ok(FatEntry::from_raw(
+            fat_type,
+            fat_entry_value(fat_type, cluster, &bytes),
+        ))
+    }
+
+    /// Updates the entry of the allocation table associated to `cluster`, in every copy of the FAT.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`IOError::InvalidCommand`] if `cluster` is not a valid data cluster. May return any other variant of
+    /// [`IOError`] in case of a failure while attempting to read from or write to disk.
+    pub(crate) fn set_fat_entry(&self, cluster: u32, entry: FatEntry) -> CanFail<IOError> {
+        if !self.bpb.is_valid_cluster(cluster) {
+            return Err(IOError::InvalidCommand);
+        }
+
+        let fat_type = self.bpb.fat_type;
+        let offset = fat_entry_offset(fat_type, cluster);
+        let mut bytes = self.read_fat_bytes(offset)?;
+
+        store_fat_entry_value(fat_type, cluster, &mut bytes, entry.to_raw(fat_type));
+
+        let fat_start = self.bpb.fat_start(self.bpb.active_fat.unwrap_or(0));
+        let entry_size = u32::try_from(self.fat_entry_size()).expect("invalid entry size");
+        let first_sector = offset / self.bpb.bytes_per_sector;
+        let last_sector = (offset + entry_size - 1) / self.bpb.bytes_per_sector;
+
+        for sector in first_sector..=last_sector {
+            let cached = self.fat_cache.borrow().get(sector).map(<[u8]>::to_vec);
+            let mut data = match cached {
+                Some(data) => data,
+                None => self.read_sectors(fat_start + sector, 1)?,
+            };
+
+            for (byte_offset, byte) in (offset..offset + entry_size).zip(bytes) {
+                if byte_offset / self.bpb.bytes_per_sector == sector {
+                    data[usize::try_from(byte_offset % self.bpb.bytes_per_sector)
+                        .expect("invalid sector offset")] = byte;
+                }
+            }
+
+            // only the active FAT is used if mirroring is disabled
+            for fat_index in (0..self.bpb.fats_count).filter(|&fat_index| {
+                self.bpb
+                    .active_fat
+                    .is_none_or(|active_fat| active_fat == fat_index)
+            }) {
+                self.write_sectors(self.bpb.fat_start(fat_index) + sector, &data)?;
+            }
+
+            self.fat_cache.borrow_mut().insert(sector, data);
+        }
+
+        Ok(())
+    }
+
+    /// Returns the size of an entry of the allocation table, in bytes (rounded up for `FAT12`).
+    fn fat_entry_size(&self) -> usize {
+        match self.bpb.fat_type {
+            FatType::Fat12 | FatType::Fat16 => 2,
+            FatType::Fat32 => 4,
+        }
+    }
+
+    /// Reads the bytes of the allocation table from `offset`, enough to contain a whole entry.
+    ///
+    /// `FAT12` entries may straddle two sectors, so the entry is read byte by byte, through the FAT cache.
+    fn read_fat_bytes(&self, offset: u32) -> IOResult<[u8; 4]> {
+        let fat_start = self.bpb.fat_start(self.bpb.active_fat.unwrap_or(0));
+        let mut fat_cache = self.fat_cache.borrow_mut();
+        let mut bytes = [0u8; 4];
+
+        for (idx, byte) in bytes.iter_mut().take(self.fat_entry_size()).enumerate() {
+            let byte_offset = offset + u32::try_from(idx).expect("invalid entry offset");
+            let sector = byte_offset / self.bpb.bytes_per_sector;
+            let sector_offset = usize::try_from(byte_offset % self.bpb.bytes_per_sector)
+                .expect("invalid sector offset");
+
+            if fat_cache.get(sector).is_none() {
+                fat_cache.insert(sector, self.read_sectors(fat_start + sector, 1)?);
+            }
+
+            *byte = *fat_cache
+                .get(sector)
+                .and_then(|data| data.get(sector_offset))
+                .ok_or(IOError::Unknown)?;
+        }
+
+        Ok(bytes)
+    }
+
+    /// Returns the clusters of the chain starting at `first_cluster`, in order.
+    ///
+    /// An empty chain is returned if `first_cluster` is 0, which is the case for empty files.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`IOError::Unknown`] if the chain is corrupted (free or bad clusters, or loops). May return any other
+    /// variant of [`IOError`] in case of a failure while attempting to read from disk.
+    pub(crate) fn cluster_chain(&self, first_cluster: u32) -> IOResult<Vec<u32>> {
+        let mut clusters = Vec::new();
+
+        if first_cluster == 0 {
+            return Ok(clusters);
+        }
+
+        let mut cluster = first_cluster;
+
+        loop {
+            // a chain cannot be longer than the volume, otherwise it contains a loop
+            if clusters.len()
+                > usize::try_from(self.bpb.clusters_count()).expect("invalid clusters count")
+            {
+                return Err(IOError::Unknown);
+            }
+
+            clusters.push(cluster);
+
+            match self.fat_entry(cluster)? {
+                FatEntry::Next(next) => cluster = next,
+                FatEntry::EndOfChain => return Ok(clusters),
+                FatEntry::Free | FatEntry::Bad | FatEntry::Reserved => {
+                    return Err(IOError::Unknown)
+                }
+            }
+        }
+    }
+
+    /// Returns the size of a cluster, in bytes.
+    pub(crate) fn cluster_size(&self) -> usize {
+        usize::try_from(self.bpb.cluster_size()).expect("invalid cluster size")
+    }
+
+    /// Reads `count` contiguous clusters, starting from `cluster`.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`IOError::InvalidCommand`] if the clusters are not valid data clusters. May return any other variant of
+    /// [`IOError`] in case of a failure while attempting to read from disk.
+    pub(crate) fn read_clusters(&self, cluster: u32, count: u32) -> IOResult<Vec<u8>> {
+        if count == 0 {
+            return Ok(Vec::new());
+        }
+
+        if !self.bpb.is_valid_cluster(cluster) || !self.bpb.is_valid_cluster(cluster + count - 1) {
+            return Err(IOError::InvalidCommand);
+        }
+
+        self.read_sectors(
+            self.bpb.cluster_start(cluster),
+            count * self.bpb.sectors_per_cluster,
+        )
+    }
+
+    /// Reads every cluster of `clusters`, in order.
+    ///
+    /// Contiguous clusters are read with a single disk request.
+    ///
+    /// # Errors
+    ///
+    /// May return any variant of [`IOError`] in case of a failure while attempting to read from disk.
+    pub(crate) fn read_chain(&self, clusters: &[u32]) -> IOResult<Vec<u8>> {
+        let mut data = Vec::new();
+        let mut remaining = clusters;
+
+        while let Some(&first) = remaining.first() {
+            let run = remaining
+                .iter()
+                .zip(first..)
+                .take_while(|(&cluster, expected)| cluster == *expected)
+                .count();
+
+            data.extend_from_slice(
+                &self.read_clusters(first, u32::try_from(run).expect("invalid clusters count"))?,
+            );
+            remaining = &remaining[run..];
+        }
+
+        Ok(data)
+    }
+
+    /// Reads the fixed root directory region of `FAT12` and `FAT16` volumes.
+    ///
+    /// # Errors
+    ///
+    /// May return any variant of [`IOError`] in case of a failure while attempting to read from disk.
+    pub(crate) fn read_root_dir_region(&self) -> IOResult<Vec<u8>> {
+        self.read_sectors(self.bpb.root_dir_start(), self.bpb.root_dir_sectors())
+    }
+
+    /// Allocates `count` free clusters, filled with zeros, and chains them together.
+    ///
+    /// The new chain is appended to the chain ending with `last_cluster`, if any. Free clusters are looked up from
+    /// the cluster following `last_cluster`, or from the hint stored in the `FSInfo` sector, so that files tend to
+    /// remain contiguous.
+    ///
+    /// Returns the allocated clusters, in order.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`IOError::NoSpace`] if there are not enough free clusters left, in which case nothing is allocated.
+    /// May return any other variant of [`IOError`] in case of a failure while attempting to read from or write to
+    /// disk.
+    pub(crate) fn allocate_clusters(
+        &self,
+        count: u32,
+        last_cluster: Option<u32>,
+    ) -> IOResult<Vec<u32>> {
+        let clusters_count = self.bpb.clusters_count();
+        let fs_info = *self.fs_info.borrow();
+
+        if count == 0 {
+            return Ok(Vec::new());
+        }
+
+        if fs_info.is_some_and(|info| info.free_count != fsinfo::UNKNOWN && info.free_count < count)
+        {
+            return Err(IOError::NoSpace);
+        }
+
+        let hint = last_cluster
+            .map(|cluster| cluster + 1)
+            .or(fs_info.map(|info| info.next_free))
+            .filter(|&cluster| self.bpb.is_valid_cluster(cluster))
+            .unwrap_or(2);
+
+        let mut clusters = Vec::new();
+
+        for idx in 0..clusters_count {
+            let cluster = 2 + (hint - 2 + idx) % clusters_count;
+
+            if self.fat_entry(cluster)? == FatEntry::Free {
+                clusters.push(cluster);
+
+                if clusters.len() == usize::try_from(count).expect("invalid clusters count") {
+                    break;
+                }
+            }
+        }
+
+        if clusters.len() < usize::try_from(count).expect("invalid clusters count") {
+            return Err(IOError::NoSpace);
+        }
+
+        let zeros = alloc::vec![0u8; self.cluster_size()];
+
+        for (idx, &cluster) in clusters.iter().enumerate() {
+            self.write_sectors(self.bpb.cluster_start(cluster), &zeros)?;
+
+            let entry = clusters
+                .get(idx + 1)
+                .map_or(FatEntry::EndOfChain, |&next| FatEntry::Next(next));
+            self.set_fat_entry(cluster, entry)?;
+        }
+
+        // the new clusters are only linked to the existing chain once they are all allocated
+        if let Some(last_cluster) = last_cluster {
+            self.set_fat_entry(last_cluster, FatEntry::Next(clusters[0]))?;
+        }
+
+        self.update_fs_info(|info| {
+            if info.free_count != fsinfo::UNKNOWN {
+                info.free_count = info.free_count.saturating_sub(count);
+            }
+
+            info.next_free = *clusters.last().expect("no cluster allocated");
+        })?;
+
+        Ok(clusters)
+    }
+
+    /// Marks every cluster of `clusters` as free.
+    ///
+    /// # Errors
+    ///
+    /// May return any variant of [`IOError`] in case of a failure while attempting to read from or write to disk.
+    pub(crate) fn free_clusters(&self, clusters: &[u32]) -> CanFail<IOError> {
+        for &cluster in clusters {
+            self.set_fat_entry(cluster, FatEntry::Free)?;
+        }
+
+        self.update_fs_info(|info| {
+            if info.free_count != fsinfo::UNKNOWN {
+                info.free_count = (info.free_count
+                    + u32::try_from(clusters.len()).expect("invalid clusters count"))
+                .min(self.bpb.clusters_count());
+            }
+        })
+    }
+
+    /// Updates the content of the `FSInfo` sector, if the volume has a valid one.
+    fn update_fs_info(&self, update: impl FnOnce(&mut FsInfo)) -> CanFail<IOError> {
+        let mut fs_info = self.fs_info.borrow_mut();
+
+        let Some(info) = fs_info.as_mut() else {
+            return Ok(());
+        };
+
+        update(info);
+
+        let mut sector = self.read_sectors(self.bpb.fs_info_sector, 1)?;
+        info.store(&mut sector);
+
+        self.write_sectors(self.bpb.fs_info_sector, &sector)
+    }
+
+    /// Reads the directory entry stored at `location`.
+    ///
+    /// # Errors
+    ///
+    /// May return any variant of [`IOError`] in case of a failure while attempting to read from disk.
+    pub(crate) fn read_dir_slot(&self, location: EntryLocation) -> IOResult<[u8; 32]> {
+        let sector = self.read_sectors(location.sector, 1)?;
+
+        sector
+            .get(location.offset..location.offset + 32)
+            .and_then(|slot| slot.try_into().ok())
+            .ok_or(IOError::Unknown)
+    }
+
+    /// Overwrites the directory entry stored at `location`.
+    ///
+    /// # Errors
+    ///
+    /// May return any variant of [`IOError`] in case of a failure while attempting to read from or write to disk.
+    pub(crate) fn write_dir_slot(
+        &self,
+        location: EntryLocation,
+        slot: &[u8; 32],
+    ) -> CanFail<IOError> {
+        let mut sector = self.read_sectors(location.sector, 1)?;
+
+        sector
+            .get_mut(location.offset..location.offset + 32)
+            .ok_or(IOError::Unknown)?
+            .copy_from_slice(slot);
+
+        self.write_sectors(location.sector, &sector)
+    }
+
+    /// Returns the root directory of this filesystem.
+    ///
+    /// # Errors
+    ///
+    /// In case of any I/O error, a generic error will be returned. An error may mean that the filesystem
+    /// is corrupted.
+    pub(crate) fn root_dir(&self) -> IOResult<Directory> {
+        Ok(Box::new(GenericFatDirectory {
+            dir: self.root_fat_dir()?,
+        }))
+    }
+
+    /// Opens the regular file located at `path`, relative to the root directory of this filesystem.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`IOError::NotFound`] if one of the path components does not exist, or is not of the expected type.
+    /// May return any other variant of [`IOError`] in case of a failure while attempting to read from disk.
+    pub(crate) fn open_file(&self, path: &str) -> IOResult<FatFile> {
+        let (parent, name) = split_path(path);
+
+        self.find_dir(parent)?
+            .search(name)
+            .and_then(|entry| entry.as_file())
+            .ok_or(IOError::NotFound)
+    }
+
+    /// Opens the regular file located at `path`, relative to the root directory of this filesystem, and creates it
+    /// (empty) if it does not exist.
+    ///
+    /// Its parent directory must already exist.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`IOError::NotFound`] if the parent directory does not exist, and [`IOError::InvalidCommand`] if `path`
+    /// is a directory, or if its name is invalid. May return any other variant of [`IOError`] in case of a failure
+    /// while attempting to read from or write to disk.
+    pub(crate) fn create_file(&self, path: &str) -> IOResult<FatFile> {
+        let (parent, name) = split_path(path);
+        let mut dir = self.find_dir(parent)?;
+
+        let entry = match dir.search(name) {
+            Some(entry) => entry,
+            None => dir.create_entry(name)?,
+        };
+
+        entry.as_file().ok_or(IOError::InvalidCommand)
+    }
+
+    /// Lists the directory located at `path`, relative to the root directory of this filesystem.
+    ///
+    /// Returns the names of its entries, except `.` and `..`, in the order in which they are stored.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`IOError::NotFound`] if one of the path components does not exist, or is not a directory.
+    /// May return any other variant of [`IOError`] in case of a failure while attempting to read from disk.
+    pub(crate) fn read_dir(&self, path: &str) -> IOResult<Vec<String>> {
+        Ok(self
+            .find_dir(path)?
+            .map(|entry| entry.name)
+            .filter(|name| name != "." && name != "..")
+            .collect())
+    }
+
+    /// Loads the directory located at `path`, relative to the root directory of this filesystem.
+    fn find_dir(&self, path: &str) -> IOResult<FatDirectory> {
+        let mut dir = self.root_fat_dir()?;
+
+        for component in path.split('/').filter(|component| !component.is_empty()) {
+            dir = dir
+                .search(component)
+                .and_then(|entry| entry.as_directory())
+                .ok_or(IOError::NotFound)?
+                .dir;
+        }
+
+        Ok(dir)
+    }
+
+    fn root_fat_dir(&self) -> IOResult<FatDirectory> {
+        FatDirectory::root(self.fs_ptr.upgrade().ok_or(IOError::Unknown)?)
+    }
+}
+
+impl Fs for FatFs {
+    fn mount(
+        drive_id: AtaDeviceIdentifier,
+        partition_id: usize,
+        partition_data: u64,
+    ) -> Result<LockedFatFs, MountError> {
+        let drive = get_sata_drive(drive_id).ok_or(MountError::IOError)?;
+        let boot_sector = drive
+            .read(partition_data, 1)
+            .complete()
+            .data
+            .ok_or(MountError::IOError)?;
+
+        let bpb = BiosParameterBlock::parse(&boot_sector).ok_or(MountError::BadSuperblock)?;
+
+        if u64::from(bpb.bytes_per_sector) % drive.logical_sector_size() != 0 {
+            return Err(MountError::BadSuperblock);
+        }
+
+        info!(
+            "fat-fs",
+            "mounted {} filesystem on drive {drive_id} partition {partition_id}",
+            bpb.fat_type.name()
+        );
+
+        // the free clusters count is only maintained if the `FSInfo` sector is valid
+        let fs_info = if bpb.fat_type == FatType::Fat32 && bpb.fs_info_sector != 0 {
+            let lba_per_sector = u64::from(bpb.bytes_per_sector) / drive.logical_sector_size();
+            let sector = drive
+                .read(
+                    partition_data + u64::from(bpb.fs_info_sector) * lba_per_sector,
+                    u16::try_from(lba_per_sector).expect("invalid sectors count"),
+                )
+                .complete()
+                .data
+                .ok_or(MountError::IOError)?;
+
+            FsInfo::parse(&sector)
+        } else {
+            None
+        };
+
+        info!(
+            "fat-fs",
+            "label = {}    volume_id = {:#x}    clusters_count = {}    cluster_size = {}",
+            bpb.volume_label.map_or_else(String::new, |label| {
+                String::from(String::from_utf8_lossy(&label).trim_end())
+            }),
+            bpb.volume_id.unwrap_or_default(),
+            bpb.clusters_count(),
+            bpb.cluster_size()
+        );
+
+        let fs = Arc::new_cyclic(|ptr| {
+            RwLock::new(FatFs {
+                drive_id,
+                partition_id,
+                start_lba: partition_data,
+                bpb,
+                fat_cache: RefCell::new(FatCache::default()),
+                fs_info: RefCell::new(fs_info),
+                fs_ptr: ptr.clone(),
+            })
+        });
+
+        Ok(fs)
+    }
+
+    fn identify(drive_id: AtaDeviceIdentifier, partition_data: u64) -> IOResult<bool> {
+        let drive = get_sata_drive(drive_id).ok_or(IOError::InvalidDevice)?;
+        let boot_sector = drive
+            .read(partition_data, 1)
+            .complete()
+            .data
+            .ok_or(IOError::Unknown)?;
+
+        Ok(BiosParameterBlock::parse(&boot_sector).is_some())
+    }
+}
+
+unsafe impl Sync for FatFs {}
+
+/// Splits `path` into the path of its parent directory, and the name of its last component.
+fn split_path(path: &str) -> (&str, &str) {
+    path.trim_end_matches('/')
+        .rsplit_once('/')
+        .unwrap_or(("", path.trim_end_matches('/')))
+}
@@ -0,0 +1,156 @@
+//! File Allocation Table (FAT) related structures.
+//!
+//! The FAT has one entry per data cluster, which either marks the cluster as free, or links it to
+//! the next cluster of the file (or directory) it belongs to. Files are therefore stored as chains
+//! of clusters, starting from the cluster referenced in their directory entry.
+
+use hashbrown::HashMap;
+
+use alloc::vec::Vec;
+
+use crate::fs::fat::bpb::FatType;
+
+/// Maximum number of FAT sectors kept in a [`FatCache`].
+const FAT_CACHE_MAX_SECTORS: usize = 64;
+
+/// Value of a FAT entry.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum FatEntry {
+    /// The cluster is not allocated.
+    Free,
+
+    /// The cluster is followed by the given one.
+    Next(u32),
+
+    /// The cluster is the last one of its chain.
+    EndOfChain,
+
+    /// The cluster contains a bad sector, and must not be used.
+    Bad,
+
+    /// The entry holds a reserved value.
+    Reserved,
+}
+
+impl FatEntry {
+    /// Decodes a raw FAT entry, of the given FAT type.
+    pub(crate) fn from_raw(fat_type: FatType, raw: u32) -> Self {
+        let (value, bad) = match fat_type {
+            FatType::Fat12 => (raw & 0xFFF, 0xFF7),
+            FatType::Fat16 => (raw & 0xFFFF, 0xFFF7),
+            FatType::Fat32 => (raw & 0x0FFF_FFFF, 0x0FFF_FFF7),
+        };
+
+        match value {
+            0 => Self::Free,
+            1 => Self::Reserved,
+            value if value == bad => Self::Bad,
+            value if value > bad => Self::EndOfChain,
+            value => Self::Next(value),
+        }
+    }
+
+    /// Encodes this entry, for the given FAT type.
+    pub(crate) fn to_raw(self, fat_type: FatType) -> u32 {
+        let (mask, bad) = match fat_type {
+            FatType::Fat12 => (0xFFF, 0xFF7),
+            FatType::Fat16 => (0xFFFF, 0xFFF7),
+            FatType::Fat32 => (0x0FFF_FFFF, 0x0FFF_FFF7),
+        };
+
+        match self {
+            Self::Free => 0,
+            Self::Reserved => 1,
+            Self::Bad => bad,
+            Self::EndOfChain => mask,
+            Self::Next(cluster) => cluster & mask,
+        }
+    }
+}
+
+/// Returns the byte offset of the entry of `cluster` in the FAT.
+pub(crate) fn fat_entry_offset(fat_type: FatType, cluster: u32) -> u32 {
+    match fat_type {
+        FatType::Fat12 => cluster + cluster / 2,
+        FatType::Fat16 => cluster * 2,
+        FatType::Fat32 => cluster * 4,
+    }
+}
+
+/// Extracts the raw value of the entry of `cluster` from the bytes stored at its offset (see
+/// [`fat_entry_offset`]).
+///
+/// `bytes` must contain 2 bytes for `FAT12` and `FAT16` volumes, and 4 for `FAT32` volumes.
+pub(crate) fn fat_entry_value(fat_type: FatType, cluster: u32, bytes: &[u8]) -> u32 {
+    match fat_type {
+        FatType::Fat12 => {
+            let value = u32::from(u16::from_le_bytes([bytes[0], bytes[1]]));
+
+            if cluster % 2 == 0 {
+                value & 0xFFF
+            } else {
+                value >> 4
+            }
+        }
+        FatType::Fat16 => u32::from(u16::from_le_bytes([bytes[0], bytes[1]])),
+        FatType::Fat32 => u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]),
+    }
+}
+
+/// Stores the raw value of the entry of `cluster` in the bytes stored at its offset (see
+/// [`fat_entry_offset`]).
+///
+/// The bits of these bytes which do not belong to the entry are preserved: the other half-byte of
+/// `FAT12` entries, and the 4 reserved high bits of `FAT32` entries.
+pub(crate) fn store_fat_entry_value(fat_type: FatType, cluster: u32, bytes: &mut [u8], value: u32) {
+    match fat_type {
+        FatType::Fat12 => {
+            let current = u16::from_le_bytes([bytes[0], bytes[1]]);
+            let value = u16::try_from(value & 0xFFF).expect("invalid FAT12 entry");
+
+            let updated = if cluster % 2 == 0 {
+                (current & 0xF000) | value
+            } else {
+                (current & 0x000F) | (value << 4)
+            };
+
+            bytes[..2].copy_from_slice(&updated.to_le_bytes());
+        }
+        FatType::Fat16 => {
+            let value = u16::try_from(value & 0xFFFF).expect("invalid FAT16 entry");
+            bytes[..2].copy_from_slice(&value.to_le_bytes());
+        }
+        FatType::Fat32 => {
+            let current = u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]);
+            let updated = (current & 0xF000_0000) | (value & 0x0FFF_FFFF);
+
+            bytes[..4].copy_from_slice(&updated.to_le_bytes());
+        }
+    }
+}
+
+/// Cache of the FAT sectors recently read.
+///
+/// Walking a cluster chain reads many entries from the same FAT sectors, which are kept in memory.
+/// Once full, the cache is simply emptied. Updated entries are written to the cached sectors as
+/// well as to the disk, so that the cache never holds stale data.
+#[derive(Debug, Default)]
+pub(crate) struct FatCache {
+    sectors: HashMap<u32, Vec<u8>>,
+}
+
+impl FatCache {
+    /// Returns a cached FAT sector, from its index relative to the start of the FAT.
+    pub(crate) fn get(&self, sector: u32) -> Option<&[u8]> {
+        self.sectors.get(&sector).map(Vec::as_slice)
+    }
+
+    /// Inserts a FAT sector in the cache, replacing any previous version of it.
+    pub(crate) fn insert(&mut self, sector: u32, data: Vec<u8>) {
+        if self.sectors.len() >= FAT_CACHE_MAX_SECTORS {
+            self.sectors.clear();
+        }
+
+        self.sectors.insert(sector, data);
+    }
+}
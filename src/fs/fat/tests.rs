@@ -0,0 +1,339 @@
+//! Tests of the write path of the `FAT` filesystem, run against volumes formatted in memory.
+//!
+//! After each test, the volume is checked like `fsck.fat` would: the copies of the FAT must be
+//! identical, every chain must match the size of its directory entry, and no cluster may be shared
+//! or lost.
+
+use alloc::vec::Vec;
+
+use crate::drivers::generics::mem_disk::MemoryDisk;
+use crate::fs::fat::bpb::{BiosParameterBlock, FatType, DIR_ENTRY_SIZE};
+use crate::fs::fat::fsinfo::FsInfo;
+use crate::fs::fat::table::{fat_entry_offset, fat_entry_value, FatEntry};
+use crate::fs::partitions::mbr::PartitionType;
+use crate::fs::partitions::Partition;
+use crate::fs::FsFile;
+
+const SECTOR_SIZE: usize = 512;
+
+/// Attribute of the directory entries holding a part of a long file name.
+const LONG_NAME_ATTR: u8 = 0x0F;
+
+/// Attribute of directory entries.
+const DIRECTORY_ATTR: u8 = 0x10;
+
+/// Formats a volume of `total_sectors` sectors, with 2 FATs.
+///
+/// `FAT16` volumes have a fixed root directory of 512 entries, `FAT32` ones a root directory
+/// starting at cluster 2, and an `FSInfo` sector.
+fn format(fat_type: FatType, total_sectors: u32, sectors_per_cluster: u8) -> Vec<u8> {
+    let is_fat32 = fat_type == FatType::Fat32;
+    let (reserved_sectors, root_entries, entry_bits) = if is_fat32 {
+        (32u16, 0u16, 32)
+    } else {
+        (1, 512, 16)
+    };
+    let root_dir_sectors = u32::from(root_entries) * DIR_ENTRY_SIZE / 512;
+
+    // the FAT must describe every cluster of the data region, which shrinks as the FAT grows
+    let mut fat_size = 1;
+    loop {
+        let data_start = u32::from(reserved_sectors) + 2 * fat_size + root_dir_sectors;
+        let clusters_count = (total_sectors - data_start) / u32::from(sectors_per_cluster);
+        let needed = ((clusters_count + 2) * entry_bits / 8).div_ceil(512);
+
+        if needed <= fat_size {
+            break;
+        }
+        fat_size = needed;
+    }
+
+    let mut image = alloc::vec![0u8; usize::try_from(total_sectors).unwrap() * SECTOR_SIZE];
+    let boot = &mut image[..SECTOR_SIZE];
+
+    boot[..3].copy_from_slice(&[0xEB, 0x3C, 0x90]);
+    boot[3..11].copy_from_slice(b"FZBOOT  ");
+    boot[11..13].copy_from_slice(&512u16.to_le_bytes());
+    boot[13] = sectors_per_cluster;
+    boot[14..16].copy_from_slice(&reserved_sectors.to_le_bytes());
+    boot[16] = 2;
+    boot[17..19].copy_from_slice(&root_entries.to_le_bytes());
+    boot[21] = 0xF8;
+    boot[32..36].copy_from_slice(&total_sectors.to_le_bytes());
+
+    let ebr_offset = if is_fat32 {
+        boot[36..40].copy_from_slice(&fat_size.to_le_bytes());
+        boot[44..48].copy_from_slice(&2u32.to_le_bytes());
+        boot[48..50].copy_from_slice(&1u16.to_le_bytes());
+        64
+    } else {
+        boot[22..24].copy_from_slice(&u16::try_from(fat_size).unwrap().to_le_bytes());
+        36
+    };
+
+    boot[ebr_offset + 2] = 0x29;
+    boot[ebr_offset + 3..ebr_offset + 7].copy_from_slice(&0x1234_5678u32.to_le_bytes());
+    boot[ebr_offset + 7..ebr_offset + 18].copy_from_slice(b"FZBOOT     ");
+    boot[510..512].copy_from_slice(&[0x55, 0xAA]);
+
+    let bpb = BiosParameterBlock::parse(&image[..SECTOR_SIZE]).expect("invalid volume");
+    assert_eq!(bpb.fat_type, fat_type);
+
+    for fat in 0..2 {
+        let start = usize::try_from(bpb.fat_start(fat)).unwrap() * SECTOR_SIZE;
+
+        if is_fat32 {
+            // media descriptor, reserved entry and root directory
+            image[start..start + 12].copy_from_slice(&[
+                0xF8, 0xFF, 0xFF, 0x0F, 0xFF, 0xFF, 0xFF, 0x0F, 0xFF, 0xFF, 0xFF, 0x0F,
+            ]);
+        } else {
+            image[start..start + 4].copy_from_slice(&[0xF8, 0xFF, 0xFF, 0xFF]);
+        }
+    }
+
+    if is_fat32 {
+        let fs_info = &mut image[SECTOR_SIZE..2 * SECTOR_SIZE];
+
+        fs_info[..4].copy_from_slice(&0x4161_5252u32.to_le_bytes());
+        fs_info[484..488].copy_from_slice(&0x6141_7272u32.to_le_bytes());
+        fs_info[488..492].copy_from_slice(&(bpb.clusters_count() - 1).to_le_bytes());
+        fs_info[492..496].copy_from_slice(&3u32.to_le_bytes());
+        fs_info[508..512].copy_from_slice(&0xAA55_0000u32.to_le_bytes());
+    }
+
+    image
+}
+
+/// Mounts a `FAT` volume on a new [`MemoryDisk`].
+fn mount(fat_type: FatType, image: &[u8]) -> (alloc::sync::Arc<MemoryDisk>, Partition) {
+    let part_type = match fat_type {
+        FatType::Fat32 => PartitionType::Fat32LBA,
+        FatType::Fat12 | FatType::Fat16 => PartitionType::DOS331Fat16,
+    };
+
+    MemoryDisk::with_partition(part_type, image)
+}
+
+/// Returns `size` bytes of data which do not repeat on cluster boundaries.
+fn pattern(size: usize) -> Vec<u8> {
+    (0..size)
+        .map(|idx| u8::try_from(idx % 251).unwrap())
+        .collect()
+}
+
+/// View of a `FAT` volume, used to check its consistency.
+struct Volume<'a> {
+    image: &'a [u8],
+    bpb: BiosParameterBlock,
+}
+
+impl<'a> Volume<'a> {
+    fn new(image: &'a [u8]) -> Self {
+        Self {
+            image,
+            bpb: BiosParameterBlock::parse(&image[..SECTOR_SIZE]).expect("invalid volume"),
+        }
+    }
+
+    fn sectors(&self, start: u32, count: u32) -> &'a [u8] {
+        let start = usize::try_from(start).unwrap() * SECTOR_SIZE;
+        &self.image[start..start + usize::try_from(count).unwrap() * SECTOR_SIZE]
+    }
+
+    fn fat(&self, index: u32) -> &'a [u8] {
+        self.sectors(self.bpb.fat_start(index), self.bpb.fat_size)
+    }
+
+    fn entry(&self, cluster: u32) -> FatEntry {
+        let fat_type = self.bpb.fat_type;
+        let offset = usize::try_from(fat_entry_offset(fat_type, cluster)).unwrap();
+
+        FatEntry::from_raw(
+            fat_type,
+            fat_entry_value(fat_type, cluster, &self.fat(0)[offset..]),
+        )
+    }
+
+    /// Follows the chain starting at `first`, and returns its clusters.
+    fn chain(&self, first: u32) -> Vec<u32> {
+        let mut chain = alloc::vec![];
+        let mut cluster = first;
+
+        loop {
+            assert!(
+                self.bpb.is_valid_cluster(cluster),
+                "invalid cluster {cluster}"
+            );
+            assert!(
+                !chain.contains(&cluster),
+                "loop in chain of cluster {first}"
+            );
+            chain.push(cluster);
+
+            match self.entry(cluster) {
+                FatEntry::Next(next) => cluster = next,
+                FatEntry::EndOfChain => return chain,
+                entry => panic!("cluster {cluster} of a chain is {entry:?}"),
+            }
+        }
+    }
+
+    fn read_chain(&self, clusters: &[u32]) -> Vec<u8> {
+        clusters
+            .iter()
+            .flat_map(|&cluster| {
+                self.sectors(
+                    self.bpb.cluster_start(cluster),
+                    self.bpb.sectors_per_cluster,
+                )
+            })
+            .copied()
+            .collect()
+    }
+
+    /// Returns the content of the root directory, and the clusters it occupies (none for `FAT16`
+    /// volumes).
+    fn root_dir(&self) -> (Vec<u8>, Vec<u32>) {
+        if self.bpb.fat_type == FatType::Fat32 {
+            let clusters = self.chain(self.bpb.root_cluster);
+            (self.read_chain(&clusters), clusters)
+        } else {
+            let root_dir = self.sectors(self.bpb.root_dir_start(), self.bpb.root_dir_sectors());
+            (root_dir.to_vec(), alloc::vec![])
+        }
+    }
+
+    /// Checks the consistency of the volume, and returns the size and the chain of each file of the
+    /// root directory.
+    fn check(&self) -> Vec<(usize, Vec<u32>)> {
+        for fat in 1..self.bpb.fats_count {
+            assert!(self.fat(fat) == self.fat(0), "FAT {fat} differs from FAT 0");
+        }
+
+        let (root_dir, mut used) = self.root_dir();
+        let cluster_size = usize::try_from(self.bpb.cluster_size()).unwrap();
+        let mut files = alloc::vec![];
+
+        for entry in root_dir.chunks_exact(32) {
+            match entry[0] {
+                0x00 => break,
+                0xE5 => continue,
+                _ => (),
+            }
+
+            let attributes = entry[11];
+            if attributes == LONG_NAME_ATTR || attributes & DIRECTORY_ATTR != 0 {
+                continue;
+            }
+
+            let first_cluster = u32::from(u16::from_le_bytes([entry[26], entry[27]]))
+                | (u32::from(u16::from_le_bytes([entry[20], entry[21]])) << 16);
+            let size =
+                usize::try_from(u32::from_le_bytes(entry[28..32].try_into().unwrap())).unwrap();
+
+            let chain = if first_cluster == 0 {
+                alloc::vec![]
+            } else {
+                self.chain(first_cluster)
+            };
+            assert_eq!(
+                chain.len(),
+                size.div_ceil(cluster_size),
+                "chain of a {size} bytes file"
+            );
+
+            for &cluster in &chain {
+                assert!(!used.contains(&cluster), "cluster {cluster} is shared");
+            }
+            used.extend_from_slice(&chain);
+            files.push((size, chain));
+        }
+
+        let allocated = (2..self.bpb.clusters_count() + 2)
+            .filter(|&cluster| self.entry(cluster) != FatEntry::Free)
+            .count();
+        assert_eq!(allocated, used.len(), "lost clusters");
+
+        if self.bpb.fat_type == FatType::Fat32 {
+            let fs_info = FsInfo::parse(self.sectors(self.bpb.fs_info_sector, 1)).unwrap();
+            let free = usize::try_from(self.bpb.clusters_count()).unwrap() - allocated;
+
+            assert_eq!(usize::try_from(fs_info.free_count).unwrap(), free);
+        }
+
+        files
+    }
+}
+
+fn check_write_truncate_append(fat_type: FatType, total_sectors: u32) {
+    let (disk, partition) = mount(fat_type, &format(fat_type, total_sectors, 1));
+    let data = pattern(3 * SECTOR_SIZE + 100);
+
+    partition.write_file("/boot-state.json", &data).unwrap();
+    partition.write_file("/LOG.TXT", b"first boot\n").unwrap();
+    assert_eq!(partition.read_file("/boot-state.json").unwrap(), data);
+
+    let image = disk.image();
+    let files = Volume::new(&image).check();
+    assert_eq!(files.len(), 2);
+    assert_eq!(files[0].0, data.len());
+    assert_eq!(files[0].1.len(), 4);
+    assert_eq!(files[1].0, 11);
+
+    // truncating releases the clusters past the new end of the file
+    partition
+        .create("/boot-state.json")
+        .unwrap()
+        .truncate(SECTOR_SIZE + 1)
+        .unwrap();
+    partition.append_file("/LOG.TXT", b"second boot\n").unwrap();
+
+    let image = disk.image();
+    let files = Volume::new(&image).check();
+    assert_eq!(files[0].0, SECTOR_SIZE + 1);
+    assert_eq!(files[0].1.len(), 2);
+    assert_eq!(files[1].0, 23);
+
+    // appending extends the chain, possibly with the clusters released above
+    partition.append_file("/boot-state.json", &data).unwrap();
+    partition.write_file("/LOG.TXT", b"").unwrap();
+
+    let mut expected = data[..=SECTOR_SIZE].to_vec();
+    expected.extend_from_slice(&data);
+    assert_eq!(partition.read_file("/boot-state.json").unwrap(), expected);
+    assert_eq!(partition.read_file("/LOG.TXT").unwrap(), b"");
+
+    let image = disk.image();
+    let files = Volume::new(&image).check();
+    assert_eq!(files[0].0, expected.len());
+    assert_eq!(files[1], (0, alloc::vec![]));
+    assert_eq!(
+        partition.read_dir("/").unwrap(),
+        ["boot-state.json", "LOG.TXT"]
+    );
+}
+
+#[test]
+fn writes_fat16_volumes() {
+    check_write_truncate_append(FatType::Fat16, 16 * 1024);
+}
+
+#[test]
+fn writes_fat32_volumes() {
+    check_write_truncate_append(FatType::Fat32, 68 * 1024);
+}
+
+#[test]
+fn reports_full_volumes() {
+    let (disk, partition) = mount(FatType::Fat16, &format(FatType::Fat16, 64 * 1024, 4));
+    let free = Volume::new(&disk.image()).bpb.clusters_count() * 4 * 512;
+    let data = pattern(usize::try_from(free).unwrap());
+
+    partition.write_file("/FULL.BIN", &data).unwrap();
+    assert!(partition.write_file("/MORE.BIN", b"more").is_err());
+
+    let image = disk.image();
+    let files = Volume::new(&image).check();
+    assert_eq!(files[0].0, data.len());
+}
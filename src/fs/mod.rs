@@ -1,7 +1,7 @@
 //! File-system related code.
 //!
 //! Contains the implementations of various common file systems, such as `ext4`, `fat12`, `fat16`,
-//! `fat32` as well as common utilities when working with files or directory.
+//! `fat32`, `exfat` as well as common utilities when working with files or directory.
 //!
 //! [`File`] and [`Directory`] are the most general representation of a file and a directory
 //! respectively. They can be used independently of the file system of the partition we are
@@ -17,10 +17,14 @@ use alloc::sync::Arc;
 use alloc::{boxed::Box, string::String, vec::Vec};
 use spin::RwLock;
 
-use crate::errors::{IOError, MountError};
+use crate::errors::{CanFail, IOError, MountError};
+use crate::fs::exfat::LockedExFatFs;
 use crate::fs::ext4::LockedExt4Fs;
+use crate::fs::fat::LockedFatFs;
 
+pub(crate) mod exfat;
 pub(crate) mod ext4;
+pub(crate) mod fat;
 pub mod partitions;
 
 /// Base [`Result`] type for I/O operations, using the corresponding custom error type.
@@ -30,6 +34,8 @@ pub type IOResult<T> = Result<T, IOError>;
 #[derive(Clone)]
 pub(crate) enum PartFS {
     Ext4(Box<LockedExt4Fs>),
+    Fat(Box<LockedFatFs>),
+    ExFat(Box<LockedExFatFs>),
     Unknown,
 }
 
@@ -76,6 +82,10 @@ impl FsFile for Box<dyn FsFile> {
         self.as_mut().read(buf)
     }
 
+    fn write(&mut self, buf: &[u8]) -> IOResult<usize> {
+        self.as_mut().write(buf)
+    }
+
     fn seek(&mut self, pos: Seek) -> usize {
         self.as_mut().seek(pos)
     }
@@ -146,6 +156,52 @@ pub trait FsDirectory: Iterator + Debug {
     /// In case of any I/O error, a generic error will be returned. An error may mean that the file
     /// is corrupted.
     fn size(&self) -> IOResult<usize>;
+
+    /// Opens the regular file named `name` in the directory, and creates it (empty) if it does not
+    /// exist.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`IOError::InvalidCommand`] if the file system does not support writes, which is the
+    /// default, or if `name` is not a valid file name. In case of any I/O error, a generic error
+    /// will be returned.
+    fn create(&mut self, _name: &str) -> IOResult<File> {
+        Err(IOError::InvalidCommand)
+    }
+
+    /// Creates an empty directory named `name` in the directory.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`IOError::InvalidCommand`] if the file system does not support writes, which is the
+    /// default, or if `name` already exists. In case of any I/O error, a generic error will be
+    /// returned.
+    fn mkdir(&mut self, _name: &str) -> IOResult<Directory> {
+        Err(IOError::InvalidCommand)
+    }
+
+    /// Removes the entry named `name` from the directory. Directories must be empty.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`IOError::InvalidCommand`] if the file system does not support writes, which is the
+    /// default, and [`IOError::NotFound`] if there is no entry named `name`. In case of any I/O
+    /// error, a generic error will be returned.
+    fn unlink(&mut self, _name: &str) -> CanFail<IOError> {
+        Err(IOError::InvalidCommand)
+    }
+
+    /// Renames the entry named `old_name` in the directory to `new_name`, replacing the entry named
+    /// `new_name` if it exists.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`IOError::InvalidCommand`] if the file system does not support writes, which is the
+    /// default, and [`IOError::NotFound`] if there is no entry named `old_name`. In case of any I/O
+    /// error, a generic error will be returned.
+    fn rename(&mut self, _old_name: &str, _new_name: &str) -> CanFail<IOError> {
+        Err(IOError::InvalidCommand)
+    }
 }
 
 /// A trait to represent a file-system independent file.
@@ -168,6 +224,19 @@ pub trait FsFile: Debug {
     /// in _some_ situations (such as in a real mode context, with reads being based on int 13h).
     fn read(&mut self, buf: &mut [u8]) -> IOResult<usize>;
 
+    /// Writes the bytes of the specified buffer to the file, starting from the current position of
+    /// the internal cursor, and extends the file if needed.
+    ///
+    /// Returns how many bytes were written in case of success.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`IOError::InvalidCommand`] if the file system does not support writes, which is the
+    /// default. In case of any I/O error, a generic error will be returned.
+    fn write(&mut self, _buf: &[u8]) -> IOResult<usize> {
+        Err(IOError::InvalidCommand)
+    }
+
     /// Changes the position of the file's internal cursor.
     ///
     /// Returns the new offset on the cursor, in bytes.
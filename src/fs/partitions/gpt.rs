@@ -292,6 +292,27 @@ impl GPTPartitionEntry {
         self.partition_guid
     }
 
+    /// Returns this partition's type GUID.
+    ///
+    /// # Examples
+    ///
+    /// Check if the first partition in the table is an EFI system partition.
+    ///
+    /// ```
+    /// let part = load_drive_gpt(drive);
+    /// assert_eq!(part.get_partition_metadata()[0].type_guid(), GPTPartType::EfiSystem.into());
+    /// ```
+    #[must_use]
+    pub fn type_guid(&self) -> u128 {
+        self.type_guid
+    }
+
+    /// Returns the [`GPTPartType`] matching this partition's type GUID.
+    #[must_use]
+    pub fn partition_type(&self) -> GPTPartType {
+        GPTPartType::from(self.type_guid)
+    }
+
     /// Returns this partition's sectors count.
     ///
     /// The _sector count_ is encoded using 32 bits, which limits the maximum partition size to 2TB.
@@ -416,11 +437,48 @@ pub fn crc32_calc(buf: &[u8]) -> u32 {
     !crc_32
 }
 
+/// Parses a GUID from its textual representation (`XXXXXXXX-XXXX-XXXX-XXXX-XXXXXXXXXXXX`).
+///
+/// The GUID is returned with the mixed-endian layout used to store it on disk, so that it can be
+/// compared with the GUIDs found in a [`GPTPartitionEntry`].
+#[must_use]
+pub fn parse_guid(text: &str) -> Option<u128> {
+    let mut fields = text.split('-');
+    let mut next_field = |len: usize| {
+        fields
+            .next()
+            .filter(|f| f.len() == len && f.bytes().all(|b| b.is_ascii_hexdigit()))
+    };
+
+    let time_low = u32::from_str_radix(next_field(8)?, 16).ok()?;
+    let time_mid = u16::from_str_radix(next_field(4)?, 16).ok()?;
+    let time_high = u16::from_str_radix(next_field(4)?, 16).ok()?;
+    let clock_seq = u16::from_str_radix(next_field(4)?, 16).ok()?;
+    let node = u64::from_str_radix(next_field(12)?, 16).ok()?;
+
+    if fields.next().is_some() {
+        return None;
+    }
+
+    let mut bytes = [0u8; 16];
+    bytes[0..4].copy_from_slice(&time_low.to_le_bytes());
+    bytes[4..6].copy_from_slice(&time_mid.to_le_bytes());
+    bytes[6..8].copy_from_slice(&time_high.to_le_bytes());
+    bytes[8..10].copy_from_slice(&clock_seq.to_be_bytes());
+    bytes[10..16].copy_from_slice(&node.to_be_bytes()[2..]);
+
+    Some(u128::from_le_bytes(bytes))
+}
+
 /// Defines usual `GPT Partition Type` field values.
 macro_rules! gpt_part_type {
-    ($([$name: tt, $id: literal]), *) => {
+    ($([$name: tt, $id: literal, $doc: literal]), *) => {
+        /// Known partition type GUIDs, used in GPT partition entries.
+        #[derive(Debug, Clone, Copy, PartialEq, Eq)]
         pub enum GPTPartType {
-            $($name,)*
+            $(#[doc = $doc] $name,)*
+
+            /// Any other partition type.
             Unknown
         }
 
@@ -443,3 +501,118 @@ macro_rules! gpt_part_type {
         }
     };
 }
+
+gpt_part_type!(
+    [
+        EfiSystem,
+        0x3BC9_3EC9_A000_4BBA_11D2_F81F_C12A_7328,
+        "EFI system partition."
+    ],
+    [
+        BiosBoot,
+        0x4946_4564_6565_4E74_6E6F_6449_2168_6148,
+        "BIOS boot partition, used by GRUB on GPT disks."
+    ],
+    [
+        MicrosoftBasicData,
+        0xC799_26B7_B668_C087_4433_B9E5_EBD0_A0A2,
+        "Microsoft basic data partition (FAT, exFAT or NTFS)."
+    ],
+    [
+        LinuxFilesystem,
+        0xE47D_47D8_693D_798E_4772_8483_0FC6_3DAF,
+        "Linux filesystem data."
+    ],
+    [
+        LinuxSwap,
+        0x4F4F_4BC8_3309_E584_43C4_A4AB_0657_FD6D,
+        "Linux swap partition."
+    ],
+    [
+        LinuxLVM,
+        0x28F9_3D2A_8F23_3CA2_44C2_F507_E6D6_D379,
+        "Linux LVM physical volume."
+    ],
+    [
+        LinuxHome,
+        0x15F9_AEE2_140E_44B8_4F13_2EB4_933A_C7E1,
+        "Linux `/home` partition."
+    ],
+    [
+        LinuxRootX86,
+        0x8A45_F0D5_31D1_F79A_41B2_F297_4447_9540,
+        "Linux root partition (x86)."
+    ],
+    [
+        LinuxRootX86_64,
+        0x09B7_84F9_CAFB_E796_4DB1_E8CD_4F68_BCE3,
+        "Linux root partition (x86-64)."
+    ],
+    [
+        LinuxExtendedBoot,
+        0x7271_6FFD_75B2_52A3_4262_59E6_BC13_C2FF,
+        "Extended boot loader partition (`/boot`)."
+    ]
+);
+
+#[cfg(test)]
+mod tests {
+    use super::{parse_guid, GPTPartType};
+
+    #[test]
+    fn parses_known_partition_types() {
+        let types = [
+            (
+                "C12A7328-F81F-11D2-BA4B-00A0C93EC93B",
+                GPTPartType::EfiSystem,
+            ),
+            (
+                "21686148-6449-6E6F-744E-656564454649",
+                GPTPartType::BiosBoot,
+            ),
+            (
+                "0FC63DAF-8483-4772-8E79-3D69D8477DE4",
+                GPTPartType::LinuxFilesystem,
+            ),
+            (
+                "bc13c2ff-59e6-4262-a352-b275fd6f7172",
+                GPTPartType::LinuxExtendedBoot,
+            ),
+        ];
+
+        for (text, part_type) in types {
+            assert_eq!(parse_guid(text), Some(u128::from(part_type)), "{text}");
+        }
+    }
+
+    #[test]
+    fn uses_on_disk_layout() {
+        let guid = parse_guid("00112233-4455-6677-8899-AABBCCDDEEFF").unwrap();
+
+        assert_eq!(
+            guid.to_le_bytes(),
+            [
+                0x33, 0x22, 0x11, 0x00, 0x55, 0x44, 0x77, 0x66, 0x88, 0x99, 0xAA, 0xBB, 0xCC, 0xDD,
+                0xEE, 0xFF
+            ]
+        );
+    }
+
+    #[test]
+    fn rejects_malformed_guids() {
+        let invalid = [
+            "",
+            "C12A7328F81F11D2BA4B00A0C93EC93B",
+            "C12A7328-F81F-11D2-BA4B",
+            "C12A7328-F81F-11D2-BA4B-00A0C93EC93B-00",
+            "C12A732-F81F-11D2-BA4B-00A0C93EC93B",
+            "C12A7328-F81F-11D2-BA4B-00A0C93EC93B0",
+            "C12A7328-F81F-11D2-BA4B-00A0C93EC93G",
+            "+12A7328-F81F-11D2-BA4B-00A0C93EC93B",
+        ];
+
+        for text in invalid {
+            assert_eq!(parse_guid(text), None, "{text}");
+        }
+    }
+}
@@ -273,6 +273,15 @@ impl MBRPartitionEntry {
         Into::<PartitionType>::into(self.part_type)
     }
 
+    /// Returns the raw partition type identifier defined for this partition.
+    ///
+    /// Unlike [`MBRPartitionEntry::partition_type`], identifiers that do not match any known
+    /// [`PartitionType`] are preserved.
+    #[must_use]
+    pub fn partition_type_id(&self) -> u8 {
+        self.part_type
+    }
+
     /// Sets the [`PartitionType`] defined for this partition.
     ///
     /// Should indicate the filesystem contained in this partition.
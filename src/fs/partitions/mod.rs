@@ -2,15 +2,19 @@
 //!
 //! Contains the implementation of the two standards partition scheme, _GPT_ and _MBR_.
 
+use alloc::{boxed::Box, string::String, vec::Vec};
+
 use crate::drivers::ide::AtaDeviceIdentifier;
-use crate::errors::{CanFail, MountError};
+use crate::errors::{CanFail, IOError, MountError};
 use crate::fs::{
+    exfat::ExFatFs,
     ext4::Ext4Fs,
+    fat::FatFs,
     partitions::{
-        gpt::{GPTPartitionEntry, GUIDPartitionTable},
+        gpt::{parse_guid, GPTPartitionEntry, GUIDPartitionTable},
         mbr::{MBRPartitionEntry, MBRPartitionTable},
     },
-    Fs, PartFS,
+    File, Fs, FsFile, IOResult, PartFS, Seek,
 };
 
 pub mod gpt;
@@ -45,48 +49,65 @@ impl Partition {
     pub fn load_fs(&mut self) -> CanFail<MountError> {
         self.fs = match self.metadata {
             PartitionMetadata::MBR(meta) => match meta.partition_type() {
-                mbr::PartitionType::Empty => PartFS::Unknown,
-                mbr::PartitionType::DOSFat12 => todo!(),
-                mbr::PartitionType::XenixRoot => todo!(),
-                mbr::PartitionType::XenixUsr => todo!(),
-                mbr::PartitionType::DOS3Fat16 => todo!(),
-                mbr::PartitionType::Extended => todo!(),
-                mbr::PartitionType::DOS331Fat16 => todo!(),
-                mbr::PartitionType::OS2IFS => todo!(),
-                mbr::PartitionType::NTFS => todo!(),
-                mbr::PartitionType::Fat32 => todo!(),
-                mbr::PartitionType::Fat32LBA => todo!(),
-                mbr::PartitionType::EXFAT => todo!(),
-                mbr::PartitionType::DOSFat16LBA => todo!(),
-                mbr::PartitionType::ExtendedLBA => todo!(),
-                mbr::PartitionType::LinuxSwap => todo!(),
                 mbr::PartitionType::LinuxNative => {
                     if Ext4Fs::identify(self.drive_id, meta.start_lba() as u64)
                         .map_err(|_| MountError::IOError)?
                     {
                         let fs = Ext4Fs::mount(self.drive_id, self.id, meta.start_lba() as u64)?;
-                        PartFS::Ext4(alloc::boxed::Box::new(fs))
+                        PartFS::Ext4(Box::new(fs))
+                    } else {
+                        PartFS::Unknown
+                    }
+                }
+
+                mbr::PartitionType::DOSFat12
+                | mbr::PartitionType::DOS3Fat16
+                | mbr::PartitionType::DOS331Fat16
+                | mbr::PartitionType::Fat32
+                | mbr::PartitionType::Fat32LBA
+                | mbr::PartitionType::DOSFat16LBA => {
+                    if FatFs::identify(self.drive_id, u64::from(meta.start_lba()))
+                        .map_err(|_| MountError::IOError)?
+                    {
+                        let fs = FatFs::mount(self.drive_id, self.id, u64::from(meta.start_lba()))?;
+                        PartFS::Fat(Box::new(fs))
                     } else {
                         PartFS::Unknown
                     }
                 }
-                mbr::PartitionType::LinuxExtended => todo!(),
-                mbr::PartitionType::LinuxLVM => todo!(),
-                mbr::PartitionType::BSDI => todo!(),
-                mbr::PartitionType::OpenBSD => todo!(),
-                mbr::PartitionType::MacOSX => todo!(),
-                mbr::PartitionType::MacOSXBoot => todo!(),
-                mbr::PartitionType::MacOSXHFS => todo!(),
-                mbr::PartitionType::LUKS => todo!(),
-                mbr::PartitionType::GPT => PartFS::Unknown,
-                mbr::PartitionType::Unknown => PartFS::Unknown,
+
+                // the same partition type is used for NTFS and exFAT volumes
+                mbr::PartitionType::NTFS | mbr::PartitionType::EXFAT => {
+                    if ExFatFs::identify(self.drive_id, u64::from(meta.start_lba()))
+                        .map_err(|_| MountError::IOError)?
+                    {
+                        let fs =
+                            ExFatFs::mount(self.drive_id, self.id, u64::from(meta.start_lba()))?;
+                        PartFS::ExFat(Box::new(fs))
+                    } else {
+                        PartFS::Unknown
+                    }
+                }
+
+                // Other filesystems are not supported yet
+                _ => PartFS::Unknown,
             },
             PartitionMetadata::GPT(meta) => {
                 if Ext4Fs::identify(self.drive_id, meta.start_lba())
                     .map_err(|_| MountError::IOError)?
                 {
                     let fs = Ext4Fs::mount(self.drive_id, self.id, meta.start_lba())?;
-                    PartFS::Ext4(alloc::boxed::Box::new(fs))
+                    PartFS::Ext4(Box::new(fs))
+                } else if FatFs::identify(self.drive_id, meta.start_lba())
+                    .map_err(|_| MountError::IOError)?
+                {
+                    let fs = FatFs::mount(self.drive_id, self.id, meta.start_lba())?;
+                    PartFS::Fat(Box::new(fs))
+                } else if ExFatFs::identify(self.drive_id, meta.start_lba())
+                    .map_err(|_| MountError::IOError)?
+                {
+                    let fs = ExFatFs::mount(self.drive_id, self.id, meta.start_lba())?;
+                    PartFS::ExFat(Box::new(fs))
                 } else {
                     PartFS::Unknown
                 }
@@ -96,6 +117,205 @@ impl Partition {
         Ok(())
     }
 
+    /// Returns `true` if a supported filesystem is mounted on this partition.
+    #[must_use]
+    pub fn is_mounted(&self) -> bool {
+        !matches!(self.fs, PartFS::Unknown)
+    }
+
+    /// Opens the regular file located at `path`, on the filesystem mounted on this partition (see
+    /// [`Partition::load_fs`]).
+    ///
+    /// `path` is absolute: its components are resolved one after the other, starting from the root
+    /// directory of the filesystem.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`IOError::NotFound`] if the file does not exist, and [`IOError::InvalidDevice`] if no
+    /// supported filesystem is mounted on this partition. May return any other variant of
+    /// [`IOError`] in case of disk I/O error.
+    pub fn open(&self, path: &str) -> IOResult<File> {
+        match &self.fs {
+            PartFS::Ext4(fs) => Ok(Box::new(fs.read().open_file(path)?)),
+            PartFS::Fat(fs) => Ok(Box::new(fs.read().open_file(path)?)),
+            PartFS::ExFat(fs) => Ok(Box::new(fs.read().open_file(path)?)),
+            PartFS::Unknown => Err(IOError::InvalidDevice),
+        }
+    }
+
+    /// Reads the whole content of the file located at `path`, on the filesystem mounted on this
+    /// partition (see [`Partition::open`]).
+    ///
+    /// # Errors
+    ///
+    /// Returns [`IOError::NotFound`] if the file does not exist, and [`IOError::InvalidDevice`] if no
+    /// supported filesystem is mounted on this partition. May return any other variant of
+    /// [`IOError`] in case of disk I/O error.
+    pub fn read_file(&self, path: &str) -> IOResult<Vec<u8>> {
+        let mut file = self.open(path)?;
+        let mut content = Vec::new();
+
+        file.read_file(&mut content)?;
+        Ok(content)
+    }
+
+    /// Opens the regular file located at `path`, on the filesystem mounted on this partition, and
+    /// creates it (empty) if it does not exist. Its parent directory must already exist.
+    ///
+    /// Only `FAT` and `ext4` filesystems can be written to.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`IOError::NotFound`] if the parent directory does not exist, and
+    /// [`IOError::InvalidCommand`] if the filesystem is read-only, or if `path` is not a valid file
+    /// name. Returns [`IOError::InvalidDevice`] if no supported filesystem is mounted on this
+    /// partition. May return any other variant of [`IOError`] in case of disk I/O error.
+    pub fn create(&self, path: &str) -> IOResult<File> {
+        match &self.fs {
+            PartFS::Ext4(fs) => Ok(Box::new(fs.read().create_file(path)?)),
+            PartFS::Fat(fs) => Ok(Box::new(fs.read().create_file(path)?)),
+            PartFS::ExFat(_) => Err(IOError::InvalidCommand),
+            PartFS::Unknown => Err(IOError::InvalidDevice),
+        }
+    }
+
+    /// Creates an empty directory at `path`, on the filesystem mounted on this partition. Its
+    /// parent directory must already exist.
+    ///
+    /// Only `ext4` filesystems support directory creation.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`IOError::NotFound`] if the parent directory does not exist, and
+    /// [`IOError::InvalidCommand`] if the filesystem is read-only, or if `path` already exists.
+    /// Returns [`IOError::InvalidDevice`] if no supported filesystem is mounted on this partition.
+    /// May return any other variant of [`IOError`] in case of disk I/O error.
+    pub fn mkdir(&self, path: &str) -> CanFail<IOError> {
+        match &self.fs {
+            PartFS::Ext4(fs) => fs.read().create_dir(path).map(|_| ()),
+            PartFS::Fat(_) | PartFS::ExFat(_) => Err(IOError::InvalidCommand),
+            PartFS::Unknown => Err(IOError::InvalidDevice),
+        }
+    }
+
+    /// Removes the file or the empty directory located at `path`, on the filesystem mounted on
+    /// this partition.
+    ///
+    /// Only `ext4` filesystems support removing entries.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`IOError::NotFound`] if `path` does not exist, and [`IOError::InvalidCommand`] if
+    /// the filesystem is read-only, or if `path` is a non-empty directory. Returns
+    /// [`IOError::InvalidDevice`] if no supported filesystem is mounted on this partition. May
+    /// return any other variant of [`IOError`] in case of disk I/O error.
+    pub fn unlink(&self, path: &str) -> CanFail<IOError> {
+        match &self.fs {
+            PartFS::Ext4(fs) => fs.read().unlink(path),
+            PartFS::Fat(_) | PartFS::ExFat(_) => Err(IOError::InvalidCommand),
+            PartFS::Unknown => Err(IOError::InvalidDevice),
+        }
+    }
+
+    /// Moves the entry located at `old_path` to `new_path`, on the filesystem mounted on this
+    /// partition. The entry located at `new_path` is replaced if it exists.
+    ///
+    /// Only `ext4` filesystems support renaming entries.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`IOError::NotFound`] if `old_path` does not exist, and [`IOError::InvalidCommand`]
+    /// if the filesystem is read-only, or if the entry at `new_path` cannot be replaced. Returns
+    /// [`IOError::InvalidDevice`] if no supported filesystem is mounted on this partition. May
+    /// return any other variant of [`IOError`] in case of disk I/O error.
+    pub fn rename(&self, old_path: &str, new_path: &str) -> CanFail<IOError> {
+        match &self.fs {
+            PartFS::Ext4(fs) => fs.read().rename(old_path, new_path),
+            PartFS::Fat(_) | PartFS::ExFat(_) => Err(IOError::InvalidCommand),
+            PartFS::Unknown => Err(IOError::InvalidDevice),
+        }
+    }
+
+    /// Replaces the content of the file located at `path` with `data`, creating the file if it
+    /// does not exist (see [`Partition::create`]).
+    ///
+    /// # Errors
+    ///
+    /// See [`Partition::create`]. Returns [`IOError::NoSpace`] if the filesystem is full.
+    pub fn write_file(&self, path: &str, data: &[u8]) -> CanFail<IOError> {
+        let mut file = self.create(path)?;
+
+        file.truncate(0)?;
+        write_all(&mut file, data)
+    }
+
+    /// Appends `data` to the end of the file located at `path`, creating the file if it does not
+    /// exist (see [`Partition::create`]).
+    ///
+    /// # Errors
+    ///
+    /// See [`Partition::create`]. Returns [`IOError::NoSpace`] if the filesystem is full.
+    pub fn append_file(&self, path: &str, data: &[u8]) -> CanFail<IOError> {
+        let mut file = self.create(path)?;
+        let size = file.size()?;
+
+        file.seek(Seek::Forward(size));
+        write_all(&mut file, data)
+    }
+
+    /// Lists the directory located at `path`, on the filesystem mounted on this partition.
+    ///
+    /// Returns the names of its entries, except `.` and `..`.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`IOError::NotFound`] if the directory does not exist, and [`IOError::InvalidDevice`]
+    /// if no supported filesystem is mounted on this partition. May return any other variant of
+    /// [`IOError`] in case of disk I/O error.
+    pub fn read_dir(&self, path: &str) -> IOResult<Vec<String>> {
+        match &self.fs {
+            PartFS::Ext4(fs) => fs.read().read_dir(path),
+            PartFS::Fat(fs) => fs.read().read_dir(path),
+            PartFS::ExFat(fs) => fs.read().read_dir(path),
+            PartFS::Unknown => Err(IOError::InvalidDevice),
+        }
+    }
+
+    /// Returns the identifier of the drive on which this partition is stored.
+    #[must_use]
+    pub fn drive_id(&self) -> AtaDeviceIdentifier {
+        self.drive_id
+    }
+
+    /// Returns the index of this partition's entry in the partition table.
+    #[must_use]
+    pub fn id(&self) -> usize {
+        self.id
+    }
+
+    /// Checks if this partition matches a [`PartitionSelector`].
+    ///
+    /// _GPT_ selectors never match _MBR_ partitions, and the other way around.
+    #[must_use]
+    pub fn matches(&self, selector: &PartitionSelector) -> bool {
+        match (selector, &self.metadata) {
+            (PartitionSelector::Label(label), PartitionMetadata::GPT(meta)) => {
+                meta.name() == *label
+            }
+            (PartitionSelector::TypeGuid(guid), PartitionMetadata::GPT(meta)) => {
+                meta.type_guid() == *guid
+            }
+            (PartitionSelector::UniqueGuid(guid), PartitionMetadata::GPT(meta)) => {
+                meta.guid() == *guid
+            }
+            (PartitionSelector::Mbr { part_type, active }, PartitionMetadata::MBR(meta)) => {
+                part_type.is_none_or(|id| meta.partition_type_id() == id)
+                    && (!active || meta.is_active())
+            }
+            _ => false,
+        }
+    }
+
     /// Returns this partition's starting LBA.
     pub fn start_lba(&self) -> u64 {
         match self.metadata {
@@ -104,6 +324,15 @@ impl Partition {
         }
     }
 
+    /// Returns this partition's size, in sectors.
+    #[must_use]
+    pub fn sectors_count(&self) -> u64 {
+        match self.metadata {
+            PartitionMetadata::MBR(meta) => u64::from(meta.sectors_count()),
+            PartitionMetadata::GPT(meta) => meta.size_in_sectors(),
+        }
+    }
+
     /// Returns the partition format dependent metadatas.
     ///
     /// They contain the original table entry for this partition.
@@ -126,9 +355,167 @@ pub enum PartitionMetadata {
     GPT(GPTPartitionEntry),
 }
 
+/// Criterion used to look up a partition, independently of the disk on which it is stored.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PartitionSelector {
+    /// _GPT_ partition with the given name.
+    Label(String),
+
+    /// _GPT_ partition with the given type GUID (see [`gpt::GPTPartType`]).
+    TypeGuid(u128),
+
+    /// _GPT_ partition with the given unique GUID.
+    UniqueGuid(u128),
+
+    /// _MBR_ partition.
+    Mbr {
+        /// Partition type identifier, or `None` to match any type.
+        part_type: Option<u8>,
+
+        /// Only match _active_ (bootable) partitions.
+        active: bool,
+    },
+}
+
+impl PartitionSelector {
+    /// Parses a partition selector, written as one of:
+    ///
+    /// - `label:<name>`
+    /// - `type:<guid>`
+    /// - `guid:<guid>`
+    /// - `mbr:<type>`, `mbr:active` or `mbr:<type>,active`, where `<type>` is the hexadecimal
+    ///   partition type identifier.
+    ///
+    /// GUIDs use the usual `XXXXXXXX-XXXX-XXXX-XXXX-XXXXXXXXXXXX` representation.
+    #[must_use]
+    pub fn parse(text: &str) -> Option<Self> {
+        let (kind, value) = text.split_once(':')?;
+
+        match kind {
+            "label" if !value.is_empty() => Some(Self::Label(String::from(value))),
+            "type" => parse_guid(value).map(Self::TypeGuid),
+            "guid" => parse_guid(value).map(Self::UniqueGuid),
+            "mbr" => {
+                let (part_type, active) = match value.split_once(',') {
+                    Some((part_type, "active")) => (Some(part_type), true),
+                    Some(_) => return None,
+                    None if value == "active" => (None, true),
+                    None => (Some(value), false),
+                };
+
+                let part_type = match part_type {
+                    Some(id) => Some(
+                        u8::from_str_radix(id.trim_start_matches("0x"), 16)
+                            .ok()
+                            .filter(|&id| id != 0)?,
+                    ),
+                    None => None,
+                };
+
+                Some(Self::Mbr { part_type, active })
+            }
+            _ => None,
+        }
+    }
+}
+
+/// Writes the whole content of `data` to `file`, starting at its cursor.
+///
+/// [`FsFile::write`] may stop early when the filesystem is full: writing resumes with the bytes left, until one of the
+/// writes fails or makes no progress.
+fn write_all(file: &mut File, data: &[u8]) -> CanFail<IOError> {
+    let mut written = 0;
+
+    while written < data.len() {
+        match file.write(&data[written..])? {
+            0 => return Err(IOError::NoSpace),
+            count => written += count,
+        }
+    }
+
+    Ok(())
+}
+
 #[derive(Debug)]
 pub enum PartitionTable {
     MBR(MBRPartitionTable),
     GPT(GUIDPartitionTable),
     Unknown,
 }
+
+#[cfg(test)]
+mod tests {
+    use alloc::string::String;
+
+    use super::{gpt::GPTPartType, PartitionSelector};
+    use crate::errors::IOError;
+    use crate::fs::ext4::tests::{fsck, mkfs, mount};
+
+    #[test]
+    fn parses_partition_selectors() {
+        assert_eq!(
+            PartitionSelector::parse("label:kernelfs"),
+            Some(PartitionSelector::Label(String::from("kernelfs")))
+        );
+        assert_eq!(
+            PartitionSelector::parse("type:BC13C2FF-59E6-4262-A352-B275FD6F7172"),
+            Some(PartitionSelector::TypeGuid(u128::from(
+                GPTPartType::LinuxExtendedBoot
+            )))
+        );
+        assert_eq!(
+            PartitionSelector::parse("guid:C12A7328-F81F-11D2-BA4B-00A0C93EC93B"),
+            Some(PartitionSelector::UniqueGuid(u128::from(
+                GPTPartType::EfiSystem
+            )))
+        );
+        assert_eq!(
+            PartitionSelector::parse("mbr:0x83"),
+            Some(PartitionSelector::Mbr {
+                part_type: Some(0x83),
+                active: false,
+            })
+        );
+        assert_eq!(
+            PartitionSelector::parse("mbr:active"),
+            Some(PartitionSelector::Mbr {
+                part_type: None,
+                active: true,
+            })
+        );
+    }
+
+    #[test]
+    fn rejects_invalid_selectors() {
+        let invalid = [
+            "kernelfs",
+            "label:",
+            "uuid:C12A7328-F81F-11D2-BA4B-00A0C93EC93B",
+            "type:C12A7328-F81F-11D2-BA4B",
+            "mbr:00",
+            "mbr:83,inactive",
+            "mbr:",
+        ];
+
+        for text in invalid {
+            assert_eq!(PartitionSelector::parse(text), None, "{text}");
+        }
+    }
+
+    #[test]
+    fn reports_full_filesystems() {
+        let (disk, partition) = mount(&mkfs(2048, &[]));
+        let data = alloc::vec![0xA5; 3 * 1024 * 1024];
+
+        assert!(matches!(
+            partition.write_file("/big.bin", &data),
+            Err(IOError::NoSpace)
+        ));
+        assert!(matches!(
+            partition.append_file("/big.bin", &data),
+            Err(IOError::NoSpace)
+        ));
+
+        fsck(&disk.image()).unwrap();
+    }
+}
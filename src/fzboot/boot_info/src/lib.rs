@@ -0,0 +1,635 @@
+//! `FrozenBoot` native boot protocol.
+//!
+//! Native kernels are started in 64-bit long mode, with the physical address of a [`BootInfo`]
+//! structure in `RCX`. That structure describes the state of the machine when the bootloader hands
+//! out control: memory map, framebuffer, firmware tables, boot modules, command line, clock
+//! calibration, paging layout, _TPM_ event log and kernel image location.
+//!
+//! The structure, along with everything it points to, is stored in memory reported as
+//! [`MemoryRegionKind::Bootloader`] in the memory map, which is identity mapped by the boot page
+//! tables. It stays valid as long as the kernel does not reuse that memory, or unmap it.
+//!
+//! ```ignore
+//! let boot_info = unsafe { BootInfo::from_ptr(boot_info_addr as *const BootInfo) }
+//!     .expect("invalid boot information");
+//!
+//! for region in boot_info.memory_map() {
+//!     if region.kind() == MemoryRegionKind::Usable {
+//!         // ...
+//!     }
+//! }
+//! ```
+//!
+//! # Versioning
+//!
+//! The structure starts with [`BOOT_INFO_MAGIC`], followed by its version and size. New versions
+//! only append fields at the end of the structure, so that kernels built against an older version
+//! of this crate can still parse the structure provided by a newer bootloader.
+//!
+//! Every structure is aligned on 8 bytes, so that its layout is the same for the 32-bit bootloader
+//! and the 64-bit kernel.
+
+#![no_std]
+#![warn(missing_copy_implementations)]
+#![warn(missing_debug_implementations)]
+#![warn(missing_docs)]
+#![warn(clippy::pedantic)]
+
+use core::{
+    mem::{align_of, offset_of, size_of},
+    slice, str,
+};
+
+/// Value of the first field of the [`BootInfo`] structure (`FZBOOTIN`).
+pub const BOOT_INFO_MAGIC: u64 = u64::from_le_bytes(*b"FZBOOTIN");
+
+/// Version of the [`BootInfo`] structure described by this crate.
+pub const BOOT_INFO_VERSION: u32 = 3;
+
+/// Set in [`ClockInfo::flags`] if the _TSC_ runs at a constant rate, regardless of the processor
+/// power state.
+pub const CLOCK_TSC_INVARIANT: u64 = 1;
+
+/// Set in [`KernelInfo::flags`] if the kernel is position-independent, and has been relocated.
+pub const KERNEL_RELOCATED: u64 = 1 << 0;
+
+/// Set in [`KernelInfo::flags`] if the base addresses of the kernel have been randomised.
+pub const KERNEL_RANDOMIZED: u64 = 1 << 1;
+
+/// Error raised while parsing a [`BootInfo`] structure.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum BootInfoError {
+    /// The structure does not start with [`BOOT_INFO_MAGIC`].
+    InvalidMagic,
+
+    /// The structure is older than the one described by this crate.
+    UnsupportedVersion(u32),
+
+    /// The structure, or one of the arrays it points to, is located at an invalid address.
+    InvalidPointer,
+}
+
+/// Boot information structure, passed by the bootloader to native kernels.
+#[derive(Clone, Copy, Debug)]
+#[repr(C, align(8))]
+pub struct BootInfo {
+    /// Always set to [`BOOT_INFO_MAGIC`].
+    magic: u64,
+
+    /// Version of the structure.
+    version: u32,
+
+    /// Size of the structure, in bytes.
+    size: u32,
+
+    /// Physical memory map, as an array of [`MemoryRegion`].
+    memory_map: RawSlice,
+
+    /// Boot modules, as an array of [`Module`].
+    modules: RawSlice,
+
+    /// Kernel command line, encoded as UTF-8 (not zero terminated).
+    cmdline: RawSlice,
+
+    /// Physical address of the _ACPI_ `RSDP` structure, or 0.
+    rsdp_addr: u64,
+
+    /// Physical address of the _SMBIOS_ entry point structure, or 0.
+    smbios_addr: u64,
+
+    /// Framebuffer description, with a null address if there is no framebuffer.
+    framebuffer: Framebuffer,
+
+    /// Clocks calibration data.
+    clock: ClockInfo,
+
+    /// Paging layout.
+    paging: PagingInfo,
+
+    /// _TCG_ event log of the measurements made by the bootloader (added in version 2).
+    tpm_event_log: RawSlice,
+
+    /// Location of the kernel image (added in version 3).
+    kernel: KernelInfo,
+}
+
+impl BootInfo {
+    /// Creates an empty `BootInfo` structure, of the current version.
+    #[must_use]
+    #[allow(clippy::missing_panics_doc)]
+    pub fn new() -> Self {
+        Self {
+            magic: BOOT_INFO_MAGIC,
+            version: BOOT_INFO_VERSION,
+            size: u32::try_from(size_of::<Self>()).expect("invalid boot information size"),
+            memory_map: RawSlice::default(),
+            modules: RawSlice::default(),
+            cmdline: RawSlice::default(),
+            rsdp_addr: 0,
+            smbios_addr: 0,
+            framebuffer: Framebuffer::default(),
+            clock: ClockInfo::default(),
+            paging: PagingInfo::default(),
+            tpm_event_log: RawSlice::default(),
+            kernel: KernelInfo::default(),
+        }
+    }
+
+    /// Checks the header of the `BootInfo` structure located at `ptr`, and returns a reference to
+    /// it.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`BootInfoError::InvalidPointer`] if `ptr`, or one of the arrays of the structure,
+    /// is null or misaligned, [`BootInfoError::InvalidMagic`] if the structure does not start with
+    /// [`BOOT_INFO_MAGIC`], and [`BootInfoError::UnsupportedVersion`] if it is older than the
+    /// version described by this crate.
+    ///
+    /// # Safety
+    ///
+    /// `ptr` must be the address provided by the bootloader, and the memory containing the
+    /// structure must be mapped (and left untouched) for the whole lifetime `'a`.
+    pub unsafe fn from_ptr<'a>(ptr: *const Self) -> Result<&'a Self, BootInfoError> {
+        if ptr.is_null() || !ptr.is_aligned() {
+            return Err(BootInfoError::InvalidPointer);
+        }
+
+        let boot_info = &*ptr;
+
+        if boot_info.magic != BOOT_INFO_MAGIC {
+            return Err(BootInfoError::InvalidMagic);
+        }
+
+        if boot_info.version == 0 || (boot_info.size as usize) < size_of::<Self>() {
+            return Err(BootInfoError::UnsupportedVersion(boot_info.version));
+        }
+
+        if !boot_info.memory_map.is_valid::<MemoryRegion>()
+            || !boot_info.modules.is_valid::<Module>()
+            || !boot_info.cmdline.is_valid::<u8>()
+            || !boot_info.tpm_event_log.is_valid::<u8>()
+        {
+            return Err(BootInfoError::InvalidPointer);
+        }
+
+        Ok(boot_info)
+    }
+
+    /// Returns the version of the structure provided by the bootloader.
+    ///
+    /// It may be newer than [`BOOT_INFO_VERSION`], in which case the fields added by newer
+    /// versions are not available.
+    #[must_use]
+    pub fn version(&self) -> u32 {
+        self.version
+    }
+
+    /// Returns the physical memory map, sorted by address.
+    #[must_use]
+    pub fn memory_map(&self) -> &[MemoryRegion] {
+        unsafe { self.memory_map.as_slice() }
+    }
+
+    /// Sets the physical memory map.
+    pub fn set_memory_map(&mut self, memory_map: &'static [MemoryRegion]) {
+        self.memory_map = RawSlice::new(memory_map);
+    }
+
+    /// Returns the boot modules loaded along with the kernel.
+    #[must_use]
+    pub fn modules(&self) -> &[Module] {
+        unsafe { self.modules.as_slice() }
+    }
+
+    /// Sets the boot modules loaded along with the kernel.
+    pub fn set_modules(&mut self, modules: &'static [Module]) {
+        self.modules = RawSlice::new(modules);
+    }
+
+    /// Returns the kernel command line, or `None` if it is not valid UTF-8.
+    #[must_use]
+    pub fn cmdline(&self) -> Option<&str> {
+        str::from_utf8(unsafe { self.cmdline.as_slice() }).ok()
+    }
+
+    /// Sets the kernel command line.
+    pub fn set_cmdline(&mut self, cmdline: &'static str) {
+        self.cmdline = RawSlice::new(cmdline.as_bytes());
+    }
+
+    /// Returns the physical address of the _ACPI_ `RSDP` structure, if found by the bootloader.
+    #[must_use]
+    pub fn rsdp_addr(&self) -> Option<u64> {
+        (self.rsdp_addr != 0).then_some(self.rsdp_addr)
+    }
+
+    /// Sets the physical address of the _ACPI_ `RSDP` structure.
+    pub fn set_rsdp_addr(&mut self, addr: u64) {
+        self.rsdp_addr = addr;
+    }
+
+    /// Returns the physical address of the _SMBIOS_ entry point structure, if found by the
+    /// bootloader.
+    #[must_use]
+    pub fn smbios_addr(&self) -> Option<u64> {
+        (self.smbios_addr != 0).then_some(self.smbios_addr)
+    }
+
+    /// Sets the physical address of the _SMBIOS_ entry point structure.
+    pub fn set_smbios_addr(&mut self, addr: u64) {
+        self.smbios_addr = addr;
+    }
+
+    /// Returns the framebuffer description, if a graphic mode was set by the bootloader.
+    #[must_use]
+    pub fn framebuffer(&self) -> Option<&Framebuffer> {
+        (self.framebuffer.addr != 0).then_some(&self.framebuffer)
+    }
+
+    /// Sets the framebuffer description.
+    pub fn set_framebuffer(&mut self, framebuffer: Framebuffer) {
+        self.framebuffer = framebuffer;
+    }
+
+    /// Returns the clocks calibration data.
+    #[must_use]
+    pub fn clock(&self) -> &ClockInfo {
+        &self.clock
+    }
+
+    /// Sets the clocks calibration data.
+    pub fn set_clock(&mut self, clock: ClockInfo) {
+        self.clock = clock;
+    }
+
+    /// Returns the paging layout.
+    #[must_use]
+    pub fn paging(&self) -> &PagingInfo {
+        &self.paging
+    }
+
+    /// Sets the paging layout.
+    pub fn set_paging(&mut self, paging: PagingInfo) {
+        self.paging = paging;
+    }
+
+    /// Returns the _TCG_ event log (in the crypto agile format) of the measurements made by the
+    /// bootloader, empty if no _TPM_ is available.
+    #[must_use]
+    pub fn tpm_event_log(&self) -> &[u8] {
+        unsafe { self.tpm_event_log.as_slice() }
+    }
+
+    /// Sets the _TCG_ event log.
+    pub fn set_tpm_event_log(&mut self, event_log: &'static [u8]) {
+        self.tpm_event_log = RawSlice::new(event_log);
+    }
+
+    /// Returns the location of the kernel image, in physical and virtual memory.
+    #[must_use]
+    pub fn kernel(&self) -> &KernelInfo {
+        &self.kernel
+    }
+
+    /// Sets the location of the kernel image.
+    pub fn set_kernel(&mut self, kernel: KernelInfo) {
+        self.kernel = kernel;
+    }
+}
+
+const _: () = assert!(size_of::<BootInfo>() == 0x108);
+const _: () = assert!(offset_of!(BootInfo, memory_map) == 0x10);
+const _: () = assert!(offset_of!(BootInfo, rsdp_addr) == 0x40);
+const _: () = assert!(offset_of!(BootInfo, framebuffer) == 0x50);
+const _: () = assert!(offset_of!(BootInfo, clock) == 0x70);
+const _: () = assert!(offset_of!(BootInfo, paging) == 0x98);
+const _: () = assert!(offset_of!(BootInfo, tpm_event_log) == 0xD0);
+const _: () = assert!(offset_of!(BootInfo, kernel) == 0xE0);
+const _: () = assert!(size_of::<MemoryRegion>() == 0x18);
+const _: () = assert!(size_of::<Module>() == 0x20);
+const _: () = assert!(size_of::<Framebuffer>() == 0x20);
+const _: () = assert!(offset_of!(Framebuffer, pitch) == 0x10);
+const _: () = assert!(size_of::<ClockInfo>() == 0x28);
+const _: () = assert!(size_of::<PagingInfo>() == 0x38);
+const _: () = assert!(size_of::<KernelInfo>() == 0x28);
+
+impl Default for BootInfo {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Type of a [`MemoryRegion`].
+///
+/// Apart from [`MemoryRegionKind::Bootloader`], the types match the ones reported by the _BIOS_
+/// (`E820` memory map).
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[repr(u32)]
+pub enum MemoryRegionKind {
+    /// Free memory, available to the kernel.
+    Usable = 1,
+
+    /// Memory reserved by the firmware or the hardware.
+    Reserved = 2,
+
+    /// Memory containing the _ACPI_ tables, which can be reused once they have been read.
+    AcpiReclaimable = 3,
+
+    /// _ACPI_ non-volatile storage, which must be preserved.
+    AcpiNvs = 4,
+
+    /// Memory in which errors were detected.
+    Unusable = 5,
+
+    /// Memory that is not enabled.
+    Disabled = 6,
+
+    /// Persistent memory.
+    Persistent = 7,
+
+    /// Memory used by the kernel image, the boot modules, the boot information structure and the
+    /// boot page tables. It can be reused once the kernel no longer needs any of them.
+    Bootloader = 0x1000,
+}
+
+impl MemoryRegionKind {
+    /// Converts a raw memory type; unknown types are considered reserved.
+    fn from_raw(kind: u32) -> Self {
+        match kind {
+            1 => Self::Usable,
+            3 => Self::AcpiReclaimable,
+            4 => Self::AcpiNvs,
+            5 => Self::Unusable,
+            6 => Self::Disabled,
+            7 => Self::Persistent,
+            0x1000 => Self::Bootloader,
+            _ => Self::Reserved,
+        }
+    }
+}
+
+/// A physical memory range, along with its type.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[repr(C, align(8))]
+pub struct MemoryRegion {
+    start: u64,
+    length: u64,
+    kind: u32,
+    reserved: u32,
+}
+
+impl MemoryRegion {
+    /// Creates a memory region of `length` bytes, starting at `start`.
+    #[must_use]
+    pub fn new(start: u64, length: u64, kind: MemoryRegionKind) -> Self {
+        Self {
+            start,
+            length,
+            kind: kind as u32,
+            reserved: 0,
+        }
+    }
+
+    /// Returns the physical start address of the region.
+    #[must_use]
+    pub fn start(&self) -> u64 {
+        self.start
+    }
+
+    /// Returns the physical end address of the region (excluded).
+    #[must_use]
+    pub fn end(&self) -> u64 {
+        self.start.saturating_add(self.length)
+    }
+
+    /// Returns the length of the region, in bytes.
+    #[must_use]
+    pub fn length(&self) -> u64 {
+        self.length
+    }
+
+    /// Returns the type of the region.
+    #[must_use]
+    pub fn kind(&self) -> MemoryRegionKind {
+        MemoryRegionKind::from_raw(self.kind)
+    }
+}
+
+/// A boot module, loaded in memory along with the kernel.
+#[derive(Clone, Copy, Debug)]
+#[repr(C, align(8))]
+pub struct Module {
+    start: u64,
+    end: u64,
+    cmdline: RawSlice,
+}
+
+impl Module {
+    /// Creates a module, loaded in the physical memory range `start..end`.
+    #[must_use]
+    pub fn new(start: u64, end: u64, cmdline: &'static str) -> Self {
+        Self {
+            start,
+            end,
+            cmdline: RawSlice::new(cmdline.as_bytes()),
+        }
+    }
+
+    /// Returns the physical start address of the module, aligned on a page boundary.
+    #[must_use]
+    pub fn start(&self) -> u64 {
+        self.start
+    }
+
+    /// Returns the physical end address of the module (excluded).
+    #[must_use]
+    pub fn end(&self) -> u64 {
+        self.end
+    }
+
+    /// Returns the size of the module, in bytes.
+    #[must_use]
+    pub fn size(&self) -> u64 {
+        self.end.saturating_sub(self.start)
+    }
+
+    /// Returns the command line (or name) associated with the module, or `None` if it is not
+    /// valid UTF-8.
+    #[must_use]
+    pub fn cmdline(&self) -> Option<&str> {
+        str::from_utf8(unsafe { self.cmdline.as_slice() }).ok()
+    }
+}
+
+/// Description of a linear framebuffer.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[repr(C, align(8))]
+pub struct Framebuffer {
+    /// Physical address of the framebuffer.
+    pub addr: u64,
+
+    /// Number of columns, in pixels.
+    pub width: u32,
+
+    /// Number of lines, in pixels.
+    pub height: u32,
+
+    /// Number of bytes between the start of two consecutive lines.
+    pub pitch: u32,
+
+    /// Number of bits per pixel.
+    pub bpp: u8,
+
+    /// Size of the red component, in bits.
+    pub red_mask_size: u8,
+
+    /// Position of the red component in a pixel, in bits.
+    pub red_shift: u8,
+
+    /// Size of the green component, in bits.
+    pub green_mask_size: u8,
+
+    /// Position of the green component in a pixel, in bits.
+    pub green_shift: u8,
+
+    /// Size of the blue component, in bits.
+    pub blue_mask_size: u8,
+
+    /// Position of the blue component in a pixel, in bits.
+    pub blue_shift: u8,
+}
+
+/// Clocks calibration data, measured by the bootloader.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[repr(C, align(8))]
+pub struct ClockInfo {
+    /// Frequency of the _TSC_, in Hz, or 0 if it could not be calibrated.
+    pub tsc_frequency: u64,
+
+    /// Value of the _TSC_ when the boot information was built.
+    pub tsc_timestamp: u64,
+
+    /// Physical address of the _HPET_ registers, or 0 if there is no _HPET_.
+    pub hpet_addr: u64,
+
+    /// Period of the _HPET_ main counter, in femtoseconds.
+    pub hpet_period: u64,
+
+    /// Clocks properties (see [`CLOCK_TSC_INVARIANT`]).
+    pub flags: u64,
+}
+
+impl ClockInfo {
+    /// Checks if the _TSC_ runs at a constant rate, regardless of the processor power state.
+    #[must_use]
+    pub fn tsc_invariant(&self) -> bool {
+        self.flags & CLOCK_TSC_INVARIANT != 0
+    }
+}
+
+/// Paging layout set up by the bootloader, and virtual memory layout expected by the kernel.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[repr(C, align(8))]
+pub struct PagingInfo {
+    /// Physical address of the level 4 page table loaded in `CR3` when the kernel is started.
+    pub boot_page_table: u64,
+
+    /// Size of the physical memory identity mapped by the boot page tables, starting at 0.
+    pub identity_mapping_size: u64,
+
+    /// Physical address at which the kernel page tables are built.
+    pub kernel_page_table: u64,
+
+    /// Virtual address at which the physical memory is mapped.
+    pub physical_mapping_base: u64,
+
+    /// Virtual address at which the kernel code is mapped (the randomised one for relocated
+    /// kernels, see [`KernelInfo`]).
+    pub kernel_code_base: u64,
+
+    /// Virtual address of the segment dedicated to the kernel stacks.
+    pub kernel_stack_base: u64,
+
+    /// Virtual address of the kernel heap.
+    pub kernel_heap_base: u64,
+}
+
+/// Location of the kernel image, as loaded by the bootloader.
+///
+/// Position-independent kernels are relocated by the bootloader, usually to randomised virtual and
+/// physical addresses. Kernels linked at fixed addresses are loaded at their link addresses.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[repr(C, align(8))]
+pub struct KernelInfo {
+    /// Physical address of the lowest loadable segment of the kernel.
+    pub phys_base: u64,
+
+    /// Virtual address of the lowest loadable segment of the kernel.
+    pub virt_base: u64,
+
+    /// Size of the kernel in memory, from the start of its lowest loadable segment to the end of
+    /// its highest one.
+    pub size: u64,
+
+    /// Offset added by the bootloader to the link-time virtual addresses of the kernel, modulo
+    /// 2^64. Zero if the kernel has not been relocated.
+    pub relocation_offset: u64,
+
+    /// Kernel loading properties (see [`KERNEL_RELOCATED`] and [`KERNEL_RANDOMIZED`]).
+    pub flags: u64,
+}
+
+impl KernelInfo {
+    /// Checks if the kernel has been relocated by the bootloader.
+    #[must_use]
+    pub fn relocated(&self) -> bool {
+        self.flags & KERNEL_RELOCATED != 0
+    }
+
+    /// Checks if the base addresses of the kernel have been randomised.
+    #[must_use]
+    pub fn randomized(&self) -> bool {
+        self.flags & KERNEL_RANDOMIZED != 0
+    }
+}
+
+/// Physical address and length of an array.
+#[derive(Clone, Copy, Debug, Default)]
+#[repr(C, align(8))]
+struct RawSlice {
+    addr: u64,
+    len: u64,
+}
+
+impl RawSlice {
+    /// Stores the address and length of a slice.
+    fn new<T>(data: &'static [T]) -> Self {
+        Self {
+            addr: data.as_ptr() as usize as u64,
+            len: data.len() as u64,
+        }
+    }
+
+    /// Checks that the array is either empty, or located at a non-null address aligned for `T`.
+    fn is_valid<T>(&self) -> bool {
+        self.len == 0
+            || (self.addr != 0
+                && usize::try_from(self.addr).is_ok_and(|addr| addr % align_of::<T>() == 0)
+                && usize::try_from(self.len).is_ok())
+    }
+
+    /// Returns the array as a slice, or an empty slice if it is not valid.
+    ///
+    /// # Safety
+    ///
+    /// The array must contain `len` valid elements of type `T`, and stay valid for the lifetime
+    /// `'a`.
+    unsafe fn as_slice<'a, T>(&self) -> &'a [T] {
+        match (usize::try_from(self.addr), usize::try_from(self.len)) {
+            (Ok(addr), Ok(len)) if len != 0 && self.is_valid::<T>() => {
+                slice::from_raw_parts(addr as *const T, len)
+            }
+            _ => &[],
+        }
+    }
+}
@@ -71,6 +71,15 @@ pub enum IOError {
     /// Invalid device identifier supplied
     InvalidDevice,
 
+    /// The requested file or directory does not exist.
+    NotFound,
+
+    /// There is not enough free space left on the device.
+    NoSpace,
+
+    /// Too many symbolic links were met while resolving a path (which usually denotes a loop).
+    TooManySymlinks,
+
     #[cfg(feature = "alloc")]
     /// Generic error.
     Exception(Box<dyn BaseError>),
@@ -104,6 +113,96 @@ pub enum ClockError {
     Exception,
 }
 
+/// `BootError` defines several error types useful when loading an operating system kernel and
+/// handing off control to it.
+#[derive(Debug)]
+pub enum BootError {
+    /// The kernel image is malformed, or is not in the expected format.
+    InvalidImage,
+
+    /// The kernel image requires a boot protocol version or feature that is not supported.
+    UnsupportedProtocol,
+
+    /// Not enough free memory is available to load the kernel image (or one of its components).
+    OutOfMemory,
+
+    /// Error while reading the kernel image (or one of its components) from a disk device.
+    IOError,
+
+    #[cfg(feature = "alloc")]
+    /// Generic error.
+    Exception(Box<dyn BaseError>),
+
+    #[cfg(not(feature = "alloc"))]
+    /// Generic error.
+    Exception,
+}
+
+impl BaseError for BootError {}
+
+/// `ConfigError` defines several error types useful when parsing the bootloader configuration
+/// file.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ConfigError {
+    /// The line is neither a comment, nor a `key = value` pair.
+    InvalidSyntax,
+
+    /// The key is not a known configuration option.
+    UnknownKey,
+
+    /// The value is not valid for this key.
+    InvalidValue,
+
+    /// The key was already set for the current boot entry (or globally).
+    DuplicateKey,
+
+    /// The key is not allowed here: global options must be set before the first boot entry, and
+    /// boot entry options after a `title` key.
+    MisplacedKey,
+
+    /// A boot entry does not define one of its mandatory keys.
+    MissingKey(&'static str),
+}
+
+impl BaseError for ConfigError {}
+
+/// `SignatureError` defines several error types useful when verifying the signature of a boot
+/// image.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SignatureError {
+    /// No public key was embedded in the bootloader at build time.
+    MissingPublicKey,
+
+    /// The detached signature of the image cannot be found.
+    MissingSignature,
+
+    /// The detached signature is not a raw `Ed25519` signature.
+    MalformedSignature,
+
+    /// The signature does not match the image, or was made with another key.
+    InvalidSignature,
+}
+
+impl BaseError for SignatureError {}
+
+/// `TpmError` defines several error types useful when communicating with a _TPM_.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TpmError {
+    /// No _TPM_ is available on the system.
+    NotPresent,
+
+    /// The _TPM_ did not complete an operation in time.
+    Timeout,
+
+    /// The response of the _TPM_ is malformed, or does not match the command.
+    InvalidResponse,
+
+    /// The _TPM_ failed to execute the command, and returned this response code.
+    ResponseCode(u32),
+}
+
+impl BaseError for TpmError {}
+
 #[derive(Debug)]
 pub enum MountError {
     Unknown,
@@ -12,16 +12,15 @@ use core::{arch::asm, panic::PanicInfo};
 
 use alloc::format;
 use fzboot::{
-    boot::multiboot::mb_information,
     exceptions::{panic::panic_entry_no_exception, register_exception_handlers},
     irq::manager::get_interrupt_manager,
     kernel_syms::KERNEL_PAGE_TABLE,
     mem::{
-        e820::E820MemoryMap,
+        e820::AddressRangeDescriptor,
         kernel_sec::enable_kernel_mem_sec,
         stack::get_kernel_stack_allocator,
         vmalloc::{init_kernel_heap, SyncKernelHeapAllocator},
-        MemoryAddress, PhyAddr, VirtAddr,
+        PhyAddr, VirtAddr,
     },
     process::init_kernel_process,
     scheduler::init_global_scheduler,
@@ -36,6 +35,7 @@ use fzboot::{
         },
     },
 };
+use fzboot_info::BootInfo;
 
 #[global_allocator]
 pub static KERNEL_HEAP_ALLOCATOR: SyncKernelHeapAllocator = SyncKernelHeapAllocator::new();
@@ -43,20 +43,19 @@ pub static KERNEL_HEAP_ALLOCATOR: SyncKernelHeapAllocator = SyncKernelHeapAlloca
 #[no_mangle]
 #[link_section = ".start"]
 pub extern "C" fn _start() -> ! {
-    let mut mb_information_ptr: u64 = 0;
+    let boot_info_ptr: u64;
     unsafe {
-        asm!("", out("rcx") mb_information_ptr);
+        asm!("", out("rcx") boot_info_ptr);
     }
 
-    let mb_information: mb_information::MultibootInformation = unsafe {
-        core::ptr::read(mb_information_ptr as *const mb_information::MultibootInformation)
-    };
+    let boot_info = unsafe { BootInfo::from_ptr(boot_info_ptr as *const BootInfo) }
+        .expect("invalid boot information");
 
     unsafe {
-        mem_init(&mb_information);
+        mem_init(boot_info);
     }
 
-    video::vesa::init_text_buffer_from_multiboot(mb_information.framebuffer().unwrap());
+    video::vesa::init_text_buffer_from_boot_info(boot_info.framebuffer().unwrap());
     let kernel_stack = get_kernel_stack_allocator().lock().alloc_stack();
 
     unsafe {
@@ -93,18 +92,17 @@ extern "C" fn _kmain() -> ! {
     loop {}
 }
 
-unsafe fn mem_init(mb_information: &mb_information::MultibootInformation) {
-    let memory_map = E820MemoryMap::new(
-        PhysicalMemoryMapping::KERNEL_DEFAULT_MAPPING
-            .convert(PhyAddr::from(mb_information.get_mmap_addr()))
-            .as_mut_ptr(),
-    );
-
+unsafe fn mem_init(boot_info: &BootInfo) {
     kernel_init_gdt(
         PhysicalMemoryMapping::KERNEL_DEFAULT_MAPPING.convert(PhyAddr::new(LONG_GDT_ADDR)),
     );
 
-    init_phys_memory_pool(memory_map);
+    init_phys_memory_pool(
+        boot_info
+            .memory_map()
+            .iter()
+            .map(AddressRangeDescriptor::from),
+    );
     init_global_mapper(KERNEL_PAGE_TABLE);
     init_kernel_heap();
 }
@@ -0,0 +1,97 @@
+//! Boot Loader Specification (_BLS_) entries discovery.
+
+use alloc::{format, vec::Vec};
+use fzboot::{
+    boot::{
+        bls::{BlsEntry, BLS_ENTRIES_DIR, BLS_ENTRY_SUFFIX},
+        config::BootEntry,
+    },
+    drivers::generics::dev_disk::{sata_drives, DiskDevice},
+    error,
+    errors::IOError,
+    fs::partitions::Partition,
+    info,
+};
+
+/// Looks for _BLS_ Type #1 entries on every partition on which an `ext4` filesystem can be
+/// mounted, and converts them to Linux boot entries.
+///
+/// Partitions are searched in the order in which disks are enumerated. The entries of each
+/// partition are sorted by version, the most recent first (see [`BlsEntry::menu_order`]), and
+/// returned along with the partition storing their files.
+pub fn discover_bls_entries() -> Vec<(Partition, BootEntry)> {
+    let mut entries = Vec::new();
+
+    for drive in sata_drives() {
+        for partition in drive.partitions() {
+            let mut partition = partition.clone();
+
+            if !partition.is_mounted() && partition.load_fs().is_err() {
+                continue;
+            }
+
+            let mut partition_entries = read_bls_entries(&partition);
+            partition_entries.sort_by(BlsEntry::menu_order);
+
+            if !partition_entries.is_empty() {
+                info!(
+                    "bls",
+                    "found {} entries ({}    partition_id = {})",
+                    partition_entries.len(),
+                    partition.drive_id(),
+                    partition.id()
+                );
+            }
+
+            entries.extend(
+                partition_entries
+                    .into_iter()
+                    .map(|entry| (partition.clone(), entry.into_boot_entry())),
+            );
+        }
+    }
+
+    entries
+}
+
+/// Reads and parses the entry files stored on a mounted partition.
+///
+/// Files that cannot be read or parsed are skipped, and reported on the console.
+fn read_bls_entries(partition: &Partition) -> Vec<BlsEntry> {
+    let names = match partition.read_dir(BLS_ENTRIES_DIR) {
+        Ok(names) => names,
+        Err(IOError::NotFound | IOError::InvalidDevice) => return Vec::new(),
+        Err(err) => {
+            error!("bls", "failed to list {}: {:?}", BLS_ENTRIES_DIR, err);
+            return Vec::new();
+        }
+    };
+
+    names
+        .iter()
+        .filter_map(|name| {
+            let id = name.strip_suffix(BLS_ENTRY_SUFFIX)?;
+            let path = format!("{BLS_ENTRIES_DIR}/{name}");
+
+            let content = match partition.read_file(&path) {
+                Ok(content) => content,
+                Err(err) => {
+                    error!("bls", "failed to read {}: {:?}", path, err);
+                    return None;
+                }
+            };
+            let Ok(content) = core::str::from_utf8(&content) else {
+                error!("bls", "{} is not a valid UTF-8 file", path);
+                return None;
+            };
+
+            let entry = BlsEntry::parse(id, content);
+
+            if entry.is_none() {
+                error!("bls", "{}: entry without `linux` key", path);
+            }
+
+            entry
+        })
+        .collect()
+}
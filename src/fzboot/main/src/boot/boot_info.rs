@@ -0,0 +1,251 @@
+//! Native boot information.
+//!
+//! Builds the [`BootInfo`] structure handed out to native kernels: memory map, framebuffer,
+//! firmware tables, boot modules, command line, clock calibration, boot paging layout, _TPM_
+//! event log and kernel image location. The structure and everything it points to are stored in
+//! memory reserved by the bootloader, which is reported as [`MemoryRegionKind::Bootloader`] in the
+//! memory map.
+
+use core::ptr;
+
+use alloc::{boxed::Box, string::String, vec::Vec};
+use fzboot::{
+    bios::smbios::smbios_entry_point,
+    errors::BootError,
+    info,
+    io::acpi::{hpet::HPET_CLK, RSDP_ADDR},
+    kernel_syms::{
+        KERNEL_CODE_MAPPING_BASE, KERNEL_HEAP_BASE, KERNEL_PAGE_TABLE, KERNEL_PHYS_MAPPING_BASE,
+        KERNEL_STACK_MAPPING_BASE, PAGE_SIZE,
+    },
+    mem::e820::{E820MemType, E820MemoryMap},
+    video::vesa::video_mode::{ModeInfoBlock, VESA_MODE_BUFFER},
+    x86::{
+        paging::bootinit_paging::{BOOT_MAPPING_SIZE, BOOT_PAGE_TABLE_ADDR},
+        tsc::TSC_CLK,
+    },
+};
+use fzboot_info::{
+    BootInfo, ClockInfo, Framebuffer, KernelInfo, MemoryRegion, MemoryRegionKind, Module,
+    PagingInfo, CLOCK_TSC_INVARIANT, KERNEL_RANDOMIZED, KERNEL_RELOCATED,
+};
+
+use super::{fzkernel::LoadedKernel, measure, memory::BootMemoryMap};
+
+/// Copies the boot modules of a native kernel to memory, each of them aligned on a page boundary.
+///
+/// # Errors
+///
+/// Returns [`BootError::OutOfMemory`] if one of the modules cannot be loaded.
+pub fn load_modules(
+    modules: &[(Vec<u8>, &str)],
+    memory: &mut BootMemoryMap,
+) -> Result<Vec<Module>, BootError> {
+    let align = u64::try_from(PAGE_SIZE).expect("invalid page size");
+
+    modules
+        .iter()
+        .map(|(data, cmdline)| {
+            let (start, end) = memory.load(data, align).ok_or(BootError::OutOfMemory)?;
+
+            info!(
+                "kernel",
+                "loaded module {} (addr = {:#x}    size = {:#x})",
+                cmdline,
+                start,
+                end - start
+            );
+
+            Ok(Module::new(start, end, String::from(*cmdline).leak()))
+        })
+        .collect()
+}
+
+/// Builds the boot information of a native kernel.
+///
+/// Must be called once every image has been loaded, so that the memory map reports all the
+/// memory used by the bootloader. The structure itself is allocated on the bootloader heap.
+///
+/// Returns the physical address of the [`BootInfo`] structure.
+pub fn build_boot_info(
+    cmdline: &str,
+    modules: Vec<Module>,
+    kernel: &LoadedKernel,
+    memory: &BootMemoryMap,
+) -> u32 {
+    let mut boot_info = BootInfo::new();
+
+    boot_info.set_memory_map(memory_map(memory).leak());
+    boot_info.set_cmdline(String::from(cmdline).leak());
+    boot_info.set_modules(modules.leak());
+    boot_info.set_framebuffer(framebuffer());
+    boot_info.set_clock(clock());
+    boot_info.set_paging(paging(kernel));
+    boot_info.set_kernel(kernel_info(kernel));
+
+    let event_log = measure::event_log();
+
+    if !event_log.is_empty() {
+        boot_info.set_tpm_event_log(event_log.leak());
+    }
+
+    if let Some(&rsdp_addr) = RSDP_ADDR.get() {
+        boot_info.set_rsdp_addr(u64::try_from(rsdp_addr).expect("invalid RSDP address"));
+    }
+
+    if let Some(entry_point) = smbios_entry_point() {
+        boot_info.set_smbios_addr(
+            u64::try_from(entry_point.data.as_ptr() as usize).expect("invalid SMBIOS address"),
+        );
+    }
+
+    let boot_info = Box::leak(Box::new(boot_info));
+
+    info!(
+        "kernel",
+        "built boot information (addr = {:p}    memory regions = {}    modules = {})",
+        boot_info,
+        boot_info.memory_map().len(),
+        boot_info.modules().len()
+    );
+
+    u32::try_from(ptr::from_mut(boot_info) as usize).expect("invalid boot information address")
+}
+
+/// Builds the memory map from the _BIOS_-provided one, reporting the usable memory reserved by
+/// the bootloader as [`MemoryRegionKind::Bootloader`].
+fn memory_map(memory: &BootMemoryMap) -> Vec<MemoryRegion> {
+    let mut reserved = memory.reserved_ranges().to_vec();
+    reserved.sort_unstable();
+
+    let mut regions: Vec<MemoryRegion> = Vec::new();
+    let mut push_region = |start: u64, end: u64, kind: MemoryRegionKind| {
+        if let Some(last) = regions.last_mut() {
+            if last.kind() == kind && last.end() == start {
+                *last = MemoryRegion::new(last.start(), end - last.start(), kind);
+                return;
+            }
+        }
+
+        regions.push(MemoryRegion::new(start, end - start, kind));
+    };
+
+    for entry in E820MemoryMap::default() {
+        let start = entry.phys_base();
+        let end = start + entry.length();
+
+        if !matches!(entry.addr_type, E820MemType::RAM) {
+            push_region(start, end, MemoryRegionKind::from(entry.addr_type));
+            continue;
+        }
+
+        let mut cursor = start;
+
+        for &(reserved_start, reserved_end) in &reserved {
+            let reserved_start = reserved_start.max(cursor);
+            let reserved_end = reserved_end.min(end);
+
+            if reserved_start >= reserved_end {
+                continue;
+            }
+
+            if reserved_start > cursor {
+                push_region(cursor, reserved_start, MemoryRegionKind::Usable);
+            }
+
+            push_region(reserved_start, reserved_end, MemoryRegionKind::Bootloader);
+            cursor = reserved_end;
+        }
+
+        if cursor < end {
+            push_region(cursor, end, MemoryRegionKind::Usable);
+        }
+    }
+
+    regions
+}
+
+/// Returns the description of the linear framebuffer set up during the VESA initialization.
+fn framebuffer() -> Framebuffer {
+    let mode_info = unsafe { ptr::read(VESA_MODE_BUFFER as *const ModeInfoBlock) };
+
+    Framebuffer {
+        addr: u64::from(mode_info.framebuffer),
+        width: u32::from(mode_info.width),
+        height: u32::from(mode_info.height),
+        pitch: u32::from(mode_info.bytes_per_scanline),
+        bpp: mode_info.bits_per_pixel,
+        red_mask_size: mode_info.red_mask_s,
+        red_shift: mode_info.red_field_pos,
+        green_mask_size: mode_info.green_mask_s,
+        green_shift: mode_info.green_field_pos,
+        blue_mask_size: mode_info.blue_mask_s,
+        blue_shift: mode_info.blue_field_pos,
+    }
+}
+
+/// Returns the calibration of the _TSC_ and _HPET_ clocks, if they are available.
+#[allow(
+    clippy::as_conversions,
+    clippy::cast_possible_truncation,
+    clippy::cast_sign_loss
+)]
+fn clock() -> ClockInfo {
+    let mut clock = ClockInfo::default();
+
+    if let Some(tsc) = TSC_CLK.get() {
+        clock.tsc_frequency = tsc.tsc_frequency() as u64;
+        clock.tsc_timestamp = tsc.tsc_read();
+
+        if tsc.invariant() {
+            clock.flags |= CLOCK_TSC_INVARIANT;
+        }
+    }
+
+    if let Some(hpet) = HPET_CLK.get() {
+        clock.hpet_addr = u64::try_from(hpet.base_address()).expect("invalid HPET address");
+        clock.hpet_period = u64::from(hpet.clk_period());
+    }
+
+    clock
+}
+
+/// Returns the layout of the boot paging structures, and of the kernel virtual address space.
+///
+/// The code base of relocated kernels is the one chosen by the bootloader.
+fn paging(kernel: &LoadedKernel) -> PagingInfo {
+    PagingInfo {
+        boot_page_table: u64::from(BOOT_PAGE_TABLE_ADDR),
+        identity_mapping_size: BOOT_MAPPING_SIZE,
+        kernel_page_table: u64::from(KERNEL_PAGE_TABLE),
+        physical_mapping_base: u64::from(KERNEL_PHYS_MAPPING_BASE),
+        kernel_code_base: if kernel.relocated {
+            kernel.virt_base
+        } else {
+            u64::from(KERNEL_CODE_MAPPING_BASE)
+        },
+        kernel_stack_base: u64::from(KERNEL_STACK_MAPPING_BASE),
+        kernel_heap_base: u64::from(KERNEL_HEAP_BASE),
+    }
+}
+
+/// Returns the location of the loaded kernel image.
+fn kernel_info(kernel: &LoadedKernel) -> KernelInfo {
+    let mut flags = 0;
+
+    if kernel.relocated {
+        flags |= KERNEL_RELOCATED;
+    }
+
+    if kernel.randomized {
+        flags |= KERNEL_RANDOMIZED;
+    }
+
+    KernelInfo {
+        phys_base: kernel.phys_base,
+        virt_base: kernel.virt_base,
+        size: kernel.size,
+        relocation_offset: kernel.relocation_offset,
+        flags,
+    }
+}
@@ -0,0 +1,268 @@
+//! Chainloading of another bootloader.
+//!
+//! The boot sector of a disk (its _MBR_) or of a partition (its _VBR_) is loaded at
+//! [`BOOT_SECTOR_ADDR`], and started in real mode the same way the _BIOS_ would have done: `DL`
+//! contains the _BIOS_ drive number of the disk and, when booting an _MBR_ partition, `DS:SI`
+//! points to its entry in a copy of the partition table.
+
+use core::{
+    arch::{asm, global_asm},
+    ptr,
+};
+
+use alloc::vec::Vec;
+use fzboot::{
+    boot::config::ChainloadTarget,
+    drivers::{
+        generics::dev_disk::{get_sata_drive, sata_drives, DiskDevice},
+        ide::AtaDeviceIdentifier,
+    },
+    errors::BootError,
+    fs::partitions::{Partition, PartitionMetadata},
+    info,
+    io::pic::PIC,
+    mem::{PhyAddr, PhyAddr32},
+    x86::{
+        descriptors::gdt::{real_mode_init_gdt, LONG_GDT_ADDR},
+        int::disable_interrupts,
+    },
+};
+
+use super::disk::{bios_drive_number, read_partition};
+
+/// Address at which boot sectors are loaded and started.
+pub const BOOT_SECTOR_ADDR: usize = 0x7C00;
+
+/// Address at which the _MBR_ is copied when booting an _MBR_ partition, as done by the usual
+/// _MBR_ boot code before loading a _VBR_.
+const MBR_RELOCATION_ADDR: usize = 0x600;
+
+/// Size of a boot sector, in bytes.
+const SECTOR_SIZE: usize = 0x200;
+
+/// Signature expected at the end of a boot sector.
+const BOOT_SIGNATURE: [u8; 2] = [0x55, 0xAA];
+
+/// Offset of the partition table in the _MBR_.
+const MBR_PARTITION_TABLE_OFFSET: usize = 0x1BE;
+
+/// Size of an _MBR_ partition table entry.
+const MBR_PARTITION_ENTRY_SIZE: usize = 0x10;
+
+/// Number of entries of the _MBR_ partition table.
+const MBR_PARTITION_ENTRIES: usize = 4;
+
+/// Interrupt vectors used by the _BIOS_ for the master and slave _PIC_ interrupts.
+const BIOS_PIC_OFFSETS: (u8, u8) = (0x08, 0x70);
+
+/// Content of the _IDTR_ register in real mode, pointing to the _BIOS_ interrupt vector table.
+#[repr(C, packed)]
+struct InterruptVectorTableRegister {
+    limit: u16,
+    base: u32,
+}
+
+/// Real mode interrupt vector table, located at address 0.
+static BIOS_IVT: InterruptVectorTableRegister = InterruptVectorTableRegister {
+    limit: 0x3FF,
+    base: 0,
+};
+
+extern "C" {
+    /// Switches back to real mode, and jumps to the boot sector loaded at [`BOOT_SECTOR_ADDR`].
+    ///
+    /// Must be entered through a far jump to the 16-bit code segment set by
+    /// [`real_mode_init_gdt`], with its base set to the address of the trampoline. `CX` contains
+    /// the real mode segment of the trampoline, `DL` the _BIOS_ drive number and `SI` the address
+    /// of the partition table entry.
+    fn real_mode_trampoline();
+}
+
+global_asm!(
+    ".pushsection .text.real_mode_trampoline, \"ax\"",
+    ".balign 16",
+    ".global real_mode_trampoline",
+    ".code16",
+    "real_mode_trampoline:",
+    "mov ax, 0x10",
+    "mov ds, ax",
+    "mov es, ax",
+    "mov fs, ax",
+    "mov gs, ax",
+    "mov ss, ax",
+    "mov eax, cr0",
+    "and eax, 0x7FFFFFFE",
+    "mov cr0, eax",
+    "push cx",
+    "push real_mode_entry - real_mode_trampoline",
+    "retf",
+    "real_mode_entry:",
+    "xor ax, ax",
+    "mov ds, ax",
+    "mov es, ax",
+    "mov fs, ax",
+    "mov gs, ax",
+    "mov ss, ax",
+    "mov sp, 0x7C00",
+    "cld",
+    "sti",
+    // back to the 80x25 text mode, as expected by most boot sectors
+    "push dx",
+    "push si",
+    "mov ax, 0x0003",
+    "int 0x10",
+    "pop si",
+    "pop dx",
+    "ljmp 0, 0x7C00",
+    ".code32",
+    ".popsection",
+);
+
+/// A boot sector read from a disk, ready to be started.
+pub struct LoadedBootSector {
+    sector: Vec<u8>,
+    drive: u8,
+    partition_table: Option<(Vec<u8>, usize)>,
+}
+
+impl LoadedBootSector {
+    /// Returns the content of the boot sector.
+    pub fn sector(&self) -> &[u8] {
+        &self.sector
+    }
+}
+
+/// Returns the disk, and the index of the partition, whose boot sector is loaded by a chainload
+/// boot entry.
+///
+/// Disks are searched in the order in which they are enumerated (see [`sata_drives`]). If no
+/// partition is selected, the index is `None` and the _MBR_ of the disk is loaded.
+pub fn locate_chainload_target(
+    target: &ChainloadTarget,
+) -> Option<(AtaDeviceIdentifier, Option<usize>)> {
+    for (disk_id, drive) in sata_drives().enumerate() {
+        if target.disk.is_some_and(|disk| disk != disk_id) {
+            continue;
+        }
+
+        let Some(selector) = &target.partition else {
+            return Some((drive.identifier(), None));
+        };
+
+        if let Some(part_id) = drive
+            .partitions()
+            .iter()
+            .position(|partition| partition.matches(selector))
+        {
+            return Some((drive.identifier(), Some(part_id)));
+        }
+    }
+
+    None
+}
+
+/// Reads the boot sector of a disk, or of one of its partitions if `partition` is set.
+///
+/// # Errors
+///
+/// Returns [`BootError::IOError`] if the sector cannot be read, and [`BootError::InvalidImage`]
+/// if it does not end with the boot signature.
+pub fn load_boot_sector(
+    device: AtaDeviceIdentifier,
+    partition: Option<usize>,
+) -> Result<LoadedBootSector, BootError> {
+    let drive = bios_drive_number(device).ok_or(BootError::IOError)?;
+    let disk = get_sata_drive(device).ok_or(BootError::IOError)?;
+    let mbr = disk.read(0, 1).complete().data.ok_or(BootError::IOError)?;
+
+    let (sector, partition_table) = match partition {
+        Some(part_id) => {
+            let sector = read_partition(device, part_id, SECTOR_SIZE).ok_or(BootError::IOError)?;
+            let entry = disk
+                .partitions()
+                .get(part_id)
+                .filter(|part| matches!(part.metadata(), PartitionMetadata::MBR(_)))
+                .map(Partition::id)
+                .filter(|&entry| entry < MBR_PARTITION_ENTRIES);
+
+            (sector, entry.map(|entry| (mbr, entry)))
+        }
+        None => (mbr, None),
+    };
+
+    if sector.len() < SECTOR_SIZE || sector[SECTOR_SIZE - 2..SECTOR_SIZE] != BOOT_SIGNATURE {
+        return Err(BootError::InvalidImage);
+    }
+
+    info!(
+        "chainload",
+        "loaded boot sector ({}    partition_id = {:?}    drive = {:#x})", device, partition, drive
+    );
+
+    Ok(LoadedBootSector {
+        sector,
+        drive,
+        partition_table,
+    })
+}
+
+/// Copies a boot sector at [`BOOT_SECTOR_ADDR`], switches back to real mode, and hands out
+/// control to it.
+///
+/// The _PIC_ is remapped to the _BIOS_ interrupt vectors, and the _BIOS_ interrupt vector table is
+/// restored, so that the boot sector can use the _BIOS_ services.
+pub fn boot_chainload(boot_sector: LoadedBootSector) -> ! {
+    let trampoline = real_mode_trampoline as unsafe extern "C" fn() as usize;
+    let segment = u16::try_from(trampoline >> 4).expect("invalid trampoline address");
+    let partition_entry = boot_sector
+        .partition_table
+        .as_ref()
+        .map_or(0, |(_, entry)| {
+            MBR_RELOCATION_ADDR + MBR_PARTITION_TABLE_OFFSET + entry * MBR_PARTITION_ENTRY_SIZE
+        });
+    let partition_entry = u16::try_from(partition_entry).expect("invalid partition entry address");
+
+    info!(
+        "chainload",
+        "jumping to boot sector (addr = {:#x}    drive = {:#x})",
+        BOOT_SECTOR_ADDR,
+        boot_sector.drive
+    );
+
+    disable_interrupts();
+
+    unsafe {
+        ptr::copy_nonoverlapping(
+            boot_sector.sector.as_ptr(),
+            BOOT_SECTOR_ADDR as *mut u8,
+            SECTOR_SIZE,
+        );
+
+        if let Some((mbr, _)) = &boot_sector.partition_table {
+            ptr::copy_nonoverlapping(mbr.as_ptr(), MBR_RELOCATION_ADDR as *mut u8, SECTOR_SIZE);
+        }
+    }
+
+    PIC::default().remap(BIOS_PIC_OFFSETS.0, BIOS_PIC_OFFSETS.1);
+
+    unsafe {
+        real_mode_init_gdt(
+            PhyAddr32::new(u32::try_from(LONG_GDT_ADDR).expect("invalid gdt address")),
+            PhyAddr::new(u64::try_from(trampoline).expect("invalid trampoline address")),
+        );
+
+        asm!("lidt [{}]", in(reg) &BIOS_IVT, options(nostack, readonly, preserves_flags));
+
+        asm!(
+            "mov esi, {partition_entry}",
+            "mov esp, 0x7C00",
+            "push 0x08",
+            "push 0",
+            "retf",
+            partition_entry = in(reg) u32::from(partition_entry),
+            in("ecx") u32::from(segment),
+            in("edx") u32::from(boot_sector.drive),
+            options(noreturn)
+        );
+    }
+}
@@ -0,0 +1,88 @@
+//! Boot configuration file loading.
+
+use alloc::vec::Vec;
+use fzboot::{
+    boot::config::{BootConfig, CONFIG_PATH},
+    drivers::generics::dev_disk::{sata_drives, DiskDevice},
+    error,
+    errors::IOError,
+    fs::partitions::Partition,
+    info,
+};
+
+use super::measure::measure_image;
+
+/// Reads and parses the configuration file, stored on the first partition containing it (see
+/// [`find_config`]).
+///
+/// Returns the configuration, along with the partition on which it is stored: the paths of the boot
+/// entries are relative to its root directory.
+///
+/// Parse errors are reported on the console, along with their line number. Returns `None` if no
+/// partition contains the configuration file.
+pub fn load_boot_config() -> Option<(BootConfig, Partition)> {
+    let Some((partition, content)) = find_config() else {
+        info!(
+            "config",
+            "{} not found, using the default configuration", CONFIG_PATH
+        );
+        return None;
+    };
+
+    measure_image(CONFIG_PATH, &content);
+
+    let Ok(content) = core::str::from_utf8(&content) else {
+        error!("config", "{} is not a valid UTF-8 file", CONFIG_PATH);
+        return None;
+    };
+
+    let (config, errors) = BootConfig::parse(content);
+
+    for err in &errors {
+        error!("config", "{}: {}", CONFIG_PATH, err);
+    }
+
+    info!(
+        "config",
+        "loaded {} (entries = {}    errors = {})",
+        CONFIG_PATH,
+        config.entries.len(),
+        errors.len()
+    );
+
+    Some((config, partition))
+}
+
+/// Looks for the configuration file on every partition with a supported filesystem, in the order
+/// in which disks are enumerated.
+///
+/// Returns the first partition containing it, along with its content. Partitions on which the file
+/// cannot be read are skipped.
+fn find_config() -> Option<(Partition, Vec<u8>)> {
+    for drive in sata_drives() {
+        for partition in drive.partitions() {
+            let mut partition = partition.clone();
+
+            if !partition.is_mounted() && partition.load_fs().is_err() {
+                continue;
+            }
+
+            match partition.read_file(CONFIG_PATH) {
+                Ok(content) => return Some((partition, content)),
+                Err(IOError::NotFound) => {}
+                Err(err) => {
+                    error!(
+                        "config",
+                        "failed to read {} ({}    partition_id = {}): {:?}",
+                        CONFIG_PATH,
+                        partition.drive_id(),
+                        partition.id(),
+                        err
+                    );
+                }
+            }
+        }
+    }
+
+    None
+}
@@ -0,0 +1,84 @@
+//! Raw disk access used to read boot images stored on partitions.
+
+use alloc::vec::Vec;
+use fzboot::{
+    drivers::{
+        generics::dev_disk::{get_sata_drive, sata_drives, DiskDevice},
+        ide::AtaDeviceIdentifier,
+    },
+    fs::partitions::PartitionSelector,
+};
+
+/// Maximum number of sectors read with a single disk request.
+const MAX_SECTORS_PER_READ: usize = 0x100;
+
+/// _BIOS_ drive number of the first hard disk.
+const BIOS_FIRST_HARD_DISK: u8 = 0x80;
+
+/// Reads the first `size` bytes of a partition.
+///
+/// Returns `None` if the device or the partition does not exist, or in case of disk I/O error.
+pub fn read_partition(
+    device: AtaDeviceIdentifier,
+    partition: usize,
+    size: usize,
+) -> Option<Vec<u8>> {
+    let device = get_sata_drive(device)?;
+    let start_lba = device.partitions().get(partition)?.start_lba();
+
+    let sectors = size.div_ceil(0x200);
+    let mut data = Vec::with_capacity(sectors * 0x200);
+    let mut sectors_read = 0;
+
+    while sectors_read < sectors {
+        let sectors_count = (sectors - sectors_read).min(MAX_SECTORS_PER_READ);
+        let read_data = device
+            .read(
+                start_lba + u64::try_from(sectors_read).expect("invalid sectors count"),
+                u16::try_from(sectors_count).expect("invalid sectors count"),
+            )
+            .complete()
+            .data?;
+
+        data.extend_from_slice(&read_data);
+        sectors_read += sectors_count;
+    }
+
+    data.truncate(size);
+    Some(data)
+}
+
+/// Reads the whole content of a partition.
+///
+/// Returns `None` if the device or the partition does not exist, or in case of disk I/O error.
+pub fn read_whole_partition(device: AtaDeviceIdentifier, partition: usize) -> Option<Vec<u8>> {
+    let sectors = get_sata_drive(device)?
+        .partitions()
+        .get(partition)?
+        .sectors_count();
+
+    read_partition(device, partition, usize::try_from(sectors * 0x200).ok()?)
+}
+
+/// Returns the disk, and the index of the first partition matching `selector`.
+///
+/// Disks are searched in the order in which they are enumerated (see [`sata_drives`]), and
+/// partitions in the order in which they are defined in the partition table.
+pub fn locate_partition(selector: &PartitionSelector) -> Option<(AtaDeviceIdentifier, usize)> {
+    sata_drives().find_map(|drive| {
+        drive
+            .partitions()
+            .iter()
+            .position(|partition| partition.matches(selector))
+            .map(|part_id| (drive.identifier(), part_id))
+    })
+}
+
+/// Returns the _BIOS_ drive number of a disk.
+///
+/// Hard disks are numbered from `0x80`, in the order they are enumerated.
+pub fn bios_drive_number(device: AtaDeviceIdentifier) -> Option<u8> {
+    let index = sata_drives().position(|drive| drive.identifier() == device)?;
+
+    BIOS_FIRST_HARD_DISK.checked_add(u8::try_from(index).ok()?)
+}
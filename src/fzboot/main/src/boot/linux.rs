@@ -0,0 +1,195 @@
+//! Linux x86 boot protocol support.
+//!
+//! Loads a `bzImage` kernel along with its initial ramdisk and command line, fills the
+//! [`BootParams`] structure, and jumps to the 32-bit or 64-bit kernel entry point.
+
+use core::{arch::asm, ptr};
+
+use alloc::{boxed::Box, vec::Vec};
+use fzboot::{
+    boot::linux::{BootParams, LinuxKernelImage, SetupHeader, LINUX_64BIT_ENTRY_OFFSET},
+    drivers::ide::AtaDeviceIdentifier,
+    errors::BootError,
+    info,
+    mem::{e820::E820MemoryMap, PhyAddr, PhyAddr32},
+    video::vesa::video_mode::{ModeInfoBlock, VESA_MODE_BUFFER},
+    x86::{
+        descriptors::gdt::{linux_init_gdt, LONG_GDT_ADDR},
+        int::disable_interrupts,
+        paging::bootinit_paging,
+    },
+};
+
+use super::{
+    disk::read_partition,
+    memory::{BootMemoryMap, MAX_PHYS_ADDR},
+};
+
+/// A Linux kernel loaded in memory, ready to be started.
+#[derive(Debug)]
+pub struct LoadedLinuxKernel {
+    params: *mut BootParams,
+    kernel_addr: u64,
+    long_mode: bool,
+}
+
+/// Reads a `bzImage` kernel image stored on a raw partition.
+///
+/// Returns `None` if the partition does not start with a valid Linux setup header, or in case of
+/// disk I/O error.
+pub fn read_raw_bzimage(device: AtaDeviceIdentifier, partition: usize) -> Option<Vec<u8>> {
+    let setup_sectors = read_partition(device, partition, 0x400)?;
+    let header = SetupHeader::from_image(&setup_sectors).ok()?;
+    let image = read_partition(device, partition, header.image_size())?;
+
+    info!(
+        "linux",
+        "found linux kernel image (protocol = {:#x}    size = {:#x})",
+        header.protocol_version(),
+        header.image_size()
+    );
+
+    Some(image)
+}
+
+/// Loads a `bzImage` kernel image to memory, along with an optional initial ramdisk, and prepares
+/// the boot parameters.
+///
+/// # Errors
+///
+/// Returns [`BootError::InvalidImage`] or [`BootError::UnsupportedProtocol`] if the kernel image
+/// cannot be parsed, and [`BootError::OutOfMemory`] if the kernel or the ramdisk do not fit in
+/// memory.
+pub fn load_linux(
+    image: &[u8],
+    initrd: Option<&[u8]>,
+    cmdline: &str,
+    memory: &mut BootMemoryMap,
+) -> Result<LoadedLinuxKernel, BootError> {
+    let kernel = LinuxKernelImage::parse(image)?;
+    let header = kernel.header();
+    let pm_kernel = kernel.protected_mode_kernel();
+
+    let kernel_addr = if memory.reserve_at(header.pref_address(), header.init_size()) {
+        header.pref_address()
+    } else if header.relocatable() {
+        memory
+            .allocate(header.init_size(), header.kernel_alignment(), MAX_PHYS_ADDR)
+            .ok_or(BootError::OutOfMemory)?
+    } else {
+        return Err(BootError::OutOfMemory);
+    };
+
+    unsafe {
+        ptr::copy_nonoverlapping(
+            pm_kernel.as_ptr(),
+            usize::try_from(kernel_addr).expect("invalid kernel address") as *mut u8,
+            pm_kernel.len(),
+        );
+    }
+
+    info!(
+        "linux",
+        "loaded protected-mode kernel (addr = {:#x}    size = {:#x})",
+        kernel_addr,
+        pm_kernel.len()
+    );
+
+    let mut params = Box::new(BootParams::new(&kernel));
+    params.set_kernel_addr(u32::try_from(kernel_addr).expect("invalid kernel address"));
+
+    if let Some(initrd) = initrd {
+        let initrd_size = u64::try_from(initrd.len()).expect("invalid initrd size");
+        let initrd_addr = memory
+            .allocate(
+                initrd_size,
+                0x1000,
+                header
+                    .initrd_addr_max()
+                    .saturating_add(1)
+                    .min(MAX_PHYS_ADDR),
+            )
+            .ok_or(BootError::OutOfMemory)?;
+
+        unsafe {
+            ptr::copy_nonoverlapping(
+                initrd.as_ptr(),
+                usize::try_from(initrd_addr).expect("invalid initrd address") as *mut u8,
+                initrd.len(),
+            );
+        }
+        params.set_ramdisk(initrd_addr, initrd_size);
+
+        info!(
+            "linux",
+            "loaded initial ramdisk (addr = {:#x}    size = {:#x})", initrd_addr, initrd_size
+        );
+    }
+
+    let cmdline_bytes = cmdline.as_bytes();
+    let mut cmdline_buf: Vec<u8> =
+        cmdline_bytes[..cmdline_bytes.len().min(header.cmdline_size())].into();
+    cmdline_buf.push(0);
+    params.set_cmdline(cmdline_buf.leak().as_ptr() as u64);
+
+    let mode_info = unsafe { ptr::read(VESA_MODE_BUFFER as *const ModeInfoBlock) };
+    params.set_framebuffer(&mode_info);
+    params.set_memory_map(E820MemoryMap::default());
+
+    Ok(LoadedLinuxKernel {
+        params: Box::into_raw(params),
+        kernel_addr,
+        long_mode: header.has_64bit_entry(),
+    })
+}
+
+/// Hands out control to a loaded Linux kernel.
+///
+/// Uses the 64-bit entry point if the kernel provides one, and the 32-bit one otherwise.
+pub fn boot_linux(kernel: LoadedLinuxKernel) -> ! {
+    let params = u32::try_from(kernel.params as usize).expect("invalid boot parameters address");
+    let entry = if kernel.long_mode {
+        kernel.kernel_addr + LINUX_64BIT_ENTRY_OFFSET
+    } else {
+        kernel.kernel_addr
+    };
+    let entry = u32::try_from(entry).expect("invalid kernel entry point");
+
+    info!(
+        "linux",
+        "jumping to kernel entry point (addr = {:#x}    64-bit = {})", entry, kernel.long_mode
+    );
+
+    disable_interrupts();
+
+    unsafe {
+        if kernel.long_mode {
+            bootinit_paging::init_paging();
+            linux_init_gdt(PhyAddr::new(LONG_GDT_ADDR), true);
+        } else {
+            linux_init_gdt(
+                PhyAddr32::new(u32::try_from(LONG_GDT_ADDR).expect("invalid gdt address")),
+                false,
+            );
+        }
+
+        asm!(
+            "push 0x10",
+            "push {entry}",
+            "mov esi, {params}",
+            "mov eax, 0x18",
+            "mov ds, ax",
+            "mov es, ax",
+            "mov fs, ax",
+            "mov gs, ax",
+            "mov ss, ax",
+            "xor ebp, ebp",
+            "xor edi, edi",
+            "xor ebx, ebx",
+            "retf",
+            entry = in(reg) entry,
+            params = in(reg) params,
+            options(noreturn)
+        );
+    }
+}
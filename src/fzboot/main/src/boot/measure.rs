@@ -0,0 +1,54 @@
+//! Measured boot.
+//!
+//! Every image loaded by the bootloader (configuration file, kernel, initial ramdisk, modules and
+//! boot sectors) is measured into [`IMAGES_PCR`], and the kernel command line into
+//! [`CMDLINE_PCR`], following the _PCR_ usage of other _BIOS_ bootloaders. The measurements are
+//! recorded in the _TCG_ event log, which is handed out to native kernels through the boot
+//! information (see [`event_log`]).
+
+use alloc::vec::Vec;
+use fzboot::{
+    drivers::tpm::{self, event_log::EV_IPL, EVENT_LOG},
+    error,
+    errors::TpmError,
+};
+
+/// _PCR_ extended with the kernel command line.
+pub const CMDLINE_PCR: u32 = 8;
+
+/// _PCR_ extended with the images loaded by the bootloader.
+pub const IMAGES_PCR: u32 = 9;
+
+/// Measures an image into [`IMAGES_PCR`], described by its name (usually its path) in the event
+/// log.
+pub fn measure_image(name: &str, data: &[u8]) {
+    measure(IMAGES_PCR, name, data, name.as_bytes());
+}
+
+/// Measures the kernel command line into [`CMDLINE_PCR`].
+pub fn measure_cmdline(cmdline: &str) {
+    measure(
+        CMDLINE_PCR,
+        "kernel command line",
+        cmdline.as_bytes(),
+        cmdline.as_bytes(),
+    );
+}
+
+/// Returns a copy of the event log, empty if nothing has been measured.
+pub fn event_log() -> Vec<u8> {
+    EVENT_LOG.lock().as_bytes().to_vec()
+}
+
+/// Measures `data` into `pcr`, if a _TPM_ is available.
+fn measure(pcr: u32, name: &str, data: &[u8], event: &[u8]) {
+    match tpm::measure(pcr, EV_IPL, data, event) {
+        Ok(()) | Err(TpmError::NotPresent) => {}
+        Err(err) => {
+            error!(
+                "tpm",
+                "failed to measure {} into PCR {}: {:?}", name, pcr, err
+            );
+        }
+    }
+}
@@ -0,0 +1,173 @@
+//! Physical memory placement of the images loaded by the bootloader.
+//!
+//! Keeps track of the physical memory ranges already in use (bootloader code and data, heap and
+//! stack, loaded images), and allocates free ranges out of the _BIOS_-provided memory map.
+
+use core::ptr;
+
+use alloc::vec::Vec;
+use fzboot::mem::{
+    e820::{E820MemType, E820MemoryMap},
+    MEM_STRUCTURE,
+};
+
+use crate::STACK_SIZE;
+
+/// End of the conventional memory, which contains the bootloader code, the _BIOS_ data and most
+/// early boot structures (`GDT`, boot page tables, memory map, VESA information).
+const LOW_MEMORY_END: u64 = 0x100_000;
+
+/// Highest physical address (excluded) that can be accessed before enabling paging.
+pub const MAX_PHYS_ADDR: u64 = 0x1_0000_0000;
+
+/// Physical memory ranges reserved by the bootloader.
+///
+/// Every range is stored as a `(start, end)` tuple, the end address being excluded.
+#[derive(Clone, Debug)]
+pub struct BootMemoryMap {
+    reserved: Vec<(u64, u64)>,
+}
+
+impl BootMemoryMap {
+    /// Creates a new `BootMemoryMap`, in which the conventional memory and the bootloader heap and
+    /// stack are already reserved.
+    ///
+    /// # Panics
+    ///
+    /// Panics if called before initializing the bootloader heap.
+    pub fn new() -> Self {
+        let mem_struct = MEM_STRUCTURE.get().expect("heap is not initialized");
+        let heap_addr = u64::try_from(mem_struct.heap_addr).expect("invalid heap address");
+        let heap_size =
+            u64::try_from(mem_struct.heap_size + STACK_SIZE).expect("invalid heap size");
+
+        let mut memory_map = Self {
+            reserved: Vec::new(),
+        };
+        memory_map.reserve(0, LOW_MEMORY_END);
+        memory_map.reserve(heap_addr, heap_size);
+
+        memory_map
+    }
+
+    /// Marks a physical memory range as used.
+    pub fn reserve(&mut self, start: u64, size: u64) {
+        self.reserved.push((start, start + size));
+    }
+
+    /// Reserves a physical memory range at a fixed address.
+    ///
+    /// Returns `false` if the range is not entirely located in usable memory, or if it overlaps
+    /// with an already reserved range.
+    pub fn reserve_at(&mut self, start: u64, size: u64) -> bool {
+        let usable = E820MemoryMap::default().any(|entry| {
+            matches!(entry.addr_type, E820MemType::RAM)
+                && entry.phys_base() <= start
+                && start + size <= entry.phys_base() + entry.length()
+        });
+
+        if !usable || self.overlapping_range(start, size).is_some() {
+            return false;
+        }
+
+        self.reserve(start, size);
+        true
+    }
+
+    /// Allocates and reserves a physical memory range of `size` bytes, aligned on `align` bytes,
+    /// and located below `max_addr`.
+    ///
+    /// Returns the start address of the range, or `None` if no range is available.
+    pub fn allocate(&mut self, size: u64, align: u64, max_addr: u64) -> Option<u64> {
+        for entry in E820MemoryMap::default() {
+            if !matches!(entry.addr_type, E820MemType::RAM) {
+                continue;
+            }
+
+            let entry_end = (entry.phys_base() + entry.length()).min(max_addr);
+            let mut start = entry.phys_base().next_multiple_of(align);
+
+            while start + size <= entry_end {
+                match self.overlapping_range(start, size) {
+                    Some(range_end) => start = range_end.next_multiple_of(align),
+                    None => {
+                        self.reserve(start, size);
+                        return Some(start);
+                    }
+                }
+            }
+        }
+
+        None
+    }
+
+    /// Allocates and reserves a physical memory range of `size` bytes, aligned on `align` bytes,
+    /// and located below `max_addr`, picked among every available range using the random number
+    /// `random`.
+    ///
+    /// Returns the start address of the range, or `None` if no range is available.
+    pub fn allocate_random(
+        &mut self,
+        size: u64,
+        align: u64,
+        max_addr: u64,
+        random: u64,
+    ) -> Option<u64> {
+        let candidates = || {
+            E820MemoryMap::default()
+                .filter(|entry| matches!(entry.addr_type, E820MemType::RAM))
+                .flat_map(move |entry| {
+                    let entry_end = (entry.phys_base() + entry.length()).min(max_addr);
+                    let first = entry.phys_base().next_multiple_of(align);
+
+                    (0..)
+                        .map(move |idx| first + idx * align)
+                        .take_while(move |start| start + size <= entry_end)
+                })
+                .filter(|&start| self.overlapping_range(start, size).is_none())
+        };
+
+        let count = u64::try_from(candidates().count()).expect("invalid candidates count");
+
+        if count == 0 {
+            return None;
+        }
+
+        let start = candidates().nth(usize::try_from(random % count).expect("invalid index"))?;
+        self.reserve(start, size);
+
+        Some(start)
+    }
+
+    /// Copies `data` to a newly allocated physical memory range, aligned on `align` bytes and
+    /// located below [`MAX_PHYS_ADDR`].
+    ///
+    /// Returns the start and end addresses of the range, or `None` if no range is available.
+    pub fn load(&mut self, data: &[u8], align: u64) -> Option<(u64, u64)> {
+        let size = u64::try_from(data.len()).expect("invalid data size");
+        let start = self.allocate(size.max(1), align, MAX_PHYS_ADDR)?;
+
+        unsafe {
+            ptr::copy_nonoverlapping(
+                data.as_ptr(),
+                usize::try_from(start).expect("invalid load address") as *mut u8,
+                data.len(),
+            );
+        }
+
+        Some((start, start + size))
+    }
+
+    /// Returns the reserved physical memory ranges, as `(start, end)` tuples.
+    pub fn reserved_ranges(&self) -> &[(u64, u64)] {
+        &self.reserved
+    }
+
+    /// Returns the end address of a reserved range overlapping with the given one, if any.
+    fn overlapping_range(&self, start: u64, size: u64) -> Option<u64> {
+        self.reserved
+            .iter()
+            .find(|(range_start, range_end)| start < *range_end && *range_start < start + size)
+            .map(|(_, range_end)| *range_end)
+    }
+}
@@ -0,0 +1,202 @@
+//! Interactive boot menu.
+//!
+//! Lists the bootable targets on the framebuffer, and lets the user pick one with the keyboard
+//! arrows. The default target is booted once the timeout expires, unless a key is pressed. The
+//! kernel command line of the selected target can be edited by pressing `e`.
+
+use alloc::{string::String, vec::Vec};
+use fzboot::{
+    io::ps2::keyboard::{Key, Keyboard},
+    time,
+    video::vesa::{framebuffer::TextFrameBuffer, text_buffer},
+};
+
+use super::target::BootTarget;
+
+/// Default number of seconds before booting the default target.
+pub const DEFAULT_TIMEOUT: u32 = 5;
+
+/// One second, in the unit used by [`time::now`] (microseconds).
+const SECOND: f64 = 1_000_000.0;
+
+/// Title displayed at the top of the menu.
+const MENU_TITLE: &str = "FrozenBoot";
+
+/// Outcome of the command line editor.
+enum EditResult {
+    /// The new command line was confirmed.
+    Confirmed(String),
+
+    /// Edition was cancelled.
+    Cancelled,
+}
+
+/// Displays the boot menu, and returns the index of the target to boot.
+///
+/// `default` is the index of the target selected initially, and booted once `timeout` seconds
+/// have elapsed without any key press. A timeout of 0 boots the default target without displaying
+/// the menu.
+///
+/// # Panics
+///
+/// Panics if `targets` is empty.
+pub fn select_target(targets: &mut [BootTarget], default: usize, timeout: u32) -> usize {
+    assert!(!targets.is_empty(), "no bootable target");
+
+    let mut selected = default.min(targets.len() - 1);
+
+    if timeout == 0 {
+        return selected;
+    }
+
+    let mut keyboard = Keyboard::new();
+    let mut remaining = Some(timeout);
+    let mut next_tick = time::now() + SECOND;
+
+    draw_menu(targets, selected, remaining);
+
+    loop {
+        if let Some(seconds) = remaining {
+            if time::now() >= next_tick {
+                if seconds <= 1 {
+                    return selected;
+                }
+
+                remaining = Some(seconds - 1);
+                next_tick += SECOND;
+                draw_menu(targets, selected, remaining);
+            }
+        }
+
+        let Some(key) = keyboard.poll() else {
+            continue;
+        };
+
+        // any key press stops the countdown
+        remaining = None;
+
+        match key {
+            Key::Up | Key::Char('k') => selected = selected.saturating_sub(1),
+            Key::Down | Key::Char('j') => selected = (selected + 1).min(targets.len() - 1),
+            Key::Home => selected = 0,
+            Key::End => selected = targets.len() - 1,
+            Key::Enter => return selected,
+            Key::Char('e') => {
+                let target = &mut targets[selected];
+
+                if let EditResult::Confirmed(cmdline) =
+                    edit_cmdline(&mut keyboard, &target.title, &target.cmdline)
+                {
+                    target.cmdline = cmdline;
+                }
+            }
+            _ => continue,
+        }
+
+        draw_menu(targets, selected, remaining);
+    }
+}
+
+/// Lets the user edit a kernel command line.
+fn edit_cmdline(keyboard: &mut Keyboard, title: &str, cmdline: &str) -> EditResult {
+    let mut line: Vec<char> = cmdline.chars().collect();
+    let mut cursor = line.len();
+
+    loop {
+        draw_editor(title, &line, cursor);
+
+        let key = loop {
+            if let Some(key) = keyboard.poll() {
+                break key;
+            }
+        };
+
+        match key {
+            Key::Enter => return EditResult::Confirmed(line.into_iter().collect()),
+            Key::Escape => return EditResult::Cancelled,
+            Key::Left => cursor = cursor.saturating_sub(1),
+            Key::Right => cursor = (cursor + 1).min(line.len()),
+            Key::Home => cursor = 0,
+            Key::End => cursor = line.len(),
+            Key::Backspace if cursor > 0 => {
+                cursor -= 1;
+                line.remove(cursor);
+            }
+            Key::Delete if cursor < line.len() => {
+                line.remove(cursor);
+            }
+            Key::Char(ch) => {
+                line.insert(cursor, ch);
+                cursor += 1;
+            }
+            _ => {}
+        }
+    }
+}
+
+/// Draws the list of targets, along with the countdown if it is still running.
+fn draw_menu(targets: &[BootTarget], selected: usize, remaining: Option<u32>) {
+    let mut framebuffer = text_buffer().buffer.lock();
+
+    draw_header(&mut framebuffer);
+
+    for (index, target) in targets.iter().enumerate() {
+        framebuffer.write_str_bitmap("    ");
+
+        if index == selected {
+            framebuffer.write_str_bitmap_reversed(&target.title);
+        } else {
+            framebuffer.write_str_bitmap(&target.title);
+        }
+
+        framebuffer.write_str_bitmap("\n");
+    }
+
+    framebuffer.write_str_bitmap("\n    cmdline: ");
+    framebuffer.write_str_bitmap(&targets[selected].cmdline);
+    framebuffer.write_str_bitmap("\n\n");
+    framebuffer.write_str_bitmap(
+        "Use the up and down arrows to select an entry, Enter to boot it, or 'e' to edit its command line.\n",
+    );
+
+    if let Some(seconds) = remaining {
+        framebuffer.write_str_bitmap(&alloc::format!(
+            "The selected entry will be booted automatically in {seconds}s.\n"
+        ));
+    }
+}
+
+/// Draws the command line editor, with the cursor displayed as a reversed character.
+fn draw_editor(title: &str, line: &[char], cursor: usize) {
+    let mut framebuffer = text_buffer().buffer.lock();
+    let mut buffer = [0; 4];
+
+    draw_header(&mut framebuffer);
+
+    framebuffer.write_str_bitmap("    Editing the command line of ");
+    framebuffer.write_str_bitmap(title);
+    framebuffer.write_str_bitmap("\n\n    ");
+
+    for (index, ch) in line.iter().enumerate() {
+        let ch = ch.encode_utf8(&mut buffer);
+
+        if index == cursor {
+            framebuffer.write_str_bitmap_reversed(ch);
+        } else {
+            framebuffer.write_str_bitmap(ch);
+        }
+    }
+
+    if cursor == line.len() {
+        framebuffer.write_str_bitmap_reversed(" ");
+    }
+
+    framebuffer.write_str_bitmap("\n\nPress Enter to confirm, or Esc to discard the changes.\n");
+}
+
+/// Clears the screen, and draws the menu title.
+fn draw_header(framebuffer: &mut TextFrameBuffer) {
+    framebuffer.clear();
+    framebuffer.write_str_bitmap_centered(MENU_TITLE, false);
+    framebuffer.write_str_bitmap("\n\n");
+}
@@ -1,108 +1,451 @@
-pub mod headers;
+pub mod bls;
+pub mod boot_info;
+pub mod chainload;
+pub mod config;
+pub mod disk;
+pub mod linux;
+pub mod measure;
+pub mod memory;
+pub mod menu;
+pub mod multiboot;
+pub mod slots;
+pub mod target;
+pub mod verify;
 
 /// Kernel loading related code.
 pub mod fzkernel {
-    use core::cmp::min;
+    use core::{arch::asm, ptr};
 
-    use alloc::format;
-    use fzboot::kernel_syms::{KERNEL_LOAD_ADDR, KERNEL_SECTOR_SZ};
+    use alloc::{string::String, vec::Vec};
+
+    use fzboot::boot::elf::{ElfClass, ElfHeader, ElfImage, ElfProgramHeader};
+    use fzboot::boot::kaslr::{random_virt_base, KaslrMode, KASLR_ALIGN};
+    use fzboot::errors::{BootError, CanFail};
+    use fzboot::kernel_syms::PAGE_SIZE;
+    use fzboot::x86::descriptors::gdt::{long_init_gdt, LONG_GDT_ADDR};
     use fzboot::x86::paging::bootinit_paging;
+    use fzboot::x86::random::RandomSource;
     use fzboot::{
-        drivers::{
-            generics::dev_disk::{get_sata_drive, sata_drives, DiskDevice},
-            ide::AtaDeviceIdentifier,
-        },
+        drivers::ide::AtaDeviceIdentifier,
+        fs::partitions::PartitionSelector,
         info,
-        mem::{MemoryAddress, PhyAddr},
-        println,
+        kernel_syms::KERNEL_PAGE_TABLE,
+        mem::{PhyAddr, VirtAddr},
     };
 
+    use super::disk::{locate_partition, read_partition};
+    use super::memory::{BootMemoryMap, MAX_PHYS_ADDR};
+
+    /// Number of bytes read from the kernel partition to locate the kernel segments.
+    const KERNEL_HEADERS_SIZE: usize = 0x1000;
+
+    /// Partition name used to locate the kernel, if no selector is configured.
+    const DEFAULT_KERNEL_LABEL: &str = "kernelfs";
+
     /// Attempts to locate the partition containing the kernel code.
-    /// Returns the drive and the partition id of the one on which the kernel is stored.
+    /// Returns the drive and the partition id of the first one matching `selectors`.
     ///
-    /// Currently, only uses the name of the partition as a reference.
-    pub fn locate_kernel_partition() -> (AtaDeviceIdentifier, usize) {
-        let mut kernel_disk = AtaDeviceIdentifier::new(
-            fzboot::drivers::generics::dev_disk::SataDeviceType::AHCI,
-            0,
-            0,
-        );
-
-        let mut kernel_part_id = 0;
-        let mut found_kernel = false;
-
-        for drive in sata_drives() {
-            for (part_id, partition) in drive.partitions().iter().enumerate() {
-                match partition.metadata() {
-                    fzboot::fs::partitions::PartitionMetadata::MBR(mbr_part) => todo!(),
-                    fzboot::fs::partitions::PartitionMetadata::GPT(gpt_part) => {
-                        if gpt_part.name() == "kernelfs" {
-                            kernel_disk = drive.identifier();
-                            kernel_part_id = part_id;
-                            found_kernel = true;
-
-                            break;
-                        }
-                    }
-                }
-            }
+    /// Selectors are tried by order of priority, each of them using [`locate_partition`].
+    ///
+    /// If `selectors` is empty, looks for a _GPT_ partition named [`DEFAULT_KERNEL_LABEL`], then
+    /// for an _active_ _MBR_ partition.
+    pub fn locate_kernel_partition(
+        selectors: &[PartitionSelector],
+    ) -> Option<(AtaDeviceIdentifier, usize)> {
+        let default_selectors = [
+            PartitionSelector::Label(String::from(DEFAULT_KERNEL_LABEL)),
+            PartitionSelector::Mbr {
+                part_type: None,
+                active: true,
+            },
+        ];
+        let selectors = if selectors.is_empty() {
+            &default_selectors[..]
+        } else {
+            selectors
+        };
 
-            if found_kernel {
+        for selector in selectors {
+            if let Some((device, part_id)) = locate_partition(selector) {
                 info!(
                     "kernel",
-                    "located kernel image ({}    partition_id = {})", kernel_disk, kernel_part_id
+                    "located kernel partition ({}    partition_id = {}    selector = {:?})",
+                    device,
+                    part_id,
+                    selector
                 );
-                break;
+
+                return Some((device, part_id));
             }
         }
 
-        if !found_kernel {
-            panic!("failed to locate kernel");
+        None
+    }
+
+    /// Reads the kernel image from a disk device.
+    ///
+    /// The kernel is stored as an ELF64 executable: only the part of the partition covered by its
+    /// headers and segments is read.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`BootError::IOError`] if the kernel image cannot be read, and
+    /// [`BootError::InvalidImage`] if its headers are not valid.
+    pub fn read_kernel(
+        device: AtaDeviceIdentifier,
+        partition: usize,
+    ) -> Result<Vec<u8>, BootError> {
+        let mut headers =
+            read_partition(device, partition, KERNEL_HEADERS_SIZE).ok_or(BootError::IOError)?;
+        let headers_end = ElfHeader::from_image(&headers)?.program_headers_end();
+
+        if headers_end > headers.len() {
+            headers = read_partition(device, partition, headers_end).ok_or(BootError::IOError)?;
         }
 
-        (kernel_disk, kernel_part_id)
+        read_partition(device, partition, ElfImage::image_size(&headers)?).ok_or(BootError::IOError)
     }
 
-    /// Loads the kernel in memory from a disk device.
-    pub fn load_kernel(device: AtaDeviceIdentifier, partition: usize) {
-        let device = get_sata_drive(device).expect("could not find kernel disk device");
-        let partition = device
-            .partitions()
-            .get(partition)
-            .expect("could not find kernel partition");
+    /// Location of a kernel image loaded to memory.
+    #[derive(Clone, Copy, Debug)]
+    pub struct LoadedKernel {
+        /// Virtual address of the entry point.
+        pub entry: VirtAddr,
 
-        let mut sectors_read = 0;
+        /// Virtual address of the lowest loadable segment.
+        pub virt_base: u64,
 
-        while sectors_read < KERNEL_SECTOR_SZ {
-            let read = device.read(
-                partition.start_lba() + u64::try_from(sectors_read).expect("invalid sectors count"),
-                0x100,
-            );
-            let result = read.complete();
-            let read_data = result.data.expect(
-                format!(
-                    "invalid data read when loading kernel (sector {})",
-                    sectors_read
-                )
-                .as_str(),
+        /// Physical address of the lowest loadable segment.
+        pub phys_base: u64,
+
+        /// Size of the image in memory, from the start of its lowest loadable segment to the end of
+        /// its highest one.
+        pub size: u64,
+
+        /// Offset added to the link-time virtual addresses of the image, 0 if it has not been
+        /// relocated.
+        pub relocation_offset: u64,
+
+        /// Whether the image is position-independent, and has been relocated.
+        pub relocated: bool,
+
+        /// Whether the base addresses of the image have been randomised.
+        pub randomized: bool,
+    }
+
+    /// Loads a kernel image, stored as an ELF64 executable, to memory.
+    ///
+    /// Every loadable segment is copied to physical memory, and mapped to its virtual address
+    /// using [`bootinit_paging`]. The page table located at [`KERNEL_PAGE_TABLE`], from which the
+    /// kernel builds its address space, is reserved as well.
+    ///
+    /// Executables linked at fixed addresses are loaded at their link addresses. Position-independent
+    /// executables are loaded in the kernel code window, at addresses randomised according to
+    /// `kaslr`, and relocated (see [`fzboot::boot::kaslr`]).
+    ///
+    /// # Errors
+    ///
+    /// Returns [`BootError::InvalidImage`] or [`BootError::UnsupportedProtocol`] if the image is not
+    /// a valid ELF64 executable, or uses unsupported relocations, and [`BootError::OutOfMemory`] if
+    /// one of its segments, or the kernel page table, cannot be loaded.
+    pub fn load_kernel_image(
+        image: &[u8],
+        kaslr: KaslrMode,
+        memory: &mut BootMemoryMap,
+    ) -> Result<LoadedKernel, BootError> {
+        let kernel = ElfImage::parse(image)?;
+
+        if kernel.header().class() != ElfClass::Elf64 {
+            return Err(BootError::UnsupportedProtocol);
+        }
+
+        let page_size = u64::try_from(PAGE_SIZE).expect("invalid page size");
+
+        if !memory.reserve_at(u64::from(KERNEL_PAGE_TABLE), page_size) {
+            return Err(BootError::OutOfMemory);
+        }
+
+        let loaded = if kernel.header().position_independent() {
+            place_relocatable(&kernel, kaslr, memory)?
+        } else {
+            place_fixed(&kernel, memory)?
+        };
+
+        for segment in kernel.load_segments() {
+            load_segment(&kernel, &segment, &loaded)?;
+        }
+
+        if loaded.relocated {
+            relocate(&kernel, &loaded)?;
+        }
+
+        info!(
+            "kernel",
+            "loaded kernel image to memory (size = {:#x}    entry = {})",
+            image.len(),
+            loaded.entry
+        );
+
+        Ok(loaded)
+    }
+
+    /// Hands out control to a loaded kernel.
+    ///
+    /// Enables the boot paging structures and switches to long mode, then jumps to the kernel entry
+    /// point with the address of the boot information in `RCX` (see
+    /// [`build_boot_info`](super::boot_info::build_boot_info)).
+    pub fn boot_kernel(entry: VirtAddr, boot_info: u32) -> ! {
+        bootinit_paging::init_paging();
+
+        info!("kernel", "jumping to kernel main (addr = {})", entry);
+
+        let entry = u64::from(entry);
+        let entry_low = u32::try_from(entry & 0xFFFF_FFFF).unwrap();
+        let entry_high = u32::try_from(entry >> 32).unwrap();
+
+        unsafe {
+            long_init_gdt(PhyAddr::new(LONG_GDT_ADDR));
+
+            // switch to the 64-bit code segment first, as the entry point may not fit in 32 bits
+            asm!(
+                "push 0x10",
+                "push offset 2f",
+                "retf",
+                ".code64",
+                "2:",
+                "shl rdx, 32",
+                "mov eax, eax",
+                "or rax, rdx",
+                "mov ecx, ecx",
+                "xor ebp, ebp",
+                "jmp rax",
+                ".code32",
+                in("eax") entry_low,
+                in("edx") entry_high,
+                in("ecx") boot_info,
+                options(noreturn)
             );
+        }
+    }
+
+    /// Reserves the physical memory of an image linked at fixed addresses, at the physical load
+    /// address of each of its segments.
+    ///
+    /// Segments sharing a page are reserved as a single range.
+    fn place_fixed(
+        kernel: &ElfImage,
+        memory: &mut BootMemoryMap,
+    ) -> Result<LoadedKernel, BootError> {
+        let page_size = u64::try_from(PAGE_SIZE).expect("invalid page size");
+        let mut ranges: Vec<(u64, u64)> = Vec::new();
+
+        for segment in kernel.load_segments() {
+            if segment.mem_size() == 0 {
+                continue;
+            }
+
+            let page_offset = segment.virt_addr() % page_size;
+
+            if segment.phys_addr() % page_size != page_offset {
+                return Err(BootError::InvalidImage);
+            }
+
+            let phys_base = segment.phys_addr() - page_offset;
+            let phys_end = phys_base
+                .saturating_add((segment.mem_size() + page_offset).next_multiple_of(page_size));
+
+            ranges.push((phys_base, phys_end));
+        }
+
+        ranges.sort_unstable();
+
+        let mut merged: Vec<(u64, u64)> = Vec::with_capacity(ranges.len());
+
+        for (start, end) in ranges {
+            match merged.last_mut() {
+                Some((_, last_end)) if start <= *last_end => *last_end = (*last_end).max(end),
+                _ => merged.push((start, end)),
+            }
+        }
+
+        for (start, end) in merged {
+            if end > MAX_PHYS_ADDR || !memory.reserve_at(start, end - start) {
+                return Err(BootError::OutOfMemory);
+            }
+        }
+
+        let (virt_start, virt_end) = kernel.virt_bounds();
+        let phys_base = kernel
+            .load_segments()
+            .find(|segment| segment.virt_addr() == virt_start)
+            .map_or(0, |segment| segment.phys_addr());
+
+        Ok(LoadedKernel {
+            entry: VirtAddr::new(kernel.entry()),
+            virt_base: virt_start,
+            phys_base,
+            size: virt_end - virt_start,
+            relocation_offset: 0,
+            relocated: false,
+            randomized: false,
+        })
+    }
+
+    /// Picks the virtual and physical base addresses of a position-independent image, and reserves
+    /// its physical memory as a single range.
+    ///
+    /// The segments keep their relative layout, in both virtual and physical memory.
+    fn place_relocatable(
+        kernel: &ElfImage,
+        kaslr: KaslrMode,
+        memory: &mut BootMemoryMap,
+    ) -> Result<LoadedKernel, BootError> {
+        let page_size = u64::try_from(PAGE_SIZE).expect("invalid page size");
+        let (virt_start, virt_end) = kernel.virt_bounds();
+        let link_base = virt_start - virt_start % page_size;
+        let size = (virt_end - link_base).next_multiple_of(page_size);
+
+        let source = RandomSource::detect();
+        let random = |enabled: bool| if enabled { source.next_u64() } else { 0 };
+
+        let virt_base =
+            random_virt_base(size, random(kaslr.randomize_virt())).ok_or(BootError::OutOfMemory)?;
+        let phys_base = if kaslr.randomize_phys() {
+            memory.allocate_random(size, KASLR_ALIGN, MAX_PHYS_ADDR, random(true))
+        } else {
+            memory.allocate(size, KASLR_ALIGN, MAX_PHYS_ADDR)
+        }
+        .ok_or(BootError::OutOfMemory)?;
+
+        let relocation_offset = virt_base.wrapping_sub(link_base);
+
+        info!(
+            "kernel",
+            "placed relocatable kernel (virt = {:#x}    phys = {:#x}    kaslr = {}    source = {})",
+            virt_base,
+            phys_base,
+            kaslr.name(),
+            source.name()
+        );
+
+        Ok(LoadedKernel {
+            entry: VirtAddr::new(kernel.entry().wrapping_add(relocation_offset)),
+            virt_base: virt_start.wrapping_add(relocation_offset),
+            phys_base: phys_base + (virt_start - link_base),
+            size: virt_end - virt_start,
+            relocation_offset,
+            relocated: true,
+            randomized: kaslr.randomize_virt(),
+        })
+    }
+
+    /// Returns the virtual and physical addresses at which a segment is loaded.
+    fn segment_addresses(segment: &ElfProgramHeader, loaded: &LoadedKernel) -> (u64, u64) {
+        let virt_addr = segment.virt_addr().wrapping_add(loaded.relocation_offset);
+
+        if loaded.relocated {
+            (virt_addr, loaded.phys_base + (virt_addr - loaded.virt_base))
+        } else {
+            (virt_addr, segment.phys_addr())
+        }
+    }
+
+    /// Applies the relocations of a position-independent image, once its segments are loaded.
+    fn relocate(kernel: &ElfImage, loaded: &LoadedKernel) -> CanFail<BootError> {
+        let link_start = loaded.virt_base.wrapping_sub(loaded.relocation_offset);
+        let mut count = 0;
+
+        for relocation in kernel.relocations()? {
+            let value = relocation
+                .value(loaded.relocation_offset)
+                .ok_or(BootError::UnsupportedProtocol)?;
+            let offset = relocation
+                .offset()
+                .checked_sub(link_start)
+                .filter(|offset| offset + 8 <= loaded.size)
+                .ok_or(BootError::InvalidImage)?;
 
             unsafe {
-                let mut mem_slice: &mut [u8] = core::slice::from_raw_parts_mut(
-                    (KERNEL_LOAD_ADDR + sectors_read * 0x200).as_mut_ptr(),
-                    min(0x200 * 0x200, read_data.len()),
+                ptr::write_unaligned(
+                    usize::try_from(loaded.phys_base + offset).expect("invalid relocation address")
+                        as *mut u64,
+                    value,
                 );
-                mem_slice.copy_from_slice(&read_data);
             }
 
-            sectors_read += 0x100;
+            count += 1;
         }
 
         info!(
             "kernel",
-            "loaded kernel image to memory (base_addr = {}    virtual_base = {})",
-            KERNEL_LOAD_ADDR,
-            bootinit_paging::KERNEL_CODE_MAPPING_BASE
+            "applied {} relocations (offset = {:#x})", count, loaded.relocation_offset
         );
+
+        Ok(())
+    }
+
+    /// Copies a loadable segment to its physical address, zeroes the remaining memory (`.bss`), and
+    /// maps it to its virtual address with the segment access rights.
+    ///
+    /// The physical memory of the segment must have been reserved beforehand. Only the memory
+    /// covered by the segment is written, so that segments sharing a page do not overwrite each
+    /// other.
+    fn load_segment(
+        kernel: &ElfImage,
+        segment: &ElfProgramHeader,
+        loaded: &LoadedKernel,
+    ) -> CanFail<BootError> {
+        if segment.mem_size() == 0 {
+            return Ok(());
+        }
+
+        let page_size = u64::try_from(PAGE_SIZE).expect("invalid page size");
+        let (virt_addr, phys_addr) = segment_addresses(segment, loaded);
+        let page_offset = virt_addr % page_size;
+
+        if phys_addr % page_size != page_offset {
+            return Err(BootError::InvalidImage);
+        }
+
+        let phys_base = phys_addr - page_offset;
+        let virt_base = virt_addr - page_offset;
+        let size = (segment.mem_size() + page_offset).next_multiple_of(page_size);
+
+        let data = kernel.segment_data(segment);
+
+        let bss_size = usize::try_from(segment.mem_size())
+            .expect("invalid segment size")
+            .saturating_sub(data.len());
+
+        unsafe {
+            let segment_ptr =
+                usize::try_from(phys_addr).expect("invalid segment address") as *mut u8;
+
+            ptr::copy_nonoverlapping(data.as_ptr(), segment_ptr, data.len());
+            ptr::write_bytes(segment_ptr.add(data.len()), 0, bss_size);
+        }
+
+        // segments linked at their physical address are mapped within the boot identity mapping
+        bootinit_paging::map_kernel_segment(
+            VirtAddr::new(virt_base),
+            PhyAddr::new(phys_base),
+            size,
+            segment.writable(),
+            segment.executable(),
+        )
+        .map_err(|_| BootError::InvalidImage)?;
+
+        info!(
+            "kernel",
+            "loaded segment (phys = {:#x}    virt = {:#x}    size = {:#x}    flags = {}{}{})",
+            phys_addr,
+            virt_addr,
+            segment.mem_size(),
+            if segment.readable() { "r" } else { "-" },
+            if segment.writable() { "w" } else { "-" },
+            if segment.executable() { "x" } else { "-" }
+        );
+
+        Ok(())
     }
 }
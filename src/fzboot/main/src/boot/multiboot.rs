@@ -0,0 +1,464 @@
+//! Multiboot boot protocols support.
+//!
+//! Loads a Multiboot or Multiboot2 compliant kernel (either an ELF executable, or a raw binary
+//! described by the header address fields) along with its boot modules, builds the boot
+//! information structure, and jumps to the kernel entry point in 32-bit protected mode.
+
+use core::{arch::asm, mem, ptr};
+
+use alloc::{boxed::Box, vec::Vec};
+use fzboot::{
+    bios::smbios::smbios_entry_point,
+    boot::{
+        elf::ElfImage,
+        multiboot::{
+            basic_memory_info,
+            mb2_header::{Multiboot2Header, MULTIBOOT2_BOOTLOADER_MAGIC, MULTIBOOT2_SEARCH_LIMIT},
+            mb2_information::Multiboot2InformationBuilder,
+            mb_header::{MultibootHeader, MULTIBOOT_BOOTLOADER_MAGIC, MULTIBOOT_SEARCH_LIMIT},
+            mb_information::{multiboot_memory_map, MultibootInformation, MultibootModuleEntry},
+            MultibootLoadAddress,
+        },
+    },
+    drivers::{
+        generics::dev_disk::{get_sata_drive, DiskDevice},
+        ide::AtaDeviceIdentifier,
+    },
+    errors::{BootError, CanFail},
+    info,
+    io::acpi::RSDP,
+    mem::{e820::E820MemoryMap, PhyAddr32},
+    video::vesa::video_mode::{ModeInfoBlock, VESA_MODE_BUFFER},
+    x86::{
+        descriptors::gdt::{linux_init_gdt, LONG_GDT_ADDR},
+        int::disable_interrupts,
+    },
+};
+
+use super::{
+    disk::{bios_drive_number, read_partition},
+    memory::{BootMemoryMap, MAX_PHYS_ADDR},
+};
+
+/// Name of the bootloader, as reported to the kernel.
+const BOOTLOADER_NAME: &str = "FrozenBoot";
+
+/// Alignment of the boot modules in memory.
+///
+/// Modules are always page aligned, which satisfies the module alignment requested by the kernel.
+const MODULE_ALIGN: u64 = 0x1000;
+
+/// A boot module, loaded in memory along with a Multiboot kernel.
+#[derive(Clone, Copy, Debug)]
+pub struct MultibootModule<'a> {
+    /// Content of the module.
+    pub data: &'a [u8],
+
+    /// Command line (or name) associated with the module.
+    pub cmdline: &'a str,
+}
+
+/// A Multiboot kernel loaded in memory, ready to be started.
+#[derive(Debug)]
+pub struct LoadedMultibootKernel {
+    entry: u32,
+    information: u32,
+    magic: u32,
+}
+
+/// Reads a Multiboot kernel image stored on a raw partition.
+///
+/// Returns `None` if the partition does not start with a valid Multiboot kernel image, or in case
+/// of disk I/O error.
+pub fn read_raw_multiboot(device: AtaDeviceIdentifier, partition: usize) -> Option<Vec<u8>> {
+    let header_area = read_partition(device, partition, MULTIBOOT_SEARCH_LIMIT)?;
+    let header = MultibootHeader::locate(&header_area).ok()??;
+
+    info!(
+        "multiboot",
+        "found multiboot kernel image (header_offset = {:#x})",
+        header.offset()
+    );
+
+    read_raw_image(device, partition, &header_area, header.load_address())
+}
+
+/// Reads a Multiboot2 kernel image stored on a raw partition.
+///
+/// Returns `None` if the partition does not start with a valid Multiboot2 kernel image, or in case
+/// of disk I/O error.
+pub fn read_raw_multiboot2(device: AtaDeviceIdentifier, partition: usize) -> Option<Vec<u8>> {
+    let header_area = read_partition(device, partition, MULTIBOOT2_SEARCH_LIMIT)?;
+    let header = Multiboot2Header::locate(&header_area).ok()??;
+
+    info!(
+        "multiboot",
+        "found multiboot2 kernel image (header_offset = {:#x})",
+        header.offset()
+    );
+
+    read_raw_image(device, partition, &header_area, header.load_address())
+}
+
+/// Loads a Multiboot kernel image to memory, along with its boot modules, and builds the boot
+/// information structure.
+///
+/// `boot_device` is the disk and partition from which the kernel image was read.
+///
+/// # Errors
+///
+/// Returns [`BootError::InvalidImage`] or [`BootError::UnsupportedProtocol`] if the kernel image
+/// cannot be parsed or requires unsupported features, and [`BootError::OutOfMemory`] if the
+/// kernel or one of the modules cannot be loaded.
+pub fn load_multiboot(
+    image: &[u8],
+    modules: &[MultibootModule],
+    cmdline: &str,
+    boot_device: (AtaDeviceIdentifier, usize),
+    memory: &mut BootMemoryMap,
+) -> Result<LoadedMultibootKernel, BootError> {
+    let header = MultibootHeader::locate(image)?.ok_or(BootError::InvalidImage)?;
+    let entry = load_kernel_image(image, header.load_address(), header.entry_addr(), memory)?;
+
+    let mut information = MultibootInformation::default();
+    information.set_cmdline(leak_c_string(cmdline));
+    information.set_bootloader_name(leak_c_string(BOOTLOADER_NAME));
+
+    if !modules.is_empty() {
+        let mut entries = Vec::with_capacity(modules.len());
+
+        for module in modules {
+            let (start, end) = load_module(module, memory)?;
+            entries.push(MultibootModuleEntry::new(
+                PhyAddr32::new(start),
+                PhyAddr32::new(end),
+                leak_c_string(module.cmdline),
+            ));
+        }
+
+        let entries = entries.leak();
+        information.set_modules(
+            phys_addr_of(entries.as_ptr()),
+            u32::try_from(entries.len()).expect("invalid modules count"),
+        );
+    }
+
+    // the memory information is always provided, whether the kernel requires it or not
+    let (mem_lower, mem_upper) = basic_memory_info(E820MemoryMap::default());
+    information.set_memory_info(mem_lower, mem_upper);
+
+    let memory_map = multiboot_memory_map(E820MemoryMap::default()).leak();
+    information.set_memory_map(
+        phys_addr_of(memory_map.as_ptr()),
+        u32::try_from(mem::size_of_val(memory_map)).expect("invalid memory map length"),
+    );
+
+    if let Some(drive) = bios_drive_number(boot_device.0) {
+        information.set_boot_device(
+            drive,
+            u8::try_from(boot_device.1).expect("invalid boot partition"),
+        );
+    }
+
+    if let Some(request) = header.video_mode() {
+        let mode_info = unsafe { ptr::read(VESA_MODE_BUFFER as *const ModeInfoBlock) };
+
+        if !request.matches(
+            u32::from(mode_info.width),
+            u32::from(mode_info.height),
+            u32::from(mode_info.bits_per_pixel),
+        ) {
+            info!(
+                "multiboot",
+                "requested video mode is not available (width = {}    height = {}    depth = {})",
+                request.width(),
+                request.height(),
+                request.depth()
+            );
+        }
+        information.insert_framebuffer_info(mode_info);
+    }
+
+    let information: &MultibootInformation = Box::leak(Box::new(information));
+
+    info!(
+        "multiboot",
+        "loaded multiboot kernel (entry = {:#x}    modules = {})",
+        entry,
+        modules.len()
+    );
+
+    Ok(LoadedMultibootKernel {
+        entry,
+        information: u32::try_from(ptr::from_ref(information) as usize)
+            .expect("invalid boot information address"),
+        magic: MULTIBOOT_BOOTLOADER_MAGIC,
+    })
+}
+
+/// Loads a Multiboot2 kernel image to memory, along with its boot modules, and builds the boot
+/// information structure.
+///
+/// # Errors
+///
+/// Returns [`BootError::InvalidImage`] or [`BootError::UnsupportedProtocol`] if the kernel image
+/// cannot be parsed or requires unsupported features, and [`BootError::OutOfMemory`] if the
+/// kernel or one of the modules cannot be loaded.
+pub fn load_multiboot2(
+    image: &[u8],
+    modules: &[MultibootModule],
+    cmdline: &str,
+    memory: &mut BootMemoryMap,
+) -> Result<LoadedMultibootKernel, BootError> {
+    let header = Multiboot2Header::locate(image)?.ok_or(BootError::InvalidImage)?;
+    let entry = load_kernel_image(image, header.load_address(), header.entry_addr(), memory)?;
+
+    let mut information = Multiboot2InformationBuilder::new();
+    information.add_cmdline(cmdline);
+    information.add_bootloader_name(BOOTLOADER_NAME);
+
+    for module in modules {
+        let (start, end) = load_module(module, memory)?;
+        information.add_module(start, end, module.cmdline);
+    }
+
+    let (mem_lower, mem_upper) = basic_memory_info(E820MemoryMap::default());
+    information.add_basic_meminfo(mem_lower, mem_upper);
+    information.add_memory_map(E820MemoryMap::default());
+
+    let mode_info = unsafe { ptr::read(VESA_MODE_BUFFER as *const ModeInfoBlock) };
+
+    if let Some(request) = header.framebuffer() {
+        if !request.matches(
+            u32::from(mode_info.width),
+            u32::from(mode_info.height),
+            u32::from(mode_info.bits_per_pixel),
+        ) {
+            info!(
+                "multiboot",
+                "requested framebuffer mode is not available (width = {}    height = {}    depth = {})",
+                request.width(),
+                request.height(),
+                request.depth()
+            );
+        }
+    }
+    information.add_framebuffer(&mode_info);
+
+    if let Some(rsdp) = RSDP.get() {
+        information.add_acpi_rsdp(rsdp);
+    }
+
+    if let Some(smbios) = smbios_entry_point() {
+        information.add_smbios(smbios.major_version, smbios.minor_version, smbios.data);
+    }
+
+    let information = Box::leak(information.build());
+
+    info!(
+        "multiboot",
+        "loaded multiboot2 kernel (entry = {:#x}    modules = {})",
+        entry,
+        modules.len()
+    );
+
+    Ok(LoadedMultibootKernel {
+        entry,
+        information: u32::try_from(information.as_ptr() as usize)
+            .expect("invalid boot information address"),
+        magic: MULTIBOOT2_BOOTLOADER_MAGIC,
+    })
+}
+
+/// Hands out control to a loaded Multiboot kernel.
+///
+/// The kernel is started in 32-bit protected mode, with paging and interrupts disabled, the
+/// bootloader magic value in `EAX` and the physical address of the boot information in `EBX`.
+pub fn boot_multiboot(kernel: LoadedMultibootKernel) -> ! {
+    info!(
+        "multiboot",
+        "jumping to kernel entry point (addr = {:#x})", kernel.entry
+    );
+
+    disable_interrupts();
+
+    unsafe {
+        linux_init_gdt(
+            PhyAddr32::new(u32::try_from(LONG_GDT_ADDR).expect("invalid gdt address")),
+            false,
+        );
+
+        asm!(
+            "push 0x10",
+            "push {entry}",
+            "mov ebx, {information}",
+            "mov edx, 0x18",
+            "mov ds, dx",
+            "mov es, dx",
+            "mov fs, dx",
+            "mov gs, dx",
+            "mov ss, dx",
+            "retf",
+            entry = in(reg) kernel.entry,
+            information = in(reg) kernel.information,
+            in("eax") kernel.magic,
+            options(noreturn)
+        );
+    }
+}
+
+/// Reads the part of a raw partition required to load a Multiboot kernel image.
+///
+/// `header_area` contains the beginning of the partition, in which the Multiboot header was found.
+fn read_raw_image(
+    device: AtaDeviceIdentifier,
+    partition: usize,
+    header_area: &[u8],
+    load_address: Option<&MultibootLoadAddress>,
+) -> Option<Vec<u8>> {
+    let image_size = match load_address {
+        Some(load_address) => load_address.image_size(),
+        None => ElfImage::image_size(header_area).ok(),
+    };
+
+    // the image is loaded up to the end of the file, which is not known on a raw partition
+    let image_size = match image_size {
+        Some(size) => size,
+        None => {
+            let sectors_count = get_sata_drive(device)?
+                .partitions()
+                .get(partition)?
+                .sectors_count();
+            usize::try_from(sectors_count * 0x200).ok()?
+        }
+    };
+
+    read_partition(device, partition, image_size)
+}
+
+/// Loads a kernel image, either as an ELF executable or using the addresses provided by the
+/// Multiboot header, and returns its entry point.
+///
+/// `entry_addr`, if provided by the header, overrides the ELF entry point.
+fn load_kernel_image(
+    image: &[u8],
+    load_address: Option<&MultibootLoadAddress>,
+    entry_addr: Option<u32>,
+    memory: &mut BootMemoryMap,
+) -> Result<u32, BootError> {
+    if let Some(load_address) = load_address {
+        load_raw_image(image, load_address, memory)?;
+        return entry_addr.ok_or(BootError::InvalidImage);
+    }
+
+    let elf = ElfImage::parse(image)?;
+
+    for segment in elf.load_segments() {
+        load_to_phys(
+            segment.phys_addr(),
+            elf.segment_data(&segment),
+            segment.mem_size(),
+            memory,
+        )?;
+    }
+
+    match entry_addr {
+        Some(entry) => Ok(entry),
+        None => u32::try_from(elf.entry()).map_err(|_| BootError::UnsupportedProtocol),
+    }
+}
+
+/// Loads a kernel image that is not stored as an ELF executable, using the addresses provided by
+/// the Multiboot header.
+fn load_raw_image(
+    image: &[u8],
+    load_address: &MultibootLoadAddress,
+    memory: &mut BootMemoryMap,
+) -> CanFail<BootError> {
+    let data = match load_address.load_size() {
+        Some(size) => load_address
+            .file_offset()
+            .checked_add(size)
+            .and_then(|end| image.get(load_address.file_offset()..end)),
+        None => image.get(load_address.file_offset()..),
+    }
+    .ok_or(BootError::InvalidImage)?;
+
+    let load_addr = u64::from(load_address.load_addr());
+    let data_size = u64::try_from(data.len()).expect("invalid image size");
+    let mem_size = load_address
+        .bss_end_addr()
+        .map_or(data_size, |bss_end| u64::from(bss_end) - load_addr)
+        .max(data_size);
+
+    load_to_phys(load_addr, data, mem_size, memory)
+}
+
+/// Copies `data` to the physical memory starting at `addr`, and zeroes the remaining
+/// `mem_size - data.len()` bytes.
+fn load_to_phys(
+    addr: u64,
+    data: &[u8],
+    mem_size: u64,
+    memory: &mut BootMemoryMap,
+) -> CanFail<BootError> {
+    if mem_size == 0 {
+        return Ok(());
+    }
+
+    if addr.saturating_add(mem_size) > MAX_PHYS_ADDR || !memory.reserve_at(addr, mem_size) {
+        return Err(BootError::OutOfMemory);
+    }
+
+    unsafe {
+        let dest = usize::try_from(addr).expect("invalid load address") as *mut u8;
+
+        ptr::copy_nonoverlapping(data.as_ptr(), dest, data.len());
+        ptr::write_bytes(
+            dest.add(data.len()),
+            0,
+            usize::try_from(mem_size).expect("invalid load size") - data.len(),
+        );
+    }
+
+    Ok(())
+}
+
+/// Copies a boot module to memory, and returns its physical start and end addresses.
+fn load_module(
+    module: &MultibootModule,
+    memory: &mut BootMemoryMap,
+) -> Result<(u32, u32), BootError> {
+    let (start, end) = memory
+        .load(module.data, MODULE_ALIGN)
+        .ok_or(BootError::OutOfMemory)?;
+
+    info!(
+        "multiboot",
+        "loaded module {} (addr = {:#x}    size = {:#x})",
+        module.cmdline,
+        start,
+        end - start
+    );
+
+    Ok((
+        u32::try_from(start).expect("invalid module address"),
+        u32::try_from(end).expect("invalid module address"),
+    ))
+}
+
+/// Copies a string to the heap as a C-style zero terminated string, which is never freed, and
+/// returns its physical address.
+fn leak_c_string(string: &str) -> PhyAddr32 {
+    let mut buffer = Vec::with_capacity(string.len() + 1);
+    buffer.extend_from_slice(string.as_bytes());
+    buffer.push(0);
+
+    phys_addr_of(buffer.leak().as_ptr())
+}
+
+/// Returns the physical address of a structure allocated on the heap, which is identity mapped
+/// below 4GB.
+fn phys_addr_of<T>(ptr: *const T) -> PhyAddr32 {
+    PhyAddr32::new(u32::try_from(ptr as usize).expect("invalid heap address"))
+}
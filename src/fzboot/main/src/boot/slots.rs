@@ -0,0 +1,123 @@
+//! A/B boot slots selection.
+//!
+//! See [`fzboot::boot::slots`] for the description of the slots state, and of the way the booted
+//! system marks its boot as successful.
+
+use alloc::format;
+use fzboot::{
+    boot::{
+        config::BootConfig,
+        slots::{read_slot_state, write_slot_state, BootSlot, SlotState, SLOT_CMDLINE_KEY},
+    },
+    drivers::generics::dev_disk::{get_sata_drive, DiskDevice},
+    error,
+    fs::partitions::Partition,
+    info,
+};
+
+use super::{disk::locate_partition, target::BootTarget};
+
+/// State of the boot slots, along with the partition storing it.
+pub struct BootSlots {
+    partition: Partition,
+    stored: SlotState,
+    state: SlotState,
+}
+
+impl BootSlots {
+    /// Reads the state of the boot slots from the partition selected by the `slot_state` option.
+    ///
+    /// Returns `None` if the option is not set, or if the state cannot be read.
+    pub fn load(config: &BootConfig) -> Option<Self> {
+        let selector = config.slot_state.as_ref()?;
+        let partition = locate_partition(selector).and_then(|(device, part_id)| {
+            get_sata_drive(device)?.partitions().get(part_id).cloned()
+        });
+
+        let Some(partition) = partition else {
+            error!("slots", "slot state partition not found ({:?})", selector);
+            return None;
+        };
+
+        let state = match read_slot_state(&partition) {
+            Ok(state) => state,
+            Err(err) => {
+                error!("slots", "failed to read slot state: {:?}", err);
+                return None;
+            }
+        };
+
+        info!(
+            "slots",
+            "loaded slot state (active = {}    a = {:?}    b = {:?})",
+            state.active.name(),
+            state.slot(BootSlot::A),
+            state.slot(BootSlot::B)
+        );
+
+        Some(Self {
+            partition,
+            stored: state,
+            state,
+        })
+    }
+
+    /// Returns the index of the target to boot by default: the first target of the active slot,
+    /// or of the other slot if the active one has no boot attempt left (see [`SlotState::select`]).
+    ///
+    /// Returns `default` if neither of the slots can be booted, or if no target belongs to the
+    /// selected slot.
+    pub fn default_target(&mut self, targets: &[BootTarget], default: usize) -> usize {
+        let active = self.state.active;
+
+        let Some(slot) = self.state.select() else {
+            error!("slots", "no boot attempt left in any slot");
+            return default;
+        };
+
+        if slot != active {
+            error!(
+                "slots",
+                "no boot attempt left in slot {}, falling back to slot {}",
+                active.name(),
+                slot.name()
+            );
+        }
+
+        targets
+            .iter()
+            .position(|target| target.slot() == Some(slot))
+            .unwrap_or(default)
+    }
+
+    /// Records a boot attempt of the selected target, and stores the updated state.
+    ///
+    /// If the target belongs to a slot, its counter is decremented (see
+    /// [`SlotState::record_attempt`]) and the slot is appended to the kernel command line.
+    pub fn record_boot(&mut self, target: &mut BootTarget) {
+        if let Some(slot) = target.slot() {
+            self.state.record_attempt(slot);
+
+            info!(
+                "slots",
+                "booting slot {} ({:?})",
+                slot.name(),
+                self.state.slot(slot)
+            );
+
+            let separator = if target.cmdline.is_empty() { "" } else { " " };
+            target.cmdline += &format!("{separator}{SLOT_CMDLINE_KEY}={}", slot.name());
+        }
+
+        if self.state == self.stored {
+            return;
+        }
+
+        match write_slot_state(&self.partition, &self.state) {
+            Ok(()) => self.stored = self.state,
+            Err(err) => {
+                error!("slots", "failed to write slot state: {:?}", err);
+            }
+        }
+    }
+}
@@ -0,0 +1,471 @@
+//! Bootable targets discovery.
+//!
+//! Bootable targets are either the boot entries of the configuration file, whose files are loaded
+//! by path from the partition storing the configuration, the Boot Loader Specification entries
+//! found on mounted partitions, or kernel images stored on raw partitions. The one selected in the
+//! boot menu is then loaded and started.
+
+use alloc::{boxed::Box, format, string::String, vec::Vec};
+use fzboot::{
+    boot::{
+        config::{BootConfig, BootEntry, BootProtocol, ModuleSource},
+        elf::{ElfClass, ElfHeader},
+        kaslr::KaslrMode,
+        linux::SetupHeader,
+        multiboot::{
+            mb2_header::{Multiboot2Header, MULTIBOOT2_SEARCH_LIMIT},
+            mb_header::MultibootHeader,
+        },
+        slots::BootSlot,
+    },
+    drivers::{
+        generics::dev_disk::{sata_drives, DiskDevice},
+        ide::AtaDeviceIdentifier,
+    },
+    fs::partitions::{Partition, PartitionSelector},
+    info,
+};
+
+use super::{
+    bls, boot_info, chainload,
+    disk::{locate_partition, read_partition, read_whole_partition},
+    fzkernel, linux, measure,
+    memory::BootMemoryMap,
+    multiboot::{self, MultibootModule},
+    verify::ImageVerifier,
+};
+
+/// Number of bytes read from each partition to identify a kernel image.
+///
+/// Large enough to contain any of the supported kernel headers.
+const PROBE_SIZE: usize = MULTIBOOT2_SEARCH_LIMIT;
+
+/// Alignment of the boot modules appended to the initial ramdisk of a Linux kernel.
+const INITRD_ALIGN: usize = 4;
+
+/// Location of the files required to boot a target.
+#[derive(Clone)]
+pub enum BootSource {
+    /// Kernel image stored on a raw partition.
+    RawPartition {
+        /// Disk on which the kernel is stored.
+        device: AtaDeviceIdentifier,
+
+        /// Partition on which the kernel is stored.
+        partition: usize,
+    },
+
+    /// Boot entry of the configuration file, or _BLS_ entry, with its files stored on a mounted
+    /// partition.
+    Entry {
+        /// Partition on which the files are stored.
+        partition: Box<Partition>,
+
+        /// Paths of the files to load.
+        entry: BootEntry,
+    },
+}
+
+/// A kernel that can be selected in the boot menu.
+#[derive(Clone)]
+pub struct BootTarget {
+    /// Name displayed in the boot menu.
+    pub title: String,
+
+    /// Boot protocol of the kernel.
+    pub protocol: BootProtocol,
+
+    /// Kernel command line.
+    pub cmdline: String,
+
+    /// Location of the kernel image.
+    pub source: BootSource,
+}
+
+impl BootTarget {
+    /// Returns the boot slot of the target, if it is a boot entry assigned to one.
+    pub fn slot(&self) -> Option<BootSlot> {
+        match &self.source {
+            BootSource::Entry { entry, .. } => entry.slot,
+            BootSource::RawPartition { .. } => None,
+        }
+    }
+}
+
+/// Returns the bootable targets: the entries of the configuration file first, in the order in
+/// which they are defined, then the _BLS_ entries (see [`bls::discover_bls_entries`]), then the
+/// kernel images found on raw partitions.
+///
+/// Entries are loaded from `config_partition`, the partition storing the configuration file. Raw
+/// partitions are probed in the order in which disks are enumerated, and use the command line of
+/// the default entry. Native kernels are only looked for on the partition matching
+/// `config.kernel_partition` (see [`fzkernel::locate_kernel_partition`]).
+pub fn discover_targets(
+    config: &BootConfig,
+    config_partition: Option<&Partition>,
+) -> Vec<BootTarget> {
+    let mut targets = Vec::new();
+
+    if let Some(partition) = config_partition {
+        for entry in &config.entries {
+            targets.push(BootTarget {
+                title: entry.title.clone(),
+                protocol: entry.protocol,
+                cmdline: entry.cmdline.clone(),
+                source: BootSource::Entry {
+                    partition: Box::new(partition.clone()),
+                    entry: entry.clone(),
+                },
+            });
+        }
+    }
+
+    for (partition, entry) in bls::discover_bls_entries() {
+        targets.push(BootTarget {
+            title: entry.title.clone(),
+            protocol: entry.protocol,
+            cmdline: entry.cmdline.clone(),
+            source: BootSource::Entry {
+                partition: Box::new(partition),
+                entry,
+            },
+        });
+    }
+
+    let cmdline = config
+        .default_entry()
+        .map_or("", |entry| entry.cmdline.as_str());
+    let kernel_part = fzkernel::locate_kernel_partition(&config.kernel_partition);
+
+    for (disk_id, drive) in sata_drives().enumerate() {
+        for part_id in 0..drive.partitions().len() {
+            let device = drive.identifier();
+            let Some(header_area) = read_partition(device, part_id, PROBE_SIZE) else {
+                continue;
+            };
+
+            let Some(protocol) =
+                probe_protocol(&header_area, kernel_part == Some((device, part_id)))
+            else {
+                continue;
+            };
+
+            info!(
+                "boot",
+                "found {} kernel ({}    partition_id = {})",
+                protocol_name(protocol),
+                device,
+                part_id
+            );
+
+            targets.push(BootTarget {
+                title: format!(
+                    "{} kernel (disk {}, partition {})",
+                    protocol_name(protocol),
+                    disk_id,
+                    part_id + 1
+                ),
+                protocol,
+                cmdline: String::from(cmdline),
+                source: BootSource::RawPartition {
+                    device,
+                    partition: part_id,
+                },
+            });
+        }
+    }
+
+    targets
+}
+
+/// Loads the kernel of a boot target, and hands out control to it.
+///
+/// Every image is checked by `verifier` before being loaded. Position-independent native kernels
+/// are loaded at addresses randomised according to `kaslr`.
+///
+/// # Panics
+///
+/// Panics if the kernel image, or one of the files of the boot entry, cannot be read or loaded,
+/// or if one of them is refused by the verification policy.
+pub fn boot_target(
+    target: &BootTarget,
+    verifier: &ImageVerifier,
+    kaslr: KaslrMode,
+    memory: &mut BootMemoryMap,
+) -> ! {
+    info!("boot", "booting {}", target.title);
+
+    measure::measure_cmdline(&target.cmdline);
+
+    match &target.source {
+        BootSource::RawPartition { device, partition } => {
+            verifier.verify_unsigned(&target.title);
+            boot_raw_partition(target, *device, *partition, kaslr, memory)
+        }
+        BootSource::Entry { partition, entry } => {
+            boot_entry(target, partition, entry, verifier, kaslr, memory)
+        }
+    }
+}
+
+/// Boots a kernel image stored on a raw partition.
+fn boot_raw_partition(
+    target: &BootTarget,
+    device: AtaDeviceIdentifier,
+    partition: usize,
+    kaslr: KaslrMode,
+    memory: &mut BootMemoryMap,
+) -> ! {
+    let cmdline = target.cmdline.as_str();
+
+    match target.protocol {
+        BootProtocol::Linux => {
+            let image = linux::read_raw_bzimage(device, partition)
+                .expect("failed to read linux kernel image");
+            measure::measure_image(&target.title, &image);
+            let kernel = linux::load_linux(&image, None, cmdline, memory)
+                .unwrap_or_else(|err| panic!("failed to load linux kernel: {err:?}"));
+
+            linux::boot_linux(kernel);
+        }
+        BootProtocol::Multiboot2 => {
+            let image = multiboot::read_raw_multiboot2(device, partition)
+                .expect("failed to read multiboot2 kernel image");
+            measure::measure_image(&target.title, &image);
+            let kernel = multiboot::load_multiboot2(&image, &[], cmdline, memory)
+                .unwrap_or_else(|err| panic!("failed to load multiboot2 kernel: {err:?}"));
+
+            multiboot::boot_multiboot(kernel);
+        }
+        BootProtocol::Multiboot => {
+            let image = multiboot::read_raw_multiboot(device, partition)
+                .expect("failed to read multiboot kernel image");
+            measure::measure_image(&target.title, &image);
+            let kernel =
+                multiboot::load_multiboot(&image, &[], cmdline, (device, partition), memory)
+                    .unwrap_or_else(|err| panic!("failed to load multiboot kernel: {err:?}"));
+
+            multiboot::boot_multiboot(kernel);
+        }
+        BootProtocol::Native => {
+            let image = fzkernel::read_kernel(device, partition)
+                .unwrap_or_else(|err| panic!("failed to read kernel image: {err:?}"));
+            measure::measure_image(&target.title, &image);
+            let kernel = fzkernel::load_kernel_image(&image, kaslr, memory)
+                .unwrap_or_else(|err| panic!("failed to load kernel: {err:?}"));
+            let boot_info = boot_info::build_boot_info(cmdline, Vec::new(), &kernel, memory);
+
+            fzkernel::boot_kernel(kernel.entry, boot_info);
+        }
+        BootProtocol::Chainload => {
+            unreachable!("chainload targets are only defined by boot entries")
+        }
+    }
+}
+
+/// Boots a boot entry of the configuration file, reading its files from `partition`.
+fn boot_entry(
+    target: &BootTarget,
+    partition: &Partition,
+    entry: &BootEntry,
+    verifier: &ImageVerifier,
+    kaslr: KaslrMode,
+    memory: &mut BootMemoryMap,
+) -> ! {
+    let cmdline = target.cmdline.as_str();
+    let image = || read_entry_file(partition, &entry.kernel, verifier);
+
+    match target.protocol {
+        BootProtocol::Linux => {
+            let initrd = read_linux_initrd(partition, entry, verifier);
+            let kernel = linux::load_linux(&image(), initrd.as_deref(), cmdline, memory)
+                .unwrap_or_else(|err| panic!("failed to load linux kernel: {err:?}"));
+
+            linux::boot_linux(kernel);
+        }
+        BootProtocol::Multiboot2 => {
+            let modules = read_entry_modules(partition, entry, verifier);
+            let kernel =
+                multiboot::load_multiboot2(&image(), &multiboot_modules(&modules), cmdline, memory)
+                    .unwrap_or_else(|err| panic!("failed to load multiboot2 kernel: {err:?}"));
+
+            multiboot::boot_multiboot(kernel);
+        }
+        BootProtocol::Multiboot => {
+            let modules = read_entry_modules(partition, entry, verifier);
+            let boot_device = (partition.drive_id(), partition.id());
+            let kernel = multiboot::load_multiboot(
+                &image(),
+                &multiboot_modules(&modules),
+                cmdline,
+                boot_device,
+                memory,
+            )
+            .unwrap_or_else(|err| panic!("failed to load multiboot kernel: {err:?}"));
+
+            multiboot::boot_multiboot(kernel);
+        }
+        BootProtocol::Native => {
+            let modules = read_entry_modules(partition, entry, verifier);
+            let kernel = fzkernel::load_kernel_image(&image(), kaslr, memory)
+                .unwrap_or_else(|err| panic!("failed to load kernel: {err:?}"));
+            let modules = boot_info::load_modules(&modules, memory)
+                .unwrap_or_else(|err| panic!("failed to load kernel modules: {err:?}"));
+            let boot_info = boot_info::build_boot_info(cmdline, modules, &kernel, memory);
+
+            fzkernel::boot_kernel(kernel.entry, boot_info);
+        }
+        BootProtocol::Chainload => {
+            // boot sectors have no detached signature
+            verifier.verify_unsigned(&target.title);
+
+            let chainload_target = entry
+                .chainload
+                .as_ref()
+                .expect("chainload entry without boot sector");
+            let (device, boot_partition) = chainload::locate_chainload_target(chainload_target)
+                .expect("failed to locate the chainloaded disk or partition");
+            let boot_sector = chainload::load_boot_sector(device, boot_partition)
+                .unwrap_or_else(|err| panic!("failed to load boot sector: {err:?}"));
+            measure::measure_image(&target.title, boot_sector.sector());
+
+            chainload::boot_chainload(boot_sector);
+        }
+    }
+}
+
+/// Reads a file of a boot entry, and verifies its signature.
+///
+/// # Panics
+///
+/// Panics if the file cannot be read, or is refused by the verification policy.
+fn read_entry_file(partition: &Partition, path: &str, verifier: &ImageVerifier) -> Vec<u8> {
+    let content = partition
+        .read_file(path)
+        .unwrap_or_else(|err| panic!("failed to read {path}: {err:?}"));
+
+    info!("boot", "read {} (size = {:#x})", path, content.len());
+
+    verifier.verify_file(partition, path, &content);
+    measure::measure_image(path, &content);
+
+    content
+}
+
+/// Reads the boot modules of a boot entry, along with their command line.
+///
+/// Modules are either read by path from `partition`, or from a raw partition.
+///
+/// # Panics
+///
+/// Panics if one of the modules cannot be located or read, or is refused by the verification
+/// policy.
+fn read_entry_modules<'a>(
+    partition: &Partition,
+    entry: &'a BootEntry,
+    verifier: &ImageVerifier,
+) -> Vec<(Vec<u8>, &'a str)> {
+    entry
+        .modules
+        .iter()
+        .map(|module| {
+            let data = match &module.source {
+                ModuleSource::File(path) => read_entry_file(partition, path, verifier),
+                ModuleSource::Partition(selector) => read_module_partition(selector, verifier),
+            };
+
+            (data, module.cmdline.as_str())
+        })
+        .collect()
+}
+
+/// Reads the whole content of the first partition matching `selector`.
+///
+/// # Panics
+///
+/// Panics if the partition cannot be located or read, or if the verification policy refuses
+/// unsigned images.
+fn read_module_partition(selector: &PartitionSelector, verifier: &ImageVerifier) -> Vec<u8> {
+    let (device, part_id) = locate_partition(selector)
+        .unwrap_or_else(|| panic!("failed to locate module partition {selector:?}"));
+
+    verifier.verify_unsigned(&format!("module partition {selector:?}"));
+
+    let content = read_whole_partition(device, part_id)
+        .unwrap_or_else(|| panic!("failed to read module partition {selector:?}"));
+
+    info!(
+        "boot",
+        "read module partition ({}    partition_id = {}    size = {:#x})",
+        device,
+        part_id,
+        content.len()
+    );
+
+    measure::measure_image(&format!("module partition {selector:?}"), &content);
+
+    content
+}
+
+/// Reads the initial ramdisk of a Linux boot entry.
+///
+/// The boot modules of the entry are appended to the initial ramdisk, each of them aligned on
+/// [`INITRD_ALIGN`] bytes, as the kernel unpacks every concatenated `cpio` archive.
+fn read_linux_initrd(
+    partition: &Partition,
+    entry: &BootEntry,
+    verifier: &ImageVerifier,
+) -> Option<Vec<u8>> {
+    let mut initrd = entry
+        .initrd
+        .as_ref()
+        .map(|path| read_entry_file(partition, path, verifier));
+
+    for (module, _) in read_entry_modules(partition, entry, verifier) {
+        let initrd = initrd.get_or_insert_with(Vec::new);
+
+        initrd.resize(initrd.len().next_multiple_of(INITRD_ALIGN), 0);
+        initrd.extend_from_slice(&module);
+    }
+
+    initrd
+}
+
+/// Builds the list of modules passed to a Multiboot kernel.
+fn multiboot_modules<'a>(modules: &'a [(Vec<u8>, &'a str)]) -> Vec<MultibootModule<'a>> {
+    modules
+        .iter()
+        .map(|(data, cmdline)| MultibootModule { data, cmdline })
+        .collect()
+}
+
+/// Identifies the boot protocol of the kernel image starting with `header_area`.
+///
+/// Native kernels are only looked for on the kernel partition, as their images are regular ELF
+/// executables.
+fn probe_protocol(header_area: &[u8], kernel_partition: bool) -> Option<BootProtocol> {
+    if SetupHeader::from_image(header_area).is_ok() {
+        Some(BootProtocol::Linux)
+    } else if matches!(Multiboot2Header::locate(header_area), Ok(Some(_))) {
+        Some(BootProtocol::Multiboot2)
+    } else if matches!(MultibootHeader::locate(header_area), Ok(Some(_))) {
+        Some(BootProtocol::Multiboot)
+    } else if kernel_partition
+        && ElfHeader::from_image(header_area).is_ok_and(|header| header.class() == ElfClass::Elf64)
+    {
+        Some(BootProtocol::Native)
+    } else {
+        None
+    }
+}
+
+/// Returns the name of a boot protocol, as displayed in the boot menu.
+fn protocol_name(protocol: BootProtocol) -> &'static str {
+    match protocol {
+        BootProtocol::Native => "FrozenBoot",
+        BootProtocol::Multiboot => "Multiboot",
+        BootProtocol::Multiboot2 => "Multiboot2",
+        BootProtocol::Linux => "Linux",
+        BootProtocol::Chainload => "Chainload",
+    }
+}
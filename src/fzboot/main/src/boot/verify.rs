@@ -0,0 +1,133 @@
+//! Boot images signature verification.
+//!
+//! See [`fzboot::boot::verify`] for the signature format, and for the way the public key and the
+//! verification policy are set.
+
+use alloc::format;
+use fzboot::{
+    boot::{
+        config::BootConfig,
+        verify::{parse_public_key, verify_image, VerifyPolicy, SIGNATURE_SUFFIX},
+    },
+    crypto::ed25519::PUBLIC_KEY_SIZE,
+    error,
+    errors::{CanFail, IOError, SignatureError},
+    fs::partitions::Partition,
+    info,
+};
+
+/// Public key embedded at build time, as 64 hexadecimal digits.
+const PUBLIC_KEY: Option<&str> = option_env!("FZBOOT_PUBLIC_KEY");
+
+/// Minimum verification policy, set at build time.
+const MIN_POLICY: Option<&str> = option_env!("FZBOOT_VERIFY_POLICY");
+
+/// Verifies the images loaded by a boot target, according to the verification policy.
+pub struct ImageVerifier {
+    policy: VerifyPolicy,
+    public_key: Option<[u8; PUBLIC_KEY_SIZE]>,
+}
+
+impl ImageVerifier {
+    /// Builds the verifier from the embedded public key, and from the strictest of the build time
+    /// and configuration file policies.
+    ///
+    /// An invalid build time policy is handled as [`VerifyPolicy::Refuse`].
+    pub fn new(config: &BootConfig) -> Self {
+        let min_policy = match MIN_POLICY.map(VerifyPolicy::from_name) {
+            None => VerifyPolicy::Ignore,
+            Some(Some(policy)) => policy,
+            Some(None) => {
+                error!("verify", "invalid build time verification policy");
+                VerifyPolicy::Refuse
+            }
+        };
+        let policy = config.verify.unwrap_or_default().max(min_policy);
+
+        let public_key = PUBLIC_KEY.and_then(parse_public_key);
+
+        if PUBLIC_KEY.is_some() && public_key.is_none() {
+            error!("verify", "invalid embedded public key");
+        }
+
+        info!(
+            "verify",
+            "signature verification policy: {} (public key = {})",
+            policy.name(),
+            if public_key.is_some() {
+                "embedded"
+            } else {
+                "none"
+            }
+        );
+
+        Self { policy, public_key }
+    }
+
+    /// Verifies a file read from `partition`, against its detached signature.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the file cannot be verified, and the policy is [`VerifyPolicy::Refuse`].
+    pub fn verify_file(&self, partition: &Partition, path: &str, image: &[u8]) {
+        if self.policy == VerifyPolicy::Ignore {
+            return;
+        }
+
+        let result = self.check_file(partition, path, image);
+        self.apply_policy(path, result);
+    }
+
+    /// Reports an image read from a raw partition, which cannot be signed.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the policy is [`VerifyPolicy::Refuse`].
+    pub fn verify_unsigned(&self, name: &str) {
+        if self.policy == VerifyPolicy::Ignore {
+            return;
+        }
+
+        self.apply_policy(name, Err(SignatureError::MissingSignature));
+    }
+
+    /// Reads the detached signature of a file, and checks it.
+    fn check_file(
+        &self,
+        partition: &Partition,
+        path: &str,
+        image: &[u8],
+    ) -> CanFail<SignatureError> {
+        let public_key = self
+            .public_key
+            .as_ref()
+            .ok_or(SignatureError::MissingPublicKey)?;
+
+        let signature_path = format!("{path}{SIGNATURE_SUFFIX}");
+        let signature = match partition.read_file(&signature_path) {
+            Ok(signature) => signature,
+            Err(IOError::NotFound) => return Err(SignatureError::MissingSignature),
+            Err(err) => {
+                error!("verify", "failed to read {}: {:?}", signature_path, err);
+                return Err(SignatureError::MissingSignature);
+            }
+        };
+
+        verify_image(public_key, image, &signature)
+    }
+
+    /// Reports the verification result of an image, and refuses to boot it if required.
+    fn apply_policy(&self, name: &str, result: CanFail<SignatureError>) {
+        match result {
+            Ok(()) => {
+                info!("verify", "valid signature for {}", name);
+            }
+            Err(err) if self.policy == VerifyPolicy::Refuse => {
+                panic!("refusing to boot {name}: {err:?}")
+            }
+            Err(err) => {
+                error!("verify", "failed to verify {}: {:?}", name, err);
+            }
+        }
+    }
+}
@@ -10,20 +10,20 @@ mod boot;
 extern crate alloc;
 
 use boot::fzkernel;
+use boot::memory::{BootMemoryMap, MAX_PHYS_ADDR};
 use core::arch::asm;
 use core::{panic::PanicInfo, ptr::NonNull};
+use fzboot::boot::config::BootConfig;
 use fzboot::boot::multiboot;
 use fzboot::drivers::generics::dev_disk::{sata_drives, DiskDevice};
 use fzboot::drivers::ide::AtaDeviceIdentifier;
 use fzboot::fs::partitions::mbr;
 use fzboot::irq::manager::{get_interrupt_manager, get_prot_interrupt_manager};
 use fzboot::mem::e820::{e820_entries_bootloader, E820_MAP_ADDR};
-use fzboot::mem::{MemoryAddress, PhyAddr, VirtAddr};
+use fzboot::mem::{MemoryAddress, VirtAddr};
 use fzboot::video::vesa::{init_text_buffer_from_vesa, text_buffer};
 use fzboot::x86::apic::InterruptVector;
-use fzboot::x86::descriptors::gdt::{long_init_gdt, LONG_GDT_ADDR};
 use fzboot::x86::int::enable_interrupts;
-use fzboot::x86::paging::bootinit_paging;
 use fzboot::{
     drivers::pci::pci_devices_init,
     mem::{
@@ -31,7 +31,7 @@ use fzboot::{
         MemoryStructure, MEM_STRUCTURE,
     },
 };
-use fzboot::{drivers::pci::pci_enumerate, io::pic::PIC};
+use fzboot::{drivers::pci::pci_enumerate, drivers::tpm::tpm_init, io::pic::PIC};
 use fzboot::{error, println};
 use fzboot::{
     info,
@@ -75,21 +75,43 @@ pub fn boot_main() -> ! {
     interrupts_init();
     pci_enumerate();
     pci_devices_init();
+    tpm_init();
 
-    let kernel_part = boot::fzkernel::locate_kernel_partition();
-    boot::fzkernel::load_kernel(kernel_part.0, kernel_part.1);
+    let (config, config_partition) = match boot::config::load_boot_config() {
+        Some((config, partition)) => (config, Some(partition)),
+        None => (BootConfig::default(), None),
+    };
+
+    let mut targets = boot::target::discover_targets(&config, config_partition.as_ref());
 
-    let mb_information_hdr_addr = boot::headers::dump_multiboot_information_header();
-    bootinit_paging::init_paging();
+    if targets.is_empty() {
+        panic!("failed to locate a bootable kernel");
+    }
 
-    info!("kernel", "jumping to kernel main (addr = 0x80000)");
+    let mut slots = boot::slots::BootSlots::load(&config);
+    let default = slots.as_mut().map_or(config.default, |slots| {
+        slots.default_target(&targets, config.default)
+    });
 
-    unsafe {
-        long_init_gdt(PhyAddr::new(LONG_GDT_ADDR));
-        asm!("mov ecx, {}", in(reg) mb_information_hdr_addr);
-        asm!("mov ebp, 0", "push 0x10", "push 0x800000", "retf");
-        core::unreachable!();
+    let selected = boot::menu::select_target(
+        &mut targets,
+        default,
+        config.timeout.unwrap_or(boot::menu::DEFAULT_TIMEOUT),
+    );
+
+    if let Some(slots) = &mut slots {
+        slots.record_boot(&mut targets[selected]);
     }
+
+    let verifier = boot::verify::ImageVerifier::new(&config);
+    let mut memory = BootMemoryMap::new();
+
+    boot::target::boot_target(
+        &targets[selected],
+        &verifier,
+        config.kaslr.unwrap_or_default(),
+        &mut memory,
+    );
 }
 
 pub fn clock_init() {
@@ -122,28 +144,45 @@ pub fn interrupts_init() {
 pub fn heap_init() {
     let e820_map = E820MemoryMap::new(E820_MAP_ADDR as *mut u8);
     let mut best_entry = AddressRangeDescriptor::default();
+    let mut best_length = 0;
 
     for entry in e820_map {
-        if matches!(entry.addr_type, E820MemType::RAM) && entry.length() > best_entry.length() {
+        // only the part of the range located below 4GB is addressable
+        let usable_length = entry
+            .length()
+            .min(MAX_PHYS_ADDR.saturating_sub(entry.phys_base()));
+
+        if matches!(entry.addr_type, E820MemType::RAM) && usable_length > best_length {
             best_entry = entry;
+            best_length = usable_length;
         }
     }
 
-    assert!(best_entry.length() >= MIN_HEAP_SIZE as u64);
+    let max_heap_size = u64::try_from(MAX_HEAP_SIZE).expect("invalid heap size");
 
-    if best_entry.length() > MAX_HEAP_SIZE as u64 {
-        // No 64-bit support for now
-        best_entry.length_high = 0;
-        best_entry.length_low = MAX_HEAP_SIZE as u32;
+    assert!(best_length >= u64::try_from(MIN_HEAP_SIZE).expect("invalid heap size"));
+
+    let mut heap_offset = 0;
+
+    if best_length > max_heap_size {
+        // Keep the heap at the end of the memory range (below 4GB), so that the start of the upper
+        // memory stays available for kernel images loaded at a fixed address.
+        heap_offset =
+            usize::try_from((best_length - max_heap_size) & !0xFFF).expect("invalid heap offset");
+        best_length = max_heap_size;
     }
 
+    // No 64-bit support for now
+    best_entry.length_high = 0;
+    best_entry.length_low = u32::try_from(best_length).expect("invalid heap size");
+
     let stack_size_min = (best_entry.length() >> 3) as usize;
     let stack_size = if stack_size_min < STACK_SIZE {
         stack_size_min as usize
     } else {
         STACK_SIZE
     };
-    let heap_addr = best_entry.base_addr();
+    let heap_addr = unsafe { best_entry.base_addr().add(heap_offset) };
     let stack_addr = unsafe { heap_addr.add(best_entry.length() as usize) } as usize;
 
     let heap_size = (best_entry.length() as usize) - stack_size;
@@ -160,10 +199,10 @@ pub fn heap_init() {
     MEM_STRUCTURE.init_once(|| mem_struct);
 
     unsafe {
-        BUDDY_ALLOCATOR.alloc.lock().resize(
-            NonNull::new(best_entry.base_addr()).unwrap(),
-            heap_size as usize,
-        )
+        BUDDY_ALLOCATOR
+            .alloc
+            .lock()
+            .resize(NonNull::new(heap_addr).unwrap(), heap_size as usize)
     };
 
     unsafe {
@@ -31,6 +31,11 @@ use crate::x86::tsc::TSC_CLK;
 /// ```
 #[must_use]
 pub fn date() -> DateTime {
+    // tests run on the host, where the CMOS ports cannot be accessed
+    #[cfg(test)]
+    return DateTime::LINUX_EPOCH;
+
+    #[cfg(not(test))]
     rtc::rtc_read()
 }
 
@@ -82,6 +82,18 @@ impl<'t> HPETClock<'t> {
         self.clk_freq
     }
 
+    /// Returns the physical address of the `HPETClock` registers.
+    #[must_use]
+    pub fn base_address(&self) -> usize {
+        self.description_table.base_address
+    }
+
+    /// Returns the period of the `HPETClock` main counter, in femtoseconds.
+    #[must_use]
+    pub fn clk_period(&self) -> u32 {
+        self.registers.__counter_clk_period()
+    }
+
     /// Returns the number of available timers.
     pub fn timer_count(&self) -> u8 {
         self.registers.__num_tim_cap() + 1
@@ -17,6 +17,9 @@ pub mod sdt;
 /// Shared [`RSDPDescriptor`] initialized during ACPI setup.
 pub static RSDP: OnceCell<RSDPDescriptor> = OnceCell::uninit();
 
+/// Physical address at which the [`RSDPDescriptor`] was found during ACPI setup.
+pub static RSDP_ADDR: OnceCell<usize> = OnceCell::uninit();
+
 /// ACPI Generic Address Structure.
 ///
 /// Used to express register addresses within ACPI-defined tables.
@@ -68,6 +71,27 @@ pub enum RSDPDescriptor {
     V2(RSDPDescriptorV2),
 }
 
+impl RSDPDescriptor {
+    /// Returns the raw content of the descriptor, as stored in memory by the firmware.
+    #[must_use]
+    pub fn as_bytes(&self) -> &[u8] {
+        match self {
+            Self::V1(rsdp) => unsafe {
+                slice::from_raw_parts(
+                    ptr::from_ref(rsdp).cast::<u8>(),
+                    mem::size_of::<RSDPDescriptorV1>(),
+                )
+            },
+            Self::V2(rsdp) => unsafe {
+                slice::from_raw_parts(
+                    ptr::from_ref(rsdp).cast::<u8>(),
+                    mem::size_of::<RSDPDescriptorV2>(),
+                )
+            },
+        }
+    }
+}
+
 /// [`RSDPDescriptor`] for ACPI revision lower than 2.
 ///
 /// Does not contain the XSDT address.
@@ -163,6 +187,8 @@ fn __load_rsdp() {
         panic!("failed to locate RSDP descriptor");
     }
 
+    RSDP_ADDR.init_once(|| address);
+
     // The first fields of the [`RSDPDescriptor`] are identical regardless of the revision.
     // So we cast it early as a V1 descriptor to check the revision first.
     let rsdp: RSDPDescriptorV1 = unsafe { ptr::read(address as *const RSDPDescriptorV1) };
@@ -0,0 +1,152 @@
+//! PS/2 keyboard input.
+//!
+//! Decodes the _scan code set 1_ bytes sent by the PS/2 controller into key presses, using a US
+//! QWERTY layout. Key releases are only used to track the state of the shift keys.
+
+use super::poll_ps2;
+
+/// Prefix byte of the extended scan codes.
+const SCANCODE_EXTENDED: u8 = 0xE0;
+
+/// Bit set in the scan code of a key release.
+const SCANCODE_RELEASE: u8 = 0x80;
+
+const SCANCODE_ESCAPE: u8 = 0x01;
+const SCANCODE_BACKSPACE: u8 = 0x0E;
+const SCANCODE_TAB: u8 = 0x0F;
+const SCANCODE_ENTER: u8 = 0x1C;
+const SCANCODE_LEFT_SHIFT: u8 = 0x2A;
+const SCANCODE_RIGHT_SHIFT: u8 = 0x36;
+
+const SCANCODE_HOME: u8 = 0x47;
+const SCANCODE_UP: u8 = 0x48;
+const SCANCODE_LEFT: u8 = 0x4B;
+const SCANCODE_RIGHT: u8 = 0x4D;
+const SCANCODE_END: u8 = 0x4F;
+const SCANCODE_DOWN: u8 = 0x50;
+const SCANCODE_DELETE: u8 = 0x53;
+
+/// Characters of the printable keys, indexed by scan code.
+const KEYMAP: &[u8] = b"\0\x001234567890-=\0\0qwertyuiop[]\0\0asdfghjkl;'`\0\\zxcvbnm,./\0*\0 ";
+
+/// Characters of the printable keys while shift is held, indexed by scan code.
+const KEYMAP_SHIFT: &[u8] = b"\0\0!@#$%^&*()_+\0\0QWERTYUIOP{}\0\0ASDFGHJKL:\"~\0|ZXCVBNM<>?\0*\0 ";
+
+/// A key pressed on the keyboard.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Key {
+    /// Printable character.
+    Char(char),
+
+    /// Up arrow.
+    Up,
+
+    /// Down arrow.
+    Down,
+
+    /// Left arrow.
+    Left,
+
+    /// Right arrow.
+    Right,
+
+    /// Home.
+    Home,
+
+    /// End.
+    End,
+
+    /// Delete.
+    Delete,
+
+    /// Backspace.
+    Backspace,
+
+    /// Enter (main or keypad).
+    Enter,
+
+    /// Escape.
+    Escape,
+
+    /// Tab.
+    Tab,
+}
+
+/// PS/2 keyboard, read by polling the controller.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Keyboard {
+    extended: bool,
+    shift: bool,
+}
+
+impl Keyboard {
+    /// Creates a keyboard with no key held.
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Returns the next key pressed, if any.
+    ///
+    /// Does not block: returns `None` if the controller has no pending data, or if the pending
+    /// scan codes do not correspond to a supported key press.
+    pub fn poll(&mut self) -> Option<Key> {
+        while let Some(scancode) = poll_ps2() {
+            if let Some(key) = self.decode(scancode) {
+                return Some(key);
+            }
+        }
+
+        None
+    }
+
+    /// Decodes a scan code byte, updating the keyboard state.
+    fn decode(&mut self, scancode: u8) -> Option<Key> {
+        if scancode == SCANCODE_EXTENDED {
+            self.extended = true;
+            return None;
+        }
+
+        let extended = core::mem::take(&mut self.extended);
+        let released = scancode & SCANCODE_RELEASE != 0;
+        let code = scancode & !SCANCODE_RELEASE;
+
+        if matches!(code, SCANCODE_LEFT_SHIFT | SCANCODE_RIGHT_SHIFT) && !extended {
+            self.shift = !released;
+            return None;
+        }
+
+        if released {
+            return None;
+        }
+
+        if extended {
+            return match code {
+                SCANCODE_UP => Some(Key::Up),
+                SCANCODE_DOWN => Some(Key::Down),
+                SCANCODE_LEFT => Some(Key::Left),
+                SCANCODE_RIGHT => Some(Key::Right),
+                SCANCODE_HOME => Some(Key::Home),
+                SCANCODE_END => Some(Key::End),
+                SCANCODE_DELETE => Some(Key::Delete),
+                SCANCODE_ENTER => Some(Key::Enter),
+                _ => None,
+            };
+        }
+
+        match code {
+            SCANCODE_ESCAPE => Some(Key::Escape),
+            SCANCODE_BACKSPACE => Some(Key::Backspace),
+            SCANCODE_TAB => Some(Key::Tab),
+            SCANCODE_ENTER => Some(Key::Enter),
+            _ => {
+                let keymap = if self.shift { KEYMAP_SHIFT } else { KEYMAP };
+
+                match keymap.get(usize::from(code)) {
+                    Some(&ch) if ch != 0 => Some(Key::Char(char::from(ch))),
+                    _ => None,
+                }
+            }
+        }
+    }
+}
@@ -3,6 +3,8 @@ use core::arch::asm;
 use crate::errors::{CanFail, IOError};
 use crate::io::{inb, outb, IOPort};
 
+pub mod keyboard;
+
 pub fn send_data(data: u8) {
     outb(IOPort::from(0x60), data);
 }
@@ -11,6 +13,21 @@ pub fn read_ps2() -> u8 {
     inb(IOPort::from(0x60))
 }
 
+/// Reads a byte from the PS/2 controller data port, if one is available.
+///
+/// Unlike [`read_ps2`], this does not block, and can be used to poll the controller when the
+/// keyboard interrupt is not handled.
+#[must_use]
+pub fn poll_ps2() -> Option<u8> {
+    let status_reg = inb(IOPort::from(0x64));
+
+    if (status_reg & 1) == 0 {
+        return None;
+    }
+
+    Some(read_ps2())
+}
+
 pub fn send_ps2(cmd: u8) {
     outb(IOPort::from(0x64), cmd);
 }
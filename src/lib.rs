@@ -34,6 +34,7 @@
 pub mod video;
 pub mod bios;
 pub mod boot;
+pub mod crypto;
 pub mod drivers;
 #[cfg(feature = "alloc")]
 pub mod fs;
@@ -56,9 +57,11 @@ pub mod kernel_syms {
     use crate::mem::{PhyAddr, VirtAddr};
 
     /// Starting physical address to which the Kernel is loaded.
+    ///
+    /// Must match the physical base address of the Kernel segments (see `kernel.ld`).
     pub const KERNEL_LOAD_ADDR: PhyAddr = PhyAddr::new(0x800_000);
 
-    /// Size of the Kernel in sectors (512 bytes chunks).
+    /// Maximum size of the Kernel in memory in sectors (512 bytes chunks).
     pub const KERNEL_SECTOR_SZ: usize = 0x20 * 0x100;
 
     /// Standard size for every Kernel stack.
@@ -1,8 +1,9 @@
 use core::{arch::asm, ptr};
 
 use bitfield::bitfield;
+use fzboot_info::{MemoryRegion, MemoryRegionKind};
 
-use crate::{errors::E820Error, hex_print, video::io::cprint_info};
+use crate::{errors::E820Error, hex_print, video::io::cprint_info, Convertible};
 
 pub const E820_MAP_ADDR: u32 = 0x4804;
 pub static mut E820_MAP_LENGTH: u32 = 0;
@@ -81,6 +82,40 @@ impl AddressRangeDescriptor {
     pub fn base_addr(&self) -> *mut u8 {
         ((self.base_addr_high as u64) << 32 | (self.base_addr_low as u64)) as *mut u8
     }
+
+    /// Returns the base physical address of this `AddressRangeDescriptor`.
+    ///
+    /// Unlike `base_addr`, it does not depend on the pointer width, and can represent addresses
+    /// above 4GB in protected mode.
+    #[must_use]
+    pub fn phys_base(&self) -> u64 {
+        u64::from(self.base_addr_high) << 32 | u64::from(self.base_addr_low)
+    }
+}
+
+impl From<&MemoryRegion> for AddressRangeDescriptor {
+    /// Converts a region of the native boot information memory map. Memory used by the bootloader
+    /// is considered reserved.
+    fn from(region: &MemoryRegion) -> Self {
+        let addr_type = match region.kind() {
+            MemoryRegionKind::Usable => E820MemType::RAM,
+            MemoryRegionKind::AcpiReclaimable => E820MemType::ACPI,
+            MemoryRegionKind::AcpiNvs => E820MemType::NVS,
+            MemoryRegionKind::Unusable => E820MemType::UNUSABLE,
+            MemoryRegionKind::Disabled => E820MemType::DISABLED,
+            MemoryRegionKind::Persistent => E820MemType::PERSISTENT,
+            MemoryRegionKind::Reserved | MemoryRegionKind::Bootloader => E820MemType::RESERVED,
+        };
+
+        Self {
+            base_addr_low: region.start().low_bits(),
+            base_addr_high: region.start().high_bits(),
+            length_low: region.length().low_bits(),
+            length_high: region.length().high_bits(),
+            addr_type,
+            extended_attributes: ExtendedAttributesARDS(0),
+        }
+    }
 }
 
 impl Default for AddressRangeDescriptor {
@@ -96,6 +131,35 @@ impl Default for AddressRangeDescriptor {
     }
 }
 
+impl From<E820MemType> for MemoryRegionKind {
+    fn from(mem_type: E820MemType) -> Self {
+        match mem_type {
+            E820MemType::RAM => Self::Usable,
+            E820MemType::ACPI => Self::AcpiReclaimable,
+            E820MemType::NVS => Self::AcpiNvs,
+            E820MemType::UNUSABLE => Self::Unusable,
+            E820MemType::DISABLED => Self::Disabled,
+            E820MemType::PERSISTENT => Self::Persistent,
+            E820MemType::RESERVED | E820MemType::OEM => Self::Reserved,
+        }
+    }
+}
+
+impl From<E820MemType> for u32 {
+    fn from(mem_type: E820MemType) -> Self {
+        match mem_type {
+            E820MemType::RAM => 1,
+            E820MemType::RESERVED => 2,
+            E820MemType::ACPI => 3,
+            E820MemType::NVS => 4,
+            E820MemType::UNUSABLE => 5,
+            E820MemType::DISABLED => 6,
+            E820MemType::PERSISTENT => 7,
+            E820MemType::OEM => 12,
+        }
+    }
+}
+
 #[derive(Debug, Clone, Copy)]
 #[repr(u16)]
 pub enum E820MemType {
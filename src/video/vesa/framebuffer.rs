@@ -149,19 +149,23 @@ impl<'b> TextFrameBuffer<'b> {
             (16, 8, 0) => PixelLayout::BGR,
             _ => PixelLayout::RGB,
         };
+        let bytes_per_px = usize::from(info.bpp >> 3);
+        let width = usize::try_from(info.width).expect("invalid framebuffer width");
+        let height = usize::try_from(info.height).expect("invalid framebuffer height");
         let metadata = FrameBufferMetadata {
             layout: pixel_layout,
-            bytes_per_px: info.bpp as usize >> 3,
-            width: info.width as usize,
-            height: info.height as usize,
-            stride: usize::try_from(info.pitch).expect("invalid framebuffer pitch"),
+            bytes_per_px,
+            width,
+            height,
+            // the pitch is expressed in bytes, the stride in pixels
+            stride: usize::try_from(info.pitch).expect("invalid framebuffer pitch") / bytes_per_px,
             bg_color: Some(DEFAULT_BG_COLOR),
         };
 
         let buffer = unsafe {
             slice::from_raw_parts_mut(
                 mapping_addr.as_mut_ptr::<u8>(),
-                (info.bpp as usize >> 3) * info.height as usize * info.width as usize,
+                bytes_per_px * height * width,
             )
         };
 
@@ -16,7 +16,8 @@ use core::fmt::{self, Write};
 use core::ptr;
 
 use crate::boot::multiboot::mb_information::FramebufferMultibootInformation;
-use crate::mem::VirtAddr;
+use crate::mem::{PhyAddr, VirtAddr};
+use fzboot_info::Framebuffer;
 use crate::video::vesa::framebuffer::{LockedTextFrameBuffer, RgbaColor, TextFrameBuffer};
 use crate::video::vesa::video_mode::{ModeInfoBlock, VESA_MODE_BUFFER};
 use crate::x86::paging::{get_memory_mapper, PageTableFlags};
@@ -68,6 +69,49 @@ pub fn init_text_buffer_from_multiboot(header: FramebufferMultibootInformation)
     });
 }
 
+/// Initializes the shared [`TextFrameBuffer`] from the framebuffer described by the native boot information.
+pub fn init_text_buffer_from_boot_info(framebuffer: &Framebuffer) {
+    init_text_buffer_from_multiboot(FramebufferMultibootInformation {
+        addr: PhyAddr::new(framebuffer.addr),
+        pitch: framebuffer.pitch,
+        width: framebuffer.width,
+        height: framebuffer.height,
+        bpp: framebuffer.bpp,
+        framebuffer_type: 1,
+        red_field_pos: framebuffer.red_shift,
+        red_mask_size: framebuffer.red_mask_size,
+        green_field_pos: framebuffer.green_shift,
+        green_mask_size: framebuffer.green_mask_size,
+        blue_field_pos: framebuffer.blue_shift,
+        blue_mask_size: framebuffer.blue_mask_size,
+    });
+}
+
+/// Initializes the shared [`TextFrameBuffer`] with a framebuffer allocated in memory, so that tests can print
+/// messages.
+#[cfg(test)]
+pub(crate) fn init_text_buffer_in_memory() {
+    use crate::video::vesa::framebuffer::{FrameBufferMetadata, TextCursor};
+    use crate::video::vesa::video_mode::PixelLayout;
+
+    let _ = TEXT_BUFFER.try_init_once(|| {
+        let metadata = FrameBufferMetadata {
+            layout: PixelLayout::RGB,
+            bytes_per_px: 4,
+            width: 640,
+            height: 480,
+            stride: 640,
+            bg_color: None,
+        };
+
+        LockedTextFrameBuffer::new(TextFrameBuffer {
+            buffer: alloc::vec![0u8; 4 * 640 * 480].leak(),
+            cursor: TextCursor::default(),
+            metadata,
+        })
+    });
+}
+
 /// Prints a formatted text input to the shared [`TextFrameBuffer`].
 ///
 /// # Panics
@@ -58,6 +58,14 @@ macro_rules! cpu_feature_edx {
     };
 }
 
+/// Defines a CPU feature retrieved from the 07h leaf (sub-leaf 0) in the `ebx` register.
+macro_rules! cpu_feature_ext_ebx {
+    ($name: tt, $mask: expr, $desc: literal) => {
+        #[doc = $desc]
+        pub const $name: (u8, u32) = (2, $mask);
+    };
+}
+
 cpu_feature_ecx!(CPU_FEAT_SSE3, 1 << 0, "Streaming SIMD Extensions 3");
 
 cpu_feature_ecx!(
@@ -302,6 +310,12 @@ cpu_feature_edx!(CPU_FEAT_TM, 1 << 29, "Thermal Monitor (TCC implemented).");
 
 cpu_feature_edx!(CPU_FEAT_PBE, 1 << 31, "Pending Break Enable.");
 
+cpu_feature_ext_ebx!(
+    CPU_FEAT_RDSEED,
+    1 << 18,
+    "CPU supports RDSEED (Read Random SEED) instruction"
+);
+
 /// Intel's CPU models.
 pub enum IntelCpuModel {
     RaptorLakeS,
@@ -389,6 +403,7 @@ pub fn cpu_feature_support(code: (u8, u32)) -> Option<bool> {
     match code.0 {
         0 => Some((features[2] & code.1) != 0),
         1 => Some((features[3] & code.1) != 0),
+        2 => Some((cpu_id_subleaf(0x7, 0)?[1] & code.1) != 0),
         _ => None,
     }
 }
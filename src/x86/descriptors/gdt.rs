@@ -57,6 +57,77 @@ pub unsafe fn long_init_gdt<A: MemoryAddress>(base_address: A) {
     gdt.update();
 }
 
+/// Initializes a flat [`GlobalDescriptorTable`] matching the layout expected by the Linux boot protocol.
+///
+/// The code segment (`__BOOT_CS`) is located at `0x10`, and the data segment (`__BOOT_DS`) at `0x18`. The code
+/// segment is a 64-bit one if `long_mode` is set, and a 32-bit one otherwise.
+///
+/// # Safety
+///
+/// Overwrites anything in memory at [`base_address`].
+#[allow(clippy::missing_panics_doc)]
+pub unsafe fn linux_init_gdt<A: MemoryAddress>(base_address: A, long_mode: bool) {
+    let data_segment = SegmentDescriptor::new_segment::<DataSegmentType>(DataSegmentType::ReadWrite)
+        .with_present(true)
+        .with_base(PhyAddr::new(0))
+        .unwrap()
+        .with_limit(0xFF_FFF)
+        .unwrap()
+        .with_granularity(true)
+        .with_size(true);
+    let code_segment =
+        SegmentDescriptor::new_segment::<CodeSegmentType>(CodeSegmentType::ExecuteRead)
+            .with_present(true)
+            .with_base(PhyAddr::new(0))
+            .unwrap()
+            .with_limit(0xFF_FFF)
+            .unwrap()
+            .with_granularity(true);
+
+    let mut gdt = GlobalDescriptorTable::new(base_address);
+    gdt.add_entry::<DataSegmentType>(data_segment).unwrap();
+    gdt.add_entry::<CodeSegmentType>(if long_mode {
+        code_segment.enable_long().unwrap()
+    } else {
+        code_segment.with_size(true)
+    })
+    .unwrap();
+    gdt.add_entry::<DataSegmentType>(data_segment).unwrap();
+    gdt.update();
+}
+
+/// Initializes a [`GlobalDescriptorTable`] with the 16-bit segments required to switch back to real mode.
+///
+/// The code segment is located at `0x08` and starts at `code_base`, and the data segment at `0x10`. Both of them
+/// are 64KB long, as expected by the processor when clearing the `PE` flag.
+///
+/// # Safety
+///
+/// Overwrites anything in memory at [`base_address`].
+#[allow(clippy::missing_panics_doc)]
+pub unsafe fn real_mode_init_gdt<A: MemoryAddress>(base_address: A, code_base: PhyAddr) {
+    let mut gdt = GlobalDescriptorTable::new(base_address);
+    gdt.add_entry::<CodeSegmentType>(
+        SegmentDescriptor::new_segment::<CodeSegmentType>(CodeSegmentType::ExecuteRead)
+            .with_present(true)
+            .with_base(code_base)
+            .unwrap()
+            .with_limit(0xFFFF)
+            .unwrap(),
+    )
+    .unwrap();
+    gdt.add_entry::<DataSegmentType>(
+        SegmentDescriptor::new_segment::<DataSegmentType>(DataSegmentType::ReadWrite)
+            .with_present(true)
+            .with_base(PhyAddr::new(0))
+            .unwrap()
+            .with_limit(0xFFFF)
+            .unwrap(),
+    )
+    .unwrap();
+    gdt.update();
+}
+
 /// Initializes the Kernel mode [`GlobalDescriptorTable`], with long code segment along with Usermode (`CPL` = 3) segments.
 ///
 /// # Safety
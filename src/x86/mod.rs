@@ -8,6 +8,7 @@
 pub mod cpuid;
 pub mod flags;
 pub mod msr;
+pub mod random;
 pub mod tsc;
 
 // #[cfg(target_arch = "x86_64")]
@@ -124,13 +124,18 @@ impl From<FrameAllocation> for Frame {
 #[cfg(not(feature = "x86_64"))]
 /// Routines to enable paging at the pre-kernel init stage.
 pub mod bootinit_paging {
+    use crate::errors::CanFail;
+    use crate::kernel_syms::PAGE_SIZE;
     use crate::mem::{MemoryAddress, PhyAddr, PhyAddr32, VirtAddr};
+    use crate::x86::cpuid::cpu_id;
     use crate::x86::int::disable_interrupts;
     use crate::x86::msr::{Ia32ExtendedFeature, ModelSpecificRegister};
     use crate::x86::paging::page_table::translate::{PageAddressTranslator, Translator};
-    use crate::x86::paging::{PageTable, PageTableFlags};
+    use crate::x86::paging::page_table::PageTableEntry;
+    use crate::x86::paging::{PageMappingError, PageTable, PageTableFlags};
     use crate::x86::registers::control::{ControlRegister, Cr0, Cr3, Cr4};
     use alloc::boxed::Box;
+    use core::ptr;
 
     /// Physical address of the layer 4 [`PageTable`] structure.
     pub const BOOT_PAGE_TABLE_ADDR: PhyAddr32 = PhyAddr32::new(0x20_000);
@@ -141,10 +146,19 @@ pub mod bootinit_paging {
     /// Base virtual address for the kernel code (`.text`) section.
     pub const KERNEL_CODE_MAPPING_BASE: VirtAddr = VirtAddr::new(0xFFFF_8C00_0000_0000);
 
+    /// Size of the physical memory mapped by [`init_paging`] (8GB), both identity mapped and mapped to the virtual
+    /// segment starting at [`KERNEL_PHYS_MAPPING_BASE`].
+    pub const BOOT_MAPPING_SIZE: u64 = 0x2_0000_0000;
+
+    /// Size of the physical memory mapped by a single level 2 [`PageTable`] made of 2MB pages.
+    const LEVEL2_MAPPING_SIZE: u64 = 4096 * 512 * 512;
+
     /// Pre-kernel load initialization of paging.
     ///
     /// Enables 64-bit level 4 paging if supported.
     /// Identity maps the physical memory, and also maps it to the virtual segment starting at [`KERNEL_PHYS_MAPPING_BASE`].
+    /// The kernel segments must have been mapped beforehand using [`map_kernel_segment`]: identity mapped segments keep
+    /// their pages and access rights, the rest of the memory is mapped using 2MB pages.
     /// Disables interrupts (the `IDT` has to be updated to support 64-bit).
     #[allow(clippy::missing_panics_doc)]
     pub fn init_paging() {
@@ -153,10 +167,6 @@ pub mod bootinit_paging {
             PageAddressTranslator::translate_address(KERNEL_PHYS_MAPPING_BASE).pml4_offset(),
             PhyAddr::new(0),
         );
-        identity_map_phys_level4(
-            PageAddressTranslator::translate_address(KERNEL_CODE_MAPPING_BASE).pml4_offset(),
-            PhyAddr::new(0x800_000),
-        );
         Cr3::write(
             Cr3::new()
                 .set_page_table_addr(BOOT_PAGE_TABLE_ADDR)
@@ -164,47 +174,174 @@ pub mod bootinit_paging {
         );
         disable_interrupts();
         Cr4::write(Cr4::read().with_phys_addr_ext(true));
-        Ia32ExtendedFeature::write(Ia32ExtendedFeature::read().unwrap().with_ia32e_enable(true));
+        Ia32ExtendedFeature::write(
+            Ia32ExtendedFeature::read()
+                .unwrap()
+                .with_ia32e_enable(true)
+                .with_nxe(nx_support()),
+        );
         Cr0::write(Cr0::read().with_paging(true));
     }
 
-    fn identity_map_phys_level4(entry_offset: u16, phy_offset: PhyAddr) {
-        let default_page_table: &mut PageTable = unsafe { &mut *BOOT_PAGE_TABLE_ADDR.as_mut_ptr() };
-        let pdpt: &mut PageTable = Box::leak(Box::default());
-
-        default_page_table
-            .get_mut(entry_offset)
-            .map_to_addr(
-                PhyAddr::new(
-                    u64::try_from((pdpt as *mut PageTable).addr())
-                        .expect("invalid pagetable address"),
-                ),
-                PageTableFlags::new().with_present(true).with_write(true),
-            )
-            .expect("failed to create level 3 pagetable");
-
-        for i in 0..8 {
-            let table_entry: &mut PageTable = Box::leak(Box::default());
-            pdpt.get_mut(i)
+    /// Checks if a virtual address is covered by the mappings created by [`init_paging`].
+    ///
+    /// Such addresses can only be mapped using [`map_kernel_segment`] to the same physical address, in the identity
+    /// mapping.
+    #[must_use]
+    pub fn is_boot_mapping(addr: VirtAddr) -> bool {
+        let pml4_offset = PageAddressTranslator::translate_address(addr).pml4_offset();
+
+        pml4_offset == 0
+            || pml4_offset
+                == PageAddressTranslator::translate_address(KERNEL_PHYS_MAPPING_BASE).pml4_offset()
+    }
+
+    /// Maps a kernel segment of `size` bytes, starting at the virtual address `virt_addr`, to the physical memory
+    /// starting at `phys_addr`.
+    ///
+    /// The segment is mapped using 4KB pages, with the given access rights. The execute-disable bit is only used if
+    /// supported by the processor.
+    ///
+    /// Pages shared with a segment mapped beforehand keep their mapping, with the access rights of both segments.
+    /// Segments linked at their physical address may be mapped within the identity mapping, which must not have been
+    /// created yet (see [`init_paging`]).
+    ///
+    /// # Errors
+    ///
+    /// Returns [`PageMappingError::BadAlignment`] if the addresses are not aligned on a 4KB boundary, and
+    /// [`PageMappingError::Overlap`] if the segment overlaps with the mappings created by [`init_paging`] (except for
+    /// identity mapped segments), or with a page already mapped to another physical address.
+    #[allow(clippy::missing_panics_doc)]
+    pub fn map_kernel_segment(
+        virt_addr: VirtAddr,
+        phys_addr: PhyAddr,
+        size: u64,
+        writable: bool,
+        executable: bool,
+    ) -> CanFail<PageMappingError> {
+        let page_size = u64::try_from(PAGE_SIZE).expect("invalid page size");
+
+        if u64::from(virt_addr) % page_size != 0 || u64::from(phys_addr) % page_size != 0 {
+            return Err(PageMappingError::BadAlignment);
+        }
+
+        let table_flags = PageTableFlags::new().with_present(true).with_write(true);
+        let page_flags = PageTableFlags::new()
+            .with_present(true)
+            .with_write(writable)
+            .with_nxe(!executable && nx_support());
+
+        let mut offset = 0;
+
+        while offset < size {
+            let page_addr = VirtAddr::new(u64::from(virt_addr) + offset);
+            let frame_addr = phys_addr + PhyAddr::new(offset);
+            let table_addr = PageAddressTranslator::translate_address(page_addr);
+
+            let identity_mapped =
+                table_addr.pml4_offset() == 0 && u64::from(page_addr) == u64::from(frame_addr);
+            if is_boot_mapping(page_addr) && !identity_mapped {
+                return Err(PageMappingError::Overlap);
+            }
+
+            let pml4: &mut PageTable = unsafe { &mut *BOOT_PAGE_TABLE_ADDR.as_mut_ptr() };
+            let pdpt = next_level_table(pml4.get_mut(table_addr.pml4_offset()), table_flags);
+            let pd = next_level_table(pdpt.get_mut(table_addr.pdpte_offset()), table_flags);
+
+            let pd_entry = pd.get_mut(table_addr.pde_offset());
+            if pd_entry.flags().huge_page() {
+                return Err(PageMappingError::Overlap);
+            }
+
+            let pt = next_level_table(pd_entry, table_flags);
+            let entry = pt.get_mut(table_addr.pte_offset());
+
+            if entry.used() {
+                if u64::from(entry.frame().addr) != u64::from(frame_addr) {
+                    return Err(PageMappingError::Overlap);
+                }
+
+                let flags = entry.flags();
+                entry.set_flags(
+                    flags
+                        .with_write(flags.write() || writable)
+                        .with_nxe(flags.nxe() && page_flags.nxe()),
+                );
+            } else {
+                entry.map_to_addr(frame_addr, page_flags)?;
+            }
+
+            offset += page_size;
+        }
+
+        Ok(())
+    }
+
+    /// Returns the table referenced by a paging structure entry, allocating it if the entry is not used yet.
+    fn next_level_table(entry: &mut PageTableEntry, flags: PageTableFlags) -> &'static mut PageTable {
+        if !entry.used() {
+            let table = ptr::from_mut(Box::leak(Box::<PageTable>::default()));
+
+            entry
                 .map_to_addr(
-                    PhyAddr::new(table_entry as *mut PageTable as u64),
-                    PageTableFlags::new().with_present(true).with_write(true),
+                    PhyAddr::new(
+                        u64::try_from(table.expose_provenance())
+                            .expect("invalid pagetable address"),
+                    ),
+                    flags,
                 )
                 .expect("failed to create pagetable");
+        }
+
+        let table_addr =
+            usize::try_from(u64::from(entry.frame().addr)).expect("invalid pagetable address");
+
+        unsafe { &mut *ptr::with_exposed_provenance_mut::<PageTable>(table_addr) }
+    }
+
+    /// Checks if the processor supports the execute-disable bit in paging structures.
+    fn nx_support() -> bool {
+        cpu_id(0x8000_0001).is_some_and(|features| features[3] & (1 << 20) != 0)
+    }
+
+    /// Maps the first [`BOOT_MAPPING_SIZE`] bytes of physical memory, starting at `phy_offset`, to the virtual memory
+    /// covered by the level 4 entry `entry_offset`.
+    ///
+    /// Pages mapped beforehand by [`map_kernel_segment`] are kept: the 2MB regions holding such pages are mapped
+    /// using 4KB pages.
+    fn identity_map_phys_level4(entry_offset: u16, phy_offset: PhyAddr) {
+        let default_page_table: &mut PageTable = unsafe { &mut *BOOT_PAGE_TABLE_ADDR.as_mut_ptr() };
+        let flags = PageTableFlags::new().with_present(true).with_write(true);
+        let pdpt = next_level_table(default_page_table.get_mut(entry_offset), flags);
+
+        let tables_count =
+            u16::try_from(BOOT_MAPPING_SIZE / LEVEL2_MAPPING_SIZE).expect("invalid boot mapping size");
+
+        for i in 0..tables_count {
+            let table_entry = next_level_table(pdpt.get_mut(i), flags);
+
             for j in 0..512 {
-                table_entry
-                    .get_mut(j)
-                    .map_to_addr(
-                        phy_offset
-                            + PhyAddr::new(
-                                4096 * 512 * 512 * u64::from(i) + 4096 * 512 * u64::from(j),
-                            ),
-                        PageTableFlags::new()
-                            .with_present(true)
-                            .with_write(true)
-                            .with_huge_page(true),
-                    )
-                    .expect("failed to create pagetable")
+                let entry = table_entry.get_mut(j);
+                let region_addr = phy_offset
+                    + PhyAddr::new(LEVEL2_MAPPING_SIZE * u64::from(i) + 4096 * 512 * u64::from(j));
+
+                if !entry.used() {
+                    entry
+                        .map_to_addr(region_addr, flags.with_huge_page(true))
+                        .expect("failed to create pagetable");
+                    continue;
+                }
+
+                let page_table = next_level_table(entry, flags);
+
+                for k in 0..512 {
+                    let page = page_table.get_mut(k);
+
+                    if !page.used() {
+                        page.map_to_addr(region_addr + PhyAddr::new(4096 * u64::from(k)), flags)
+                            .expect("failed to create pagetable");
+                    }
+                }
             }
         }
     }
@@ -215,6 +352,9 @@ pub mod bootinit_paging {
 pub enum PageMappingError {
     /// The [`Page`] is not properly aligned.
     BadAlignment,
+
+    /// The [`Page`] is already mapped to another [`Frame`].
+    Overlap,
 }
 
 impl BaseError for PageMappingError {}
@@ -12,7 +12,7 @@ use conquer_once::spin::OnceCell;
 use spin::Mutex;
 
 use crate::kernel_syms;
-use crate::mem::e820::{AddressRangeDescriptor, E820MemType, E820MemoryMap};
+use crate::mem::e820::{AddressRangeDescriptor, E820MemType};
 use crate::mem::{MemoryAddress, PhyAddr};
 use crate::x86::paging::page_table::mapper::{MemoryMapping, PhysicalMemoryMapping};
 use core::cmp::{max, min};
@@ -91,8 +91,17 @@ pub unsafe extern "C" fn pm_free(alloc_base: *mut u8, alloc_size: usize) {
     }
 }
 
-#[no_mangle]
-pub unsafe extern "C" fn init_phys_memory_pool(memory_map: E820MemoryMap) {
+/// Initializes the physical memory pool, using the largest usable range of `memory_map`.
+///
+/// # Panics
+///
+/// Panics if the physical memory pool is already initialized.
+///
+/// # Safety
+///
+/// `memory_map` must describe the actual physical memory: the usable ranges are handed out by the allocator
+/// without further checks.
+pub unsafe fn init_phys_memory_pool(memory_map: impl IntoIterator<Item = AddressRangeDescriptor>) {
     let mut largest_ram_segment = AddressRangeDescriptor::default();
 
     for entry in memory_map {
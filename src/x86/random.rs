@@ -0,0 +1,171 @@
+//! Hardware random numbers.
+//!
+//! Random numbers are read from the `RDSEED` or `RDRAND` instructions, if `CPUID` reports them.
+//! Otherwise, they are derived from the jitter of the Time Stamp Counter: the number of cycles
+//! taken by a short busy loop varies with caches, interrupts and bus contention, and a few hundred
+//! of these timings are hashed together. This is far weaker than a hardware source, but good
+//! enough to randomise memory layouts.
+
+use core::arch::asm;
+
+use crate::{
+    crypto::sha256::Sha256,
+    x86::cpuid::{cpu_feature_support, CPU_FEAT_RDRAND, CPU_FEAT_RDSEED, CPU_FEAT_TSC},
+};
+
+/// Number of attempts before giving up on `RDRAND`, as recommended by Intel.
+const RDRAND_RETRIES: usize = 10;
+
+/// Number of attempts before giving up on `RDSEED`, which fails more often than `RDRAND` when
+/// the entropy source is drained.
+const RDSEED_RETRIES: usize = 100;
+
+/// Number of _TSC_ timings hashed to produce a single random number.
+const JITTER_SAMPLES: usize = 256;
+
+/// Source of the random numbers.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RandomSource {
+    /// `RDSEED` instruction, directly backed by the processor entropy source.
+    Rdseed,
+
+    /// `RDRAND` instruction, backed by a generator periodically reseeded by the entropy source.
+    Rdrand,
+
+    /// Jitter of the Time Stamp Counter.
+    TscJitter,
+}
+
+impl RandomSource {
+    /// Returns the best random source available on this processor.
+    #[must_use]
+    pub fn detect() -> Self {
+        if cpu_feature_support(CPU_FEAT_RDSEED).unwrap_or(false) {
+            Self::Rdseed
+        } else if cpu_feature_support(CPU_FEAT_RDRAND).unwrap_or(false) {
+            Self::Rdrand
+        } else {
+            Self::TscJitter
+        }
+    }
+
+    /// Returns the name of the source.
+    #[must_use]
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Rdseed => "RDSEED",
+            Self::Rdrand => "RDRAND",
+            Self::TscJitter => "TSC jitter",
+        }
+    }
+
+    /// Reads a random number from this source.
+    ///
+    /// If the hardware instructions keep failing, falls back to the next source (`RDSEED`, then
+    /// `RDRAND`, then the _TSC_ jitter).
+    #[must_use]
+    pub fn next_u64(self) -> u64 {
+        let hardware = match self {
+            Self::Rdseed => read_u64(rdseed32, RDSEED_RETRIES).or_else(|| {
+                cpu_feature_support(CPU_FEAT_RDRAND)
+                    .unwrap_or(false)
+                    .then(|| read_u64(rdrand32, RDRAND_RETRIES))
+                    .flatten()
+            }),
+            Self::Rdrand => read_u64(rdrand32, RDRAND_RETRIES),
+            Self::TscJitter => None,
+        };
+
+        hardware.unwrap_or_else(tsc_jitter)
+    }
+}
+
+/// Returns a random number, read from the best source available.
+#[must_use]
+pub fn random_u64() -> u64 {
+    RandomSource::detect().next_u64()
+}
+
+/// Builds a 64-bit random number from two 32-bit reads, each attempted up to `retries` times.
+///
+/// 32-bit reads are used so that the same code runs in protected and in long mode.
+fn read_u64(read: fn() -> Option<u32>, retries: usize) -> Option<u64> {
+    let read_retry = || (0..retries).find_map(|_| read());
+
+    let low = read_retry()?;
+    let high = read_retry()?;
+
+    Some((u64::from(high) << 32) | u64::from(low))
+}
+
+/// Executes `RDRAND`, which sets the carry flag if a random number was available.
+fn rdrand32() -> Option<u32> {
+    let value: u32;
+    let success: u8;
+
+    unsafe {
+        asm!("rdrand {0:e}", "setc {1}", out(reg) value, out(reg_byte) success, options(nomem, nostack));
+    }
+
+    (success != 0).then_some(value)
+}
+
+/// Executes `RDSEED`, which sets the carry flag if a random number was available.
+fn rdseed32() -> Option<u32> {
+    let value: u32;
+    let success: u8;
+
+    unsafe {
+        asm!("rdseed {0:e}", "setc {1}", out(reg) value, out(reg_byte) success, options(nomem, nostack));
+    }
+
+    if success == 0 {
+        // give the entropy source some time to refill
+        unsafe {
+            asm!("pause", options(nomem, nostack));
+        }
+    }
+
+    (success != 0).then_some(value)
+}
+
+/// Reads the Time Stamp Counter.
+fn rdtsc() -> u64 {
+    let low: u32;
+    let high: u32;
+
+    unsafe {
+        asm!("rdtsc", out("eax") low, out("edx") high, options(nomem, nostack));
+    }
+
+    (u64::from(high) << 32) | u64::from(low)
+}
+
+/// Derives a random number from the jitter of the Time Stamp Counter.
+///
+/// Without a _TSC_, the result is constant: callers should not rely on it for anything but
+/// best-effort randomisation.
+fn tsc_jitter() -> u64 {
+    let mut hasher = Sha256::new();
+
+    if cpu_feature_support(CPU_FEAT_TSC).unwrap_or(false) {
+        let mut previous = rdtsc();
+
+        for round in 0..JITTER_SAMPLES {
+            // busy loop of varying length, whose duration is not deterministic
+            for _ in 0..(previous % 64) + u64::try_from(round).expect("invalid round") {
+                core::hint::spin_loop();
+            }
+
+            let now = rdtsc();
+            hasher.update(&now.wrapping_sub(previous).to_le_bytes());
+            previous = now;
+        }
+
+        hasher.update(&previous.to_le_bytes());
+    }
+
+    let digest = hasher.finalize();
+
+    u64::from_le_bytes(digest[..8].try_into().expect("invalid digest size"))
+}
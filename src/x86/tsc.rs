@@ -155,6 +155,18 @@ impl TSCClock {
         (1_000_000_f64 * self.tsc_read() as f64) / self.tsc_freq
     }
 
+    /// Returns the frequency of the TSC counter, in Hz, determined during the clock calibration.
+    #[must_use]
+    pub fn tsc_frequency(&self) -> f64 {
+        self.tsc_freq
+    }
+
+    /// Checks if the TSC counter runs at a constant rate, regardless of the processor power state.
+    #[must_use]
+    pub fn invariant(&self) -> bool {
+        self.invariant
+    }
+
     /// Converts raw TSC counter value to microseconds.
     pub fn tsc_ticks_to_micro(&self, ticks: f64) -> f64 {
         (1_000_000_f64 * ticks) / self.tsc_freq